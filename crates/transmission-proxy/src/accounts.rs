@@ -0,0 +1,95 @@
+//! Logical users, which can log in with several identities
+//!
+//! ACLs can name a logical user instead of each of its identities, and the proxy records the
+//! logical user as the owner of torrents and uploads, so users keep access to them whichever
+//! provider they log in with.
+
+use std::collections::HashSet;
+
+use color_eyre::eyre;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    acl::{AclIdentity, Acls},
+    auth::AuthUser,
+};
+
+/// Logical user, identified by any of its identities
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct Account {
+    pub name: String,
+    pub identities: Vec<AclIdentity>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(transparent)]
+pub struct Accounts {
+    accounts: Vec<Account>,
+}
+
+impl Accounts {
+    pub fn is_empty(&self) -> bool {
+        self.accounts.is_empty()
+    }
+
+    /// Check that identities belong to one account at most, and that ACLs only name known
+    /// accounts
+    pub fn validate(&self, acls: &Acls) -> eyre::Result<()> {
+        let mut names = HashSet::new();
+        let mut identities = HashSet::new();
+
+        for account in &self.accounts {
+            if !names.insert(account.name.as_str()) {
+                eyre::bail!("duplicate user {}", account.name);
+            }
+
+            for identity in &account.identities {
+                if matches!(identity, AclIdentity::User { .. }) {
+                    eyre::bail!("user {} can't be identified by another user", account.name);
+                }
+
+                if !identities.insert(identity) {
+                    eyre::bail!("{identity:?} belongs to several users");
+                }
+            }
+        }
+
+        for acl in acls.rules() {
+            for identity in &acl.identities {
+                if let AclIdentity::User { name } = identity {
+                    if !names.contains(name.as_str()) {
+                        eyre::bail!("unknown user {name} in ACL {}", acls.name_of(acl));
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Identity to use for the given account, e.g. to explain its requests
+    pub fn first_identity(&self, name: &str) -> Option<&AclIdentity> {
+        self.accounts
+            .iter()
+            .find(|account| account.name == name)
+            .and_then(|account| account.identities.first())
+    }
+
+    /// Attach the account of an authenticated user, if they have one
+    pub fn link(&self, user: AuthUser) -> AuthUser {
+        match self.accounts.iter().find(|account| {
+            account
+                .identities
+                .iter()
+                .any(|identity| identity.matches(&user))
+        }) {
+            Some(account) => AuthUser::Linked {
+                account: account.name.clone(),
+                identity: Box::new(user),
+            },
+            None => user,
+        }
+    }
+}
@@ -1,36 +1,238 @@
-use std::collections::HashSet;
+use std::{
+    borrow::Cow,
+    collections::{BTreeMap, HashMap, HashSet},
+    sync::Arc,
+};
 
+use color_eyre::eyre;
+use hyper::{
+    header::{HeaderName, HeaderValue, CONTENT_TYPE},
+    HeaderMap,
+};
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
 use crate::{
     auth::{AuthUser, Providers},
+    provisioning::Provisioning,
     rpc,
 };
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
 #[serde(deny_unknown_fields)]
 pub struct Acls {
-    rules: Vec<Acl>,
+    rules: Vec<Arc<Acl>>,
+
+    /// Don't tell clients which constraint denied their request
+    #[serde(default)]
+    pub terse_denials: bool,
+
+    /// Forward the RPC calls of ACLs which don't filter anything and allow dangerous methods
+    /// untouched, streaming their bodies, unless another feature needs to read them
+    #[serde(default)]
+    pub fast_path: bool,
+
+    /// Access granted when no ACL matches
+    #[serde(default)]
+    pub default_policy: DefaultPolicy,
+
+    /// Web seed policy of the ACLs which don't set one
+    #[serde(default)]
+    pub webseeds: WebseedPolicy,
+
+    /// ACLs created for OAuth2 users on their first login
+    #[serde(default)]
+    pub provisioning: Provisioning,
+}
+
+/// Access granted to users which no ACL matched
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum DefaultPolicy {
+    /// Forward requests without restrictions
+    Allow,
+    /// Deny access, as if the `deny` flag was set
+    #[default]
+    Deny,
+}
+
+/// What to do with the web seeds of added torrents, which may point to internal hosts or
+/// bypass tracker policies
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum WebseedPolicy {
+    /// Leave them as is
+    #[default]
+    Keep,
+    /// Remove them
+    Strip,
+    /// Apply the tracker rules of the ACL to them, dropping the URLs a rule removes
+    Rewrite,
 }
 
 impl Acls {
-    fn get_anon(&self) -> Option<&Acl> {
+    /// Name of the given ACL, or its index in the rule list if it has none
+    pub fn name_of<'a>(&'a self, acl: &'a Acl) -> Cow<'a, str> {
+        if let Some(name) = &acl.name {
+            return Cow::Borrowed(name.as_str());
+        }
+
+        Cow::Owned(
+            self.rules
+                .iter()
+                .position(|rule| std::ptr::eq(rule.as_ref(), acl))
+                .map(|index| index.to_string())
+                .unwrap_or_default(),
+        )
+    }
+
+    /// ACLs, in the order they are matched
+    pub fn rules(&self) -> &[Arc<Acl>] {
+        &self.rules
+    }
+
+    /// Returns true if access is denied to users matching the given ACL, or no ACL
+    pub fn denies(&self, acl: Option<&Acl>) -> bool {
+        acl.map_or(self.default_policy == DefaultPolicy::Deny, |acl| acl.deny)
+    }
+
+    /// Expand the tracker rule sets referenced by the ACLs, and set the default web seed policy
+    /// on those without one
+    pub fn resolve_tracker_rules(
+        &mut self,
+        sets: &HashMap<String, Vec<Arc<TrackerRule>>>,
+    ) -> eyre::Result<()> {
+        let webseeds = self.webseeds;
+
+        for acl in &mut self.rules {
+            let tracker_rules = Self::expand_tracker_rules(acl, sets)?;
+
+            // Clones the ACL if it is already shared, e.g. on a config reload
+            let acl = Arc::make_mut(acl);
+            acl.webseeds.get_or_insert(webseeds);
+            acl.tracker_rules = tracker_rules;
+        }
+
+        Ok(())
+    }
+
+    /// Resolve and validate an ACL created after startup, like the ones in the configuration
+    pub fn resolve(
+        &self,
+        acl: &mut Acl,
+        sets: &HashMap<String, Vec<Arc<TrackerRule>>>,
+    ) -> eyre::Result<()> {
+        acl.tracker_rules = Self::expand_tracker_rules(acl, sets)?;
+        acl.webseeds.get_or_insert(self.webseeds);
+        self.validate_acl(acl)
+    }
+
+    fn expand_tracker_rules(
+        acl: &Acl,
+        sets: &HashMap<String, Vec<Arc<TrackerRule>>>,
+    ) -> eyre::Result<Vec<Arc<TrackerRule>>> {
+        let mut tracker_rules = Vec::new();
+        for rule in &acl.tracker_rule_refs {
+            match rule {
+                TrackerRuleRef::Rule(rule) => tracker_rules.push(rule.clone()),
+                TrackerRuleRef::Set(name) => tracker_rules.extend(
+                    sets.get(name)
+                        .ok_or_else(|| eyre::eyre!("unknown tracker rule set {name}"))?
+                        .iter()
+                        .cloned(),
+                ),
+            }
+        }
+
+        Ok(tracker_rules)
+    }
+
+    /// Check that the web interface headers of the ACLs are valid
+    pub fn validate(&self) -> eyre::Result<()> {
+        for acl in &self.rules {
+            self.validate_acl(acl)?;
+        }
+
+        Ok(())
+    }
+
+    fn validate_acl(&self, acl: &Acl) -> eyre::Result<()> {
+        for (name, value) in &acl.web_ui_headers {
+            HeaderName::try_from(name.as_str()).map_err(|err| {
+                eyre::eyre!(
+                    "invalid web_ui_headers name {name} in ACL {}: {err}",
+                    self.name_of(acl)
+                )
+            })?;
+            HeaderValue::try_from(value.as_str()).map_err(|err| {
+                eyre::eyre!(
+                    "invalid value for web_ui_headers {name} in ACL {}: {err}",
+                    self.name_of(acl)
+                )
+            })?;
+        }
+
+        Ok(())
+    }
+
+    /// Replace the ACLs which reference unknown tracker rule sets or have invalid headers by
+    /// rules denying access to their identities, returning the problems found
+    pub fn deny_invalid(&mut self, sets: &HashMap<String, Vec<Arc<TrackerRule>>>) -> Vec<String> {
+        let mut problems = Vec::new();
+
+        for index in 0..self.rules.len() {
+            let acl = &self.rules[index];
+            let result = Self::expand_tracker_rules(acl, sets)
+                .map_err(|err| eyre::eyre!("ACL {}: {err}", self.name_of(acl)))
+                .and_then(|_| self.validate_acl(acl));
+
+            if let Err(err) = result {
+                problems.push(err.to_string());
+                self.rules[index] = Arc::new(Acl {
+                    name: acl.name.clone(),
+                    identities: acl.identities.clone(),
+                    deny: true,
+                    ..Default::default()
+                });
+            }
+        }
+
+        problems
+    }
+
+    /// Identities named by the ACLs, along with the name of the first ACL naming them
+    pub fn identities(&self) -> Vec<(&AclIdentity, Cow<'_, str>)> {
+        let mut seen = HashSet::new();
+        self.rules
+            .iter()
+            .flat_map(|acl| acl.identities.iter().map(move |identity| (identity, acl)))
+            .filter(|(identity, _)| seen.insert(*identity))
+            .map(|(identity, acl)| (identity, self.name_of(acl)))
+            .collect()
+    }
+
+    /// Returns true if an ACL names the given user
+    pub fn names(&self, user: &AuthUser) -> bool {
+        self.rules
+            .iter()
+            .any(|acl| acl.identities.iter().any(|identity| identity.matches(user)))
+    }
+
+    fn get_anon(&self) -> Option<&Arc<Acl>> {
         self.rules.iter().find(|acl| acl.identities.is_empty())
     }
 
-    pub async fn get(&self, user: &AuthUser, providers: &Providers) -> Option<&Acl> {
-        match user {
+    pub async fn get(&self, user: &AuthUser, providers: &Providers) -> Option<Arc<Acl>> {
+        // Find a matching identity
+        let acl = self
+            .rules
+            .iter()
+            .find(|acl| acl.identities.iter().any(|identity| identity.matches(user)));
+
+        match user.identity() {
             AuthUser::Anonymous => None,
             AuthUser::Basic { username, password } => {
-                let basic_user = self.rules.iter().find(|acl| {
-                    // Find a matching identity
-                    acl.identities.iter().any(|identity| match identity {
-                        AclIdentity::Basic { name } => name == username.as_str(),
-                        _ => false,
-                    })
-                });
-
-                let basic_user = basic_user?;
+                let basic_user = acl?;
 
                 if let Some(password) = password {
                     providers
@@ -43,34 +245,61 @@ impl Acls {
                     Some(basic_user)
                 }
             }
-            AuthUser::OAuth2 { username, provider } => {
-                let oauth_user = self.rules.iter().find(|acl| {
-                    // Find a matching identity
-                    acl.identities.iter().any(|identity| match identity {
-                        AclIdentity::OAuth2 { name, oauth2 } => {
-                            name == username.as_str() && oauth2 == provider
-                        }
-                        _ => false,
-                    })
-                });
-
-                oauth_user
-            }
+            AuthUser::OAuth2 { .. } => acl,
+            AuthUser::Linked { .. } => unreachable!("identities are not linked users"),
         }
         .or_else(|| self.get_anon())
+        .cloned()
     }
 }
 
-#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Hash, JsonSchema)]
 #[serde(rename_all = "lowercase", tag = "provider", deny_unknown_fields)]
 pub enum AclIdentity {
-    Basic { name: String },
-    OAuth2 { name: String, oauth2: String },
+    Basic {
+        name: String,
+    },
+    OAuth2 {
+        name: String,
+        oauth2: String,
+    },
+    /// Logical user from the `users` section, whichever identity they logged in with
+    User {
+        name: String,
+    },
+}
+
+impl AclIdentity {
+    /// Name of the user, without the provider
+    pub fn name(&self) -> &str {
+        match self {
+            AclIdentity::Basic { name }
+            | AclIdentity::OAuth2 { name, .. }
+            | AclIdentity::User { name } => name,
+        }
+    }
+
+    /// Returns true if this identity designates the given user
+    pub fn matches(&self, user: &AuthUser) -> bool {
+        match (self, user) {
+            (AclIdentity::User { name }, AuthUser::Linked { account, .. }) => name == account,
+            (identity, AuthUser::Linked { identity: user, .. }) => identity.matches(user),
+            (AclIdentity::Basic { name }, AuthUser::Basic { username, .. }) => name == username,
+            (AclIdentity::OAuth2 { name, oauth2 }, AuthUser::OAuth2 { username, provider }) => {
+                name == username && oauth2 == provider
+            }
+            _ => false,
+        }
+    }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Default, Debug, Clone, Serialize, Deserialize, JsonSchema)]
 #[serde(deny_unknown_fields)]
 pub struct Acl {
+    /// Name of this ACL, for logging and identification
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+
     /// List of identities concerned by this ACL
     #[serde(default)]
     pub identities: HashSet<AclIdentity>,
@@ -82,46 +311,410 @@ pub struct Acl {
     #[serde(default)]
     pub allowed_methods: Vec<rpc::MethodName>,
 
+    /// Only allow methods which don't modify torrents or the session
+    #[serde(default)]
+    pub read_only: bool,
+
     /// Deny all access to matched members
     #[serde(default)]
     pub deny: bool,
 
-    /// Tracker rules
-    #[serde(default, skip_serializing_if = "Vec::is_empty")]
-    pub tracker_rules: Vec<TrackerRule>,
+    /// Allow session-close, session-set, blocklist-update and port-test without listing them in
+    /// `allowed_methods`
+    #[serde(default)]
+    pub allow_dangerous_methods: bool,
+
+    /// Grant access to the proxy administration endpoints
+    #[serde(default)]
+    pub admin: bool,
+
+    /// Tracker rules, or names of rule sets defined in `tracker_rule_sets`
+    #[serde(
+        default,
+        rename = "tracker_rules",
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    tracker_rule_refs: Vec<TrackerRuleRef>,
+
+    /// Tracker rules, with the rule sets expanded
+    #[serde(skip)]
+    pub tracker_rules: Vec<Arc<TrackerRule>>,
+
+    /// Maximum number of trackers in added torrents, after tracker rules
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_trackers: Option<usize>,
+
+    /// Maximum number of tracker tiers in added torrents, after tracker rules
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_tracker_tiers: Option<usize>,
+
+    /// Merge the tracker tiers of added torrents into a single tier, after tracker rules
+    #[serde(default)]
+    pub flatten_tracker_tiers: bool,
+
+    /// Reject added torrents containing hidden files
+    #[serde(default)]
+    pub deny_hidden_files: bool,
+
+    /// Reject added torrents without any tracker matching this pattern, after tracker rules
+    #[serde(default, with = "serde_regex", skip_serializing_if = "Option::is_none")]
+    #[schemars(with = "Option<String>")]
+    pub require_tracker: Option<regex::Regex>,
+
+    /// Disk space in bytes allotted to this ACL, reported by free-space calls instead of the
+    /// free space of the filesystem
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub quota: Option<u64>,
+
+    /// Headers set on the HTML pages of the web interface, e.g. `X-Robots-Tag: noindex`
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub web_ui_headers: BTreeMap<String, String>,
+
+    /// What to do with the cookies sent by the daemon when fetching torrents added by URL:
+    /// `strip`, `pass`, or the list of domains they may be sent to. Stripped by default if the
+    /// ACL restricts anything else.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub torrent_cookies: Option<CookiePolicy>,
+
+    /// Web seed policy for added torrents, `keep`, `strip` or `rewrite`. Defaults to the
+    /// `webseeds` setting of all ACLs.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub webseeds: Option<WebseedPolicy>,
 }
 
 impl Acl {
     /// Returns true if this ACL does not filter anything (all requests, download dirs and methods
-    /// are allowed). This is used to skip request deserialization if needed.
+    /// are allowed, besides dangerous methods which are checked separately). This is used to skip
+    /// request deserialization if needed.
     pub fn is_nop(&self) -> bool {
         self.download_dir.is_none()
             && self.allowed_methods.is_empty()
+            && !self.read_only
             && !self.deny
             && self.tracker_rules.is_empty()
+            && !self.limits_trackers()
+            && !self.flatten_tracker_tiers
+            && !self.deny_hidden_files
+            && self.quota.is_none()
+            && self.require_tracker.is_none()
+            && self.webseed_policy() == WebseedPolicy::Keep
+            && matches!(
+                self.torrent_cookies,
+                None | Some(CookiePolicy::Mode(CookieMode::Pass))
+            )
+    }
+
+    /// Cookie policy of this ACL, defaulting to stripping them from restricted users
+    pub fn cookie_policy(&self) -> Cow<'_, CookiePolicy> {
+        match &self.torrent_cookies {
+            Some(policy) => Cow::Borrowed(policy),
+            None if self.is_nop() => Cow::Owned(CookiePolicy::Mode(CookieMode::Pass)),
+            None => Cow::Owned(CookiePolicy::Mode(CookieMode::Strip)),
+        }
+    }
+
+    /// Set the web interface headers of this ACL on a response, if it is an HTML page
+    pub fn apply_web_ui_headers(&self, headers: &mut HeaderMap) {
+        if self.web_ui_headers.is_empty() {
+            return;
+        }
+
+        let is_html = headers
+            .get(CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .map_or(false, |value| {
+                value
+                    .trim_start()
+                    .to_ascii_lowercase()
+                    .starts_with("text/html")
+            });
+        if !is_html {
+            return;
+        }
+
+        for (name, value) in &self.web_ui_headers {
+            if let (Ok(name), Ok(value)) = (
+                HeaderName::try_from(name.as_str()),
+                HeaderValue::try_from(value.as_str()),
+            ) {
+                headers.insert(name, value);
+            }
+        }
+    }
+
+    /// Web seed policy of this ACL
+    pub fn webseed_policy(&self) -> WebseedPolicy {
+        self.webseeds.unwrap_or_default()
+    }
+
+    /// Returns true if this ACL limits the trackers of added torrents
+    pub fn limits_trackers(&self) -> bool {
+        self.max_trackers.is_some() || self.max_tracker_tiers.is_some()
+    }
+
+    /// Returns true if this ACL allows calling the given method
+    pub fn allows(&self, method: rpc::MethodName) -> bool {
+        (self.allowed_methods.is_empty() || self.allowed_methods.contains(&method))
+            && (!self.read_only || is_read_only(method))
+            && (!is_dangerous(method)
+                || self.allow_dangerous_methods
+                || self.allowed_methods.contains(&method))
     }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+/// Returns true if the given method affects the daemon itself, so it must be explicitly allowed
+fn is_dangerous(method: rpc::MethodName) -> bool {
+    use rpc::MethodName::*;
+
+    matches!(
+        method,
+        SessionClose | SessionSet | BlocklistUpdate | PortTest
+    )
+}
+
+/// Returns true if the given method doesn't modify torrents or the session
+pub(crate) fn is_read_only(method: rpc::MethodName) -> bool {
+    use rpc::MethodName::*;
+
+    matches!(method, TorrentGet | SessionGet | SessionStats | FreeSpace)
+}
+
+/// Policy for the `cookies` argument of torrent-add calls
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(untagged)]
+pub enum CookiePolicy {
+    Mode(CookieMode),
+    /// Domains the cookies may be sent to, including their subdomains
+    Domains(Vec<String>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum CookieMode {
+    /// Remove the cookies
+    Strip,
+    /// Forward the cookies as is
+    Pass,
+}
+
+impl CookiePolicy {
+    /// Returns true if cookies may be sent along with a request to the given torrent URL
+    pub fn allows(&self, url: Option<&str>) -> bool {
+        match self {
+            CookiePolicy::Mode(mode) => *mode == CookieMode::Pass,
+            CookiePolicy::Domains(domains) => {
+                let Some(host) = url
+                    .and_then(|url| url::Url::parse(url.trim()).ok())
+                    .and_then(|url| url.host_str().map(str::to_ascii_lowercase))
+                else {
+                    return false;
+                };
+
+                domains.iter().any(|domain| {
+                    let domain = domain.trim_start_matches('.').to_ascii_lowercase();
+                    host == domain
+                        || host
+                            .strip_suffix(&domain)
+                            .map_or(false, |prefix| prefix.ends_with('.'))
+                })
+            }
+        }
+    }
+}
+
+/// Tracker rule of an ACL
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(untagged)]
+pub enum TrackerRuleRef {
+    /// Name of a rule set
+    Set(String),
+    Rule(Arc<TrackerRule>),
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
 #[serde(untagged)]
 pub enum TrackerRule {
+    /// Replace the first match of `from`. `to` may refer to capture groups, e.g. `${name}`.
     Replace {
         #[serde(with = "serde_regex")]
+        #[schemars(with = "String")]
         from: regex::Regex,
         to: String,
     },
+    /// Rewrite the components of announce URLs whose host matches `match_host`
+    Url {
+        #[serde(with = "serde_regex")]
+        #[schemars(with = "String")]
+        match_host: regex::Regex,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        scheme: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        host: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        path: Option<String>,
+        /// Query parameters to set, or to remove if null
+        #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+        query: BTreeMap<String, Option<String>>,
+        /// Replace the passkey with the one of the user
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        set_passkey: Option<SetPasskey>,
+    },
+}
+
+fn default_passkey_query() -> String {
+    "passkey".to_owned()
+}
+
+/// Location of the passkey in announce URLs, and the passkeys of each user
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct SetPasskey {
+    /// Query parameter holding the passkey
+    #[serde(default = "default_passkey_query")]
+    pub query: String,
+
+    /// Index of the path segment holding the passkey, instead of a query parameter
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub segment: Option<usize>,
+
+    /// Passkey of each user. Announce URLs are removed for other users.
+    pub passkeys: Vec<Passkey>,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct Passkey {
+    pub identity: AclIdentity,
+    pub passkey: String,
+}
+
+impl SetPasskey {
+    /// Put the passkey of the user in an URL, returning false if the user has none
+    fn apply(&self, url: &mut url::Url, user: &AuthUser) -> bool {
+        let Some(passkey) = self
+            .passkeys
+            .iter()
+            .find(|passkey| passkey.identity.matches(user))
+        else {
+            return false;
+        };
+
+        if let Some(index) = self.segment {
+            let mut segments: Vec<String> = url
+                .path_segments()
+                .map(|segments| segments.map(str::to_owned).collect())
+                .unwrap_or_default();
+
+            let Some(segment) = segments.get_mut(index) else {
+                return false;
+            };
+            *segment = passkey.passkey.clone();
+
+            url.set_path(&segments.join("/"));
+        } else {
+            let pairs: Vec<(String, String)> = url
+                .query_pairs()
+                .filter(|(name, _)| *name != self.query)
+                .map(|(name, value)| (name.into_owned(), value.into_owned()))
+                .collect();
+
+            url.query_pairs_mut()
+                .clear()
+                .extend_pairs(pairs)
+                .append_pair(&self.query, &passkey.passkey);
+        }
+
+        true
+    }
 }
 
 impl TrackerRule {
-    pub fn matches(&self, _announce: &str) -> bool {
+    pub fn matches(&self, announce: &str) -> bool {
         match self {
             TrackerRule::Replace { .. } => true,
+            TrackerRule::Url { match_host, .. } => url::Url::parse(announce)
+                .ok()
+                .and_then(|url| url.host_str().map(|host| match_host.is_match(host)))
+                .unwrap_or(false),
         }
     }
 
-    pub fn apply(&self, announce: &str) -> Option<String> {
+    /// Rewrite an announce URL for the given user, returning None if it should be removed
+    pub fn apply(&self, announce: &str, user: &AuthUser) -> Option<String> {
         match self {
             TrackerRule::Replace { from, to } => Some(from.replace(announce, to).to_string()),
+            TrackerRule::Url {
+                scheme,
+                host,
+                path,
+                query,
+                set_passkey,
+                ..
+            } => {
+                let Some(mut url) = rewrite_url(
+                    announce,
+                    scheme.as_deref(),
+                    host.as_deref(),
+                    path.as_deref(),
+                    query,
+                ) else {
+                    return Some(announce.to_owned());
+                };
+
+                if let Some(set_passkey) = set_passkey {
+                    if !set_passkey.apply(&mut url, user) {
+                        return None;
+                    }
+                }
+
+                Some(url.into())
+            }
         }
     }
 }
+
+/// Rewrite the components of an URL, returning None if the result is invalid
+fn rewrite_url(
+    announce: &str,
+    scheme: Option<&str>,
+    host: Option<&str>,
+    path: Option<&str>,
+    query: &BTreeMap<String, Option<String>>,
+) -> Option<url::Url> {
+    let mut url = url::Url::parse(announce).ok()?;
+
+    // The url crate can't switch between special (http) and other (udp) schemes, so rebuild the
+    // URL instead
+    if let Some(scheme) = scheme {
+        let rest = &url.as_str()[url.scheme().len()..];
+        url = url::Url::parse(&format!("{scheme}{rest}")).ok()?;
+    }
+
+    if let Some(host) = host {
+        url.set_host(Some(host)).ok()?;
+    }
+
+    if let Some(path) = path {
+        url.set_path(path);
+    }
+
+    if !query.is_empty() {
+        let mut pairs: Vec<(String, String)> = url
+            .query_pairs()
+            .filter(|(name, _)| !query.contains_key(name.as_ref()))
+            .map(|(name, value)| (name.into_owned(), value.into_owned()))
+            .collect();
+        pairs.extend(
+            query
+                .iter()
+                .filter_map(|(name, value)| Some((name.clone(), value.clone()?))),
+        );
+
+        url.set_query(None);
+        if !pairs.is_empty() {
+            url.query_pairs_mut().extend_pairs(pairs);
+        }
+    }
+
+    Some(url)
+}
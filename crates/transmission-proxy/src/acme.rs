@@ -0,0 +1,308 @@
+//! Certificates issued by an ACME provider such as Let's Encrypt
+//!
+//! Domains are validated with TLS-ALPN-01 challenges answered by the listener itself, so no other
+//! port needs to be reachable. The account and the issued certificate are kept in `cache_dir`
+//! and renewed in the background.
+
+use std::{
+    collections::HashMap,
+    fs::{self, OpenOptions},
+    io::{BufReader, Write},
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use color_eyre::eyre::{self, WrapErr};
+use instant_acme::{
+    Account, AccountCredentials, AuthorizationStatus, ChallengeType, Identifier, NewAccount,
+    NewOrder, Order, OrderStatus,
+};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tokio_rustls::rustls::{
+    self,
+    server::{ClientHello, ResolvesServerCert},
+    sign::{self, CertifiedKey},
+};
+use tracing::{error, info, warn};
+
+/// ALPN protocol of TLS-ALPN-01 challenge connections
+const ACME_TLS_ALPN: &[u8] = b"acme-tls/1";
+
+/// Delay before retrying a failed renewal
+const RETRY_DELAY: Duration = Duration::from_secs(3600);
+
+/// Longest time between two checks of the certificate expiry
+const CHECK_INTERVAL: Duration = Duration::from_secs(24 * 3600);
+
+fn default_directory() -> String {
+    instant_acme::LetsEncrypt::Production.url().to_owned()
+}
+
+fn default_renew_before() -> u64 {
+    30
+}
+
+/// Automatic certificate management for serving https
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct AcmeConfig {
+    /// Domain names of the certificate
+    pub domains: Vec<String>,
+
+    /// Contact URLs of the ACME account, e.g. `mailto:admin@example.com`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub contact: Vec<String>,
+
+    /// Directory URL of the ACME provider
+    #[serde(default = "default_directory")]
+    pub directory: String,
+
+    /// Directory holding the ACME account and the issued certificate
+    pub cache_dir: PathBuf,
+
+    /// Renew the certificate this many days before it expires
+    #[serde(default = "default_renew_before")]
+    pub renew_before: u64,
+}
+
+/// Picks the issued certificate, or the challenge certificate for validation connections
+#[derive(Default)]
+pub struct CertResolver {
+    cert: RwLock<Option<Arc<CertifiedKey>>>,
+    challenges: RwLock<HashMap<String, Arc<CertifiedKey>>>,
+}
+
+impl ResolvesServerCert for CertResolver {
+    fn resolve(&self, client_hello: ClientHello) -> Option<Arc<CertifiedKey>> {
+        let is_challenge = client_hello
+            .alpn()
+            .map_or(false, |mut protocols| protocols.any(|p| p == ACME_TLS_ALPN));
+
+        if is_challenge {
+            let domain = client_hello.server_name()?;
+            self.challenges.read().unwrap().get(domain).cloned()
+        } else {
+            self.cert.read().unwrap().clone()
+        }
+    }
+}
+
+/// Build a certified key from DER encoded certificates and private key
+fn certified_key(certs: Vec<Vec<u8>>, key: Vec<u8>) -> eyre::Result<Arc<CertifiedKey>> {
+    let key = sign::any_supported_type(&rustls::PrivateKey(key))
+        .map_err(|_| eyre::eyre!("unsupported private key type"))?;
+
+    Ok(Arc::new(CertifiedKey::new(
+        certs.into_iter().map(rustls::Certificate).collect(),
+        key,
+    )))
+}
+
+/// Self-signed certificate answering a TLS-ALPN-01 challenge
+fn challenge_cert(domain: &str, digest: &[u8]) -> eyre::Result<Arc<CertifiedKey>> {
+    let mut params = rcgen::CertificateParams::new(vec![domain.to_owned()]);
+    params.custom_extensions = vec![rcgen::CustomExtension::new_acme_identifier(digest)];
+
+    let cert = rcgen::Certificate::from_params(params)?;
+    certified_key(
+        vec![cert.serialize_der()?],
+        cert.serialize_private_key_der(),
+    )
+}
+
+/// Write a file only readable by its owner
+fn write_private(path: &Path, contents: &[u8]) -> eyre::Result<()> {
+    let mut options = OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+
+    options
+        .open(path)
+        .and_then(|mut file| file.write_all(contents))
+        .wrap_err_with(|| format!("could not write {}", path.display()))
+}
+
+impl AcmeConfig {
+    /// TLS configuration serving the certificates of the resolver
+    pub fn server_config(&self, resolver: Arc<CertResolver>) -> rustls::ServerConfig {
+        let mut config = rustls::ServerConfig::builder()
+            .with_safe_defaults()
+            .with_no_client_auth()
+            .with_cert_resolver(resolver);
+
+        config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec(), ACME_TLS_ALPN.to_vec()];
+        config
+    }
+
+    fn cert_path(&self) -> PathBuf {
+        self.cache_dir.join("cert.pem")
+    }
+
+    fn key_path(&self) -> PathBuf {
+        self.cache_dir.join("key.pem")
+    }
+
+    fn account_path(&self) -> PathBuf {
+        self.cache_dir.join("account.json")
+    }
+
+    /// Load the cached certificate into the resolver, returning its expiry time
+    fn load(&self, resolver: &CertResolver) -> eyre::Result<Option<SystemTime>> {
+        if !self.cert_path().exists() || !self.key_path().exists() {
+            return Ok(None);
+        }
+
+        let certs = rustls_pemfile::certs(&mut BufReader::new(fs::File::open(self.cert_path())?))?;
+        let key = rustls_pemfile::pkcs8_private_keys(&mut BufReader::new(fs::File::open(
+            self.key_path(),
+        )?))?
+        .into_iter()
+        .next()
+        .ok_or_else(|| eyre::eyre!("no private key in {}", self.key_path().display()))?;
+
+        let not_after = x509_parser::parse_x509_certificate(
+            certs
+                .first()
+                .ok_or_else(|| eyre::eyre!("no certificate in {}", self.cert_path().display()))?,
+        )?
+        .1
+        .validity()
+        .not_after
+        .timestamp();
+
+        *resolver.cert.write().unwrap() = Some(certified_key(certs, key)?);
+        Ok(Some(
+            UNIX_EPOCH + Duration::from_secs(not_after.try_into().unwrap_or_default()),
+        ))
+    }
+
+    /// Keep the certificate of the resolver valid
+    pub async fn run(self, resolver: Arc<CertResolver>) {
+        let renew_before = Duration::from_secs(self.renew_before * 24 * 3600);
+
+        loop {
+            let remaining = match self.load(&resolver) {
+                Ok(Some(expiry)) => expiry.duration_since(SystemTime::now()).unwrap_or_default(),
+                Ok(None) => Duration::ZERO,
+                Err(err) => {
+                    warn!(%err, "could not load cached certificate");
+                    Duration::ZERO
+                }
+            };
+
+            if remaining > renew_before {
+                tokio::time::sleep((remaining - renew_before).min(CHECK_INTERVAL)).await;
+                continue;
+            }
+
+            info!(domains = ?self.domains, "requesting certificate");
+            if let Err(err) = self.renew(&resolver).await {
+                error!(%err, "could not obtain certificate");
+                tokio::time::sleep(RETRY_DELAY).await;
+            }
+        }
+    }
+
+    /// Load the ACME account, or create it
+    async fn account(&self) -> eyre::Result<Account> {
+        if self.account_path().exists() {
+            let credentials: AccountCredentials =
+                serde_json::from_slice(&fs::read(self.account_path())?)?;
+            return Ok(Account::from_credentials(credentials).await?);
+        }
+
+        let contact: Vec<&str> = self.contact.iter().map(String::as_str).collect();
+        let (account, credentials) = Account::create(
+            &NewAccount {
+                contact: &contact,
+                terms_of_service_agreed: true,
+                only_return_existing: false,
+            },
+            &self.directory,
+            None,
+        )
+        .await?;
+
+        write_private(&self.account_path(), &serde_json::to_vec(&credentials)?)?;
+        Ok(account)
+    }
+
+    /// Order a new certificate and store it in the cache directory
+    async fn renew(&self, resolver: &CertResolver) -> eyre::Result<()> {
+        fs::create_dir_all(&self.cache_dir)?;
+
+        let account = self.account().await?;
+        let identifiers: Vec<_> = self.domains.iter().cloned().map(Identifier::Dns).collect();
+        let mut order = account
+            .new_order(&NewOrder {
+                identifiers: &identifiers,
+            })
+            .await?;
+
+        let validation = validate(&mut order, resolver).await;
+        resolver.challenges.write().unwrap().clear();
+        validation?;
+
+        let mut params = rcgen::CertificateParams::new(self.domains.clone());
+        params.distinguished_name = rcgen::DistinguishedName::new();
+        let cert = rcgen::Certificate::from_params(params)?;
+        order.finalize(&cert.serialize_request_der()?).await?;
+
+        let chain = loop {
+            match order.certificate().await? {
+                Some(chain) => break chain,
+                None => tokio::time::sleep(Duration::from_secs(1)).await,
+            }
+        };
+
+        write_private(
+            &self.key_path(),
+            cert.serialize_private_key_pem().as_bytes(),
+        )?;
+        fs::write(self.cert_path(), chain)?;
+
+        info!(domains = ?self.domains, "obtained certificate");
+        Ok(())
+    }
+}
+
+/// Answer the TLS-ALPN-01 challenges of an order, until it is ready to be finalized
+async fn validate(order: &mut Order, resolver: &CertResolver) -> eyre::Result<()> {
+    for authorization in order.authorizations().await? {
+        if let AuthorizationStatus::Valid = authorization.status {
+            continue;
+        }
+
+        let Identifier::Dns(domain) = &authorization.identifier;
+        let challenge = authorization
+            .challenges
+            .iter()
+            .find(|challenge| challenge.r#type == ChallengeType::TlsAlpn01)
+            .ok_or_else(|| eyre::eyre!("no tls-alpn-01 challenge offered for {domain}"))?;
+
+        let key_authorization = order.key_authorization(challenge);
+        resolver.challenges.write().unwrap().insert(
+            domain.clone(),
+            challenge_cert(domain, key_authorization.digest().as_ref())?,
+        );
+
+        order.set_challenge_ready(&challenge.url).await?;
+    }
+
+    let mut delay = Duration::from_secs(1);
+    for _ in 0..10 {
+        tokio::time::sleep(delay).await;
+
+        match order.refresh().await?.status {
+            OrderStatus::Ready => return Ok(()),
+            OrderStatus::Invalid => eyre::bail!("domain validation failed"),
+            _ => delay = (delay * 2).min(Duration::from_secs(30)),
+        }
+    }
+
+    eyre::bail!("timed out waiting for domain validation")
+}
@@ -0,0 +1,135 @@
+//! Scopes of API tokens, which users issue for automation tools
+//!
+//! A token acts on behalf of the user who issued it, with the permissions of their ACL narrowed
+//! down to the methods and download dir of its scope.
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{acl::Acl, rpc::MethodName};
+
+/// Permissions of a token, within the ones of the ACL of its owner
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TokenScope {
+    /// RPC methods the token may call, all the ones of the ACL if empty
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub methods: Vec<MethodName>,
+
+    /// Directory the torrents of the token must be in, inside the download dir of the ACL
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub download_dir: Option<String>,
+}
+
+#[derive(Debug, Error)]
+pub enum ScopeError {
+    #[error("the ACL doesn't allow the {0:?} method")]
+    MethodNotAllowed(MethodName),
+    #[error("{0} is not inside the download dir of the ACL")]
+    DirNotAllowed(String),
+}
+
+/// Returns true if `dir` is `parent` or one of its subdirectories
+pub(crate) fn within(dir: &str, parent: &str) -> bool {
+    if dir.split('/').any(|segment| segment == "..") {
+        return false;
+    }
+
+    let parent = parent.trim_end_matches('/');
+    dir.trim_end_matches('/') == parent || dir.starts_with(&(parent.to_owned() + "/"))
+}
+
+impl TokenScope {
+    /// Check that the scope is narrower than the given ACL, when issuing a token
+    pub fn check(&self, acl: &Acl) -> Result<(), ScopeError> {
+        if let Some(method) = self.methods.iter().find(|method| !acl.allows(**method)) {
+            return Err(ScopeError::MethodNotAllowed(*method));
+        }
+
+        match (&self.download_dir, &acl.download_dir) {
+            (Some(dir), Some(parent)) if !within(dir, parent) => {
+                Err(ScopeError::DirNotAllowed(dir.clone()))
+            }
+            (Some(dir), None) if !within(dir, "/") => Err(ScopeError::DirNotAllowed(dir.clone())),
+            _ => Ok(()),
+        }
+    }
+
+    /// ACL of requests made with the token: the intersection of the scope and the ACL. Since the
+    /// ACL may have changed since the token was issued, what it doesn't allow anymore is denied.
+    pub fn narrow(&self, acl: &Acl) -> Acl {
+        let mut narrowed = acl.clone();
+        // Tokens are meant for RPC clients, not for administration
+        narrowed.admin = false;
+
+        if !self.methods.is_empty() {
+            narrowed.allowed_methods = self
+                .methods
+                .iter()
+                .copied()
+                .filter(|method| acl.allows(*method))
+                .collect();
+            narrowed.deny |= narrowed.allowed_methods.is_empty();
+        }
+
+        if let Some(dir) = &self.download_dir {
+            narrowed.deny |= !within(dir, acl.download_dir.as_deref().unwrap_or("/"));
+            narrowed.download_dir = Some(dir.clone());
+        }
+
+        narrowed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn acl() -> Acl {
+        serde_yaml::from_str(
+            "download_dir: /data/alice\nallowed_methods: [torrent-get, torrent-add, torrent-remove]\nadmin: true",
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn scopes_narrow_acls() {
+        let scope = TokenScope {
+            methods: vec![MethodName::TorrentGet, MethodName::TorrentAdd],
+            download_dir: Some("/data/alice/tv".to_owned()),
+        };
+        scope.check(&acl()).unwrap();
+
+        let narrowed = scope.narrow(&acl());
+        assert!(!narrowed.deny);
+        assert!(!narrowed.admin);
+        assert!(narrowed.allows(MethodName::TorrentAdd));
+        assert!(!narrowed.allows(MethodName::TorrentRemove));
+        assert_eq!(narrowed.download_dir.as_deref(), Some("/data/alice/tv"));
+    }
+
+    #[test]
+    fn scopes_cannot_widen_acls() {
+        for scope in [
+            TokenScope {
+                methods: vec![MethodName::SessionSet],
+                ..Default::default()
+            },
+            TokenScope {
+                download_dir: Some("/data/bob".to_owned()),
+                ..Default::default()
+            },
+            TokenScope {
+                download_dir: Some("/data/alice/../bob".to_owned()),
+                ..Default::default()
+            },
+            TokenScope {
+                download_dir: Some("/data/alice-other".to_owned()),
+                ..Default::default()
+            },
+        ] {
+            assert!(scope.check(&acl()).is_err(), "{scope:?}");
+            assert!(scope.narrow(&acl()).deny, "{scope:?}");
+        }
+    }
+}
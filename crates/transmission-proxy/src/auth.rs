@@ -1,10 +1,32 @@
 use std::collections::HashMap;
 
+use color_eyre::eyre;
+
+use schemars::JsonSchema;
 use secrecy::{ExposeSecret, SecretString};
 use serde::{Deserialize, Serialize};
 use tokio::sync::Mutex;
 use tracing::warn;
 
+use crate::{
+    password,
+    secret_file::{SecretFile, SecretValue},
+    totp,
+};
+
+#[cfg(feature = "oauth")]
+use oauth2::{AuthUrl, ClientId, ClientSecret, TokenUrl};
+
+// Builds without OAuth2 still parse provider settings, to reject them with a clear error
+#[cfg(not(feature = "oauth"))]
+type ClientId = String;
+#[cfg(not(feature = "oauth"))]
+type ClientSecret = String;
+#[cfg(not(feature = "oauth"))]
+type AuthUrl = url::Url;
+#[cfg(not(feature = "oauth"))]
+type TokenUrl = url::Url;
+
 fn default_true() -> bool {
     true
 }
@@ -20,52 +42,220 @@ pub enum AuthUser {
         username: String,
         provider: String,
     },
+    /// User of a logical account, logged in with one of its identities
+    Linked {
+        account: String,
+        identity: Box<AuthUser>,
+    },
 }
 
 impl AuthUser {
     pub fn is_anonymous(&self) -> bool {
         matches!(self, AuthUser::Anonymous)
     }
+
+    /// Name of the user, which is the name of their account for linked users
+    pub fn username(&self) -> Option<&str> {
+        match self {
+            AuthUser::Anonymous => None,
+            AuthUser::Basic { username, .. } | AuthUser::OAuth2 { username, .. } => {
+                Some(username.as_str())
+            }
+            AuthUser::Linked { account, .. } => Some(account.as_str()),
+        }
+    }
+
+    /// Identity the user logged in with
+    pub fn identity(&self) -> &AuthUser {
+        match self {
+            AuthUser::Linked { identity, .. } => identity.identity(),
+            user => user,
+        }
+    }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
 #[serde(deny_unknown_fields)]
 pub struct BasicAuthUser {
     pub username: String,
     pub password: String,
+    /// Base32 TOTP secret. If set, the user must also enter a code from their authenticator app.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub totp_secret: Option<String>,
+    /// Address for notifications about the user
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub email: Option<String>,
+}
+
+impl SecretValue for Vec<BasicAuthUser> {
+    fn parse(text: &str) -> eyre::Result<Self> {
+        Ok(serde_yaml::from_str(text)?)
+    }
+}
+
+fn default_client_user_agents() -> Vec<String> {
+    vec!["transmission-remote".into()]
 }
 
-#[derive(Default, Debug, Serialize, Deserialize)]
+/// When unauthenticated RPC requests get a basic auth challenge
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "kebab-case")]
+pub enum RpcBasicAuth {
+    /// Only for the client apps listed in `client_user_agents`, others are redirected to the
+    /// login page
+    #[default]
+    UserAgents,
+    /// For all RPC requests
+    Always,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
 #[serde(deny_unknown_fields)]
 pub struct BasicAuthProvider {
     pub enabled: bool,
     #[serde(default = "default_true")]
     pub visible: bool,
+    #[serde(default)]
     pub users: Vec<BasicAuthUser>,
 
+    /// YAML file with more users, read again when it changes
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub users_file: Option<SecretFile<Vec<BasicAuthUser>>>,
+
+    /// When to answer unauthenticated RPC requests with a basic auth challenge
+    #[serde(default)]
+    pub rpc_basic_auth: RpcBasicAuth,
+
+    /// User agent prefixes of client apps which can only use basic auth
+    #[serde(default = "default_client_user_agents")]
+    pub client_user_agents: Vec<String>,
+
     #[serde(skip)]
     verify_cache: Mutex<HashMap<String, SecretString>>,
 }
 
+impl Default for BasicAuthProvider {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            visible: true,
+            users: Vec::new(),
+            users_file: None,
+            rpc_basic_auth: Default::default(),
+            client_user_agents: default_client_user_agents(),
+            verify_cache: Default::default(),
+        }
+    }
+}
+
 impl BasicAuthProvider {
+    /// Check that the users file can be read and that TOTP secrets are valid base32
+    pub fn validate(&self) -> eyre::Result<()> {
+        let file_users = match &self.users_file {
+            Some(file) => file.get()?,
+            None => Default::default(),
+        };
+
+        for user in self.users.iter().chain(file_users.iter()) {
+            if let Some(secret) = &user.totp_secret {
+                if totp::decode_secret(secret).is_none() {
+                    eyre::bail!("invalid totp_secret for {}, expected base32", user.username);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Look up a user, in the configuration first and then in the users file
+    fn find<R>(&self, user: &str, f: impl FnOnce(&BasicAuthUser) -> R) -> Option<R> {
+        if let Some(entry) = self.users.iter().find(|entry| entry.username == user) {
+            return Some(f(entry));
+        }
+
+        let file_users = match self.users_file.as_ref()?.get() {
+            Ok(users) => users,
+            Err(err) => {
+                warn!(%err, "could not read the basic auth users file");
+                return None;
+            }
+        };
+
+        file_users
+            .iter()
+            .find(|entry| entry.username == user)
+            .map(f)
+    }
+
+    /// Names of the configured users
+    pub fn usernames(&self) -> Vec<String> {
+        let file_users = self
+            .users_file
+            .as_ref()
+            .and_then(|file| file.get().ok())
+            .unwrap_or_default();
+
+        self.users
+            .iter()
+            .chain(file_users.iter())
+            .map(|entry| entry.username.clone())
+            .collect()
+    }
+
+    /// Returns true if the user is configured
+    pub fn has_user(&self, user: &str) -> bool {
+        self.find(user, |_| ()).is_some()
+    }
+
+    /// Address for notifications about the user, if set
+    pub fn email_of(&self, user: &str) -> Option<String> {
+        self.find(user, |entry| entry.email.clone()).flatten()
+    }
+
+    /// Returns true if the user needs a TOTP code on top of their password
+    pub fn requires_totp(&self, user: &str) -> bool {
+        self.find(user, |entry| entry.totp_secret.is_some())
+            .unwrap_or(false)
+    }
+
+    /// Check a TOTP code for the user
+    pub fn verify_totp(&self, user: &str, code: &str) -> bool {
+        self.find(user, |entry| entry.totp_secret.clone())
+            .flatten()
+            .as_deref()
+            .and_then(totp::decode_secret)
+            .map_or(false, |secret| totp::verify(&secret, code))
+    }
+
+    /// true if an unauthenticated request should get a basic auth challenge instead of being
+    /// redirected to the login page
+    pub fn challenges(&self, is_rpc: bool, user_agent: Option<&[u8]>) -> bool {
+        match self.rpc_basic_auth {
+            RpcBasicAuth::Always if is_rpc => true,
+            _ => user_agent.map_or(false, |user_agent| {
+                self.client_user_agents
+                    .iter()
+                    .any(|prefix| user_agent.starts_with(prefix.as_bytes()))
+            }),
+        }
+    }
+
     pub async fn auth(&self, user: &str, password: &SecretString) -> bool {
-        if let Some(basic_auth_user) = self.users.iter().find(|entry| entry.username == user) {
+        if let Some(hash) = self.find(user, |entry| entry.password.clone()) {
             let mut verify_cache = self.verify_cache.lock().await;
 
-            // Check the cache first to skip bcrypt verification
-            if let Some(already_verified) = verify_cache.get(&basic_auth_user.username) {
+            // Check the cache first to skip hash verification. It is keyed by hash, so changed
+            // passwords are verified again.
+            if let Some(already_verified) = verify_cache.get(&hash) {
                 return already_verified.expose_secret().as_str()
                     == password.expose_secret().as_str();
             }
 
-            // If not found, verify with bcrypt
-            match bcrypt::verify(
-                password.expose_secret().as_bytes(),
-                &basic_auth_user.password,
-            ) {
+            // If not found, verify with the algorithm of the stored hash
+            match password::verify(password.expose_secret().as_bytes(), &hash) {
                 Ok(result) => {
                     if result {
-                        verify_cache.insert(basic_auth_user.username.to_string(), password.clone());
+                        verify_cache.insert(hash, password.clone());
                     }
 
                     return result;
@@ -80,7 +270,7 @@ impl BasicAuthProvider {
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct OAuth2Provider {
     pub name: String,
     #[serde(default = "default_true")]
@@ -88,25 +278,152 @@ pub struct OAuth2Provider {
     #[serde(default = "default_true")]
     pub visible: bool,
 
-    pub client_id: oauth2::ClientId,
-    pub client_secret: oauth2::ClientSecret,
-    pub auth_url: oauth2::AuthUrl,
-    pub token_url: oauth2::TokenUrl,
+    #[schemars(with = "String")]
+    pub client_id: ClientId,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schemars(with = "Option<String>")]
+    pub client_secret: Option<ClientSecret>,
+    /// File holding the client secret, read again when it changes
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client_secret_file: Option<SecretFile>,
+    #[schemars(with = "url::Url")]
+    pub auth_url: AuthUrl,
+    #[schemars(with = "url::Url")]
+    pub token_url: TokenUrl,
     pub userinfo_url: url::Url,
     pub email_path: String,
+
+    /// JSONPath of the flag telling that the provider verified the email of the user, e.g.
+    /// `$.email_verified`. Users are refused unless it is true.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub email_verified_path: Option<String>,
     #[serde(default = "default_scopes")]
     pub scopes: String,
+
+    /// Only let users with an email in these domains log in
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub allowed_domains: Vec<String>,
+
+    /// Only let users with these emails log in, on top of those in `allowed_domains`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub allowed_emails: Vec<String>,
 }
 
 fn default_scopes() -> String {
     "email".into()
 }
 
-#[derive(Default, Debug, Serialize, Deserialize)]
+impl OAuth2Provider {
+    /// Returns true if the user with the given email may log in with this provider
+    pub fn allows(&self, email: &str) -> bool {
+        if self.allowed_domains.is_empty() && self.allowed_emails.is_empty() {
+            return true;
+        }
+
+        let domain = email.rsplit_once('@').map(|(_, domain)| domain);
+        self.allowed_emails
+            .iter()
+            .any(|allowed| allowed.eq_ignore_ascii_case(email))
+            || domain.map_or(false, |domain| {
+                self.allowed_domains
+                    .iter()
+                    .any(|allowed| allowed.eq_ignore_ascii_case(domain))
+            })
+    }
+
+    /// Check that exactly one of the client secret and its file is set
+    pub fn validate(&self) -> eyre::Result<()> {
+        match (&self.client_secret, &self.client_secret_file) {
+            (Some(_), Some(_)) => eyre::bail!(
+                "client_secret and client_secret_file are both set for provider {}",
+                self.name
+            ),
+            (None, None) => eyre::bail!(
+                "client_secret or client_secret_file is required for provider {}",
+                self.name
+            ),
+            (None, Some(file)) => file.get().map(|_| ()),
+            (Some(_), None) => Ok(()),
+        }
+    }
+
+    /// Current client secret
+    #[cfg(feature = "oauth")]
+    pub fn client_secret(&self) -> eyre::Result<ClientSecret> {
+        match (&self.client_secret, &self.client_secret_file) {
+            (Some(secret), _) => Ok(secret.clone()),
+            (None, Some(file)) => Ok(ClientSecret::new(file.get()?.to_string())),
+            (None, None) => eyre::bail!("no client secret for provider {}", self.name),
+        }
+    }
+}
+
+/// Passkey logins, for users who registered a passkey while logged in with another provider
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct WebauthnProvider {
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default = "default_true")]
+    pub visible: bool,
+
+    /// Domain passkeys are bound to, the host of the public URL by default
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rp_id: Option<String>,
+
+    /// Origin of the login page, derived from the public URL by default
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub origin: Option<String>,
+}
+
+impl Default for WebauthnProvider {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            visible: true,
+            rp_id: None,
+            origin: None,
+        }
+    }
+}
+
+#[derive(Default, Debug, Serialize, Deserialize, JsonSchema)]
 #[serde(deny_unknown_fields)]
 pub struct Providers {
     #[serde(default)]
     pub basic: BasicAuthProvider,
     #[serde(default)]
     pub oauth2: Vec<OAuth2Provider>,
+    #[serde(default)]
+    pub webauthn: WebauthnProvider,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn oauth2_logins_follow_the_allowlists() {
+        let provider: OAuth2Provider = serde_yaml::from_str(
+            r#"
+name: google
+client_id: id
+client_secret: secret
+auth_url: https://accounts.google.com/o/oauth2/v2/auth
+token_url: https://www.googleapis.com/oauth2/v3/token
+userinfo_url: https://www.googleapis.com/oauth2/v3/userinfo
+email_path: $.email
+allowed_domains: [example.org]
+allowed_emails: [friend@gmail.com]
+"#,
+        )
+        .unwrap();
+
+        assert!(provider.allows("alice@example.org"));
+        assert!(provider.allows("Alice@EXAMPLE.org"));
+        assert!(provider.allows("friend@gmail.com"));
+        assert!(!provider.allows("stranger@gmail.com"));
+        assert!(!provider.allows("alice@sub.example.org"));
+        assert!(!provider.allows("example.org"));
+    }
 }
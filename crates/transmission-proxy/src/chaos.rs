@@ -0,0 +1,102 @@
+//! Faults injected into the RPC calls of clients, to test how they cope with a misbehaving
+//! daemon
+//!
+//! Faults are only available in builds with the `chaos` feature, and set at runtime by admins.
+
+use std::{io, sync::Mutex, time::Duration};
+
+use hyper::{body::Bytes, Body, Response, StatusCode};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+/// Faults injected into the RPC calls of clients
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Faults {
+    /// Delay added before forwarding calls, in milliseconds
+    #[serde(default)]
+    pub delay: u64,
+
+    /// Percentage of calls answered with a 500 error instead of being forwarded
+    #[serde(default)]
+    pub error_percent: f64,
+
+    /// Percentage of calls whose connection is dropped instead of being answered
+    #[serde(default)]
+    pub drop_percent: f64,
+}
+
+impl Faults {
+    fn validate(&self) -> Result<(), &'static str> {
+        let percent = 0. ..=100.;
+        if !percent.contains(&self.error_percent) || !percent.contains(&self.drop_percent) {
+            return Err("percentages must be between 0 and 100");
+        }
+
+        if self.error_percent + self.drop_percent > 100. {
+            return Err("error_percent and drop_percent add up to more than 100");
+        }
+
+        Ok(())
+    }
+}
+
+/// Outcome of a call with faults injected
+pub enum Fault {
+    /// Answer with a 500 error
+    Error,
+    /// Drop the connection
+    Drop,
+}
+
+impl Fault {
+    pub fn response(&self) -> Response<Body> {
+        match self {
+            Self::Error => Response::builder()
+                .status(StatusCode::INTERNAL_SERVER_ERROR)
+                .body(Body::from("Injected fault"))
+                .unwrap(),
+            // Failing the body makes the server close the connection mid-response
+            Self::Drop => Response::new(Body::wrap_stream(futures_util::stream::once(async {
+                Err::<Bytes, _>(io::Error::new(
+                    io::ErrorKind::ConnectionAborted,
+                    "injected fault",
+                ))
+            }))),
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct Chaos {
+    faults: Mutex<Faults>,
+}
+
+impl Chaos {
+    pub fn faults(&self) -> Faults {
+        self.faults.lock().unwrap().clone()
+    }
+
+    pub fn set_faults(&self, faults: Faults) -> Result<(), &'static str> {
+        faults.validate()?;
+        *self.faults.lock().unwrap() = faults;
+        Ok(())
+    }
+
+    /// Apply the delay of the current faults, and pick the fault of a call, if any
+    pub async fn inject(&self) -> Option<Fault> {
+        let faults = self.faults();
+        if faults.delay > 0 {
+            tokio::time::sleep(Duration::from_millis(faults.delay)).await;
+        }
+
+        let roll = rand::thread_rng().gen_range(0. ..100.);
+        if roll < faults.error_percent {
+            Some(Fault::Error)
+        } else if roll < faults.error_percent + faults.drop_percent {
+            Some(Fault::Drop)
+        } else {
+            None
+        }
+    }
+}
@@ -0,0 +1,160 @@
+//! Profiles of known client apps, adjusting how the proxy treats their requests
+//!
+//! Profiles are matched on the prefix of the user agent of requests, and the first matching
+//! profile applies. The default profiles cover common client apps which can only log in with
+//! basic auth, besides `transmission-remote` which is covered by `client_user_agents`.
+
+use std::{
+    borrow::Cow,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use crate::{
+    rpc::{MethodCall, Request},
+    state::SharedState,
+};
+
+/// Length of the windows RPC calls are counted in for rate limits
+const RATE_WINDOW: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct ClientProfile {
+    /// Name of the client app, for logs
+    pub name: String,
+
+    /// User agent prefixes of the client app
+    pub user_agents: Vec<String>,
+
+    /// Answer unauthenticated requests with a basic auth challenge, instead of redirecting to
+    /// the login page
+    #[serde(default)]
+    pub basic_auth: bool,
+
+    /// Torrent fields added to torrent-get calls, for clients which expect them without asking
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub torrent_fields: Vec<String>,
+
+    /// Never answer torrent-get polls with 304 Not Modified
+    #[serde(default)]
+    pub disable_etag: bool,
+
+    /// RPC calls allowed per minute for each user, unlimited if unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rate_limit: Option<u32>,
+}
+
+impl ClientProfile {
+    fn new(name: &str, user_agents: &[&str]) -> Self {
+        Self {
+            name: name.to_owned(),
+            user_agents: user_agents
+                .iter()
+                .map(|prefix| (*prefix).to_owned())
+                .collect(),
+            basic_auth: true,
+            torrent_fields: Vec::new(),
+            disable_etag: false,
+            rate_limit: None,
+        }
+    }
+
+    fn matches(&self, user_agent: &[u8]) -> bool {
+        self.user_agents
+            .iter()
+            .any(|prefix| user_agent.starts_with(prefix.as_bytes()))
+    }
+
+    /// Returns true if the profile changes the RPC calls of the client
+    pub fn rewrites_requests(&self) -> bool {
+        !self.torrent_fields.is_empty()
+    }
+
+    /// Add the fields of the profile to torrent-get calls
+    pub fn apply(&self, request: &mut Request) {
+        let MethodCall::TorrentGet { arguments } = &mut request.call else {
+            return;
+        };
+
+        for field in &self.torrent_fields {
+            if !arguments.fields.iter().any(|requested| requested == field) {
+                arguments.fields.push(Cow::Owned(field.clone()));
+            }
+        }
+    }
+
+    /// Count an RPC call of the user, returning false if it goes over the rate limit.
+    ///
+    /// Calls are counted in the shared state, so the limit holds across replicas.
+    pub async fn allows_call(&self, user: &str, state: &SharedState) -> bool {
+        let Some(limit) = self.rate_limit else {
+            return true;
+        };
+
+        let window = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs()
+            / RATE_WINDOW.as_secs();
+        let key = format!("rate-limit:{}:{user}:{window}", self.name);
+
+        match state.incr(&key, RATE_WINDOW).await {
+            Ok(calls) => calls <= u64::from(limit),
+            Err(err) => {
+                warn!(%err, client = %self.name, "could not count rpc call");
+                true
+            }
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+#[serde(transparent)]
+pub struct ClientProfiles {
+    profiles: Vec<ClientProfile>,
+}
+
+impl Default for ClientProfiles {
+    fn default() -> Self {
+        Self {
+            profiles: vec![
+                ClientProfile::new("Transmission Remote GUI", &["Transmission Remote GUI"]),
+                ClientProfile::new("Transdroid", &["Transdroid"]),
+                ClientProfile::new("Flood", &["Flood"]),
+            ],
+        }
+    }
+}
+
+impl ClientProfiles {
+    /// Profile of the client app sending a request, if it is known
+    pub fn find(&self, user_agent: Option<&[u8]>) -> Option<&ClientProfile> {
+        let user_agent = user_agent?;
+        self.profiles
+            .iter()
+            .find(|profile| profile.matches(user_agent))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn calls_over_the_rate_limit_are_refused() {
+        let mut profile = ClientProfile::new("test", &["test"]);
+        profile.rate_limit = Some(2);
+        let state = SharedState::new(None, None).unwrap();
+
+        assert!(profile.allows_call("alice", &state).await);
+        assert!(profile.allows_call("alice", &state).await);
+        assert!(!profile.allows_call("alice", &state).await);
+
+        // Users have their own limit
+        assert!(profile.allows_call("bob", &state).await);
+    }
+}
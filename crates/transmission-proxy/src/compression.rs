@@ -0,0 +1,196 @@
+use std::io::{Read, Write};
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+/// Content encodings the proxy can produce
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Encoding {
+    Zstd,
+    #[serde(rename = "br")]
+    Brotli,
+    Gzip,
+}
+
+impl Encoding {
+    /// Token of the encoding in Accept-Encoding and Content-Encoding headers
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Zstd => "zstd",
+            Self::Brotli => "br",
+            Self::Gzip => "gzip",
+        }
+    }
+}
+
+fn default_min_size() -> usize {
+    1024
+}
+
+fn default_encodings() -> Vec<Encoding> {
+    vec![Encoding::Zstd, Encoding::Brotli, Encoding::Gzip]
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_max_decoded_size() -> usize {
+    32 * 1024 * 1024
+}
+
+fn default_gzip_level() -> u32 {
+    6
+}
+
+fn default_brotli_level() -> u32 {
+    5
+}
+
+fn default_zstd_level() -> i32 {
+    3
+}
+
+/// Compression of the responses generated by the proxy: RPC calls and API endpoints
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct CompressionConfig {
+    /// Compress responses for clients which accept it
+    #[serde(default)]
+    pub enabled: bool,
+
+    /// Smallest response body that gets compressed, in bytes
+    #[serde(default = "default_min_size")]
+    pub min_size: usize,
+
+    /// Supported encodings, in order of preference
+    #[serde(default = "default_encodings")]
+    pub encodings: Vec<Encoding>,
+
+    /// gzip compression level, from 0 to 9
+    #[serde(default = "default_gzip_level")]
+    pub gzip_level: u32,
+
+    /// Brotli compression level, from 0 to 11
+    #[serde(default = "default_brotli_level")]
+    pub brotli_level: u32,
+
+    /// zstd compression level, from 1 to 22
+    #[serde(default = "default_zstd_level")]
+    pub zstd_level: i32,
+
+    /// Decode gzip and deflate compressed RPC request bodies before filtering them
+    #[serde(default = "default_true")]
+    pub decode_requests: bool,
+
+    /// Largest decoded RPC request body, in bytes
+    #[serde(default = "default_max_decoded_size")]
+    pub max_decoded_size: usize,
+}
+
+impl Default for CompressionConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            min_size: default_min_size(),
+            encodings: default_encodings(),
+            gzip_level: default_gzip_level(),
+            brotli_level: default_brotli_level(),
+            zstd_level: default_zstd_level(),
+            decode_requests: default_true(),
+            max_decoded_size: default_max_decoded_size(),
+        }
+    }
+}
+
+impl CompressionConfig {
+    /// Pick the preferred encoding accepted by the client, given its Accept-Encoding header
+    pub fn negotiate(&self, accept_encoding: &str) -> Option<Encoding> {
+        let accepted: Vec<(&str, bool)> = accept_encoding
+            .split(',')
+            .filter_map(|item| {
+                let mut params = item.split(';').map(str::trim);
+                let token = params.next().filter(|token| !token.is_empty())?;
+                let refused = params
+                    .filter_map(|param| param.strip_prefix("q="))
+                    .any(|q| q.parse::<f32>().map_or(false, |q| q <= 0.));
+
+                Some((token, !refused))
+            })
+            .collect();
+
+        let accepts = |token: &str| {
+            accepted
+                .iter()
+                .find(|(accepted, _)| accepted.eq_ignore_ascii_case(token))
+                .or_else(|| accepted.iter().find(|(accepted, _)| *accepted == "*"))
+                .map_or(false, |(_, ok)| *ok)
+        };
+
+        self.encodings
+            .iter()
+            .copied()
+            .find(|encoding| accepts(encoding.as_str()))
+    }
+
+    /// Compress a response body with the given encoding
+    pub fn compress(&self, encoding: Encoding, body: &[u8]) -> std::io::Result<Vec<u8>> {
+        match encoding {
+            Encoding::Zstd => zstd::encode_all(body, self.zstd_level),
+            Encoding::Brotli => {
+                let mut output = Vec::new();
+                {
+                    let mut writer = brotli::CompressorWriter::new(
+                        &mut output,
+                        4096,
+                        self.brotli_level.min(11),
+                        22,
+                    );
+                    writer.write_all(body)?;
+                }
+                Ok(output)
+            }
+            Encoding::Gzip => {
+                let mut encoder = flate2::write::GzEncoder::new(
+                    Vec::new(),
+                    flate2::Compression::new(self.gzip_level.min(9)),
+                );
+                encoder.write_all(body)?;
+                encoder.finish()
+            }
+        }
+    }
+}
+
+/// Decode a request body sent with the given Content-Encoding, if it is gzip or deflate. Bodies
+/// larger than `max_size` once decoded are rejected.
+pub fn decode_request(
+    content_encoding: &str,
+    body: &[u8],
+    max_size: usize,
+) -> Option<std::io::Result<Vec<u8>>> {
+    let decode = |reader: &mut dyn Read| {
+        let mut output = Vec::new();
+        reader.take(max_size as u64 + 1).read_to_end(&mut output)?;
+
+        if output.len() > max_size {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "decoded body is too large",
+            ));
+        }
+
+        Ok(output)
+    };
+
+    Some(
+        match content_encoding.trim().to_ascii_lowercase().as_str() {
+            "gzip" | "x-gzip" => decode(&mut flate2::read::GzDecoder::new(body)),
+            // Some clients send raw deflate streams instead of zlib ones
+            "deflate" => decode(&mut flate2::read::ZlibDecoder::new(body))
+                .or_else(|_| decode(&mut flate2::read::DeflateDecoder::new(body))),
+            _ => return None,
+        },
+    )
+}
@@ -1,14 +1,313 @@
+use std::{collections::HashMap, sync::Arc};
+
+use color_eyre::eyre;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
+use serde_yaml::Value;
+use tracing::warn;
 
-use crate::{acl::Acls, auth::Providers};
+use crate::{
+    accounts::Accounts,
+    acl::{Acl, AclIdentity, Acls, TrackerRule},
+    auth::Providers,
+    client_profiles::ClientProfiles,
+    compression::CompressionConfig,
+    cors::Cors,
+    csrf::CsrfProtection,
+    custom_routes::CustomRoute,
+    failover::Failover,
+    forwarding::{HeaderPolicy, IdentityHeaders},
+    hooks::PluginConfig,
+    http_client::HttpClientConfig,
+    latency::LatencyConfig,
+    legacy_paths::LegacyPaths,
+    listener::ListenerConfig,
+    maintenance::MaintenanceConfig,
+    notifications::NotificationsConfig,
+    ownership::OwnerLabels,
+    port_test::{PortTestConfig, SessionStatsConfig},
+    request_log::RequestLog,
+    rpc::{coalesce::CoalesceConfig, compat::Compat, mirror::MirrorConfig},
+    scheduler::SchedulerConfig,
+    security_headers::SecurityHeaders,
+    session_cookies::SessionCookies,
+    shares::SharesConfig,
+    storage::StorageConfig,
+    torrent_index::TorrentIndexConfig,
+    torrent_stream::TorrentStreamConfig,
+    torrent_urls::TorrentUrlsConfig,
+    tracker_stats::TrackerStatsConfig,
+    uploads::UploadsConfig,
+    upstream_auth::UpstreamAuth,
+    version_check::VersionCheck,
+    web_ui::WebUi,
+};
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
 #[serde(deny_unknown_fields)]
 pub struct Config {
+    /// Version of the configuration format, updated by the migrate-config command
+    #[serde(default)]
+    pub version: u32,
+
     /// List of ACLs
     pub acl: Acls,
 
+    /// Logical users which can log in with several identities
+    #[serde(default, skip_serializing_if = "Accounts::is_empty")]
+    pub users: Accounts,
+
+    /// Named tracker rule sets, which ACLs can use in their tracker rules
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub tracker_rule_sets: HashMap<String, Vec<Arc<TrackerRule>>>,
+
+    /// Extra path prefixes proxied to other upstreams
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub routes: Vec<CustomRoute>,
+
+    /// Standby upstream daemon
+    #[serde(default)]
+    pub failover: Failover,
+
+    /// Translation of RPC calls for older upstreams
+    #[serde(default)]
+    pub compat: Compat,
+
+    /// Mirroring of read-only RPC calls to another upstream
+    #[serde(default)]
+    pub mirror: MirrorConfig,
+
+    /// Sharing of identical torrent-get calls between polling users
+    #[serde(default)]
+    pub coalesce: CoalesceConfig,
+
+    /// HTTP listener settings
+    #[serde(default)]
+    pub listener: ListenerConfig,
+
     /// List of identity providers
     #[serde(default)]
     pub providers: Providers,
+
+    /// Outbound HTTPS requests
+    #[serde(default)]
+    pub http_client: HttpClientConfig,
+
+    /// List of plugins for rewriting RPC calls
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub plugins: Vec<PluginConfig>,
+
+    /// Known client apps, matched by user agent
+    #[serde(default)]
+    pub client_profiles: Arc<ClientProfiles>,
+
+    /// Credentials sent to an upstream which keeps its own authentication enabled
+    #[serde(default)]
+    pub upstream_auth: Arc<UpstreamAuth>,
+
+    /// Headers identifying the proxy user in upstream requests
+    #[serde(default)]
+    pub identity_headers: IdentityHeaders,
+
+    /// Headers forwarded, dropped or set when proxying
+    #[serde(default)]
+    pub headers: HeaderPolicy,
+
+    /// Upstream latency of RPC calls
+    #[serde(default)]
+    pub latency: LatencyConfig,
+
+    /// Logging of the requests to the proxy
+    #[serde(default)]
+    pub request_log: RequestLog,
+
+    /// Labels recording the owner of torrents
+    #[serde(default)]
+    pub owner_labels: OwnerLabels,
+
+    /// Maintenance mode
+    #[serde(default)]
+    pub maintenance: MaintenanceConfig,
+
+    /// Upstream RPC version checks
+    #[serde(default)]
+    pub version_check: VersionCheck,
+
+    /// Caching and restriction of port tests
+    #[serde(default)]
+    pub port_test: PortTestConfig,
+
+    /// Caching of session statistics
+    #[serde(default)]
+    pub session_stats: SessionStatsConfig,
+
+    /// Periodic collection of tracker statistics
+    #[serde(default)]
+    pub tracker_stats: TrackerStatsConfig,
+
+    /// Index of the torrents of the upstream, by info hash
+    #[serde(default)]
+    pub torrent_index: TorrentIndexConfig,
+
+    /// Resumable uploads of large torrent files
+    #[serde(default)]
+    pub uploads: UploadsConfig,
+
+    /// Read-only links to the status of torrents
+    #[serde(default)]
+    pub shares: SharesConfig,
+
+    /// Torrent lists pushed to users as server-sent events
+    #[serde(default)]
+    pub torrent_stream: TorrentStreamConfig,
+
+    /// Torrent files added by URL
+    #[serde(default)]
+    pub torrent_urls: TorrentUrlsConfig,
+
+    /// Compression of RPC and API responses
+    #[serde(default)]
+    pub compression: CompressionConfig,
+
+    /// Queueing of upstream requests by priority
+    #[serde(default)]
+    pub scheduler: SchedulerConfig,
+
+    /// Attributes of the cookies set on login
+    #[serde(default)]
+    pub session_cookies: SessionCookies,
+
+    /// Cross-site request forgery protection
+    #[serde(default)]
+    pub csrf: CsrfProtection,
+
+    /// Cross-origin access to the RPC and API endpoints
+    #[serde(default)]
+    pub cors: Cors,
+
+    /// Security headers added to responses
+    #[serde(default)]
+    pub security_headers: SecurityHeaders,
+
+    /// Changes made to the proxied web interface
+    #[serde(default)]
+    pub web_ui: WebUi,
+
+    /// Endpoints older clients expect at fixed paths
+    #[serde(default)]
+    pub legacy_paths: LegacyPaths,
+
+    /// Notifications of security events
+    #[serde(default)]
+    pub notifications: NotificationsConfig,
+
+    /// Where state shared between replicas is kept, e.g. redis://localhost:6379, in memory if unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub state: Option<String>,
+
+    /// Prefix of the keys of the shared state, to share a Redis database between deployments
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub state_namespace: Option<String>,
+
+    /// Where data persisted across restarts is kept
+    #[serde(default)]
+    pub storage: StorageConfig,
+
+    /// Invalid sections which were skipped in lenient mode
+    #[serde(skip)]
+    pub skipped: Vec<String>,
+}
+
+impl Config {
+    /// Parse a configuration file. In lenient mode, invalid ACLs are replaced by rules denying
+    /// access to their identities, and invalid tracker rule sets and routes are dropped.
+    pub fn load(text: &str, lenient: bool) -> eyre::Result<Self> {
+        let err = match serde_yaml::from_str(text) {
+            Ok(config) => return Ok(config),
+            Err(err) if lenient => err,
+            Err(err) => return Err(err.into()),
+        };
+
+        let mut value: Value = serde_yaml::from_str(text)?;
+        let mut skipped = Vec::new();
+
+        if let Some(rules) = value
+            .get_mut("acl")
+            .and_then(|acl| acl.get_mut("rules"))
+            .and_then(Value::as_sequence_mut)
+        {
+            for (index, rule) in rules.iter_mut().enumerate() {
+                if let Err(err) = serde_yaml::from_value::<Acl>(rule.clone()) {
+                    skipped.push(format!("ACL {index}: {err}"));
+                    *rule = denying(rule);
+                }
+            }
+        }
+
+        if let Some(sets) = value
+            .get_mut("tracker_rule_sets")
+            .and_then(Value::as_mapping_mut)
+        {
+            sets.retain(|name, set| {
+                let result = serde_yaml::from_value::<Vec<TrackerRule>>(set.clone());
+                if let Err(err) = &result {
+                    skipped.push(format!("tracker rule set {}: {err}", name_of(name)));
+                }
+                result.is_ok()
+            });
+        }
+
+        if let Some(routes) = value.get_mut("routes").and_then(Value::as_sequence_mut) {
+            let mut index = 0;
+            routes.retain(|route| {
+                let result = serde_yaml::from_value::<CustomRoute>(route.clone());
+                if let Err(err) = &result {
+                    skipped.push(format!("route {index}: {err}"));
+                }
+                index += 1;
+                result.is_ok()
+            });
+        }
+
+        // Problems outside of the sections we can skip are still fatal
+        let mut config: Self = serde_yaml::from_value(value).map_err(|_| err)?;
+        for problem in &skipped {
+            warn!(%problem, "skipped invalid configuration");
+        }
+        config.skipped = skipped;
+        Ok(config)
+    }
+}
+
+/// Rule denying access to the identities of an invalid ACL
+fn denying(rule: &Value) -> Value {
+    let mut acl = serde_yaml::Mapping::new();
+    if let Some(name) = rule.get("name").filter(|name| name.is_string()) {
+        acl.insert("name".into(), name.clone());
+    }
+    let identities = rule
+        .get("identities")
+        .and_then(Value::as_sequence)
+        .into_iter()
+        .flatten()
+        .filter(|identity| serde_yaml::from_value::<AclIdentity>((*identity).clone()).is_ok())
+        .cloned()
+        .collect::<Vec<_>>();
+    acl.insert("identities".into(), identities.into());
+    acl.insert("deny".into(), true.into());
+    acl.into()
+}
+
+fn name_of(key: &Value) -> String {
+    match key {
+        Value::String(name) => name.clone(),
+        other => format!("{other:?}"),
+    }
+}
+
+/// Print the JSON schema of the configuration file, for editors and linters
+pub fn print_schema() -> eyre::Result<()> {
+    let schema = schemars::schema_for!(Config);
+    println!("{}", serde_json::to_string_pretty(&schema)?);
+    Ok(())
 }
@@ -0,0 +1,120 @@
+use hyper::{
+    header::{
+        HeaderName, HeaderValue, ACCESS_CONTROL_ALLOW_CREDENTIALS, ACCESS_CONTROL_ALLOW_HEADERS,
+        ACCESS_CONTROL_ALLOW_METHODS, ACCESS_CONTROL_ALLOW_ORIGIN, ACCESS_CONTROL_EXPOSE_HEADERS,
+        ACCESS_CONTROL_MAX_AGE, VARY,
+    },
+    HeaderMap,
+};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+fn default_allowed_headers() -> Vec<String> {
+    vec![
+        "Authorization".into(),
+        "Content-Type".into(),
+        "X-Transmission-Session-Id".into(),
+    ]
+}
+
+fn default_exposed_headers() -> Vec<String> {
+    vec!["X-Transmission-Session-Id".into()]
+}
+
+fn default_max_age() -> u64 {
+    600
+}
+
+/// Cross-origin access to the RPC and API endpoints
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct Cors {
+    /// Origins allowed to call the proxy, e.g. `https://dashboard.example.com`. `*` allows any
+    /// origin, without credentials. CORS is disabled if empty.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub allowed_origins: Vec<String>,
+
+    /// Request headers allowed in cross-origin requests
+    #[serde(default = "default_allowed_headers")]
+    pub allowed_headers: Vec<String>,
+
+    /// Response headers readable by cross-origin clients
+    #[serde(default = "default_exposed_headers")]
+    pub exposed_headers: Vec<String>,
+
+    /// Allow cross-origin requests with credentials (cookies and basic auth)
+    #[serde(default)]
+    pub allow_credentials: bool,
+
+    /// How long browsers may cache preflight results, in seconds
+    #[serde(default = "default_max_age")]
+    pub max_age: u64,
+}
+
+impl Default for Cors {
+    fn default() -> Self {
+        Self {
+            allowed_origins: Vec::new(),
+            allowed_headers: default_allowed_headers(),
+            exposed_headers: default_exposed_headers(),
+            allow_credentials: false,
+            max_age: default_max_age(),
+        }
+    }
+}
+
+impl Cors {
+    fn is_listed(&self, origin: &str) -> bool {
+        self.allowed_origins
+            .iter()
+            .any(|allowed| allowed.trim_end_matches('/') == origin)
+    }
+
+    /// Returns true if requests from this origin are allowed to carry credentials
+    pub fn trusts(&self, origin: &str) -> bool {
+        self.allow_credentials && self.is_listed(origin)
+    }
+
+    fn allows(&self, origin: &str) -> bool {
+        self.is_listed(origin) || self.allowed_origins.iter().any(|allowed| allowed == "*")
+    }
+
+    fn set(headers: &mut HeaderMap, name: HeaderName, value: &str) {
+        if let Ok(value) = HeaderValue::try_from(value) {
+            headers.insert(name, value);
+        }
+    }
+
+    /// Add the CORS headers for a request from the given origin to a response. Returns false if
+    /// the origin is not allowed.
+    pub fn apply(&self, origin: &str, preflight: bool, headers: &mut HeaderMap) -> bool {
+        if !self.allows(origin) {
+            return false;
+        }
+
+        Self::set(headers, ACCESS_CONTROL_ALLOW_ORIGIN, origin);
+        headers.append(VARY, HeaderValue::from_static("Origin"));
+
+        if self.trusts(origin) {
+            Self::set(headers, ACCESS_CONTROL_ALLOW_CREDENTIALS, "true");
+        }
+
+        if preflight {
+            Self::set(headers, ACCESS_CONTROL_ALLOW_METHODS, "GET, POST");
+            Self::set(
+                headers,
+                ACCESS_CONTROL_ALLOW_HEADERS,
+                &self.allowed_headers.join(", "),
+            );
+            Self::set(headers, ACCESS_CONTROL_MAX_AGE, &self.max_age.to_string());
+        } else if !self.exposed_headers.is_empty() {
+            Self::set(
+                headers,
+                ACCESS_CONTROL_EXPOSE_HEADERS,
+                &self.exposed_headers.join(", "),
+            );
+        }
+
+        true
+    }
+}
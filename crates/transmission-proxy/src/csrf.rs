@@ -0,0 +1,108 @@
+use hyper::{
+    header::{HOST, ORIGIN, REFERER},
+    http::HeaderValue,
+    Body, Request, Uri,
+};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tracing::debug;
+
+use crate::cors::Cors;
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_header() -> String {
+    "X-Transmission-Session-Id".into()
+}
+
+/// Protection of cookie-authenticated requests against cross-site requests
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct CsrfProtection {
+    /// Check the origin of the cookie-authenticated requests which change state
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+
+    /// Header which must be present when the browser doesn't send the request origin. Browsers
+    /// only send custom headers cross-site after a CORS preflight.
+    #[serde(default = "default_header")]
+    pub header: String,
+
+    /// Additional origins allowed to make requests, e.g. `https://example.com`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub allowed_origins: Vec<String>,
+}
+
+impl Default for CsrfProtection {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            header: default_header(),
+            allowed_origins: Vec::new(),
+        }
+    }
+}
+
+impl CsrfProtection {
+    fn origin_allowed(
+        &self,
+        origin: &str,
+        req: &Request<Body>,
+        public_url: &Uri,
+        cors: &Cors,
+    ) -> bool {
+        if cors.trusts(origin)
+            || self
+                .allowed_origins
+                .iter()
+                .any(|allowed| allowed.trim_end_matches('/') == origin)
+        {
+            return true;
+        }
+
+        let Ok(origin) = origin.parse::<Uri>() else {
+            return false;
+        };
+
+        let Some(authority) = origin.authority() else {
+            // Opaque origins, e.g. "null"
+            return false;
+        };
+
+        // Same origin as the public URL, or as the host the browser connected to
+        (origin.scheme() == public_url.scheme() && Some(authority) == public_url.authority())
+            || req.headers().get(HOST).map(HeaderValue::as_bytes)
+                == Some(authority.as_str().as_bytes())
+    }
+
+    /// Returns true if a cookie-authenticated request was made by the proxied web interface
+    pub fn allows(&self, req: &Request<Body>, public_url: &Uri, cors: &Cors) -> bool {
+        if !self.enabled {
+            return true;
+        }
+
+        let origin = req
+            .headers()
+            .get(ORIGIN)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_owned)
+            .or_else(|| {
+                // Older browsers only send the referer
+                let referer: Uri = req.headers().get(REFERER)?.to_str().ok()?.parse().ok()?;
+                Some(format!("{}://{}", referer.scheme()?, referer.authority()?))
+            });
+
+        let allowed = match &origin {
+            Some(origin) => self.origin_allowed(origin, req, public_url, cors),
+            None => req.headers().contains_key(self.header.as_str()),
+        };
+
+        if !allowed {
+            debug!(?origin, "rejected cross-site request");
+        }
+
+        allowed
+    }
+}
@@ -0,0 +1,96 @@
+use color_eyre::eyre;
+use hyper::{
+    header::{AUTHORIZATION, COOKIE},
+    http::uri::PathAndQuery,
+    HeaderMap, Uri,
+};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+/// An extra path prefix reverse-proxied to another upstream, behind the proxy authentication
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct CustomRoute {
+    /// Path prefix handled by this route, e.g. `/flood/`
+    pub prefix: String,
+
+    /// Upstream URL. The path following the prefix is appended to the upstream path.
+    pub upstream: String,
+
+    /// Names of the ACLs allowed to use this route. Any non-denying ACL is allowed if empty.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub acls: Vec<String>,
+
+    /// Forward the client `Authorization` and `Cookie` headers to the upstream. They hold the
+    /// proxy credentials and session, so they are removed by default.
+    #[serde(default)]
+    pub forward_credentials: bool,
+}
+
+impl CustomRoute {
+    pub fn validate(&self) -> eyre::Result<()> {
+        if !self.prefix.starts_with('/') || self.base().is_empty() {
+            eyre::bail!("invalid route prefix {}", self.prefix);
+        }
+
+        let upstream: Uri = self.upstream.parse()?;
+        if upstream.scheme_str() != Some("http") || upstream.authority().is_none() {
+            eyre::bail!("route upstream {} must be an http URL", self.upstream);
+        }
+
+        Ok(())
+    }
+
+    fn base(&self) -> &str {
+        self.prefix.trim_end_matches('/')
+    }
+
+    /// Returns true if this route handles the given path
+    pub fn matches(&self, path: &str) -> bool {
+        path.strip_prefix(self.base())
+            .map_or(false, |rest| rest.is_empty() || rest.starts_with('/'))
+    }
+
+    /// Returns true if the ACL with the given name may use this route
+    pub fn allows(&self, acl: Option<&str>) -> bool {
+        self.acls.is_empty() || acl.map_or(false, |acl| self.acls.iter().any(|name| name == acl))
+    }
+
+    /// Remove the client credentials from a request, unless the route forwards them
+    pub fn strip_credentials(&self, headers: &mut HeaderMap) {
+        if !self.forward_credentials {
+            headers.remove(AUTHORIZATION);
+            headers.remove(COOKIE);
+        }
+    }
+
+    /// Upstream URL for a request to the given URI
+    pub fn upstream_url(&self, uri: &Uri) -> eyre::Result<Uri> {
+        let rest = uri.path().strip_prefix(self.base()).unwrap_or_default();
+
+        let mut parts = self.upstream.parse::<Uri>()?.into_parts();
+        let mut path = parts
+            .path_and_query
+            .as_ref()
+            .map_or("", |path| path.path())
+            .trim_end_matches('/')
+            .to_owned()
+            + rest;
+
+        if !path.starts_with('/') {
+            path.insert(0, '/');
+        }
+
+        if let Some(query) = uri.query() {
+            path = path + "?" + query;
+        }
+
+        parts.path_and_query = Some(PathAndQuery::try_from(path)?);
+        Ok(Uri::from_parts(parts)?)
+    }
+}
+
+/// Returns the first route handling the given path
+pub fn find<'a>(routes: &'a [CustomRoute], path: &str) -> Option<&'a CustomRoute> {
+    routes.iter().find(|route| route.matches(path))
+}
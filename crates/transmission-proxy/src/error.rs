@@ -5,4 +5,6 @@ use tracing::error;
 pub enum Error {
     #[error("could not resolve {0} as a into an IP address and port")]
     BindResolve(Uri),
+    #[error("unsupported upstream rpc version {0}")]
+    UnsupportedRpcVersion(i32),
 }
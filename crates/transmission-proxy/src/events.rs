@@ -0,0 +1,179 @@
+//! Events emitted by the proxy, for the notifier, the audit log and event stream subscribers
+
+use std::{
+    collections::VecDeque,
+    net::IpAddr,
+    sync::{Arc, Mutex},
+};
+
+use serde::Serialize;
+use serde_json::Value;
+use tokio::sync::broadcast;
+use tracing::{info, warn};
+
+use crate::{request_log::Redaction, torrent_reports::TorrentProblem};
+
+/// Number of events kept for subscribers which are lagging behind
+const CAPACITY: usize = 256;
+
+/// Something that happened in the proxy
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event {
+    /// Successful login
+    Login {
+        user: String,
+        /// Address of the user, if known
+        #[serde(skip_serializing_if = "Option::is_none")]
+        email: Option<String>,
+        provider: String,
+        client_ip: Option<IpAddr>,
+        user_agent: Option<String>,
+        /// The user logged in before, but never from this address and browser
+        new_device: bool,
+        /// Unix timestamp of the login
+        time: u64,
+    },
+    /// RPC call denied by the ACL of the user
+    RpcDenied {
+        user: Option<String>,
+        /// Failure code of the denial, as in RPC responses
+        code: &'static str,
+        reason: String,
+    },
+    /// Torrent added through the proxy
+    TorrentAdded {
+        user: Option<String>,
+        id: i32,
+        hash: Option<String>,
+        name: String,
+    },
+    /// The download dirs of the ACL of the user hold more than its quota
+    QuotaExceeded {
+        user: Option<String>,
+        /// Bytes used by the torrents of the ACL
+        used: u64,
+        quota: u64,
+    },
+    /// The primary upstream is unreachable, requests go to the standby
+    UpstreamUnavailable {
+        /// Time the primary upstream has been unreachable for, in seconds
+        down_for: u64,
+    },
+    /// The primary upstream is reachable again
+    UpstreamRestored,
+    /// ACL created for a user on their first login
+    AclProvisioned { user: String, acl: String },
+    /// Torrents of a user have errors or are stalled
+    TorrentProblems {
+        user: String,
+        /// Address of the user, if known
+        #[serde(skip_serializing_if = "Option::is_none")]
+        email: Option<String>,
+        torrents: Vec<TorrentProblem>,
+    },
+}
+
+impl Event {
+    /// Name of the event, as in the `event` field
+    pub fn name(&self) -> &'static str {
+        match self {
+            Event::Login { .. } => "login",
+            Event::RpcDenied { .. } => "rpc_denied",
+            Event::TorrentAdded { .. } => "torrent_added",
+            Event::QuotaExceeded { .. } => "quota_exceeded",
+            Event::UpstreamUnavailable { .. } => "upstream_unavailable",
+            Event::UpstreamRestored => "upstream_restored",
+            Event::AclProvisioned { .. } => "acl_provisioned",
+            Event::TorrentProblems { .. } => "torrent_problems",
+        }
+    }
+}
+
+/// Broadcasts events to all subscribers. Events emitted while nobody listens are dropped.
+#[derive(Clone)]
+pub struct EventBus {
+    sender: broadcast::Sender<Arc<Event>>,
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        Self {
+            sender: broadcast::channel(CAPACITY).0,
+        }
+    }
+}
+
+impl EventBus {
+    pub fn emit(&self, event: Event) {
+        // Failing only means there are no subscribers
+        let _ = self.sender.send(Arc::new(event));
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<Arc<Event>> {
+        self.sender.subscribe()
+    }
+}
+
+/// Next event of a subscription, skipping the ones it lagged behind on. Returns `None` once the
+/// bus is dropped.
+pub async fn next(receiver: &mut broadcast::Receiver<Arc<Event>>) -> Option<Arc<Event>> {
+    loop {
+        match receiver.recv().await {
+            Ok(event) => return Some(event),
+            Err(broadcast::error::RecvError::Lagged(count)) => {
+                warn!(count, "subscriber lagged behind, events were dropped");
+            }
+            Err(broadcast::error::RecvError::Closed) => return None,
+        }
+    }
+}
+
+/// Latest events of the audit log, redacted, for snapshots
+#[derive(Debug, Default)]
+pub struct AuditTail {
+    events: Mutex<VecDeque<Value>>,
+}
+
+impl AuditTail {
+    fn push(&self, event: Value) {
+        let mut events = self.events.lock().unwrap();
+        if events.len() == CAPACITY {
+            events.pop_front();
+        }
+        events.push_back(event);
+    }
+
+    /// Events of the tail, oldest first
+    pub fn events(&self) -> Vec<Value> {
+        self.events.lock().unwrap().iter().cloned().collect()
+    }
+
+    /// Add events which happened before the ones in the tail, e.g. from a snapshot
+    pub fn prepend(&self, older: Vec<Value>) {
+        let mut events = self.events.lock().unwrap();
+        for event in older.into_iter().rev() {
+            if events.len() == CAPACITY {
+                break;
+            }
+            events.push_front(event);
+        }
+    }
+}
+
+/// Log all events for auditing, under the `audit` target
+pub async fn audit(
+    mut receiver: broadcast::Receiver<Arc<Event>>,
+    redaction: &Redaction,
+    tail: &AuditTail,
+) {
+    while let Some(event) = next(&mut receiver).await {
+        match redaction.event_value(&event) {
+            Ok(json) => {
+                info!(target: "audit", event = event.name(), %json);
+                tail.push(json);
+            }
+            Err(err) => warn!(%err, "could not serialize event"),
+        }
+    }
+}
@@ -0,0 +1,64 @@
+//! Traces of the filter decisions made for a request, for debugging ACLs
+
+use std::{cell::RefCell, future::Future};
+
+use serde::Serialize;
+
+/// Header requesting an explanation of the filter decisions instead of the upstream response
+pub const EXPLAIN_HEADER: &str = "X-Proxy-Explain";
+
+/// A single filter decision
+#[derive(Debug, Clone, Serialize)]
+pub struct Decision {
+    /// Name of the check
+    pub check: &'static str,
+    /// Whether the check let the request through
+    pub allowed: bool,
+    /// What was checked or rewritten
+    pub detail: String,
+}
+
+#[derive(Default)]
+struct Trace {
+    dry_run: bool,
+    decisions: Vec<Decision>,
+}
+
+tokio::task_local! {
+    static TRACE: RefCell<Trace>;
+}
+
+/// Record a decision, if the current request is being explained
+pub fn record(check: &'static str, allowed: bool, detail: impl FnOnce() -> String) {
+    TRACE
+        .try_with(|trace| {
+            trace.borrow_mut().decisions.push(Decision {
+                check,
+                allowed,
+                detail: detail(),
+            })
+        })
+        .ok();
+}
+
+/// Returns true if the current request should be filtered but not forwarded
+pub fn is_dry_run() -> bool {
+    TRACE
+        .try_with(|trace| trace.borrow().dry_run)
+        .unwrap_or(false)
+}
+
+/// Run a future while recording its filter decisions
+pub async fn scope<F: Future>(dry_run: bool, future: F) -> (F::Output, Vec<Decision>) {
+    let trace = RefCell::new(Trace {
+        dry_run,
+        decisions: Vec::new(),
+    });
+
+    TRACE
+        .scope(trace, async {
+            let output = future.await;
+            (output, TRACE.with(|trace| trace.take().decisions))
+        })
+        .await
+}
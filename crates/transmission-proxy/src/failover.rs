@@ -0,0 +1,83 @@
+use std::time::{Duration, Instant};
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use crate::{
+    events::{Event, EventBus},
+    rpc::proxy::RpcProxyClient,
+};
+
+fn default_after() -> u64 {
+    30
+}
+
+fn default_probe_interval() -> u64 {
+    5
+}
+
+/// Standby upstream used while the primary upstream is unreachable
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct Failover {
+    /// Standby upstream daemon. Failover is disabled if not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub upstream: Option<String>,
+
+    /// Time the primary upstream must be unreachable before failing over, in seconds
+    #[serde(default = "default_after")]
+    pub after: u64,
+
+    /// Interval between health probes of the primary upstream, in seconds
+    #[serde(default = "default_probe_interval")]
+    pub probe_interval: u64,
+}
+
+impl Default for Failover {
+    fn default() -> Self {
+        Self {
+            upstream: None,
+            after: default_after(),
+            probe_interval: default_probe_interval(),
+        }
+    }
+}
+
+impl Failover {
+    /// Probe the primary upstream forever, switching to the standby while it is down
+    pub async fn run(&self, client: &RpcProxyClient, events: &EventBus) {
+        let probe_interval = Duration::from_secs(self.probe_interval.max(1));
+        let after = Duration::from_secs(self.after);
+
+        let mut interval = tokio::time::interval(probe_interval);
+        let mut down_since = None;
+
+        loop {
+            interval.tick().await;
+
+            if client.probe(0, probe_interval).await {
+                down_since = None;
+
+                if client.active_upstream() != 0 {
+                    info!("primary upstream is reachable again, failing back");
+                    client.set_active_upstream(0).await;
+                    events.emit(Event::UpstreamRestored);
+                }
+            } else {
+                let since = *down_since.get_or_insert_with(Instant::now);
+
+                if client.active_upstream() == 0 && since.elapsed() >= after {
+                    warn!(
+                        down_for = ?since.elapsed(),
+                        "primary upstream is unreachable, failing over to the standby"
+                    );
+                    client.set_active_upstream(1).await;
+                    events.emit(Event::UpstreamUnavailable {
+                        down_for: since.elapsed().as_secs(),
+                    });
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,319 @@
+use std::{collections::BTreeMap, net::IpAddr};
+
+use color_eyre::eyre;
+use hyper::{
+    header::{HeaderName, FORWARDED, VIA},
+    http::HeaderValue,
+    Body, HeaderMap, Request,
+};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use crate::{auth::AuthUser, rpc::proxy::SESSION_ID_HEADER};
+
+fn default_user_header() -> String {
+    "X-Proxy-User".into()
+}
+
+fn default_acl_header() -> String {
+    "X-Proxy-ACL".into()
+}
+
+/// Headers forwarded to the upstream daemon to identify the proxy user
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct IdentityHeaders {
+    /// Inject the identity headers in upstream requests
+    #[serde(default)]
+    pub enabled: bool,
+
+    /// Name of the header holding the authenticated user name
+    #[serde(default = "default_user_header")]
+    pub user: String,
+
+    /// Name of the header holding the matched ACL name
+    #[serde(default = "default_acl_header")]
+    pub acl: String,
+}
+
+impl Default for IdentityHeaders {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            user: default_user_header(),
+            acl: default_acl_header(),
+        }
+    }
+}
+
+impl IdentityHeaders {
+    fn set(req: &mut Request<Body>, name: &str, value: Option<&str>) {
+        let name = match HeaderName::try_from(name) {
+            Ok(name) => name,
+            Err(err) => {
+                warn!(%err, %name, "invalid identity header name");
+                return;
+            }
+        };
+
+        // Never trust values sent by the client
+        req.headers_mut().remove(&name);
+
+        if let Some(value) = value {
+            match HeaderValue::try_from(value) {
+                Ok(value) => {
+                    req.headers_mut().insert(name, value);
+                }
+                Err(err) => {
+                    warn!(%err, %name, %value, "invalid identity header value");
+                }
+            }
+        }
+    }
+
+    /// Replace the identity headers of a request to be forwarded upstream
+    pub fn apply(&self, req: &mut Request<Body>, user: &AuthUser, acl: Option<&str>) {
+        if self.enabled {
+            Self::set(req, &self.user, user.username());
+            Self::set(req, &self.acl, acl);
+        } else {
+            Self::set(req, &self.user, None);
+            Self::set(req, &self.acl, None);
+        }
+    }
+}
+
+/// Changes made to the headers of proxied requests or responses
+#[derive(Debug, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct HeaderRules {
+    /// Headers removed
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub drop: Vec<String>,
+
+    /// Headers set, replacing the values already present
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub set: BTreeMap<String, String>,
+}
+
+impl HeaderRules {
+    fn validate(&self, direction: &str) -> eyre::Result<()> {
+        for name in self.drop.iter().chain(self.set.keys()) {
+            let name = HeaderName::try_from(name.as_str())
+                .map_err(|err| eyre::eyre!("invalid {direction} header name {name}: {err}"))?;
+
+            // Transmission clients can't work without the session id exchange
+            if name == SESSION_ID_HEADER {
+                eyre::bail!("the {SESSION_ID_HEADER} {direction} header can't be changed");
+            }
+        }
+
+        for (name, value) in &self.set {
+            HeaderValue::try_from(value.as_str())
+                .map_err(|err| eyre::eyre!("invalid value for {direction} header {name}: {err}"))?;
+        }
+
+        Ok(())
+    }
+
+    fn apply(&self, headers: &mut HeaderMap) {
+        for name in &self.drop {
+            headers.remove(name.as_str());
+        }
+
+        for (name, value) in &self.set {
+            if let (Ok(name), Ok(value)) = (
+                HeaderName::try_from(name.as_str()),
+                HeaderValue::try_from(value.as_str()),
+            ) {
+                headers.insert(name, value);
+            }
+        }
+    }
+}
+
+/// Headers of proxied requests and responses, on the RPC and web interface paths alike
+#[derive(Debug, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct HeaderPolicy {
+    /// Remove the Forwarded and X-Forwarded-* headers sent by clients other than trusted proxies
+    #[serde(default)]
+    pub strip_forwarded: bool,
+
+    /// Addresses of the reverse proxies whose forwarding headers are kept
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub trusted_proxies: Vec<IpAddr>,
+
+    /// Value of the Via header added to upstream requests
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub via: Option<String>,
+
+    /// Changes made to requests sent to the upstream
+    #[serde(default)]
+    pub request: HeaderRules,
+
+    /// Changes made to responses from the upstream
+    #[serde(default)]
+    pub response: HeaderRules,
+}
+
+impl HeaderPolicy {
+    /// Check that all header names and values are valid
+    pub fn validate(&self) -> eyre::Result<()> {
+        if let Some(via) = &self.via {
+            HeaderValue::try_from(via.as_str())
+                .map_err(|err| eyre::eyre!("invalid via header value: {err}"))?;
+        }
+
+        self.request.validate("request")?;
+        self.response.validate("response")
+    }
+
+    /// Adjust the headers of a request to be forwarded upstream
+    pub fn apply_request(&self, req: &mut Request<Body>, client_ip: Option<IpAddr>) {
+        let headers = req.headers_mut();
+
+        if self.strip_forwarded && !client_ip.map_or(false, |ip| self.trusted_proxies.contains(&ip))
+        {
+            let forwarded: Vec<_> = headers
+                .keys()
+                .filter(|name| *name == FORWARDED || name.as_str().starts_with("x-forwarded-"))
+                .cloned()
+                .collect();
+
+            for name in forwarded {
+                headers.remove(name);
+            }
+        }
+
+        if let Some(via) = self
+            .via
+            .as_deref()
+            .and_then(|via| HeaderValue::try_from(via).ok())
+        {
+            headers.append(VIA, via);
+        }
+
+        self.request.apply(headers);
+    }
+
+    /// Adjust the headers of a response from the upstream
+    pub fn apply_response(&self, headers: &mut HeaderMap) {
+        self.response.apply(headers);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(headers: &[(&str, &str)]) -> Request<Body> {
+        let mut req = Request::new(Body::empty());
+        for (name, value) in headers {
+            req.headers_mut().insert(
+                HeaderName::try_from(*name).unwrap(),
+                HeaderValue::try_from(*value).unwrap(),
+            );
+        }
+        req
+    }
+
+    fn alice() -> AuthUser {
+        AuthUser::Basic {
+            username: "alice".to_owned(),
+            password: None,
+        }
+    }
+
+    fn identity_headers(enabled: bool) -> IdentityHeaders {
+        IdentityHeaders {
+            enabled,
+            ..Default::default()
+        }
+    }
+
+    const SPOOFED: &[(&str, &str)] = &[("X-Proxy-User", "admin"), ("X-Proxy-ACL", "admins")];
+
+    #[test]
+    fn identity_headers_are_set_for_authenticated_users() {
+        let mut req = request(SPOOFED);
+        identity_headers(true).apply(&mut req, &alice(), Some("users"));
+
+        assert_eq!(req.headers()["X-Proxy-User"], "alice");
+        assert_eq!(req.headers()["X-Proxy-ACL"], "users");
+        assert_eq!(req.headers().get_all("X-Proxy-User").iter().count(), 1);
+    }
+
+    #[test]
+    fn identity_headers_are_not_injected_for_anonymous_users() {
+        let mut req = request(SPOOFED);
+        identity_headers(true).apply(&mut req, &AuthUser::Anonymous, None);
+
+        assert!(!req.headers().contains_key("X-Proxy-User"));
+        assert!(!req.headers().contains_key("X-Proxy-ACL"));
+    }
+
+    #[test]
+    fn client_identity_headers_are_stripped() {
+        let mut req = request(SPOOFED);
+        identity_headers(false).apply(&mut req, &alice(), Some("users"));
+
+        assert!(!req.headers().contains_key("X-Proxy-User"));
+        assert!(!req.headers().contains_key("X-Proxy-ACL"));
+    }
+
+    #[test]
+    fn forwarding_headers_are_only_kept_from_trusted_proxies() {
+        let policy = HeaderPolicy {
+            strip_forwarded: true,
+            trusted_proxies: vec!["10.0.0.1".parse().unwrap()],
+            via: Some("1.1 transmission-proxy".to_owned()),
+            ..Default::default()
+        };
+        let headers = [("Forwarded", "for=1.2.3.4"), ("X-Forwarded-For", "1.2.3.4")];
+
+        let mut req = request(&headers);
+        policy.apply_request(&mut req, Some("192.168.1.1".parse().unwrap()));
+        assert!(!req.headers().contains_key(FORWARDED));
+        assert!(!req.headers().contains_key("X-Forwarded-For"));
+        assert_eq!(req.headers()[VIA], "1.1 transmission-proxy");
+
+        let mut req = request(&headers);
+        policy.apply_request(&mut req, Some("10.0.0.1".parse().unwrap()));
+        assert_eq!(req.headers()[FORWARDED], "for=1.2.3.4");
+        assert_eq!(req.headers()["X-Forwarded-For"], "1.2.3.4");
+    }
+
+    #[test]
+    fn header_rules_drop_and_set_headers() {
+        let policy: HeaderPolicy = serde_yaml::from_str(
+            r#"
+request:
+  drop: [Referer]
+  set:
+    X-Api-Version: "2"
+response:
+  drop: [Server]
+"#,
+        )
+        .unwrap();
+        policy.validate().unwrap();
+
+        let mut req = request(&[("Referer", "https://example.com"), ("X-Api-Version", "1")]);
+        policy.apply_request(&mut req, None);
+        assert!(!req.headers().contains_key("Referer"));
+        assert_eq!(req.headers()["X-Api-Version"], "2");
+
+        let mut headers = request(&[("Server", "Transmission")]).headers().clone();
+        policy.apply_response(&mut headers);
+        assert!(headers.is_empty());
+    }
+
+    #[test]
+    fn session_id_header_cannot_be_changed() {
+        let policy: HeaderPolicy =
+            serde_yaml::from_str("response:\n  drop: [X-Transmission-Session-Id]").unwrap();
+        assert!(policy.validate().is_err());
+    }
+}
@@ -0,0 +1,146 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use color_eyre::eyre;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tracing::debug;
+
+use crate::rpc::{Request, Response};
+
+#[cfg(feature = "rhai")]
+mod rhai;
+#[cfg(feature = "wasm")]
+mod wasm;
+
+fn default_true() -> bool {
+    true
+}
+
+#[derive(Debug, Error)]
+pub enum HookError {
+    #[error("invalid json exchanged with hook")]
+    Json(#[from] serde_json::Error),
+    #[error("hook failed: {0}")]
+    Runtime(String),
+}
+
+/// Trait for hooks that can inspect and rewrite RPC requests and responses
+pub trait RpcHook: Send + Sync {
+    /// Rewrite a request, after it was filtered according to the ACL
+    fn on_request(&self, request: Request) -> Result<Request, HookError>;
+
+    /// Rewrite a response, after it was filtered according to the ACL
+    fn on_response(&self, request: &Request, response: Response) -> Result<Response, HookError>;
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct PluginConfig {
+    /// Path to the WebAssembly module implementing the plugin
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub wasm: Option<PathBuf>,
+
+    /// Path to the Rhai script implementing the plugin
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rhai: Option<PathBuf>,
+
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+}
+
+/// Ordered list of hooks to run on proxied RPC calls
+#[derive(Default)]
+pub struct Hooks {
+    hooks: Vec<Arc<dyn RpcHook>>,
+}
+
+impl PluginConfig {
+    fn load(&self) -> eyre::Result<Arc<dyn RpcHook>> {
+        match (&self.wasm, &self.rhai) {
+            (Some(path), None) => load_wasm(path),
+            (None, Some(path)) => load_rhai(path),
+            _ => Err(eyre::eyre!(
+                "plugins must specify exactly one of wasm or rhai"
+            )),
+        }
+    }
+}
+
+#[cfg(feature = "wasm")]
+fn load_wasm(path: &Path) -> eyre::Result<Arc<dyn RpcHook>> {
+    Ok(Arc::new(wasm::WasmPlugin::load(path)?))
+}
+
+#[cfg(not(feature = "wasm"))]
+fn load_wasm(path: &Path) -> eyre::Result<Arc<dyn RpcHook>> {
+    Err(eyre::eyre!(
+        "cannot load plugin {}: compiled without wasm support",
+        path.display()
+    ))
+}
+
+#[cfg(feature = "rhai")]
+fn load_rhai(path: &Path) -> eyre::Result<Arc<dyn RpcHook>> {
+    Ok(Arc::new(rhai::RhaiScript::load(path)?))
+}
+
+#[cfg(not(feature = "rhai"))]
+fn load_rhai(path: &Path) -> eyre::Result<Arc<dyn RpcHook>> {
+    Err(eyre::eyre!(
+        "cannot load script {}: compiled without rhai support",
+        path.display()
+    ))
+}
+
+impl Hooks {
+    pub fn load(plugins: &[PluginConfig]) -> eyre::Result<Self> {
+        Ok(Self {
+            hooks: plugins
+                .iter()
+                .filter(|plugin| plugin.enabled)
+                .map(PluginConfig::load)
+                .collect::<eyre::Result<_>>()?,
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.hooks.is_empty()
+    }
+
+    pub async fn on_request(&self, mut request: Request) -> Result<Request, HookError> {
+        for hook in &self.hooks {
+            let hook = hook.clone();
+            request = run_blocking(move || hook.on_request(request)).await?;
+        }
+
+        debug!(?request, "request after hooks");
+        Ok(request)
+    }
+
+    pub async fn on_response(
+        &self,
+        request: &Request,
+        mut response: Response,
+    ) -> Result<Response, HookError> {
+        for hook in &self.hooks {
+            let (hook, request) = (hook.clone(), request.clone());
+            response = run_blocking(move || hook.on_response(&request, response)).await?;
+        }
+
+        Ok(response)
+    }
+}
+
+/// Run a hook on the blocking thread pool, so long-running scripts and plugins don't stall the
+/// other requests served by the same worker
+async fn run_blocking<T: Send + 'static>(
+    hook: impl FnOnce() -> Result<T, HookError> + Send + 'static,
+) -> Result<T, HookError> {
+    tokio::task::spawn_blocking(hook)
+        .await
+        .map_err(|err| HookError::Runtime(err.to_string()))?
+}
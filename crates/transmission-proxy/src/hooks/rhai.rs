@@ -0,0 +1,103 @@
+//! Rhai scripting hooks
+//!
+//! Scripts may define any of the following functions:
+//!
+//! - `on_request(request)`: receives the request as an object map
+//! - `on_response(request, response)`: receives the request and response as object maps
+//!
+//! Hooks return the rewritten value, or `()` to leave it unchanged.
+
+use std::path::Path;
+
+use color_eyre::eyre;
+use rhai::{Dynamic, Engine, EvalAltResult, Scope, AST};
+use serde::de::DeserializeOwned;
+use tracing::info;
+
+use crate::rpc::{Request, Response};
+
+use super::{HookError, RpcHook};
+
+/// Maximum number of operations a single hook call can run
+const MAX_OPERATIONS: u64 = 1_000_000;
+
+const ON_REQUEST: &str = "on_request";
+const ON_RESPONSE: &str = "on_response";
+
+impl From<Box<EvalAltResult>> for HookError {
+    fn from(err: Box<EvalAltResult>) -> Self {
+        Self::Runtime(err.to_string())
+    }
+}
+
+pub struct RhaiScript {
+    engine: Engine,
+    ast: AST,
+    has_on_request: bool,
+    has_on_response: bool,
+}
+
+impl RhaiScript {
+    pub fn load(path: &Path) -> eyre::Result<Self> {
+        let mut engine = Engine::new();
+        engine.set_max_operations(MAX_OPERATIONS);
+
+        let ast = engine
+            .compile_file(path.to_owned())
+            .map_err(|err| eyre::eyre!("could not load script {}: {err}", path.display()))?;
+
+        let has_fn = |name, arity| {
+            ast.iter_functions()
+                .any(|f| f.name == name && f.params.len() == arity)
+        };
+        let has_on_request = has_fn(ON_REQUEST, 1);
+        let has_on_response = has_fn(ON_RESPONSE, 2);
+
+        info!(path = %path.display(), has_on_request, has_on_response, "loaded rhai script");
+
+        Ok(Self {
+            engine,
+            ast,
+            has_on_request,
+            has_on_response,
+        })
+    }
+
+    fn call<O>(&self, name: &str, args: impl rhai::FuncArgs) -> Result<Option<O>, HookError>
+    where
+        O: DeserializeOwned,
+    {
+        let result: Dynamic = self
+            .engine
+            .call_fn(&mut Scope::new(), &self.ast, name, args)?;
+
+        if result.is_unit() {
+            return Ok(None);
+        }
+
+        Ok(Some(rhai::serde::from_dynamic(&result)?))
+    }
+}
+
+impl RpcHook for RhaiScript {
+    fn on_request(&self, request: Request) -> Result<Request, HookError> {
+        if !self.has_on_request {
+            return Ok(request);
+        }
+
+        let arg = rhai::serde::to_dynamic(&request)?;
+        Ok(self.call(ON_REQUEST, (arg,))?.unwrap_or(request))
+    }
+
+    fn on_response(&self, request: &Request, response: Response) -> Result<Response, HookError> {
+        if !self.has_on_response {
+            return Ok(response);
+        }
+
+        let args = (
+            rhai::serde::to_dynamic(request)?,
+            rhai::serde::to_dynamic(&response)?,
+        );
+        Ok(self.call(ON_RESPONSE, args)?.unwrap_or(response))
+    }
+}
@@ -0,0 +1,167 @@
+//! WebAssembly plugins
+//!
+//! Plugins are core WebAssembly modules without any imports. They must export:
+//!
+//! - `memory`: the linear memory used to exchange data
+//! - `alloc(len: i32) -> i32`: allocate `len` bytes and return a pointer to them
+//!
+//! And any of the following hooks:
+//!
+//! - `on_request(ptr: i32, len: i32) -> i64`: receives the JSON-encoded request
+//! - `on_response(ptr: i32, len: i32) -> i64`: receives a JSON object with the `request` and
+//!   `response` keys
+//!
+//! Hooks return the location of their JSON output packed as `(ptr << 32) | len`, or 0 to leave
+//! the value unchanged. Outputs are limited to 16 MiB, and the linear memory to 64 MiB. A new
+//! instance is created for every call, so plugins can't keep state between calls.
+
+use std::path::Path;
+
+use color_eyre::eyre;
+use serde::{de::DeserializeOwned, Serialize};
+use tracing::info;
+use wasmtime::{Config, Engine, Instance, Module, Store, StoreLimits, StoreLimitsBuilder};
+
+use crate::rpc::{Request, Response};
+
+use super::{HookError, RpcHook};
+
+/// Maximum amount of fuel a single hook call can consume
+const FUEL_LIMIT: u64 = 100_000_000;
+
+/// Maximum size of the JSON output of a hook call
+const MAX_OUTPUT_LEN: usize = 16 * 1024 * 1024;
+
+/// Maximum size of the linear memory of a plugin instance
+const MAX_MEMORY_SIZE: usize = 64 * 1024 * 1024;
+
+const ON_REQUEST: &str = "on_request";
+const ON_RESPONSE: &str = "on_response";
+
+pub struct WasmPlugin {
+    engine: Engine,
+    module: Module,
+    has_on_request: bool,
+    has_on_response: bool,
+}
+
+#[derive(Serialize)]
+struct ResponseHookInput<'r> {
+    request: &'r Request,
+    response: &'r Response,
+}
+
+impl WasmPlugin {
+    pub fn load(path: &Path) -> eyre::Result<Self> {
+        let mut config = Config::new();
+        config.consume_fuel(true);
+
+        let engine = Engine::new(&config)
+            .map_err(|err| eyre::eyre!("could not create wasm engine: {err:#}"))?;
+        let module = Module::from_file(&engine, path)
+            .map_err(|err| eyre::eyre!("could not load plugin {}: {err:#}", path.display()))?;
+
+        let has_export = |name| module.exports().any(|export| export.name() == name);
+        let has_on_request = has_export(ON_REQUEST);
+        let has_on_response = has_export(ON_RESPONSE);
+
+        info!(path = %path.display(), has_on_request, has_on_response, "loaded wasm plugin");
+
+        Ok(Self {
+            engine,
+            module,
+            has_on_request,
+            has_on_response,
+        })
+    }
+
+    fn call<I, O>(&self, name: &str, input: &I) -> Result<Option<O>, HookError>
+    where
+        I: Serialize,
+        O: DeserializeOwned,
+    {
+        let runtime = |err: wasmtime::Error| HookError::Runtime(format!("{name}: {err:#}"));
+
+        let input = serde_json::to_vec(input)?;
+        let input_len = i32::try_from(input.len())
+            .map_err(|_| HookError::Runtime(format!("{name}: input too large")))?;
+
+        let limits = StoreLimitsBuilder::new()
+            .memory_size(MAX_MEMORY_SIZE)
+            .instances(1)
+            .build();
+        let mut store = Store::new(&self.engine, limits);
+        store.limiter(|limits: &mut StoreLimits| limits);
+        store.set_fuel(FUEL_LIMIT).map_err(runtime)?;
+
+        let instance = Instance::new(&mut store, &self.module, &[]).map_err(runtime)?;
+        let memory = instance
+            .get_memory(&mut store, "memory")
+            .ok_or_else(|| HookError::Runtime(format!("{name}: missing memory export")))?;
+        let alloc = instance
+            .get_typed_func::<i32, i32>(&mut store, "alloc")
+            .map_err(runtime)?;
+        let hook = instance
+            .get_typed_func::<(i32, i32), i64>(&mut store, name)
+            .map_err(runtime)?;
+
+        // Copy the input into the plugin memory
+        let ptr = alloc.call(&mut store, input_len).map_err(runtime)?;
+        memory
+            .write(&mut store, ptr as u32 as usize, &input)
+            .map_err(|err| runtime(err.into()))?;
+
+        // Run the hook
+        let packed = hook.call(&mut store, (ptr, input_len)).map_err(runtime)? as u64;
+        if packed == 0 {
+            return Ok(None);
+        }
+
+        // Read back the output
+        let out_ptr = (packed >> 32) as usize;
+        let out_len = (packed & 0xffff_ffff) as usize;
+        if out_len > MAX_OUTPUT_LEN {
+            return Err(HookError::Runtime(format!(
+                "{name}: output of {out_len} bytes is too large"
+            )));
+        }
+        if out_ptr.saturating_add(out_len) > memory.data_size(&store) {
+            return Err(HookError::Runtime(format!(
+                "{name}: output is out of the plugin memory"
+            )));
+        }
+
+        let mut output = vec![0; out_len];
+        memory
+            .read(&store, out_ptr, &mut output)
+            .map_err(|err| runtime(err.into()))?;
+
+        Ok(Some(serde_json::from_slice(&output)?))
+    }
+}
+
+impl RpcHook for WasmPlugin {
+    fn on_request(&self, request: Request) -> Result<Request, HookError> {
+        if !self.has_on_request {
+            return Ok(request);
+        }
+
+        Ok(self.call(ON_REQUEST, &request)?.unwrap_or(request))
+    }
+
+    fn on_response(&self, request: &Request, response: Response) -> Result<Response, HookError> {
+        if !self.has_on_response {
+            return Ok(response);
+        }
+
+        Ok(self
+            .call(
+                ON_RESPONSE,
+                &ResponseHookInput {
+                    request,
+                    response: &response,
+                },
+            )?
+            .unwrap_or(response))
+    }
+}
@@ -0,0 +1,104 @@
+use std::path::PathBuf;
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "client")]
+use {
+    color_eyre::eyre::{self, WrapErr},
+    std::{fs::File, io::BufReader},
+    tracing::warn,
+};
+
+/// Settings for the outbound HTTPS requests made by the proxy, such as OAuth2 token and userinfo
+/// requests
+#[derive(Debug, Default, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct HttpClientConfig {
+    /// PEM bundle of additional trusted CA certificates
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ca_bundle: Option<PathBuf>,
+
+    /// Proxy for outbound requests, as an `http://`, `https://`, `socks5://` or `socks5h://` URL.
+    /// The `HTTP_PROXY` and `HTTPS_PROXY` environment variables are used if not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub proxy: Option<String>,
+
+    /// Hosts reached without going through the proxy. A leading dot matches all subdomains.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub no_proxy: Vec<String>,
+
+    /// DANGEROUS: accept any server certificate, including self-signed or expired ones. This
+    /// allows attackers on the network to impersonate identity providers.
+    #[serde(default)]
+    pub danger_accept_invalid_certs: bool,
+}
+
+#[cfg(feature = "client")]
+impl HttpClientConfig {
+    /// Build an HTTP client with these settings. Redirects are not followed, as recommended for
+    /// OAuth2 clients.
+    pub fn build(&self) -> eyre::Result<reqwest::Client> {
+        Ok(self.builder()?.build()?)
+    }
+
+    /// Builder of an HTTP client with these settings, for further customization
+    pub fn builder(&self) -> eyre::Result<reqwest::ClientBuilder> {
+        let mut builder = reqwest::Client::builder().redirect(reqwest::redirect::Policy::none());
+
+        // Prefer rustls when both TLS backends are enabled
+        #[cfg(feature = "rustls")]
+        {
+            builder = builder.use_rustls_tls();
+        }
+
+        if let Some(path) = &self.ca_bundle {
+            let certs = File::open(path)
+                .map(BufReader::new)
+                .and_then(|mut reader| rustls_pemfile::certs(&mut reader))
+                .wrap_err_with(|| format!("could not read {}", path.display()))?;
+
+            if certs.is_empty() {
+                eyre::bail!("no certificate in {}", path.display());
+            }
+
+            for cert in certs {
+                builder = builder.add_root_certificate(reqwest::Certificate::from_der(&cert)?);
+            }
+        }
+
+        if let Some(proxy) = &self.proxy {
+            let proxy_url = url::Url::parse(proxy)?;
+            if !matches!(proxy_url.scheme(), "http" | "https" | "socks5" | "socks5h") {
+                eyre::bail!("unsupported proxy scheme in {}", proxy);
+            }
+
+            let no_proxy = self.no_proxy.clone();
+            builder = builder.proxy(reqwest::Proxy::custom(move |url| {
+                let bypass = url.host_str().map_or(false, |host| {
+                    no_proxy.iter().any(|entry| host_matches(entry, host))
+                });
+
+                (!bypass).then(|| proxy_url.clone())
+            }));
+        }
+
+        if self.danger_accept_invalid_certs {
+            warn!("certificate verification is disabled for outbound requests");
+            builder = builder.danger_accept_invalid_certs(true);
+        }
+
+        Ok(builder)
+    }
+}
+
+/// Returns true if the host pattern matches the host. A leading dot matches all subdomains.
+#[cfg(feature = "client")]
+pub(crate) fn host_matches(entry: &str, host: &str) -> bool {
+    let entry = entry.to_ascii_lowercase();
+
+    match entry.strip_prefix('.') {
+        Some(domain) => host == domain || host.ends_with(&entry),
+        None => host == entry,
+    }
+}
@@ -0,0 +1,218 @@
+//! Upstream latency of RPC calls, by method
+//!
+//! The durations of the most recent calls of each method are kept to compute percentiles, which
+//! admins get as JSON or in the Prometheus text format. Calls slower than a threshold are
+//! logged along with the number of torrents they targeted.
+
+use std::{
+    collections::{BTreeMap, VecDeque},
+    fmt::Write,
+    sync::Mutex,
+    time::Duration,
+};
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_samples() -> usize {
+    1000
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct LatencyConfig {
+    /// Track the upstream latency of RPC calls
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+
+    /// Number of recent calls of each method the percentiles are computed on
+    #[serde(default = "default_samples")]
+    pub samples: usize,
+
+    /// Log the calls which take longer than this, in milliseconds
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub slow_threshold: Option<u64>,
+}
+
+impl Default for LatencyConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            samples: default_samples(),
+            slow_threshold: None,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct MethodSamples {
+    /// Durations of the recent calls, oldest first
+    recent: VecDeque<Duration>,
+    count: u64,
+    total: Duration,
+}
+
+impl MethodSamples {
+    /// Durations of the recent calls, shortest first
+    fn sorted(&self) -> Vec<Duration> {
+        let mut sorted: Vec<_> = self.recent.iter().copied().collect();
+        sorted.sort_unstable();
+        sorted
+    }
+}
+
+/// Duration under which the given fraction of the sorted calls completed
+fn percentile(sorted: &[Duration], fraction: f64) -> Duration {
+    if sorted.is_empty() {
+        return Duration::ZERO;
+    }
+
+    let rank = (fraction * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+/// Latency of the calls of a method, in milliseconds
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MethodLatency {
+    /// Number of calls since the proxy started
+    pub count: u64,
+    pub p50: f64,
+    pub p95: f64,
+    pub p99: f64,
+    pub max: f64,
+}
+
+pub struct LatencyTracker {
+    samples: usize,
+    slow_threshold: Option<Duration>,
+    methods: Mutex<BTreeMap<String, MethodSamples>>,
+}
+
+impl LatencyTracker {
+    pub fn new(config: &LatencyConfig) -> Option<Self> {
+        config.enabled.then(|| Self {
+            samples: config.samples.max(1),
+            slow_threshold: config.slow_threshold.map(Duration::from_millis),
+            methods: Default::default(),
+        })
+    }
+
+    /// Record the duration of an upstream call. `torrents` is the number of torrents the call
+    /// targeted, if it named them.
+    pub fn record(&self, method: &str, elapsed: Duration, torrents: Option<usize>) {
+        if self
+            .slow_threshold
+            .map_or(false, |threshold| elapsed >= threshold)
+        {
+            warn!(
+                method,
+                torrents,
+                elapsed_ms = elapsed.as_millis() as u64,
+                "slow upstream call"
+            );
+        }
+
+        let mut methods = self.methods.lock().unwrap();
+        let samples = methods.entry(method.to_owned()).or_default();
+        if samples.recent.len() >= self.samples {
+            samples.recent.pop_front();
+        }
+        samples.recent.push_back(elapsed);
+        samples.count += 1;
+        samples.total += elapsed;
+    }
+
+    /// Latency percentiles of each method
+    pub fn summary(&self) -> BTreeMap<String, MethodLatency> {
+        let methods = self.methods.lock().unwrap();
+        methods
+            .iter()
+            .map(|(method, samples)| {
+                let sorted = samples.sorted();
+                let millis = |duration: Duration| duration.as_micros() as f64 / 1000.;
+                let latency = MethodLatency {
+                    count: samples.count,
+                    p50: millis(percentile(&sorted, 0.5)),
+                    p95: millis(percentile(&sorted, 0.95)),
+                    p99: millis(percentile(&sorted, 0.99)),
+                    max: millis(sorted.last().copied().unwrap_or_default()),
+                };
+
+                (method.clone(), latency)
+            })
+            .collect()
+    }
+
+    /// Latency percentiles of each method, in the Prometheus text format
+    pub fn prometheus(&self) -> String {
+        const NAME: &str = "transmission_proxy_upstream_latency_seconds";
+
+        let mut text = format!(
+            "# HELP {NAME} Upstream latency of RPC calls, by method\n# TYPE {NAME} summary\n"
+        );
+
+        let methods = self.methods.lock().unwrap();
+        for (method, samples) in methods.iter() {
+            let sorted = samples.sorted();
+            for quantile in [0.5, 0.95, 0.99] {
+                let value = percentile(&sorted, quantile).as_secs_f64();
+                let _ = writeln!(
+                    text,
+                    "{NAME}{{method=\"{method}\",quantile=\"{quantile}\"}} {value}"
+                );
+            }
+            let _ = writeln!(
+                text,
+                "{NAME}_sum{{method=\"{method}\"}} {}",
+                samples.total.as_secs_f64()
+            );
+            let _ = writeln!(
+                text,
+                "{NAME}_count{{method=\"{method}\"}} {}",
+                samples.count
+            );
+        }
+
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn percentiles_cover_the_recent_calls() {
+        let tracker = LatencyTracker::new(&LatencyConfig {
+            samples: 100,
+            ..Default::default()
+        })
+        .unwrap();
+
+        // The oldest calls are forgotten
+        tracker.record("torrent-set", Duration::from_secs(60), Some(1));
+        for millis in 1..=100 {
+            tracker.record("torrent-set", Duration::from_millis(millis), Some(500));
+        }
+
+        let latency = &tracker.summary()["torrent-set"];
+        assert_eq!(latency.count, 101);
+        assert_eq!(latency.p50, 50.);
+        assert_eq!(latency.p95, 95.);
+        assert_eq!(latency.p99, 99.);
+        assert_eq!(latency.max, 100.);
+
+        let text = tracker.prometheus();
+        assert!(text.contains(
+            "transmission_proxy_upstream_latency_seconds{method=\"torrent-set\",quantile=\"0.5\"} 0.05\n"
+        ));
+        assert!(text.contains(
+            "transmission_proxy_upstream_latency_seconds_count{method=\"torrent-set\"} 101\n"
+        ));
+    }
+}
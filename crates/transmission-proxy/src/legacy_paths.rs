@@ -0,0 +1,131 @@
+//! Non-RPC endpoints which older Transmission clients expect at fixed paths
+//!
+//! Clients hard-code `/transmission/web/` and `/transmission/upload`, and browsers ask for
+//! `/favicon.ico`. When the proxy is bound to another path, these are redirected to the web
+//! interface of the proxy, and uploads are added through the ACL of the user.
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_prefix() -> String {
+    "/transmission".into()
+}
+
+fn default_favicon() -> String {
+    "images/favicon.ico".into()
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct LegacyPaths {
+    /// Serve the legacy endpoints
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+
+    /// Path prefix hard-coded by older clients
+    #[serde(default = "default_prefix")]
+    pub prefix: String,
+
+    /// Path of the favicon, relative to the web interface
+    #[serde(default = "default_favicon")]
+    pub favicon: String,
+}
+
+impl Default for LegacyPaths {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            prefix: default_prefix(),
+            favicon: default_favicon(),
+        }
+    }
+}
+
+impl LegacyPaths {
+    pub fn prefix(&self) -> &str {
+        self.prefix.trim_end_matches('/')
+    }
+}
+
+/// Boundary of a multipart/form-data content type
+pub fn multipart_boundary(content_type: &str) -> Option<&str> {
+    let (mime, params) = content_type.split_once(';')?;
+    if !mime.trim().eq_ignore_ascii_case("multipart/form-data") {
+        return None;
+    }
+
+    params.split(';').find_map(|param| {
+        let (name, value) = param.split_once('=')?;
+        name.trim()
+            .eq_ignore_ascii_case("boundary")
+            .then(|| value.trim().trim_matches('"'))
+            .filter(|boundary| !boundary.is_empty())
+    })
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+/// Contents of the parts of a multipart/form-data body, without their headers
+pub fn multipart_parts<'b>(body: &'b [u8], boundary: &str) -> Vec<&'b [u8]> {
+    let delimiter = format!("--{boundary}");
+    let mut parts = Vec::new();
+
+    let Some(start) = find(body, delimiter.as_bytes()) else {
+        return parts;
+    };
+    let mut rest = &body[start + delimiter.len()..];
+
+    // Each part follows a delimiter, the last one being followed by "--"
+    while !rest.starts_with(b"--") {
+        let Some(end) = find(rest, delimiter.as_bytes()) else {
+            break;
+        };
+
+        let part = &rest[..end];
+        if let Some(headers_end) = find(part, b"\r\n\r\n") {
+            let content = &part[headers_end + 4..];
+            parts.push(content.strip_suffix(b"\r\n").unwrap_or(content));
+        }
+
+        rest = &rest[end + delimiter.len()..];
+    }
+
+    parts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn multipart_bodies_are_split() {
+        let content_type = "multipart/form-data; boundary=\"xyz\"";
+        let boundary = multipart_boundary(content_type).unwrap();
+        assert_eq!(boundary, "xyz");
+
+        let body = b"--xyz\r\n\
+            Content-Disposition: form-data; name=\"torrent_files[]\"; filename=\"a.torrent\"\r\n\
+            Content-Type: application/x-bittorrent\r\n\
+            \r\n\
+            d4:infod4:name1:aee\r\n\
+            --xyz\r\n\
+            Content-Disposition: form-data; name=\"torrent_files[]\"; filename=\"b.torrent\"\r\n\
+            \r\n\
+            d4:infod4:name1:bee\r\n\
+            --xyz--\r\n";
+
+        assert_eq!(
+            multipart_parts(body, boundary),
+            vec![&b"d4:infod4:name1:aee"[..], &b"d4:infod4:name1:bee"[..]]
+        );
+        assert_eq!(multipart_boundary("application/json"), None);
+    }
+}
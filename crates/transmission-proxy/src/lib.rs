@@ -3,16 +3,75 @@ use std::{num::NonZeroUsize, path::PathBuf};
 use clap::Parser;
 use color_eyre::eyre;
 use hyper::Uri;
-use rand::Rng;
-use tracing::{span, warn, Level};
+use tracing::{span, Level};
 
+#[cfg(all(
+    feature = "client",
+    not(any(feature = "rustls", feature = "native-tls"))
+))]
+compile_error!("the client feature needs a TLS backend, enable either rustls or native-tls");
+
+#[cfg(all(feature = "smtp", not(any(feature = "rustls", feature = "native-tls"))))]
+compile_error!("the smtp feature needs a TLS backend, enable either rustls or native-tls");
+
+mod accounts;
 mod acl;
+mod acme;
+mod api_tokens;
 mod auth;
+#[cfg(feature = "chaos")]
+mod chaos;
+mod client_profiles;
+mod compression;
 mod config;
+mod cors;
+mod csrf;
+mod custom_routes;
 mod error;
+mod events;
+mod explain;
+mod failover;
+mod forwarding;
+mod hooks;
+mod http_client;
+mod latency;
+mod legacy_paths;
+mod listener;
+mod maintenance;
+mod migrate;
+mod notifications;
+mod ownership;
+mod password;
+mod port_test;
+mod provisioning;
+mod record;
+mod request_log;
 mod rpc;
+mod scheduler;
+mod secret_file;
+mod secret_key;
+mod security_headers;
 mod server;
+mod session_cookies;
+mod shares;
+mod snapshot;
+mod state;
+mod storage;
+#[cfg(feature = "test-util")]
+pub mod testing;
 pub mod torrent;
+mod torrent_index;
+mod torrent_reports;
+mod torrent_stream;
+mod torrent_urls;
+mod totp;
+mod tracker_stats;
+mod uploads;
+mod upstream_auth;
+mod version_check;
+mod web_ui;
+#[cfg(feature = "webauthn")]
+mod webauthn;
 
 #[derive(Debug, Parser)]
 #[clap(author, version, about, long_about = None)]
@@ -60,6 +119,59 @@ pub struct Args {
     /// Secret key for signing JWTs
     #[clap(long, default_value = "", env = "TRANSMISSION_PROXY_SECRET_KEY")]
     pub secret_key: String,
+
+    /// File holding the secret key for signing JWTs, read again when it changes
+    #[clap(
+        long,
+        conflicts_with = "secret_key",
+        env = "TRANSMISSION_PROXY_SECRET_KEY_FILE"
+    )]
+    pub secret_key_file: Option<PathBuf>,
+
+    /// Where the secret key comes from when none is specified
+    #[clap(
+        long,
+        value_enum,
+        default_value_t,
+        env = "TRANSMISSION_PROXY_GENERATED_KEY"
+    )]
+    pub generated_key: secret_key::GeneratedKey,
+
+    /// File the generated secret key is saved to, with --generated-key persist
+    #[clap(
+        long,
+        default_value = "transmission-proxy.key",
+        env = "TRANSMISSION_PROXY_GENERATED_KEY_FILE"
+    )]
+    pub generated_key_file: PathBuf,
+
+    /// Record proxied RPC calls to this file, for debugging
+    #[clap(long, env = "TRANSMISSION_PROXY_RECORD")]
+    pub record: Option<PathBuf>,
+
+    /// Skip invalid ACLs, tracker rule sets and routes with a warning instead of refusing to
+    /// start
+    #[clap(long, env = "TRANSMISSION_PROXY_LENIENT_CONFIG")]
+    pub lenient_config: bool,
+
+    #[clap(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Debug, clap::Subcommand)]
+pub enum Command {
+    /// Re-send RPC calls recorded with --record to a daemon or proxy
+    #[cfg(feature = "client")]
+    Replay(record::ReplayArgs),
+    /// Export or import the state of a running proxy
+    #[cfg(feature = "client")]
+    Snapshot(snapshot::SnapshotArgs),
+    /// Hash a password read from standard input, for basic auth users
+    HashPassword(password::HashPasswordArgs),
+    /// Print the JSON schema of the configuration file
+    Schema,
+    /// Update a configuration file written for an older version
+    MigrateConfig(migrate::MigrateConfigArgs),
 }
 
 impl Args {
@@ -69,26 +181,29 @@ impl Args {
 }
 
 pub async fn run(mut args: Args) -> eyre::Result<()> {
+    if let Some(command) = args.command.take() {
+        return match command {
+            #[cfg(feature = "client")]
+            Command::Replay(replay_args) => record::replay(replay_args).await,
+            #[cfg(feature = "client")]
+            Command::Snapshot(snapshot_args) => snapshot::run(snapshot_args).await,
+            Command::HashPassword(hash_args) => password::run(hash_args),
+            Command::Schema => config::print_schema(),
+            Command::MigrateConfig(migrate_args) => migrate::run(&args.config, migrate_args),
+        };
+    }
+
     // Parse configuration
     let config: config::Config = {
         let span = span!(Level::INFO, "config", config = %args.config.display());
         let _guard = span.enter();
 
-        let f = std::fs::File::open(&args.config)?;
-        serde_yaml::from_reader(f)?
+        let text = std::fs::read_to_string(&args.config)?;
+        config::Config::load(&text, args.lenient_config)?
     };
+    migrate::check_version(&config)?;
 
-    // Generate key if needed
-    if args.secret_key.is_empty() {
-        const LEN: usize = 32;
-        let mut rng = rand::thread_rng();
-        args.secret_key.reserve(LEN);
-        for _ in 0..LEN {
-            args.secret_key.push(rng.gen_range('0'..'z'))
-        }
-
-        warn!("generated secret key because none was specified");
-    }
+    secret_key::generate(&mut args)?;
 
     server::run(args, config).await
 }
@@ -0,0 +1,256 @@
+use std::{
+    fs::File,
+    future::Future,
+    io::{self, BufReader},
+    net::{SocketAddr, TcpListener},
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+
+use axum::{extract::connect_info::Connected, Router};
+use color_eyre::eyre::{self, WrapErr};
+use futures_util::stream;
+use hyper::server::{
+    accept,
+    conn::{AddrIncoming, AddrStream},
+    Builder, Server,
+};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use socket2::{SockRef, TcpKeepalive};
+use tokio::sync::mpsc;
+use tokio_rustls::{rustls, server::TlsStream, TlsAcceptor};
+use tracing::{debug, warn};
+
+use crate::acme::{AcmeConfig, CertResolver};
+
+/// Address of the client of a connection, available through `ConnectInfo`
+#[derive(Debug, Clone, Copy)]
+pub struct ClientAddr(pub Option<SocketAddr>);
+
+impl Connected<&AddrStream> for ClientAddr {
+    fn connect_info(target: &AddrStream) -> Self {
+        Self(Some(target.remote_addr()))
+    }
+}
+
+impl Connected<&TlsStream<tokio::net::TcpStream>> for ClientAddr {
+    fn connect_info(target: &TlsStream<tokio::net::TcpStream>) -> Self {
+        Self(target.get_ref().0.peer_addr().ok())
+    }
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_http2_keep_alive_timeout() -> u64 {
+    20
+}
+
+/// Certificate and private key for serving https
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct TlsConfig {
+    /// PEM file holding the certificate chain
+    pub cert: PathBuf,
+    /// PEM file holding the private key
+    pub key: PathBuf,
+}
+
+impl TlsConfig {
+    fn server_config(&self) -> eyre::Result<rustls::ServerConfig> {
+        fn open(path: &Path) -> eyre::Result<BufReader<File>> {
+            Ok(BufReader::new(File::open(path).wrap_err_with(|| {
+                format!("could not open {}", path.display())
+            })?))
+        }
+
+        let certs = rustls_pemfile::certs(&mut open(&self.cert)?)?
+            .into_iter()
+            .map(rustls::Certificate)
+            .collect();
+
+        let key = rustls_pemfile::read_all(&mut open(&self.key)?)?
+            .into_iter()
+            .find_map(|item| match item {
+                rustls_pemfile::Item::RSAKey(key)
+                | rustls_pemfile::Item::PKCS8Key(key)
+                | rustls_pemfile::Item::ECKey(key) => Some(rustls::PrivateKey(key)),
+                _ => None,
+            })
+            .ok_or_else(|| eyre::eyre!("no private key in {}", self.key.display()))?;
+
+        let mut config = rustls::ServerConfig::builder()
+            .with_safe_defaults()
+            .with_no_client_auth()
+            .with_single_cert(certs, key)?;
+
+        config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+        Ok(config)
+    }
+}
+
+/// Connection settings of the HTTP listener
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct ListenerConfig {
+    /// Serve HTTP/2, with prior knowledge (h2c) or negotiated over TLS
+    #[serde(default = "default_true")]
+    pub http2: bool,
+
+    /// Maximum number of concurrent HTTP/2 streams per connection
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub http2_max_concurrent_streams: Option<u32>,
+
+    /// Interval between HTTP/2 keep-alive pings, in seconds. Disabled if unset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub http2_keep_alive_interval: Option<u64>,
+
+    /// Time to wait for HTTP/2 keep-alive ping acknowledgements before closing the connection,
+    /// in seconds
+    #[serde(default = "default_http2_keep_alive_timeout")]
+    pub http2_keep_alive_timeout: u64,
+
+    /// Keep HTTP/1 connections alive between requests
+    #[serde(default = "default_true")]
+    pub http1_keep_alive: bool,
+
+    /// Idle time before sending TCP keep-alive probes, in seconds. Disabled if unset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tcp_keep_alive: Option<u64>,
+
+    /// Time the upstream has to answer a proxied request, in seconds. Unlimited if unset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request_deadline: Option<u64>,
+
+    /// Serve https directly instead of relying on a reverse proxy
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tls: Option<TlsConfig>,
+
+    /// Serve https with certificates obtained from an ACME provider
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub acme: Option<AcmeConfig>,
+}
+
+impl Default for ListenerConfig {
+    fn default() -> Self {
+        Self {
+            http2: true,
+            http2_max_concurrent_streams: None,
+            http2_keep_alive_interval: None,
+            http2_keep_alive_timeout: default_http2_keep_alive_timeout(),
+            http1_keep_alive: true,
+            tcp_keep_alive: None,
+            request_deadline: None,
+            tls: None,
+            acme: None,
+        }
+    }
+}
+
+impl ListenerConfig {
+    fn configure<I>(&self, builder: Builder<I>) -> Builder<I> {
+        builder
+            .http1_only(!self.http2)
+            .http1_keepalive(self.http1_keep_alive)
+            .http2_max_concurrent_streams(self.http2_max_concurrent_streams)
+            .http2_keep_alive_interval(self.http2_keep_alive_interval.map(Duration::from_secs))
+            .http2_keep_alive_timeout(Duration::from_secs(self.http2_keep_alive_timeout))
+    }
+
+    fn tcp_keep_alive(&self) -> Option<Duration> {
+        self.tcp_keep_alive.map(Duration::from_secs)
+    }
+
+    /// Serve the application on the given listener until `shutdown` completes
+    pub async fn serve(
+        &self,
+        listener: TcpListener,
+        router: Router,
+        shutdown: impl Future<Output = ()>,
+    ) -> eyre::Result<()> {
+        listener.set_nonblocking(true)?;
+        let listener = tokio::net::TcpListener::from_std(listener)?;
+
+        let server_config = match (&self.tls, &self.acme) {
+            (Some(_), Some(_)) => eyre::bail!("tls and acme can't be enabled together"),
+            (Some(tls), None) => Some(tls.server_config()?),
+            (None, Some(acme)) => {
+                let resolver = Arc::new(CertResolver::default());
+                tokio::spawn(acme.clone().run(resolver.clone()));
+                Some(acme.server_config(resolver))
+            }
+            (None, None) => None,
+        };
+
+        if let Some(server_config) = server_config {
+            let acceptor = TlsAcceptor::from(Arc::new(server_config));
+            let incoming = self.accept_tls(listener, acceptor);
+
+            self.configure(Server::builder(accept::from_stream(incoming)))
+                .serve(router.into_make_service_with_connect_info::<ClientAddr>())
+                .with_graceful_shutdown(shutdown)
+                .await?;
+        } else {
+            let mut incoming = AddrIncoming::from_listener(listener)?;
+            incoming.set_keepalive(self.tcp_keep_alive());
+
+            self.configure(Server::builder(incoming))
+                .serve(router.into_make_service_with_connect_info::<ClientAddr>())
+                .with_graceful_shutdown(shutdown)
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    /// Accept TLS connections, running the handshakes in parallel
+    fn accept_tls(
+        &self,
+        listener: tokio::net::TcpListener,
+        acceptor: TlsAcceptor,
+    ) -> impl futures_util::Stream<Item = io::Result<TlsStream<tokio::net::TcpStream>>> {
+        let (tx, rx) = mpsc::channel(64);
+        let keep_alive = self.tcp_keep_alive();
+
+        tokio::spawn(async move {
+            while !tx.is_closed() {
+                let stream = match listener.accept().await {
+                    Ok((stream, _)) => stream,
+                    Err(err) => {
+                        // Usually out of file descriptors, wait for connections to close
+                        warn!(%err, "could not accept connection");
+                        tokio::time::sleep(Duration::from_millis(100)).await;
+                        continue;
+                    }
+                };
+
+                if let Some(keep_alive) = keep_alive {
+                    let keep_alive = TcpKeepalive::new().with_time(keep_alive);
+                    if let Err(err) = SockRef::from(&stream).set_tcp_keepalive(&keep_alive) {
+                        warn!(%err, "could not enable tcp keep-alive");
+                    }
+                }
+
+                let acceptor = acceptor.clone();
+                let tx = tx.clone();
+                tokio::spawn(async move {
+                    match acceptor.accept(stream).await {
+                        Ok(stream) => {
+                            tx.send(Ok(stream)).await.ok();
+                        }
+                        Err(err) => {
+                            debug!(%err, "tls handshake failed");
+                        }
+                    }
+                });
+            }
+        });
+
+        stream::unfold(rx, |mut rx| async move {
+            rx.recv().await.map(|stream| (stream, rx))
+        })
+    }
+}
@@ -0,0 +1,91 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use crate::state::{SharedState, StateError};
+
+fn default_message() -> String {
+    "Transmission is down for maintenance, please come back later.".into()
+}
+
+/// Maintenance mode, where only admins can use the proxy
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct MaintenanceConfig {
+    /// Start in maintenance mode
+    #[serde(default)]
+    pub enabled: bool,
+
+    /// Message shown to users while in maintenance mode
+    #[serde(default = "default_message")]
+    pub message: String,
+}
+
+impl Default for MaintenanceConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            message: default_message(),
+        }
+    }
+}
+
+/// Key of the maintenance flag in the shared state
+const STATE_KEY: &str = "maintenance";
+
+/// How long the flag read from the shared state is reused, since it is checked on every request
+const CACHE_TTL: Duration = Duration::from_secs(1);
+
+/// Current maintenance mode state, which can be toggled at runtime by admins
+#[derive(Debug)]
+pub struct Maintenance {
+    state: Arc<SharedState>,
+    /// State until an admin toggles it
+    default: bool,
+    /// Last flag read from the shared state
+    cached: Mutex<Option<(Instant, bool)>>,
+}
+
+impl Maintenance {
+    pub fn new(config: &MaintenanceConfig, state: Arc<SharedState>) -> Self {
+        Self {
+            state,
+            default: config.enabled,
+            cached: Default::default(),
+        }
+    }
+
+    pub async fn is_enabled(&self) -> bool {
+        if let Some((at, enabled)) = *self.cached.lock().unwrap() {
+            if at.elapsed() < CACHE_TTL {
+                return enabled;
+            }
+        }
+
+        let enabled = match self.state.get(STATE_KEY).await {
+            Ok(enabled) => enabled.map_or(self.default, |enabled| enabled == "true"),
+            Err(err) => {
+                warn!(%err, "could not read maintenance mode");
+                self.default
+            }
+        };
+
+        *self.cached.lock().unwrap() = Some((Instant::now(), enabled));
+        enabled
+    }
+
+    pub async fn set_enabled(&self, enabled: bool) -> Result<(), StateError> {
+        self.state
+            .set(STATE_KEY, if enabled { "true" } else { "false" }, None)
+            .await?;
+
+        // Other replicas see the change once their cached flag expires
+        *self.cached.lock().unwrap() = Some((Instant::now(), enabled));
+        Ok(())
+    }
+}
@@ -0,0 +1,278 @@
+//! Migrations of configuration files written for older versions of the proxy
+//!
+//! Migrations edit the text of the file where they can, so comments and formatting are kept.
+//! Sections they can't find as plain block mappings are rewritten from the parsed document
+//! instead, which drops the comments.
+
+use std::path::{Path, PathBuf};
+
+use color_eyre::eyre;
+use serde_yaml::Value;
+use tracing::warn;
+
+use crate::config::Config;
+
+/// Version of the configuration format of this build
+pub const CONFIG_VERSION: u32 = 1;
+
+#[derive(Debug, clap::Args)]
+pub struct MigrateConfigArgs {
+    /// Write the migrated configuration to this file instead of standard output
+    #[clap(short, long, conflicts_with = "in_place")]
+    pub output: Option<PathBuf>,
+
+    /// Replace the configuration file with the migrated one
+    #[clap(long)]
+    pub in_place: bool,
+
+    /// Only check whether the configuration needs migrations, failing if it does
+    #[clap(long)]
+    pub check: bool,
+}
+
+/// Change of the configuration format
+struct Migration {
+    /// Version of the configuration format after this migration
+    version: u32,
+    description: &'static str,
+    apply: fn(&mut Document) -> eyre::Result<()>,
+}
+
+const MIGRATIONS: &[Migration] = &[Migration {
+    version: 1,
+    description: "users matching no ACL are now denied, set acl.default_policy to allow to keep \
+                  forwarding their requests",
+    apply: |document| {
+        if document.value()?["acl"].get("default_policy").is_some() {
+            return Ok(());
+        }
+
+        document.insert(Some("acl"), "default_policy", "allow")
+    },
+}];
+
+/// Text of a configuration file being migrated
+struct Document {
+    text: String,
+    /// Comments were lost because a section had to be rewritten
+    rewritten: bool,
+}
+
+impl Document {
+    fn value(&self) -> eyre::Result<Value> {
+        Ok(serde_yaml::from_str(&self.text)?)
+    }
+
+    /// Index of the line after the leading comments and document marker
+    fn start(&self) -> usize {
+        let lines: Vec<_> = self.text.lines().collect();
+        let mut start = lines
+            .iter()
+            .take_while(|line| line.trim().is_empty() || line.starts_with('#'))
+            .count();
+        if lines
+            .get(start)
+            .map_or(false, |line| line.starts_with("---"))
+        {
+            start += 1;
+        }
+        start
+    }
+
+    /// Add a key with a scalar value to a top-level section, or to the root if `section` is
+    /// `None`
+    fn insert(&mut self, section: Option<&str>, key: &str, value: &str) -> eyre::Result<()> {
+        let mut lines: Vec<String> = self.text.lines().map(str::to_owned).collect();
+
+        let position = match section {
+            None => Some((self.start(), String::new())),
+            Some(section) => lines
+                .iter()
+                .position(|line| is_block_key(line, section))
+                .map(|index| {
+                    // Match the indentation of the first entry of the section
+                    let indent = lines[index + 1..]
+                        .iter()
+                        .find(|line| !line.trim().is_empty() && !line.trim().starts_with('#'))
+                        .map(|line| &line[..line.len() - line.trim_start().len()])
+                        .filter(|indent| !indent.is_empty())
+                        .unwrap_or("  ");
+
+                    (index + 1, indent.to_owned())
+                }),
+        };
+
+        if let Some((index, indent)) = position {
+            lines.insert(index, format!("{indent}{key}: {value}"));
+            self.text = lines.join("\n") + "\n";
+            return Ok(());
+        }
+
+        // The section is in flow style or spread over several lines, rewrite the whole file
+        let mut document = self.value()?;
+        let mapping = match section {
+            None => document.as_mapping_mut(),
+            Some(section) => document.get_mut(section).and_then(Value::as_mapping_mut),
+        }
+        .ok_or_else(|| eyre::eyre!("{} is not a mapping", section.unwrap_or("the document")))?;
+        mapping.insert(key.into(), serde_yaml::from_str(value)?);
+
+        self.text = serde_yaml::to_string(&document)?;
+        self.rewritten = true;
+        Ok(())
+    }
+
+    /// Set the version of the configuration format
+    fn set_version(&mut self, version: u32) -> eyre::Result<()> {
+        let mut lines: Vec<String> = self.text.lines().map(str::to_owned).collect();
+        if let Some(line) = lines.iter_mut().find(|line| line.starts_with("version:")) {
+            *line = format!("version: {version}");
+            self.text = lines.join("\n") + "\n";
+            return Ok(());
+        }
+
+        self.insert(None, "version", &version.to_string())
+    }
+}
+
+/// Returns true if the line starts a top-level block mapping for the given key
+fn is_block_key(line: &str, key: &str) -> bool {
+    line.strip_prefix(key)
+        .and_then(|rest| rest.strip_prefix(':'))
+        .map_or(false, |rest| {
+            let rest = rest.trim();
+            rest.is_empty() || rest.starts_with('#')
+        })
+}
+
+/// Version of a configuration file, 0 if it predates versioning
+fn version_of(value: &Value) -> eyre::Result<u32> {
+    match value.get("version") {
+        None => Ok(0),
+        Some(version) => Ok(serde_yaml::from_value(version.clone())?),
+    }
+}
+
+/// Apply the migrations a configuration needs, returning the new text and the descriptions of
+/// the migrations which were applied
+fn migrate(text: &str) -> eyre::Result<(Document, Vec<&'static str>)> {
+    let mut document = Document {
+        text: text.to_owned(),
+        rewritten: false,
+    };
+
+    let version = match document.value()? {
+        Value::Null => 0,
+        value @ Value::Mapping(_) => version_of(&value)?,
+        _ => eyre::bail!("the configuration is not a mapping"),
+    };
+    if version > CONFIG_VERSION {
+        eyre::bail!("the configuration is for a newer version ({version} > {CONFIG_VERSION})");
+    }
+
+    let mut applied = Vec::new();
+    for migration in MIGRATIONS.iter().filter(|m| m.version > version) {
+        let before = document.text.clone();
+        (migration.apply)(&mut document)?;
+        if document.text != before {
+            applied.push(migration.description);
+        }
+    }
+
+    if version < CONFIG_VERSION {
+        document.set_version(CONFIG_VERSION)?;
+    }
+
+    Ok((document, applied))
+}
+
+/// Check the version of a loaded configuration, warning about pending migrations
+pub fn check_version(config: &Config) -> eyre::Result<()> {
+    if config.version > CONFIG_VERSION {
+        eyre::bail!(
+            "the configuration is for a newer version ({} > {CONFIG_VERSION})",
+            config.version
+        );
+    }
+
+    if config.version < CONFIG_VERSION {
+        warn!(
+            version = config.version,
+            "the configuration format is outdated, see the migrate-config command"
+        );
+    }
+
+    Ok(())
+}
+
+/// Migrate a configuration file, printing the applied migrations to standard error
+pub fn run(config: &Path, args: MigrateConfigArgs) -> eyre::Result<()> {
+    let text = std::fs::read_to_string(config)?;
+    let (document, applied) = migrate(&text)?;
+
+    for description in &applied {
+        eprintln!("- {description}");
+    }
+
+    if args.check {
+        if document.text != text {
+            eyre::bail!("{} needs to be migrated", config.display());
+        }
+
+        return Ok(());
+    }
+
+    // Refuse to write a file the proxy would not load
+    serde_yaml::from_str::<Config>(&document.text)
+        .map_err(|err| eyre::eyre!("the migrated configuration is invalid: {err}"))?;
+    if document.rewritten {
+        eprintln!("warning: the comments of the configuration could not be preserved");
+    }
+
+    match (args.output, args.in_place) {
+        (Some(output), _) => std::fs::write(output, &document.text)?,
+        (None, true) => std::fs::write(config, &document.text)?,
+        (None, false) => print!("{}", document.text),
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn migrations_keep_comments() {
+        let (document, applied) = migrate(
+            "# Proxy configuration\nacl:\n    # Admins first\n    rules: []\nproviders: {}\n",
+        )
+        .unwrap();
+
+        assert_eq!(applied.len(), 1);
+        assert!(!document.rewritten);
+        assert_eq!(
+            document.text,
+            "# Proxy configuration\nversion: 1\nacl:\n    default_policy: allow\n    \
+             # Admins first\n    rules: []\nproviders: {}\n"
+        );
+
+        // Migrated configurations are left alone
+        let (again, applied) = migrate(&document.text).unwrap();
+        assert!(applied.is_empty());
+        assert_eq!(again.text, document.text);
+    }
+
+    #[test]
+    fn flow_sections_are_rewritten() {
+        let (document, _) = migrate("acl: { rules: [], default_policy: deny }\n").unwrap();
+        assert!(!document.rewritten);
+        assert!(document.text.starts_with("version: 1\n"));
+
+        let (document, _) = migrate("acl: { rules: [] }\n").unwrap();
+        assert!(document.rewritten);
+        let value = document.value().unwrap();
+        assert_eq!(value["acl"]["default_policy"], "allow");
+        assert_eq!(value["version"], 1);
+    }
+}
@@ -0,0 +1,400 @@
+use std::{collections::BTreeMap, net::IpAddr};
+
+use color_eyre::eyre;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use tokio::sync::broadcast;
+use tracing::debug;
+#[cfg(feature = "client")]
+use {
+    hyper::header::{HeaderMap, HeaderName, HeaderValue},
+    tracing::warn,
+};
+
+use crate::{
+    events::{self, Event},
+    http_client::HttpClientConfig,
+    torrent_reports::{TorrentProblem, TorrentReportsConfig},
+};
+
+#[cfg(feature = "client")]
+mod apprise;
+#[cfg(feature = "smtp")]
+mod smtp;
+
+/// Notifications of security events and upstream outages, sent to administrators or the affected
+/// users
+#[derive(Debug, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct NotificationsConfig {
+    /// Notify logins from an address and browser the user never logged in from
+    #[serde(default)]
+    pub new_logins: bool,
+
+    /// Endpoints receiving notifications as JSON POST requests
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub webhooks: Vec<WebhookConfig>,
+
+    /// Mail server sending notifications by email
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub smtp: Option<SmtpConfig>,
+
+    /// Apprise API server forwarding notifications to chat and push services
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub apprise: Option<AppriseConfig>,
+
+    /// Reports of errored and stalled torrents to their owners
+    #[serde(default)]
+    pub torrent_reports: TorrentReportsConfig,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct WebhookConfig {
+    pub url: url::Url,
+
+    /// Headers added to the requests, e.g. for authentication
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub headers: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct AppriseConfig {
+    /// URL of the Apprise API server, e.g. `http://apprise:8000`
+    pub url: url::Url,
+
+    /// Configuration key of the server notifications are sent to
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key: Option<String>,
+
+    /// Configuration keys of specific events, overriding `key`
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub keys: BTreeMap<String, String>,
+}
+
+/// Encryption of the connection to the mail server
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum SmtpTls {
+    /// Upgrade the connection with STARTTLS, on port 587 by default
+    #[default]
+    Starttls,
+    /// Implicit TLS, on port 465 by default
+    Tls,
+    /// DANGEROUS: no encryption, on port 25 by default
+    None,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct SmtpConfig {
+    pub host: String,
+
+    /// Port of the mail server, the default of the TLS mode if unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub port: Option<u16>,
+
+    #[serde(default)]
+    pub tls: SmtpTls,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub password: Option<String>,
+
+    /// Sender of the emails, e.g. `Transmission Proxy <proxy@example.com>`
+    pub from: String,
+
+    /// Administrators receiving all notifications
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub to: Vec<String>,
+
+    /// Also email notifications about a user to their address, if it is known
+    #[serde(default)]
+    pub notify_users: bool,
+}
+
+/// Event worth telling someone about
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Notification {
+    /// Successful login from an address and browser the user never used before
+    NewLogin {
+        user: String,
+        /// Address of the user, if known
+        #[serde(skip_serializing_if = "Option::is_none")]
+        email: Option<String>,
+        provider: String,
+        client_ip: Option<IpAddr>,
+        user_agent: Option<String>,
+        /// Unix timestamp of the login
+        time: u64,
+    },
+    /// The primary upstream is unreachable, requests go to the standby
+    FailedOver {
+        /// Time the primary upstream has been unreachable for, in seconds
+        down_for: u64,
+    },
+    /// The primary upstream is reachable again
+    FailedBack,
+    /// Torrents of a user have errors or are stalled
+    TorrentProblems {
+        user: String,
+        /// Address of the user, if known
+        #[serde(skip_serializing_if = "Option::is_none")]
+        email: Option<String>,
+        torrents: Vec<TorrentProblem>,
+    },
+}
+
+impl Notification {
+    /// Notification of an event, if it deserves one
+    fn of(event: &Event, new_logins: bool) -> Option<Self> {
+        match event {
+            Event::Login {
+                user,
+                email,
+                provider,
+                client_ip,
+                user_agent,
+                new_device: true,
+                time,
+            } if new_logins => Some(Notification::NewLogin {
+                user: user.clone(),
+                email: email.clone(),
+                provider: provider.clone(),
+                client_ip: *client_ip,
+                user_agent: user_agent.clone(),
+                time: *time,
+            }),
+            Event::UpstreamUnavailable { down_for } => Some(Notification::FailedOver {
+                down_for: *down_for,
+            }),
+            Event::UpstreamRestored => Some(Notification::FailedBack),
+            Event::TorrentProblems {
+                user,
+                email,
+                torrents,
+            } => Some(Notification::TorrentProblems {
+                user: user.clone(),
+                email: email.clone(),
+                torrents: torrents.clone(),
+            }),
+            _ => None,
+        }
+    }
+
+    /// Names of the events, as in the `event` field
+    #[cfg_attr(not(feature = "client"), allow(dead_code))]
+    pub const EVENTS: &'static [&'static str] = &[
+        "new_login",
+        "failed_over",
+        "failed_back",
+        "torrent_problems",
+    ];
+
+    /// Name of the event, as in the `event` field
+    #[cfg_attr(not(feature = "client"), allow(dead_code))]
+    pub fn event(&self) -> &'static str {
+        match self {
+            Notification::NewLogin { .. } => "new_login",
+            Notification::FailedOver { .. } => "failed_over",
+            Notification::FailedBack => "failed_back",
+            Notification::TorrentProblems { .. } => "torrent_problems",
+        }
+    }
+
+    /// Title of the notification, e.g. for email subjects
+    #[cfg_attr(not(any(feature = "client", feature = "smtp")), allow(dead_code))]
+    pub fn subject(&self) -> &'static str {
+        match self {
+            Notification::NewLogin { .. } => "New login to Transmission",
+            Notification::FailedOver { .. } => "Transmission upstream unreachable",
+            Notification::FailedBack => "Transmission upstream restored",
+            Notification::TorrentProblems { .. } => "Transmission torrents need attention",
+        }
+    }
+
+    /// Address of the user the notification is about, if any
+    #[cfg_attr(not(feature = "smtp"), allow(dead_code))]
+    pub fn user_email(&self) -> Option<&str> {
+        match self {
+            Notification::NewLogin { email, .. } | Notification::TorrentProblems { email, .. } => {
+                email.as_deref()
+            }
+            _ => None,
+        }
+    }
+
+    /// Short description, for channels which only take text
+    pub fn message(&self) -> String {
+        match self {
+            Notification::NewLogin {
+                user,
+                provider,
+                client_ip,
+                user_agent,
+                ..
+            } => format!(
+                "New login for {user} with {provider} from {} ({})",
+                client_ip.map_or_else(|| "an unknown address".to_owned(), |ip| ip.to_string()),
+                user_agent.as_deref().unwrap_or("unknown browser"),
+            ),
+            Notification::FailedOver { down_for } => format!(
+                "The primary upstream has been unreachable for {down_for}s, requests now go to \
+                 the standby"
+            ),
+            Notification::FailedBack => {
+                "The primary upstream is reachable again, requests go to it again".to_owned()
+            }
+            Notification::TorrentProblems { user, torrents, .. } => {
+                let mut message = format!("Torrents of {user} need attention:");
+                for torrent in torrents {
+                    message += &format!("\n- {}: {}", torrent.name, torrent.error);
+                }
+                message
+            }
+        }
+    }
+}
+
+#[cfg(feature = "client")]
+struct Webhook {
+    url: url::Url,
+    headers: HeaderMap,
+}
+
+/// Sends notifications to the configured channels, in the background
+pub struct Notifier {
+    new_logins: bool,
+    #[cfg(feature = "client")]
+    client: Option<reqwest::Client>,
+    #[cfg(feature = "client")]
+    webhooks: Arc<Vec<Webhook>>,
+    #[cfg(feature = "client")]
+    apprise: Option<Arc<apprise::AppriseNotifier>>,
+    #[cfg(feature = "smtp")]
+    smtp: Option<Arc<smtp::SmtpNotifier>>,
+}
+
+impl Notifier {
+    pub fn new(config: &NotificationsConfig, http_client: &HttpClientConfig) -> eyre::Result<Self> {
+        #[cfg(not(feature = "client"))]
+        {
+            let _ = http_client;
+            if !config.webhooks.is_empty() || config.apprise.is_some() {
+                eyre::bail!(
+                    "webhooks and apprise are not supported by this build, enable the client \
+                     feature"
+                );
+            }
+        }
+
+        #[cfg(not(feature = "smtp"))]
+        if config.smtp.is_some() {
+            eyre::bail!(
+                "email notifications are not supported by this build, enable the smtp feature"
+            );
+        }
+
+        #[cfg(feature = "client")]
+        let webhooks = config
+            .webhooks
+            .iter()
+            .map(|webhook| {
+                let headers = webhook
+                    .headers
+                    .iter()
+                    .map(|(name, value)| {
+                        Ok((
+                            HeaderName::try_from(name.as_str())?,
+                            HeaderValue::try_from(value.as_str())?,
+                        ))
+                    })
+                    .collect::<eyre::Result<HeaderMap>>()?;
+
+                Ok(Webhook {
+                    url: webhook.url.clone(),
+                    headers,
+                })
+            })
+            .collect::<eyre::Result<Vec<_>>>()?;
+
+        #[cfg(feature = "client")]
+        let client = (!webhooks.is_empty() || config.apprise.is_some())
+            .then(|| http_client.build())
+            .transpose()?;
+
+        Ok(Self {
+            new_logins: config.new_logins,
+            #[cfg(feature = "client")]
+            apprise: config
+                .apprise
+                .as_ref()
+                .zip(client.clone())
+                .map(|(apprise, client)| apprise::AppriseNotifier::new(apprise, client))
+                .transpose()?
+                .map(Arc::new),
+            #[cfg(feature = "client")]
+            client: client.filter(|_| !webhooks.is_empty()),
+            #[cfg(feature = "client")]
+            webhooks: Arc::new(webhooks),
+            #[cfg(feature = "smtp")]
+            smtp: config
+                .smtp
+                .as_ref()
+                .map(smtp::SmtpNotifier::new)
+                .transpose()?
+                .map(Arc::new),
+        })
+    }
+
+    /// Notify the events of the bus which deserve it, until it is dropped
+    pub async fn run(self: Arc<Self>, mut receiver: broadcast::Receiver<Arc<Event>>) {
+        while let Some(event) = events::next(&mut receiver).await {
+            if let Some(notification) = Notification::of(&event, self.new_logins) {
+                self.notify(notification);
+            }
+        }
+    }
+
+    /// Send a notification to all channels, without waiting for it to be delivered
+    fn notify(&self, notification: Notification) {
+        debug!(message = %notification.message(), "sending notification");
+
+        #[cfg(feature = "smtp")]
+        if let Some(smtp) = self.smtp.clone() {
+            let notification = notification.clone();
+            tokio::spawn(async move { smtp.send(&notification).await });
+        }
+
+        #[cfg(feature = "client")]
+        if let Some(apprise) = self.apprise.clone() {
+            let notification = notification.clone();
+            tokio::spawn(async move { apprise.send(&notification).await });
+        }
+
+        #[cfg(feature = "client")]
+        if let Some(client) = self.client.clone() {
+            let webhooks = self.webhooks.clone();
+            tokio::spawn(async move {
+                for webhook in webhooks.iter() {
+                    let result = client
+                        .post(webhook.url.clone())
+                        .headers(webhook.headers.clone())
+                        .json(&notification)
+                        .send()
+                        .await
+                        .and_then(|response| response.error_for_status());
+
+                    if let Err(err) = result {
+                        warn!(%err, url = %webhook.url, "could not send notification");
+                    }
+                }
+            });
+        }
+    }
+}
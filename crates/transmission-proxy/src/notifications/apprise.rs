@@ -0,0 +1,81 @@
+use std::collections::BTreeMap;
+
+use color_eyre::eyre;
+use serde_json::json;
+use tracing::warn;
+
+use super::{AppriseConfig, Notification};
+
+/// Sends notifications to an Apprise API server, which forwards them to the services of its
+/// configuration keys
+pub struct AppriseNotifier {
+    client: reqwest::Client,
+    url: url::Url,
+    key: Option<String>,
+    keys: BTreeMap<String, String>,
+}
+
+impl AppriseNotifier {
+    pub fn new(config: &AppriseConfig, client: reqwest::Client) -> eyre::Result<Self> {
+        if let Some(event) = config
+            .keys
+            .keys()
+            .find(|event| !Notification::EVENTS.contains(&event.as_str()))
+        {
+            eyre::bail!(
+                "unknown apprise notification event {event}, expected one of {}",
+                Notification::EVENTS.join(", ")
+            );
+        }
+
+        // Keep the path of the server when joining the notify endpoint
+        let mut url = config.url.clone();
+        if !url.path().ends_with('/') {
+            url.set_path(&format!("{}/", url.path()));
+        }
+
+        Ok(Self {
+            client,
+            url,
+            key: config.key.clone(),
+            keys: config.keys.clone(),
+        })
+    }
+
+    /// Post the notification to the key of its event, if any
+    pub async fn send(&self, notification: &Notification) {
+        let Some(key) = self.keys.get(notification.event()).or(self.key.as_ref()) else {
+            return;
+        };
+
+        let url = match self.url.join(&format!("notify/{key}")) {
+            Ok(url) => url,
+            Err(err) => {
+                warn!(%err, %key, "invalid apprise configuration key");
+                return;
+            }
+        };
+
+        let kind = match notification {
+            Notification::NewLogin { .. } | Notification::TorrentProblems { .. } => "warning",
+            Notification::FailedOver { .. } => "failure",
+            Notification::FailedBack => "success",
+        };
+
+        let result = self
+            .client
+            .post(url)
+            .json(&json!({
+                "title": notification.subject(),
+                "body": notification.message(),
+                "type": kind,
+            }))
+            .send()
+            .await
+            .and_then(|response| response.error_for_status());
+
+        if let Err(err) = result {
+            warn!(%err, %key, "could not send notification to apprise");
+        }
+    }
+}
@@ -0,0 +1,91 @@
+use color_eyre::eyre;
+use lettre::{
+    message::Mailbox,
+    transport::smtp::{authentication::Credentials, AsyncSmtpTransport},
+    AsyncTransport, Message, Tokio1Executor,
+};
+use tracing::warn;
+
+use super::{Notification, SmtpConfig, SmtpTls};
+
+/// Sends notifications by email
+pub struct SmtpNotifier {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+    from: Mailbox,
+    to: Vec<Mailbox>,
+    notify_users: bool,
+}
+
+impl SmtpNotifier {
+    pub fn new(config: &SmtpConfig) -> eyre::Result<Self> {
+        let builder = match config.tls {
+            SmtpTls::Starttls => {
+                AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&config.host)?
+            }
+            SmtpTls::Tls => AsyncSmtpTransport::<Tokio1Executor>::relay(&config.host)?,
+            SmtpTls::None => {
+                warn!(host = %config.host, "notification emails are sent without encryption");
+                AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(&config.host).port(25)
+            }
+        };
+
+        let builder = match config.port {
+            Some(port) => builder.port(port),
+            None => builder,
+        };
+
+        let builder = match (&config.username, &config.password) {
+            (Some(username), Some(password)) => {
+                builder.credentials(Credentials::new(username.clone(), password.clone()))
+            }
+            (None, None) => builder,
+            _ => eyre::bail!("smtp username and password must be set together"),
+        };
+
+        Ok(Self {
+            transport: builder.build(),
+            from: config.from.parse()?,
+            to: config
+                .to
+                .iter()
+                .map(|to| to.parse())
+                .collect::<Result<_, _>>()?,
+            notify_users: config.notify_users,
+        })
+    }
+
+    /// Email the notification to the administrators, and to the user it is about
+    pub async fn send(&self, notification: &Notification) {
+        let user = notification
+            .user_email()
+            .filter(|_| self.notify_users)
+            .and_then(|email| match email.parse::<Mailbox>() {
+                Ok(mailbox) => Some(mailbox),
+                Err(err) => {
+                    warn!(%err, %email, "invalid user email address");
+                    None
+                }
+            });
+
+        // One email each, so recipients don't see each other's addresses
+        for to in self.to.iter().chain(user.as_ref()) {
+            let message = Message::builder()
+                .from(self.from.clone())
+                .to(to.clone())
+                .subject(notification.subject())
+                .body(notification.message());
+
+            let result = match message {
+                Ok(message) => self.transport.send(message).await.map(|_| ()),
+                Err(err) => {
+                    warn!(%err, "could not build notification email");
+                    continue;
+                }
+            };
+
+            if let Err(err) = result {
+                warn!(%err, %to, "could not send notification email");
+            }
+        }
+    }
+}
@@ -0,0 +1,131 @@
+use std::sync::Arc;
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    acl::{Acl, Acls},
+    api_tokens::within,
+    auth::AuthUser,
+    rpc::Torrent,
+};
+
+fn default_prefix() -> String {
+    "owner:".into()
+}
+
+/// Labels recording which user added a torrent
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct OwnerLabels {
+    /// Add an owner label to torrents added through the proxy
+    #[serde(default)]
+    pub enabled: bool,
+
+    /// Prefix of owner labels, followed by the user name
+    #[serde(default = "default_prefix")]
+    pub prefix: String,
+
+    /// Strip the owner labels of other users from torrent-get responses
+    #[serde(default)]
+    pub hide_from_others: bool,
+
+    /// Add an `addedBy` field with the owner name to torrent-get responses which request it
+    #[serde(default)]
+    pub added_by: bool,
+}
+
+impl Default for OwnerLabels {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            prefix: default_prefix(),
+            hide_from_others: false,
+            added_by: false,
+        }
+    }
+}
+
+impl OwnerLabels {
+    /// Owner label for the given user
+    pub fn label_for(&self, user: &AuthUser) -> Option<String> {
+        user.username()
+            .filter(|_| self.enabled)
+            .map(|username| self.prefix.clone() + username)
+    }
+
+    pub fn is_owner_label(&self, label: &str) -> bool {
+        label.starts_with(&self.prefix)
+    }
+
+    /// Name of the owner recorded in a list of labels
+    pub fn owner<'l>(&self, labels: &'l [String]) -> Option<&'l str> {
+        labels
+            .iter()
+            .find_map(|label| label.strip_prefix(&self.prefix))
+    }
+
+    /// Remove owner labels from a list of labels supplied by a client
+    pub fn strip_all(&self, labels: &mut Vec<String>) {
+        labels.retain(|label| !self.is_owner_label(label));
+    }
+
+    /// Remove owner labels which don't belong to the user from a list of labels
+    pub fn strip_foreign(&self, labels: &mut Vec<String>, user: &AuthUser) {
+        let own = self.label_for(user);
+        labels.retain(|label| !self.is_owner_label(label) || Some(label) == own.as_ref());
+    }
+}
+
+/// Attributes torrents to users, by their owner label or else by the ACL whose download dir
+/// holds them
+pub struct Attribution<'c> {
+    acls: &'c Acls,
+    owner_labels: &'c OwnerLabels,
+    rules: Vec<Arc<Acl>>,
+}
+
+impl<'c> Attribution<'c> {
+    /// Attribution through the ACLs of the configuration and the provisioned ones
+    pub fn new(acls: &'c Acls, owner_labels: &'c OwnerLabels, provisioned: Vec<Arc<Acl>>) -> Self {
+        let mut rules = acls.rules().to_vec();
+        rules.extend(provisioned);
+        Self {
+            acls,
+            owner_labels,
+            rules,
+        }
+    }
+
+    /// Name of an ACL owner: the user it designates, or the ACL name if it has several users
+    fn acl_owner(&self, acl: &Acl) -> String {
+        match acl.identities.iter().next() {
+            Some(identity) if acl.identities.len() == 1 => identity.name().to_owned(),
+            _ => self.acls.name_of(acl).into_owned(),
+        }
+    }
+
+    /// Owner of a torrent, if any. The torrent must have its labels and download dir.
+    pub fn owner(&self, torrent: &Torrent) -> Option<String> {
+        if let Some(owner) = torrent
+            .labels
+            .as_deref()
+            .and_then(|labels| self.owner_labels.owner(labels))
+        {
+            return Some(owner.to_owned());
+        }
+
+        // The most specific download dir wins
+        let dir = torrent.download_dir.as_deref()?;
+        self.rules
+            .iter()
+            .filter_map(|acl| {
+                acl.download_dir
+                    .as_deref()
+                    .filter(|parent| within(dir, parent))
+                    .map(|parent| (parent.trim_end_matches('/').len(), acl))
+            })
+            .max_by_key(|(len, _)| *len)
+            .map(|(_, acl)| self.acl_owner(acl))
+    }
+}
@@ -0,0 +1,105 @@
+use std::io::{self, BufRead};
+
+use argon2::{
+    password_hash::{
+        self, rand_core::OsRng, PasswordHash, PasswordHasher, PasswordVerifier, SaltString,
+    },
+    Argon2,
+};
+use color_eyre::eyre;
+use scrypt::Scrypt;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum PasswordError {
+    #[error(transparent)]
+    Bcrypt(#[from] bcrypt::BcryptError),
+    #[error(transparent)]
+    Hash(#[from] password_hash::Error),
+}
+
+/// Algorithm for new password hashes
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Algorithm {
+    #[default]
+    Argon2id,
+    Scrypt,
+    Bcrypt,
+}
+
+/// Check a password against a bcrypt, argon2 or scrypt hash. The algorithm is detected from the
+/// prefix of the hash.
+pub fn verify(password: &[u8], hash: &str) -> Result<bool, PasswordError> {
+    let result = if hash.starts_with("$argon2") {
+        Argon2::default().verify_password(password, &PasswordHash::new(hash)?)
+    } else if hash.starts_with("$scrypt$") {
+        Scrypt.verify_password(password, &PasswordHash::new(hash)?)
+    } else {
+        return Ok(bcrypt::verify(password, hash)?);
+    };
+
+    match result {
+        Ok(()) => Ok(true),
+        Err(password_hash::Error::Password) => Ok(false),
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// Hash a password with the given algorithm and a random salt
+pub fn hash(password: &[u8], algorithm: Algorithm) -> Result<String, PasswordError> {
+    let salt = SaltString::generate(&mut OsRng);
+
+    Ok(match algorithm {
+        Algorithm::Argon2id => Argon2::default()
+            .hash_password(password, &salt)?
+            .to_string(),
+        Algorithm::Scrypt => Scrypt.hash_password(password, &salt)?.to_string(),
+        Algorithm::Bcrypt => bcrypt::hash(password, bcrypt::DEFAULT_COST)?,
+    })
+}
+
+#[derive(Debug, clap::Args)]
+pub struct HashPasswordArgs {
+    /// Hashing algorithm
+    #[clap(long, value_enum, default_value_t)]
+    pub algorithm: Algorithm,
+}
+
+/// Read a password from standard input, and print its hash for the basic auth provider
+pub fn run(args: HashPasswordArgs) -> eyre::Result<()> {
+    let mut password = String::new();
+    io::stdin().lock().read_line(&mut password)?;
+    let password = password.trim_end_matches(['\r', '\n']);
+
+    if password.is_empty() {
+        eyre::bail!("no password on standard input");
+    }
+
+    println!("{}", hash(password.as_bytes(), args.algorithm)?);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hashes_are_verified_by_their_prefix() {
+        for algorithm in [Algorithm::Argon2id, Algorithm::Scrypt] {
+            let hash = hash(b"password", algorithm).unwrap();
+            assert!(verify(b"password", &hash).unwrap(), "{hash}");
+            assert!(!verify(b"wrong", &hash).unwrap(), "{hash}");
+        }
+
+        let hash = bcrypt::hash("password", 4).unwrap();
+        assert!(verify(b"password", &hash).unwrap());
+        assert!(!verify(b"wrong", &hash).unwrap());
+    }
+
+    #[test]
+    fn malformed_hashes_never_match() {
+        for hash in ["$argon2id$garbage", "$scrypt$garbage", "password"] {
+            assert!(!verify(b"password", hash).unwrap_or_default(), "{hash}");
+        }
+    }
+}
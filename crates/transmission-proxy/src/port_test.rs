@@ -0,0 +1,235 @@
+use std::{
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+
+use hyper::{header::CONTENT_TYPE, Body};
+use rand::Rng;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tracing::warn;
+
+use crate::{
+    acl::Acl,
+    rpc::{MethodName, ResponseStatus},
+    state::SharedState,
+};
+
+/// Key of the last port-test result in the shared state
+const STATE_KEY: &str = "port-test";
+
+/// Key of the last session-stats result in the shared state
+const SESSION_STATS_KEY: &str = "session-stats";
+
+/// Fraction of the cache duration by which results may expire early, so the users and replicas
+/// sharing a result don't all refresh it at once
+const JITTER: f64 = 0.2;
+
+#[derive(Debug, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct PortTestConfig {
+    /// Seconds during which port-test results are served from cache
+    #[serde(default)]
+    pub cache: u64,
+
+    /// Only let admins run port tests, other users get the last result
+    #[serde(default)]
+    pub admin_only: bool,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct SessionStatsConfig {
+    /// Seconds during which session-stats results are served from cache
+    #[serde(default)]
+    pub cache: u64,
+}
+
+/// Result of a port test, as stored in the shared state
+#[derive(Debug, Serialize, Deserialize)]
+struct LastResult {
+    /// When the test was run, in seconds since the Unix epoch
+    at: u64,
+    /// Seconds during which the result is fresh, jittered when it was stored
+    #[serde(default)]
+    ttl: Option<u64>,
+    arguments: Value,
+}
+
+/// Last result of a call, as read from the shared state
+#[derive(Debug)]
+pub struct CachedResult {
+    pub age: Duration,
+    pub fresh: bool,
+    pub arguments: Value,
+}
+
+/// Last result of a call, shared between users and replicas
+#[derive(Debug)]
+struct ResultCache {
+    key: &'static str,
+    ttl: Duration,
+    state: Arc<SharedState>,
+}
+
+impl ResultCache {
+    async fn last(&self) -> Option<CachedResult> {
+        let last = match self.state.get(self.key).await {
+            Ok(last) => last?,
+            Err(err) => {
+                warn!(%err, key = self.key, "could not read last result");
+                return None;
+            }
+        };
+
+        let last: LastResult = serde_json::from_str(&last).ok()?;
+        let age = Duration::from_secs(unix_time().saturating_sub(last.at));
+        let ttl = last.ttl.map_or(self.ttl, Duration::from_secs);
+        Some(CachedResult {
+            age,
+            fresh: age < ttl,
+            arguments: last.arguments,
+        })
+    }
+
+    async fn store(&self, arguments: Value) {
+        let jitter = rand::thread_rng().gen_range(0.0..JITTER);
+        let last = LastResult {
+            at: unix_time(),
+            ttl: Some((self.ttl.as_secs_f64() * (1. - jitter)).ceil() as u64),
+            arguments,
+        };
+
+        if let Err(err) = self
+            .state
+            .set(self.key, &serde_json::to_string(&last).unwrap(), None)
+            .await
+        {
+            warn!(%err, key = self.key, "could not store result");
+        }
+    }
+}
+
+/// Last result of a port test, shared between users
+#[derive(Debug)]
+pub struct PortTestCache {
+    results: ResultCache,
+    admin_only: bool,
+}
+
+impl PortTestCache {
+    pub fn new(config: &PortTestConfig, state: Arc<SharedState>) -> Option<Self> {
+        (config.cache > 0 || config.admin_only).then(|| Self {
+            results: ResultCache {
+                key: STATE_KEY,
+                ttl: Duration::from_secs(config.cache),
+                state,
+            },
+            admin_only: config.admin_only,
+        })
+    }
+
+    /// Returns true if the ACL may be served cached results: its method list, if any, includes
+    /// port tests. Reading a result doesn't reach the external service, so dangerous method and
+    /// read-only restrictions don't apply.
+    pub fn may_read(&self, acl: &Acl) -> bool {
+        acl.allowed_methods.is_empty() || acl.allowed_methods.contains(&MethodName::PortTest)
+    }
+
+    /// Returns true if the ACL may ask the upstream to run a port test
+    pub fn may_run(&self, acl: &Acl) -> bool {
+        acl.allows(MethodName::PortTest) && (!self.admin_only || acl.admin)
+    }
+
+    /// Last result, fresh if it may be served to users who can run tests
+    pub async fn last(&self) -> Option<CachedResult> {
+        self.results.last().await
+    }
+
+    pub async fn store(&self, arguments: Value) {
+        self.results.store(arguments).await
+    }
+
+    /// Response serving a cached result, with its age in seconds
+    pub fn response(&self, tag: Option<i32>, cached: CachedResult) -> hyper::Response<Body> {
+        let mut arguments = cached.arguments;
+        if let Value::Object(arguments) = &mut arguments {
+            arguments.insert("cached".into(), true.into());
+            arguments.insert("cache-age".into(), cached.age.as_secs().into());
+        }
+
+        success_response(tag, arguments)
+    }
+}
+
+/// Last result of session-stats, shared between users
+#[derive(Debug)]
+pub struct SessionStatsCache {
+    results: ResultCache,
+}
+
+impl SessionStatsCache {
+    pub fn new(config: &SessionStatsConfig, state: Arc<SharedState>) -> Option<Self> {
+        (config.cache > 0).then(|| Self {
+            results: ResultCache {
+                key: SESSION_STATS_KEY,
+                ttl: Duration::from_secs(config.cache),
+                state,
+            },
+        })
+    }
+
+    /// Arguments of the last result, if it is still fresh
+    pub async fn fresh(&self) -> Option<Value> {
+        self.results
+            .last()
+            .await
+            .filter(|cached| cached.fresh)
+            .map(|cached| cached.arguments)
+    }
+
+    pub async fn store(&self, arguments: Value) {
+        self.results.store(arguments).await
+    }
+
+    /// Response serving a cached result
+    pub fn response(&self, tag: Option<i32>, arguments: Value) -> hyper::Response<Body> {
+        success_response(tag, arguments)
+    }
+}
+
+fn success_response(tag: Option<i32>, arguments: Value) -> hyper::Response<Body> {
+    let mut response = serde_json::json!({
+        "arguments": arguments,
+        "result": "success",
+    });
+    if let Some(tag) = tag {
+        response["tag"] = tag.into();
+    }
+
+    hyper::Response::builder()
+        .header(CONTENT_TYPE, "application/json")
+        .body(Body::from(response.to_string()))
+        .unwrap()
+}
+
+fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |time| time.as_secs())
+}
+
+/// Arguments of a successful response
+pub fn result_arguments(body: &[u8]) -> Option<Value> {
+    #[derive(Deserialize)]
+    struct SuccessResponse {
+        arguments: Value,
+        result: ResponseStatus,
+    }
+
+    serde_json::from_slice::<SuccessResponse>(body)
+        .ok()
+        .filter(|response| response.result.is_success())
+        .map(|response| response.arguments)
+}
@@ -0,0 +1,343 @@
+//! ACLs created for OAuth2 users on their first login
+//!
+//! Provisioned ACLs are rendered from a template and kept in the storage rather than in the
+//! configuration file, so new users can be onboarded without editing it. ACLs of the
+//! configuration take precedence, which lets admins customize the access of a provisioned user
+//! by naming them in an ACL.
+
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Arc, RwLock},
+};
+
+use color_eyre::eyre;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tracing::warn;
+
+use crate::{
+    acl::{Acl, AclIdentity, Acls, TrackerRule},
+    auth::AuthUser,
+    storage::Storage,
+};
+
+/// Storage namespace for provisioned ACLs
+pub const NAMESPACE: &str = "provisioned_acls";
+
+/// How to create the ACL of OAuth2 users which no ACL names
+#[derive(Debug, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct Provisioning {
+    /// Create an ACL on the first login of users which no ACL names
+    #[serde(default)]
+    pub enabled: bool,
+
+    /// OAuth2 providers whose users get an ACL, all of them if empty
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub providers: Vec<String>,
+
+    /// ACL of new users, without identities. `{user}` in its strings is replaced by the part of
+    /// the email before the `@`, `{email}` by the whole email and `{provider}` by the name of
+    /// the provider.
+    #[serde(default)]
+    pub template: Acl,
+}
+
+impl Provisioning {
+    /// Returns true if users of the given provider get an ACL
+    fn applies_to(&self, provider: &str) -> bool {
+        self.enabled && (self.providers.is_empty() || self.providers.iter().any(|p| p == provider))
+    }
+
+    /// Check that the template renders to a valid ACL, and only names known providers
+    pub fn validate(
+        &self,
+        acls: &Acls,
+        sets: &HashMap<String, Vec<Arc<TrackerRule>>>,
+        oauth2_providers: &[&str],
+    ) -> eyre::Result<()> {
+        if !self.enabled {
+            return Ok(());
+        }
+
+        if !self.template.identities.is_empty() {
+            eyre::bail!("the provisioning template can't have identities");
+        }
+
+        for provider in &self.providers {
+            if !oauth2_providers.contains(&provider.as_str()) {
+                eyre::bail!("unknown OAuth2 provider {provider} in provisioning");
+            }
+        }
+
+        let mut acl = self.render("user@example.org", "provider")?;
+        acls.resolve(&mut acl, sets)
+            .map_err(|err| eyre::eyre!("invalid provisioning template: {err}"))
+    }
+
+    /// ACL of the given user, rendered from the template
+    fn render(&self, username: &str, provider: &str) -> eyre::Result<Acl> {
+        let user = path_safe(username.split_once('@').map_or(username, |(user, _)| user));
+        let email = path_safe(username);
+
+        let mut value = serde_json::to_value(&self.template)?;
+        substitute(
+            &mut value,
+            &[
+                ("{user}", &user),
+                ("{email}", &email),
+                ("{provider}", provider),
+            ],
+        );
+
+        let mut acl: Acl = serde_json::from_value(value)?;
+        acl.identities = HashSet::from([AclIdentity::OAuth2 {
+            name: username.to_owned(),
+            oauth2: provider.to_owned(),
+        }]);
+        acl.name
+            .get_or_insert_with(|| format!("provisioned:{provider}:{username}"));
+        Ok(acl)
+    }
+}
+
+/// Replace placeholders in all the strings of a value
+fn substitute(value: &mut Value, replacements: &[(&str, &str)]) {
+    match value {
+        Value::String(string) => {
+            for (placeholder, replacement) in replacements {
+                *string = string.replace(placeholder, replacement);
+            }
+        }
+        Value::Array(values) => values
+            .iter_mut()
+            .for_each(|value| substitute(value, replacements)),
+        Value::Object(values) => values
+            .values_mut()
+            .for_each(|value| substitute(value, replacements)),
+        _ => {}
+    }
+}
+
+/// Make a name from an identity provider safe to use in a path, since emails may contain
+/// slashes or dots which would escape the download dir of the template
+fn path_safe(name: &str) -> String {
+    let name: String = name
+        .chars()
+        .map(|c| {
+            if c.is_alphanumeric() || "@.+-_".contains(c) {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+
+    if name.starts_with('.') {
+        format!("_{name}")
+    } else {
+        name
+    }
+}
+
+fn key(identity: &AclIdentity) -> String {
+    match identity {
+        AclIdentity::OAuth2 { name, oauth2 } => format!("{oauth2}:{name}"),
+        AclIdentity::Basic { name } | AclIdentity::User { name } => name.clone(),
+    }
+}
+
+/// ACLs created by provisioning, by identity
+#[derive(Debug, Default)]
+pub struct ProvisionedAcls {
+    acls: RwLock<HashMap<AclIdentity, Arc<Acl>>>,
+}
+
+impl ProvisionedAcls {
+    /// Load the ACLs provisioned by previous runs, skipping the ones which became invalid
+    pub fn load(
+        acls: &Acls,
+        sets: &HashMap<String, Vec<Arc<TrackerRule>>>,
+        storage: &dyn Storage,
+    ) -> eyre::Result<Self> {
+        let mut provisioned = HashMap::new();
+
+        for (key, value) in storage.list(NAMESPACE)? {
+            let result = serde_json::from_slice::<Acl>(&value)
+                .map_err(eyre::Report::from)
+                .and_then(|mut acl| {
+                    acls.resolve(&mut acl, sets)?;
+                    Ok(acl)
+                });
+
+            match result {
+                Ok(acl) => {
+                    if let Some(identity) = acl.identities.iter().next().cloned() {
+                        provisioned.insert(identity, Arc::new(acl));
+                    }
+                }
+                Err(err) => warn!(%key, %err, "skipping invalid provisioned ACL"),
+            }
+        }
+
+        Ok(Self {
+            acls: RwLock::new(provisioned),
+        })
+    }
+
+    /// Provisioned ACL of the given user, if any
+    pub fn get(&self, user: &AuthUser) -> Option<Arc<Acl>> {
+        let AuthUser::OAuth2 { username, provider } = user.identity() else {
+            return None;
+        };
+
+        self.acls
+            .read()
+            .unwrap()
+            .get(&AclIdentity::OAuth2 {
+                name: username.clone(),
+                oauth2: provider.clone(),
+            })
+            .cloned()
+    }
+
+    /// All provisioned ACLs
+    pub fn list(&self) -> Vec<Arc<Acl>> {
+        let mut acls: Vec<_> = self.acls.read().unwrap().values().cloned().collect();
+        acls.sort_by(|a, b| a.name.cmp(&b.name));
+        acls
+    }
+
+    /// Create the ACL of a user logging in, if no ACL names them and they don't have one yet
+    pub fn provision(
+        &self,
+        acls: &Acls,
+        sets: &HashMap<String, Vec<Arc<TrackerRule>>>,
+        storage: &dyn Storage,
+        user: &AuthUser,
+    ) -> eyre::Result<Option<Arc<Acl>>> {
+        let AuthUser::OAuth2 { username, provider } = user.identity() else {
+            return Ok(None);
+        };
+
+        if !acls.provisioning.applies_to(provider) || acls.names(user) {
+            return Ok(None);
+        }
+
+        let identity = AclIdentity::OAuth2 {
+            name: username.clone(),
+            oauth2: provider.clone(),
+        };
+
+        let mut provisioned = self.acls.write().unwrap();
+        if provisioned.contains_key(&identity) {
+            return Ok(None);
+        }
+
+        let mut acl = acls.provisioning.render(username, provider)?;
+        acls.resolve(&mut acl, sets)?;
+        storage.put_json(NAMESPACE, &key(&identity), &acl)?;
+
+        let acl = Arc::new(acl);
+        provisioned.insert(identity, acl.clone());
+        Ok(Some(acl))
+    }
+
+    /// Remove the provisioned ACL of an identity, returning false if it had none. The user gets
+    /// a new one on their next login if provisioning still applies to them.
+    pub fn remove(&self, storage: &dyn Storage, identity: &AclIdentity) -> eyre::Result<bool> {
+        let mut provisioned = self.acls.write().unwrap();
+        if !provisioned.contains_key(identity) {
+            return Ok(false);
+        }
+
+        storage.delete(NAMESPACE, &key(identity))?;
+        provisioned.remove(identity);
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::storage::MemoryStorage;
+
+    use super::*;
+
+    fn acls() -> Acls {
+        serde_yaml::from_str(
+            r#"
+rules:
+  - identities:
+      - provider: oauth2
+        oauth2: google
+        name: admin@example.org
+    admin: true
+provisioning:
+  enabled: true
+  template:
+    download_dir: /data/{user}
+    quota: 1000
+"#,
+        )
+        .unwrap()
+    }
+
+    fn user(username: &str) -> AuthUser {
+        AuthUser::OAuth2 {
+            username: username.to_owned(),
+            provider: "google".to_owned(),
+        }
+    }
+
+    #[test]
+    fn first_logins_provision_acls() {
+        let acls = acls();
+        let sets = HashMap::new();
+        let storage = MemoryStorage::default();
+        let provisioned = ProvisionedAcls::default();
+
+        let alice = user("alice@example.org");
+        let acl = provisioned
+            .provision(&acls, &sets, &storage, &alice)
+            .unwrap()
+            .unwrap();
+        assert_eq!(acl.download_dir.as_deref(), Some("/data/alice"));
+        assert_eq!(acl.quota, Some(1000));
+        assert_eq!(acls.name_of(&acl), "provisioned:google:alice@example.org");
+
+        // Users get a single ACL, and named users none
+        assert!(provisioned
+            .provision(&acls, &sets, &storage, &alice)
+            .unwrap()
+            .is_none());
+        assert!(provisioned
+            .provision(&acls, &sets, &storage, &user("admin@example.org"))
+            .unwrap()
+            .is_none());
+
+        // Provisioned ACLs are persisted
+        let loaded = ProvisionedAcls::load(&acls, &sets, &storage).unwrap();
+        assert_eq!(
+            loaded.get(&alice).unwrap().download_dir.as_deref(),
+            Some("/data/alice")
+        );
+
+        let identity = acl.identities.iter().next().unwrap();
+        assert!(loaded.remove(&storage, identity).unwrap());
+        assert!(loaded.get(&alice).is_none());
+        assert!(ProvisionedAcls::load(&acls, &sets, &storage)
+            .unwrap()
+            .list()
+            .is_empty());
+    }
+
+    #[test]
+    fn names_are_safe_in_paths() {
+        let acl = acls()
+            .provisioning
+            .render("../../etc@example.org", "google")
+            .unwrap();
+        assert_eq!(acl.download_dir.as_deref(), Some("/data/_.._.._etc"));
+    }
+}
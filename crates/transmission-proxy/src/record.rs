@@ -0,0 +1,100 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, Write},
+    path::Path,
+    sync::Mutex,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use hyper::StatusCode;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tracing::{error, warn};
+
+#[cfg(feature = "client")]
+mod replay;
+#[cfg(feature = "client")]
+pub use replay::{replay, ReplayArgs};
+
+/// Fields removed from recorded request arguments
+const REDACTED_ARGUMENTS: &[&str] = &["cookies"];
+
+/// A recorded RPC request/response pair
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Exchange {
+    /// Unix timestamp of the request
+    pub time: u64,
+    /// User agent of the client that sent the request
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user_agent: Option<String>,
+    /// Request sent by the client, before filtering
+    pub request: Value,
+    /// HTTP status returned to the client
+    pub status: u16,
+    /// Response returned to the client, after filtering
+    #[serde(default)]
+    pub response: Value,
+}
+
+fn decode(bytes: &[u8]) -> Value {
+    if bytes.is_empty() {
+        Value::Null
+    } else {
+        serde_json::from_slice(bytes)
+            .unwrap_or_else(|_| Value::String(String::from_utf8_lossy(bytes).into_owned()))
+    }
+}
+
+fn redact(request: &mut Value) {
+    if let Some(arguments) = request.get_mut("arguments").and_then(Value::as_object_mut) {
+        for field in REDACTED_ARGUMENTS {
+            if let Some(value) = arguments.get_mut(*field) {
+                *value = Value::String("<redacted>".to_owned());
+            }
+        }
+    }
+}
+
+/// Records proxied RPC calls to a file, one JSON object per line
+pub struct Recorder {
+    file: Mutex<File>,
+}
+
+impl Recorder {
+    pub fn open(path: &Path) -> io::Result<Self> {
+        warn!(path = %path.display(), "recording rpc traffic");
+
+        Ok(Self {
+            file: Mutex::new(OpenOptions::new().create(true).append(true).open(path)?),
+        })
+    }
+
+    pub fn record(
+        &self,
+        user_agent: Option<&str>,
+        request: &[u8],
+        status: StatusCode,
+        response: &[u8],
+    ) {
+        let mut request = decode(request);
+        redact(&mut request);
+
+        let exchange = Exchange {
+            time: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or_default(),
+            user_agent: user_agent.map(str::to_owned),
+            request,
+            status: status.as_u16(),
+            response: decode(response),
+        };
+
+        let mut line = serde_json::to_vec(&exchange).expect("failed to serialize exchange");
+        line.push(b'\n');
+
+        if let Err(err) = self.file.lock().unwrap().write_all(&line) {
+            error!(%err, "could not record rpc exchange");
+        }
+    }
+}
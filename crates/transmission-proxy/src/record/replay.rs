@@ -0,0 +1,96 @@
+use std::{
+    fs::File,
+    io::{BufRead, BufReader},
+    path::PathBuf,
+};
+
+use color_eyre::eyre;
+use hyper::StatusCode;
+use tracing::{info, warn};
+
+use crate::rpc::proxy::SESSION_ID_HEADER;
+
+use super::{decode, Exchange};
+
+#[derive(Debug, clap::Args)]
+pub struct ReplayArgs {
+    /// Recording to replay
+    pub file: PathBuf,
+
+    /// RPC endpoint to send the requests to
+    #[clap(long, default_value = "http://localhost:9091/transmission/rpc")]
+    pub target: url::Url,
+
+    /// Username for basic authentication against the target
+    #[clap(long)]
+    pub username: Option<String>,
+
+    /// Password for basic authentication against the target
+    #[clap(long, env = "TRANSMISSION_PROXY_REPLAY_PASSWORD")]
+    pub password: Option<String>,
+}
+
+/// Re-send recorded requests to the target, printing the new responses
+pub async fn replay(args: ReplayArgs) -> eyre::Result<()> {
+    let client = reqwest::Client::new();
+    let mut session_id: Option<String> = None;
+    let mut differences = 0;
+
+    for line in BufReader::new(File::open(&args.file)?).lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let exchange: Exchange = serde_json::from_str(&line)?;
+
+        // Handshakes are replayed as needed
+        if exchange.status == StatusCode::CONFLICT.as_u16() {
+            continue;
+        }
+
+        let res = loop {
+            let mut req = client.post(args.target.clone()).json(&exchange.request);
+            if let Some(username) = &args.username {
+                req = req.basic_auth(username, args.password.as_ref());
+            }
+            if let Some(session_id) = &session_id {
+                req = req.header(SESSION_ID_HEADER, session_id);
+            }
+
+            let res = req.send().await?;
+            if res.status() == StatusCode::CONFLICT {
+                if let Some(new_id) = res.headers().get(SESSION_ID_HEADER) {
+                    let new_id = new_id.to_str()?.to_owned();
+                    if session_id.as_ref() != Some(&new_id) {
+                        session_id = Some(new_id);
+                        continue;
+                    }
+                }
+            }
+
+            break res;
+        };
+
+        let status = res.status().as_u16();
+        let response = decode(&res.bytes().await?);
+
+        if status != exchange.status || response != exchange.response {
+            differences += 1;
+            warn!(request = %exchange.request, "response differs from recording");
+        }
+
+        println!(
+            "{}",
+            serde_json::json!({
+                "request": exchange.request,
+                "recorded": { "status": exchange.status, "response": exchange.response },
+                "replayed": { "status": status, "response": response },
+            })
+        );
+    }
+
+    info!(differences, "replay complete");
+
+    Ok(())
+}
@@ -0,0 +1,311 @@
+//! Per-route request logging, with redaction of the sensitive values of requests and events
+
+use std::{
+    collections::{BTreeMap, VecDeque},
+    sync::Mutex,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use hyper::{HeaderMap, Request};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tracing::info;
+
+use crate::events::Event;
+
+/// Replacement of redacted values
+pub const REDACTED: &str = "[redacted]";
+
+/// Headers which are always redacted, unless verbose
+const SENSITIVE_HEADERS: &[&str] = &[
+    "authorization",
+    "cookie",
+    "proxy-authorization",
+    "set-cookie",
+    "x-transmission-session-id",
+];
+
+/// Query parameters which are always redacted, unless verbose
+const SENSITIVE_QUERY: &[&str] = &["access_token", "code", "state", "token"];
+
+fn default_recent() -> usize {
+    100
+}
+
+/// Redaction of the values which identify users or grant access
+#[derive(Debug, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct Redaction {
+    /// Log all values as-is, including credentials. Only meant for debugging.
+    #[serde(default)]
+    pub verbose: bool,
+
+    /// Headers to redact, in addition to the ones carrying credentials and sessions
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub headers: Vec<String>,
+
+    /// Query parameters to redact, in addition to tokens and OAuth2 codes
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub query: Vec<String>,
+
+    /// Fields of the events to redact in the audit log and the admin event stream, e.g.
+    /// `client_ip`, `user_agent` or `email`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub event_fields: Vec<String>,
+}
+
+impl Redaction {
+    fn redacts(defaults: &[&str], extra: &[String], name: &str) -> bool {
+        defaults
+            .iter()
+            .copied()
+            .chain(extra.iter().map(String::as_str))
+            .any(|redacted| redacted.eq_ignore_ascii_case(name))
+    }
+
+    /// Returns true if the values of the given header are redacted
+    pub fn redacts_header(&self, name: &str) -> bool {
+        !self.verbose && Self::redacts(SENSITIVE_HEADERS, &self.headers, name)
+    }
+
+    /// Headers as logged, multiple values being joined by commas
+    pub fn headers(&self, headers: &HeaderMap) -> BTreeMap<String, String> {
+        let mut logged = BTreeMap::<String, String>::new();
+        for (name, value) in headers {
+            let value = if self.redacts_header(name.as_str()) {
+                REDACTED.into()
+            } else {
+                String::from_utf8_lossy(value.as_bytes()).into_owned()
+            };
+
+            logged
+                .entry(name.as_str().to_owned())
+                .and_modify(|values| {
+                    if values != REDACTED {
+                        values.push_str(", ");
+                        values.push_str(&value);
+                    }
+                })
+                .or_insert(value);
+        }
+
+        logged
+    }
+
+    /// Query string as logged
+    pub fn query(&self, query: &str) -> String {
+        if self.verbose {
+            return query.to_owned();
+        }
+
+        query
+            .split('&')
+            .map(|pair| match pair.split_once('=') {
+                Some((name, _)) if Self::redacts(SENSITIVE_QUERY, &self.query, name) => {
+                    format!("{name}={REDACTED}")
+                }
+                _ => pair.to_owned(),
+            })
+            .collect::<Vec<_>>()
+            .join("&")
+    }
+
+    /// Event as logged and streamed to admins, in JSON
+    pub fn event(&self, event: &Event) -> serde_json::Result<String> {
+        if self.verbose || self.event_fields.is_empty() {
+            return serde_json::to_string(event);
+        }
+
+        Ok(self.event_value(event)?.to_string())
+    }
+
+    /// Event with the sensitive fields redacted, as a JSON value
+    pub fn event_value(&self, event: &Event) -> serde_json::Result<Value> {
+        let mut json = serde_json::to_value(event)?;
+        if self.verbose {
+            return Ok(json);
+        }
+
+        if let Value::Object(fields) = &mut json {
+            for field in &self.event_fields {
+                if let Some(value) = fields.get_mut(field).filter(|value| !value.is_null()) {
+                    *value = REDACTED.into();
+                }
+            }
+        }
+
+        Ok(json)
+    }
+}
+
+/// Logging of the requests to the paths starting with a given prefix
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct RouteLog {
+    /// Path prefix, e.g. `/transmission/rpc`
+    pub prefix: String,
+
+    /// Log the request headers
+    #[serde(default)]
+    pub headers: bool,
+
+    /// Log the query string
+    #[serde(default)]
+    pub query: bool,
+}
+
+/// A logged request
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RequestLogEntry {
+    /// Unix timestamp of the request
+    pub time: u64,
+    /// Identifier of the request, as in the other logs
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user: Option<String>,
+    pub method: String,
+    pub path: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub query: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub headers: Option<BTreeMap<String, String>>,
+    pub status: u16,
+    /// Time taken to respond, in milliseconds
+    pub duration_ms: u64,
+}
+
+/// Requests logged under the `request` target, the recent ones being kept for the admin API
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct RequestLog {
+    /// Routes to log the requests of. The longest matching prefix applies.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub routes: Vec<RouteLog>,
+
+    /// Number of logged requests kept for the admin API
+    #[serde(default = "default_recent")]
+    pub recent: usize,
+
+    /// Redaction of sensitive values, in the request log, the audit log and the admin API
+    #[serde(default)]
+    pub redaction: Redaction,
+
+    #[serde(skip)]
+    entries: Mutex<VecDeque<RequestLogEntry>>,
+}
+
+impl Default for RequestLog {
+    fn default() -> Self {
+        Self {
+            routes: Vec::new(),
+            recent: default_recent(),
+            redaction: Redaction::default(),
+            entries: Mutex::default(),
+        }
+    }
+}
+
+impl RequestLog {
+    /// Logging of the given path, if any
+    pub fn route(&self, path: &str) -> Option<&RouteLog> {
+        self.routes
+            .iter()
+            .filter(|route| path.starts_with(&route.prefix))
+            .max_by_key(|route| route.prefix.len())
+    }
+
+    /// Start the entry of a request to a logged route. The status and duration are filled in
+    /// once the response is known.
+    pub fn entry<B>(&self, route: &RouteLog, req: &Request<B>) -> RequestLogEntry {
+        RequestLogEntry {
+            time: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs(),
+            id: None,
+            user: None,
+            method: req.method().to_string(),
+            path: req.uri().path().to_owned(),
+            query: req
+                .uri()
+                .query()
+                .filter(|_| route.query)
+                .map(|query| self.redaction.query(query)),
+            headers: route.headers.then(|| self.redaction.headers(req.headers())),
+            status: 0,
+            duration_ms: 0,
+        }
+    }
+
+    pub fn record(&self, entry: RequestLogEntry) {
+        info!(
+            target: "request",
+            id = entry.id,
+            user = entry.user.as_deref(),
+            method = %entry.method,
+            path = %entry.path,
+            query = entry.query.as_deref(),
+            headers = ?entry.headers,
+            status = entry.status,
+            duration_ms = entry.duration_ms,
+        );
+
+        if self.recent > 0 {
+            let mut entries = self.entries.lock().unwrap();
+            if entries.len() >= self.recent {
+                entries.pop_front();
+            }
+            entries.push_back(entry);
+        }
+    }
+
+    /// Recently logged requests, oldest first
+    pub fn recent(&self) -> Vec<RequestLogEntry> {
+        self.entries.lock().unwrap().iter().cloned().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use hyper::header::{HeaderValue, AUTHORIZATION, COOKIE, USER_AGENT};
+
+    use super::*;
+
+    #[test]
+    fn sensitive_values_are_redacted() {
+        let redaction = Redaction {
+            headers: vec!["X-Api-Key".into()],
+            ..Default::default()
+        };
+
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            AUTHORIZATION,
+            HeaderValue::from_static("Basic YWxpY2U6cGFzcw=="),
+        );
+        headers.append(COOKIE, HeaderValue::from_static("a=1"));
+        headers.append(COOKIE, HeaderValue::from_static("b=2"));
+        headers.insert("x-api-key", HeaderValue::from_static("secret"));
+        headers.insert(USER_AGENT, HeaderValue::from_static("curl/8.0"));
+
+        let logged = redaction.headers(&headers);
+        assert_eq!(logged["authorization"], REDACTED);
+        assert_eq!(logged["cookie"], REDACTED);
+        assert_eq!(logged["x-api-key"], REDACTED);
+        assert_eq!(logged["user-agent"], "curl/8.0");
+
+        assert_eq!(
+            redaction.query("code=abc&redirect_to=%2Fweb%2F"),
+            "code=[redacted]&redirect_to=%2Fweb%2F"
+        );
+
+        let verbose = Redaction {
+            verbose: true,
+            ..Default::default()
+        };
+        assert_eq!(verbose.headers(&headers)["cookie"], "a=1, b=2");
+        assert_eq!(verbose.query("code=abc"), "code=abc");
+    }
+}
@@ -1,3 +1,7 @@
+pub mod coalesce;
+pub mod compat;
+pub mod filter;
+pub mod mirror;
 pub mod proxy;
 
 pub use transmission_rpc_client::types::*;
@@ -0,0 +1,167 @@
+//! Coalescing of identical torrent-get calls in flight at the same time, so users polling the
+//! same fields share a single upstream call. The shared response is filtered for each of them.
+
+use std::{collections::HashMap, sync::Arc};
+
+use hyper::{body::Bytes, header::HeaderValue, Body, HeaderMap, StatusCode};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tokio::sync::watch;
+
+/// Coalescing settings
+#[derive(Debug, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct CoalesceConfig {
+    /// Share the upstream response of identical torrent-get calls made at the same time. The
+    /// upstream only sees the headers of the first of these calls, e.g. its identity headers.
+    #[serde(default)]
+    pub enabled: bool,
+}
+
+/// Identical calls: same upstream, same credentials, same session id and same call, regardless
+/// of the tag. Calls made with different upstream credentials may see different torrents.
+pub type CallKey = (usize, Option<HeaderValue>, Option<HeaderValue>, String);
+
+/// Upstream response shared by coalesced calls
+#[derive(Debug)]
+pub struct SharedResponse {
+    pub status: StatusCode,
+    pub headers: HeaderMap,
+    pub body: Bytes,
+}
+
+impl SharedResponse {
+    /// Copy of the response, with the body decoded separately
+    pub fn response(&self) -> hyper::Response<Body> {
+        let mut response = hyper::Response::new(Body::empty());
+        *response.status_mut() = self.status;
+        *response.headers_mut() = self.headers.clone();
+        response
+    }
+}
+
+type Slot = watch::Receiver<Option<Arc<SharedResponse>>>;
+
+/// Calls currently waiting for the upstream
+#[derive(Debug, Default)]
+pub struct Coalescer {
+    in_flight: std::sync::Mutex<HashMap<CallKey, Slot>>,
+}
+
+/// Role of a call in its group of identical calls
+pub enum Flight<'a> {
+    /// First call, which queries the upstream and shares the response
+    Leader(Leader<'a>),
+    /// Identical call made while the first one is in flight
+    Follower(Slot),
+}
+
+impl Coalescer {
+    pub fn new(config: &CoalesceConfig) -> Option<Self> {
+        config.enabled.then(Self::default)
+    }
+
+    /// Join the group of calls identical to the given one, leading it if there is none
+    pub fn join(&self, key: CallKey) -> Flight<'_> {
+        let mut in_flight = self.in_flight.lock().unwrap();
+        if let Some(slot) = in_flight.get(&key) {
+            return Flight::Follower(slot.clone());
+        }
+
+        let (sender, slot) = watch::channel(None);
+        in_flight.insert(key.clone(), slot);
+
+        Flight::Leader(Leader {
+            coalescer: self,
+            key,
+            sender: Some(sender),
+        })
+    }
+
+    /// Wait for the response of the leader, None if it failed
+    pub async fn wait(mut slot: Slot) -> Option<Arc<SharedResponse>> {
+        slot.changed().await.ok()?;
+        let response = slot.borrow().clone();
+        response
+    }
+}
+
+/// First of a group of identical calls. Followers make their own call if it is dropped without
+/// sharing a response.
+pub struct Leader<'a> {
+    coalescer: &'a Coalescer,
+    key: CallKey,
+    sender: Option<watch::Sender<Option<Arc<SharedResponse>>>>,
+}
+
+impl Leader<'_> {
+    /// Share the upstream response with the followers
+    pub fn share(mut self, response: SharedResponse) {
+        // Later calls are not served this response
+        self.finish();
+        if let Some(sender) = self.sender.take() {
+            sender.send(Some(Arc::new(response))).ok();
+        }
+    }
+
+    fn finish(&mut self) {
+        if self.sender.is_some() {
+            self.coalescer.in_flight.lock().unwrap().remove(&self.key);
+        }
+    }
+}
+
+impl Drop for Leader<'_> {
+    fn drop(&mut self) {
+        self.finish();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key() -> CallKey {
+        (0, None, None, r#"{"method":"torrent-get"}"#.to_owned())
+    }
+
+    fn response() -> SharedResponse {
+        SharedResponse {
+            status: StatusCode::OK,
+            headers: HeaderMap::new(),
+            body: Bytes::from_static(b"{}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn followers_get_the_response_of_the_leader() {
+        let coalescer = Coalescer::default();
+
+        let Flight::Leader(leader) = coalescer.join(key()) else {
+            panic!("first call should lead");
+        };
+        let Flight::Follower(slot) = coalescer.join(key()) else {
+            panic!("identical call should follow");
+        };
+
+        leader.share(response());
+        assert_eq!(Coalescer::wait(slot).await.unwrap().body, "{}");
+
+        // The group is over
+        assert!(matches!(coalescer.join(key()), Flight::Leader(_)));
+    }
+
+    #[tokio::test]
+    async fn followers_are_released_when_the_leader_fails() {
+        let coalescer = Coalescer::default();
+
+        let leader = coalescer.join(key());
+        let Flight::Follower(slot) = coalescer.join(key()) else {
+            panic!("identical call should follow");
+        };
+
+        drop(leader);
+        assert!(Coalescer::wait(slot).await.is_none());
+        assert!(coalescer.in_flight.lock().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,158 @@
+//! Translation of RPC calls between clients and upstreams of different RPC versions
+//!
+//! Clients written for Transmission 4 (RPC 17) use fields which Transmission 3 daemons don't
+//! know about. When the upstream is older, these fields are synthesized from the fields it
+//! supports.
+
+use std::borrow::Cow;
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use super::{Torrent, TorrentGet, TorrentGetFormat, Tracker};
+
+/// RPC version of Transmission 4.0
+pub const TRANSMISSION_4_RPC_VERSION: i32 = 17;
+
+/// RPC compatibility settings
+#[derive(Debug, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct Compat {
+    /// Translate RPC calls for the detected upstream version
+    #[serde(default)]
+    pub enabled: bool,
+}
+
+/// Fields to synthesize in a torrent-get response
+#[derive(Debug, Default)]
+pub struct Translation {
+    /// Fields added to the request, which the client didn't ask for
+    added_fields: Vec<&'static str>,
+    tracker_list: bool,
+    file_count: bool,
+}
+
+impl Translation {
+    fn require(&mut self, fields: &mut Vec<Cow<'static, str>>, field: &'static str) {
+        if !fields.iter().any(|f| f == field) {
+            fields.push(Cow::Borrowed(field));
+            self.added_fields.push(field);
+        }
+    }
+
+    /// Request the fields needed to synthesize the newer torrent fields
+    pub fn torrent_get(arguments: &mut TorrentGet, rpc_version: i32) -> Self {
+        let mut translation = Self::default();
+
+        // Table responses are not translated
+        if rpc_version >= TRANSMISSION_4_RPC_VERSION || arguments.format == TorrentGetFormat::Table
+        {
+            return translation;
+        }
+
+        let requested = |field: &str| arguments.fields.iter().any(|f| f == field);
+        translation.tracker_list = requested("trackerList");
+        translation.file_count = requested("file-count");
+
+        if translation.tracker_list {
+            translation.require(&mut arguments.fields, "trackers");
+        }
+
+        if translation.file_count {
+            translation.require(&mut arguments.fields, "files");
+        }
+
+        translation
+    }
+
+    /// Add the synthesized fields to the torrents of a torrent-get response
+    pub fn apply(&self, arguments: &mut Value) {
+        if !self.tracker_list && !self.file_count {
+            return;
+        }
+
+        let torrents = match arguments.get_mut("torrents").and_then(Value::as_array_mut) {
+            Some(torrents) => torrents,
+            None => return,
+        };
+
+        for torrent in torrents.iter_mut().filter_map(Value::as_object_mut) {
+            if self.tracker_list {
+                let trackers: Vec<Tracker> = torrent
+                    .get("trackers")
+                    .cloned()
+                    .and_then(|trackers| serde_json::from_value(trackers).ok())
+                    .unwrap_or_default();
+
+                torrent.insert("trackerList".into(), tracker_list(&trackers).into());
+            }
+
+            if self.file_count {
+                let count = torrent
+                    .get("files")
+                    .and_then(Value::as_array)
+                    .map_or(0, Vec::len);
+
+                torrent.insert("file-count".into(), count.into());
+            }
+
+            for field in &self.added_fields {
+                torrent.remove(*field);
+            }
+        }
+    }
+}
+
+/// Format trackers as a tracker list: one announce URL per line, with a blank line between tiers
+pub fn tracker_list(trackers: &[Tracker]) -> String {
+    let mut trackers: Vec<_> = trackers.iter().collect();
+    trackers.sort_by_key(|tracker| tracker.tier);
+
+    let mut list = String::new();
+    let mut tier = None;
+    for tracker in trackers {
+        if tier.is_some() {
+            list.push('\n');
+
+            if tier != Some(tracker.tier) {
+                list.push('\n');
+            }
+        }
+
+        list.push_str(&tracker.announce);
+        tier = Some(tracker.tier);
+    }
+
+    list
+}
+
+/// Trackers to add and tracker ids to remove for a torrent to use the given tracker list
+///
+/// Tiers are not preserved, as older daemons add each tracker to its own tier.
+pub fn tracker_changes(torrent: &Torrent, list: &str) -> (Vec<String>, Vec<i32>) {
+    let trackers = torrent.trackers.as_deref().unwrap_or_default();
+    let announces: Vec<&str> = list
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .collect();
+
+    let add = announces
+        .iter()
+        .filter(|announce| {
+            !trackers
+                .iter()
+                .any(|tracker| tracker.announce == **announce)
+        })
+        .map(|announce| announce.to_string())
+        .collect();
+
+    let remove = trackers
+        .iter()
+        .filter(|tracker| !announces.contains(&tracker.announce.as_str()))
+        .map(|tracker| tracker.id)
+        .collect();
+
+    (add, remove)
+}
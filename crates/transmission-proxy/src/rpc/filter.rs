@@ -0,0 +1,1553 @@
+//! Stages RPC requests and responses go through before being forwarded, assembled for each ACL
+
+use std::{
+    borrow::Cow,
+    path::{Component, Path},
+    sync::Arc,
+};
+
+use axum::async_trait;
+use base64::Engine;
+use hyper::Body;
+use tracing::{debug, error, warn};
+
+use crate::{
+    acl::{Acl, CookieMode, CookiePolicy, TrackerRule, WebseedPolicy},
+    auth::AuthUser,
+    explain,
+    ownership::OwnerLabels,
+    server, torrent_index,
+};
+
+use super::{
+    proxy::{Denial, FilterErrorKind, RpcProxyClient},
+    MethodCall, MethodName, Request, Response, ResponseKind, SessionArguments, Torrent,
+    TorrentAction, TorrentGet, TorrentId, TorrentIds, TorrentRemove, TorrentRenamePath, TorrentSet,
+    TorrentSetLocation, Torrents,
+};
+
+/// Torrent fields which are arrays indexed like the `files` field
+const FILE_INDEXED_FIELDS: &[&str] = &["fileStats", "priorities", "wanted"];
+
+/// What request stages know about the call being filtered
+pub struct RequestContext<'a> {
+    pub client: &'a RpcProxyClient,
+    pub acl: &'a Acl,
+    /// Authorization context of the HTTP request making the call
+    pub caller: &'a server::RequestContext,
+    /// HTTP request of the call, whose headers are reused to look up torrents
+    pub http_request: &'a hyper::Request<Body>,
+}
+
+/// What response stages know about the call being filtered
+pub struct ResponseContext<'a> {
+    pub client: &'a RpcProxyClient,
+    pub acl: &'a Acl,
+    /// Authorization context of the HTTP request making the call
+    pub caller: &'a server::RequestContext,
+}
+
+/// Stage checking or rewriting requests before they are forwarded
+#[async_trait]
+pub trait RequestFilter: std::fmt::Debug + Send + Sync {
+    async fn filter_request(
+        &self,
+        request: &mut Request,
+        ctx: &RequestContext<'_>,
+    ) -> Result<(), FilterErrorKind>;
+}
+
+/// Stage rewriting the upstream responses to filtered requests
+pub trait ResponseFilter: std::fmt::Debug + Send + Sync {
+    fn filter_response(
+        &self,
+        request: &Request,
+        response: &mut Response,
+        ctx: &ResponseContext<'_>,
+    ) -> Result<(), FilterErrorKind>;
+}
+
+/// Stages applying the constraints of an ACL, in order
+#[derive(Debug, Default)]
+pub struct Pipeline {
+    request: Vec<Box<dyn RequestFilter>>,
+    response: Vec<Box<dyn ResponseFilter>>,
+}
+
+impl Pipeline {
+    /// Assemble the stages needed by an ACL
+    pub fn new(acl: &Acl, owner_labels: &OwnerLabels) -> Self {
+        let mut pipeline = Self::default();
+        let scoped = acl.download_dir.is_some();
+
+        pipeline.request.push(Box::new(MethodAcl));
+        if scoped {
+            pipeline.request.push(Box::new(IdScope));
+        }
+        pipeline.request.push(Box::new(PathCheck));
+        if owner_labels.enabled {
+            pipeline.request.push(Box::new(OwnerLabelAssignment {
+                owner_labels: owner_labels.clone(),
+            }));
+        }
+        if *acl.cookie_policy() != CookiePolicy::Mode(CookieMode::Pass) {
+            pipeline.request.push(Box::new(CookieFilter));
+        }
+        if !acl.tracker_rules.is_empty()
+            || acl.limits_trackers()
+            || acl.flatten_tracker_tiers
+            || acl.require_tracker.is_some()
+            || acl.webseed_policy() != WebseedPolicy::Keep
+        {
+            pipeline.request.push(Box::new(TrackerRewrite));
+        }
+        if scoped || owner_labels.added_by {
+            pipeline.request.push(Box::new(FieldSelection {
+                added_by: owner_labels.added_by,
+            }));
+        }
+        if scoped || owner_labels.enabled {
+            pipeline.request.push(Box::new(DuplicateCheck {
+                owner_labels: owner_labels.clone(),
+            }));
+        }
+        if scoped {
+            pipeline.response.push(Box::new(DownloadDirScope));
+        }
+
+        if owner_labels.hide_from_others && !acl.is_nop() {
+            pipeline.response.push(Box::new(OwnerLabelRedaction {
+                owner_labels: owner_labels.clone(),
+            }));
+        }
+        if owner_labels.added_by {
+            pipeline.response.push(Box::new(AddedByField {
+                owner_labels: owner_labels.clone(),
+            }));
+        }
+
+        pipeline
+    }
+
+    pub async fn filter_request(
+        &self,
+        request: &mut Request,
+        ctx: &RequestContext<'_>,
+    ) -> Result<(), FilterErrorKind> {
+        for stage in &self.request {
+            stage.filter_request(request, ctx).await?;
+        }
+
+        Ok(())
+    }
+
+    pub fn filter_response(
+        &self,
+        request: &Request,
+        response: &mut Response,
+        ctx: &ResponseContext<'_>,
+    ) -> Result<(), FilterErrorKind> {
+        for stage in &self.response {
+            stage.filter_response(request, response, ctx)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Trait for requests that hold torrent ids
+trait HasTorrentIds: Send + Sync {
+    /// true if the response torrents should be filtered instead of the request
+    fn filters_on_response(&self) -> bool {
+        false
+    }
+
+    /// Get the torrent ids
+    fn ids(&self) -> &Option<TorrentIds>;
+    /// Get a mutable reference to the torrent ids
+    fn ids_mut(&mut self) -> &mut Option<TorrentIds>;
+}
+
+macro_rules! impl_has_torrent_ids {
+    ($t:ty) => {
+        impl HasTorrentIds for $t {
+            fn ids(&self) -> &Option<TorrentIds> {
+                &self.ids
+            }
+
+            fn ids_mut(&mut self) -> &mut Option<TorrentIds> {
+                &mut self.ids
+            }
+        }
+    };
+
+    ($t:ty, $e:expr) => {
+        impl HasTorrentIds for $t {
+            fn filters_on_response(&self) -> bool {
+                $e
+            }
+
+            fn ids(&self) -> &Option<TorrentIds> {
+                &self.ids
+            }
+
+            fn ids_mut(&mut self) -> &mut Option<TorrentIds> {
+                &mut self.ids
+            }
+        }
+    };
+}
+
+impl_has_torrent_ids!(TorrentAction);
+impl_has_torrent_ids!(TorrentSet);
+impl_has_torrent_ids!(TorrentGet, true);
+impl_has_torrent_ids!(TorrentRemove);
+impl_has_torrent_ids!(TorrentSetLocation);
+impl_has_torrent_ids!(TorrentRenamePath);
+
+fn torrent_ids_mut(call: &mut MethodCall) -> Option<&mut dyn HasTorrentIds> {
+    match call {
+        MethodCall::TorrentStart { arguments } => Some(arguments),
+        MethodCall::TorrentStartNow { arguments } => Some(arguments),
+        MethodCall::TorrentStop { arguments } => Some(arguments),
+        MethodCall::TorrentVerify { arguments } => Some(arguments),
+        MethodCall::TorrentReannounce { arguments } => Some(arguments),
+        MethodCall::TorrentSet { arguments } => Some(arguments),
+        MethodCall::TorrentGet { arguments } => Some(arguments),
+        MethodCall::TorrentRemove { arguments } => Some(arguments),
+        MethodCall::TorrentSetLocation { arguments } => Some(arguments),
+        MethodCall::TorrentRenamePath { arguments } => Some(arguments),
+        _ => None,
+    }
+}
+
+fn torrent_ids(call: &MethodCall) -> Option<&dyn HasTorrentIds> {
+    match call {
+        MethodCall::TorrentStart { arguments } => Some(arguments),
+        MethodCall::TorrentStartNow { arguments } => Some(arguments),
+        MethodCall::TorrentStop { arguments } => Some(arguments),
+        MethodCall::TorrentVerify { arguments } => Some(arguments),
+        MethodCall::TorrentReannounce { arguments } => Some(arguments),
+        MethodCall::TorrentSet { arguments } => Some(arguments),
+        MethodCall::TorrentGet { arguments } => Some(arguments),
+        MethodCall::TorrentRemove { arguments } => Some(arguments),
+        MethodCall::TorrentSetLocation { arguments } => Some(arguments),
+        MethodCall::TorrentRenamePath { arguments } => Some(arguments),
+        _ => None,
+    }
+}
+
+/// Number of torrents targeted by a call, if it names them
+pub(super) fn torrent_count(call: &MethodCall) -> Option<usize> {
+    match torrent_ids(call)?.ids().as_ref()? {
+        TorrentIds::Id(_) => Some(1),
+        TorrentIds::Ids(ids) => Some(ids.len()),
+        TorrentIds::Set(_) => None,
+    }
+}
+
+/// true if a path relative to a torrent download dir stays inside of it
+fn is_contained(path: &str) -> bool {
+    !path.is_empty()
+        && Path::new(path)
+            .components()
+            .all(|component| matches!(component, Component::Normal(_) | Component::CurDir))
+}
+
+/// Check that the ACL allows calling a method
+pub(super) fn check_method(
+    method: MethodName,
+    acl: &Acl,
+    user: &AuthUser,
+) -> Result<(), FilterErrorKind> {
+    let allowed = acl.allows(method);
+    explain::record("method", allowed, || format!("{method:?}"));
+
+    if !allowed {
+        // Guests may be allowed more methods after logging in
+        return Err(if user.is_anonymous() {
+            FilterErrorKind::LoginRequired
+        } else {
+            FilterErrorKind::Forbidden(Some(Denial::Method(method)))
+        });
+    }
+
+    Ok(())
+}
+
+/// Check that a location is within the ACL download dir, if any
+pub(super) fn prefix_ok(location: &str, acl: &Acl) -> bool {
+    if let Some(download_dir) = &acl.download_dir {
+        // Exact match, we can exit already
+        if location == download_dir {
+            return true;
+        }
+
+        // Else, check that it's a prefix match
+        let prefix = if download_dir.ends_with('/') {
+            download_dir.clone()
+        } else {
+            format!("{}/", download_dir)
+        };
+
+        if !location.starts_with(&prefix) {
+            // The download dir field was tampered with
+            return false;
+        }
+    }
+
+    true
+}
+
+/// Check that a location requested by the client is within the ACL download dir
+fn check_location(location: &str, acl: &Acl) -> Result<(), FilterErrorKind> {
+    let ok = prefix_ok(location, acl);
+    explain::record("location", ok, || {
+        format!(
+            "{location} in {}",
+            acl.download_dir.as_deref().unwrap_or("any dir")
+        )
+    });
+
+    if ok {
+        Ok(())
+    } else {
+        Err(FilterErrorKind::Forbidden(Some(Denial::Location(
+            location.to_owned(),
+        ))))
+    }
+}
+
+fn filter_tracker(
+    tracker: &mut Option<String>,
+    tracker_rules: &[Arc<TrackerRule>],
+    user: &AuthUser,
+) {
+    for rule in tracker_rules.iter() {
+        if let Some(announce) = tracker {
+            if !rule.matches(announce.as_str()) {
+                continue;
+            }
+
+            let result = rule.apply(announce.as_str(), user);
+            explain::record("tracker rule", result.is_some(), || {
+                format!("{announce} -> {}", result.as_deref().unwrap_or("removed"))
+            });
+
+            if let Some(result) = result {
+                *tracker = Some(result);
+            } else {
+                // The announce URL was removed
+                *tracker = None;
+            }
+        } else {
+            break;
+        }
+    }
+}
+
+/// Apply tracker rules to a list of announce URLs, dropping the removed ones
+pub(crate) fn filter_tracker_list(
+    tracker_list: &mut Vec<String>,
+    tracker_rules: &[Arc<TrackerRule>],
+    user: &AuthUser,
+) {
+    let mut new_list = Vec::with_capacity(tracker_list.len());
+
+    for item in tracker_list.iter() {
+        let mut result = Some(item.clone());
+
+        filter_tracker(&mut result, tracker_rules, user);
+
+        if let Some(announce) = result {
+            new_list.push(announce);
+        }
+    }
+
+    *tracker_list = new_list;
+}
+
+/// Check that an added torrent has one of the trackers required by the ACL
+fn check_required_tracker<'t>(
+    mut trackers: impl Iterator<Item = &'t String>,
+    acl: &Acl,
+) -> Result<(), FilterErrorKind> {
+    let Some(pattern) = &acl.require_tracker else {
+        return Ok(());
+    };
+
+    let allowed = trackers.any(|announce| pattern.is_match(announce));
+    explain::record("required tracker", allowed, || pattern.to_string());
+
+    if allowed {
+        Ok(())
+    } else {
+        Err(FilterErrorKind::Forbidden(Some(Denial::RequiredTracker)))
+    }
+}
+
+/// Decode the metainfo of an added torrent
+fn decode_metainfo(metainfo: &str) -> Result<crate::torrent::Torrent, FilterErrorKind> {
+    let b64 = &base64::engine::general_purpose::STANDARD;
+    Ok(serde_bencode::de::from_bytes(
+        b64.decode(metainfo)?.as_ref(),
+    )?)
+}
+
+/// Take the torrents out of a torrent-get response, leaving other responses untouched
+fn take_torrents(response: &mut Response) -> Result<Option<Torrents>, FilterErrorKind> {
+    match response.arguments.take() {
+        Some(ResponseKind::Torrents(torrents)) => Ok(Some(torrents)),
+        Some(ResponseKind::Other { extra }) => Ok(Some(serde_json::from_value(extra)?)),
+        other => {
+            response.arguments = other;
+            Ok(None)
+        }
+    }
+}
+
+/// Deny methods the ACL doesn't allow
+#[derive(Debug)]
+pub struct MethodAcl;
+
+#[async_trait]
+impl RequestFilter for MethodAcl {
+    async fn filter_request(
+        &self,
+        request: &mut Request,
+        ctx: &RequestContext<'_>,
+    ) -> Result<(), FilterErrorKind> {
+        check_method((&request.call).into(), ctx.acl, &ctx.caller.user)
+    }
+}
+
+/// Restrict the target torrents to those in the ACL download dir. Torrent-get calls are
+/// filtered on response instead.
+#[derive(Debug)]
+pub struct IdScope;
+
+#[async_trait]
+impl RequestFilter for IdScope {
+    async fn filter_request(
+        &self,
+        request: &mut Request,
+        ctx: &RequestContext<'_>,
+    ) -> Result<(), FilterErrorKind> {
+        let Some(torrent_ids) = torrent_ids_mut(&mut request.call) else {
+            return Ok(());
+        };
+
+        if torrent_ids.filters_on_response() {
+            return Ok(());
+        }
+
+        let input = torrent_ids.ids().clone();
+
+        // Fetch torrent details so we can authorize the targets
+        let torrents = ctx
+            .client
+            .fetch_torrents(
+                input.clone(),
+                vec![Cow::Borrowed("id"), Cow::Borrowed("downloadDir")],
+                ctx.http_request,
+            )
+            .await
+            .map_err(|err| {
+                error!(?err, "failed filtering torrent ids");
+                err
+            })?;
+        ctx.client.index().update(&torrents);
+
+        *torrent_ids.ids_mut() = Some(TorrentIds::Ids(
+            torrents
+                .into_iter()
+                .filter(|torrent| prefix_ok(torrent.download_dir.as_ref().unwrap(), ctx.acl))
+                .map(|torrent| torrent.id.unwrap())
+                .collect(),
+        ));
+
+        debug!(input = ?input, output = ?torrent_ids.ids().as_ref().unwrap(), "filtered torrent ids");
+        explain::record("torrent ids", true, || {
+            format!("{:?} -> {:?}", input, torrent_ids.ids().as_ref().unwrap())
+        });
+
+        Ok(())
+    }
+}
+
+/// Keep new locations, renamed paths and the files of added torrents within the download dir
+#[derive(Debug)]
+pub struct PathCheck;
+
+impl PathCheck {
+    /// Prevent renames from escaping the torrent download dir, or from colliding with other
+    /// torrents in the same directory
+    async fn check_rename(
+        arguments: &TorrentRenamePath,
+        ctx: &RequestContext<'_>,
+    ) -> Result<(), FilterErrorKind> {
+        // The new name must be a single path component
+        let name_ok = !arguments.name.contains('/')
+            && matches!(
+                Path::new(&arguments.name).components().collect::<Vec<_>>()[..],
+                [Component::Normal(_)]
+            );
+
+        if !is_contained(&arguments.path) || !name_ok {
+            explain::record("rename path", false, || {
+                format!(
+                    "{} -> {} escapes the download dir",
+                    arguments.path, arguments.name
+                )
+            });
+            return Err(FilterErrorKind::Forbidden(Some(Denial::RenamePath(
+                arguments.path.clone(),
+            ))));
+        }
+
+        // Only renaming the torrent root can collide with other torrents
+        if arguments.path.trim_end_matches('/').contains('/') {
+            return Ok(());
+        }
+
+        let fields = vec![
+            Cow::Borrowed("id"),
+            Cow::Borrowed("name"),
+            Cow::Borrowed("downloadDir"),
+        ];
+
+        let targets = ctx
+            .client
+            .fetch_torrents(arguments.ids.clone(), fields.clone(), ctx.http_request)
+            .await?;
+        let torrents = ctx
+            .client
+            .fetch_torrents(None, fields, ctx.http_request)
+            .await?;
+
+        let dir_of = |torrent: &Torrent| {
+            torrent
+                .download_dir
+                .as_deref()
+                .map(|dir| dir.trim_end_matches('/').to_owned())
+        };
+
+        for target in &targets {
+            if torrents.iter().any(|torrent| {
+                torrent.id != target.id
+                    && torrent.name == arguments.name
+                    && dir_of(torrent) == dir_of(target)
+            }) {
+                warn!(name = %arguments.name, "rename would collide with another torrent");
+                explain::record("rename path", false, || {
+                    format!("{} collides with another torrent", arguments.name)
+                });
+                return Err(FilterErrorKind::Forbidden(Some(Denial::RenameCollision(
+                    arguments.name.clone(),
+                ))));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl RequestFilter for PathCheck {
+    async fn filter_request(
+        &self,
+        request: &mut Request,
+        ctx: &RequestContext<'_>,
+    ) -> Result<(), FilterErrorKind> {
+        match &request.call {
+            MethodCall::TorrentSet { arguments } => {
+                if let Some(new_location) = &arguments.location {
+                    check_location(new_location, ctx.acl)?;
+                }
+            }
+
+            MethodCall::TorrentSetLocation { arguments } => {
+                check_location(&arguments.location, ctx.acl)?;
+            }
+
+            MethodCall::TorrentRenamePath { arguments } if ctx.acl.download_dir.is_some() => {
+                Self::check_rename(arguments, ctx).await?;
+            }
+
+            MethodCall::TorrentAdd { arguments } => {
+                check_location(&arguments.download_dir, ctx.acl)?;
+
+                // Reject files which would be written outside of the download dir
+                if !arguments.metainfo.is_empty() {
+                    let torrent = decode_metainfo(&arguments.metainfo)?;
+                    let unsafe_path = torrent.info.unsafe_path(ctx.acl.deny_hidden_files);
+                    explain::record("file paths", unsafe_path.is_none(), || {
+                        unsafe_path
+                            .clone()
+                            .unwrap_or_else(|| torrent.info.name.clone())
+                    });
+
+                    if let Some(path) = unsafe_path {
+                        return Err(FilterErrorKind::UnsafePath(path));
+                    }
+                }
+            }
+
+            _ => {}
+        }
+
+        Ok(())
+    }
+}
+
+/// Record the owner of added torrents, and preserve the owner labels of torrents whose labels
+/// are replaced
+#[derive(Debug)]
+pub struct OwnerLabelAssignment {
+    owner_labels: OwnerLabels,
+}
+
+#[async_trait]
+impl RequestFilter for OwnerLabelAssignment {
+    async fn filter_request(
+        &self,
+        request: &mut Request,
+        ctx: &RequestContext<'_>,
+    ) -> Result<(), FilterErrorKind> {
+        match &mut request.call {
+            MethodCall::TorrentAdd { arguments } => {
+                self.owner_labels.strip_all(&mut arguments.labels);
+                arguments
+                    .labels
+                    .extend(self.owner_labels.label_for(&ctx.caller.user));
+            }
+
+            MethodCall::TorrentSet { arguments } if !arguments.labels.is_empty() => {
+                // Clients can't assign ownership
+                self.owner_labels.strip_all(&mut arguments.labels);
+
+                let torrents = ctx
+                    .client
+                    .fetch_torrents(
+                        arguments.ids.clone(),
+                        vec![Cow::Borrowed("id"), Cow::Borrowed("labels")],
+                        ctx.http_request,
+                    )
+                    .await?;
+
+                let mut owner_labels = torrents.into_iter().map(|torrent| {
+                    let mut labels = torrent.labels.unwrap_or_default();
+                    labels.retain(|label| self.owner_labels.is_owner_label(label));
+                    labels
+                });
+
+                if let Some(first) = owner_labels.next() {
+                    if owner_labels.any(|labels| labels != first) {
+                        return Err(FilterErrorKind::Unsupported(
+                            "setting labels on torrents with different owners",
+                        ));
+                    }
+
+                    arguments.labels.extend(first);
+                }
+            }
+
+            _ => {}
+        }
+
+        Ok(())
+    }
+}
+
+/// Strip the cookies of torrent-add calls, unless the ACL allows sending them to the torrent URL
+#[derive(Debug)]
+pub struct CookieFilter;
+
+#[async_trait]
+impl RequestFilter for CookieFilter {
+    async fn filter_request(
+        &self,
+        request: &mut Request,
+        ctx: &RequestContext<'_>,
+    ) -> Result<(), FilterErrorKind> {
+        if let MethodCall::TorrentAdd { arguments } = &mut request.call {
+            if arguments.cookies.is_some()
+                && !ctx
+                    .acl
+                    .cookie_policy()
+                    .allows(arguments.filename.as_deref())
+            {
+                debug!(filename = ?arguments.filename, "stripping torrent-add cookies");
+                explain::record("torrent cookies", true, || {
+                    format!("stripped for {:?}", arguments.filename)
+                });
+                arguments.cookies = None;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Apply the tracker rules, tracker limits and required trackers of the ACL
+#[derive(Debug)]
+pub struct TrackerRewrite;
+
+impl TrackerRewrite {
+    fn filter_torrent_set(
+        arguments: &mut TorrentSet,
+        ctx: &RequestContext<'_>,
+    ) -> Result<(), FilterErrorKind> {
+        let tracker_rules = &ctx.acl.tracker_rules;
+        if tracker_rules.is_empty() {
+            return Ok(());
+        }
+
+        filter_tracker_list(&mut arguments.tracker_add, tracker_rules, &ctx.caller.user);
+
+        if let Some(tracker_list) = &mut arguments.tracker_list {
+            // Keep the blank lines separating tiers
+            *tracker_list = tracker_list
+                .split('\n')
+                .filter_map(|line| {
+                    if line.trim().is_empty() {
+                        return Some(String::new());
+                    }
+
+                    let mut announce = Some(line.to_owned());
+                    filter_tracker(&mut announce, tracker_rules, &ctx.caller.user);
+                    announce
+                })
+                .collect::<Vec<_>>()
+                .join("\n");
+        }
+
+        // TODO: Support trackerReplace
+        if !arguments.tracker_replace.is_empty() {
+            return Err(FilterErrorKind::Unsupported(
+                "trackerReplace in torrent-set",
+            ));
+        }
+
+        Ok(())
+    }
+
+    fn filter_torrent_add(
+        metainfo: &mut String,
+        filename: &mut Option<String>,
+        ctx: &RequestContext<'_>,
+    ) -> Result<(), FilterErrorKind> {
+        let acl = ctx.acl;
+        let tracker_rules = &acl.tracker_rules;
+        let rewrite_trackers =
+            !tracker_rules.is_empty() || acl.limits_trackers() || acl.flatten_tracker_tiers;
+        let webseeds = acl.webseed_policy();
+
+        if !metainfo.is_empty() {
+            let mut torrent = decode_metainfo(metainfo)?;
+
+            if !rewrite_trackers && webseeds == WebseedPolicy::Keep {
+                return check_required_tracker(torrent.trackers(), acl);
+            }
+
+            // Replace announce list
+            for list in &mut torrent.announce_list {
+                for sublist in list.iter_mut() {
+                    filter_tracker_list(sublist, tracker_rules, &ctx.caller.user);
+                }
+            }
+
+            // Replace main announce URL
+            filter_tracker(&mut torrent.announce, tracker_rules, &ctx.caller.user);
+
+            // Rules may leave duplicate trackers and empty tiers
+            torrent.normalize_trackers(acl.flatten_tracker_tiers);
+
+            // Drop the trackers over the limits
+            if acl.limits_trackers() {
+                torrent.limit_trackers(acl.max_trackers, acl.max_tracker_tiers);
+                explain::record("tracker limit", true, || {
+                    format!("{:?}", torrent.announce_list)
+                });
+            }
+
+            match webseeds {
+                WebseedPolicy::Keep => {}
+                WebseedPolicy::Strip => torrent.filter_webseeds(Vec::clear),
+                WebseedPolicy::Rewrite => torrent.filter_webseeds(|urls| {
+                    filter_tracker_list(urls, tracker_rules, &ctx.caller.user);
+                }),
+            }
+
+            check_required_tracker(torrent.trackers(), acl)?;
+
+            // Replace argument
+            let b64 = &base64::engine::general_purpose::STANDARD;
+            *metainfo = b64.encode(serde_bencode::ser::to_bytes(&torrent)?);
+
+            Ok(())
+        } else if let Some(magnet) = filename
+            .as_mut()
+            .filter(|filename| filename.starts_with("magnet:") && tracker_rules.is_empty())
+        {
+            *magnet = crate::torrent::dedup_magnet_trackers(magnet);
+
+            // Every tracker of a magnet link is in its own tier
+            if acl.limits_trackers() {
+                let max = acl
+                    .max_trackers
+                    .unwrap_or(usize::MAX)
+                    .min(acl.max_tracker_tiers.unwrap_or(usize::MAX));
+
+                *magnet = crate::torrent::limit_magnet_trackers(magnet, max);
+                explain::record("tracker limit", true, || magnet.clone());
+            }
+
+            if webseeds == WebseedPolicy::Strip {
+                *magnet = crate::torrent::strip_magnet_webseeds(magnet);
+            }
+
+            check_required_tracker(crate::torrent::magnet_trackers(magnet).iter(), acl)
+        } else if filename
+            .as_ref()
+            .map_or(false, |filename| filename.starts_with("magnet:"))
+        {
+            // TODO: Support tracker rules on magnet links
+            Err(FilterErrorKind::UnfilteredAdd("magnet links"))
+        } else {
+            // Torrent URLs are only filtered once fetched by the proxy
+            Err(FilterErrorKind::UnfilteredAdd("torrent URLs"))
+        }
+    }
+}
+
+#[async_trait]
+impl RequestFilter for TrackerRewrite {
+    async fn filter_request(
+        &self,
+        request: &mut Request,
+        ctx: &RequestContext<'_>,
+    ) -> Result<(), FilterErrorKind> {
+        match &mut request.call {
+            MethodCall::TorrentSet { arguments } => Self::filter_torrent_set(arguments, ctx),
+            MethodCall::TorrentAdd { arguments } => {
+                Self::filter_torrent_add(&mut arguments.metainfo, &mut arguments.filename, ctx)
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Request the torrent fields later stages need to filter torrent-get responses
+#[derive(Debug)]
+pub struct FieldSelection {
+    added_by: bool,
+}
+
+#[async_trait]
+impl RequestFilter for FieldSelection {
+    async fn filter_request(
+        &self,
+        request: &mut Request,
+        ctx: &RequestContext<'_>,
+    ) -> Result<(), FilterErrorKind> {
+        let MethodCall::TorrentGet { arguments } = &mut request.call else {
+            return Ok(());
+        };
+
+        let has = |fields: &[Cow<'static, str>], name: &str| fields.iter().any(|f| f == name);
+
+        // File arrays can only be filtered if we know the file names
+        if ctx.acl.download_dir.is_some()
+            && arguments
+                .fields
+                .iter()
+                .any(|field| FILE_INDEXED_FIELDS.contains(&field.as_ref()))
+            && !has(&arguments.fields, "files")
+        {
+            arguments.fields.push(Cow::Borrowed("files"));
+        }
+
+        // The owner is found in the labels
+        if self.added_by && has(&arguments.fields, "addedBy") && !has(&arguments.fields, "labels") {
+            arguments.fields.push(Cow::Borrowed("labels"));
+        }
+
+        Ok(())
+    }
+}
+
+/// Deny adding a torrent which another user already added, so its details don't leak
+#[derive(Debug)]
+pub struct DuplicateCheck {
+    owner_labels: OwnerLabels,
+}
+
+impl DuplicateCheck {
+    /// Returns true if an existing torrent belongs to the user: it is in their download dir, and
+    /// owned by nobody else
+    fn is_own(
+        &self,
+        download_dir: Option<&str>,
+        owner: Option<&str>,
+        ctx: &RequestContext<'_>,
+    ) -> bool {
+        let in_download_dir = ctx.acl.download_dir.is_none()
+            || download_dir.map_or(false, |download_dir| prefix_ok(download_dir, ctx.acl));
+        let owned_by_other = self.owner_labels.enabled
+            && owner.map_or(false, |owner| Some(owner) != ctx.caller.user.username());
+
+        in_download_dir && !owned_by_other
+    }
+}
+
+#[async_trait]
+impl RequestFilter for DuplicateCheck {
+    async fn filter_request(
+        &self,
+        request: &mut Request,
+        ctx: &RequestContext<'_>,
+    ) -> Result<(), FilterErrorKind> {
+        let MethodCall::TorrentAdd { arguments } = &request.call else {
+            return Ok(());
+        };
+
+        let Some(info_hash) = torrent_index::added_info_hash(arguments)? else {
+            return Ok(());
+        };
+
+        let index = ctx.client.index();
+
+        // Torrents already known to be the user's own need not be looked up
+        if index.get(&info_hash).map_or(false, |entry| {
+            self.is_own(entry.download_dir.as_deref(), entry.owner.as_deref(), ctx)
+        }) {
+            explain::record("duplicate", true, || info_hash);
+            return Ok(());
+        }
+
+        let torrents = ctx
+            .client
+            .fetch_torrents(
+                Some(TorrentIds::Ids(vec![TorrentId::Sha1(info_hash.clone())])),
+                vec![
+                    Cow::Borrowed("id"),
+                    Cow::Borrowed("hashString"),
+                    Cow::Borrowed("downloadDir"),
+                    Cow::Borrowed("labels"),
+                ],
+                ctx.http_request,
+            )
+            .await?;
+        index.update(&torrents);
+
+        // The upstream reports duplicates of the user's own torrents
+        let foreign = !torrents.is_empty()
+            && !torrents.iter().any(|torrent| {
+                let owner = torrent
+                    .labels
+                    .as_deref()
+                    .and_then(|labels| self.owner_labels.owner(labels));
+                self.is_own(torrent.download_dir.as_deref(), owner, ctx)
+            });
+
+        explain::record("duplicate", !foreign, || info_hash);
+
+        if foreign {
+            return Err(FilterErrorKind::Forbidden(Some(Denial::Duplicate)));
+        }
+
+        Ok(())
+    }
+}
+
+/// Name reported for the files of a torrent outside of its download dir
+const HIDDEN_FILE: &str = "[hidden]";
+
+/// Only return the torrents in the ACL download dir, and report it as the session download dir
+#[derive(Debug)]
+pub struct DownloadDirScope;
+
+impl DownloadDirScope {
+    /// Only report the removal of torrents which were in the ACL download dir
+    fn filter_removed(torrents: &mut Torrents, ctx: &ResponseContext<'_>) {
+        let Some(serde_json::Value::Array(removed)) = torrents.extra.get_mut("removed") else {
+            return;
+        };
+
+        let index = ctx.client.index();
+        let as_id = |id: &serde_json::Value| id.as_i64().and_then(|id| i32::try_from(id).ok());
+        index.remove(removed.iter().filter_map(as_id));
+
+        let count = removed.len();
+        removed.retain(|id| {
+            as_id(id)
+                .and_then(|id| index.download_dir(id))
+                .map_or(false, |download_dir| prefix_ok(&download_dir, ctx.acl))
+        });
+
+        explain::record("removed", true, || {
+            format!("{} of {count} removed torrents", removed.len())
+        });
+    }
+
+    /// Mask file entries which resolve outside of the torrent download dir. They keep their
+    /// position, as clients address files by their index in torrent-set calls.
+    fn filter_files(torrent: &mut Torrent) {
+        let Some(files) = torrent.files.as_mut() else {
+            return;
+        };
+
+        let hidden: Vec<_> = files.iter().map(|file| !is_contained(&file.name)).collect();
+        if !hidden.contains(&true) {
+            return;
+        }
+
+        warn!(torrent = %torrent.name, "hiding files outside of the download dir");
+
+        let is_hidden = |index: usize| hidden.get(index).copied().unwrap_or(false);
+        for (index, file) in files.iter_mut().enumerate() {
+            if is_hidden(index) {
+                file.name = HIDDEN_FILE.to_owned();
+                file.length = 0;
+                file.bytes_completed = 0;
+            }
+        }
+
+        if let Some(file_stats) = torrent.file_stats.as_mut() {
+            for (index, stats) in file_stats.iter_mut().enumerate() {
+                if is_hidden(index) {
+                    stats.bytes_completed = 0;
+                }
+            }
+        }
+    }
+}
+
+impl ResponseFilter for DownloadDirScope {
+    fn filter_response(
+        &self,
+        request: &Request,
+        response: &mut Response,
+        ctx: &ResponseContext<'_>,
+    ) -> Result<(), FilterErrorKind> {
+        let Some(download_dir) = &ctx.acl.download_dir else {
+            return Ok(());
+        };
+
+        match &request.call {
+            MethodCall::TorrentGet { .. } => {
+                let Some(mut torrents) = take_torrents(response)? else {
+                    return Ok(());
+                };
+
+                ctx.client.index().update(&torrents.torrents);
+                Self::filter_removed(&mut torrents, ctx);
+
+                let count = torrents.torrents.len();
+                torrents.torrents.retain(|torrent| {
+                    if let Some(torrent_download_dir) = torrent.download_dir.as_ref() {
+                        // Strip trailing /
+                        let torrent_download_dir = torrent_download_dir
+                            .strip_suffix('/')
+                            .unwrap_or(torrent_download_dir);
+
+                        torrent_download_dir.starts_with(download_dir)
+                    } else {
+                        error!(
+                            ?torrent,
+                            "torrent {} has empty download dir, this is unexpected", torrent.name
+                        );
+
+                        false
+                    }
+                });
+
+                explain::record("response", true, || {
+                    format!(
+                        "{} of {count} torrents in {download_dir}",
+                        torrents.torrents.len()
+                    )
+                });
+
+                for torrent in &mut torrents.torrents {
+                    Self::filter_files(torrent);
+                }
+
+                response.arguments = Some(ResponseKind::Torrents(torrents));
+            }
+
+            MethodCall::SessionGet { .. } => {
+                let Some(ResponseKind::Other { extra }) = response.arguments.take() else {
+                    return Ok(());
+                };
+
+                let mut session: SessionArguments = serde_json::from_value(extra)?;
+                session.download_dir = download_dir.to_owned();
+                response.arguments = Some(ResponseKind::Session(session));
+            }
+
+            _ => {}
+        }
+
+        Ok(())
+    }
+}
+
+/// Hide the owners of other users' torrents
+#[derive(Debug)]
+pub struct OwnerLabelRedaction {
+    owner_labels: OwnerLabels,
+}
+
+impl ResponseFilter for OwnerLabelRedaction {
+    fn filter_response(
+        &self,
+        request: &Request,
+        response: &mut Response,
+        ctx: &ResponseContext<'_>,
+    ) -> Result<(), FilterErrorKind> {
+        if !matches!(request.call, MethodCall::TorrentGet { .. }) {
+            return Ok(());
+        }
+
+        let Some(mut torrents) = take_torrents(response)? else {
+            return Ok(());
+        };
+
+        for torrent in &mut torrents.torrents {
+            if let Some(labels) = torrent.labels.as_mut() {
+                self.owner_labels.strip_foreign(labels, &ctx.caller.user);
+            }
+        }
+
+        response.arguments = Some(ResponseKind::Torrents(torrents));
+        Ok(())
+    }
+}
+
+/// Add the name of the owner to torrents, from the owner labels visible to the user
+#[derive(Debug)]
+pub struct AddedByField {
+    owner_labels: OwnerLabels,
+}
+
+impl ResponseFilter for AddedByField {
+    fn filter_response(
+        &self,
+        request: &Request,
+        response: &mut Response,
+        _ctx: &ResponseContext<'_>,
+    ) -> Result<(), FilterErrorKind> {
+        let requested = match &request.call {
+            MethodCall::TorrentGet { arguments } => {
+                arguments.fields.iter().any(|field| field == "addedBy")
+            }
+            _ => false,
+        };
+
+        if !requested {
+            return Ok(());
+        }
+
+        let Some(mut torrents) = take_torrents(response)? else {
+            return Ok(());
+        };
+
+        for torrent in &mut torrents.torrents {
+            torrent.added_by = torrent
+                .labels
+                .as_deref()
+                .and_then(|labels| self.owner_labels.owner(labels))
+                .map(str::to_owned);
+        }
+
+        response.arguments = Some(ResponseKind::Torrents(torrents));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+    use serde_json::json;
+
+    use crate::{config::Config, rpc::RawResponse, state::SharedState, Args};
+
+    use super::*;
+
+    /// Client and first ACL of a configuration
+    fn setup(acl: &str) -> (RpcProxyClient, Arc<Acl>) {
+        let mut config: Config = serde_yaml::from_str(&format!(
+            r#"
+acl:
+  rules:
+    - {acl}
+owner_labels:
+  enabled: true
+  hide_from_others: true
+  added_by: true
+"#
+        ))
+        .unwrap();
+        config
+            .acl
+            .resolve_tracker_rules(&config.tracker_rule_sets)
+            .unwrap();
+
+        let args = Args::parse_from(["transmission-proxy"]);
+        let state = Arc::new(SharedState::new(None, None).unwrap());
+        let client = RpcProxyClient::new(&args, &config, state, Default::default()).unwrap();
+        (client, config.acl.rules()[0].clone())
+    }
+
+    fn alice() -> AuthUser {
+        AuthUser::Basic {
+            username: "alice".into(),
+            password: None,
+        }
+    }
+
+    fn caller(user: AuthUser) -> server::RequestContext {
+        server::RequestContext {
+            id: 0,
+            user,
+            acl: None,
+            acl_name: None,
+            client_ip: None,
+        }
+    }
+
+    fn request(call: serde_json::Value) -> Request {
+        serde_json::from_value(call).unwrap()
+    }
+
+    async fn run(
+        stage: &dyn RequestFilter,
+        acl: &str,
+        user: &AuthUser,
+        call: serde_json::Value,
+    ) -> Result<Request, FilterErrorKind> {
+        let (client, acl) = setup(acl);
+        let http_request = hyper::Request::new(Body::empty());
+        let caller = caller(user.clone());
+        let ctx = RequestContext {
+            client: &client,
+            acl: &acl,
+            caller: &caller,
+            http_request: &http_request,
+        };
+
+        let mut request = request(call);
+        stage.filter_request(&mut request, &ctx).await?;
+        Ok(request)
+    }
+
+    fn respond(
+        stage: &dyn ResponseFilter,
+        acl: &str,
+        call: serde_json::Value,
+        arguments: serde_json::Value,
+    ) -> serde_json::Value {
+        let (client, acl) = setup(acl);
+        let caller = caller(alice());
+        let ctx = ResponseContext {
+            client: &client,
+            acl: &acl,
+            caller: &caller,
+        };
+
+        let raw: RawResponse =
+            serde_json::from_value(json!({ "result": "success", "arguments": arguments })).unwrap();
+        let mut response = Response {
+            tag: None,
+            arguments: raw.arguments.map(|extra| ResponseKind::Other { extra }),
+            result: raw.result,
+        };
+
+        stage
+            .filter_response(&request(call), &mut response, &ctx)
+            .unwrap();
+        serde_json::to_value(response.arguments).unwrap()
+    }
+
+    #[test]
+    fn pipeline_skips_unneeded_stages() {
+        let (_, nop) = setup("{ name: admins }");
+        let pipeline = Pipeline::new(&nop, &OwnerLabels::default());
+        assert_eq!(pipeline.request.len(), 2);
+        assert!(pipeline.response.is_empty());
+
+        let (_, scoped) = setup("{ download_dir: /data, max_trackers: 1 }");
+        let pipeline = Pipeline::new(&scoped, &OwnerLabels::default());
+        assert_eq!(pipeline.request.len(), 7);
+        assert_eq!(pipeline.response.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn method_acl_denies_methods() {
+        let call = json!({ "method": "torrent-start", "arguments": { "ids": [1] } });
+
+        let result = run(&MethodAcl, "{ read_only: true }", &alice(), call.clone()).await;
+        assert!(matches!(
+            result,
+            Err(FilterErrorKind::Forbidden(Some(Denial::Method(
+                MethodName::TorrentStart
+            ))))
+        ));
+
+        let result = run(
+            &MethodAcl,
+            "{ read_only: true }",
+            &AuthUser::Anonymous,
+            call,
+        )
+        .await;
+        assert!(matches!(result, Err(FilterErrorKind::LoginRequired)));
+
+        let call = json!({ "method": "torrent-get", "arguments": { "fields": ["id"] } });
+        assert!(run(&MethodAcl, "{ read_only: true }", &alice(), call)
+            .await
+            .is_ok());
+    }
+
+    #[tokio::test]
+    async fn path_check_denies_outside_locations() {
+        let acl = "{ download_dir: /data }";
+        let call = |location: &str| {
+            json!({
+                "method": "torrent-set-location",
+                "arguments": { "ids": [1], "location": location },
+            })
+        };
+
+        let result = run(&PathCheck, acl, &alice(), call("/database")).await;
+        assert!(matches!(
+            result,
+            Err(FilterErrorKind::Forbidden(Some(Denial::Location(_))))
+        ));
+
+        assert!(run(&PathCheck, acl, &alice(), call("/data/movies"))
+            .await
+            .is_ok());
+    }
+
+    #[tokio::test]
+    async fn path_check_denies_escaping_renames() {
+        let call = json!({
+            "method": "torrent-rename-path",
+            "arguments": { "ids": [1], "path": "torrent/file", "name": "../file" },
+        });
+
+        let result = run(&PathCheck, "{ download_dir: /data }", &alice(), call).await;
+        assert!(matches!(
+            result,
+            Err(FilterErrorKind::Forbidden(Some(Denial::RenamePath(_))))
+        ));
+    }
+
+    #[tokio::test]
+    async fn owner_label_assignment_labels_added_torrents() {
+        let call = json!({
+            "method": "torrent-add",
+            "arguments": {
+                "download-dir": "/data",
+                "filename": "magnet:?xt=urn:btih:aa",
+                "metainfo": "",
+                "paused": false,
+                "labels": ["owner:bob", "tv"],
+            },
+        });
+
+        let stage = OwnerLabelAssignment {
+            owner_labels: serde_yaml::from_str("enabled: true").unwrap(),
+        };
+        let request = run(&stage, "{ name: users }", &alice(), call)
+            .await
+            .unwrap();
+        let MethodCall::TorrentAdd { arguments } = request.call else {
+            panic!("unexpected call {:?}", request.call);
+        };
+        assert_eq!(arguments.labels, ["tv", "owner:alice"]);
+    }
+
+    #[tokio::test]
+    async fn tracker_rewrite_applies_rules() {
+        let acl = r#"{ tracker_rules: [{ from: "^http://", to: "https://" }] }"#;
+        let call = json!({
+            "method": "torrent-set",
+            "arguments": {
+                "ids": [1],
+                "trackerAdd": ["http://tracker/announce"],
+                "trackerList": "http://a/announce\n\nhttp://b/announce",
+            },
+        });
+
+        let request = run(&TrackerRewrite, acl, &alice(), call).await.unwrap();
+        let MethodCall::TorrentSet { arguments } = request.call else {
+            panic!("unexpected call {:?}", request.call);
+        };
+        assert_eq!(arguments.tracker_add, ["https://tracker/announce"]);
+        assert_eq!(
+            arguments.tracker_list.as_deref(),
+            Some("https://a/announce\n\nhttps://b/announce")
+        );
+
+        let call = json!({
+            "method": "torrent-set",
+            "arguments": { "ids": [1], "trackerReplace": ["http://c/announce"] },
+        });
+        let result = run(&TrackerRewrite, acl, &alice(), call).await;
+        assert!(matches!(result, Err(FilterErrorKind::Unsupported(_))));
+
+        for (filename, kind) in [
+            ("http://example.org/a.torrent", "torrent URLs"),
+            ("magnet:?xt=urn:btih:0000", "magnet links"),
+        ] {
+            let call = json!({
+                "method": "torrent-add",
+                "arguments": { "filename": filename, "download-dir": "/data" },
+            });
+            let result = run(&TrackerRewrite, acl, &alice(), call).await;
+            assert!(matches!(result, Err(FilterErrorKind::UnfilteredAdd(k)) if k == kind));
+        }
+    }
+
+    #[tokio::test]
+    async fn cookie_filter_applies_policy() {
+        let add = |filename: &str| {
+            json!({
+                "method": "torrent-add",
+                "arguments": {
+                    "download-dir": "/data",
+                    "filename": filename,
+                    "cookies": "uid=1; pass=secret",
+                },
+            })
+        };
+        let cookies = |request: Request| match request.call {
+            MethodCall::TorrentAdd { arguments } => arguments.cookies,
+            call => panic!("unexpected call {call:?}"),
+        };
+
+        // Restricted ACLs strip cookies by default
+        let acl = "{ download_dir: /data }";
+        let request = run(&CookieFilter, acl, &alice(), add("https://a.example/t"))
+            .await
+            .unwrap();
+        assert_eq!(cookies(request), None);
+
+        let acl = "{ download_dir: /data, torrent_cookies: [example.org] }";
+        for (url, kept) in [
+            ("https://example.org/t", true),
+            ("https://tracker.EXAMPLE.org/t", true),
+            ("https://badexample.org/t", false),
+            ("https://example.org.evil/t", false),
+        ] {
+            let request = run(&CookieFilter, acl, &alice(), add(url)).await.unwrap();
+            assert_eq!(cookies(request).is_some(), kept, "{url}");
+        }
+
+        let acl = "{ download_dir: /data, torrent_cookies: pass }";
+        let request = run(&CookieFilter, acl, &alice(), add("https://a.example/t"))
+            .await
+            .unwrap();
+        assert_eq!(cookies(request).as_deref(), Some("uid=1; pass=secret"));
+    }
+
+    #[tokio::test]
+    async fn field_selection_requests_needed_fields() {
+        let call = json!({
+            "method": "torrent-get",
+            "arguments": { "fields": ["id", "wanted", "addedBy"] },
+        });
+
+        let stage = FieldSelection { added_by: true };
+        let request = run(&stage, "{ download_dir: /data }", &alice(), call)
+            .await
+            .unwrap();
+        let MethodCall::TorrentGet { arguments } = request.call else {
+            panic!("unexpected call {:?}", request.call);
+        };
+        assert_eq!(
+            arguments.fields,
+            ["id", "wanted", "addedBy", "files", "labels"]
+        );
+    }
+
+    #[test]
+    fn download_dir_scope_hides_other_torrents() {
+        let acl = "{ download_dir: /data }";
+        let call = json!({
+            "method": "torrent-get",
+            "arguments": { "fields": ["id", "downloadDir", "files", "wanted"] },
+        });
+
+        let arguments = respond(
+            &DownloadDirScope,
+            acl,
+            call,
+            json!({
+                "torrents": [
+                    {
+                        "id": 1,
+                        "downloadDir": "/data/",
+                        "files": [
+                            { "name": "a", "length": 1, "bytesCompleted": 0 },
+                            { "name": "../b", "length": 1, "bytesCompleted": 0 },
+                        ],
+                        "wanted": [true, false],
+                    },
+                    { "id": 2, "downloadDir": "/other" },
+                ],
+            }),
+        );
+
+        let torrents = arguments["torrents"].as_array().unwrap();
+        assert_eq!(torrents.len(), 1);
+        assert_eq!(torrents[0]["id"], 1);
+        assert_eq!(
+            torrents[0]["files"],
+            json!([
+                { "name": "a", "length": 1, "bytesCompleted": 0 },
+                { "name": "[hidden]", "length": 0, "bytesCompleted": 0 },
+            ])
+        );
+        assert_eq!(torrents[0]["wanted"], json!([true, false]));
+    }
+
+    #[test]
+    fn owner_stages_redact_and_report_owners() {
+        let owner_labels: OwnerLabels = serde_yaml::from_str("enabled: true").unwrap();
+        let call = json!({
+            "method": "torrent-get",
+            "arguments": { "fields": ["id", "labels", "addedBy"] },
+        });
+        let torrents = json!({
+            "torrents": [
+                { "id": 1, "labels": ["owner:alice", "tv"] },
+                { "id": 2, "labels": ["owner:bob"] },
+            ],
+        });
+
+        let redaction = OwnerLabelRedaction {
+            owner_labels: owner_labels.clone(),
+        };
+        let arguments = respond(
+            &redaction,
+            "{ name: users }",
+            call.clone(),
+            torrents.clone(),
+        );
+        assert_eq!(
+            arguments["torrents"][0]["labels"],
+            json!(["owner:alice", "tv"])
+        );
+        assert_eq!(arguments["torrents"][1]["labels"], json!([]));
+
+        let added_by = AddedByField { owner_labels };
+        let arguments = respond(&added_by, "{ name: users }", call, torrents);
+        assert_eq!(arguments["torrents"][0]["addedBy"], "alice");
+        assert_eq!(arguments["torrents"][1]["addedBy"], "bob");
+    }
+}
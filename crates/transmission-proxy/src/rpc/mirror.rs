@@ -0,0 +1,190 @@
+//! Mirroring of read-only RPC calls to a second upstream, to compare its responses before a
+//! migration
+
+use color_eyre::eyre;
+use hyper::{client::HttpConnector, header::HeaderValue, Body, Client, Uri};
+use rand::Rng;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio::sync::Mutex;
+use tracing::{debug, warn};
+
+use super::{proxy::SESSION_ID_HEADER, MethodName, Request, Response};
+
+/// Maximum number of differences logged for a single response
+const MAX_DIFFERENCES: usize = 10;
+
+fn default_percent() -> f64 {
+    100.
+}
+
+/// Mirroring settings
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct MirrorConfig {
+    /// Upstream daemon receiving the mirrored calls. Mirroring is disabled if unset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub upstream: Option<String>,
+
+    /// Percentage of the read-only calls to mirror
+    #[serde(default = "default_percent")]
+    pub percent: f64,
+}
+
+impl Default for MirrorConfig {
+    fn default() -> Self {
+        Self {
+            upstream: None,
+            percent: default_percent(),
+        }
+    }
+}
+
+pub struct Mirror {
+    upstream: Uri,
+    percent: f64,
+    client: Client<HttpConnector, Body>,
+    session_id: Mutex<Option<HeaderValue>>,
+}
+
+impl Mirror {
+    pub fn new(config: &MirrorConfig) -> eyre::Result<Option<Self>> {
+        let Some(upstream) = &config.upstream else {
+            return Ok(None);
+        };
+
+        if !(0. ..=100.).contains(&config.percent) {
+            eyre::bail!("mirror percent must be between 0 and 100");
+        }
+
+        Ok(Some(Self {
+            upstream: upstream.parse()?,
+            percent: config.percent,
+            client: Client::new(),
+            session_id: Default::default(),
+        }))
+    }
+
+    /// Upstream receiving the mirrored calls
+    pub fn upstream(&self) -> &Uri {
+        &self.upstream
+    }
+
+    /// Returns the method and body to mirror, if this call was sampled
+    pub fn sample(&self, request: Option<&Request>, body: &[u8]) -> Option<(MethodName, Vec<u8>)> {
+        if !rand::thread_rng().gen_bool(self.percent / 100.) {
+            return None;
+        }
+
+        let parsed;
+        let request = match request {
+            Some(request) => request,
+            None => {
+                parsed = serde_json::from_slice::<Request>(body).ok()?;
+                &parsed
+            }
+        };
+
+        let method = (&request.call).into();
+        crate::acl::is_read_only(method).then(|| (method, serde_json::to_vec(request).unwrap()))
+    }
+
+    async fn call(&self, uri: &Uri, body: Vec<u8>) -> eyre::Result<Response> {
+        // Retry once if the session id changed
+        for _ in 0..2 {
+            let mut req = hyper::Request::post(uri.clone())
+                .body(Body::from(body.clone()))
+                .unwrap();
+
+            if let Some(session_id) = self.session_id.lock().await.clone() {
+                req.headers_mut().insert(SESSION_ID_HEADER, session_id);
+            }
+
+            let mut res = self.client.request(req).await?;
+
+            if res.status() == 409 {
+                *self.session_id.lock().await = res.headers().get(SESSION_ID_HEADER).cloned();
+                continue;
+            }
+
+            return Ok(serde_json::from_slice(
+                hyper::body::to_bytes(res.body_mut()).await?.as_ref(),
+            )?);
+        }
+
+        eyre::bail!("could not get a session id")
+    }
+
+    /// Send a call to the mirror and log how its response differs from the primary response
+    pub async fn compare(&self, uri: Uri, method: MethodName, body: Vec<u8>, primary: Vec<u8>) {
+        let primary = match serde_json::from_slice::<Response>(&primary) {
+            Ok(response) => response,
+            Err(err) => {
+                debug!(%err, ?method, "could not decode primary response, not comparing");
+                return;
+            }
+        };
+
+        let mirrored = match self.call(&uri, body).await {
+            Ok(response) => response,
+            Err(err) => {
+                warn!(%err, ?method, "mirrored call failed");
+                return;
+            }
+        };
+
+        let mut differences = Vec::new();
+        diff(
+            "",
+            &serde_json::to_value(primary).unwrap_or_default(),
+            &serde_json::to_value(mirrored).unwrap_or_default(),
+            &mut differences,
+        );
+
+        if differences.is_empty() {
+            debug!(?method, "mirrored response matches");
+        } else {
+            warn!(?method, ?differences, "mirrored response differs");
+        }
+    }
+}
+
+/// Collect the paths at which two values differ
+fn diff(path: &str, primary: &Value, mirrored: &Value, differences: &mut Vec<String>) {
+    if differences.len() >= MAX_DIFFERENCES || primary == mirrored {
+        return;
+    }
+
+    match (primary, mirrored) {
+        (Value::Object(primary), Value::Object(mirrored)) => {
+            for (key, value) in primary {
+                let path = format!("{path}.{key}");
+                match mirrored.get(key) {
+                    Some(other) => diff(&path, value, other, differences),
+                    None => differences.push(format!("{path}: missing from mirror")),
+                }
+            }
+
+            for key in mirrored.keys().filter(|key| !primary.contains_key(*key)) {
+                differences.push(format!("{path}.{key}: only in mirror"));
+            }
+        }
+
+        (Value::Array(primary), Value::Array(mirrored)) if primary.len() == mirrored.len() => {
+            for (index, (value, other)) in primary.iter().zip(mirrored).enumerate() {
+                diff(&format!("{path}[{index}]"), value, other, differences);
+            }
+        }
+
+        (Value::Array(primary), Value::Array(mirrored)) => differences.push(format!(
+            "{path}: {} items != {} items",
+            primary.len(),
+            mirrored.len()
+        )),
+
+        _ => differences.push(format!("{path}: {primary} != {mirrored}")),
+    }
+
+    differences.truncate(MAX_DIFFERENCES);
+}
@@ -1,114 +1,129 @@
-use std::borrow::Cow;
+use std::{
+    borrow::Cow,
+    sync::{
+        atomic::{AtomicI32, AtomicUsize, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
 
 use axum::extract::OriginalUri;
 
-use base64::Engine;
+use color_eyre::eyre;
 use hyper::{
+    body::Bytes,
     client::HttpConnector,
-    header::{ACCEPT_ENCODING, CONTENT_LENGTH, HOST},
-    Body, Client, Uri,
+    header::{
+        HeaderMap, HeaderValue, ACCEPT_ENCODING, AUTHORIZATION, CONTENT_ENCODING, CONTENT_LENGTH,
+        CONTENT_LOCATION, ETAG, HOST, IF_NONE_MATCH, LOCATION, USER_AGENT, WWW_AUTHENTICATE,
+    },
+    Body, Client, StatusCode, Uri,
 };
+use serde::{de::DeserializeOwned, Deserialize};
+use sha1::{Digest, Sha1};
 use thiserror::Error;
+use tokio::sync::Mutex;
 use tracing::{debug, error, warn};
 
+#[cfg(feature = "chaos")]
+use crate::chaos::Chaos;
 use crate::{
-    acl::{Acl, TrackerRule},
+    acl::Acl,
+    auth::AuthUser,
+    client_profiles::{ClientProfile, ClientProfiles},
+    compression,
+    config::Config,
+    events::{Event, EventBus},
+    explain,
+    hooks::{HookError, Hooks},
+    latency::LatencyTracker,
+    ownership::OwnerLabels,
+    port_test::{self, PortTestCache, SessionStatsCache},
+    record::Recorder,
     rpc::RawResponse,
+    scheduler::{Priority, Scheduler},
+    server,
+    state::SharedState,
+    torrent_index::{IndexEntry, TorrentIndex},
+    upstream_auth::UpstreamAuth,
+    Args,
 };
+#[cfg(feature = "client")]
+use {crate::torrent_urls::TorrentUrlFetcher, base64::Engine};
 
 use super::{
-    MethodCall, Request, Response, ResponseKind, ResponseStatus, SessionArguments, TorrentAction,
-    TorrentGet, TorrentIds, TorrentRemove, TorrentRenamePath, TorrentSet, TorrentSetLocation,
-    Torrents,
+    coalesce::{Coalescer, Flight, SharedResponse},
+    compat::{self, Translation, TRANSMISSION_4_RPC_VERSION},
+    filter::{check_method, prefix_ok, torrent_count, Pipeline, RequestContext, ResponseContext},
+    mirror::Mirror,
+    FreeSpaceResult, MethodCall, MethodName, Request, Response, ResponseKind, ResponseStatus,
+    SessionGet, SessionStats, Torrent, TorrentGet, TorrentId, TorrentIds, Torrents,
 };
 
-/// Trait for requests that hold torrent ids
-trait HasTorrentIds: Send + Sync {
-    /// true if the response torrents should be filtered instead of the request
-    fn filters_on_response(&self) -> bool {
-        false
-    }
-
-    /// Get the torrent ids
-    fn ids(&self) -> &Option<TorrentIds>;
-    /// Get a mutable reference to the torrent ids
-    fn ids_mut(&mut self) -> &mut Option<TorrentIds>;
-}
-
-macro_rules! impl_has_torrent_ids {
-    ($t:ty) => {
-        impl HasTorrentIds for $t {
-            fn ids(&self) -> &Option<TorrentIds> {
-                &self.ids
-            }
-
-            fn ids_mut(&mut self) -> &mut Option<TorrentIds> {
-                &mut self.ids
+/// Header used by Transmission for the session id handshake
+pub const SESSION_ID_HEADER: &str = "X-Transmission-Session-Id";
+
+/// Method name the latency of calls forwarded on the fast path is tracked under
+const FAST_PATH_METHOD: &str = "fast-path";
+
+/// Report the space left in a quota in a free-space response
+fn apply_quota(response: &mut Response, remaining: u64, quota: u64) {
+    let mut free_space: FreeSpaceResult = match response.arguments.take() {
+        Some(ResponseKind::FreeSpace(free_space)) => free_space,
+        Some(ResponseKind::Other { extra }) => match serde_json::from_value(extra.clone()) {
+            Ok(free_space) => free_space,
+            Err(_) => {
+                response.arguments = Some(ResponseKind::Other { extra });
+                return;
             }
+        },
+        other => {
+            response.arguments = other;
+            return;
         }
     };
 
-    ($t:ty, $e:expr) => {
-        impl HasTorrentIds for $t {
-            fn filters_on_response(&self) -> bool {
-                $e
-            }
-
-            fn ids(&self) -> &Option<TorrentIds> {
-                &self.ids
-            }
-
-            fn ids_mut(&mut self) -> &mut Option<TorrentIds> {
-                &mut self.ids
-            }
-        }
-    };
+    free_space.size_bytes = free_space
+        .size_bytes
+        .min(i64::try_from(remaining).unwrap_or(i64::MAX));
+    let quota = i64::try_from(quota).unwrap_or(i64::MAX);
+    free_space.total_size = Some(
+        free_space
+            .total_size
+            .map_or(quota, |total| total.min(quota)),
+    );
+
+    response.arguments = Some(ResponseKind::FreeSpace(free_space));
 }
 
-impl_has_torrent_ids!(TorrentAction);
-impl_has_torrent_ids!(TorrentSet);
-impl_has_torrent_ids!(TorrentGet, true);
-impl_has_torrent_ids!(TorrentRemove);
-impl_has_torrent_ids!(TorrentSetLocation);
-impl_has_torrent_ids!(TorrentRenamePath);
-
-trait MaybeTorrentIds {
-    fn torrent_ids(&self) -> Option<&dyn HasTorrentIds>;
-    fn torrent_ids_mut(&mut self) -> Option<&mut dyn HasTorrentIds>;
+/// Method and tag of a request, for requests which are otherwise forwarded as is
+#[derive(Deserialize)]
+struct MethodPeek {
+    method: MethodName,
+    #[serde(default)]
+    tag: Option<i32>,
 }
 
-impl MaybeTorrentIds for MethodCall {
-    fn torrent_ids(&self) -> Option<&dyn HasTorrentIds> {
-        match self {
-            MethodCall::TorrentStart { arguments } => Some(arguments),
-            MethodCall::TorrentStartNow { arguments } => Some(arguments),
-            MethodCall::TorrentStop { arguments } => Some(arguments),
-            MethodCall::TorrentVerify { arguments } => Some(arguments),
-            MethodCall::TorrentReannounce { arguments } => Some(arguments),
-            MethodCall::TorrentSet { arguments } => Some(arguments),
-            MethodCall::TorrentGet { arguments } => Some(arguments),
-            MethodCall::TorrentRemove { arguments } => Some(arguments),
-            MethodCall::TorrentSetLocation { arguments } => Some(arguments),
-            MethodCall::TorrentRenamePath { arguments } => Some(arguments),
-            _ => None,
-        }
-    }
+/// Entity tag of a filtered torrent-get response, for the user, fields and ids of the request
+fn torrent_get_etag(
+    request: &Request,
+    response: &Response,
+    user: &AuthUser,
+) -> Option<HeaderValue> {
+    let MethodCall::TorrentGet { arguments } = &request.call else {
+        return None;
+    };
 
-    fn torrent_ids_mut(&mut self) -> Option<&mut dyn HasTorrentIds> {
-        match self {
-            MethodCall::TorrentStart { arguments } => Some(arguments),
-            MethodCall::TorrentStartNow { arguments } => Some(arguments),
-            MethodCall::TorrentStop { arguments } => Some(arguments),
-            MethodCall::TorrentVerify { arguments } => Some(arguments),
-            MethodCall::TorrentReannounce { arguments } => Some(arguments),
-            MethodCall::TorrentSet { arguments } => Some(arguments),
-            MethodCall::TorrentGet { arguments } => Some(arguments),
-            MethodCall::TorrentRemove { arguments } => Some(arguments),
-            MethodCall::TorrentSetLocation { arguments } => Some(arguments),
-            MethodCall::TorrentRenamePath { arguments } => Some(arguments),
-            _ => None,
-        }
+    if !response.result.is_success() {
+        return None;
     }
+
+    let mut hasher = Sha1::new();
+    hasher.update(user.username().unwrap_or_default());
+    hasher.update(serde_json::to_vec(arguments).ok()?);
+    hasher.update(serde_json::to_vec(&response.arguments).ok()?);
+
+    HeaderValue::try_from(format!("\"{}\"", crate::torrent::hex(&hasher.finalize()))).ok()
 }
 
 #[derive(Debug, Error)]
@@ -123,16 +138,64 @@ impl std::fmt::Display for FilterError {
     }
 }
 
+/// Name of a method, as used in RPC calls
+fn method_name(method: MethodName) -> String {
+    serde_json::to_value(method)
+        .ok()
+        .and_then(|name| name.as_str().map(str::to_owned))
+        .unwrap_or_default()
+}
+
+/// ACL constraint which denied a request
+#[derive(Debug, Error)]
+pub enum Denial {
+    #[error("method {} is not allowed", method_name(*.0))]
+    Method(MethodName),
+    #[error("{0} is outside of the allowed download dir")]
+    Location(String),
+    #[error("renaming {0} would escape the torrent download dir")]
+    RenamePath(String),
+    #[error("{0} would collide with another torrent")]
+    RenameCollision(String),
+    #[error("no tracker of the torrent is allowed")]
+    RequiredTracker,
+    #[error("the torrent was already added by another user")]
+    Duplicate,
+    #[error("torrent files can't be fetched from {0}")]
+    TorrentUrl(String),
+}
+
+impl Denial {
+    /// Failure code of this denial in RPC responses
+    pub fn code(&self) -> &'static str {
+        match self {
+            Denial::Method(_) => "method-not-allowed",
+            Denial::Location(_) => "location-not-allowed",
+            Denial::RenamePath(_) => "rename-path-not-allowed",
+            Denial::RenameCollision(_) => "rename-collision",
+            Denial::RequiredTracker => "tracker-required",
+            Denial::Duplicate => "duplicate-torrent",
+            Denial::TorrentUrl(_) => "torrent-url-not-allowed",
+        }
+    }
+}
+
 #[derive(Debug, Error)]
 pub enum FilterErrorKind {
     #[error("unsupported: {0}")]
     Unsupported(&'static str),
-    #[error("access denied")]
-    Forbidden,
+    #[error("the trackers of {0} can't be filtered")]
+    UnfilteredAdd(&'static str),
+    #[error("access denied{}", .0.as_ref().map(|denial| format!(": {denial}")).unwrap_or_default())]
+    Forbidden(Option<Denial>),
+    #[error("login required")]
+    LoginRequired,
     #[error("torrent error")]
     Torrent(#[from] serde_bencode::Error),
     #[error("base64 error")]
     Base64(#[from] base64::DecodeError),
+    #[error("unsafe file path in torrent: {0}")]
+    UnsafePath(String),
     #[error("could not parse request body")]
     ParseBody,
     #[error("could not decode body")]
@@ -141,25 +204,62 @@ pub enum FilterErrorKind {
     Upstream(#[from] hyper::Error),
     #[error("unknown upstream error")]
     UpstreamUnknown,
+    #[error("hook error")]
+    Hook(#[from] HookError),
+    #[error("session id required")]
+    SessionRequired(Option<HeaderValue>),
+    #[error("upstream is busy, try again later")]
+    Busy,
+    #[error("too many requests, try again later")]
+    RateLimited,
+    #[error("could not fetch torrent file: {0}")]
+    Fetch(String),
 }
 
 impl From<FilterError> for hyper::Response<hyper::Body> {
     fn from(value: FilterError) -> Self {
-        hyper::Response::builder()
+        if let FilterErrorKind::SessionRequired(session_id) = &value.kind {
+            // Forward the upstream session id so the client can retry
+            let mut builder = hyper::Response::builder().status(409);
+            if let Some(session_id) = session_id {
+                builder = builder.header(SESSION_ID_HEADER, session_id);
+            }
+
+            return builder.body(hyper::Body::empty()).unwrap();
+        }
+
+        let mut builder = hyper::Response::builder();
+        if let FilterErrorKind::LoginRequired = &value.kind {
+            builder = builder.header(WWW_AUTHENTICATE, "Basic realm=\"Transmission\"");
+        }
+
+        // Machine-readable reason for denied requests
+        let arguments = match &value.kind {
+            FilterErrorKind::Forbidden(Some(denial)) => Some(ResponseKind::Other {
+                extra: serde_json::json!({ "denial": denial.code() }),
+            }),
+            _ => None,
+        };
+
+        builder
             .status(match value.kind {
-                FilterErrorKind::Unsupported(_) => 501,
-                FilterErrorKind::Forbidden => 403,
+                FilterErrorKind::Unsupported(_) | FilterErrorKind::UnfilteredAdd(_) => 501,
+                FilterErrorKind::Forbidden(_) => 403,
+                FilterErrorKind::LoginRequired => 401,
                 FilterErrorKind::Torrent(_)
                 | FilterErrorKind::Base64(_)
+                | FilterErrorKind::UnsafePath(_)
                 | FilterErrorKind::ParseBody => 400,
-                FilterErrorKind::Serde(_) => 500,
-                FilterErrorKind::Upstream(_) => 503,
-                FilterErrorKind::UpstreamUnknown => 502,
+                FilterErrorKind::Serde(_) | FilterErrorKind::Hook(_) => 500,
+                FilterErrorKind::Upstream(_) | FilterErrorKind::Busy => 503,
+                FilterErrorKind::RateLimited => 429,
+                FilterErrorKind::UpstreamUnknown | FilterErrorKind::Fetch(_) => 502,
+                FilterErrorKind::SessionRequired(_) => 409,
             })
             .body(hyper::Body::from(
                 serde_json::to_string(&Response {
                     tag: value.tag,
-                    arguments: None,
+                    arguments,
                     result: ResponseStatus::Failure(value.kind.to_string()),
                 })
                 .unwrap(),
@@ -168,270 +268,434 @@ impl From<FilterError> for hyper::Response<hyper::Body> {
     }
 }
 
+pub(super) fn upstream_url(upstream: &Uri, req_url: &Uri) -> Uri {
+    let mut parts = upstream.clone().into_parts();
+
+    // TODO: Combine upstream path instead of replacing
+    parts.path_and_query = req_url.path_and_query().cloned();
+
+    // TODO: Handle possible errors
+    Uri::from_parts(parts).expect("failed building upstream uri")
+}
+
 pub struct RpcProxyClient {
-    upstream: Uri,
+    /// Primary upstream, followed by the standby upstream if any
+    upstreams: Vec<Uri>,
+    active: AtomicUsize,
+    /// RPC version of the active upstream, 0 if unknown
+    rpc_version: AtomicI32,
+    compat: bool,
+    mirror: Option<Arc<Mirror>>,
+    /// Torrent-get calls in flight, shared by identical calls
+    coalescer: Option<Coalescer>,
+    /// Known client apps, which change how their requests are handled
+    client_profiles: Arc<ClientProfiles>,
+    /// State shared with the other replicas, holding the rate limit counters
+    state: Arc<SharedState>,
+    /// Hide the reason of denied requests
+    terse_denials: bool,
+    /// Forward the calls of unrestricted ACLs untouched
+    fast_path: bool,
+    /// Upstream latency of RPC calls, if tracked
+    latency: Option<LatencyTracker>,
+    /// Faults injected into the RPC calls of clients
+    #[cfg(feature = "chaos")]
+    chaos: Chaos,
     client: Client<HttpConnector, Body>,
+    /// Credentials sent to the upstream
+    upstream_auth: Arc<UpstreamAuth>,
+    hooks: Hooks,
+    recorder: Option<Recorder>,
+    owner_labels: OwnerLabels,
+    rpc_path: String,
+    /// Path the proxy is bound to, and the public URL it maps to in redirects
+    bind_path: String,
+    public_url: Uri,
+    session_id: Mutex<Option<HeaderValue>>,
+    /// Torrents seen so far, to authorize their ids once they are removed
+    index: TorrentIndex,
+    port_test: Option<PortTestCache>,
+    session_stats: Option<SessionStatsCache>,
+    scheduler: Option<Scheduler>,
+    /// Filter stages of each configured ACL
+    pipelines: Vec<(Arc<Acl>, Arc<Pipeline>)>,
+    events: EventBus,
+    /// Largest decoded size of compressed request bodies, if they are decoded
+    max_decoded_size: Option<usize>,
+    /// Fetches the torrent files added by URL, if enabled
+    #[cfg(feature = "client")]
+    torrent_urls: Option<TorrentUrlFetcher>,
 }
 
 impl RpcProxyClient {
-    pub fn new(upstream: Uri) -> Self {
-        Self {
-            upstream,
-            client: Client::new(),
+    pub fn new(
+        args: &Args,
+        config: &Config,
+        state: Arc<SharedState>,
+        events: EventBus,
+    ) -> eyre::Result<Self> {
+        #[cfg(not(feature = "client"))]
+        if config.torrent_urls.fetch {
+            eyre::bail!(
+                "fetching torrent urls is not supported by this build, enable the client feature"
+            );
         }
+
+        let mut upstreams = vec![args.upstream.clone()];
+        if let Some(standby) = &config.failover.upstream {
+            upstreams.push(standby.parse()?);
+        }
+
+        Ok(Self {
+            upstreams,
+            active: AtomicUsize::new(0),
+            rpc_version: AtomicI32::new(0),
+            compat: config.compat.enabled,
+            mirror: Mirror::new(&config.mirror)?.map(Arc::new),
+            coalescer: Coalescer::new(&config.coalesce),
+            client_profiles: config.client_profiles.clone(),
+            terse_denials: config.acl.terse_denials,
+            fast_path: config.acl.fast_path,
+            latency: LatencyTracker::new(&config.latency),
+            #[cfg(feature = "chaos")]
+            chaos: Default::default(),
+            client: Client::new(),
+            upstream_auth: config.upstream_auth.clone(),
+            hooks: Hooks::load(&config.plugins)?,
+            recorder: args.record.as_deref().map(Recorder::open).transpose()?,
+            owner_labels: config.owner_labels.clone(),
+            rpc_path: args.bind.path().trim_end_matches('/').to_owned() + "/rpc",
+            bind_path: args.bind.path().trim_end_matches('/').to_owned(),
+            public_url: args.public_url(),
+            session_id: Default::default(),
+            index: TorrentIndex::new(&config.owner_labels),
+            port_test: PortTestCache::new(&config.port_test, state.clone()),
+            session_stats: SessionStatsCache::new(&config.session_stats, state.clone()),
+            state,
+            scheduler: Scheduler::new(&config.scheduler),
+            pipelines: config
+                .acl
+                .rules()
+                .iter()
+                .map(|acl| {
+                    (
+                        acl.clone(),
+                        Arc::new(Pipeline::new(acl, &config.owner_labels)),
+                    )
+                })
+                .collect(),
+            events,
+            max_decoded_size: config
+                .compression
+                .decode_requests
+                .then_some(config.compression.max_decoded_size),
+            #[cfg(feature = "client")]
+            torrent_urls: TorrentUrlFetcher::new(&config.torrent_urls, &config.http_client),
+        })
     }
 
-    async fn filter_torrent_ids(
-        &self,
-        torrent_ids: &mut dyn HasTorrentIds,
-        current_rpc_request: &hyper::Request<Body>,
-        acl: &Acl,
-    ) -> Result<(), FilterErrorKind> {
-        if torrent_ids.filters_on_response() {
-            // Nothing to do, filter on response
-            Ok(())
-        } else {
-            let input = torrent_ids.ids().clone();
-
-            // Fetch torrent details so we can authorize the targets
-            let torrents_rpc_req = Request {
-                call: MethodCall::TorrentGet {
-                    arguments: TorrentGet {
-                        ids: input.clone(),
-                        fields: vec![Cow::Borrowed("id"), Cow::Borrowed("downloadDir")],
-                        format: Default::default(),
-                    },
-                },
-                tag: None,
-            };
+    /// Upstream latency of RPC calls, if tracked
+    pub fn latency(&self) -> Option<&LatencyTracker> {
+        self.latency.as_ref()
+    }
 
-            // Prepare the HTTP request
-            let mut req = hyper::Request::builder()
-                .uri(current_rpc_request.uri())
-                .method(current_rpc_request.method())
-                .body(Body::from(
-                    serde_json::to_string(&torrents_rpc_req).unwrap(),
-                ))
-                .unwrap();
+    /// Faults injected into the RPC calls of clients
+    #[cfg(feature = "chaos")]
+    pub fn chaos(&self) -> &Chaos {
+        &self.chaos
+    }
 
-            for header in current_rpc_request.headers() {
-                if header.0 != CONTENT_LENGTH {
-                    req.headers_mut().insert(header.0, header.1.clone());
-                }
-            }
+    /// Record the upstream latency of a call
+    fn record_latency(&self, call: &MethodCall, start: Instant) {
+        if let Some(latency) = &self.latency {
+            latency.record(
+                &method_name(MethodName::from(call)),
+                start.elapsed(),
+                torrent_count(call),
+            );
+        }
+    }
 
-            // Send it
-            let mut res = self.client.request(req).await?;
+    /// Torrents of the upstream seen by the proxy
+    pub fn index(&self) -> &TorrentIndex {
+        &self.index
+    }
 
-            // Decode the response
-            let response: RawResponse =
-                serde_json::from_slice(hyper::body::to_bytes(res.body_mut()).await?.as_ref())?;
-
-            // Decode the response arguments
-            let torrents: Torrents = serde_json::from_value(
-                response.arguments.ok_or(FilterErrorKind::UpstreamUnknown)?,
-            )?;
-
-            *torrent_ids.ids_mut() = Some(TorrentIds::Ids(
-                torrents
-                    .torrents
-                    .into_iter()
-                    .filter(|torrent| self.prefix_ok(torrent.download_dir.as_ref().unwrap(), acl))
-                    .map(|torrent| torrent.id.unwrap())
-                    .collect(),
-            ));
+    /// Index of the upstream requests are forwarded to
+    pub fn active_upstream(&self) -> usize {
+        self.active.load(Ordering::Relaxed)
+    }
 
-            debug!(input = ?input, output = ?torrent_ids.ids().as_ref().unwrap(), "filtered torrent ids");
+    /// Forward requests to another upstream
+    pub async fn set_active_upstream(&self, index: usize) {
+        if index < self.upstreams.len() {
+            self.active.store(index, Ordering::Relaxed);
+            self.rpc_version.store(0, Ordering::Relaxed);
 
-            Ok(())
+            // Session ids are not shared between daemons
+            *self.session_id.lock().await = None;
         }
     }
 
-    fn filter_tracker(&self, tracker: &mut Option<String>, tracker_rules: &[TrackerRule]) {
-        for rule in tracker_rules.iter() {
-            if let Some(announce) = tracker {
-                if !rule.matches(announce.as_str()) {
-                    continue;
-                }
+    /// RPC version of the active upstream, fetched on first use
+    pub async fn upstream_rpc_version(&self) -> Result<i32, FilterErrorKind> {
+        #[derive(Deserialize)]
+        #[serde(rename_all = "kebab-case")]
+        struct Version {
+            rpc_version: i32,
+        }
 
-                if let Some(result) = rule.apply(announce.as_str()) {
-                    *tracker = Some(result);
-                } else {
-                    // The announce URL was removed
-                    *tracker = None;
-                }
-            } else {
-                break;
-            }
+        let rpc_version = self.rpc_version.load(Ordering::Relaxed);
+        if rpc_version != 0 {
+            return Ok(rpc_version);
         }
-    }
 
-    fn filter_tracker_list(&self, tracker_list: &mut Vec<String>, tracker_rules: &[TrackerRule]) {
-        let mut new_list = Vec::with_capacity(tracker_list.len());
+        let version: Version = self
+            .session_get(SessionGet {
+                fields: vec![Cow::Borrowed("rpc-version")],
+            })
+            .await?;
 
-        for item in tracker_list.iter() {
-            let mut result = Some(item.clone());
+        self.set_upstream_rpc_version(version.rpc_version);
+        Ok(version.rpc_version)
+    }
 
-            self.filter_tracker(&mut result, tracker_rules);
+    pub fn set_upstream_rpc_version(&self, rpc_version: i32) {
+        self.rpc_version.store(rpc_version, Ordering::Relaxed);
+    }
 
-            if let Some(announce) = result {
-                new_list.push(announce);
-            }
+    /// Session id of the proxy with the active upstream, acquired if needed
+    pub async fn session_id(&self) -> Result<Option<HeaderValue>, FilterErrorKind> {
+        if self.session_id.lock().await.is_none() {
+            self.call(MethodCall::SessionStats).await?;
         }
 
-        *tracker_list = new_list;
+        Ok(self.session_id.lock().await.clone())
+    }
+
+    /// Returns true if the upstream at the given index answers HTTP requests
+    pub async fn probe(&self, index: usize, timeout: Duration) -> bool {
+        let uri = upstream_url(
+            &self.upstreams[index],
+            &Uri::try_from(self.rpc_path.as_str()).unwrap(),
+        );
+        let mut req = hyper::Request::post(uri).body(Body::empty()).unwrap();
+        self.upstream_auth.apply(&mut req, None);
+
+        matches!(
+            tokio::time::timeout(timeout, self.client.request(req)).await,
+            Ok(Ok(_))
+        )
     }
 
-    fn prefix_ok(&self, location: &str, acl: &Acl) -> bool {
-        if let Some(download_dir) = &acl.download_dir {
-            // Exact match, we can exit already
-            if location == download_dir {
-                return true;
+    /// Perform an RPC call on behalf of the proxy itself
+    pub async fn call(&self, call: MethodCall) -> Result<RawResponse, FilterErrorKind> {
+        let request = Request { call, tag: None };
+        let body = serde_json::to_string(&request)?;
+        let uri = self.get_upstream_url(&Uri::try_from(self.rpc_path.as_str()).unwrap());
+
+        // Retry once if the session id changed
+        for _ in 0..2 {
+            let mut req = hyper::Request::post(uri.clone())
+                .body(Body::from(body.clone()))
+                .unwrap();
+
+            if let Some(session_id) = self.session_id.lock().await.clone() {
+                req.headers_mut().insert(SESSION_ID_HEADER, session_id);
             }
+            self.upstream_auth.apply(&mut req, None);
 
-            // Else, check that it's a prefix match
-            let prefix = if download_dir.ends_with('/') {
-                download_dir.clone()
-            } else {
-                format!("{}/", download_dir)
-            };
+            let start = Instant::now();
+            let mut res = self.client.request(req).await?;
 
-            if !location.starts_with(&prefix) {
-                // The download dir field was tampered with
-                return false;
+            if res.status() == 409 {
+                *self.session_id.lock().await = res.headers().get(SESSION_ID_HEADER).cloned();
+                continue;
             }
+
+            let bytes = hyper::body::to_bytes(res.body_mut()).await?;
+            self.record_latency(&request.call, start);
+            return Ok(serde_json::from_slice(bytes.as_ref())?);
         }
 
-        true
+        Err(FilterErrorKind::UpstreamUnknown)
     }
 
-    async fn do_filter_request(
+    /// Perform an RPC call on behalf of the proxy itself and decode its arguments
+    async fn call_typed<T: DeserializeOwned>(
         &self,
-        mut request: Request,
-        acl: &Acl,
-        current_rpc_request: &hyper::Request<Body>,
-    ) -> Result<Request, FilterErrorKind> {
-        // Check ACL
-        if !acl.allowed_methods.is_empty() {
-            // Restrict allowed methods
-            if !acl.allowed_methods.contains(&(&request.call).into()) {
-                return Err(FilterErrorKind::Forbidden);
-            }
-        }
+        call: MethodCall,
+    ) -> Result<T, FilterErrorKind> {
+        let response = self.call(call).await?;
+        Ok(serde_json::from_value(
+            response.arguments.ok_or(FilterErrorKind::UpstreamUnknown)?,
+        )?)
+    }
 
-        // Filter torrent ids
-        if acl.download_dir.is_some() {
-            if let Some(torrent_ids) = request.call.torrent_ids_mut() {
-                self.filter_torrent_ids(torrent_ids, current_rpc_request, acl)
-                    .await
-                    .map_err(|err| {
-                        error!(?err, "failed filtering torrent ids");
-                        err
-                    })?;
-            }
-        }
+    pub async fn torrent_get(&self, arguments: TorrentGet) -> Result<Torrents, FilterErrorKind> {
+        self.call_typed(MethodCall::TorrentGet { arguments }).await
+    }
 
-        match &mut request.call {
-            // Torrent actions: they were authorized by filter_torrent_ids
-            MethodCall::TorrentStart { .. } => Ok(request),
-            MethodCall::TorrentStartNow { .. } => Ok(request),
-            MethodCall::TorrentStop { .. } => Ok(request),
-            MethodCall::TorrentVerify { .. } => Ok(request),
-            MethodCall::TorrentReannounce { .. } => Ok(request),
-            MethodCall::TorrentRemove { .. } => Ok(request),
-
-            // Queue actions: they were authorized by filter_torrent_ids
-            MethodCall::QueueMoveTop { .. } => Ok(request),
-            MethodCall::QueueMoveUp { .. } => Ok(request),
-            MethodCall::QueueMoveDown { .. } => Ok(request),
-            MethodCall::QueueMoveBottom { .. } => Ok(request),
-
-            MethodCall::TorrentSet { arguments } => {
-                // Check the new location, if any
-                if let Some(new_location) = &arguments.location {
-                    if !self.prefix_ok(new_location, acl) {
-                        return Err(FilterErrorKind::Forbidden);
-                    }
-                }
+    /// Fetch session fields into the given type, which may only hold the requested fields
+    pub async fn session_get<T: DeserializeOwned>(
+        &self,
+        arguments: SessionGet,
+    ) -> Result<T, FilterErrorKind> {
+        self.call_typed(MethodCall::SessionGet { arguments }).await
+    }
 
-                if let Some(tracker_rules) =
-                    (!acl.tracker_rules.is_empty()).then_some(&acl.tracker_rules)
-                {
-                    self.filter_tracker_list(&mut arguments.tracker_add, tracker_rules);
-                    self.filter_tracker_list(&mut arguments.tracker_remove, tracker_rules);
-
-                    // TODO: Support trackerReplace
-                    if !arguments.tracker_replace.is_empty() {
-                        return Err(FilterErrorKind::Unsupported(
-                            "trackerReplace in torrent-set",
-                        ));
-                    }
-                }
+    pub async fn session_stats(&self) -> Result<SessionStats, FilterErrorKind> {
+        self.call_typed(MethodCall::SessionStats).await
+    }
 
-                Ok(request)
-            }
+    /// Fetch the given fields of the target torrents from the upstream
+    pub(super) async fn fetch_torrents(
+        &self,
+        ids: Option<TorrentIds>,
+        fields: Vec<Cow<'static, str>>,
+        current_rpc_request: &hyper::Request<Body>,
+    ) -> Result<Vec<Torrent>, FilterErrorKind> {
+        let torrents_rpc_req = Request {
+            call: MethodCall::TorrentGet {
+                arguments: TorrentGet {
+                    ids,
+                    fields,
+                    format: Default::default(),
+                },
+            },
+            tag: None,
+        };
 
-            MethodCall::TorrentSetLocation { arguments } => {
-                if !self.prefix_ok(&arguments.location, acl) {
-                    return Err(FilterErrorKind::Forbidden);
-                }
+        // Prepare the HTTP request
+        let mut req = hyper::Request::builder()
+            .uri(current_rpc_request.uri())
+            .method(current_rpc_request.method())
+            .body(Body::from(
+                serde_json::to_string(&torrents_rpc_req).unwrap(),
+            ))
+            .unwrap();
 
-                Ok(request)
+        for header in current_rpc_request.headers() {
+            if header.0 != CONTENT_LENGTH {
+                req.headers_mut().insert(header.0, header.1.clone());
             }
+        }
+
+        // Send it
+        let mut res = self.client.request(req).await?;
+
+        // The client didn't go through the session id handshake yet
+        if res.status() == 409 {
+            return Err(FilterErrorKind::SessionRequired(
+                res.headers().get(SESSION_ID_HEADER).cloned(),
+            ));
+        }
 
-            // TODO: Could this be used to escape path filtering?
-            MethodCall::TorrentRenamePath { .. } => Ok(request),
+        // Decode the response
+        let response: RawResponse =
+            serde_json::from_slice(hyper::body::to_bytes(res.body_mut()).await?.as_ref())?;
+        let torrents: Torrents =
+            serde_json::from_value(response.arguments.ok_or(FilterErrorKind::UpstreamUnknown)?)?;
 
-            // Session methods: authorized by acl.allowed_methods
-            MethodCall::SessionSet { .. } => Ok(request),
-            MethodCall::SessionGet { .. } => Ok(request),
-            MethodCall::SessionStats => Ok(request),
-            MethodCall::BlocklistUpdate => Ok(request),
-            MethodCall::PortTest => Ok(request),
-            MethodCall::SessionClose => Ok(request),
-            MethodCall::FreeSpace { .. } => Ok(request),
+        Ok(torrents.torrents)
+    }
 
-            // Torrent get: filters on response
-            MethodCall::TorrentGet { .. } => Ok(request),
+    /// Record the torrents added and removed through the proxy in the index
+    fn index_response(&self, request: &Request, response: &RawResponse, user: &AuthUser) {
+        if !response.result.is_success() {
+            return;
+        }
 
+        match &request.call {
             MethodCall::TorrentAdd { arguments } => {
-                if !self.prefix_ok(&arguments.download_dir, acl) {
-                    return Err(FilterErrorKind::Forbidden);
+                let Some(added) = response
+                    .arguments
+                    .as_ref()
+                    .and_then(|response| response.get("torrent-added"))
+                else {
+                    return;
+                };
+
+                let Ok(torrent) = serde_json::from_value::<Torrent>(added.clone()) else {
+                    return;
+                };
+
+                if let Some(TorrentId::Id(id)) = torrent.id {
+                    self.index.insert(IndexEntry {
+                        id,
+                        info_hash: torrent.hash_string.clone(),
+                        download_dir: Some(arguments.download_dir.clone()),
+                        owner: user.username().map(str::to_owned),
+                    });
+
+                    self.events.emit(Event::TorrentAdded {
+                        user: user.username().map(str::to_owned),
+                        id,
+                        hash: torrent.hash_string,
+                        name: torrent.name,
+                    });
                 }
+            }
+            MethodCall::TorrentRemove { arguments } => {
+                if let Some(TorrentIds::Ids(ids)) = &arguments.ids {
+                    self.index.remove(ids.iter().filter_map(|id| match id {
+                        TorrentId::Id(id) => Some(*id),
+                        TorrentId::Sha1(_) => None,
+                    }));
+                }
+            }
+            _ => {}
+        }
+    }
 
-                if let Some(tracker_rules) =
-                    (!acl.tracker_rules.is_empty()).then_some(&acl.tracker_rules)
-                {
-                    let b64 = &base64::engine::general_purpose::STANDARD;
-
-                    // Parse torrent in metainfo
-                    if !arguments.metainfo.is_empty() {
-                        let mut torrent = serde_bencode::de::from_bytes::<crate::torrent::Torrent>(
-                            b64.decode(&arguments.metainfo)?.as_ref(),
-                        )?;
-
-                        // Replace announce list
-                        for list in &mut torrent.announce_list {
-                            for sublist in list.iter_mut() {
-                                self.filter_tracker_list(sublist, tracker_rules);
-                            }
-                        }
+    /// Translate a request for the RPC version of the upstream
+    async fn translate_request(
+        &self,
+        request: &mut Request,
+        current_rpc_request: &hyper::Request<Body>,
+    ) -> Result<Translation, FilterErrorKind> {
+        if !self.compat {
+            return Ok(Default::default());
+        }
 
-                        // Replace main announce URL
-                        self.filter_tracker(&mut torrent.announce, tracker_rules);
+        let rpc_version = self.upstream_rpc_version().await?;
 
-                        // Replace argument
-                        arguments.metainfo = b64.encode(serde_bencode::ser::to_bytes(&torrent)?);
+        match &mut request.call {
+            MethodCall::TorrentGet { arguments } => {
+                Ok(Translation::torrent_get(arguments, rpc_version))
+            }
+
+            MethodCall::TorrentSet { arguments } if rpc_version < TRANSMISSION_4_RPC_VERSION => {
+                if let Some(tracker_list) = arguments.tracker_list.take() {
+                    let torrents = self
+                        .fetch_torrents(
+                            arguments.ids.clone(),
+                            vec![Cow::Borrowed("id"), Cow::Borrowed("trackers")],
+                            current_rpc_request,
+                        )
+                        .await?;
+
+                    // All torrents get the same changes, so they must have the same trackers
+                    let mut changes = torrents
+                        .iter()
+                        .map(|torrent| compat::tracker_changes(torrent, &tracker_list));
+
+                    if let Some((add, remove)) = changes.next() {
+                        if changes.any(|other| other != (add.clone(), remove.clone())) {
+                            return Err(FilterErrorKind::Unsupported(
+                                "trackerList on torrents with different trackers",
+                            ));
+                        }
 
-                        Ok(request)
-                    } else {
-                        // TODO: Support magnet links
-                        Err(FilterErrorKind::Unsupported("magnet links"))
+                        arguments.tracker_add.extend(add);
+                        arguments.tracker_remove.extend(remove);
                     }
-                } else {
-                    Ok(request)
                 }
+
+                Ok(Default::default())
             }
+
+            _ => Ok(Default::default()),
         }
     }
 
@@ -439,130 +703,598 @@ impl RpcProxyClient {
         &self,
         request: Request,
         acl: &Acl,
+        caller: &server::RequestContext,
         current_rpc_request: &hyper::Request<Body>,
     ) -> Result<Request, FilterError> {
         let tag = request.tag;
+        let user = &caller.user;
+        let ctx = RequestContext {
+            client: self,
+            acl,
+            caller,
+            http_request: current_rpc_request,
+        };
+
+        let mut request = request;
+
+        // Replace torrent URLs by their metainfo, for the filters to check it
+        #[cfg(feature = "client")]
+        if let Some(fetcher) = &self.torrent_urls {
+            Self::fetch_torrent_url(fetcher, &mut request, acl)
+                .await
+                .map_err(|kind| self.filter_error(tag, kind, user))?;
+        }
 
-        self.do_filter_request(request, acl, current_rpc_request)
+        self.pipeline(acl)
+            .filter_request(&mut request, &ctx)
             .await
-            .map_err(|kind| FilterError { tag, kind })
+            .map_err(|kind| self.filter_error(tag, kind, user))?;
+
+        self.hooks
+            .on_request(request)
+            .await
+            .map_err(|err| self.filter_error(tag, err.into(), user))
     }
 
-    fn do_filter_response(
-        &self,
-        request: &Request,
-        response: RawResponse,
+    /// Returns true if torrent files added by URL are fetched by the proxy
+    fn fetches_torrent_urls(&self) -> bool {
+        #[cfg(feature = "client")]
+        return self.torrent_urls.is_some();
+        #[cfg(not(feature = "client"))]
+        false
+    }
+
+    /// Returns true if an RPC call of the ACL can be forwarded untouched, without reading its body
+    fn takes_fast_path(&self, acl: &Acl, req: &hyper::Request<Body>) -> bool {
+        let profile = self.client_profiles.find(
+            req.headers()
+                .get(USER_AGENT)
+                .map(|user_agent| user_agent.as_bytes()),
+        );
+
+        self.fast_path
+            && acl.is_nop()
+            && acl.allow_dangerous_methods
+            && self.hooks.is_empty()
+            && !self.owner_labels.enabled
+            && !self.compat
+            && !self.fetches_torrent_urls()
+            && self.mirror.is_none()
+            && self.scheduler.is_none()
+            && self.port_test.is_none()
+            && self.session_stats.is_none()
+            && self.recorder.is_none()
+            && !explain::is_dry_run()
+            && (self.max_decoded_size.is_none() || !req.headers().contains_key(CONTENT_ENCODING))
+            && profile.map_or(true, |profile| {
+                profile.rate_limit.is_none() && !profile.rewrites_requests()
+            })
+    }
+
+    /// Fetch the torrent file of a torrent-add call by URL, and add it by metainfo instead
+    #[cfg(feature = "client")]
+    async fn fetch_torrent_url(
+        fetcher: &TorrentUrlFetcher,
+        request: &mut Request,
         acl: &Acl,
-    ) -> Result<Response, FilterErrorKind> {
-        if let Some(download_dir) = &acl.download_dir {
-            match &request.call {
-                MethodCall::TorrentGet { .. } => {
-                    if let Some(torrent_get_raw) = response.arguments {
-                        let mut torrents: Torrents = serde_json::from_value(torrent_get_raw)?;
-
-                        torrents.torrents = torrents
-                            .torrents
-                            .drain(..)
-                            .filter(|torrent| {
-                                // Strip trailing /
-                                if let Some(torrent_download_dir) = torrent.download_dir.as_ref() {
-                                    let torrent_download_dir = torrent_download_dir
-                                        .strip_suffix('/')
-                                        .unwrap_or_else(|| torrent.download_dir.as_ref().unwrap());
-
-                                    torrent_download_dir.starts_with(download_dir)
-                                } else {
-                                    error!(
-                                        ?torrent,
-                                        "torrent {} has empty download dir, this is unexpected",
-                                        torrent.name
-                                    );
+    ) -> Result<(), FilterErrorKind> {
+        let MethodCall::TorrentAdd { arguments } = &mut request.call else {
+            return Ok(());
+        };
 
-                                    false
-                                }
-                            })
-                            .collect();
-
-                        return Ok(Response {
-                            tag: request.tag,
-                            arguments: ResponseKind::Torrents(torrents).into(),
-                            result: response.result,
-                        });
-                    }
-                }
+        // Users who can't add torrents are denied by the filters, without fetching anything
+        if !acl.allows(MethodName::TorrentAdd) || !arguments.metainfo.is_empty() {
+            return Ok(());
+        }
 
-                MethodCall::SessionGet { .. } => {
-                    if let Some(session_arguments_raw) = response.arguments {
-                        let mut session: SessionArguments =
-                            serde_json::from_value(session_arguments_raw)?;
+        let Some(url) = arguments
+            .filename
+            .clone()
+            .filter(|filename| TorrentUrlFetcher::handles(filename))
+        else {
+            return Ok(());
+        };
 
-                        session.download_dir = download_dir.to_owned();
+        let metainfo = fetcher.fetch(&url).await;
+        explain::record("torrent url", metainfo.is_ok(), || url.clone());
 
-                        return Ok(Response {
-                            tag: request.tag,
-                            arguments: ResponseKind::Session(session).into(),
-                            result: response.result,
-                        });
-                    }
+        arguments.metainfo = base64::engine::general_purpose::STANDARD.encode(metainfo?);
+        arguments.filename = None;
+        Ok(())
+    }
+
+    /// Filter stages of an ACL, assembled when the configuration was loaded for configured ACLs
+    fn pipeline(&self, acl: &Acl) -> Arc<Pipeline> {
+        match self
+            .pipelines
+            .iter()
+            .find(|(configured, _)| std::ptr::eq(configured.as_ref(), acl))
+        {
+            Some((_, pipeline)) => pipeline.clone(),
+            None => Arc::new(Pipeline::new(acl, &self.owner_labels)),
+        }
+    }
+
+    /// Build the error returned to the client, hiding the denial reason if needed
+    fn filter_error(
+        &self,
+        tag: Option<i32>,
+        kind: FilterErrorKind,
+        user: &AuthUser,
+    ) -> FilterError {
+        // Explained requests are not actually denied
+        if let (FilterErrorKind::Forbidden(Some(denial)), false) = (&kind, explain::is_dry_run()) {
+            self.events.emit(Event::RpcDenied {
+                user: user.username().map(str::to_owned),
+                code: denial.code(),
+                reason: denial.to_string(),
+            });
+        }
+
+        FilterError {
+            tag,
+            kind: match kind {
+                FilterErrorKind::Forbidden(_) if self.terse_denials => {
+                    FilterErrorKind::Forbidden(None)
                 }
-                _ => {}
-            }
+                kind => kind,
+            },
         }
+    }
 
-        Ok(Response {
+    pub async fn filter_response(
+        &self,
+        request: &Request,
+        response: RawResponse,
+        acl: &Acl,
+        caller: &server::RequestContext,
+    ) -> Result<Response, FilterError> {
+        self.index_response(request, &response, &caller.user);
+
+        let mut response = Response {
             tag: response.tag,
             arguments: response
                 .arguments
                 .map(|raw| ResponseKind::Other { extra: raw }),
             result: response.result,
+        };
+
+        let ctx = ResponseContext {
+            client: self,
+            acl,
+            caller,
+        };
+
+        let filtered = match self
+            .pipeline(acl)
+            .filter_response(request, &mut response, &ctx)
+        {
+            Ok(()) => self
+                .hooks
+                .on_response(request, response)
+                .await
+                .map_err(FilterErrorKind::from),
+            Err(kind) => Err(kind),
+        };
+
+        filtered.map_err(|kind| {
+            error!(request=?request, err=?kind, "error filtering response");
+
+            FilterError {
+                tag: request.tag,
+                kind,
+            }
         })
     }
 
-    pub fn filter_response(
+    /// Space left in the quota of the ACL, for free-space calls
+    async fn remaining_quota(
         &self,
-        request: &Request,
-        response: RawResponse,
+        request: Option<&Request>,
         acl: &Acl,
-    ) -> Result<Response, FilterError> {
-        self.do_filter_response(request, response, acl)
-            .map_err(|kind| {
-                error!(request=?request, err=?kind, "error filtering response");
+        user: &AuthUser,
+        current_rpc_request: &hyper::Request<Body>,
+    ) -> Result<Option<u64>, FilterErrorKind> {
+        let (
+            Some(quota),
+            Some(Request {
+                call: MethodCall::FreeSpace { .. },
+                ..
+            }),
+        ) = (acl.quota, request)
+        else {
+            return Ok(None);
+        };
 
-                FilterError {
-                    tag: request.tag,
-                    kind,
-                }
+        let used: i64 = self
+            .fetch_torrents(
+                None,
+                vec![Cow::Borrowed("downloadDir"), Cow::Borrowed("sizeWhenDone")],
+                current_rpc_request,
+            )
+            .await?
+            .iter()
+            .filter(|torrent| {
+                torrent.download_dir.as_deref().map_or(false, |dir| {
+                    prefix_ok(dir.strip_suffix('/').unwrap_or(dir), acl)
+                })
             })
+            .filter_map(|torrent| torrent.size_when_done)
+            .sum();
+
+        let used = used.max(0) as u64;
+        let remaining = quota.saturating_sub(used);
+        explain::record("quota", remaining > 0, || {
+            format!("{used} of {quota} bytes used")
+        });
+
+        if remaining == 0 && !explain::is_dry_run() {
+            self.events.emit(Event::QuotaExceeded {
+                user: user.username().map(str::to_owned),
+                used,
+                quota,
+            });
+        }
+
+        Ok(Some(remaining))
     }
 
     fn get_upstream_url(&self, req_url: &Uri) -> Uri {
-        let mut parts = self.upstream.clone().into_parts();
+        upstream_url(&self.upstreams[self.active_upstream()], req_url)
+    }
+
+    /// Run a port test, or serve the last result to users who may not run one
+    async fn cached_port_test(
+        &self,
+        req: hyper::Request<Body>,
+        tag: Option<i32>,
+        acl: &Acl,
+        user: &AuthUser,
+        cache: &PortTestCache,
+    ) -> Result<hyper::Response<Body>, hyper::Error> {
+        let may_run = cache.may_run(acl);
+        let denied = || {
+            let kind = check_method(MethodName::PortTest, acl, user)
+                .err()
+                .unwrap_or(FilterErrorKind::Forbidden(Some(Denial::Method(
+                    MethodName::PortTest,
+                ))));
+            Ok(self.filter_error(tag, kind, user).into())
+        };
+
+        // Cached results are only served to ACLs which allow port tests
+        if !cache.may_read(acl) {
+            return denied();
+        }
 
-        // TODO: Combine upstream path instead of replacing
-        parts.path_and_query = req_url.path_and_query().cloned();
+        match cache.last().await {
+            Some(cached) if !may_run || cached.fresh => {
+                explain::record("port test", true, || {
+                    format!("cached {}s ago", cached.age.as_secs())
+                });
+                return self
+                    .cached_call_hooks(cache.response(tag, cached), MethodCall::PortTest, tag, user)
+                    .await;
+            }
+            None if !may_run => return denied(),
+            _ => {}
+        }
 
-        // TODO: Handle possible errors
-        Uri::from_parts(parts).expect("failed building upstream uri")
+        explain::record("port test", true, || "forwarded".to_owned());
+        if explain::is_dry_run() {
+            return Ok(hyper::Response::new(req.into_body()));
+        }
+
+        let (parts, body) = self.client.request(req).await?.into_parts();
+        let bytes = hyper::body::to_bytes(body).await?;
+
+        if parts.status.is_success() {
+            if let Some(arguments) = port_test::result_arguments(&bytes) {
+                cache.store(arguments).await;
+            }
+        }
+
+        self.cached_call_hooks(
+            hyper::Response::from_parts(parts, Body::from(bytes)),
+            MethodCall::PortTest,
+            tag,
+            user,
+        )
+        .await
+    }
+
+    /// Answer session-stats from the cache, or forward it and cache its result
+    async fn cached_session_stats(
+        &self,
+        req: hyper::Request<Body>,
+        tag: Option<i32>,
+        acl: &Acl,
+        user: &AuthUser,
+        cache: &SessionStatsCache,
+    ) -> Result<hyper::Response<Body>, hyper::Error> {
+        if let Err(kind) = check_method(MethodName::SessionStats, acl, user) {
+            return Ok(self.filter_error(tag, kind, user).into());
+        }
+
+        if let Some(arguments) = cache.fresh().await {
+            explain::record("session stats", true, || "cached".to_owned());
+            return self
+                .cached_call_hooks(
+                    cache.response(tag, arguments),
+                    MethodCall::SessionStats,
+                    tag,
+                    user,
+                )
+                .await;
+        }
+
+        explain::record("session stats", true, || "forwarded".to_owned());
+        if explain::is_dry_run() {
+            return Ok(hyper::Response::new(req.into_body()));
+        }
+
+        let (parts, body) = self.client.request(req).await?.into_parts();
+        let bytes = hyper::body::to_bytes(body).await?;
+
+        if parts.status.is_success() {
+            if let Some(arguments) = port_test::result_arguments(&bytes) {
+                cache.store(arguments).await;
+            }
+        }
+
+        self.cached_call_hooks(
+            hyper::Response::from_parts(parts, Body::from(bytes)),
+            MethodCall::SessionStats,
+            tag,
+            user,
+        )
+        .await
+    }
+
+    /// Run the response hooks on the answer to a cached call, which doesn't go through the
+    /// filter pipeline
+    async fn cached_call_hooks(
+        &self,
+        response: hyper::Response<Body>,
+        call: MethodCall,
+        tag: Option<i32>,
+        user: &AuthUser,
+    ) -> Result<hyper::Response<Body>, hyper::Error> {
+        if self.hooks.is_empty() || !response.status().is_success() {
+            return Ok(response);
+        }
+
+        let (mut parts, body) = response.into_parts();
+        let bytes = hyper::body::to_bytes(body).await?;
+
+        let request = Request { call, tag };
+        let response = match serde_json::from_slice::<Response>(&bytes) {
+            Ok(response) => self.hooks.on_response(&request, response).await,
+            Err(err) => Err(err.into()),
+        };
+
+        match response {
+            Ok(response) => {
+                parts.headers.remove(CONTENT_LENGTH);
+                Ok(hyper::Response::from_parts(
+                    parts,
+                    Body::from(serde_json::to_vec(&response).unwrap()),
+                ))
+            }
+            Err(err) => Ok(self.filter_error(tag, err.into(), user).into()),
+        }
+    }
+
+    /// Send an RPC request to the upstream, sharing the response of identical torrent-get calls
+    /// in flight. Returns true along with the response if it was shared by another call.
+    async fn send_rpc_request(
+        &self,
+        req: hyper::Request<Body>,
+        request: Option<&Request>,
+    ) -> Result<(hyper::Response<Body>, Bytes, bool), hyper::Error> {
+        let flight = match (&self.coalescer, request) {
+            (
+                Some(coalescer),
+                Some(Request {
+                    call: call @ MethodCall::TorrentGet { .. },
+                    ..
+                }),
+            ) => {
+                let key = (
+                    self.active_upstream(),
+                    req.headers().get(AUTHORIZATION).cloned(),
+                    req.headers().get(SESSION_ID_HEADER).cloned(),
+                    serde_json::to_string(call).unwrap(),
+                );
+                Some(coalescer.join(key))
+            }
+            _ => None,
+        };
+
+        let leader = match flight {
+            Some(Flight::Follower(slot)) => {
+                if let Some(shared) = Coalescer::wait(slot).await {
+                    debug!("shared the response of an identical torrent-get call");
+                    return Ok((shared.response(), shared.body.clone(), true));
+                }
+
+                // The first call failed, try on our own
+                None
+            }
+            Some(Flight::Leader(leader)) => Some(leader),
+            None => None,
+        };
+
+        let start = Instant::now();
+        let mut response = self.client.request(req).await?;
+        let bytes = hyper::body::to_bytes(response.body_mut()).await?;
+
+        // Calls answered with a session id challenge never reached the daemon
+        if let Some(request) = request.filter(|_| response.status() != StatusCode::CONFLICT) {
+            self.record_latency(&request.call, start);
+        }
+
+        if let Some(leader) = leader {
+            leader.share(SharedResponse {
+                status: response.status(),
+                headers: response.headers().clone(),
+                body: bytes.clone(),
+            });
+        }
+
+        Ok((response, bytes, false))
+    }
+
+    /// Decode a compressed request body, since the upstream only takes plain JSON
+    fn decode_body(
+        &self,
+        req: &mut hyper::Request<Body>,
+        body: Bytes,
+    ) -> Result<Bytes, FilterError> {
+        let (Some(max_size), Some(encoding)) = (
+            self.max_decoded_size,
+            req.headers()
+                .get(CONTENT_ENCODING)
+                .and_then(|value| value.to_str().ok()),
+        ) else {
+            return Ok(body);
+        };
+
+        match compression::decode_request(encoding, &body, max_size) {
+            None => Ok(body),
+            Some(Ok(decoded)) => {
+                debug!(%encoding, size = decoded.len(), "decoded request body");
+                req.headers_mut().remove(CONTENT_ENCODING);
+                req.headers_mut().remove(CONTENT_LENGTH);
+                Ok(decoded.into())
+            }
+            Some(Err(err)) => {
+                warn!(%err, %encoding, "could not decode request body");
+                Err(FilterError {
+                    tag: None,
+                    kind: FilterErrorKind::ParseBody,
+                })
+            }
+        }
     }
 
     async fn forward_rpc_request_acl(
         &self,
         mut req: hyper::Request<Body>,
         acl: &Acl,
+        caller: &server::RequestContext,
     ) -> Result<hyper::Response<Body>, hyper::Error> {
+        let user = &caller.user;
+
         // Parse the request body
         let req_body_bytes = hyper::body::to_bytes(req.body_mut()).await?;
+        let req_body_bytes = match self.decode_body(&mut req, req_body_bytes) {
+            Ok(bytes) => bytes,
+            Err(err) => return Ok(err.into()),
+        };
         *req.body_mut() = Body::from(req_body_bytes.clone());
 
-        let request = if acl.is_nop() {
-            // Nothing to filter here
+        let profile = self.client_profiles.find(
+            req.headers()
+                .get(USER_AGENT)
+                .map(|user_agent| user_agent.as_bytes()),
+        );
+        if let Some(profile) = profile {
+            if !profile
+                .allows_call(user.username().unwrap_or_default(), &self.state)
+                .await
+            {
+                debug!(client = %profile.name, "client went over its rate limit");
+                let tag = serde_json::from_slice::<MethodPeek>(&req_body_bytes)
+                    .ok()
+                    .and_then(|peek| peek.tag);
+                return Ok(self
+                    .filter_error(tag, FilterErrorKind::RateLimited, user)
+                    .into());
+            }
+        }
+
+        // Wait for our turn if the upstream is busy
+        let _permit = if let Some(scheduler) = &self.scheduler {
+            let peek = serde_json::from_slice::<MethodPeek>(&req_body_bytes).ok();
+            let priority = peek.as_ref().map_or(Priority::Interactive, |peek| {
+                scheduler.priority(peek.method)
+            });
+
+            match scheduler.acquire(priority).await {
+                Some(permit) => Some(permit),
+                None => {
+                    debug!(?priority, "upstream queue is full");
+                    let tag = peek.and_then(|peek| peek.tag);
+                    return Ok(self.filter_error(tag, FilterErrorKind::Busy, user).into());
+                }
+            }
+        } else {
+            None
+        };
+
+        // Port tests may be served from cache
+        if let Some(cache) = &self.port_test {
+            if let Ok(MethodPeek {
+                method: MethodName::PortTest,
+                tag,
+            }) = serde_json::from_slice(&req_body_bytes)
+            {
+                return self.cached_port_test(req, tag, acl, user, cache).await;
+            }
+        }
+
+        // Session statistics may be served from cache
+        if let Some(cache) = &self.session_stats {
+            if let Ok(MethodPeek {
+                method: MethodName::SessionStats,
+                tag,
+            }) = serde_json::from_slice(&req_body_bytes)
+            {
+                return self.cached_session_stats(req, tag, acl, user, cache).await;
+            }
+        }
+
+        let mut translation = Translation::default();
+        let request = if acl.is_nop()
+            && self.hooks.is_empty()
+            && !self.owner_labels.enabled
+            && !self.compat
+            && !self.fetches_torrent_urls()
+            && !profile.map_or(false, ClientProfile::rewrites_requests)
+        {
+            // Nothing to filter here, besides dangerous methods
+            if !acl.allow_dangerous_methods {
+                if let Ok(peek) = serde_json::from_slice::<MethodPeek>(&req_body_bytes) {
+                    if let Err(kind) = check_method(peek.method, acl, user) {
+                        return Ok(self.filter_error(peek.tag, kind, user).into());
+                    }
+                }
+            }
+
             None
         } else {
             Some(match serde_json::from_slice::<Request>(&req_body_bytes) {
-                Ok(rpc_request) => {
+                Ok(mut rpc_request) => {
+                    if let Some(profile) = profile {
+                        profile.apply(&mut rpc_request);
+                    }
+
                     // Check that torrent add respects the download dir
-                    match self.filter_request(rpc_request, acl, &req).await {
-                        Ok(request) => {
+                    match self.filter_request(rpc_request, acl, caller, &req).await {
+                        Ok(mut request) => {
+                            // Adapt the request to the upstream version
+                            translation = match self.translate_request(&mut request, &req).await {
+                                Ok(translation) => translation,
+                                Err(kind) => {
+                                    return Ok(FilterError {
+                                        tag: request.tag,
+                                        kind,
+                                    }
+                                    .into());
+                                }
+                            };
+
                             // Replace body
                             *req.body_mut() = Body::from(serde_json::to_string(&request).unwrap());
                             req.headers_mut().remove(CONTENT_LENGTH);
@@ -587,25 +1319,88 @@ impl RpcProxyClient {
             })
         };
 
+        // Explaining a request: return what would be forwarded
+        if explain::is_dry_run() {
+            return Ok(hyper::Response::new(req.into_body()));
+        }
+
+        // Report the quota of the user instead of the filesystem free space
+        let remaining_quota = match self
+            .remaining_quota(request.as_ref(), acl, user, &req)
+            .await
+        {
+            Ok(remaining) => remaining,
+            Err(kind) => {
+                return Ok(FilterError {
+                    tag: request.as_ref().and_then(|request| request.tag),
+                    kind,
+                }
+                .into());
+            }
+        };
+
+        // Compare some read-only calls with the mirror
+        let mirrored = self.mirror.as_ref().and_then(|mirror| {
+            let (method, body) = mirror.sample(request.as_ref(), &req_body_bytes)?;
+            Some((
+                mirror.clone(),
+                upstream_url(mirror.upstream(), req.uri()),
+                method,
+                body,
+            ))
+        });
+
         // Fetch response
-        let mut response = self.client.request(req).await?;
-        debug!(?response);
+        // Polling clients may already have the filtered torrent list
+        let if_none_match = req.headers_mut().remove(IF_NONE_MATCH);
+        let mut etag = None;
 
-        // Decode the response body
-        let mut bytes = hyper::body::to_bytes(response.body_mut()).await?.to_vec();
+        let (response, bytes, shared) = self.send_rpc_request(req, request.as_ref()).await?;
+        debug!(?response, shared);
+        let mut bytes = bytes.to_vec();
 
         // HTTP 409 is used by transmission to exchange session keys
         if response.status() != 409 {
+            if let Some((mirror, uri, method, body)) = mirrored {
+                let primary = bytes.clone();
+                tokio::spawn(async move { mirror.compare(uri, method, body, primary).await });
+            }
+
             // Perform replacements in RPC response
-            if let Ok(rpc_response) = serde_json::from_slice(&bytes).map_err(|err| {
-                error!(?err);
-            }) {
+            if let Ok(mut rpc_response) =
+                serde_json::from_slice::<RawResponse>(&bytes).map_err(|err| {
+                    error!(?err);
+                })
+            {
+                if let Some(arguments) = rpc_response.arguments.as_mut() {
+                    translation.apply(arguments);
+                }
+
+                // Shared responses carry the tag of another call
+                if shared {
+                    rpc_response.tag = request.as_ref().and_then(|request| request.tag);
+                }
+
                 // Only filter response if we had to filter the request as well
                 if let Some(request) = request {
                     let response;
                     bytes = serde_json::to_string(
-                        match self.filter_response(&request, rpc_response, acl) {
-                            Ok(resp) => {
+                        match self
+                            .filter_response(&request, rpc_response, acl, caller)
+                            .await
+                        {
+                            Ok(mut resp) => {
+                                if let Some(remaining) = remaining_quota {
+                                    apply_quota(
+                                        &mut resp,
+                                        remaining,
+                                        acl.quota.unwrap_or_default(),
+                                    );
+                                }
+
+                                etag = torrent_get_etag(&request, &resp, user).filter(|_| {
+                                    !profile.map_or(false, |profile| profile.disable_etag)
+                                });
                                 response = resp;
                                 &response
                             }
@@ -623,27 +1418,199 @@ impl RpcProxyClient {
         // Replace response body and return response
         let (mut parts, _) = response.into_parts();
         parts.headers.remove(CONTENT_LENGTH);
+
+        if let Some(etag) = etag {
+            let matches = if_none_match
+                .as_ref()
+                .and_then(|value| value.to_str().ok())
+                .map_or(false, |value| {
+                    value.split(',').any(|tag| {
+                        let tag = tag.trim();
+                        tag == "*" || tag.trim_start_matches("W/").as_bytes() == etag.as_bytes()
+                    })
+                });
+
+            if matches {
+                explain::record("etag", true, || "not modified".to_owned());
+                return Ok(hyper::Response::builder()
+                    .status(StatusCode::NOT_MODIFIED)
+                    .header(ETAG, etag)
+                    .body(Body::empty())
+                    .unwrap());
+            }
+
+            parts.headers.insert(ETAG, etag);
+        }
+
         Ok(hyper::Response::from_parts(parts, Body::from(bytes)))
     }
 
     pub async fn handle_request(
         &self,
         mut req: hyper::Request<Body>,
-        acl: Option<&Acl>,
+        caller: &server::RequestContext,
     ) -> Result<hyper::Response<Body>, hyper::Error> {
+        let user = &caller.user;
+        let acl = caller.acl();
+
         // Update target url
         *req.uri_mut() = self.get_upstream_url(&req.extensions().get::<OriginalUri>().unwrap().0);
         req.headers_mut().remove(HOST);
+        self.upstream_auth.apply(&mut req, Some(user));
+
+        let mut response = if req.uri().path().ends_with("/rpc") {
+            // Run unmatched requests through the default ACL, which only denies dangerous methods
+            let default_acl = Acl::default();
+            let acl = acl.unwrap_or(&default_acl);
 
-        if req.uri().path().ends_with("/rpc") {
-            if let Some(acl) = acl {
+            #[cfg(feature = "chaos")]
+            if let Some(fault) = self.chaos.inject().await {
+                return Ok(fault.response());
+            }
+
+            if self.takes_fast_path(acl, &req) {
+                debug!("forwarding rpc call untouched");
+
+                // The method of calls forwarded untouched is unknown, they are tracked together
+                let start = Instant::now();
+                let response = self.client.request(req).await?;
+                if let Some(latency) = &self.latency {
+                    latency.record(FAST_PATH_METHOD, start.elapsed(), None);
+                }
+                response
+            } else if let Some(recorder) = &self.recorder {
+                self.record_rpc_request(req, acl, caller, recorder).await?
+            } else {
                 // We don't accept gzip to simplify things for rpc mapping
                 req.headers_mut().remove(ACCEPT_ENCODING);
 
-                return self.forward_rpc_request_acl(req, acl).await;
+                self.forward_rpc_request_acl(req, acl, caller).await?
+            }
+        } else {
+            let _permit = match &self.scheduler {
+                Some(scheduler) => match scheduler.acquire(Priority::Interactive).await {
+                    Some(permit) => Some(permit),
+                    None => {
+                        return Ok(hyper::Response::builder()
+                            .status(StatusCode::SERVICE_UNAVAILABLE)
+                            .body(Body::from(FilterErrorKind::Busy.to_string()))
+                            .unwrap())
+                    }
+                },
+                None => None,
+            };
+
+            self.client.request(req).await?
+        };
+
+        self.rewrite_locations(response.headers_mut());
+        Ok(response)
+    }
+
+    /// Point the URLs of upstream redirects to the public URL of the proxy
+    fn rewrite_locations(&self, headers: &mut HeaderMap) {
+        for name in [LOCATION, CONTENT_LOCATION] {
+            let Some(location) = headers
+                .get(&name)
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| self.public_location(value))
+            else {
+                continue;
+            };
+
+            debug!(header = %name, %location, "rewrote upstream location");
+            if let Ok(value) = HeaderValue::try_from(location) {
+                headers.insert(name, value);
+            }
+        }
+    }
+
+    /// Public equivalent of a location set by the upstream, if it needs rewriting
+    fn public_location(&self, location: &str) -> Option<String> {
+        let uri: Uri = location.parse().ok()?;
+
+        // Absolute URLs to other hosts are left alone
+        let absolute = uri.authority().is_some();
+        if absolute {
+            if !self.upstreams.iter().any(|upstream| {
+                upstream.scheme() == uri.scheme() && upstream.authority() == uri.authority()
+            }) {
+                return None;
+            }
+        } else if !location.starts_with('/') || location.starts_with("//") {
+            return None;
+        }
+
+        // Map the bind path to the public path
+        let path_and_query = uri.path_and_query().map_or("/", |pq| pq.as_str());
+        let public_path = self.public_url.path().trim_end_matches('/');
+        let path_and_query = match path_and_query.strip_prefix(&self.bind_path) {
+            Some(rest) if rest.is_empty() || rest.starts_with(['/', '?']) => {
+                Cow::Owned(public_path.to_owned() + rest)
             }
+            _ => Cow::Borrowed(path_and_query),
+        };
+
+        if absolute {
+            Some(format!(
+                "{}://{}{}",
+                self.public_url.scheme_str().unwrap_or("http"),
+                self.public_url.authority()?,
+                path_and_query
+            ))
+        } else if path_and_query != location {
+            Some(path_and_query.into_owned())
+        } else {
+            None
         }
+    }
 
+    /// Forward a request to another upstream as is
+    pub async fn forward(
+        &self,
+        req: hyper::Request<Body>,
+    ) -> Result<hyper::Response<Body>, hyper::Error> {
         self.client.request(req).await
     }
+
+    async fn record_rpc_request(
+        &self,
+        mut req: hyper::Request<Body>,
+        acl: &Acl,
+        caller: &server::RequestContext,
+        recorder: &Recorder,
+    ) -> Result<hyper::Response<Body>, hyper::Error> {
+        req.headers_mut().remove(ACCEPT_ENCODING);
+
+        let user_agent = req
+            .headers()
+            .get(USER_AGENT)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_owned);
+
+        let req_body_bytes = hyper::body::to_bytes(req.body_mut()).await?;
+        let req_body_bytes = match self.decode_body(&mut req, req_body_bytes) {
+            Ok(bytes) => bytes,
+            Err(err) => return Ok(err.into()),
+        };
+        *req.body_mut() = Body::from(req_body_bytes.clone());
+
+        let (parts, body) = self
+            .forward_rpc_request_acl(req, acl, caller)
+            .await?
+            .into_parts();
+        let res_body_bytes = hyper::body::to_bytes(body).await?;
+
+        recorder.record(
+            user_agent.as_deref(),
+            &req_body_bytes,
+            parts.status,
+            &res_body_bytes,
+        );
+
+        Ok(hyper::Response::from_parts(
+            parts,
+            Body::from(res_body_bytes),
+        ))
+    }
 }
@@ -0,0 +1,194 @@
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+};
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tokio::sync::oneshot;
+
+use crate::rpc::MethodName;
+
+fn default_concurrency() -> usize {
+    4
+}
+
+fn default_interactive_queue() -> usize {
+    64
+}
+
+fn default_background_queue() -> usize {
+    16
+}
+
+fn default_background_methods() -> Vec<MethodName> {
+    vec![
+        MethodName::BlocklistUpdate,
+        MethodName::PortTest,
+        MethodName::TorrentSet,
+        MethodName::TorrentSetLocation,
+        MethodName::TorrentRenamePath,
+        MethodName::TorrentVerify,
+        MethodName::QueueMoveTop,
+        MethodName::QueueMoveUp,
+        MethodName::QueueMoveDown,
+        MethodName::QueueMoveBottom,
+    ]
+}
+
+/// Limits concurrent upstream requests, serving interactive ones first when the upstream is busy
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct SchedulerConfig {
+    /// Queue requests to the upstream
+    #[serde(default)]
+    pub enabled: bool,
+
+    /// Number of requests the upstream handles at once
+    #[serde(default = "default_concurrency")]
+    pub concurrency: usize,
+
+    /// Number of interactive requests which can wait for the upstream, others are refused
+    #[serde(default = "default_interactive_queue")]
+    pub interactive_queue: usize,
+
+    /// Number of background requests which can wait for the upstream, others are refused
+    #[serde(default = "default_background_queue")]
+    pub background_queue: usize,
+
+    /// RPC methods which only run once no interactive request is waiting
+    #[serde(default = "default_background_methods")]
+    pub background_methods: Vec<MethodName>,
+}
+
+impl Default for SchedulerConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            concurrency: default_concurrency(),
+            interactive_queue: default_interactive_queue(),
+            background_queue: default_background_queue(),
+            background_methods: default_background_methods(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Priority {
+    Interactive,
+    Background,
+}
+
+#[derive(Debug, Default)]
+struct Queues {
+    running: usize,
+    interactive: VecDeque<oneshot::Sender<Permit>>,
+    background: VecDeque<oneshot::Sender<Permit>>,
+}
+
+#[derive(Debug)]
+struct Inner {
+    concurrency: usize,
+    interactive_queue: usize,
+    background_queue: usize,
+    queues: Mutex<Queues>,
+}
+
+/// Hands out permits to send requests to the upstream
+#[derive(Debug)]
+pub struct Scheduler {
+    inner: Arc<Inner>,
+    background_methods: Vec<MethodName>,
+}
+
+/// Allows sending a request to the upstream until dropped
+#[derive(Debug)]
+pub struct Permit {
+    inner: Option<Arc<Inner>>,
+}
+
+impl Scheduler {
+    pub fn new(config: &SchedulerConfig) -> Option<Self> {
+        config.enabled.then(|| Self {
+            inner: Arc::new(Inner {
+                concurrency: config.concurrency.max(1),
+                interactive_queue: config.interactive_queue,
+                background_queue: config.background_queue,
+                queues: Default::default(),
+            }),
+            background_methods: config.background_methods.clone(),
+        })
+    }
+
+    pub fn priority(&self, method: MethodName) -> Priority {
+        if self.background_methods.contains(&method) {
+            Priority::Background
+        } else {
+            Priority::Interactive
+        }
+    }
+
+    /// Wait for the upstream to be available, or return None if too many requests are waiting
+    pub async fn acquire(&self, priority: Priority) -> Option<Permit> {
+        let receiver = {
+            let mut queues = self.inner.queues.lock().unwrap();
+
+            if queues.running < self.inner.concurrency {
+                queues.running += 1;
+                return Some(Permit {
+                    inner: Some(self.inner.clone()),
+                });
+            }
+
+            let (queue, depth) = match priority {
+                Priority::Interactive => (&mut queues.interactive, self.inner.interactive_queue),
+                Priority::Background => (&mut queues.background, self.inner.background_queue),
+            };
+
+            // Forget about requests which were abandoned while waiting
+            queue.retain(|sender| !sender.is_closed());
+            if queue.len() >= depth {
+                return None;
+            }
+
+            let (sender, receiver) = oneshot::channel();
+            queue.push_back(sender);
+            receiver
+        };
+
+        receiver.await.ok()
+    }
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        let Some(inner) = self.inner.take() else {
+            return;
+        };
+
+        let mut queues = inner.queues.lock().unwrap();
+        let mut permit = Permit {
+            inner: Some(inner.clone()),
+        };
+
+        // Hand the permit over to the next waiting request, interactive ones first
+        while let Some(sender) = queues
+            .interactive
+            .pop_front()
+            .or_else(|| queues.background.pop_front())
+        {
+            match sender.send(permit) {
+                Ok(()) => return,
+                Err(mut unsent) => {
+                    permit = Permit {
+                        inner: unsent.inner.take(),
+                    }
+                }
+            }
+        }
+
+        // Nobody is waiting
+        permit.inner = None;
+        queues.running -= 1;
+    }
+}
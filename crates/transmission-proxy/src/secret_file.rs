@@ -0,0 +1,168 @@
+//! Secrets read from files, e.g. Kubernetes secrets mounted as volumes
+//!
+//! Files are read again when their modification time changes, so rotated secrets are picked up
+//! without restarting the proxy.
+
+use std::{
+    fmt,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::SystemTime,
+};
+
+use color_eyre::eyre;
+use schemars::JsonSchema;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use tracing::{info, warn};
+
+/// Value which can be read from a secret file
+pub trait SecretValue: Sized {
+    fn parse(text: &str) -> eyre::Result<Self>;
+}
+
+impl SecretValue for String {
+    /// Secrets are written by tools which may add a trailing newline
+    fn parse(text: &str) -> eyre::Result<Self> {
+        let secret = text.trim_end_matches(['\r', '\n']);
+        if secret.is_empty() {
+            eyre::bail!("the file is empty");
+        }
+
+        Ok(secret.to_owned())
+    }
+}
+
+struct Cached<T> {
+    modified: SystemTime,
+    value: Arc<T>,
+}
+
+/// File holding a secret, re-read when it changes
+pub struct SecretFile<T = String> {
+    path: PathBuf,
+    cached: Arc<Mutex<Option<Cached<T>>>>,
+}
+
+impl<T> SecretFile<T> {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            cached: Default::default(),
+        }
+    }
+}
+
+impl<T: SecretValue> SecretFile<T> {
+    /// Current value of the secret. If the file can't be read anymore, e.g. while it is being
+    /// rotated, the last value read is kept.
+    pub fn get(&self) -> eyre::Result<Arc<T>> {
+        let mut cached = self.cached.lock().unwrap();
+
+        let modified = std::fs::metadata(&self.path).and_then(|metadata| metadata.modified());
+        if let (Ok(modified), Some(cached)) = (&modified, &*cached) {
+            if cached.modified == *modified {
+                return Ok(cached.value.clone());
+            }
+        }
+
+        let changed = modified.as_ref().ok().copied();
+        let result = modified.map_err(eyre::Report::from).and_then(|modified| {
+            let text = std::fs::read_to_string(&self.path)?;
+            Ok((modified, T::parse(&text)?))
+        });
+
+        match (result, &mut *cached) {
+            (Ok((modified, value)), previous) => {
+                if previous.is_some() {
+                    info!(path = %self.path.display(), "secret file changed, reloading");
+                }
+
+                let value = Arc::new(value);
+                *previous = Some(Cached {
+                    modified,
+                    value: value.clone(),
+                });
+                Ok(value)
+            }
+            (Err(err), Some(previous)) => {
+                warn!(path = %self.path.display(), %err, "could not read secret file, keeping the previous value");
+
+                // Don't warn again until the file changes
+                if let Some(modified) = changed {
+                    previous.modified = modified;
+                }
+                Ok(previous.value.clone())
+            }
+            (Err(err), None) => Err(eyre::eyre!("{}: {err}", self.path.display())),
+        }
+    }
+}
+
+impl<T> Clone for SecretFile<T> {
+    fn clone(&self) -> Self {
+        Self {
+            path: self.path.clone(),
+            cached: self.cached.clone(),
+        }
+    }
+}
+
+impl<T> fmt::Debug for SecretFile<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("SecretFile").field(&self.path).finish()
+    }
+}
+
+impl<T> Serialize for SecretFile<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.path.serialize(serializer)
+    }
+}
+
+impl<'de, T> Deserialize<'de> for SecretFile<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        PathBuf::deserialize(deserializer).map(Self::new)
+    }
+}
+
+impl<T> JsonSchema for SecretFile<T> {
+    fn schema_name() -> String {
+        "SecretFile".to_owned()
+    }
+
+    fn json_schema(gen: &mut schemars::gen::SchemaGenerator) -> schemars::schema::Schema {
+        PathBuf::json_schema(gen)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn secrets_are_reloaded_when_the_file_changes() {
+        let path =
+            std::env::temp_dir().join(format!("transmission-proxy-secret-{}", std::process::id()));
+        std::fs::write(&path, "first\n").unwrap();
+
+        let secret = SecretFile::<String>::new(&path);
+        assert_eq!(*secret.get().unwrap(), "first");
+
+        std::fs::write(&path, "second").unwrap();
+        let file = std::fs::File::options().write(true).open(&path).unwrap();
+        file.set_modified(SystemTime::now() + std::time::Duration::from_secs(1))
+            .unwrap();
+        assert_eq!(*secret.get().unwrap(), "second");
+
+        // Missing or empty files keep the previous value
+        std::fs::write(&path, "\n").unwrap();
+        let file = std::fs::File::options().write(true).open(&path).unwrap();
+        file.set_modified(SystemTime::now() + std::time::Duration::from_secs(2))
+            .unwrap();
+        assert_eq!(*secret.get().unwrap(), "second");
+
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(*secret.get().unwrap(), "second");
+        assert!(SecretFile::<String>::new(&path).get().is_err());
+    }
+}
@@ -0,0 +1,171 @@
+//! Secret key for signing session cookies, when none is specified
+//!
+//! A changed key invalidates all sessions, so the proxy remembers a fingerprint of the key it
+//! used and warns when it starts with another one.
+
+use std::{io::Write, path::Path};
+
+use color_eyre::eyre;
+use hmac::Mac;
+use rand::Rng;
+use tracing::{info, warn};
+
+use crate::{server::JwtKey, storage::Storage, torrent::hex, Args};
+
+/// Where the secret key comes from when none is specified
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum GeneratedKey {
+    /// A new random key on each start, which signs out all users
+    #[default]
+    Random,
+    /// A random key saved to the generated key file, and read from it on the next starts
+    Persist,
+    /// A key derived from the machine id, stable as long as the proxy runs on the same host
+    MachineId,
+}
+
+/// Storage namespace for the fingerprint of the secret key
+pub const NAMESPACE: &str = "secret_key";
+
+/// Files holding the machine id, for systemd and older dbus setups
+const MACHINE_ID_PATHS: &[&str] = &["/etc/machine-id", "/var/lib/dbus/machine-id"];
+
+fn random_key() -> String {
+    const LEN: usize = 32;
+    let mut rng = rand::thread_rng();
+    (0..LEN).map(|_| rng.gen_range('0'..'z')).collect()
+}
+
+/// Read the persisted key, or generate and persist a new one
+fn persisted_key(path: &Path) -> eyre::Result<String> {
+    match std::fs::read_to_string(path) {
+        Ok(key) if !key.trim().is_empty() => return Ok(key.trim().to_owned()),
+        Ok(_) => {}
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+        Err(err) => eyre::bail!("could not read {}: {err}", path.display()),
+    }
+
+    let key = random_key();
+
+    let mut options = std::fs::File::options();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    options
+        .open(path)
+        .and_then(|mut file| writeln!(file, "{key}"))
+        .map_err(|err| eyre::eyre!("could not write {}: {err}", path.display()))?;
+
+    warn!(path = %path.display(), "generated secret key saved to a file, keep it private");
+    Ok(key)
+}
+
+/// Derive a key from the machine id. The id is hashed so it isn't used as is, as recommended
+/// by machine-id(5), but anyone who can read it can sign sessions.
+fn machine_key() -> eyre::Result<String> {
+    let machine_id = MACHINE_ID_PATHS
+        .iter()
+        .find_map(|path| std::fs::read_to_string(path).ok())
+        .map(|id| id.trim().to_owned())
+        .filter(|id| !id.is_empty())
+        .ok_or_else(|| eyre::eyre!("could not read the machine id"))?;
+
+    let mut mac = JwtKey::new_from_slice(machine_id.as_bytes()).unwrap();
+    mac.update(b"transmission-proxy secret key");
+
+    warn!("secret key derived from the machine id, local users can sign sessions with it");
+    Ok(hex(&mac.finalize().into_bytes()))
+}
+
+/// Set the secret key of the arguments if none was specified
+pub fn generate(args: &mut Args) -> eyre::Result<()> {
+    if !args.secret_key.is_empty() || args.secret_key_file.is_some() {
+        return Ok(());
+    }
+
+    args.secret_key = match args.generated_key {
+        GeneratedKey::Random => {
+            warn!("generated secret key because none was specified, users will be signed out on restart");
+            random_key()
+        }
+        GeneratedKey::Persist => persisted_key(&args.generated_key_file)?,
+        GeneratedKey::MachineId => machine_key()?,
+    };
+
+    Ok(())
+}
+
+/// Fingerprint of a key, which doesn't reveal it
+fn fingerprint(key: &JwtKey) -> String {
+    let mut mac = key.clone();
+    mac.update(b"fingerprint");
+    hex(&mac.finalize().into_bytes()[..8])
+}
+
+/// Remember the fingerprint of the key, returning true and warning if it changed since the last
+/// start
+pub fn check_fingerprint(key: &JwtKey, storage: &dyn Storage) -> bool {
+    let fingerprint = fingerprint(key);
+
+    let changed = match storage.get_json::<String>(NAMESPACE, "fingerprint") {
+        Ok(Some(previous)) if previous == fingerprint => return false,
+        Ok(Some(_)) => {
+            warn!("the secret key changed since the last start, all sessions were invalidated");
+            true
+        }
+        Ok(None) => {
+            info!("remembering the fingerprint of the secret key");
+            false
+        }
+        Err(err) => {
+            warn!(%err, "could not read the fingerprint of the secret key");
+            false
+        }
+    };
+
+    if let Err(err) = storage.put_json(NAMESPACE, "fingerprint", &fingerprint) {
+        warn!(%err, "could not save the fingerprint of the secret key");
+    }
+
+    changed
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::storage::MemoryStorage;
+
+    use super::*;
+
+    #[test]
+    fn persisted_keys_are_reused() {
+        let path = std::env::temp_dir().join(format!(
+            "transmission-proxy-generated-key-{}",
+            std::process::id()
+        ));
+
+        let key = persisted_key(&path).unwrap();
+        assert_eq!(persisted_key(&path).unwrap(), key);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+
+        std::fs::remove_file(&path).unwrap();
+        assert_ne!(persisted_key(&path).unwrap(), key);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn key_changes_are_detected() {
+        let storage = MemoryStorage::default();
+        let first = JwtKey::new_from_slice(b"first").unwrap();
+        let second = JwtKey::new_from_slice(b"second").unwrap();
+
+        assert!(!check_fingerprint(&first, &storage));
+        assert!(!check_fingerprint(&first, &storage));
+        assert!(check_fingerprint(&second, &storage));
+        assert!(!check_fingerprint(&second, &storage));
+    }
+}
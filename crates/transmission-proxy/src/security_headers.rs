@@ -0,0 +1,147 @@
+use std::collections::BTreeMap;
+
+use color_eyre::eyre;
+use hyper::{
+    header::{HeaderName, HeaderValue, CONTENT_SECURITY_POLICY, STRICT_TRANSPORT_SECURITY},
+    HeaderMap,
+};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_headers() -> BTreeMap<String, String> {
+    [
+        (
+            "Content-Security-Policy",
+            "default-src 'self'; style-src 'self' 'unsafe-inline'; img-src 'self' data:; \
+             frame-ancestors 'none'",
+        ),
+        ("Referrer-Policy", "same-origin"),
+        ("X-Content-Type-Options", "nosniff"),
+        ("X-Frame-Options", "DENY"),
+    ]
+    .into_iter()
+    .map(|(name, value)| (name.to_owned(), value.to_owned()))
+    .collect()
+}
+
+fn default_hsts() -> String {
+    "max-age=31536000".into()
+}
+
+/// Headers for the paths starting with a given prefix
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct PathHeaders {
+    /// Path prefix, e.g. `/transmission/web/`
+    pub prefix: String,
+
+    /// Headers to set, overriding the defaults. An empty value removes the header.
+    pub headers: BTreeMap<String, String>,
+}
+
+/// Security headers added to the responses served by the proxy
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct SecurityHeaders {
+    /// Add security headers to responses
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+
+    /// Headers set on all responses
+    #[serde(default = "default_headers")]
+    pub headers: BTreeMap<String, String>,
+
+    /// Strict-Transport-Security value, used when the public URL is served over https
+    #[serde(default = "default_hsts")]
+    pub hsts: String,
+
+    /// Apply the default Content-Security-Policy to the proxied web UI. The web UI relies on
+    /// inline scripts, so it is only sent there when enabled or set for a matching path.
+    #[serde(default)]
+    pub web_csp: bool,
+
+    /// Headers for specific paths, applied in order
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub paths: Vec<PathHeaders>,
+}
+
+impl Default for SecurityHeaders {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            headers: default_headers(),
+            hsts: default_hsts(),
+            web_csp: false,
+            paths: Vec::new(),
+        }
+    }
+}
+
+impl SecurityHeaders {
+    fn all_headers(&self) -> impl Iterator<Item = (&String, &String)> {
+        self.headers
+            .iter()
+            .chain(self.paths.iter().flat_map(|path| path.headers.iter()))
+    }
+
+    /// Check that all header names and values are valid
+    pub fn validate(&self) -> eyre::Result<()> {
+        for (name, value) in self.all_headers() {
+            HeaderName::try_from(name.as_str())
+                .map_err(|err| eyre::eyre!("invalid header name {name}: {err}"))?;
+            HeaderValue::try_from(value.as_str())
+                .map_err(|err| eyre::eyre!("invalid value for header {name}: {err}"))?;
+        }
+
+        HeaderValue::try_from(self.hsts.as_str())
+            .map_err(|err| eyre::eyre!("invalid hsts value: {err}"))?;
+
+        Ok(())
+    }
+
+    fn set(headers: &mut HeaderMap, name: &str, value: &str) {
+        let Ok(name) = HeaderName::try_from(name) else {
+            return;
+        };
+
+        if value.is_empty() {
+            headers.remove(name);
+        } else if let Ok(value) = HeaderValue::try_from(value) {
+            headers.insert(name, value);
+        }
+    }
+
+    /// Add the security headers for the given request path to a response
+    pub fn apply(&self, path: &str, web_path: &str, https: bool, headers: &mut HeaderMap) {
+        if !self.enabled {
+            return;
+        }
+
+        if https && !self.hsts.is_empty() {
+            Self::set(headers, STRICT_TRANSPORT_SECURITY.as_str(), &self.hsts);
+        }
+
+        let web_ui = !self.web_csp && path.starts_with(web_path);
+        for (name, value) in &self.headers {
+            if web_ui && name.eq_ignore_ascii_case(CONTENT_SECURITY_POLICY.as_str()) {
+                continue;
+            }
+
+            Self::set(headers, name, value);
+        }
+
+        for path_headers in self
+            .paths
+            .iter()
+            .filter(|path_headers| path.starts_with(&path_headers.prefix))
+        {
+            for (name, value) in &path_headers.headers {
+                Self::set(headers, name, value);
+            }
+        }
+    }
+}
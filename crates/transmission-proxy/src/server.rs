@@ -1,53 +1,421 @@
-use std::sync::Arc;
+use std::sync::{atomic::AtomicU64, Arc};
 
-use axum::{routing, Extension, Router};
+use axum::{handler::Handler, middleware, routing, Extension, Router};
 use color_eyre::eyre;
 
 use hmac::Mac;
-use hyper::Server;
 
 use tower_cookies::CookieManagerLayer;
-use tracing::{info, span, Instrument, Level};
+use tracing::{info, span, warn, Instrument, Level};
 
-use crate::{config::Config, error::Error, rpc::proxy::RpcProxyClient, Args};
+use crate::{
+    auth::AuthUser,
+    config::Config,
+    error::Error,
+    events::{self, AuditTail, EventBus},
+    maintenance::Maintenance,
+    notifications::Notifier,
+    provisioning::ProvisionedAcls,
+    rpc::proxy::RpcProxyClient,
+    secret_file::SecretFile,
+    secret_key,
+    state::SharedState,
+    storage::Storage,
+    torrent_reports,
+    tracker_stats::TrackerStatsCollector,
+    uploads::Uploads,
+    Args,
+};
 
+mod api_tokens;
 mod auth;
+#[cfg(feature = "chaos")]
+mod chaos;
+mod context;
+mod export;
+mod legacy_paths;
+#[cfg(feature = "oauth")]
 mod oauth;
+mod owners;
 mod routes;
+mod sessions;
+mod shares;
+mod torrent_stream;
 mod views;
+#[cfg(feature = "webauthn")]
+mod webauthn;
+
+pub use context::RequestContext;
 use views::Views;
 
 use self::routes::Paths;
 
 pub type JwtKey = hmac::Hmac<sha2::Sha256>;
 
+/// Secret key for signing JWTs
+enum SecretKey {
+    Static(JwtKey),
+    /// Read again when the file changes, which invalidates existing sessions
+    File(SecretFile),
+}
+
 struct Ctx {
     args: Args,
     config: Config,
     client: RpcProxyClient,
-    jwt_key: JwtKey,
+    /// Client for outbound requests, other than to the upstream
+    #[cfg(feature = "oauth")]
+    http_client: reqwest::Client,
+    secret_key: SecretKey,
     views: Views,
     paths: Paths,
+    /// State shared between replicas
+    #[cfg(any(feature = "oauth", feature = "webauthn"))]
+    state: Arc<SharedState>,
+    /// Data persisted across restarts
+    storage: Arc<dyn Storage>,
+    /// ACLs created for first-time OAuth2 users
+    provisioned: ProvisionedAcls,
+    maintenance: Maintenance,
+    tracker_stats: TrackerStatsCollector,
+    uploads: Uploads,
+    notifier: Arc<Notifier>,
+    /// Events of the proxy, for cross-cutting concerns
+    events: EventBus,
+    /// Latest events of the audit log
+    audit: AuditTail,
+    /// Identity of the proxy for passkeys
+    #[cfg(feature = "webauthn")]
+    relying_party: crate::webauthn::RelyingParty,
+    /// Identifier of the next request
+    next_request_id: AtomicU64,
 }
 
 impl Ctx {
-    pub fn new(args: Args, config: Config) -> Self {
+    pub fn new(args: Args, mut config: Config) -> eyre::Result<Self> {
+        if args.lenient_config {
+            let mut skipped = config.acl.deny_invalid(&config.tracker_rule_sets);
+            config.routes.retain(|route| match route.validate() {
+                Ok(()) => true,
+                Err(err) => {
+                    skipped.push(format!("route {}: {err}", route.prefix));
+                    false
+                }
+            });
+
+            for problem in &skipped {
+                warn!(%problem, "skipped invalid configuration");
+            }
+            config.skipped.extend(skipped);
+        }
+
+        config
+            .acl
+            .resolve_tracker_rules(&config.tracker_rule_sets)?;
+
         let views = Views::new();
-        let jwt_key = JwtKey::new_from_slice(args.secret_key.as_bytes()).unwrap();
+        let secret_key = match &args.secret_key_file {
+            Some(path) => {
+                let file = SecretFile::new(path);
+                file.get()?;
+                SecretKey::File(file)
+            }
+            None => SecretKey::Static(JwtKey::new_from_slice(args.secret_key.as_bytes()).unwrap()),
+        };
         let paths = Paths::new(&args);
+        let state = Arc::new(SharedState::new(
+            config.state.as_deref(),
+            config.state_namespace.as_deref(),
+        )?);
+        let storage = config.storage.open()?;
+        if let SecretKey::Static(key) = &secret_key {
+            secret_key::check_fingerprint(key, &*storage);
+        }
+        let provisioned = ProvisionedAcls::load(&config.acl, &config.tracker_rule_sets, &*storage)?;
+        let events = EventBus::default();
+        let client = RpcProxyClient::new(&args, &config, state.clone(), events.clone())?;
+        #[cfg(feature = "oauth")]
+        let http_client = config.http_client.build()?;
+        #[cfg(not(feature = "oauth"))]
+        if config
+            .providers
+            .oauth2
+            .iter()
+            .any(|provider| provider.enabled)
+        {
+            eyre::bail!(
+                "OAuth2 providers are not supported by this build, enable the oauth feature"
+            );
+        }
+        #[cfg(feature = "webauthn")]
+        let relying_party =
+            webauthn::relying_party(&config.providers.webauthn, &args.public_url())?;
+        #[cfg(not(feature = "webauthn"))]
+        if config.providers.webauthn.enabled {
+            eyre::bail!(
+                "the webauthn provider is not supported by this build, enable the webauthn feature"
+            );
+        }
+        config.acl.validate()?;
+        config.users.validate(&config.acl)?;
+        config.providers.basic.validate()?;
+        for provider in config.providers.oauth2.iter().filter(|p| p.enabled) {
+            provider.validate()?;
+        }
+        let oauth2_providers: Vec<_> = config
+            .providers
+            .oauth2
+            .iter()
+            .map(|p| p.name.as_str())
+            .collect();
+        config.acl.provisioning.validate(
+            &config.acl,
+            &config.tracker_rule_sets,
+            &oauth2_providers,
+        )?;
+        config.security_headers.validate()?;
+        config.headers.validate()?;
+        config.upstream_auth.validate()?;
+        for route in &config.routes {
+            route.validate()?;
+        }
+        let maintenance = Maintenance::new(&config.maintenance, state.clone());
+        let uploads = Uploads::new(&config.uploads);
+        let notifier = Arc::new(Notifier::new(&config.notifications, &config.http_client)?);
 
-        let upstream = args.upstream.clone();
-        Self {
+        Ok(Self {
             args,
             config,
-            client: RpcProxyClient::new(upstream),
-            jwt_key,
+            client,
+            #[cfg(feature = "oauth")]
+            http_client,
+            secret_key,
             views,
             paths,
+            #[cfg(any(feature = "oauth", feature = "webauthn"))]
+            state,
+            storage,
+            provisioned,
+            maintenance,
+            tracker_stats: Default::default(),
+            uploads,
+            notifier,
+            events,
+            audit: Default::default(),
+            #[cfg(feature = "webauthn")]
+            relying_party,
+            next_request_id: Default::default(),
+        })
+    }
+
+    /// Create the ACL of a user logging in for the first time, if provisioning applies to them
+    #[cfg_attr(not(feature = "oauth"), allow(dead_code))]
+    fn provision(&self, user: &AuthUser) {
+        let username = user.username().unwrap_or_default();
+        match self.provisioned.provision(
+            &self.config.acl,
+            &self.config.tracker_rule_sets,
+            &*self.storage,
+            user,
+        ) {
+            Ok(Some(acl)) => {
+                let acl = self.config.acl.name_of(&acl).into_owned();
+                info!(user = username, %acl, "provisioned ACL");
+                self.events.emit(events::Event::AclProvisioned {
+                    user: username.to_owned(),
+                    acl,
+                });
+            }
+            Ok(None) => {}
+            Err(err) => warn!(user = username, %err, "could not provision ACL"),
+        }
+    }
+
+    /// Key for signing JWTs
+    fn jwt_key(&self) -> JwtKey {
+        match &self.secret_key {
+            SecretKey::Static(key) => key.clone(),
+            SecretKey::File(file) => {
+                let key = file.get().expect("the secret key file is read at startup");
+                JwtKey::new_from_slice(key.as_bytes()).unwrap()
+            }
         }
     }
 }
 
+/// Build the application router for the given arguments and configuration
+#[cfg(feature = "test-util")]
+pub(crate) fn app(args: Args, config: Config) -> eyre::Result<Router> {
+    router(Arc::new(Ctx::new(args, config)?))
+}
+
+fn router(ctx: Arc<Ctx>) -> eyre::Result<Router> {
+    let bind = ctx.args.bind.clone();
+
+    // Start background jobs
+    tokio::spawn({
+        let ctx = ctx.clone();
+        let receiver = ctx.events.subscribe();
+        async move { events::audit(receiver, &ctx.config.request_log.redaction, &ctx.audit).await }
+    });
+    tokio::spawn(ctx.notifier.clone().run(ctx.events.subscribe()));
+    tokio::spawn(sessions::run_pruning(ctx.clone()));
+
+    if ctx.config.failover.upstream.is_some() {
+        let ctx = ctx.clone();
+        tokio::spawn(async move { ctx.config.failover.run(&ctx.client, &ctx.events).await });
+    }
+
+    if ctx.config.notifications.torrent_reports.enabled {
+        let ctx = ctx.clone();
+        tokio::spawn(async move {
+            torrent_reports::run(&ctx.client, &ctx.config, &ctx.provisioned, &ctx.events).await
+        });
+    }
+
+    if ctx.config.version_check.enabled {
+        let ctx = ctx.clone();
+        tokio::spawn(async move { ctx.config.version_check.run(&ctx.client).await });
+    }
+
+    if ctx.config.tracker_stats.enabled {
+        let ctx = ctx.clone();
+        tokio::spawn(async move {
+            ctx.tracker_stats
+                .run(&ctx.client, &ctx.config.tracker_stats, &*ctx.storage)
+                .await
+        });
+    }
+
+    if ctx.config.uploads.enabled && ctx.config.uploads.ttl > 0 {
+        let ctx = ctx.clone();
+        tokio::spawn(async move { ctx.uploads.run().await });
+    }
+
+    if ctx.config.torrent_index.seed {
+        let ctx = ctx.clone();
+        tokio::spawn(async move { ctx.client.index().seed(&ctx.client).await });
+    }
+
+    // Create axum router
+    // Nested routes
+    let sub_router = {
+        let router = Router::new()
+            .route("/", routing::get(routes::default))
+            .route(
+                "/login",
+                routing::get(routes::login).post(routes::login_form),
+            )
+            .route("/logout", routing::get(routes::logout))
+            .route("/account/activity", routing::get(sessions::activity))
+            .route(
+                "/account/tokens",
+                routing::get(api_tokens::list).post(api_tokens::create),
+            )
+            .route("/account/tokens/:id", routing::delete(api_tokens::revoke))
+            .route(
+                "/account/sign-out-everywhere",
+                routing::post(sessions::sign_out_everywhere),
+            )
+            .route("/export", routing::get(export::export))
+            .route("/inspect", routing::post(routes::inspect))
+            .route("/torrents/stream", routing::get(torrent_stream::stream))
+            .route("/stats/trackers", routing::get(routes::tracker_stats))
+            .route(
+                "/admin/maintenance",
+                routing::get(routes::maintenance).put(routes::set_maintenance),
+            )
+            .route("/admin/explain", routing::post(routes::explain))
+            .route("/admin/events", routing::get(routes::events))
+            .route("/admin/config", routing::get(routes::config))
+            .route("/admin/requests", routing::get(routes::requests))
+            .route("/admin/latency", routing::get(routes::latency))
+            .route("/admin/metrics", routing::get(routes::metrics))
+            .route("/admin/owners", routing::get(owners::overview))
+            .route("/admin/owners/stop", routing::post(owners::stop))
+            .route("/admin/owners/remove", routing::post(owners::remove))
+            .route(
+                "/admin/provisioned",
+                routing::get(routes::provisioned).delete(routes::remove_provisioned),
+            )
+            .route(
+                "/admin/snapshot",
+                routing::get(routes::snapshot).put(routes::restore_snapshot),
+            );
+
+        // Enable resumable uploads
+        let router = if ctx.config.uploads.enabled {
+            router
+                .route("/uploads", routing::post(routes::create_upload))
+                .route(
+                    "/uploads/:id",
+                    routing::get(routes::upload_status)
+                        .patch(routes::append_upload)
+                        .delete(routes::cancel_upload),
+                )
+                .route("/uploads/:id/add", routing::post(routes::add_upload))
+        } else {
+            router
+        };
+
+        // Enable share links
+        let router = if ctx.config.shares.enabled {
+            router
+                .route("/shares", routing::post(shares::create))
+                .route("/share/:token", routing::get(shares::view))
+        } else {
+            router
+        };
+
+        // Enable oauth routes
+        #[cfg(feature = "oauth")]
+        let router = oauth::add_provider_routes(ctx.clone(), router)?;
+
+        // Enable passkey logins
+        #[cfg(feature = "webauthn")]
+        let router = webauthn::add_routes(&ctx, router);
+
+        // Inject faults into rpc calls
+        #[cfg(feature = "chaos")]
+        let router = chaos::add_routes(router);
+
+        // Take uploads of older clients
+        let router = if ctx.config.legacy_paths.enabled {
+            router.route("/upload", routing::post(legacy_paths::upload))
+        } else {
+            router
+        };
+
+        // Enable basic auth
+        if ctx.config.providers.basic.enabled {
+            router.route(
+                "/auth/basic",
+                routing::get(routes::auth_basic).post(routes::auth_basic_totp),
+            )
+        } else {
+            router
+        }
+    };
+
+    // Root routes
+    let router = Router::new()
+        .route("/", routing::get(routes::default))
+        .route("/healthz", routing::get(routes::healthz))
+        .route("/readyz", routing::get(routes::readyz));
+
+    Ok(legacy_paths::add_routes(&ctx, router)
+        .nest(bind.path(), sub_router)
+        .fallback(routes::proxy_request.layer(middleware::from_fn(routes::deadline)))
+        .layer(middleware::from_fn(routes::csrf))
+        .layer(middleware::from_fn(routes::request_log))
+        .layer(middleware::from_fn(context::resolve))
+        .layer(middleware::from_fn(routes::compression))
+        .layer(middleware::from_fn(routes::security_headers))
+        .layer(middleware::from_fn(routes::cors))
+        .layer(Extension(ctx.clone()))
+        .layer(CookieManagerLayer::new()))
+}
+
 pub async fn run(args: Args, config: Config) -> eyre::Result<()> {
     // Server status span
     let server_span =
@@ -72,47 +440,27 @@ pub async fn run(args: Args, config: Config) -> eyre::Result<()> {
         .ok_or_else(|| Error::BindResolve(args.bind.clone()))?
     };
 
-    // Initialize context
-    let bind = args.bind.clone();
-    let ctx = Arc::new(Ctx::new(args, config));
-
-    // Create axum router
-    // Nested routes
-    let sub_router = {
-        let router = Router::new()
-            .route("/", routing::get(routes::default))
-            .route("/login", routing::get(routes::login))
-            .route("/logout", routing::get(routes::logout));
-
-        // Enable basic auth
-        let router = if ctx.config.providers.basic.enabled {
-            router.route("/auth/basic", routing::get(routes::auth_basic))
-        } else {
-            router
-        };
+    let listener = config.listener.clone();
+    let ctx = Arc::new(Ctx::new(args, config)?);
 
-        // Enable oauth routes
-        oauth::add_provider_routes(ctx.clone(), router)?
-    };
+    // Check that we understand the upstream
+    if ctx.config.version_check.enabled {
+        ctx.config
+            .version_check
+            .check(&ctx.client, ctx.config.version_check.strict)
+            .instrument(server_span.clone())
+            .await?;
+    }
 
-    // Root routes
-    let router = Router::new()
-        .route("/", routing::get(routes::default))
-        .route("/healthz", routing::get(routes::healthz))
-        .nest(bind.path(), sub_router)
-        .fallback(routes::proxy_request)
-        .layer(Extension(ctx.clone()))
-        .layer(CookieManagerLayer::new());
+    let router = router(ctx)?;
 
     // Bind server
-    let server = Server::try_bind(&addr)?
-        .serve(router.into_make_service())
-        .instrument(server_span.clone());
-
-    info!(parent: server_span, "listening");
+    let tcp_listener = std::net::TcpListener::bind(addr)?;
+    info!(parent: &server_span, "listening");
 
     // Run server
-    server.await?;
-
-    Ok(())
+    listener
+        .serve(tcp_listener, router, std::future::pending())
+        .instrument(server_span)
+        .await
 }
@@ -0,0 +1,287 @@
+//! API tokens issued by users, see [`crate::api_tokens`]
+
+use std::sync::Arc;
+
+use axum::{
+    extract::Path,
+    headers::{authorization::Basic, authorization::Bearer, Authorization, HeaderMapExt},
+    response::{IntoResponse, Response},
+    Extension, Json,
+};
+use hyper::{HeaderMap, StatusCode};
+use jwt::{SignWithKey, VerifyWithKey};
+use rand::{distributions::Alphanumeric, Rng};
+use serde::{Deserialize, Serialize};
+use tracing::{debug, error, info};
+
+use crate::{acl::Acl, api_tokens::TokenScope, auth::AuthUser};
+
+use super::{
+    auth::{TokenType, UserClaim},
+    context::RequestContext,
+    sessions::now,
+    Ctx,
+};
+
+/// Issued tokens, by token id. Removing a token revokes it.
+const API_TOKENS: &str = "api_tokens";
+
+/// Request extension marking requests authenticated by an API token
+#[derive(Debug, Clone)]
+pub struct TokenAuth {
+    pub id: String,
+    pub scope: TokenScope,
+}
+
+/// Contents of an API token. The scope is kept in the storage, so tokens can be revoked.
+#[derive(Debug, Serialize, Deserialize)]
+struct TokenClaim {
+    typ: TokenType,
+    tid: String,
+    owner: UserClaim,
+}
+
+/// Token as kept in the storage
+#[derive(Debug, Serialize, Deserialize)]
+struct StoredToken {
+    owner: UserClaim,
+    name: String,
+    /// Unix timestamp of the creation of the token
+    created: u64,
+    /// Unix timestamp after which the token is invalid, if it expires
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    expires_at: Option<u64>,
+    scope: TokenScope,
+}
+
+/// API token of a request, as a bearer token or as the password of basic auth for clients
+/// which don't support other schemes. The username of basic auth is ignored.
+pub(super) fn from_headers(headers: &HeaderMap) -> Option<String> {
+    if let Some(Authorization(bearer)) = headers.typed_get::<Authorization<Bearer>>() {
+        return Some(bearer.token().to_owned());
+    }
+
+    // Tokens are JWTs, which start with an encoded JSON object
+    headers
+        .typed_get::<Authorization<Basic>>()
+        .map(|Authorization(basic)| basic.password().to_owned())
+        .filter(|password| password.starts_with("eyJ") && password.matches('.').count() == 2)
+}
+
+/// Verify an API token, returning the user it acts for
+pub(super) fn authenticate(ctx: &Ctx, token: &str) -> Option<(AuthUser, TokenAuth)> {
+    let claim: TokenClaim = token.verify_with_key(&ctx.jwt_key()).ok()?;
+    if claim.typ != TokenType::ApiToken {
+        return None;
+    }
+
+    let stored = match ctx.storage.get_json::<StoredToken>(API_TOKENS, &claim.tid) {
+        Ok(Some(stored)) => stored,
+        Ok(None) => {
+            debug!(tid = claim.tid, "ignoring revoked API token");
+            return None;
+        }
+        Err(err) => {
+            error!(%err, "could not load API token");
+            return None;
+        }
+    };
+
+    if stored.owner != claim.owner || stored.expires_at.map_or(false, |exp| exp <= now()) {
+        return None;
+    }
+
+    // Tokens of users removed from the configuration stop working with their sessions
+    if !claim.owner.is_allowed(ctx) {
+        debug!(
+            tid = claim.tid,
+            "ignoring API token of a user who is not allowed anymore"
+        );
+        return None;
+    }
+
+    Some((
+        claim.owner.into(),
+        TokenAuth {
+            id: claim.tid,
+            scope: stored.scope,
+        },
+    ))
+}
+
+#[derive(Debug, Deserialize)]
+pub(super) struct CreateToken {
+    name: String,
+    /// Lifetime of the token in seconds, it doesn't expire if unset
+    #[serde(default)]
+    lifetime: Option<u64>,
+    #[serde(flatten)]
+    scope: TokenScope,
+}
+
+#[derive(Debug, Serialize)]
+struct TokenInfo {
+    id: String,
+    name: String,
+    created: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    expires_at: Option<u64>,
+    #[serde(flatten)]
+    scope: TokenScope,
+    /// The token itself, only returned when it is created
+    #[serde(skip_serializing_if = "Option::is_none")]
+    token: Option<String>,
+}
+
+impl TokenInfo {
+    fn new(id: String, stored: StoredToken) -> Self {
+        Self {
+            id,
+            name: stored.name,
+            created: stored.created,
+            expires_at: stored.expires_at,
+            scope: stored.scope,
+            token: None,
+        }
+    }
+}
+
+/// User issuing or managing tokens, who must not be authenticated by a token
+fn owner(
+    request: &RequestContext,
+    token_auth: Option<&Extension<TokenAuth>>,
+) -> Result<UserClaim, Response> {
+    if token_auth.is_some() {
+        return Err((StatusCode::FORBIDDEN, "API tokens can't manage tokens").into_response());
+    }
+
+    UserClaim::from_auth_user(&request.user).ok_or_else(|| StatusCode::UNAUTHORIZED.into_response())
+}
+
+pub(super) async fn create(
+    Extension(ctx): Extension<Arc<Ctx>>,
+    request: RequestContext,
+    token_auth: Option<Extension<TokenAuth>>,
+    Json(create): Json<CreateToken>,
+) -> Response {
+    let owner = match owner(&request, token_auth.as_ref()) {
+        Ok(owner) => owner,
+        Err(response) => return response,
+    };
+
+    if ctx.config.acl.denies(request.acl()) {
+        return StatusCode::UNAUTHORIZED.into_response();
+    }
+    if let Err(err) = create.scope.check(request.acl().unwrap_or(&Acl::default())) {
+        return (StatusCode::BAD_REQUEST, err.to_string()).into_response();
+    }
+
+    let id: String = rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(16)
+        .map(char::from)
+        .collect();
+    let created = now();
+    let stored = StoredToken {
+        owner: owner.clone(),
+        name: create.name,
+        created,
+        expires_at: create.lifetime.map(|lifetime| created + lifetime),
+        scope: create.scope,
+    };
+
+    if let Err(err) = ctx.storage.put_json(API_TOKENS, &id, &stored) {
+        error!(%err, "could not store API token");
+        return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+    }
+
+    let token = TokenClaim {
+        typ: TokenType::ApiToken,
+        tid: id.clone(),
+        owner,
+    }
+    .sign_with_key(&ctx.jwt_key())
+    .expect("failed to sign jwt");
+
+    info!(
+        user = request.user.username(),
+        tid = id,
+        name = stored.name,
+        "issued API token"
+    );
+    Json(TokenInfo {
+        token: Some(token),
+        ..TokenInfo::new(id, stored)
+    })
+    .into_response()
+}
+
+/// Tokens of a user, by id
+fn tokens_of(ctx: &Ctx, owner: &UserClaim) -> Result<Vec<(String, StoredToken)>, Response> {
+    let tokens = ctx.storage.list(API_TOKENS).map_err(|err| {
+        error!(%err, "could not list API tokens");
+        StatusCode::INTERNAL_SERVER_ERROR.into_response()
+    })?;
+
+    Ok(tokens
+        .into_iter()
+        .filter_map(|(id, value)| {
+            serde_json::from_slice::<StoredToken>(&value)
+                .ok()
+                .filter(|stored| &stored.owner == owner)
+                .map(|stored| (id, stored))
+        })
+        .collect())
+}
+
+pub(super) async fn list(
+    Extension(ctx): Extension<Arc<Ctx>>,
+    request: RequestContext,
+    token_auth: Option<Extension<TokenAuth>>,
+) -> Response {
+    let owner = match owner(&request, token_auth.as_ref()) {
+        Ok(owner) => owner,
+        Err(response) => return response,
+    };
+
+    match tokens_of(&ctx, &owner) {
+        Ok(tokens) => Json(
+            tokens
+                .into_iter()
+                .map(|(id, stored)| TokenInfo::new(id, stored))
+                .collect::<Vec<_>>(),
+        )
+        .into_response(),
+        Err(response) => response,
+    }
+}
+
+pub(super) async fn revoke(
+    Extension(ctx): Extension<Arc<Ctx>>,
+    request: RequestContext,
+    token_auth: Option<Extension<TokenAuth>>,
+    Path(id): Path<String>,
+) -> Response {
+    let owner = match owner(&request, token_auth.as_ref()) {
+        Ok(owner) => owner,
+        Err(response) => return response,
+    };
+
+    match tokens_of(&ctx, &owner) {
+        Ok(tokens) if tokens.iter().any(|(tid, _)| *tid == id) => {
+            if let Err(err) = ctx.storage.delete(API_TOKENS, &id) {
+                error!(%err, "could not revoke API token");
+                return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+            }
+
+            info!(
+                user = request.user.username(),
+                tid = id,
+                "revoked API token"
+            );
+            StatusCode::NO_CONTENT.into_response()
+        }
+        Ok(_) => StatusCode::NOT_FOUND.into_response(),
+        Err(response) => response,
+    }
+}
@@ -17,24 +17,47 @@ use secrecy::SecretString;
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 use tower_cookies::Cookies;
-use tracing::error;
+use tracing::{debug, error, info};
 
 use crate::{
     auth::AuthUser,
-    server::{Ctx, JwtKey},
+    server::{api_tokens, sessions, Ctx, JwtKey},
 };
 
-pub const COOKIE_NAME: &str = "_transmission_proxy";
+/// Header with the TOTP code of basic auth users, for clients which can't use the login form
+pub const TOTP_HEADER: &str = "X-Transmission-Proxy-Totp";
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// Request extension marking requests authenticated by the session cookie
+#[derive(Debug, Clone)]
+pub struct CookieAuth {
+    /// Session of the cookie, if it has one
+    pub sid: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum UserClaim {
     Basic { username: String },
     OAuth2 { username: String, provider: String },
 }
 
 impl UserClaim {
+    /// Returns false if the user was removed from the configuration, or their provider doesn't
+    /// allow them to log in anymore
+    pub(super) fn is_allowed(&self, ctx: &Ctx) -> bool {
+        match self {
+            Self::Basic { username } => ctx.config.providers.basic.has_user(username),
+            Self::OAuth2 { username, provider } => ctx
+                .config
+                .providers
+                .oauth2
+                .iter()
+                .find(|p| p.enabled && &p.name == provider)
+                .map_or(false, |provider| provider.allows(username)),
+        }
+    }
+
     pub fn from_auth_user(value: &AuthUser) -> Option<Self> {
-        match value {
+        match value.identity() {
             AuthUser::Anonymous => None,
             AuthUser::Basic {
                 username,
@@ -46,15 +69,50 @@ impl UserClaim {
                 username: username.clone(),
                 provider: provider.clone(),
             }),
+            AuthUser::Linked { .. } => unreachable!("identities are not linked users"),
         }
     }
+}
+
+/// Kind of the tokens signed with the secret key, checked when decoding them so that one kind
+/// can't be used as another
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TokenType {
+    Session,
+    ApiToken,
+    Share,
+}
+
+/// Contents of the session cookie
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionClaim {
+    /// Kind of the token. Cookies set by older versions don't have one, and are ignored.
+    #[serde(default)]
+    pub typ: Option<TokenType>,
+    #[serde(flatten)]
+    pub user: UserClaim,
+    /// Identifier of the session, to revoke it. Cookies set by older versions don't have one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sid: Option<String>,
+    /// Unix timestamp of the login, to expire the session. Cookies set by older versions don't
+    /// have one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub iat: Option<u64>,
+}
 
+impl SessionClaim {
     pub fn jwt(&self, key: &JwtKey) -> String {
         self.sign_with_key(key).expect("failed to sign jwt")
     }
 
     pub fn verify(key: &JwtKey, jwt: &str) -> Result<Self, jwt::Error> {
-        jwt.verify_with_key(key)
+        let claim: Self = jwt.verify_with_key(key)?;
+        if claim.typ != Some(TokenType::Session) {
+            return Err(jwt::Error::Format);
+        }
+
+        Ok(claim)
     }
 }
 
@@ -78,6 +136,8 @@ pub enum AuthenticationError {
     InvalidAuthHeader(#[from] TypedHeaderRejection),
     #[error("invalid credentials for {0}")]
     InvalidCredentials(String),
+    #[error("valid password for {0}, but a TOTP code is needed")]
+    TotpRequired(String),
     #[error("cookies error")]
     Cookies((StatusCode, &'static str)),
     #[error("invalid claim")]
@@ -91,6 +151,9 @@ impl IntoResponse for AuthenticationError {
             AuthenticationError::InvalidCredentials(_) => {
                 (StatusCode::UNAUTHORIZED, "invalid credentials").into_response()
             }
+            AuthenticationError::TotpRequired(_) => {
+                (StatusCode::UNAUTHORIZED, "two-factor code required").into_response()
+            }
             AuthenticationError::Cookies(err) => err.into_response(),
             AuthenticationError::Jwt(_) => {
                 (StatusCode::UNAUTHORIZED, "invalid claim").into_response()
@@ -111,16 +174,52 @@ where
             .await
             .expect("missing ctx");
 
+        // API tokens take precedence, as automation tools may also send cookies
+        if let Some(token) = api_tokens::from_headers(&parts.headers) {
+            let Some((user, token_auth)) = api_tokens::authenticate(&ctx, &token) else {
+                return Err(AuthenticationError::InvalidCredentials(
+                    "API token".to_owned(),
+                ));
+            };
+
+            parts.extensions.insert(token_auth);
+            return Ok(user);
+        }
+
         // Try to check auth cookie
         let cookies = Cookies::from_request_parts(parts, state)
             .await
             .map_err(AuthenticationError::Cookies)?;
 
-        if let Some(cookie) = cookies.get(COOKIE_NAME) {
-            return match UserClaim::verify(&ctx.jwt_key, cookie.value()) {
-                Ok(claim) => Ok(claim.into()),
-                Err(err) => Err(err.into()),
-            };
+        if let Some(cookie) = cookies.get(ctx.config.session_cookies.login_name()) {
+            match SessionClaim::verify(&ctx.jwt_key(), cookie.value()) {
+                // Expired and revoked sessions are ignored, so the user can log in again
+                Ok(claim) if !sessions::is_current(&ctx, &claim) => {
+                    debug!(
+                        sid = claim.sid,
+                        "ignoring expired or revoked session cookie"
+                    );
+                }
+                // So are sessions of users removed from the allowlists since they logged in
+                Ok(claim) if !claim.user.is_allowed(&ctx) => {
+                    info!(
+                        sid = claim.sid,
+                        "ignoring session cookie of a user who is not allowed anymore"
+                    );
+                }
+                Ok(claim) => {
+                    parts.extensions.insert(CookieAuth { sid: claim.sid });
+                    return Ok(claim.user.into());
+                }
+                // Sessions signed with a previous secret key are ignored too
+                Err(jwt::Error::InvalidSignature | jwt::Error::RustCryptoMac(_)) => {
+                    info!("ignoring session cookie signed with another secret key");
+                }
+                // And so are cookies set by older versions, or holding other tokens
+                Err(err) => {
+                    info!(%err, "ignoring invalid session cookie");
+                }
+            }
         }
 
         if ctx.config.providers.basic.enabled {
@@ -128,23 +227,29 @@ where
             match TypedHeader::<Authorization<Basic>>::from_request_parts(parts, state).await {
                 Ok(TypedHeader(Authorization(basic))) => {
                     let password: SecretString = basic.password().to_owned().into();
+                    let provider = &ctx.config.providers.basic;
+                    let username = basic.username().to_owned();
 
-                    if ctx
-                        .config
-                        .providers
-                        .basic
-                        .auth(basic.username(), &password)
-                        .await
-                    {
-                        Ok(Self::Basic {
-                            username: basic.username().to_owned(),
-                            password: Some(password),
-                        })
-                    } else {
-                        Err(AuthenticationError::InvalidCredentials(
-                            basic.username().to_owned(),
-                        ))
+                    if !provider.auth(&username, &password).await {
+                        return Err(AuthenticationError::InvalidCredentials(username));
                     }
+
+                    // The second factor is checked on each request, the login form sets a cookie
+                    // instead
+                    if provider.requires_totp(&username) {
+                        match parts.headers.get(TOTP_HEADER).map(|code| code.to_str()) {
+                            Some(Ok(code)) if provider.verify_totp(&username, code) => {}
+                            Some(_) => {
+                                return Err(AuthenticationError::InvalidCredentials(username))
+                            }
+                            None => return Err(AuthenticationError::TotpRequired(username)),
+                        }
+                    }
+
+                    Ok(Self::Basic {
+                        username,
+                        password: Some(password),
+                    })
                 }
 
                 Err(err) => match err.reason() {
@@ -157,3 +262,70 @@ where
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn claims_without_a_session_id_are_parsed() {
+        let claim: SessionClaim =
+            serde_json::from_str(r#"{"Basic":{"username":"alice"}}"#).unwrap();
+        assert_eq!(
+            claim.user,
+            UserClaim::Basic {
+                username: "alice".into()
+            }
+        );
+        assert!(claim.typ.is_none());
+        assert!(claim.sid.is_none());
+        assert!(claim.iat.is_none());
+
+        let claim = SessionClaim {
+            typ: Some(TokenType::Session),
+            user: claim.user,
+            sid: Some("abc".into()),
+            iat: Some(1700000000),
+        };
+        let json = serde_json::to_string(&claim).unwrap();
+        let claim: SessionClaim = serde_json::from_str(&json).unwrap();
+        assert_eq!(claim.sid.as_deref(), Some("abc"));
+        assert_eq!(claim.iat, Some(1700000000));
+    }
+
+    #[test]
+    fn other_tokens_are_not_session_claims() {
+        use hmac::Mac;
+
+        let key = JwtKey::new_from_slice(b"secret").unwrap();
+        let claim = SessionClaim {
+            typ: Some(TokenType::Share),
+            user: UserClaim::Basic {
+                username: "alice".into(),
+            },
+            sid: Some("abc".into()),
+            iat: Some(1700000000),
+        };
+
+        assert!(SessionClaim::verify(&key, &claim.jwt(&key)).is_err());
+        // Nor are the cookies of older versions, which don't say
+        assert!(SessionClaim::verify(
+            &key,
+            &SessionClaim {
+                typ: None,
+                ..claim.clone()
+            }
+            .jwt(&key)
+        )
+        .is_err());
+        assert!(SessionClaim::verify(
+            &key,
+            &SessionClaim {
+                typ: Some(TokenType::Session),
+                ..claim
+            }
+            .jwt(&key)
+        )
+        .is_ok());
+    }
+}
@@ -0,0 +1,56 @@
+//! Admin endpoints injecting faults into the RPC calls of clients
+
+use std::sync::Arc;
+
+use axum::{
+    response::{IntoResponse, Response},
+    routing, Extension, Json, Router,
+};
+use hyper::StatusCode;
+use tracing::{info, warn};
+
+use crate::chaos::Faults;
+
+use super::{context::RequestContext, Ctx};
+
+async fn faults(Extension(ctx): Extension<Arc<Ctx>>, request: RequestContext) -> Response {
+    if !request.is_admin() {
+        return StatusCode::FORBIDDEN.into_response();
+    }
+
+    Json(ctx.client.chaos().faults()).into_response()
+}
+
+async fn set_faults(
+    Extension(ctx): Extension<Arc<Ctx>>,
+    request: RequestContext,
+    Json(faults): Json<Faults>,
+) -> Response {
+    if !request.is_admin() {
+        return StatusCode::FORBIDDEN.into_response();
+    }
+
+    if let Err(err) = ctx.client.chaos().set_faults(faults.clone()) {
+        return (StatusCode::BAD_REQUEST, err).into_response();
+    }
+
+    warn!(?faults, user = ?request.user, "injecting faults into rpc calls");
+    Json(faults).into_response()
+}
+
+async fn clear_faults(Extension(ctx): Extension<Arc<Ctx>>, request: RequestContext) -> Response {
+    if !request.is_admin() {
+        return StatusCode::FORBIDDEN.into_response();
+    }
+
+    let _ = ctx.client.chaos().set_faults(Faults::default());
+    info!(user = ?request.user, "cleared injected faults");
+    StatusCode::NO_CONTENT.into_response()
+}
+
+pub(super) fn add_routes(router: Router) -> Router {
+    router.route(
+        "/admin/faults",
+        routing::get(faults).put(set_faults).delete(clear_faults),
+    )
+}
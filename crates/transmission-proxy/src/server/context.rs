@@ -0,0 +1,206 @@
+use std::{
+    net::IpAddr,
+    sync::{atomic::Ordering, Arc},
+};
+
+use axum::{
+    async_trait,
+    extract::{ConnectInfo, FromRequestParts, OriginalUri},
+    http::request::Parts,
+    middleware::Next,
+    response::IntoResponse,
+    Extension,
+};
+use hyper::{Body, Request, StatusCode};
+use serde_json::{json, Value};
+use tracing::{debug, info_span, warn, Instrument};
+
+use crate::{
+    acl::Acl,
+    api_tokens::TokenScope,
+    auth::AuthUser,
+    listener::ClientAddr,
+    rpc::{proxy::SESSION_ID_HEADER, TorrentId},
+};
+
+use super::{api_tokens::TokenAuth, auth::AuthenticationError, Ctx};
+
+/// Authorization context of a request: who is making it, and which ACL applies
+#[derive(Debug, Clone)]
+pub struct RequestContext {
+    /// Identifier of the request, for logging
+    pub id: u64,
+    /// Authenticated user
+    pub user: AuthUser,
+    /// Matched ACL, if any
+    pub acl: Option<Arc<Acl>>,
+    /// Name of the matched ACL
+    pub acl_name: Option<String>,
+    /// Address of the client
+    pub client_ip: Option<IpAddr>,
+}
+
+impl RequestContext {
+    /// Build the context of a request made by the given user
+    pub(super) async fn new(ctx: &Ctx, user: AuthUser, client_ip: Option<IpAddr>) -> Self {
+        let user = ctx.config.users.link(user);
+        // ACLs of the configuration take precedence over provisioned ones
+        let acl = match ctx.provisioned.get(&user) {
+            Some(acl) if !ctx.config.acl.names(&user) => Some(acl),
+            _ => ctx.config.acl.get(&user, &ctx.config.providers).await,
+        };
+        let acl_name = acl
+            .as_deref()
+            .map(|acl| ctx.config.acl.name_of(acl).into_owned());
+
+        Self {
+            id: ctx.next_request_id.fetch_add(1, Ordering::Relaxed),
+            user,
+            acl,
+            acl_name,
+            client_ip,
+        }
+    }
+
+    /// Matched ACL, if any
+    pub fn acl(&self) -> Option<&Acl> {
+        self.acl.as_deref()
+    }
+
+    /// Restrict the context to the scope of the API token of the request
+    pub(super) fn restrict(&mut self, ctx: &Ctx, scope: &TokenScope) {
+        if ctx.config.acl.denies(self.acl()) {
+            return;
+        }
+
+        let acl = scope.narrow(self.acl().unwrap_or(&Acl::default()));
+        self.acl = Some(Arc::new(acl));
+    }
+
+    /// Returns true if the user is allowed to use the administration endpoints
+    pub fn is_admin(&self) -> bool {
+        matches!(self.acl(), Some(acl) if acl.admin && !acl.deny)
+    }
+
+    /// Fetch torrents on behalf of the user, through the filters of their ACL. All the torrents
+    /// they can see are returned if `ids` is `None`.
+    pub(super) async fn torrent_get(
+        &self,
+        ctx: &Ctx,
+        ids: Option<Vec<TorrentId>>,
+        fields: &[&str],
+    ) -> Result<Vec<Value>, StatusCode> {
+        if ctx.config.acl.denies(self.acl()) {
+            return Err(StatusCode::UNAUTHORIZED);
+        }
+
+        let body = match ids {
+            Some(ids) => json!({
+                "method": "torrent-get",
+                "arguments": { "ids": ids, "fields": fields },
+            }),
+            None => json!({
+                "method": "torrent-get",
+                "arguments": { "fields": fields },
+            }),
+        };
+        let mut req = Request::post(ctx.paths.rpc_path.as_str())
+            .body(Body::from(body.to_string()))
+            .unwrap();
+        req.extensions_mut()
+            .insert(OriginalUri(ctx.paths.rpc_path.parse().unwrap()));
+
+        // Filters query the upstream with the session id of the client
+        match ctx.client.session_id().await {
+            Ok(Some(session_id)) => {
+                req.headers_mut().insert(SESSION_ID_HEADER, session_id);
+            }
+            Ok(None) => {}
+            Err(err) => {
+                warn!(%err, "could not acquire upstream session");
+                return Err(StatusCode::BAD_GATEWAY);
+            }
+        }
+
+        let response = ctx.client.handle_request(req, self).await.map_err(|err| {
+            warn!(%err, "could not fetch torrents");
+            StatusCode::BAD_GATEWAY
+        })?;
+        match response.status() {
+            status if status.is_success() => {}
+            // The ACL doesn't allow torrent-get
+            StatusCode::FORBIDDEN => return Err(StatusCode::FORBIDDEN),
+            _ => return Err(StatusCode::BAD_GATEWAY),
+        }
+
+        let bytes = hyper::body::to_bytes(response.into_body())
+            .await
+            .map_err(|_| StatusCode::BAD_GATEWAY)?;
+        let mut response: Value =
+            serde_json::from_slice(&bytes).map_err(|_| StatusCode::BAD_GATEWAY)?;
+
+        match response["arguments"]["torrents"].take() {
+            Value::Array(torrents) => Ok(torrents),
+            _ => Err(StatusCode::BAD_GATEWAY),
+        }
+    }
+}
+
+/// Resolve the context of each request once, for the handlers and filters to share
+pub(super) async fn resolve(
+    Extension(ctx): Extension<Arc<Ctx>>,
+    user: Result<AuthUser, AuthenticationError>,
+    mut req: Request<Body>,
+    next: Next<Body>,
+) -> axum::response::Response {
+    // Handlers which need a user will reject the request themselves
+    let user = match user {
+        Ok(user) => user,
+        Err(err) => {
+            debug!(%err, "could not authenticate request");
+            return next.run(req).await;
+        }
+    };
+
+    let client_ip = req
+        .extensions()
+        .get::<ConnectInfo<ClientAddr>>()
+        .and_then(|info| info.0 .0)
+        .map(|addr| addr.ip());
+
+    let mut request = RequestContext::new(&ctx, user, client_ip).await;
+    if let Some(token_auth) = req.extensions().get::<TokenAuth>() {
+        request.restrict(&ctx, &token_auth.scope);
+    }
+
+    let span = info_span!(
+        "request",
+        id = request.id,
+        user = request.user.username(),
+        acl = request.acl_name.as_deref(),
+        client_ip = ?request.client_ip,
+    );
+
+    req.extensions_mut().insert(request);
+    next.run(req).instrument(span).await
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for RequestContext
+where
+    S: Send + Sync,
+{
+    type Rejection = axum::response::Response;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        if let Some(request) = parts.extensions.get::<RequestContext>() {
+            return Ok(request.clone());
+        }
+
+        // The context is only missing if authentication failed, report why
+        match AuthUser::from_request_parts(parts, state).await {
+            Ok(_) => Err(hyper::StatusCode::INTERNAL_SERVER_ERROR.into_response()),
+            Err(err) => Err(err.into_response()),
+        }
+    }
+}
@@ -0,0 +1,228 @@
+//! Export of the torrents of a user as CSV or NDJSON, streamed in batches so large instances
+//! don't hold the whole list in memory
+
+use std::{io, sync::Arc};
+
+use axum::{
+    extract::Query,
+    response::{IntoResponse, Response},
+    Extension,
+};
+use hyper::{
+    header::{CONTENT_DISPOSITION, CONTENT_TYPE},
+    Body, StatusCode,
+};
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use tracing::warn;
+
+use crate::rpc::TorrentId;
+
+use super::{context::RequestContext, routes::Streamed, Ctx};
+
+/// Number of torrents fetched from the upstream at once
+const BATCH_SIZE: usize = 500;
+
+/// Fields exported when none are requested
+const DEFAULT_FIELDS: &[&str] = &[
+    "id",
+    "name",
+    "hashString",
+    "status",
+    "percentDone",
+    "totalSize",
+    "uploadedEver",
+    "downloadDir",
+    "addedDate",
+];
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub(super) enum Format {
+    Csv,
+    #[default]
+    Ndjson,
+}
+
+#[derive(Debug, Deserialize)]
+pub(super) struct ExportQuery {
+    #[serde(default)]
+    format: Format,
+    /// Comma-separated torrent-get fields
+    #[serde(default)]
+    fields: Option<String>,
+}
+
+/// Text of a CSV cell: nested values are written as JSON
+fn csv_value(value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::String(string) => string.clone(),
+        other => other.to_string(),
+    }
+}
+
+fn csv_row(cells: impl Iterator<Item = String>) -> String {
+    let mut row = cells
+        .map(|cell| {
+            if cell.contains([',', '"', '\n', '\r']) {
+                format!("\"{}\"", cell.replace('"', "\"\""))
+            } else {
+                cell
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(",");
+    row.push_str("\r\n");
+    row
+}
+
+/// Write a batch of torrents with only the exported fields, in their order
+fn write_batch(format: Format, fields: &[String], torrents: &[Value]) -> String {
+    let mut output = String::new();
+
+    for torrent in torrents {
+        match format {
+            Format::Csv => {
+                output += &csv_row(
+                    fields
+                        .iter()
+                        .map(|field| csv_value(&torrent[field.as_str()])),
+                );
+            }
+            Format::Ndjson => {
+                let object: Map<String, Value> = fields
+                    .iter()
+                    .map(|field| (field.clone(), torrent[field.as_str()].clone()))
+                    .collect();
+                output += &Value::Object(object).to_string();
+                output.push('\n');
+            }
+        }
+    }
+
+    output
+}
+
+/// Comma-separated torrent-get fields of a query, or the default ones
+pub(super) fn requested_fields(
+    fields: Option<&str>,
+    default: &[&str],
+) -> Result<Vec<String>, Response> {
+    let fields: Vec<String> = match fields {
+        Some(fields) => fields
+            .split(',')
+            .map(str::trim)
+            .filter(|field| !field.is_empty())
+            .map(str::to_owned)
+            .collect(),
+        None => default.iter().map(|field| (*field).to_owned()).collect(),
+    };
+    if fields.is_empty() {
+        return Err((StatusCode::BAD_REQUEST, "no fields requested").into_response());
+    }
+    if let Some(field) = fields
+        .iter()
+        .find(|field| !field.chars().all(|c| c.is_ascii_alphanumeric()))
+    {
+        return Err((StatusCode::BAD_REQUEST, format!("invalid field {field}")).into_response());
+    }
+
+    Ok(fields)
+}
+
+/// Torrents the user can see, in the requested format and with the requested fields
+pub(super) async fn export(
+    Extension(ctx): Extension<Arc<Ctx>>,
+    request: RequestContext,
+    Query(query): Query<ExportQuery>,
+) -> Response {
+    let fields = match requested_fields(query.fields.as_deref(), DEFAULT_FIELDS) {
+        Ok(fields) => fields,
+        Err(response) => return response,
+    };
+
+    // Only the ids are listed upfront, the fields are fetched batch by batch
+    let ids: Vec<TorrentId> = match request.torrent_get(&ctx, None, &["id"]).await {
+        Ok(torrents) => torrents
+            .iter()
+            .filter_map(|torrent| torrent["id"].as_i64())
+            .map(|id| TorrentId::Id(id as i32))
+            .collect(),
+        Err(status) => return status.into_response(),
+    };
+
+    let format = query.format;
+    let header = match format {
+        Format::Csv => csv_row(fields.iter().cloned()),
+        Format::Ndjson => String::new(),
+    };
+
+    let batches: Vec<Vec<TorrentId>> = ids.chunks(BATCH_SIZE).map(<[_]>::to_vec).collect();
+    let stream = futures_util::stream::unfold(
+        (batches.into_iter(), Some(header)),
+        move |(mut batches, header)| {
+            let ctx = ctx.clone();
+            let request = request.clone();
+            let fields = fields.clone();
+
+            async move {
+                if let Some(header) = header.filter(|header| !header.is_empty()) {
+                    return Some((Ok(header), (batches, None)));
+                }
+
+                let batch = batches.next()?;
+                let field_refs: Vec<&str> = fields.iter().map(String::as_str).collect();
+                let output = match request.torrent_get(&ctx, Some(batch), &field_refs).await {
+                    Ok(torrents) => Ok(write_batch(format, &fields, &torrents)),
+                    Err(status) => {
+                        // The client sees a truncated download
+                        warn!(%status, "could not export torrents");
+                        batches = Vec::new().into_iter();
+                        Err(io::Error::new(io::ErrorKind::Other, status.to_string()))
+                    }
+                };
+
+                Some((output, (batches, None)))
+            }
+        },
+    );
+
+    let (content_type, extension) = match format {
+        Format::Csv => ("text/csv; charset=utf-8", "csv"),
+        Format::Ndjson => ("application/x-ndjson", "ndjson"),
+    };
+
+    let mut response = Response::builder()
+        .header(CONTENT_TYPE, content_type)
+        .header(
+            CONTENT_DISPOSITION,
+            format!("attachment; filename=\"torrents.{extension}\""),
+        )
+        .body(Body::wrap_stream(stream))
+        .unwrap()
+        .into_response();
+    response.extensions_mut().insert(Streamed);
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn csv_cells_are_escaped() {
+        let torrents = [
+            json!({ "id": 1, "name": "Plain", "labels": ["a", "b"] }),
+            json!({ "id": 2, "name": "With \"quotes\", commas" }),
+        ];
+        let fields = ["id".to_owned(), "name".to_owned(), "labels".to_owned()];
+
+        assert_eq!(
+            write_batch(Format::Csv, &fields, &torrents),
+            "1,Plain,\"[\"\"a\"\",\"\"b\"\"]\"\r\n2,\"With \"\"quotes\"\", commas\",\r\n"
+        );
+    }
+}
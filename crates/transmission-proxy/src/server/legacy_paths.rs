@@ -0,0 +1,178 @@
+//! Legacy endpoints of the Transmission web server, outside of the RPC
+
+use std::{borrow::Cow, sync::Arc};
+
+use axum::{
+    extract::{OriginalUri, Path, Query},
+    response::{IntoResponse, Redirect, Response},
+    routing, Extension, Router,
+};
+use base64::Engine;
+use hyper::{body::HttpBody, header::CONTENT_TYPE, Body, Request, StatusCode};
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use tracing::{debug, warn};
+use transmission_rpc_client::types::SessionGet;
+
+use crate::{
+    legacy_paths::{multipart_boundary, multipart_parts},
+    rpc::proxy::FilterErrorKind,
+    torrent::Metadata,
+};
+
+use super::{context::RequestContext, routes, Ctx};
+
+/// Response of Transmission to successful uploads
+const UPLOAD_OK: &str = "<h1>200: OK</h1>";
+
+#[derive(Debug, Deserialize)]
+pub(super) struct UploadQuery {
+    /// Add the torrents paused
+    #[serde(default)]
+    paused: Option<String>,
+}
+
+/// Send the legacy web interface path to the web interface of the proxy
+async fn web(
+    Extension(ctx): Extension<Arc<Ctx>>,
+    rest: Option<Path<String>>,
+    uri: OriginalUri,
+) -> Redirect {
+    let mut url = ctx.paths.web_path.clone() + rest.as_deref().map_or("", String::as_str);
+    if let Some(query) = uri.0.query() {
+        url = url + "?" + query;
+    }
+
+    debug!(%url, "redirecting legacy web interface path");
+    Redirect::permanent(&url)
+}
+
+async fn favicon(Extension(ctx): Extension<Arc<Ctx>>) -> Redirect {
+    Redirect::permanent(&(ctx.paths.web_path.clone() + &ctx.config.legacy_paths.favicon))
+}
+
+async fn default_download_dir(ctx: &Ctx) -> Result<String, FilterErrorKind> {
+    #[derive(Deserialize)]
+    #[serde(rename_all = "kebab-case")]
+    struct Session {
+        download_dir: String,
+    }
+
+    let session: Session = ctx
+        .client
+        .session_get(SessionGet {
+            fields: vec![Cow::Borrowed("download-dir")],
+        })
+        .await?;
+
+    Ok(session.download_dir)
+}
+
+/// Add the torrents of a multipart upload, as Transmission's own upload endpoint
+pub(super) async fn upload(
+    Extension(ctx): Extension<Arc<Ctx>>,
+    request: RequestContext,
+    Query(query): Query<UploadQuery>,
+    req: Request<Body>,
+) -> Response {
+    if let Err(response) = routes::upload_owner(&ctx, &request) {
+        return response;
+    }
+
+    let Some(boundary) = req
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(multipart_boundary)
+        .map(str::to_owned)
+    else {
+        return (
+            StatusCode::BAD_REQUEST,
+            "expected a multipart/form-data body",
+        )
+            .into_response();
+    };
+
+    // Uploads may hold a few torrent files
+    let limit = ctx.config.uploads.max_size.saturating_mul(4);
+    let mut body = req.into_body();
+    let mut bytes = Vec::new();
+    while let Some(chunk) = body.data().await {
+        let Ok(chunk) = chunk else {
+            return StatusCode::BAD_REQUEST.into_response();
+        };
+        if (bytes.len() + chunk.len()) as u64 > limit {
+            return StatusCode::PAYLOAD_TOO_LARGE.into_response();
+        }
+        bytes.extend_from_slice(&chunk);
+    }
+
+    // Transmission adds uploads to its default download dir, unless the ACL forces one
+    let download_dir = match request.acl().and_then(|acl| acl.download_dir.clone()) {
+        Some(download_dir) => download_dir,
+        None => match default_download_dir(&ctx).await {
+            Ok(download_dir) => download_dir,
+            Err(err) => {
+                warn!(%err, "could not get the upstream download dir");
+                return StatusCode::BAD_GATEWAY.into_response();
+            }
+        },
+    };
+
+    let paused = query
+        .paused
+        .map_or(false, |paused| paused == "true" || paused == "1");
+
+    for part in multipart_parts(&bytes, &boundary) {
+        let mut arguments = Map::new();
+        arguments.insert("download-dir".into(), download_dir.clone().into());
+        arguments.insert("paused".into(), paused.into());
+
+        // Parts are either torrent files or links to them
+        match std::str::from_utf8(part).map(str::trim) {
+            Ok(link) if link.starts_with("magnet:") || link.starts_with("http") => {
+                arguments.insert("filename".into(), link.into());
+            }
+            _ => {
+                if let Err(err) = Metadata::from_metainfo(part) {
+                    debug!(%err, "uploaded torrent is invalid");
+                    return (StatusCode::BAD_REQUEST, err.to_string()).into_response();
+                }
+
+                arguments.insert(
+                    "metainfo".into(),
+                    Value::from(base64::engine::general_purpose::STANDARD.encode(part)),
+                );
+            }
+        }
+
+        let response = routes::add_torrent(&ctx, &request, arguments).await;
+        if !response.status().is_success() {
+            return response;
+        }
+    }
+
+    ([(CONTENT_TYPE, "text/html; charset=UTF-8")], UPLOAD_OK).into_response()
+}
+
+/// Add the legacy endpoints outside of the bind path
+pub(super) fn add_routes(ctx: &Ctx, router: Router) -> Router {
+    let config = &ctx.config.legacy_paths;
+    if !config.enabled || ctx.paths.base_path.is_empty() {
+        return router;
+    }
+
+    let router = router.route("/favicon.ico", routing::get(favicon));
+
+    // The bind path already serves them
+    let prefix = config.prefix();
+    if prefix == ctx.paths.base_path {
+        return router;
+    }
+
+    router
+        .route(&format!("{prefix}/web"), routing::get(web))
+        .route(&format!("{prefix}/web/"), routing::get(web))
+        .route(&format!("{prefix}/web/*rest"), routing::get(web))
+        .route(&format!("{prefix}/upload"), routing::post(upload))
+}
@@ -1,24 +1,63 @@
 use std::sync::Arc;
 
-use async_session::{MemoryStore, Session, SessionStore};
+use async_session::{Session, SessionStore};
 use axum::{
     extract::Query,
     response::{IntoResponse, Redirect, Response},
     routing, Extension, Router,
 };
 use color_eyre::eyre;
-use cookie::Cookie;
 use hyper::{header::ACCEPT, StatusCode};
 use oauth2::{
     AuthorizationCode, CsrfToken, PkceCodeChallenge, PkceCodeVerifier, Scope, TokenResponse,
 };
 use serde::{Deserialize, Serialize};
 use tower_cookies::Cookies;
-use tracing::{debug, error};
+use tracing::{debug, error, info, warn};
 
-use crate::server::auth::{UserClaim, COOKIE_NAME};
+use crate::{
+    auth::{AuthUser, OAuth2Provider},
+    server::auth::UserClaim,
+    state::SharedSessionStore,
+};
+
+use super::{
+    routes::AuthRedirect,
+    sessions::{self, LoginClient},
+    Ctx,
+};
 
-use super::Ctx;
+/// Perform an OAuth2 request with the configured HTTP client
+async fn oauth2_request(
+    client: &reqwest::Client,
+    request: oauth2::HttpRequest,
+) -> Result<oauth2::HttpResponse, reqwest::Error> {
+    let response = client
+        .request(request.method, request.url.as_str())
+        .headers(request.headers)
+        .body(request.body)
+        .send()
+        .await?;
+
+    Ok(oauth2::HttpResponse {
+        status_code: response.status(),
+        headers: response.headers().clone(),
+        body: response.bytes().await?.to_vec(),
+    })
+}
+
+fn new_client(
+    provider: &OAuth2Provider,
+    redirect_url: oauth2::RedirectUrl,
+) -> eyre::Result<oauth2::basic::BasicClient> {
+    Ok(oauth2::basic::BasicClient::new(
+        provider.client_id.clone(),
+        Some(provider.client_secret()?),
+        provider.auth_url.clone(),
+        Some(provider.token_url.clone()),
+    )
+    .set_redirect_uri(redirect_url))
+}
 
 pub(super) fn add_provider_routes(ctx: Arc<Ctx>, mut router: Router) -> eyre::Result<Router> {
     let bind = ctx.args.public_url();
@@ -39,8 +78,25 @@ pub(super) fn add_provider_routes(ctx: Arc<Ctx>, mut router: Router) -> eyre::Re
             })?,
         );
 
-        // Memory store for this provider
-        let ms = MemoryStore::new();
+        // The allowlists trust the email, so the provider should tell whether the user owns it
+        let verified_selector = match &provider.email_verified_path {
+            Some(path) => Some(Arc::new(jsonpath::Selector::new(path).map_err(|err| {
+                color_eyre::eyre::eyre!("invalid jsonpath for provider {}: {}", provider.name, err)
+            })?)),
+            None => {
+                if !provider.allowed_domains.is_empty() || !provider.allowed_emails.is_empty() {
+                    warn!(
+                        provider = %provider.name,
+                        "allowlists are set without email_verified_path, unverified emails are trusted"
+                    );
+                }
+
+                None
+            }
+        };
+
+        // Session store for this provider
+        let ms = SharedSessionStore::new(ctx.state.clone(), provider.name.clone());
 
         // What we'll store in this session
         #[derive(Serialize, Deserialize)]
@@ -56,35 +112,32 @@ pub(super) fn add_provider_routes(ctx: Arc<Ctx>, mut router: Router) -> eyre::Re
             code: AuthorizationCode,
         }
 
-        // Where we'll store the session id
-        const SESSION_COOKIE_NAME: &str = "_transmission_proxy_session";
-
         // Create the oauth2 client
-        let client = oauth2::basic::BasicClient::new(
-            provider.client_id.clone(),
-            Some(provider.client_secret.clone()),
-            provider.auth_url.clone(),
-            Some(provider.token_url.clone()),
+        let redirect_url = oauth2::RedirectUrl::new(
+            bind.to_string().trim_end_matches('/').to_owned()
+                + "/auth/"
+                + provider.name.as_str()
+                + "/callback",
         )
-        .set_redirect_uri(
-            oauth2::RedirectUrl::new(
-                bind.to_string().trim_end_matches('/').to_owned()
-                    + "/auth/"
-                    + &provider.name
-                    + "/callback",
-            )
-            .unwrap(),
-        );
+        .unwrap();
+        let client = new_client(&provider, redirect_url.clone())?;
+        let current_client = {
+            let provider = provider.clone();
+            move || new_client(&provider, redirect_url.clone())
+        };
+        let scopes = provider.scopes.clone();
 
         router = router.nest(
-            ("/auth/".to_owned() + &provider.name).as_str(),
+            ("/auth/".to_owned() + provider.name.as_str()).as_str(),
             Router::new()
                 .route(
                     "/login",
                     routing::get(
-                        |Extension(client): Extension<oauth2::basic::BasicClient>,
+                        |Extension(ctx): Extension<Arc<Ctx>>,
+                         Extension(client): Extension<oauth2::basic::BasicClient>,
                          cookies: Cookies,
-                         Extension(store): Extension<MemoryStore>| async move {
+                         Extension(store): Extension<SharedSessionStore>,
+                         query: Query<AuthRedirect>| async move {
                             let (pkce_challenge, pkce_verifier) =
                                 PkceCodeChallenge::new_random_sha256();
 
@@ -92,9 +145,7 @@ pub(super) fn add_provider_routes(ctx: Arc<Ctx>, mut router: Router) -> eyre::Re
                                 let mut client = client.authorize_url(CsrfToken::new_random);
 
                                 // Add scopes from provider config
-                                for scope in
-                                    provider.scopes.split(' ').filter(|scope| !scope.is_empty())
-                                {
+                                for scope in scopes.split(' ').filter(|scope| !scope.is_empty()) {
                                     client = client.add_scope(Scope::new(scope.into()));
                                 }
 
@@ -113,13 +164,31 @@ pub(super) fn add_provider_routes(ctx: Arc<Ctx>, mut router: Router) -> eyre::Re
                                 )
                                 .unwrap();
 
+                            // Remember where to go after the callback
+                            session
+                                .insert(
+                                    "redirect_to",
+                                    ctx.paths.redirect_target(query.redirect_to.as_deref()),
+                                )
+                                .unwrap();
+
                             // Store session, set cookie
-                            let cookie = store.store_session(session).await.unwrap().unwrap();
-                            cookies.add(Cookie::build(SESSION_COOKIE_NAME, cookie).finish());
+                            let cookie = match store.store_session(session).await {
+                                Ok(cookie) => cookie.unwrap(),
+                                Err(err) => {
+                                    error!(%err, "could not store oauth2 session");
+                                    return Err((
+                                        StatusCode::SERVICE_UNAVAILABLE,
+                                        "Could not start login",
+                                    )
+                                        .into_response());
+                                }
+                            };
+                            cookies.add(ctx.config.session_cookies.oauth_cookie(cookie));
 
                             // Redirect to identity provider
                             debug!(url = %auth_url, "Redirecting to identity provider");
-                            Redirect::to(auth_url.as_str())
+                            Ok(Redirect::to(auth_url.as_str()))
                         },
                     ),
                 )
@@ -127,13 +196,13 @@ pub(super) fn add_provider_routes(ctx: Arc<Ctx>, mut router: Router) -> eyre::Re
                     "/callback",
                     routing::get(
                         move |Extension(ctx): Extension<Arc<Ctx>>,
-                              Extension(client): Extension<oauth2::basic::BasicClient>,
                               cookies: Cookies,
-                              Extension(store): Extension<MemoryStore>,
+                              Extension(store): Extension<SharedSessionStore>,
+                              login_client: LoginClient,
                               query: Query<CallbackQuery>| async move {
                             // Get the cookie
                             let session_cookie =
-                                cookies.get(SESSION_COOKIE_NAME).ok_or_else(|| {
+                                cookies.get(ctx.config.session_cookies.oauth_name()).ok_or_else(|| {
                                     (StatusCode::BAD_REQUEST, "Missing session cookie")
                                         .into_response()
                                 })?;
@@ -160,11 +229,18 @@ pub(super) fn add_provider_routes(ctx: Arc<Ctx>, mut router: Router) -> eyre::Re
                                 );
                             }
 
+                            // Build a client with the current secret, which may have been rotated
+                            let client = current_client().map_err(|err| {
+                                error!(%err, "could not read oauth2 client secret");
+                                (StatusCode::SERVICE_UNAVAILABLE, "Could not fetch token")
+                                    .into_response()
+                            })?;
+
                             // Fetch access token
                             let token_result = client
                                 .exchange_code(query.code.clone())
                                 .set_pkce_verifier(challenge.pkce_verifier)
-                                .request_async(oauth2::reqwest::async_http_client)
+                                .request_async(|request| oauth2_request(&ctx.http_client, request))
                                 .await
                                 .map_err(|err| {
                                     error!(%err, "could not fetch oauth2 access token");
@@ -173,8 +249,8 @@ pub(super) fn add_provider_routes(ctx: Arc<Ctx>, mut router: Router) -> eyre::Re
                                 })?;
 
                             // Get userinfo
-                            let client = reqwest::Client::new();
-                            let res = client
+                            let res = ctx
+                                .http_client
                                 .get(provider.userinfo_url.as_ref())
                                 .bearer_auth(token_result.access_token().secret())
                                 .header(ACCEPT, "application/json")
@@ -208,24 +284,74 @@ pub(super) fn add_provider_routes(ctx: Arc<Ctx>, mut router: Router) -> eyre::Re
                                 })?
                                 .to_string();
 
-                            // Add claim to JWT
-                            let claim = UserClaim::OAuth2 {
-                                username,
+                            // Reject users whose email wasn't verified, who could claim any email
+                            let verified = verified_selector.as_ref().map_or(true, |selector| {
+                                selector.find(&body).next().map_or(false, |value| {
+                                    value.as_bool() == Some(true) || value.as_str() == Some("true")
+                                })
+                            });
+                            if !verified {
+                                info!(%username, provider = %provider.name, "email not verified by the provider");
+                                sessions::record_failure(
+                                    &ctx,
+                                    &UserClaim::OAuth2 {
+                                        username,
+                                        provider: provider.name.clone(),
+                                    },
+                                    &provider.name,
+                                    &login_client,
+                                );
+                                return Err((StatusCode::FORBIDDEN, "This email is not verified")
+                                    .into_response());
+                            }
+
+                            // Reject users outside of the allowlists before they get a session
+                            if !provider.allows(&username) {
+                                info!(%username, provider = %provider.name, "email not allowed by the provider allowlists");
+                                sessions::record_failure(
+                                    &ctx,
+                                    &UserClaim::OAuth2 {
+                                        username,
+                                        provider: provider.name.clone(),
+                                    },
+                                    &provider.name,
+                                    &login_client,
+                                );
+                                return Err((StatusCode::FORBIDDEN, "This account is not allowed")
+                                    .into_response());
+                            }
+
+                            // Create the ACL of first-time users
+                            ctx.provision(&ctx.config.users.link(AuthUser::OAuth2 {
+                                username: username.clone(),
                                 provider: provider.name.clone(),
-                            };
+                            }));
+
+                            // Add claim to JWT
+                            let claim = sessions::start(
+                                &ctx,
+                                UserClaim::OAuth2 {
+                                    username,
+                                    provider: provider.name.clone(),
+                                },
+                                &provider.name,
+                                &login_client,
+                            );
 
                             cookies.add(
-                                Cookie::build(COOKIE_NAME, claim.jwt(&ctx.jwt_key))
-                                    .same_site(cookie::SameSite::Strict)
-                                    .http_only(true)
+                                ctx.config
+                                    .session_cookies
+                                    .login_cookie(claim.jwt(&ctx.jwt_key()))
                                     .path(bind.path().to_string())
                                     .finish(),
                             );
 
                             // Redirect to application
-                            let url = ctx.paths.web_path.as_str();
+                            let url = session
+                                .get::<String>("redirect_to")
+                                .unwrap_or_else(|| ctx.paths.web_path.clone());
                             debug!(%url, "Redirecting to application");
-                            Ok::<_, Response>(Redirect::to(url).into_response())
+                            Ok::<_, Response>(Redirect::to(&url).into_response())
                         },
                     ),
                 )
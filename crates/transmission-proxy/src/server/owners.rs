@@ -0,0 +1,298 @@
+//! Admin overview of the torrents of all users, grouped by owner
+
+use std::{borrow::Cow, collections::BTreeMap, sync::Arc};
+
+use axum::{
+    extract::Query,
+    response::{IntoResponse, Redirect, Response},
+    Extension, Json,
+};
+use hyper::{header::ACCEPT, HeaderMap, StatusCode};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tracing::{error, info, warn};
+
+use crate::{
+    ownership::Attribution,
+    rpc::{
+        MethodCall, MethodName, Torrent, TorrentAction, TorrentGet, TorrentId, TorrentIds,
+        TorrentRemove, TorrentStatus,
+    },
+};
+
+use super::{
+    context::RequestContext,
+    views::{self, Views},
+    Ctx,
+};
+
+const FIELDS: &[&str] = &[
+    "id",
+    "name",
+    "hashString",
+    "downloadDir",
+    "labels",
+    "status",
+    "sizeWhenDone",
+];
+
+#[derive(Debug, Serialize)]
+struct OwnedTorrent {
+    id: Option<TorrentId>,
+    name: String,
+    hash_string: Option<String>,
+    status: Option<TorrentStatus>,
+    size_when_done: i64,
+    download_dir: Option<String>,
+}
+
+/// Torrents attributed to one user, with their totals
+#[derive(Debug, Serialize)]
+struct OwnerGroup {
+    /// None for the torrents attributed to no user
+    owner: Option<String>,
+    count: usize,
+    size_when_done: i64,
+    /// Number of torrents which are not stopped
+    active: usize,
+    torrents: Vec<OwnedTorrent>,
+}
+
+/// All torrents of the upstream, by owner
+async fn torrents_by_owner(ctx: &Ctx) -> Result<BTreeMap<Option<String>, Vec<Torrent>>, Response> {
+    let torrents = ctx
+        .client
+        .torrent_get(TorrentGet {
+            fields: FIELDS.iter().map(|field| Cow::Borrowed(*field)).collect(),
+            ..Default::default()
+        })
+        .await
+        .map_err(|err| {
+            warn!(%err, "could not fetch torrents");
+            StatusCode::BAD_GATEWAY.into_response()
+        })?;
+
+    let attribution = Attribution::new(
+        &ctx.config.acl,
+        &ctx.config.owner_labels,
+        ctx.provisioned.list(),
+    );
+    let mut owners: BTreeMap<_, Vec<_>> = BTreeMap::new();
+    for torrent in torrents.torrents {
+        owners
+            .entry(attribution.owner(&torrent))
+            .or_default()
+            .push(torrent);
+    }
+
+    Ok(owners)
+}
+
+fn format_size(bytes: i64) -> String {
+    const UNITS: &[&str] = &["B", "kB", "MB", "GB", "TB"];
+
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1000. && unit < UNITS.len() - 1 {
+        size /= 1000.;
+        unit += 1;
+    }
+
+    if unit == 0 {
+        format!("{bytes} B")
+    } else {
+        format!("{size:.1} {}", UNITS[unit])
+    }
+}
+
+fn accepts_html(headers: &HeaderMap) -> bool {
+    headers
+        .get(ACCEPT)
+        .and_then(|accept| accept.to_str().ok())
+        .map_or(false, |accept| accept.contains("text/html"))
+}
+
+/// Torrents grouped by owner with their totals, as JSON or as a page for browsers
+pub(super) async fn overview(
+    Extension(ctx): Extension<Arc<Ctx>>,
+    request: RequestContext,
+    headers: HeaderMap,
+) -> Response {
+    if !request.is_admin() {
+        return StatusCode::FORBIDDEN.into_response();
+    }
+
+    let owners = match torrents_by_owner(&ctx).await {
+        Ok(owners) => owners,
+        Err(response) => return response,
+    };
+
+    let groups: Vec<OwnerGroup> = owners
+        .into_iter()
+        .map(|(owner, torrents)| OwnerGroup {
+            owner,
+            count: torrents.len(),
+            size_when_done: torrents
+                .iter()
+                .filter_map(|torrent| torrent.size_when_done)
+                .sum(),
+            active: torrents
+                .iter()
+                .filter(|torrent| torrent.status != Some(TorrentStatus::Stopped))
+                .count(),
+            torrents: torrents
+                .into_iter()
+                .map(|torrent| OwnedTorrent {
+                    id: torrent.id,
+                    name: torrent.name,
+                    hash_string: torrent.hash_string,
+                    status: torrent.status,
+                    size_when_done: torrent.size_when_done.unwrap_or_default(),
+                    download_dir: torrent.download_dir,
+                })
+                .collect(),
+        })
+        .collect();
+
+    if !(accepts_html(&headers) && Views::ENABLED) {
+        return Json(groups).into_response();
+    }
+
+    let data = views::owners::Data {
+        owners: groups
+            .into_iter()
+            .map(|group| views::owners::Owner {
+                owner: group.owner,
+                count: group.count,
+                size: format_size(group.size_when_done),
+                active: group.active,
+                torrents: group
+                    .torrents
+                    .into_iter()
+                    .map(|torrent| views::owners::Torrent {
+                        name: torrent.name,
+                        status: views::status_label(torrent.status).to_owned(),
+                        size: format_size(torrent.size_when_done),
+                        download_dir: torrent.download_dir,
+                    })
+                    .collect(),
+            })
+            .collect(),
+    };
+
+    match ctx.views.render(&data) {
+        Ok(response) => response.into_response(),
+        Err(err) => {
+            error!(%err, "could not render owners page");
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub(super) struct OwnerQuery {
+    /// Owner whose torrents are affected, the unattributed torrents if unset
+    #[serde(default)]
+    owner: Option<String>,
+    /// Delete the data of removed torrents
+    #[serde(default)]
+    delete_local_data: bool,
+}
+
+/// Apply an action to all the torrents of an owner, returning the ids of the affected torrents
+async fn apply(
+    ctx: &Ctx,
+    request: &RequestContext,
+    headers: &HeaderMap,
+    query: OwnerQuery,
+    action: impl FnOnce(TorrentIds) -> MethodCall,
+) -> Response {
+    if !request.is_admin() {
+        return StatusCode::FORBIDDEN.into_response();
+    }
+
+    let ids: Vec<i32> = match torrents_by_owner(ctx).await {
+        Ok(mut owners) => owners
+            .remove(&query.owner)
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|torrent| match torrent.id {
+                Some(TorrentId::Id(id)) => Some(id),
+                _ => None,
+            })
+            .collect(),
+        Err(response) => return response,
+    };
+
+    // Empty ids would select all torrents
+    if !ids.is_empty() {
+        let call = action(TorrentIds::Ids(
+            ids.iter().copied().map(TorrentId::Id).collect(),
+        ));
+        let method = MethodName::from(&call);
+
+        match ctx.client.call(call).await {
+            Ok(response) if response.result.is_success() => {
+                if method == MethodName::TorrentRemove {
+                    ctx.client.index().remove(ids.iter().copied());
+                }
+
+                info!(
+                    user = request.user.username(),
+                    owner = query.owner,
+                    ?method,
+                    count = ids.len(),
+                    "applied action to the torrents of an owner"
+                );
+            }
+            Ok(response) => {
+                warn!(result = ?response.result, "upstream refused action on torrents");
+                return StatusCode::BAD_GATEWAY.into_response();
+            }
+            Err(err) => {
+                warn!(%err, "could not apply action on torrents");
+                return StatusCode::BAD_GATEWAY.into_response();
+            }
+        }
+    }
+
+    if accepts_html(headers) {
+        Redirect::to(&(ctx.paths.base_path.clone() + "/admin/owners")).into_response()
+    } else {
+        Json(json!({ "ids": ids })).into_response()
+    }
+}
+
+/// Pause all the torrents of an owner
+pub(super) async fn stop(
+    Extension(ctx): Extension<Arc<Ctx>>,
+    request: RequestContext,
+    headers: HeaderMap,
+    Query(query): Query<OwnerQuery>,
+) -> Response {
+    apply(&ctx, &request, &headers, query, |ids| {
+        MethodCall::TorrentStop {
+            arguments: TorrentAction { ids: Some(ids) },
+        }
+    })
+    .await
+}
+
+/// Remove all the torrents of an owner
+pub(super) async fn remove(
+    Extension(ctx): Extension<Arc<Ctx>>,
+    request: RequestContext,
+    headers: HeaderMap,
+    Query(query): Query<OwnerQuery>,
+) -> Response {
+    let delete_local_data = query.delete_local_data;
+    apply(&ctx, &request, &headers, query, |ids| {
+        MethodCall::TorrentRemove {
+            arguments: TorrentRemove {
+                ids: Some(ids),
+                delete_local_data: Some(delete_local_data),
+            },
+        }
+    })
+    .await
+}
@@ -1,34 +1,64 @@
-use std::sync::Arc;
+use std::{
+    convert::Infallible,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
 use axum::{
-    extract::Query,
-    response::{IntoResponse, Redirect},
-    Extension,
+    body::Bytes,
+    extract::{OriginalUri, Path, Query},
+    middleware::Next,
+    response::{
+        sse::{self, Sse},
+        IntoResponse, Redirect,
+    },
+    Extension, Form, Json,
 };
-use cookie::{time::OffsetDateTime, Cookie};
+use cookie::time::OffsetDateTime;
 use hyper::{
-    header::{USER_AGENT, WWW_AUTHENTICATE},
-    Body, Request, Response,
+    body::HttpBody,
+    header::{
+        HeaderValue, ACCEPT_ENCODING, ACCESS_CONTROL_REQUEST_METHOD, CONTENT_ENCODING,
+        CONTENT_LENGTH, CONTENT_TYPE, HOST, ORIGIN, USER_AGENT, VARY, WWW_AUTHENTICATE,
+    },
+    Body, HeaderMap, Method, Request, Response, StatusCode, Uri,
 };
 use serde::{Deserialize, Serialize};
 use tower_cookies::Cookies;
-use tracing::{debug, warn};
+use tracing::{debug, error, info, warn};
 
-use crate::{auth::AuthUser, Args};
+use crate::{
+    acl::AclIdentity,
+    auth::AuthUser,
+    custom_routes, events,
+    explain::{self, Decision, EXPLAIN_HEADER},
+    rpc::{filter, proxy::SESSION_ID_HEADER},
+    snapshot::{Snapshot, SnapshotError},
+    torrent::Metadata,
+    uploads::UploadError,
+    Args,
+};
 
 use super::{
-    auth::{UserClaim, COOKIE_NAME},
-    views, Ctx,
+    auth::{AuthenticationError, CookieAuth, UserClaim},
+    context::RequestContext,
+    sessions::{self, LoginClient},
+    views::{self, RenderError, Views},
+    Ctx,
 };
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AuthRedirect {
-    redirect_to: Option<String>,
+    pub redirect_to: Option<String>,
 }
 
 pub struct Paths {
     pub login_path: String,
+    pub logout_path: String,
     pub web_path: String,
+    pub rpc_path: String,
+    pub(super) base_path: String,
+    public_url: Uri,
 }
 
 impl Paths {
@@ -37,9 +67,72 @@ impl Paths {
 
         Self {
             login_path: base.to_owned() + "/login",
+            logout_path: base.to_owned() + "/logout",
             web_path: base.to_owned() + "/web/",
+            rpc_path: base.to_owned() + "/rpc",
+            base_path: base.to_owned(),
+            public_url: args.public_url(),
         }
     }
+
+    /// Returns true if the path is the RPC endpoint or an API endpoint
+    pub fn is_api(&self, path: &str) -> bool {
+        path == self.rpc_path
+            || path == self.base_path.clone() + "/inspect"
+            || path == self.base_path.clone() + "/uploads"
+            || path == self.base_path.clone() + "/shares"
+            || path == self.base_path.clone() + "/export"
+            || path == self.base_path.clone() + "/torrents/stream"
+            || path.starts_with(&(self.base_path.clone() + "/uploads/"))
+            || path.starts_with(&(self.base_path.clone() + "/stats/"))
+            || path.starts_with(&(self.base_path.clone() + "/admin/"))
+    }
+
+    /// Validate a redirection target. Only paths served by the proxy are allowed, to prevent open
+    /// redirects, otherwise this returns the web interface path.
+    pub fn redirect_target(&self, redirect_to: Option<&str>) -> String {
+        redirect_to
+            .and_then(|redirect_to| self.validate_redirect(redirect_to))
+            .unwrap_or_else(|| self.web_path.clone())
+    }
+
+    fn validate_redirect(&self, redirect_to: &str) -> Option<String> {
+        // Backslashes are treated as slashes by some browsers
+        if redirect_to.contains('\\') {
+            return None;
+        }
+
+        let uri: Uri = redirect_to.parse().ok()?;
+
+        // Absolute URLs must point to the proxy itself
+        if uri.scheme().is_some() || uri.authority().is_some() {
+            if uri.scheme() != self.public_url.scheme()
+                || uri.authority() != self.public_url.authority()
+            {
+                return None;
+            }
+        } else if !redirect_to.starts_with('/') || redirect_to.starts_with("//") {
+            return None;
+        }
+
+        let path = uri.path();
+        if !(path == self.base_path
+            || path.starts_with(&(self.base_path.clone() + "/"))
+            || self.base_path.is_empty() && path.starts_with('/'))
+        {
+            return None;
+        }
+
+        // Keep the fragment of relative targets, so deep links into the web interface work
+        let fragment = redirect_to
+            .find('#')
+            .filter(|_| uri.authority().is_none())
+            .map(|start| &redirect_to[start..])
+            .unwrap_or_default();
+
+        uri.path_and_query()
+            .map(|path_and_query| path_and_query.as_str().to_owned() + fragment)
+    }
 }
 
 pub(super) async fn default(Extension(ctx): Extension<Arc<Ctx>>) -> impl IntoResponse {
@@ -50,40 +143,280 @@ pub(super) async fn default(Extension(ctx): Extension<Arc<Ctx>>) -> impl IntoRes
     Redirect::to(&url)
 }
 
+/// Add the configured security headers to responses
+pub(super) async fn security_headers(
+    Extension(ctx): Extension<Arc<Ctx>>,
+    req: Request<Body>,
+    next: Next<Body>,
+) -> axum::response::Response {
+    // Match against the full path, also when running inside the nested router
+    let path = req
+        .extensions()
+        .get::<OriginalUri>()
+        .map_or(req.uri(), |uri| &uri.0)
+        .path()
+        .to_owned();
+    let mut response = next.run(req).await;
+
+    ctx.config.security_headers.apply(
+        &path,
+        &ctx.paths.web_path,
+        ctx.args.public_url().scheme_str() == Some("https"),
+        response.headers_mut(),
+    );
+
+    response
+}
+
+/// Log the requests to the routes selected by the request log
+pub(super) async fn request_log(
+    Extension(ctx): Extension<Arc<Ctx>>,
+    req: Request<Body>,
+    next: Next<Body>,
+) -> axum::response::Response {
+    let log = &ctx.config.request_log;
+    let Some(route) = log.route(req.uri().path()) else {
+        return next.run(req).await;
+    };
+
+    let start = Instant::now();
+    let mut entry = log.entry(route, &req);
+    if let Some(request) = req.extensions().get::<RequestContext>() {
+        entry.id = Some(request.id);
+        entry.user = request.user.username().map(ToOwned::to_owned);
+    }
+
+    let response = next.run(req).await;
+    entry.status = response.status().as_u16();
+    entry.duration_ms = start.elapsed().as_millis().try_into().unwrap_or(u64::MAX);
+    log.record(entry);
+
+    response
+}
+
+/// Reject the state-changing requests authenticated by the session cookie which don't come from
+/// the proxied web interface, as browsers send the cookie along with cross-site requests
+pub(super) async fn csrf(
+    Extension(ctx): Extension<Arc<Ctx>>,
+    req: Request<Body>,
+    next: Next<Body>,
+) -> axum::response::Response {
+    let safe = matches!(*req.method(), Method::GET | Method::HEAD | Method::OPTIONS);
+
+    if !safe
+        && req.extensions().get::<CookieAuth>().is_some()
+        && !ctx
+            .config
+            .csrf
+            .allows(&req, &ctx.args.public_url(), &ctx.config.cors)
+    {
+        return (StatusCode::FORBIDDEN, "Cross-site request rejected").into_response();
+    }
+
+    next.run(req).await
+}
+
+/// Response extension marking streamed responses, which are not buffered for compression
+#[derive(Debug, Clone, Copy)]
+pub(super) struct Streamed;
+
+/// Compress the responses of the RPC and API endpoints, for clients which accept it
+pub(super) async fn compression(
+    Extension(ctx): Extension<Arc<Ctx>>,
+    req: Request<Body>,
+    next: Next<Body>,
+) -> axum::response::Response {
+    let config = &ctx.config.compression;
+    let encoding = req
+        .headers()
+        .get(ACCEPT_ENCODING)
+        .and_then(|value| value.to_str().ok())
+        .filter(|_| config.enabled && ctx.paths.is_api(req.uri().path()))
+        .and_then(|accept_encoding| config.negotiate(accept_encoding));
+
+    let Some(encoding) = encoding else {
+        return next.run(req).await;
+    };
+
+    let response = next.run(req).await;
+    if response.headers().contains_key(CONTENT_ENCODING)
+        || response.extensions().get::<Streamed>().is_some()
+    {
+        return response;
+    }
+
+    let (mut parts, body) = response.into_parts();
+    let bytes = match hyper::body::to_bytes(body).await {
+        Ok(bytes) => bytes,
+        Err(err) => {
+            warn!(%err, "could not read response to compress");
+            return StatusCode::BAD_GATEWAY.into_response();
+        }
+    };
+
+    parts
+        .headers
+        .append(VARY, HeaderValue::from_static("accept-encoding"));
+
+    if bytes.len() < config.min_size {
+        return Response::from_parts(parts, axum::body::boxed(Body::from(bytes)));
+    }
+
+    let compressor = ctx.clone();
+    let compressed = tokio::task::spawn_blocking(move || {
+        compressor.config.compression.compress(encoding, &bytes)
+    })
+    .await;
+
+    match compressed {
+        Ok(Ok(compressed)) => {
+            parts.headers.insert(
+                CONTENT_ENCODING,
+                HeaderValue::from_static(encoding.as_str()),
+            );
+            parts.headers.remove(CONTENT_LENGTH);
+            Response::from_parts(parts, axum::body::boxed(Body::from(compressed)))
+        }
+        Ok(Err(err)) => {
+            error!(%err, "could not compress response");
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+        Err(err) => {
+            error!(%err, "could not compress response");
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+/// Give up on proxied requests the upstream takes too long to answer. Dropping the request
+/// aborts it, as when the client disconnects.
+pub(super) async fn deadline(
+    Extension(ctx): Extension<Arc<Ctx>>,
+    req: Request<Body>,
+    next: Next<Body>,
+) -> axum::response::Response {
+    let Some(deadline) = ctx.config.listener.request_deadline else {
+        return next.run(req).await;
+    };
+
+    let path = req.uri().path().to_owned();
+    match tokio::time::timeout(Duration::from_secs(deadline), next.run(req)).await {
+        Ok(response) => response,
+        Err(_) => {
+            warn!(%path, deadline, "request deadline exceeded");
+            (
+                StatusCode::GATEWAY_TIMEOUT,
+                "The upstream took too long to answer",
+            )
+                .into_response()
+        }
+    }
+}
+
+/// Answer CORS preflight requests and add CORS headers to RPC and API responses
+pub(super) async fn cors(
+    Extension(ctx): Extension<Arc<Ctx>>,
+    req: Request<Body>,
+    next: Next<Body>,
+) -> axum::response::Response {
+    let origin = req
+        .headers()
+        .get(ORIGIN)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_owned);
+
+    let origin = match origin {
+        Some(origin) if ctx.paths.is_api(req.uri().path()) => origin,
+        _ => return next.run(req).await,
+    };
+
+    if req.method() == Method::OPTIONS && req.headers().contains_key(ACCESS_CONTROL_REQUEST_METHOD)
+    {
+        let mut response = StatusCode::NO_CONTENT.into_response();
+        if !ctx.config.cors.apply(&origin, true, response.headers_mut()) {
+            debug!(%origin, "rejected cors preflight");
+            return StatusCode::FORBIDDEN.into_response();
+        }
+
+        return response;
+    }
+
+    let mut response = next.run(req).await;
+    ctx.config
+        .cors
+        .apply(&origin, false, response.headers_mut());
+    response
+}
+
 pub(super) async fn healthz() {
     // empty
 }
 
+/// Readiness check: succeeds only if the upstream daemon answers RPC calls
+pub(super) async fn readyz(Extension(ctx): Extension<Arc<Ctx>>) -> impl IntoResponse {
+    match ctx.client.session_stats().await {
+        Ok(stats) => Json(serde_json::json!({
+            "upstream": "ok",
+            "torrents": stats.torrent_count,
+        }))
+        .into_response(),
+        Err(err) => {
+            warn!(%err, "upstream is not ready");
+            StatusCode::SERVICE_UNAVAILABLE.into_response()
+        }
+    }
+}
+
 pub(super) async fn login(
     Extension(ctx): Extension<Arc<Ctx>>,
     query: Query<AuthRedirect>,
-    user: AuthUser,
+    request: RequestContext,
 ) -> impl IntoResponse {
-    if user.is_anonymous() {
+    if request.user.is_anonymous() && !Views::ENABLED {
+        // Without a login page, send users straight to the basic auth prompt
+        let url = ctx.paths.base_path.clone()
+            + "/auth/basic?redirect_to="
+            + urlencoding::encode(&ctx.paths.redirect_target(query.redirect_to.as_deref()))
+                .as_ref();
+        Redirect::to(&url).into_response()
+    } else if request.user.is_anonymous() {
         ctx.views
             .render(&views::login::Data {
                 config: &ctx.config,
-                redirect_to: query.redirect_to.clone(),
+                redirect_to: Some(ctx.paths.redirect_target(query.redirect_to.as_deref())),
             })
             .unwrap()
             .into_response()
     } else {
         // Authenticated, redirect
-        let url = query
-            .redirect_to
-            .as_deref()
-            .unwrap_or(ctx.paths.web_path.as_str());
+        let url = ctx.paths.redirect_target(query.redirect_to.as_deref());
         debug!(%url, "Redirecting authenticated user");
-        Redirect::to(url).into_response()
+        Redirect::to(&url).into_response()
     }
 }
 
+/// Login page reached through a form, for clients which can't put the target in the URL
+pub(super) async fn login_form(
+    ctx: Extension<Arc<Ctx>>,
+    request: RequestContext,
+    form: Form<AuthRedirect>,
+) -> impl IntoResponse {
+    login(ctx, Query(form.0), request).await
+}
+
 pub(super) async fn logout(
     Extension(ctx): Extension<Arc<Ctx>>,
     cookies: Cookies,
+    cookie_auth: Option<Extension<CookieAuth>>,
 ) -> impl IntoResponse {
+    if let Some(sid) = cookie_auth.and_then(|Extension(auth)| auth.sid) {
+        sessions::revoke(&ctx, &sid);
+    }
+
     cookies.add(
-        Cookie::build(COOKIE_NAME, "")
+        ctx.config
+            .session_cookies
+            .login_cookie(String::new())
             .expires(OffsetDateTime::now_utc() - cookie::time::Duration::new(60, 0))
             .finish(),
     );
@@ -97,83 +430,766 @@ pub(super) async fn auth_basic(
     Extension(ctx): Extension<Arc<Ctx>>,
     query: Query<AuthRedirect>,
     cookies: Cookies,
-    user: AuthUser,
+    client: LoginClient,
+    user: Result<AuthUser, AuthenticationError>,
 ) -> impl IntoResponse {
-    if user.is_anonymous() {
+    match user {
         // Not authenticated
-        Response::builder()
-            .status(401)
-            .header(
-                WWW_AUTHENTICATE,
-                r#"Basic realm="Transmission", charset="UTF-8""#.to_owned(),
-            )
-            .body(Body::empty())
-            .unwrap()
-            .into_response()
-    } else {
+        Ok(AuthUser::Anonymous) => basic_challenge(),
         // Authenticated, redirect
-        cookies.add(
-            Cookie::build(
-                COOKIE_NAME,
-                UserClaim::from_auth_user(&user).unwrap().jwt(&ctx.jwt_key),
-            )
-            .same_site(cookie::SameSite::Strict)
-            .http_only(true)
+        Ok(user) => authenticated(&ctx, &cookies, &user, &client, query.redirect_to.as_deref()),
+        // Valid password, ask for the second factor
+        Err(AuthenticationError::TotpRequired(_)) => totp_page(&ctx, query.0.redirect_to, false),
+        Err(err) => {
+            if let AuthenticationError::InvalidCredentials(username) = &err {
+                basic_failure(&ctx, username, &client);
+            }
+
+            err.into_response()
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TotpForm {
+    pub code: String,
+    pub redirect_to: Option<String>,
+}
+
+/// Second step of the basic auth login, for users with a TOTP secret
+pub(super) async fn auth_basic_totp(
+    Extension(ctx): Extension<Arc<Ctx>>,
+    cookies: Cookies,
+    client: LoginClient,
+    user: Result<AuthUser, AuthenticationError>,
+    Form(form): Form<TotpForm>,
+) -> impl IntoResponse {
+    match user {
+        Ok(AuthUser::Anonymous) => basic_challenge(),
+        Ok(user) => authenticated(&ctx, &cookies, &user, &client, form.redirect_to.as_deref()),
+        Err(AuthenticationError::TotpRequired(username)) => {
+            if ctx
+                .config
+                .providers
+                .basic
+                .verify_totp(&username, &form.code)
+            {
+                let user = AuthUser::Basic {
+                    username,
+                    password: None,
+                };
+                authenticated(&ctx, &cookies, &user, &client, form.redirect_to.as_deref())
+            } else {
+                warn!(%username, "invalid totp code");
+                basic_failure(&ctx, &username, &client);
+                totp_page(&ctx, form.redirect_to, true)
+            }
+        }
+        Err(err) => err.into_response(),
+    }
+}
+
+/// Record a failed basic auth login. Unknown usernames are not recorded.
+fn basic_failure(ctx: &Ctx, username: &str, client: &LoginClient) {
+    if ctx.config.providers.basic.has_user(username) {
+        let user = UserClaim::Basic {
+            username: username.to_owned(),
+        };
+        sessions::record_failure(ctx, &user, "basic", client);
+    }
+}
+
+/// Ask the browser for basic auth credentials
+fn basic_challenge() -> axum::response::Response {
+    Response::builder()
+        .status(401)
+        .header(
+            WWW_AUTHENTICATE,
+            r#"Basic realm="Transmission", charset="UTF-8""#.to_owned(),
+        )
+        .body(Body::empty())
+        .unwrap()
+        .into_response()
+}
+
+/// Set the session cookie of an authenticated user, and redirect them to their target
+fn authenticated(
+    ctx: &Ctx,
+    cookies: &Cookies,
+    user: &AuthUser,
+    client: &LoginClient,
+    redirect_to: Option<&str>,
+) -> axum::response::Response {
+    set_session_cookie(ctx, cookies, user, "basic", client);
+
+    let url = ctx.paths.redirect_target(redirect_to);
+    debug!(%url, "Redirecting user after authentication");
+    Redirect::to(&url).into_response()
+}
+
+/// Start a session for a user who logged in with the given provider, and set its cookie
+pub(super) fn set_session_cookie(
+    ctx: &Ctx,
+    cookies: &Cookies,
+    user: &AuthUser,
+    provider: &str,
+    client: &LoginClient,
+) {
+    let claim = sessions::start(
+        ctx,
+        UserClaim::from_auth_user(user).unwrap(),
+        provider,
+        client,
+    );
+
+    cookies.add(
+        ctx.config
+            .session_cookies
+            .login_cookie(claim.jwt(&ctx.jwt_key()))
             .path(ctx.args.public_url().path().to_string())
             .finish(),
-        );
+    );
+}
 
-        let url = query
-            .redirect_to
-            .as_deref()
-            .unwrap_or(ctx.paths.web_path.as_str());
-        debug!(%url, "Redirecting user after authentication");
-        Redirect::to(url).into_response()
+/// Form for the TOTP code of basic auth users
+fn totp_page(ctx: &Ctx, redirect_to: Option<String>, invalid: bool) -> axum::response::Response {
+    let response = ctx.views.render(&views::totp::Data {
+        redirect_to: Some(ctx.paths.redirect_target(redirect_to.as_deref())),
+        invalid,
+    });
+
+    match response {
+        Ok(mut response) => {
+            *response.status_mut() = StatusCode::UNAUTHORIZED;
+            response.into_response()
+        }
+        Err(_) => (StatusCode::UNAUTHORIZED, "two-factor code required").into_response(),
     }
 }
 
-pub(super) async fn proxy_request(
+pub(super) async fn tracker_stats(
+    Extension(ctx): Extension<Arc<Ctx>>,
+    request: RequestContext,
+) -> impl IntoResponse {
+    if !request.is_admin() {
+        return StatusCode::FORBIDDEN.into_response();
+    }
+
+    match ctx.tracker_stats.report().await {
+        Some(report) => Json(report).into_response(),
+        None => StatusCode::SERVICE_UNAVAILABLE.into_response(),
+    }
+}
+
+/// Stream the events of the proxy to admins, as server-sent events
+pub(super) async fn events(
+    Extension(ctx): Extension<Arc<Ctx>>,
+    request: RequestContext,
+) -> impl IntoResponse {
+    if !request.is_admin() {
+        return StatusCode::FORBIDDEN.into_response();
+    }
+
+    let stream = futures_util::stream::unfold(ctx.events.subscribe(), move |mut receiver| {
+        let ctx = ctx.clone();
+        async move {
+            let event = events::next(&mut receiver).await?;
+            let json = ctx.config.request_log.redaction.event(&event).ok()?;
+            let sse = sse::Event::default().event(event.name()).data(json);
+            Some((Ok::<_, Infallible>(sse), receiver))
+        }
+    });
+
+    Sse::new(stream)
+        .keep_alive(sse::KeepAlive::default())
+        .into_response()
+}
+
+/// Upstream latency percentiles of each RPC method
+pub(super) async fn latency(
+    Extension(ctx): Extension<Arc<Ctx>>,
+    request: RequestContext,
+) -> impl IntoResponse {
+    if !request.is_admin() {
+        return StatusCode::FORBIDDEN.into_response();
+    }
+
+    match ctx.client.latency() {
+        Some(latency) => Json(latency.summary()).into_response(),
+        None => StatusCode::NOT_FOUND.into_response(),
+    }
+}
+
+/// Upstream latency percentiles of each RPC method, for Prometheus
+pub(super) async fn metrics(
+    Extension(ctx): Extension<Arc<Ctx>>,
+    request: RequestContext,
+) -> impl IntoResponse {
+    if !request.is_admin() {
+        return StatusCode::FORBIDDEN.into_response();
+    }
+
+    match ctx.client.latency() {
+        Some(latency) => (
+            [(CONTENT_TYPE, "text/plain; version=0.0.4")],
+            latency.prometheus(),
+        )
+            .into_response(),
+        None => StatusCode::NOT_FOUND.into_response(),
+    }
+}
+
+/// Requests recently logged by the request log
+pub(super) async fn requests(
+    Extension(ctx): Extension<Arc<Ctx>>,
+    request: RequestContext,
+) -> impl IntoResponse {
+    if !request.is_admin() {
+        return StatusCode::FORBIDDEN.into_response();
+    }
+
+    Json(ctx.config.request_log.recent()).into_response()
+}
+
+/// Configuration state reported to admins
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ConfigState {
+    pub version: u32,
+    pub lenient: bool,
+    /// Invalid sections which were skipped
+    pub skipped: Vec<String>,
+}
+
+pub(super) async fn config(
+    Extension(ctx): Extension<Arc<Ctx>>,
+    request: RequestContext,
+) -> impl IntoResponse {
+    if !request.is_admin() {
+        return StatusCode::FORBIDDEN.into_response();
+    }
+
+    Json(ConfigState {
+        version: ctx.config.version,
+        lenient: ctx.args.lenient_config,
+        skipped: ctx.config.skipped.clone(),
+    })
+    .into_response()
+}
+
+pub(super) async fn provisioned(
+    Extension(ctx): Extension<Arc<Ctx>>,
+    request: RequestContext,
+) -> impl IntoResponse {
+    if !request.is_admin() {
+        return StatusCode::FORBIDDEN.into_response();
+    }
+
+    Json(ctx.provisioned.list()).into_response()
+}
+
+/// Remove the provisioned ACL of the identity in the body
+pub(super) async fn remove_provisioned(
+    Extension(ctx): Extension<Arc<Ctx>>,
+    request: RequestContext,
+    Json(identity): Json<AclIdentity>,
+) -> impl IntoResponse {
+    if !request.is_admin() {
+        return StatusCode::FORBIDDEN.into_response();
+    }
+
+    match ctx.provisioned.remove(&*ctx.storage, &identity) {
+        Ok(true) => StatusCode::NO_CONTENT.into_response(),
+        Ok(false) => StatusCode::NOT_FOUND.into_response(),
+        Err(err) => {
+            error!(%err, "could not remove provisioned ACL");
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MaintenanceState {
+    pub enabled: bool,
+}
+
+pub(super) async fn maintenance(
     Extension(ctx): Extension<Arc<Ctx>>,
-    user: AuthUser,
+    request: RequestContext,
+) -> impl IntoResponse {
+    if !request.is_admin() {
+        return StatusCode::FORBIDDEN.into_response();
+    }
+
+    Json(MaintenanceState {
+        enabled: ctx.maintenance.is_enabled().await,
+    })
+    .into_response()
+}
+
+pub(super) async fn set_maintenance(
+    Extension(ctx): Extension<Arc<Ctx>>,
+    request: RequestContext,
+    Json(state): Json<MaintenanceState>,
+) -> impl IntoResponse {
+    if !request.is_admin() {
+        return StatusCode::FORBIDDEN.into_response();
+    }
+
+    info!(enabled = state.enabled, user = ?request.user, "maintenance mode changed");
+    if let Err(err) = ctx.maintenance.set_enabled(state.enabled).await {
+        error!(%err, "could not change maintenance mode");
+        return StatusCode::SERVICE_UNAVAILABLE.into_response();
+    }
+
+    Json(state).into_response()
+}
+
+pub(super) async fn snapshot(
+    Extension(ctx): Extension<Arc<Ctx>>,
+    request: RequestContext,
+) -> impl IntoResponse {
+    if !request.is_admin() {
+        return StatusCode::FORBIDDEN.into_response();
+    }
+
+    match Snapshot::collect(
+        &ctx.config,
+        &ctx.provisioned,
+        ctx.client.index(),
+        &*ctx.storage,
+        &ctx.audit,
+    ) {
+        Ok(snapshot) => Json(snapshot).into_response(),
+        Err(err) => {
+            error!(%err, "could not export snapshot");
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+pub(super) async fn restore_snapshot(
+    Extension(ctx): Extension<Arc<Ctx>>,
+    request: RequestContext,
+    Json(snapshot): Json<Snapshot>,
+) -> impl IntoResponse {
+    if !request.is_admin() {
+        return StatusCode::FORBIDDEN.into_response();
+    }
+
+    match snapshot.restore(ctx.client.index(), &*ctx.storage, &ctx.audit) {
+        Ok(summary) => {
+            info!(torrents = summary.torrents, entries = summary.entries, user = ?request.user, "snapshot restored");
+            Json(summary).into_response()
+        }
+        Err(err @ SnapshotError::UnsupportedVersion(_)) => {
+            (StatusCode::BAD_REQUEST, err.to_string()).into_response()
+        }
+        Err(err) => {
+            error!(%err, "could not restore snapshot");
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+/// Parse a torrent file or magnet link, returning its metadata with the trackers the ACL of the
+/// user would keep
+pub(super) async fn inspect(
+    Extension(ctx): Extension<Arc<Ctx>>,
+    request: RequestContext,
+    body: Bytes,
+) -> impl IntoResponse {
+    if ctx.config.acl.denies(request.acl()) {
+        return if request.user.is_anonymous() {
+            StatusCode::UNAUTHORIZED
+        } else {
+            StatusCode::FORBIDDEN
+        }
+        .into_response();
+    }
+
+    let metadata = if body.starts_with(b"magnet:") {
+        std::str::from_utf8(&body)
+            .ok()
+            .and_then(|magnet| Metadata::from_magnet(magnet.trim()))
+            .ok_or_else(|| "invalid magnet link".to_owned())
+    } else {
+        Metadata::from_metainfo(&body).map_err(|err| err.to_string())
+    };
+
+    let mut metadata = match metadata {
+        Ok(metadata) => metadata,
+        Err(err) => {
+            debug!(%err, "could not inspect torrent");
+            return (StatusCode::BAD_REQUEST, err).into_response();
+        }
+    };
+
+    if let Some(acl) = request.acl() {
+        filter::filter_tracker_list(&mut metadata.trackers, &acl.tracker_rules, &request.user);
+    }
+
+    Json(metadata).into_response()
+}
+
+/// Header with the total size of an upload, when starting it
+const UPLOAD_LENGTH_HEADER: &str = "Upload-Length";
+/// Header with the offset a chunk of an upload starts at
+const UPLOAD_OFFSET_HEADER: &str = "Upload-Offset";
+
+/// Name of the user uploads are stored for, or the response for users who may not upload
+pub(super) fn upload_owner<'r>(
+    ctx: &Ctx,
+    request: &'r RequestContext,
+) -> Result<&'r str, axum::response::Response> {
+    match request.user.username() {
+        Some(username) if !ctx.config.acl.denies(request.acl()) => Ok(username),
+        Some(_) => Err(StatusCode::FORBIDDEN.into_response()),
+        None => Err(StatusCode::UNAUTHORIZED.into_response()),
+    }
+}
+
+fn upload_error(err: UploadError) -> axum::response::Response {
+    debug!(%err, "upload failed");
+    (err.status(), err.to_string()).into_response()
+}
+
+/// Start a resumable upload of a torrent file
+pub(super) async fn create_upload(
+    Extension(ctx): Extension<Arc<Ctx>>,
+    request: RequestContext,
+    headers: HeaderMap,
+) -> axum::response::Response {
+    let owner = match upload_owner(&ctx, &request) {
+        Ok(owner) => owner,
+        Err(response) => return response,
+    };
+
+    let length = match headers.get(UPLOAD_LENGTH_HEADER).map(|value| {
+        value
+            .to_str()
+            .ok()
+            .and_then(|value| value.parse::<u64>().ok())
+    }) {
+        Some(None) => return StatusCode::BAD_REQUEST.into_response(),
+        Some(length) => length,
+        None => None,
+    };
+
+    match ctx.uploads.create(owner, length).await {
+        Ok(status) => (StatusCode::CREATED, Json(status)).into_response(),
+        Err(err) => upload_error(err),
+    }
+}
+
+/// Report how much of an upload was received
+pub(super) async fn upload_status(
+    Extension(ctx): Extension<Arc<Ctx>>,
+    request: RequestContext,
+    Path(id): Path<String>,
+) -> axum::response::Response {
+    let owner = match upload_owner(&ctx, &request) {
+        Ok(owner) => owner,
+        Err(response) => return response,
+    };
+
+    match ctx.uploads.status(&id, owner).await {
+        Ok(status) => Json(status).into_response(),
+        Err(err) => upload_error(err),
+    }
+}
+
+/// Append a chunk to an upload
+pub(super) async fn append_upload(
+    Extension(ctx): Extension<Arc<Ctx>>,
+    request: RequestContext,
+    Path(id): Path<String>,
     req: Request<Body>,
+) -> axum::response::Response {
+    let owner = match upload_owner(&ctx, &request) {
+        Ok(owner) => owner,
+        Err(response) => return response,
+    };
+
+    let Some(offset) = req
+        .headers()
+        .get(UPLOAD_OFFSET_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<u64>().ok())
+    else {
+        return StatusCode::BAD_REQUEST.into_response();
+    };
+
+    match ctx
+        .uploads
+        .append(&id, owner, offset, req.into_body())
+        .await
+    {
+        Ok(status) => Json(status).into_response(),
+        Err(err) => upload_error(err),
+    }
+}
+
+/// Cancel an upload
+pub(super) async fn cancel_upload(
+    Extension(ctx): Extension<Arc<Ctx>>,
+    request: RequestContext,
+    Path(id): Path<String>,
+) -> axum::response::Response {
+    let owner = match upload_owner(&ctx, &request) {
+        Ok(owner) => owner,
+        Err(response) => return response,
+    };
+
+    match ctx.uploads.remove(&id, owner).await {
+        Ok(()) => StatusCode::NO_CONTENT.into_response(),
+        Err(err) => upload_error(err),
+    }
+}
+
+/// Add the torrent of a finished upload. The body holds the other torrent-add arguments, and the
+/// call goes through the same filters as RPC requests.
+pub(super) async fn add_upload(
+    Extension(ctx): Extension<Arc<Ctx>>,
+    request: RequestContext,
+    Path(id): Path<String>,
+    Json(mut arguments): Json<serde_json::Map<String, serde_json::Value>>,
+) -> axum::response::Response {
+    let owner = match upload_owner(&ctx, &request) {
+        Ok(owner) => owner,
+        Err(response) => return response,
+    };
+
+    // The torrent is checked by the filters and the upstream, without decoding it here
+    let file = match ctx.uploads.take(&id, owner).await {
+        Ok(file) => file,
+        Err(err) => return upload_error(err),
+    };
+
+    match file.to_base64().await {
+        Ok(metainfo) => arguments.insert("metainfo".into(), metainfo.into()),
+        Err(err) => return upload_error(err),
+    };
+    drop(file);
+
+    add_torrent(&ctx, &request, arguments).await
+}
+
+/// Add a torrent on behalf of the user, through the filters of their ACL
+pub(super) async fn add_torrent(
+    ctx: &Ctx,
+    request: &RequestContext,
+    arguments: serde_json::Map<String, serde_json::Value>,
+) -> axum::response::Response {
+    let body = serde_json::json!({ "method": "torrent-add", "arguments": arguments });
+    let mut req = Request::post(ctx.paths.rpc_path.as_str())
+        .body(Body::from(body.to_string()))
+        .unwrap();
+    req.extensions_mut()
+        .insert(OriginalUri(ctx.paths.rpc_path.parse().unwrap()));
+
+    match ctx.client.session_id().await {
+        Ok(Some(session_id)) => {
+            req.headers_mut().insert(SESSION_ID_HEADER, session_id);
+        }
+        Ok(None) => {}
+        Err(err) => {
+            warn!(%err, "could not acquire upstream session");
+            return StatusCode::BAD_GATEWAY.into_response();
+        }
+    }
+
+    match ctx.client.handle_request(req, request).await {
+        Ok(response) => response.into_response(),
+        Err(err) => {
+            warn!(%err, "could not add uploaded torrent");
+            StatusCode::BAD_GATEWAY.into_response()
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ExplainRequest {
+    /// Identity to explain the request for, anonymous if not set
+    #[serde(default)]
+    pub identity: Option<AclIdentity>,
+    /// RPC request to explain
+    pub request: serde_json::Value,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Explanation {
+    /// Name of the matched ACL
+    pub acl: Option<String>,
+    /// Filter decisions, in order
+    pub decisions: Vec<Decision>,
+    /// HTTP status of the response
+    pub status: u16,
+    /// Upstream response, or request which would have been forwarded for dry runs
+    pub body: serde_json::Value,
+}
+
+/// Explain the filter decisions for an RPC request on behalf of another user, without forwarding
+/// it upstream
+pub(super) async fn explain(
+    Extension(ctx): Extension<Arc<Ctx>>,
+    admin: RequestContext,
+    Json(explain): Json<ExplainRequest>,
 ) -> impl IntoResponse {
-    // Check authorization
-    let acl = ctx.config.acl.get(&user, &ctx.config.providers).await;
+    if !admin.is_admin() {
+        return StatusCode::FORBIDDEN.into_response();
+    }
 
-    if let Some(acl) = acl {
-        // One ACL rule matched
-        debug!(?acl, ?user, "matched acl");
+    // Logical users are explained as their first identity
+    let identity = match explain.identity {
+        Some(AclIdentity::User { name }) => match ctx.config.users.first_identity(&name) {
+            Some(identity) => Some(identity.clone()),
+            None => return (StatusCode::BAD_REQUEST, "unknown user").into_response(),
+        },
+        identity => identity,
+    };
 
-        // Does this rule deny access?
-        if acl.deny {
-            if user.is_anonymous() {
-                if req.headers().get(USER_AGENT).map(|hdr| hdr.as_ref())
-                    == Some(b"transmission-remote-gtk")
-                {
-                    // Unauthenticated client app, this will always use basic auth
-                    return Response::builder()
-                        .status(401)
-                        .header(WWW_AUTHENTICATE, "Basic realm=\"Transmission\"")
-                        .body(Body::empty())
-                        .unwrap()
-                        .into_response();
-                }
+    let user = match identity {
+        None | Some(AclIdentity::User { .. }) => AuthUser::Anonymous,
+        Some(AclIdentity::Basic { name }) => AuthUser::Basic {
+            username: name,
+            password: None,
+        },
+        Some(AclIdentity::OAuth2 { name, oauth2 }) => AuthUser::OAuth2 {
+            username: name,
+            provider: oauth2,
+        },
+    };
 
-                // This is an unauthenticated user, redirect to the login page
-                let url = ctx.paths.login_path.clone()
-                    + "?redirect_to="
-                    + urlencoding::encode(&req.uri().to_string()).as_ref();
-                debug!(%url, "Redirecting unauthenticated user");
-                return Redirect::to(&url).into_response();
-            } else {
-                // This is an authenticated, but not allowed user
-                return Response::builder()
-                    .status(401)
-                    .body(Body::from("Unauthorized"))
-                    .unwrap()
-                    .into_response();
+    let request = RequestContext::new(&ctx, user, admin.client_ip).await;
+
+    let mut req = Request::post(ctx.paths.rpc_path.as_str())
+        .body(Body::from(explain.request.to_string()))
+        .unwrap();
+    req.extensions_mut()
+        .insert(OriginalUri(ctx.paths.rpc_path.parse().unwrap()));
+
+    // Filters query the upstream with the session id of the client
+    match ctx.client.session_id().await {
+        Ok(Some(session_id)) => {
+            req.headers_mut().insert(SESSION_ID_HEADER, session_id);
+        }
+        Ok(None) => {}
+        Err(err) => {
+            warn!(%err, "could not acquire upstream session");
+            return StatusCode::BAD_GATEWAY.into_response();
+        }
+    }
+
+    explain_request(&ctx, req, &request, true).await
+}
+
+/// Run a request through the filters, returning the decisions made instead of the response
+async fn explain_request(
+    ctx: &Ctx,
+    mut req: Request<Body>,
+    request: &RequestContext,
+    dry_run: bool,
+) -> axum::response::Response {
+    let acl_name = request.acl_name.clone();
+    let acl_decision = Decision {
+        check: "acl",
+        allowed: !ctx.config.acl.denies(request.acl()),
+        detail: acl_name
+            .clone()
+            .unwrap_or_else(|| "no matching acl".to_owned()),
+    };
+
+    if !acl_decision.allowed {
+        return Json(Explanation {
+            acl: acl_name,
+            decisions: vec![acl_decision],
+            status: StatusCode::UNAUTHORIZED.as_u16(),
+            body: serde_json::Value::Null,
+        })
+        .into_response();
+    }
+
+    // We need a readable body
+    req.headers_mut().remove(ACCEPT_ENCODING);
+
+    let (result, mut decisions) =
+        explain::scope(dry_run, ctx.client.handle_request(req, request)).await;
+    decisions.insert(0, acl_decision);
+
+    let response = match result {
+        Ok(response) => response,
+        Err(err) => {
+            warn!(%err, "could not explain request");
+            return StatusCode::BAD_GATEWAY.into_response();
+        }
+    };
+
+    let status = response.status().as_u16();
+    let bytes = hyper::body::to_bytes(response.into_body())
+        .await
+        .unwrap_or_default();
+    let body = serde_json::from_slice(&bytes).unwrap_or_else(|_| {
+        serde_json::Value::String(String::from_utf8_lossy(&bytes).into_owned())
+    });
+
+    Json(Explanation {
+        acl: acl_name,
+        decisions,
+        status,
+        body,
+    })
+    .into_response()
+}
+
+/// Response for users while in maintenance mode
+fn maintenance_response(ctx: &Ctx, path: &str) -> axum::response::Response {
+    let message = &ctx.config.maintenance.message;
+
+    let mut response = if ctx.paths.is_api(path) {
+        Json(serde_json::json!({ "result": message })).into_response()
+    } else if !Views::ENABLED {
+        message.clone().into_response()
+    } else {
+        match ctx.views.render(&views::maintenance::Data { message }) {
+            Ok(response) => response.into_response(),
+            Err(err) => {
+                error!(%err, "could not render maintenance page");
+                message.clone().into_response()
             }
         }
+    };
+
+    *response.status_mut() = StatusCode::SERVICE_UNAVAILABLE;
+    response
+}
+
+pub(super) async fn proxy_request(
+    Extension(ctx): Extension<Arc<Ctx>>,
+    request: RequestContext,
+    mut req: Request<Body>,
+) -> impl IntoResponse {
+    let RequestContext {
+        user,
+        acl_name,
+        client_ip,
+        ..
+    } = &request;
+    let acl = request.acl();
+
+    let path = req
+        .extensions()
+        .get::<OriginalUri>()
+        .map_or(req.uri().path(), |uri| uri.0.path())
+        .to_owned();
+
+    let route = custom_routes::find(&ctx.config.routes, &path);
+
+    if let Some(acl) = acl {
+        // One ACL rule matched
+        debug!(?acl, ?user, "matched acl");
+    } else if ctx.config.acl.denies(None) {
+        debug!(?user, "no matched acl, denying access");
     } else {
         // No ACL rules matched, authorize by default
         warn!(
@@ -183,13 +1199,223 @@ pub(super) async fn proxy_request(
         );
     }
 
+    // Does this rule deny access?
+    if ctx.config.acl.denies(acl) || route.map_or(false, |route| !route.allows(acl_name.as_deref()))
+    {
+        if user.is_anonymous() {
+            let user_agent = req.headers().get(USER_AGENT).map(|hdr| hdr.as_ref());
+            if ctx
+                .config
+                .providers
+                .basic
+                .challenges(path == ctx.paths.rpc_path, user_agent)
+                || ctx
+                    .config
+                    .client_profiles
+                    .find(user_agent)
+                    .map_or(false, |profile| profile.basic_auth)
+            {
+                // Unauthenticated client app, this will always use basic auth
+                return Response::builder()
+                    .status(401)
+                    .header(WWW_AUTHENTICATE, "Basic realm=\"Transmission\"")
+                    .body(Body::empty())
+                    .unwrap()
+                    .into_response();
+            }
+
+            // This is an unauthenticated user, redirect to the login page
+            let target = req
+                .extensions()
+                .get::<OriginalUri>()
+                .and_then(|uri| uri.0.path_and_query())
+                .map_or(path.as_str(), |path_and_query| path_and_query.as_str());
+            let url = ctx.paths.login_path.clone()
+                + "?redirect_to="
+                + urlencoding::encode(target).as_ref();
+            debug!(%url, "Redirecting unauthenticated user");
+            return Redirect::to(&url).into_response();
+        } else {
+            // This is an authenticated, but not allowed user
+            return Response::builder()
+                .status(401)
+                .body(Body::from("Unauthorized"))
+                .unwrap()
+                .into_response();
+        }
+    }
+
+    // Only admins may go through while in maintenance
+    if ctx.maintenance.is_enabled().await && !acl.map_or(false, |acl| acl.admin) {
+        return maintenance_response(&ctx, &path);
+    }
+
+    // Identify the user to the upstream
+    ctx.config
+        .identity_headers
+        .apply(&mut req, user, acl_name.as_deref());
+    ctx.config.headers.apply_request(&mut req, *client_ip);
+
+    // Admins may ask for the filter decisions made for their own requests
+    if path == ctx.paths.rpc_path
+        && req.headers().contains_key(EXPLAIN_HEADER)
+        && acl.map_or(false, |acl| acl.admin)
+    {
+        return explain_request(&ctx, req, &request, false).await;
+    }
+
+    // Forward custom routes to their own upstream
+    if let Some(route) = route {
+        *req.uri_mut() = match route.upstream_url(req.uri()) {
+            Ok(uri) => uri,
+            Err(err) => {
+                error!(%err, prefix = %route.prefix, "invalid route upstream");
+                return StatusCode::BAD_GATEWAY.into_response();
+            }
+        };
+        req.headers_mut().remove(HOST);
+        route.strip_credentials(req.headers_mut());
+
+        return match ctx.client.forward(req).await {
+            Ok(mut response) => {
+                ctx.config.headers.apply_response(response.headers_mut());
+                response.into_response()
+            }
+            Err(err) => Response::builder()
+                .status(502)
+                .body(Body::from(err.to_string()))
+                .unwrap()
+                .into_response(),
+        };
+    }
+
+    let is_index = req.method() == Method::GET
+        && (path == ctx.paths.web_path || path == ctx.paths.web_path.clone() + "index.html");
+
+    // Let guests know they can log in for more access, and users that they can log out
+    let fragment = if !is_index || !Views::ENABLED {
+        None
+    } else if let Some(username) = user.username().filter(|_| ctx.config.web_ui.toolbar) {
+        let stats_path = ctx.paths.base_path.clone() + "/stats/trackers";
+        let activity_path = ctx.paths.base_path.clone() + "/account/activity";
+        Some(
+            ctx.views.render_fragment(&views::toolbar::Data {
+                username,
+                activity_path: &activity_path,
+                logout_path: &ctx.paths.logout_path,
+                stats_path: acl
+                    .map_or(false, |acl| acl.admin)
+                    .then_some(stats_path.as_str()),
+            }),
+        )
+    } else if user.is_anonymous() && acl.map_or(false, |acl| acl.read_only) {
+        Some(ctx.views.render_fragment(&views::guest_banner::Data {
+            login_path: &ctx.paths.login_path,
+            redirect_to: &path,
+        }))
+    } else {
+        None
+    };
+
+    if fragment.is_some() {
+        // We need to edit the page
+        req.headers_mut().remove(ACCEPT_ENCODING);
+    }
+
     // Forward to upstream
-    match ctx.client.handle_request(req, acl).await {
-        Ok(response) => response.into_response(),
-        Err(err) => Response::builder()
+    let response = ctx
+        .client
+        .handle_request(req, &request)
+        .await
+        .map(|mut response| {
+            ctx.config.headers.apply_response(response.headers_mut());
+            if let Some(acl) = acl {
+                acl.apply_web_ui_headers(response.headers_mut());
+            }
+            response
+        });
+
+    match (response, fragment) {
+        (Ok(response), Some(fragment)) => insert_fragment(response, fragment).await.into_response(),
+        (Ok(response), None) => response.into_response(),
+        (Err(err), _) => Response::builder()
             .status(500)
             .body(Body::from(err.to_string()))
             .unwrap()
             .into_response(),
     }
 }
+
+/// Largest part of a web page read while looking for its body tag
+const MAX_PAGE_HEAD: usize = 64 * 1024;
+
+/// Position right after the opening body tag of an HTML page
+fn body_start(page: &[u8]) -> Option<usize> {
+    let start = page.windows(5).position(|window| window == b"<body")?;
+    page[start..]
+        .iter()
+        .position(|c| *c == b'>')
+        .map(|end| start + end + 1)
+}
+
+/// Insert a rendered view at the start of an HTML page body. Only the start of the page is read,
+/// the rest is streamed as is.
+async fn insert_fragment(
+    response: Response<Body>,
+    fragment: Result<String, RenderError>,
+) -> Response<Body> {
+    let is_html = response
+        .headers()
+        .get(CONTENT_TYPE)
+        .map_or(false, |value| value.as_bytes().starts_with(b"text/html"));
+
+    if !response.status().is_success() || !is_html {
+        return response;
+    }
+
+    let (mut parts, mut body) = response.into_parts();
+    let mut head = Vec::new();
+    while body_start(&head).is_none() && head.len() < MAX_PAGE_HEAD {
+        match body.data().await {
+            Some(Ok(chunk)) => head.extend_from_slice(&chunk),
+            Some(Err(err)) => {
+                warn!(%err, "could not read web page");
+                return Response::from_parts(parts, Body::empty());
+            }
+            None => break,
+        }
+    }
+
+    match (fragment, body_start(&head)) {
+        (Ok(fragment), Some(position)) => {
+            head.splice(position..position, fragment.into_bytes());
+        }
+        (Err(err), _) => warn!(%err, "could not render web page fragment"),
+        (_, None) => debug!("no body in web page, skipping fragment"),
+    }
+
+    let (mut sender, page) = Body::channel();
+    tokio::spawn(async move {
+        if sender.send_data(head.into()).await.is_err() {
+            return;
+        }
+
+        while let Some(chunk) = body.data().await {
+            match chunk {
+                Ok(chunk) => {
+                    if sender.send_data(chunk).await.is_err() {
+                        return;
+                    }
+                }
+                Err(err) => {
+                    warn!(%err, "could not read web page");
+                    sender.abort();
+                    return;
+                }
+            }
+        }
+    });
+
+    parts.headers.remove(CONTENT_LENGTH);
+    Response::from_parts(parts, page)
+}
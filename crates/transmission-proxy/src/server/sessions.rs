@@ -0,0 +1,527 @@
+//! Login history and active sessions of users, for the account activity page
+
+use std::{
+    convert::Infallible,
+    net::IpAddr,
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use axum::{
+    async_trait,
+    extract::{ConnectInfo, FromRequestParts},
+    http::request::Parts,
+    response::{IntoResponse, Redirect, Response},
+    Extension, Json,
+};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use cookie::time::{format_description::well_known::Rfc3339, OffsetDateTime};
+use hyper::{header::USER_AGENT, StatusCode};
+use rand::{distributions::Alphanumeric, Rng};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tower_cookies::Cookies;
+use tracing::{error, info};
+
+use crate::{events::Event, listener::ClientAddr, storage::StorageError};
+
+use super::{
+    auth::{CookieAuth, SessionClaim, TokenType, UserClaim},
+    context::RequestContext,
+    routes,
+    views::{self, Views},
+    Ctx,
+};
+
+/// Active sessions, by session id
+const SESSIONS: &str = "sessions";
+
+/// Ids of the active sessions of each user, by user
+const USER_SESSIONS: &str = "user_sessions";
+
+/// Revoked session ids, whose cookies are ignored
+const REVOKED: &str = "revoked_sessions";
+
+/// Recent login attempts, by user
+const LOGINS: &str = "logins";
+
+/// Address and browser pairs users logged in from, by user
+const DEVICES: &str = "login_devices";
+
+/// Time of the first successful login of each user
+const FIRST_LOGINS: &str = "first_logins";
+
+/// Number of login attempts kept for each user
+const MAX_LOGINS: usize = 20;
+
+/// Lifetime of sessions whose cookie lasts until the browser is closed, in seconds
+const DEFAULT_LIFETIME: u64 = 30 * 24 * 3600;
+
+/// Interval between removals of expired sessions and revocations
+const PRUNE_INTERVAL: Duration = Duration::from_secs(3600);
+
+/// Session started by a login
+#[derive(Debug, Serialize, Deserialize)]
+struct Session {
+    user: UserClaim,
+    provider: String,
+    /// Unix timestamp of the login
+    created: u64,
+    client_ip: Option<IpAddr>,
+    user_agent: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct LoginAttempt {
+    /// Unix timestamp of the attempt
+    time: u64,
+    provider: String,
+    client_ip: Option<IpAddr>,
+    user_agent: Option<String>,
+    success: bool,
+}
+
+/// Client making a login request
+#[derive(Debug, Clone, Default)]
+pub struct LoginClient {
+    pub ip: Option<IpAddr>,
+    pub user_agent: Option<String>,
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for LoginClient
+where
+    S: Send + Sync,
+{
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        Ok(Self {
+            ip: parts
+                .extensions
+                .get::<ConnectInfo<ClientAddr>>()
+                .and_then(|info| info.0 .0)
+                .map(|addr| addr.ip()),
+            user_agent: parts
+                .headers
+                .get(USER_AGENT)
+                .and_then(|value| value.to_str().ok())
+                .map(ToOwned::to_owned),
+        })
+    }
+}
+
+pub(super) fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+/// Lifetime of sessions in seconds, after which their cookie is refused and they are forgotten
+fn lifetime(ctx: &Ctx) -> u64 {
+    ctx.config
+        .session_cookies
+        .login
+        .max_age
+        .unwrap_or(DEFAULT_LIFETIME)
+}
+
+/// Storage key of the login attempts of a user
+fn logins_key(user: &UserClaim) -> String {
+    match user {
+        UserClaim::Basic { username } => format!("basic:{username}"),
+        UserClaim::OAuth2 { username, provider } => format!("oauth2:{provider}:{username}"),
+    }
+}
+
+/// Record a login attempt
+fn record_attempt(ctx: &Ctx, user: &UserClaim, attempt: LoginAttempt) {
+    let key = logins_key(user);
+    let mut attempts: Vec<LoginAttempt> = match ctx.storage.get_json(LOGINS, &key) {
+        Ok(attempts) => attempts.unwrap_or_default(),
+        Err(err) => {
+            error!(%err, "could not load login attempts");
+            return;
+        }
+    };
+
+    attempts.insert(0, attempt);
+    attempts.truncate(MAX_LOGINS);
+
+    if let Err(err) = ctx.storage.put_json(LOGINS, &key, &attempts) {
+        error!(%err, "could not store login attempts");
+    }
+}
+
+/// Remember that the user logged in, returning true if they logged in before. Unlike the login
+/// attempts, this is not lost when failed attempts push the successful ones out of the history.
+fn remember_login(ctx: &Ctx, user: &UserClaim, time: u64) -> bool {
+    let key = logins_key(user);
+    match ctx.storage.get(FIRST_LOGINS, &key) {
+        Ok(Some(_)) => true,
+        Ok(None) => {
+            if let Err(err) = ctx.storage.put_json(FIRST_LOGINS, &key, &time) {
+                error!(%err, "could not store first login");
+            }
+
+            // Logins recorded before first logins were remembered
+            ctx.storage
+                .get_json::<Vec<LoginAttempt>>(LOGINS, &key)
+                .ok()
+                .flatten()
+                .map_or(false, |attempts| {
+                    attempts.iter().any(|attempt| attempt.success)
+                })
+        }
+        Err(err) => {
+            error!(%err, "could not load first login");
+            false
+        }
+    }
+}
+
+/// Remember the device of a login, returning true if it was seen before
+fn remember_device(ctx: &Ctx, user: &UserClaim, client: &LoginClient) -> bool {
+    let mut hasher = Sha256::new();
+    hasher.update(client.ip.map(|ip| ip.to_string()).unwrap_or_default());
+    hasher.update([0]);
+    hasher.update(client.user_agent.as_deref().unwrap_or_default());
+    let key = format!(
+        "{}:{}",
+        logins_key(user),
+        URL_SAFE_NO_PAD.encode(hasher.finalize())
+    );
+
+    match ctx.storage.get(DEVICES, &key) {
+        Ok(Some(_)) => true,
+        Ok(None) => {
+            if let Err(err) = ctx.storage.put_json(DEVICES, &key, &now()) {
+                error!(%err, "could not store login device");
+            }
+
+            false
+        }
+        Err(err) => {
+            error!(%err, "could not load login device");
+            true
+        }
+    }
+}
+
+/// Start a session for a user who logged in with the given provider, returning the claim of
+/// their session cookie
+pub(super) fn start(
+    ctx: &Ctx,
+    user: UserClaim,
+    provider: &str,
+    client: &LoginClient,
+) -> SessionClaim {
+    let sid: String = rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(24)
+        .map(char::from)
+        .collect();
+
+    let session = Session {
+        user: user.clone(),
+        provider: provider.to_owned(),
+        created: now(),
+        client_ip: client.ip,
+        user_agent: client.user_agent.clone(),
+    };
+
+    if let Err(err) = ctx
+        .storage
+        .put_json(SESSIONS, &sid, &session)
+        .and_then(|()| update_index(ctx, &user, |sids| sids.push(sid.clone())))
+    {
+        error!(%err, "could not store session");
+    }
+
+    let known_device = remember_device(ctx, &user, client);
+    let logged_in_before = remember_login(ctx, &user, session.created);
+    record_attempt(
+        ctx,
+        &user,
+        LoginAttempt {
+            time: session.created,
+            provider: session.provider.clone(),
+            client_ip: session.client_ip,
+            user_agent: session.user_agent.clone(),
+            success: true,
+        },
+    );
+
+    // The first login of a user only tells which devices are theirs
+    ctx.events.emit(Event::Login {
+        user: user_name(&user).to_owned(),
+        email: user_email(ctx, &user),
+        provider: session.provider,
+        client_ip: session.client_ip,
+        user_agent: session.user_agent,
+        new_device: logged_in_before && !known_device,
+        time: session.created,
+    });
+
+    SessionClaim {
+        typ: Some(TokenType::Session),
+        user,
+        sid: Some(sid),
+        iat: Some(session.created),
+    }
+}
+
+/// Change the ids of the active sessions of a user
+fn update_index(
+    ctx: &Ctx,
+    user: &UserClaim,
+    f: impl FnOnce(&mut Vec<String>),
+) -> Result<(), StorageError> {
+    let key = logins_key(user);
+    let mut sids: Vec<String> = ctx
+        .storage
+        .get_json(USER_SESSIONS, &key)?
+        .unwrap_or_default();
+    f(&mut sids);
+
+    if sids.is_empty() {
+        ctx.storage.delete(USER_SESSIONS, &key)
+    } else {
+        ctx.storage.put_json(USER_SESSIONS, &key, &sids)
+    }
+}
+
+fn user_name(user: &UserClaim) -> &str {
+    match user {
+        UserClaim::Basic { username } | UserClaim::OAuth2 { username, .. } => username,
+    }
+}
+
+/// Address of a user for notifications: the configured one for basic auth users, and the
+/// username for OAuth2 users, which is usually their email
+fn user_email(ctx: &Ctx, user: &UserClaim) -> Option<String> {
+    match user {
+        UserClaim::Basic { username } => ctx.config.providers.basic.email_of(username),
+        UserClaim::OAuth2 { username, .. } => username.contains('@').then(|| username.clone()),
+    }
+}
+
+/// Record a failed login attempt, e.g. a wrong password or second factor
+pub(super) fn record_failure(ctx: &Ctx, user: &UserClaim, provider: &str, client: &LoginClient) {
+    record_attempt(
+        ctx,
+        user,
+        LoginAttempt {
+            time: now(),
+            provider: provider.to_owned(),
+            client_ip: client.ip,
+            user_agent: client.user_agent.clone(),
+            success: false,
+        },
+    );
+}
+
+/// Returns true if the session cookie was issued within the session lifetime, and its session
+/// was not revoked. Cookies without a session id can't be revoked, so they are refused.
+pub(super) fn is_current(ctx: &Ctx, claim: &SessionClaim) -> bool {
+    let (Some(sid), Some(iat)) = (&claim.sid, claim.iat) else {
+        return false;
+    };
+
+    iat.saturating_add(lifetime(ctx)) > now() && !is_revoked(ctx, sid)
+}
+
+fn is_revoked(ctx: &Ctx, sid: &str) -> bool {
+    match ctx.storage.get(REVOKED, sid) {
+        Ok(revoked) => revoked.is_some(),
+        Err(err) => {
+            // Fail closed, the user can log in again
+            error!(%err, "could not check session revocation");
+            true
+        }
+    }
+}
+
+/// End a session, so its cookie can't be used anymore
+pub(super) fn revoke(ctx: &Ctx, sid: &str) {
+    let result = ctx.storage.put_json(REVOKED, sid, &now()).and_then(|()| {
+        if let Some(session) = ctx.storage.get_json::<Session>(SESSIONS, sid)? {
+            update_index(ctx, &session.user, |sids| sids.retain(|other| other != sid))?;
+        }
+
+        ctx.storage.delete(SESSIONS, sid)
+    });
+
+    if let Err(err) = result {
+        error!(%err, "could not revoke session");
+    }
+}
+
+/// Active sessions of a user, by session id
+fn sessions_of(ctx: &Ctx, user: &UserClaim) -> Vec<(String, Session)> {
+    let sids: Vec<String> = match ctx.storage.get_json(USER_SESSIONS, &logins_key(user)) {
+        Ok(sids) => sids.unwrap_or_default(),
+        Err(err) => {
+            error!(%err, "could not list sessions");
+            return Vec::new();
+        }
+    };
+
+    let expired_before = now().saturating_sub(lifetime(ctx));
+    sids.into_iter()
+        .filter_map(
+            |sid| match ctx.storage.get_json::<Session>(SESSIONS, &sid) {
+                Ok(session) => session
+                    .filter(|session| session.created > expired_before)
+                    .map(|session| (sid, session)),
+                Err(err) => {
+                    error!(%err, "could not load session");
+                    None
+                }
+            },
+        )
+        .collect()
+}
+
+/// Forget the sessions and revocations older than the session lifetime, since their cookies are
+/// refused anyway. Returns the number of removed entries.
+fn prune(ctx: &Ctx) -> Result<usize, StorageError> {
+    let expired_before = now().saturating_sub(lifetime(ctx));
+    let mut count = 0;
+
+    for (sid, value) in ctx.storage.list(SESSIONS)? {
+        match serde_json::from_slice::<Session>(&value) {
+            Ok(session) if session.created > expired_before => continue,
+            Ok(session) => update_index(ctx, &session.user, |sids| {
+                sids.retain(|other| other != &sid)
+            })?,
+            Err(_) => {}
+        }
+
+        ctx.storage.delete(SESSIONS, &sid)?;
+        count += 1;
+    }
+
+    // Revocations happen after the cookie was issued, so the cookie has expired by now
+    for (sid, value) in ctx.storage.list(REVOKED)? {
+        let revoked: u64 = serde_json::from_slice(&value).unwrap_or_default();
+        if revoked <= expired_before {
+            ctx.storage.delete(REVOKED, &sid)?;
+            count += 1;
+        }
+    }
+
+    Ok(count)
+}
+
+/// Remove expired sessions and revocations periodically
+pub(super) async fn run_pruning(ctx: Arc<Ctx>) {
+    let mut interval = tokio::time::interval(PRUNE_INTERVAL);
+    loop {
+        interval.tick().await;
+
+        match prune(&ctx) {
+            Ok(0) => {}
+            Ok(count) => info!(count, "pruned expired sessions"),
+            Err(err) => error!(%err, "could not prune sessions"),
+        }
+    }
+}
+
+pub(super) fn format_time(time: u64) -> String {
+    OffsetDateTime::from_unix_timestamp(time as i64)
+        .ok()
+        .and_then(|time| time.format(&Rfc3339).ok())
+        .unwrap_or_default()
+}
+
+pub(super) async fn activity(
+    Extension(ctx): Extension<Arc<Ctx>>,
+    request: RequestContext,
+    cookie_auth: Option<Extension<CookieAuth>>,
+) -> Response {
+    let Some(user) = UserClaim::from_auth_user(&request.user) else {
+        let url = ctx.paths.login_path.clone()
+            + "?redirect_to="
+            + urlencoding::encode(&(ctx.paths.base_path.clone() + "/account/activity")).as_ref();
+        return Redirect::to(&url).into_response();
+    };
+
+    let current = cookie_auth.and_then(|Extension(auth)| auth.sid);
+
+    let logins = match ctx
+        .storage
+        .get_json::<Vec<LoginAttempt>>(LOGINS, &logins_key(&user))
+    {
+        Ok(logins) => logins.unwrap_or_default(),
+        Err(err) => {
+            error!(%err, "could not load login attempts");
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+
+    let mut sessions = sessions_of(&ctx, &user);
+    sessions.sort_by(|a, b| b.1.created.cmp(&a.1.created));
+
+    let data = views::activity::Data {
+        username: request.user.username().unwrap_or_default().to_owned(),
+        logins: logins
+            .into_iter()
+            .map(|login| views::activity::Login {
+                time: format_time(login.time),
+                provider: login.provider,
+                client_ip: login.client_ip.map(|ip| ip.to_string()),
+                user_agent: login.user_agent,
+                success: login.success,
+            })
+            .collect(),
+        sessions: sessions
+            .into_iter()
+            .map(|(sid, session)| views::activity::Session {
+                current: current.as_deref() == Some(sid.as_str()),
+                created: format_time(session.created),
+                provider: session.provider,
+                client_ip: session.client_ip.map(|ip| ip.to_string()),
+                user_agent: session.user_agent,
+            })
+            .collect(),
+    };
+
+    // Builds without web pages get the same data as JSON
+    if !Views::ENABLED {
+        return Json(data).into_response();
+    }
+
+    match ctx.views.render(&data) {
+        Ok(response) => response.into_response(),
+        Err(err) => {
+            error!(%err, "could not render activity page");
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+/// Revoke all the sessions of the user, and log them out
+pub(super) async fn sign_out_everywhere(
+    ctx: Extension<Arc<Ctx>>,
+    request: RequestContext,
+    cookies: Cookies,
+) -> Response {
+    let Some(user) = UserClaim::from_auth_user(&request.user) else {
+        return StatusCode::UNAUTHORIZED.into_response();
+    };
+
+    let sessions = sessions_of(&ctx, &user);
+    for (sid, _) in &sessions {
+        revoke(&ctx, sid);
+    }
+
+    info!(
+        user = request.user.username(),
+        count = sessions.len(),
+        "revoked all sessions"
+    );
+
+    routes::logout(ctx, cookies, None).await.into_response()
+}
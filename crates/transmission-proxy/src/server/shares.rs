@@ -0,0 +1,199 @@
+//! Handlers of the share links, see [`crate::shares`]
+
+use std::sync::Arc;
+
+use axum::{
+    extract::Path,
+    response::{IntoResponse, Response},
+    Extension, Json,
+};
+use hyper::{
+    header::{ACCEPT, CACHE_CONTROL},
+    HeaderMap, StatusCode,
+};
+use jwt::{SignWithKey, VerifyWithKey};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tracing::{debug, error};
+
+use crate::{
+    rpc::{TorrentId, TorrentStatus},
+    shares,
+};
+
+use super::{
+    auth::{TokenType, UserClaim},
+    context::RequestContext,
+    sessions::{format_time, now},
+    views::{self, Views},
+    Ctx, JwtKey,
+};
+
+/// Contents of the token of a share link
+#[derive(Debug, Serialize, Deserialize)]
+struct ShareClaim {
+    typ: TokenType,
+    /// User who shared the torrents
+    owner: UserClaim,
+    /// Hashes of the shared torrents
+    hashes: Vec<String>,
+    /// Unix timestamp after which the link is invalid
+    exp: u64,
+}
+
+impl ShareClaim {
+    fn jwt(&self, key: &JwtKey) -> String {
+        self.sign_with_key(key).expect("failed to sign jwt")
+    }
+
+    /// Verify a token, returning `None` if it is invalid or expired
+    fn verify(key: &JwtKey, jwt: &str) -> Option<Self> {
+        let claim: Self = jwt.verify_with_key(key).ok()?;
+        (claim.typ == TokenType::Share && claim.exp > now()).then_some(claim)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub(super) struct CreateShare {
+    /// Ids or hashes of the torrents to share
+    ids: Vec<TorrentId>,
+    /// Lifetime of the link in seconds, capped by the configuration
+    #[serde(default)]
+    lifetime: Option<u64>,
+}
+
+#[derive(Debug, Serialize)]
+struct Share {
+    url: String,
+    /// Unix timestamp after which the link is invalid
+    expires_at: u64,
+    /// Hashes of the shared torrents, the ones the user can't access are left out
+    hashes: Vec<String>,
+}
+
+/// Create a share link for some torrents of the user
+pub(super) async fn create(
+    Extension(ctx): Extension<Arc<Ctx>>,
+    request: RequestContext,
+    Json(share): Json<CreateShare>,
+) -> Response {
+    let Some(owner) = UserClaim::from_auth_user(&request.user) else {
+        return StatusCode::UNAUTHORIZED.into_response();
+    };
+
+    // Empty ids would select all torrents
+    if share.ids.is_empty() {
+        return (StatusCode::BAD_REQUEST, "no torrents to share").into_response();
+    }
+
+    // Only share the torrents the user can see
+    let hashes: Vec<String> = match request
+        .torrent_get(&ctx, Some(share.ids), &["hashString"])
+        .await
+    {
+        Ok(torrents) => torrents
+            .iter()
+            .filter_map(|torrent| torrent["hashString"].as_str().map(str::to_owned))
+            .collect(),
+        Err(status) => return status.into_response(),
+    };
+    if hashes.is_empty() {
+        return (StatusCode::NOT_FOUND, "no such torrents").into_response();
+    }
+
+    let expires_at = now() + ctx.config.shares.lifetime(share.lifetime);
+    let claim = ShareClaim {
+        typ: TokenType::Share,
+        owner,
+        hashes,
+        exp: expires_at,
+    };
+    let url = ctx
+        .args
+        .public_url()
+        .to_string()
+        .trim_end_matches('/')
+        .to_owned()
+        + "/share/"
+        + &claim.jwt(&ctx.jwt_key());
+
+    debug!(
+        user = request.user.username(),
+        count = claim.hashes.len(),
+        "shared torrents"
+    );
+    Json(Share {
+        url,
+        expires_at,
+        hashes: claim.hashes,
+    })
+    .into_response()
+}
+
+/// Status of shared torrents, as JSON or as a page for browsers
+pub(super) async fn view(
+    Extension(ctx): Extension<Arc<Ctx>>,
+    Path(token): Path<String>,
+    headers: HeaderMap,
+    request: RequestContext,
+) -> Response {
+    let Some(claim) = ShareClaim::verify(&ctx.jwt_key(), &token) else {
+        return (StatusCode::NOT_FOUND, "invalid or expired link").into_response();
+    };
+
+    // Torrents are fetched with the current access of the owner
+    let owner = RequestContext::new(&ctx, claim.owner.into(), request.client_ip).await;
+    let ids = claim.hashes.into_iter().map(TorrentId::Sha1).collect();
+    let torrents: Vec<Value> = match owner.torrent_get(&ctx, Some(ids), shares::FIELDS).await {
+        Ok(torrents) => torrents.iter().map(shares::project).collect(),
+        // The owner lost access to the proxy or to their torrents
+        Err(StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN) => Vec::new(),
+        Err(status) => return status.into_response(),
+    };
+
+    let html = headers
+        .get(ACCEPT)
+        .and_then(|accept| accept.to_str().ok())
+        .map_or(false, |accept| accept.contains("text/html"));
+
+    let mut response = if html && Views::ENABLED {
+        let data = views::share::Data {
+            shared_by: owner.user.username().unwrap_or_default().to_owned(),
+            expires_at: format_time(claim.exp),
+            torrents: torrents
+                .iter()
+                .map(|torrent| views::share::Torrent {
+                    name: torrent["name"].as_str().unwrap_or_default().to_owned(),
+                    percent_done: (torrent["percentDone"].as_f64().unwrap_or_default() * 100.)
+                        as u32,
+                    status: views::status_label(
+                        torrent["status"]
+                            .as_i64()
+                            .map(|status| TorrentStatus::from(status as i32)),
+                    )
+                    .to_owned(),
+                    error: torrent["errorString"]
+                        .as_str()
+                        .filter(|error| !error.is_empty())
+                        .map(str::to_owned),
+                })
+                .collect(),
+        };
+
+        match ctx.views.render(&data) {
+            Ok(response) => response.into_response(),
+            Err(err) => {
+                error!(%err, "could not render share page");
+                return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+            }
+        }
+    } else {
+        Json(json!({ "expires_at": claim.exp, "torrents": torrents })).into_response()
+    };
+
+    // Progress changes, and links may be revoked by the owner losing access
+    response
+        .headers_mut()
+        .insert(CACHE_CONTROL, "no-store".parse().unwrap());
+    response
+}
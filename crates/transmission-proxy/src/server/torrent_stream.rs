@@ -0,0 +1,154 @@
+//! Torrent list of a user, pushed as server-sent snapshots and deltas
+
+use std::{convert::Infallible, sync::Arc, time::Duration};
+
+use axum::{
+    extract::Query,
+    response::{
+        sse::{self, Sse},
+        IntoResponse, Response,
+    },
+    Extension,
+};
+use hyper::StatusCode;
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+use tracing::warn;
+
+use crate::torrent_stream::Snapshot;
+
+use super::{context::RequestContext, export, routes::Streamed, Ctx};
+
+/// Fields streamed when none are requested
+const DEFAULT_FIELDS: &[&str] = &[
+    "id",
+    "name",
+    "status",
+    "percentDone",
+    "rateDownload",
+    "rateUpload",
+    "eta",
+    "error",
+];
+
+#[derive(Debug, Deserialize)]
+pub(super) struct StreamQuery {
+    /// Comma-separated torrent-get fields
+    #[serde(default)]
+    fields: Option<String>,
+}
+
+/// Torrents the user can see, with only the requested fields
+async fn fetch(
+    ctx: &Ctx,
+    request: &RequestContext,
+    fields: &[String],
+) -> Result<Vec<Value>, StatusCode> {
+    let field_refs: Vec<&str> = fields.iter().map(String::as_str).collect();
+    let torrents = request.torrent_get(ctx, None, &field_refs).await?;
+
+    Ok(torrents
+        .iter()
+        .map(|torrent| {
+            let object: Map<String, Value> = fields
+                .iter()
+                .filter_map(|field| Some((field.clone(), torrent.get(field)?.clone())))
+                .collect();
+            Value::Object(object)
+        })
+        .collect())
+}
+
+fn snapshot_event(snapshot: &Snapshot) -> Option<sse::Event> {
+    sse::Event::default()
+        .event("snapshot")
+        .json_data(json!({ "torrents": snapshot.torrents() }))
+        .ok()
+}
+
+/// State of a stream between two polls
+struct Poller {
+    ctx: Arc<Ctx>,
+    request: RequestContext,
+    fields: Vec<String>,
+    snapshot: Snapshot,
+    polls: u32,
+}
+
+impl Poller {
+    /// Next event to send, once something changed. Returns `None` once the user lost access.
+    async fn next(&mut self) -> Option<sse::Event> {
+        let config = &self.ctx.config.torrent_stream;
+
+        loop {
+            tokio::time::sleep(Duration::from_secs(config.interval.max(1))).await;
+
+            let torrents = match fetch(&self.ctx, &self.request, &self.fields).await {
+                Ok(torrents) => torrents,
+                Err(status @ (StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN)) => {
+                    warn!(%status, "stopped streaming torrents");
+                    return None;
+                }
+                // Try again on the next poll
+                Err(status) => {
+                    warn!(%status, "could not poll torrents");
+                    continue;
+                }
+            };
+
+            self.polls = self.polls.wrapping_add(1);
+            if self.polls % config.snapshot_every.max(1) == 0 {
+                self.snapshot = Snapshot::new(torrents);
+                return snapshot_event(&self.snapshot);
+            }
+
+            let delta = self.snapshot.update(torrents);
+            if !delta.is_empty() {
+                return sse::Event::default().event("delta").json_data(delta).ok();
+            }
+        }
+    }
+}
+
+/// Stream the torrents the user can see: a snapshot first, then the changes since each poll
+pub(super) async fn stream(
+    Extension(ctx): Extension<Arc<Ctx>>,
+    request: RequestContext,
+    Query(query): Query<StreamQuery>,
+) -> Response {
+    let mut fields = match export::requested_fields(query.fields.as_deref(), DEFAULT_FIELDS) {
+        Ok(fields) => fields,
+        Err(response) => return response,
+    };
+    // Deltas are keyed by torrent id
+    if !fields.iter().any(|field| field == "id") {
+        fields.insert(0, "id".to_owned());
+    }
+
+    let snapshot = match fetch(&ctx, &request, &fields).await {
+        Ok(torrents) => Snapshot::new(torrents),
+        Err(status) => return status.into_response(),
+    };
+    let first = snapshot_event(&snapshot);
+
+    let poller = Poller {
+        ctx,
+        request,
+        fields,
+        snapshot,
+        polls: 0,
+    };
+    let stream = futures_util::stream::unfold((first, poller), |(first, mut poller)| async move {
+        let event = match first {
+            Some(event) => event,
+            None => poller.next().await?,
+        };
+        Some((Ok::<_, Infallible>(event), (None, poller)))
+    });
+
+    let mut response = Sse::new(stream)
+        .keep_alive(sse::KeepAlive::default())
+        .into_response();
+    response.extensions_mut().insert(Streamed);
+    response
+}
@@ -1,10 +1,26 @@
-use handlebars::{Handlebars, RenderError};
+#[cfg(feature = "views")]
+use handlebars::Handlebars;
 use hyper::{header::CONTENT_TYPE, Body, Response};
 
+use crate::rpc::TorrentStatus;
+
+#[cfg(feature = "views")]
+pub use handlebars::RenderError;
+
+#[cfg(feature = "views")]
 mod helpers;
 
 // View module declarations
+pub mod activity;
+pub mod guest_banner;
 pub mod login;
+pub mod maintenance;
+pub mod owners;
+pub mod share;
+pub mod toolbar;
+pub mod totp;
+#[cfg(feature = "webauthn")]
+pub mod webauthn;
 
 /// Trait for the data required for a view
 pub trait ViewData: serde::Serialize {
@@ -15,11 +31,27 @@ pub trait ViewData: serde::Serialize {
     const SOURCE: &'static str;
 }
 
+/// Error returned when rendering views in builds without the `views` feature
+#[cfg(not(feature = "views"))]
+#[derive(Debug, thiserror::Error)]
+#[error("web pages are not included in this build")]
+pub struct RenderError;
+
 pub struct Views {
+    #[cfg(feature = "views")]
     handlebars: Handlebars<'static>,
 }
 
 impl Views {
+    /// True if views can be rendered by this build
+    pub const ENABLED: bool = cfg!(feature = "views");
+
+    #[cfg(not(feature = "views"))]
+    pub fn new() -> Self {
+        Self {}
+    }
+
+    #[cfg(feature = "views")]
     pub fn new() -> Self {
         let mut handlebars = Handlebars::new();
 
@@ -27,9 +59,34 @@ impl Views {
         handlebars.register_helper("urlencode", Box::new(helpers::urlencode_helper));
 
         // Register templates
+        handlebars
+            .register_template_string(activity::Data::NAME, activity::Data::SOURCE)
+            .expect("failed to load template");
         handlebars
             .register_template_string(login::Data::NAME, login::Data::SOURCE)
             .expect("failed to load template");
+        handlebars
+            .register_template_string(guest_banner::Data::NAME, guest_banner::Data::SOURCE)
+            .expect("failed to load template");
+        handlebars
+            .register_template_string(maintenance::Data::NAME, maintenance::Data::SOURCE)
+            .expect("failed to load template");
+        handlebars
+            .register_template_string(owners::Data::NAME, owners::Data::SOURCE)
+            .expect("failed to load template");
+        handlebars
+            .register_template_string(share::Data::NAME, share::Data::SOURCE)
+            .expect("failed to load template");
+        handlebars
+            .register_template_string(toolbar::Data::NAME, toolbar::Data::SOURCE)
+            .expect("failed to load template");
+        handlebars
+            .register_template_string(totp::Data::NAME, totp::Data::SOURCE)
+            .expect("failed to load template");
+        #[cfg(feature = "webauthn")]
+        handlebars
+            .register_template_string(webauthn::Data::NAME, webauthn::Data::SOURCE)
+            .expect("failed to load template");
 
         Self { handlebars }
     }
@@ -41,7 +98,36 @@ impl Views {
         Ok(Response::builder()
             .status(200)
             .header(CONTENT_TYPE, "text/html")
-            .body(Body::from(self.handlebars.render(T::NAME, &data)?))
+            .body(Body::from(self.render_fragment(data)?))
             .unwrap())
     }
+
+    /// Render a view which is embedded into another page
+    #[cfg(feature = "views")]
+    pub fn render_fragment<T>(&self, data: &T) -> Result<String, RenderError>
+    where
+        T: ViewData,
+    {
+        self.handlebars.render(T::NAME, &data)
+    }
+
+    #[cfg(not(feature = "views"))]
+    pub fn render_fragment<T>(&self, _data: &T) -> Result<String, RenderError>
+    where
+        T: ViewData,
+    {
+        Err(RenderError)
+    }
+}
+
+/// Label of a torrent status, as shown by the web interface
+pub fn status_label(status: Option<TorrentStatus>) -> &'static str {
+    match status {
+        Some(TorrentStatus::Stopped) => "Stopped",
+        Some(TorrentStatus::CheckWait | TorrentStatus::Checking) => "Verifying",
+        Some(TorrentStatus::DownloadWait | TorrentStatus::SeedWait) => "Queued",
+        Some(TorrentStatus::Downloading) => "Downloading",
+        Some(TorrentStatus::Seeding) => "Seeding",
+        Some(TorrentStatus::Unknown(_)) | None => "Unknown",
+    }
 }
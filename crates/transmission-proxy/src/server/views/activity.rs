@@ -0,0 +1,36 @@
+use serde::Serialize;
+
+use super::ViewData;
+
+#[derive(Debug, Serialize)]
+pub struct Login {
+    pub time: String,
+    pub provider: String,
+    pub client_ip: Option<String>,
+    pub user_agent: Option<String>,
+    pub success: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Session {
+    /// true for the session of the current request
+    pub current: bool,
+    pub created: String,
+    pub provider: String,
+    pub client_ip: Option<String>,
+    pub user_agent: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Data {
+    pub username: String,
+    /// Most recent first
+    pub logins: Vec<Login>,
+    pub sessions: Vec<Session>,
+}
+
+impl ViewData for Data {
+    const NAME: &'static str = "activity";
+
+    const SOURCE: &'static str = include_str!("activity.html.hbs");
+}
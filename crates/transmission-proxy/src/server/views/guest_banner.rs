@@ -0,0 +1,15 @@
+use serde::Serialize;
+
+use super::ViewData;
+
+#[derive(Debug, Serialize)]
+pub struct Data<'p> {
+    pub login_path: &'p str,
+    pub redirect_to: &'p str,
+}
+
+impl ViewData for Data<'_> {
+    const NAME: &'static str = "guest_banner";
+
+    const SOURCE: &'static str = include_str!("guest_banner.html.hbs");
+}
@@ -0,0 +1,14 @@
+use serde::Serialize;
+
+use super::ViewData;
+
+#[derive(Debug, Serialize)]
+pub struct Data<'m> {
+    pub message: &'m str,
+}
+
+impl ViewData for Data<'_> {
+    const NAME: &'static str = "maintenance";
+
+    const SOURCE: &'static str = include_str!("maintenance.html.hbs");
+}
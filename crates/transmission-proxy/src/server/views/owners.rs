@@ -0,0 +1,32 @@
+use serde::Serialize;
+
+use super::ViewData;
+
+#[derive(Debug, Serialize)]
+pub struct Torrent {
+    pub name: String,
+    pub status: String,
+    pub size: String,
+    pub download_dir: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Owner {
+    /// None for the torrents attributed to no user
+    pub owner: Option<String>,
+    pub count: usize,
+    pub size: String,
+    pub active: usize,
+    pub torrents: Vec<Torrent>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Data {
+    pub owners: Vec<Owner>,
+}
+
+impl ViewData for Data {
+    const NAME: &'static str = "owners";
+
+    const SOURCE: &'static str = include_str!("owners.html.hbs");
+}
@@ -0,0 +1,25 @@
+use serde::Serialize;
+
+use super::ViewData;
+
+#[derive(Debug, Serialize)]
+pub struct Torrent {
+    pub name: String,
+    /// Percentage of the torrent downloaded, rounded down
+    pub percent_done: u32,
+    pub status: String,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Data {
+    pub shared_by: String,
+    pub expires_at: String,
+    pub torrents: Vec<Torrent>,
+}
+
+impl ViewData for Data {
+    const NAME: &'static str = "share";
+
+    const SOURCE: &'static str = include_str!("share.html.hbs");
+}
@@ -0,0 +1,17 @@
+use serde::Serialize;
+
+use super::ViewData;
+
+#[derive(Debug, Serialize)]
+pub struct Data<'p> {
+    pub username: &'p str,
+    pub activity_path: &'p str,
+    pub logout_path: &'p str,
+    pub stats_path: Option<&'p str>,
+}
+
+impl ViewData for Data<'_> {
+    const NAME: &'static str = "toolbar";
+
+    const SOURCE: &'static str = include_str!("toolbar.html.hbs");
+}
@@ -0,0 +1,16 @@
+use serde::Serialize;
+
+use super::ViewData;
+
+#[derive(Debug, Serialize)]
+pub struct Data {
+    pub redirect_to: Option<String>,
+    /// true if the previous code was rejected
+    pub invalid: bool,
+}
+
+impl ViewData for Data {
+    const NAME: &'static str = "totp";
+
+    const SOURCE: &'static str = include_str!("totp.html.hbs");
+}
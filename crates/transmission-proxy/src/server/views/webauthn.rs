@@ -0,0 +1,16 @@
+use serde::Serialize;
+
+use super::ViewData;
+
+#[derive(Debug, Serialize)]
+pub struct Data {
+    /// true to register a new passkey, false to log in with one
+    pub register: bool,
+    pub redirect_to: Option<String>,
+}
+
+impl ViewData for Data {
+    const NAME: &'static str = "webauthn";
+
+    const SOURCE: &'static str = include_str!("webauthn.html.hbs");
+}
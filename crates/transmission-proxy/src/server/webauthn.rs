@@ -0,0 +1,412 @@
+use std::{
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use axum::{
+    extract::Query,
+    response::{IntoResponse, Redirect, Response},
+    routing, Extension, Json, Router,
+};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use color_eyre::eyre;
+use hyper::{StatusCode, Uri};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tower_cookies::Cookies;
+use tracing::{error, info, warn};
+
+use crate::{
+    auth::{AuthUser, WebauthnProvider},
+    webauthn::{self, ClientData, RelyingParty, WebauthnError, ES256},
+};
+
+use super::{
+    auth::UserClaim,
+    context::RequestContext,
+    routes::{self, AuthRedirect},
+    sessions::LoginClient,
+    views, Ctx,
+};
+
+/// Storage namespace of the registered passkeys, by credential id
+const STORAGE_NAMESPACE: &str = "webauthn";
+
+/// Time users have to answer a challenge
+const CHALLENGE_TTL: Duration = Duration::from_secs(300);
+
+/// A passkey registered by a user
+#[derive(Debug, Serialize, Deserialize)]
+struct StoredCredential {
+    /// Base64url SEC1 public key
+    public_key: String,
+    sign_count: u32,
+    /// User logged in by this passkey
+    claim: UserClaim,
+    /// Unix timestamp of the registration
+    created: u64,
+}
+
+/// Challenge waiting for an answer
+#[derive(Debug, Serialize, Deserialize)]
+struct PendingChallenge {
+    /// User registering a passkey, none for logins
+    claim: Option<UserClaim>,
+}
+
+/// Relying party of the proxy, from the configuration or the public URL
+pub(super) fn relying_party(
+    provider: &WebauthnProvider,
+    public_url: &Uri,
+) -> eyre::Result<RelyingParty> {
+    let id = match (&provider.rp_id, public_url.host()) {
+        (Some(id), _) => id.clone(),
+        (None, Some(host)) => host.to_owned(),
+        (None, None) => eyre::bail!("could not derive the webauthn rp_id from the public url"),
+    };
+
+    let origin = match (
+        &provider.origin,
+        public_url.scheme(),
+        public_url.authority(),
+    ) {
+        (Some(origin), _, _) => origin.clone(),
+        (None, Some(scheme), Some(authority)) => format!("{scheme}://{authority}"),
+        _ => eyre::bail!("could not derive the webauthn origin from the public url"),
+    };
+
+    Ok(RelyingParty { id, origin })
+}
+
+pub(super) fn add_routes(ctx: &Ctx, router: Router) -> Router {
+    if !ctx.config.providers.webauthn.enabled {
+        return router;
+    }
+
+    router
+        .route("/auth/webauthn/login", routing::get(login_page))
+        .route("/auth/webauthn/login/start", routing::post(login_start))
+        .route("/auth/webauthn/login/finish", routing::post(login_finish))
+        .route("/auth/webauthn/register", routing::get(register_page))
+        .route(
+            "/auth/webauthn/register/start",
+            routing::post(register_start),
+        )
+        .route(
+            "/auth/webauthn/register/finish",
+            routing::post(register_finish),
+        )
+}
+
+/// Issue a new challenge, remembered until it is answered
+async fn challenge(ctx: &Ctx, pending: PendingChallenge) -> Result<String, Response> {
+    let mut bytes = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    let challenge = URL_SAFE_NO_PAD.encode(bytes);
+
+    let value = serde_json::to_string(&pending).expect("failed to serialize challenge");
+    match ctx
+        .state
+        .set(&challenge_key(&challenge), &value, Some(CHALLENGE_TTL))
+        .await
+    {
+        Ok(()) => Ok(challenge),
+        Err(err) => {
+            error!(%err, "could not store webauthn challenge");
+            Err(StatusCode::INTERNAL_SERVER_ERROR.into_response())
+        }
+    }
+}
+
+/// Consume the challenge answered by the client data, so it can't be replayed
+async fn take_challenge(ctx: &Ctx, client_data_json: &[u8]) -> Result<PendingChallenge, Response> {
+    let client_data = ClientData::parse(client_data_json).map_err(rejected)?;
+    let key = challenge_key(&client_data.challenge);
+
+    let pending = match ctx.state.get(&key).await {
+        Ok(Some(pending)) => pending,
+        Ok(None) => {
+            warn!("unknown or expired webauthn challenge");
+            return Err(StatusCode::UNAUTHORIZED.into_response());
+        }
+        Err(err) => {
+            error!(%err, "could not load webauthn challenge");
+            return Err(StatusCode::INTERNAL_SERVER_ERROR.into_response());
+        }
+    };
+
+    if let Err(err) = ctx.state.delete(&key).await {
+        error!(%err, "could not delete webauthn challenge");
+        return Err(StatusCode::INTERNAL_SERVER_ERROR.into_response());
+    }
+
+    serde_json::from_str(&pending).map_err(|err| {
+        error!(%err, "invalid webauthn challenge");
+        StatusCode::INTERNAL_SERVER_ERROR.into_response()
+    })
+}
+
+fn challenge_key(challenge: &str) -> String {
+    format!("webauthn:challenge:{challenge}")
+}
+
+fn rejected(err: WebauthnError) -> Response {
+    warn!(%err, "webauthn response rejected");
+    (StatusCode::UNAUTHORIZED, err.to_string()).into_response()
+}
+
+fn page(ctx: &Ctx, register: bool, redirect_to: Option<String>) -> Response {
+    match ctx.views.render(&views::webauthn::Data {
+        register,
+        redirect_to: Some(ctx.paths.redirect_target(redirect_to.as_deref())),
+    }) {
+        Ok(response) => response.into_response(),
+        Err(err) => {
+            error!(%err, "could not render webauthn page");
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+async fn login_page(Extension(ctx): Extension<Arc<Ctx>>, query: Query<AuthRedirect>) -> Response {
+    page(&ctx, false, query.0.redirect_to)
+}
+
+async fn login_start(Extension(ctx): Extension<Arc<Ctx>>) -> Response {
+    let challenge = match challenge(&ctx, PendingChallenge { claim: None }).await {
+        Ok(challenge) => challenge,
+        Err(response) => return response,
+    };
+
+    // Passkeys are discoverable, the browser lets the user pick one
+    Json(json!({
+        "publicKey": {
+            "challenge": challenge,
+            "rpId": ctx.relying_party.id,
+            "userVerification": "preferred",
+            "timeout": CHALLENGE_TTL.as_millis() as u64,
+        }
+    }))
+    .into_response()
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct LoginResponse {
+    id: String,
+    #[serde(rename = "clientDataJSON")]
+    client_data_json: String,
+    authenticator_data: String,
+    signature: String,
+    #[serde(default)]
+    redirect_to: Option<String>,
+}
+
+async fn login_finish(
+    Extension(ctx): Extension<Arc<Ctx>>,
+    cookies: Cookies,
+    client: LoginClient,
+    Json(response): Json<LoginResponse>,
+) -> Response {
+    let decoded = (|| {
+        Ok::<_, WebauthnError>((
+            webauthn::decode(&response.client_data_json)?,
+            webauthn::decode(&response.authenticator_data)?,
+            webauthn::decode(&response.signature)?,
+        ))
+    })();
+    let (client_data_json, authenticator_data, signature) = match decoded {
+        Ok(decoded) => decoded,
+        Err(err) => return rejected(err),
+    };
+
+    if let Err(response) = take_challenge(&ctx, &client_data_json).await {
+        return response;
+    }
+
+    let mut credential = match ctx
+        .storage
+        .get_json::<StoredCredential>(STORAGE_NAMESPACE, &response.id)
+    {
+        Ok(Some(credential)) => credential,
+        Ok(None) => {
+            warn!(id = %response.id, "unknown passkey");
+            return StatusCode::UNAUTHORIZED.into_response();
+        }
+        Err(err) => {
+            error!(%err, "could not load passkey");
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+
+    // Passkeys of users removed from the configuration don't log in anymore
+    if !credential.claim.is_allowed(&ctx) {
+        warn!(id = %response.id, "passkey of a user who is not allowed anymore");
+        return StatusCode::UNAUTHORIZED.into_response();
+    }
+
+    let public_key = match webauthn::decode(&credential.public_key) {
+        Ok(public_key) => public_key,
+        Err(err) => return rejected(err),
+    };
+
+    credential.sign_count = match ctx.relying_party.authenticate(
+        &client_data_json,
+        &authenticator_data,
+        &signature,
+        &public_key,
+        credential.sign_count,
+    ) {
+        Ok(sign_count) => sign_count,
+        Err(err) => return rejected(err),
+    };
+
+    if let Err(err) = ctx
+        .storage
+        .put_json(STORAGE_NAMESPACE, &response.id, &credential)
+    {
+        error!(%err, "could not update passkey");
+        return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+    }
+
+    let user = AuthUser::from(credential.claim);
+    info!(user = user.username(), "logged in with a passkey");
+
+    // The page follows the redirection itself
+    routes::set_session_cookie(&ctx, &cookies, &user, "webauthn", &client);
+    Json(json!({
+        "redirect_to": ctx.paths.redirect_target(response.redirect_to.as_deref()),
+    }))
+    .into_response()
+}
+
+async fn register_page(
+    Extension(ctx): Extension<Arc<Ctx>>,
+    request: RequestContext,
+    query: Query<AuthRedirect>,
+) -> Response {
+    if request.user.is_anonymous() {
+        // Log in with another provider first
+        let url = ctx.paths.login_path.clone()
+            + "?redirect_to="
+            + urlencoding::encode(&(ctx.paths.base_path.clone() + "/auth/webauthn/register"))
+                .as_ref();
+        return Redirect::to(&url).into_response();
+    }
+
+    page(&ctx, true, query.0.redirect_to)
+}
+
+async fn register_start(Extension(ctx): Extension<Arc<Ctx>>, request: RequestContext) -> Response {
+    let Some(claim) = UserClaim::from_auth_user(&request.user) else {
+        return StatusCode::UNAUTHORIZED.into_response();
+    };
+
+    let username = request.user.username().unwrap_or_default().to_owned();
+    let challenge = match challenge(&ctx, PendingChallenge { claim: Some(claim) }).await {
+        Ok(challenge) => challenge,
+        Err(response) => return response,
+    };
+
+    Json(json!({
+        "publicKey": {
+            "challenge": challenge,
+            "rp": { "id": ctx.relying_party.id, "name": "Transmission Proxy" },
+            "user": {
+                "id": URL_SAFE_NO_PAD.encode(&username),
+                "name": username,
+                "displayName": username,
+            },
+            "pubKeyCredParams": [{ "type": "public-key", "alg": ES256 }],
+            "authenticatorSelection": {
+                "residentKey": "required",
+                "userVerification": "preferred",
+            },
+            "attestation": "none",
+            "timeout": CHALLENGE_TTL.as_millis() as u64,
+        }
+    }))
+    .into_response()
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RegisterResponse {
+    #[serde(rename = "clientDataJSON")]
+    client_data_json: String,
+    attestation_object: String,
+}
+
+async fn register_finish(
+    Extension(ctx): Extension<Arc<Ctx>>,
+    request: RequestContext,
+    Json(response): Json<RegisterResponse>,
+) -> Response {
+    let Some(claim) = UserClaim::from_auth_user(&request.user) else {
+        return StatusCode::UNAUTHORIZED.into_response();
+    };
+
+    let decoded = (|| {
+        Ok::<_, WebauthnError>((
+            webauthn::decode(&response.client_data_json)?,
+            webauthn::decode(&response.attestation_object)?,
+        ))
+    })();
+    let (client_data_json, attestation_object) = match decoded {
+        Ok(decoded) => decoded,
+        Err(err) => return rejected(err),
+    };
+
+    // The challenge must have been issued to the same user
+    let pending = match take_challenge(&ctx, &client_data_json).await {
+        Ok(pending) => pending,
+        Err(response) => return response,
+    };
+    if pending.claim.as_ref() != Some(&claim) {
+        warn!("webauthn challenge was issued to another user");
+        return StatusCode::UNAUTHORIZED.into_response();
+    }
+
+    let credential = match ctx
+        .relying_party
+        .register(&client_data_json, &attestation_object)
+    {
+        Ok(credential) => credential,
+        Err(err) => return rejected(err),
+    };
+
+    // Credential ids are chosen by the authenticator, so they must not replace another passkey
+    match ctx.storage.get(STORAGE_NAMESPACE, &credential.id) {
+        Ok(None) => {}
+        Ok(Some(_)) => {
+            warn!(id = %credential.id, "passkey is already registered");
+            return StatusCode::CONFLICT.into_response();
+        }
+        Err(err) => {
+            error!(%err, "could not load passkey");
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    }
+
+    let stored = StoredCredential {
+        public_key: URL_SAFE_NO_PAD.encode(&credential.public_key),
+        sign_count: credential.sign_count,
+        claim,
+        created: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default(),
+    };
+
+    if let Err(err) = ctx
+        .storage
+        .put_json(STORAGE_NAMESPACE, &credential.id, &stored)
+    {
+        error!(%err, "could not store passkey");
+        return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+    }
+
+    info!(user = request.user.username(), id = %credential.id, "passkey registered");
+    (StatusCode::CREATED, Json(json!({ "id": credential.id }))).into_response()
+}
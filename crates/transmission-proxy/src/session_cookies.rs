@@ -0,0 +1,121 @@
+//! Attributes of the cookies set on login: the login session cookie, and the short-lived cookie
+//! of OAuth2 logins in progress
+
+use cookie::{time::Duration, Cookie, CookieBuilder, SameSite};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+/// Default name of the login session cookie
+pub const LOGIN_COOKIE: &str = "_transmission_proxy";
+
+/// Default name of the cookie of OAuth2 logins in progress
+#[cfg(feature = "oauth")]
+pub const OAUTH_COOKIE: &str = "_transmission_proxy_session";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum SameSitePolicy {
+    Strict,
+    Lax,
+    /// Also sent along with cross-site requests, e.g. when the proxy is embedded in a frame of
+    /// another site. Implies `secure`.
+    None,
+}
+
+impl From<SameSitePolicy> for SameSite {
+    fn from(value: SameSitePolicy) -> Self {
+        match value {
+            SameSitePolicy::Strict => SameSite::Strict,
+            SameSitePolicy::Lax => SameSite::Lax,
+            SameSitePolicy::None => SameSite::None,
+        }
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct CookieAttributes {
+    /// Name of the cookie
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+
+    /// SameSite attribute of the cookie
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub same_site: Option<SameSitePolicy>,
+
+    /// Only send the cookie over HTTPS
+    #[serde(default)]
+    pub secure: bool,
+
+    /// Domain the cookie is sent to, only the host of the proxy if unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub domain: Option<String>,
+
+    /// Lifetime of the cookie in seconds, until the browser is closed if unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_age: Option<u64>,
+}
+
+impl CookieAttributes {
+    fn build(
+        &self,
+        default_name: &'static str,
+        default_same_site: Option<SameSitePolicy>,
+        value: String,
+    ) -> CookieBuilder<'static> {
+        let mut builder = Cookie::build(
+            self.name.clone().unwrap_or_else(|| default_name.to_owned()),
+            value,
+        )
+        .http_only(true);
+
+        let same_site = self.same_site.or(default_same_site);
+        if let Some(same_site) = same_site {
+            builder = builder.same_site(same_site.into());
+        }
+        if self.secure || same_site == Some(SameSitePolicy::None) {
+            builder = builder.secure(true);
+        }
+        if let Some(domain) = &self.domain {
+            builder = builder.domain(domain.clone());
+        }
+        if let Some(max_age) = self.max_age {
+            builder = builder.max_age(Duration::seconds(max_age.min(i64::MAX as u64) as i64));
+        }
+
+        builder
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct SessionCookies {
+    /// Login session cookie, SameSite=Strict by default
+    #[serde(default)]
+    pub login: CookieAttributes,
+
+    /// Cookie of OAuth2 logins in progress, without SameSite attribute by default
+    #[serde(default)]
+    pub oauth: CookieAttributes,
+}
+
+impl SessionCookies {
+    pub fn login_name(&self) -> &str {
+        self.login.name.as_deref().unwrap_or(LOGIN_COOKIE)
+    }
+
+    pub fn login_cookie(&self, value: String) -> CookieBuilder<'static> {
+        self.login
+            .build(LOGIN_COOKIE, Some(SameSitePolicy::Strict), value)
+    }
+
+    #[cfg(feature = "oauth")]
+    pub fn oauth_name(&self) -> &str {
+        self.oauth.name.as_deref().unwrap_or(OAUTH_COOKIE)
+    }
+
+    #[cfg(feature = "oauth")]
+    pub fn oauth_cookie(&self, value: String) -> Cookie<'static> {
+        self.oauth.build(OAUTH_COOKIE, None, value).finish()
+    }
+}
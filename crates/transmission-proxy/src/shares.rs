@@ -0,0 +1,78 @@
+//! Read-only links to the status of some torrents, for people without an account
+//!
+//! Links carry a token signed with the secret key, naming the user who shared them and the
+//! hashes of the shared torrents. Torrents are fetched on behalf of that user, so links stop
+//! showing torrents they can't access anymore.
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+fn default_lifetime() -> u64 {
+    24 * 60 * 60
+}
+
+fn default_max_lifetime() -> u64 {
+    7 * 24 * 60 * 60
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct SharesConfig {
+    /// Let users create share links
+    #[serde(default)]
+    pub enabled: bool,
+
+    /// Lifetime of links which don't specify one, in seconds
+    #[serde(default = "default_lifetime")]
+    pub lifetime: u64,
+
+    /// Maximum lifetime of links, in seconds
+    #[serde(default = "default_max_lifetime")]
+    pub max_lifetime: u64,
+}
+
+impl Default for SharesConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            lifetime: default_lifetime(),
+            max_lifetime: default_max_lifetime(),
+        }
+    }
+}
+
+impl SharesConfig {
+    /// Lifetime of a new link, in seconds
+    pub fn lifetime(&self, requested: Option<u64>) -> u64 {
+        requested.unwrap_or(self.lifetime).min(self.max_lifetime)
+    }
+}
+
+/// Fields of shared torrents. Paths, trackers and peers are left out.
+pub const FIELDS: &[&str] = &[
+    "hashString",
+    "name",
+    "status",
+    "percentDone",
+    "rateDownload",
+    "rateUpload",
+    "eta",
+    "totalSize",
+    "error",
+    "errorString",
+];
+
+/// Keep only the shared fields of a torrent
+pub fn project(torrent: &Value) -> Value {
+    let fields: Map<String, Value> = FIELDS
+        .iter()
+        .filter_map(|field| {
+            torrent
+                .get(field)
+                .map(|value| (field.to_string(), value.clone()))
+        })
+        .collect();
+
+    Value::Object(fields)
+}
@@ -0,0 +1,286 @@
+use std::{
+    collections::BTreeMap,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use thiserror::Error;
+
+use crate::{
+    acl::AclIdentity,
+    config::Config,
+    events::AuditTail,
+    provisioning::ProvisionedAcls,
+    secret_key,
+    storage::{Storage, StorageError},
+    torrent_index::{IndexEntry, TorrentIndex},
+};
+
+#[cfg(feature = "client")]
+mod client;
+#[cfg(feature = "client")]
+pub use client::{run, SnapshotArgs};
+
+/// Version of the snapshot format
+const VERSION: u32 = 1;
+
+/// Storage namespaces which only make sense for the instance which wrote them
+const LOCAL_NAMESPACES: &[&str] = &[secret_key::NAMESPACE];
+
+/// Configuration keys whose values are left out of snapshots
+const SECRET_KEYS: &[&str] = &[
+    "password",
+    "client_secret",
+    "secret_key",
+    "totp_secret",
+    "passkey",
+];
+
+/// Configuration keys holding headers, whose values may be credentials
+const SECRET_MAPS: &[&str] = &["headers", "set"];
+
+const REDACTED: &str = "<redacted>";
+
+#[derive(Debug, Error)]
+pub enum SnapshotError {
+    #[error("unsupported snapshot version {0}, expected {VERSION}")]
+    UnsupportedVersion(u32),
+    #[error(transparent)]
+    Storage(#[from] StorageError),
+    #[error(transparent)]
+    Base64(#[from] base64::DecodeError),
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+}
+
+/// A user known to the proxy
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SnapshotUser {
+    pub identity: AclIdentity,
+    /// Name of the ACL matching the user, if any names them
+    pub acl: Option<String>,
+}
+
+/// State of the proxy, to move it to another host
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub version: u32,
+    /// Unix timestamp of the export
+    pub created_at: u64,
+    /// Configuration, with secrets redacted
+    pub config: Value,
+    pub users: Vec<SnapshotUser>,
+    /// Torrents seen by the proxy, along with their owners
+    pub torrent_index: Vec<IndexEntry>,
+    /// Disk space allotted to each ACL with a quota, in bytes
+    #[serde(default)]
+    pub quotas: BTreeMap<String, u64>,
+    /// Latest events of the audit log, oldest first
+    #[serde(default)]
+    pub audit: Vec<Value>,
+    /// Persisted data, by namespace and key, encoded in base64
+    pub storage: BTreeMap<String, BTreeMap<String, String>>,
+}
+
+/// What was restored from a snapshot
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RestoreSummary {
+    pub torrents: usize,
+    pub entries: usize,
+}
+
+impl Snapshot {
+    pub fn collect(
+        config: &Config,
+        provisioned: &ProvisionedAcls,
+        index: &TorrentIndex,
+        storage: &dyn Storage,
+        audit: &AuditTail,
+    ) -> Result<Self, SnapshotError> {
+        let mut config_value = serde_json::to_value(config)?;
+        redact(&mut config_value);
+
+        let mut users: Vec<_> = config
+            .acl
+            .identities()
+            .into_iter()
+            .map(|(identity, acl)| SnapshotUser {
+                identity: identity.clone(),
+                acl: Some(acl.into_owned()),
+            })
+            .collect();
+
+        // Basic users which no ACL names
+        for username in config.providers.basic.usernames() {
+            let identity = AclIdentity::Basic { name: username };
+
+            if !users.iter().any(|user| user.identity == identity) {
+                users.push(SnapshotUser {
+                    identity,
+                    acl: None,
+                });
+            }
+        }
+
+        users.sort_by_cached_key(|user| serde_json::to_string(&user.identity).unwrap());
+
+        let mut quotas = BTreeMap::new();
+        for acl in config.acl.rules() {
+            if let Some(quota) = acl.quota {
+                quotas.insert(config.acl.name_of(acl).into_owned(), quota);
+            }
+        }
+        for acl in provisioned.list() {
+            if let (Some(name), Some(quota)) = (&acl.name, acl.quota) {
+                quotas.insert(name.clone(), quota);
+            }
+        }
+
+        let b64 = &base64::engine::general_purpose::STANDARD;
+        let mut entries = BTreeMap::new();
+        for namespace in storage.namespaces()? {
+            if LOCAL_NAMESPACES.contains(&namespace.as_str()) {
+                continue;
+            }
+
+            let values = storage
+                .list(&namespace)?
+                .into_iter()
+                .map(|(key, value)| (key, b64.encode(value)))
+                .collect();
+            entries.insert(namespace, values);
+        }
+
+        Ok(Self {
+            version: VERSION,
+            created_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or_default(),
+            config: config_value,
+            users,
+            torrent_index: index.entries(),
+            quotas,
+            audit: audit.events(),
+            storage: entries,
+        })
+    }
+
+    /// Restore the torrent index, persisted data and audit tail. The configuration, and the
+    /// quotas it sets, are left as is.
+    pub fn restore(
+        self,
+        index: &TorrentIndex,
+        storage: &dyn Storage,
+        audit: &AuditTail,
+    ) -> Result<RestoreSummary, SnapshotError> {
+        if self.version != VERSION {
+            return Err(SnapshotError::UnsupportedVersion(self.version));
+        }
+
+        // Decode everything before writing anything
+        let b64 = &base64::engine::general_purpose::STANDARD;
+        let mut entries = Vec::new();
+        for (namespace, values) in self.storage {
+            for (key, value) in values {
+                entries.push((namespace.clone(), key, b64.decode(value)?));
+            }
+        }
+
+        for (namespace, key, value) in &entries {
+            storage.put(namespace, key, value)?;
+        }
+
+        let torrents = self.torrent_index.len();
+        for entry in self.torrent_index {
+            index.insert(entry);
+        }
+
+        audit.prepend(self.audit);
+
+        Ok(RestoreSummary {
+            torrents,
+            entries: entries.len(),
+        })
+    }
+}
+
+/// Replace secrets in a configuration value
+fn redact(value: &mut Value) {
+    match value {
+        Value::Object(map) => {
+            for (key, value) in map.iter_mut() {
+                if SECRET_KEYS.contains(&key.as_str()) {
+                    *value = Value::String(REDACTED.into());
+                } else if SECRET_MAPS.contains(&key.as_str()) {
+                    redact_map(value);
+                } else {
+                    redact(value);
+                }
+            }
+        }
+        Value::Array(values) => values.iter_mut().for_each(redact),
+        Value::String(url) => {
+            if let Some(redacted) = redact_url(url) {
+                *url = redacted;
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Replace the string values of a map, e.g. of header names to values
+fn redact_map(value: &mut Value) {
+    match value {
+        Value::Object(map) => {
+            for value in map.values_mut() {
+                if value.is_string() {
+                    *value = Value::String(REDACTED.into());
+                } else {
+                    redact(value);
+                }
+            }
+        }
+        _ => redact(value),
+    }
+}
+
+/// Replace the password of a URL, e.g. of the Redis shared state, if it has one
+fn redact_url(url: &str) -> Option<String> {
+    let mut url = url::Url::parse(url).ok()?;
+    url.password()?;
+    url.set_password(Some(REDACTED)).ok()?;
+    Some(url.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn secrets_are_redacted() {
+        let mut config = json!({
+            "state": "redis://:hunter2@redis:6379/0",
+            "upstream": "http://transmission:9091",
+            "headers": { "request": { "drop": ["Cookie"], "set": { "Authorization": "Bearer x" } } },
+            "notifications": { "webhooks": [{ "url": "https://example.org", "headers": { "X-Token": "x" } }] },
+            "acl": { "rules": [{ "passkeys": [{ "tracker": "example.org", "passkey": "abcd" }] }] },
+        });
+        redact(&mut config);
+
+        assert_eq!(
+            config,
+            json!({
+                "state": "redis://:%3Credacted%3E@redis:6379/0",
+                "upstream": "http://transmission:9091",
+                "headers": { "request": { "drop": ["Cookie"], "set": { "Authorization": REDACTED } } },
+                "notifications": { "webhooks": [{ "url": "https://example.org", "headers": { "X-Token": REDACTED } }] },
+                "acl": { "rules": [{ "passkeys": [{ "tracker": "example.org", "passkey": REDACTED }] }] },
+            })
+        );
+    }
+}
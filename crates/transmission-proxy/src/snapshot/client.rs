@@ -0,0 +1,66 @@
+use std::path::PathBuf;
+
+use color_eyre::eyre;
+
+#[derive(Debug, clap::Args)]
+pub struct SnapshotArgs {
+    #[clap(subcommand)]
+    pub action: SnapshotAction,
+
+    /// Snapshot endpoint of the proxy
+    #[clap(
+        long,
+        default_value = "http://localhost:3000/transmission/admin/snapshot"
+    )]
+    pub target: url::Url,
+
+    /// Username of an admin, for basic authentication against the proxy
+    #[clap(long)]
+    pub username: Option<String>,
+
+    /// Password of the admin
+    #[clap(long, env = "TRANSMISSION_PROXY_SNAPSHOT_PASSWORD")]
+    pub password: Option<String>,
+}
+
+#[derive(Debug, clap::Subcommand)]
+pub enum SnapshotAction {
+    /// Write a snapshot of the proxy state to a file, or standard output
+    Export {
+        #[clap(long, short)]
+        output: Option<PathBuf>,
+    },
+    /// Restore the proxy state from a snapshot file
+    Import { file: PathBuf },
+}
+
+/// Export or import the state of a running proxy
+pub async fn run(args: SnapshotArgs) -> eyre::Result<()> {
+    let client = reqwest::Client::new();
+    let request = match &args.action {
+        SnapshotAction::Export { .. } => client.get(args.target.clone()),
+        SnapshotAction::Import { file } => client
+            .put(args.target.clone())
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .body(std::fs::read(file)?),
+    };
+
+    let request = match &args.username {
+        Some(username) => request.basic_auth(username, args.password.as_deref()),
+        None => request,
+    };
+
+    let response = request.send().await?.error_for_status()?;
+    let body = response.bytes().await?;
+
+    match args.action {
+        SnapshotAction::Export {
+            output: Some(output),
+        } => std::fs::write(output, body)?,
+        SnapshotAction::Export { output: None } | SnapshotAction::Import { .. } => {
+            println!("{}", String::from_utf8_lossy(&body))
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,350 @@
+use std::{
+    borrow::Cow,
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use redis::{Cmd, FromRedisValue, Pipeline};
+use thiserror::Error;
+#[cfg(feature = "oauth")]
+use {
+    async_session::{async_trait, Session, SessionStore},
+    std::sync::Arc,
+};
+
+/// Lifetime of sessions which do not set an expiry
+#[cfg(feature = "oauth")]
+const SESSION_TTL: Duration = Duration::from_secs(3600);
+
+#[derive(Debug, Error)]
+pub enum StateError {
+    #[error("unsupported state url {0:?}, expected redis://")]
+    UnsupportedUrl(String),
+    #[error(transparent)]
+    Redis(#[from] redis::RedisError),
+}
+
+/// State shared between the subsystems of the proxy, and between replicas when backed by Redis
+#[derive(Debug)]
+pub struct SharedState {
+    backend: Backend,
+    /// Prefix of the keys, so deployments can share a Redis database
+    namespace: Option<String>,
+}
+
+#[derive(Debug)]
+enum Backend {
+    Memory(MemoryState),
+    Redis(RedisState),
+}
+
+impl SharedState {
+    /// Open the state at the given url, keeping it in memory if unset
+    pub fn new(url: Option<&str>, namespace: Option<&str>) -> Result<Self, StateError> {
+        let backend = match url {
+            None => Backend::Memory(Default::default()),
+            Some(url) if url.starts_with("redis://") || url.starts_with("rediss://") => {
+                Backend::Redis(RedisState::new(url)?)
+            }
+            Some(url) => return Err(StateError::UnsupportedUrl(url.to_owned())),
+        };
+
+        Ok(Self {
+            backend,
+            namespace: namespace.map(str::to_owned),
+        })
+    }
+
+    fn key<'k>(&self, key: &'k str) -> Cow<'k, str> {
+        match &self.namespace {
+            Some(namespace) => Cow::Owned(format!("{namespace}:{key}")),
+            None => Cow::Borrowed(key),
+        }
+    }
+
+    pub async fn get(&self, key: &str) -> Result<Option<String>, StateError> {
+        let key = self.key(key);
+        match &self.backend {
+            Backend::Memory(state) => Ok(state.get(&key)),
+            Backend::Redis(state) => state.get(&key).await,
+        }
+    }
+
+    /// Store a value, which expires after the given duration if any
+    pub async fn set(
+        &self,
+        key: &str,
+        value: &str,
+        ttl: Option<Duration>,
+    ) -> Result<(), StateError> {
+        let key = self.key(key);
+        match &self.backend {
+            Backend::Memory(state) => {
+                state.set(&key, value, ttl);
+                Ok(())
+            }
+            Backend::Redis(state) => state.set(&key, value, ttl).await,
+        }
+    }
+
+    /// Increment a counter, which expires after the given duration from its first increment.
+    /// Returns the new value of the counter.
+    pub async fn incr(&self, key: &str, ttl: Duration) -> Result<u64, StateError> {
+        let key = self.key(key);
+        match &self.backend {
+            Backend::Memory(state) => Ok(state.incr(&key, ttl)),
+            Backend::Redis(state) => state.incr(&key, ttl).await,
+        }
+    }
+
+    #[cfg_attr(not(any(feature = "oauth", feature = "webauthn")), allow(dead_code))]
+    pub async fn delete(&self, key: &str) -> Result<(), StateError> {
+        let key = self.key(key);
+        match &self.backend {
+            Backend::Memory(state) => {
+                state.delete(&key);
+                Ok(())
+            }
+            Backend::Redis(state) => state.delete(&key).await,
+        }
+    }
+}
+
+/// State local to this process
+#[derive(Debug, Default)]
+pub struct MemoryState {
+    entries: Mutex<HashMap<String, (String, Option<Instant>)>>,
+}
+
+impl MemoryState {
+    fn get(&self, key: &str) -> Option<String> {
+        let mut entries = self.entries.lock().unwrap();
+        match entries.get(key) {
+            Some((_, Some(expires))) if *expires <= Instant::now() => {
+                entries.remove(key);
+                None
+            }
+            entry => entry.map(|(value, _)| value.clone()),
+        }
+    }
+
+    fn set(&self, key: &str, value: &str, ttl: Option<Duration>) {
+        let now = Instant::now();
+        let mut entries = self.entries.lock().unwrap();
+
+        // Drop expired entries, nothing else would
+        entries.retain(|_, (_, expires)| expires.map_or(true, |expires| expires > now));
+        entries.insert(key.to_owned(), (value.to_owned(), ttl.map(|ttl| now + ttl)));
+    }
+
+    fn incr(&self, key: &str, ttl: Duration) -> u64 {
+        let now = Instant::now();
+        let mut entries = self.entries.lock().unwrap();
+
+        let entry = entries
+            .entry(key.to_owned())
+            .or_insert_with(|| ("0".to_owned(), Some(now + ttl)));
+        if entry.1.map_or(false, |expires| expires <= now) {
+            *entry = ("0".to_owned(), Some(now + ttl));
+        }
+
+        let value = entry.0.parse::<u64>().unwrap_or_default() + 1;
+        entry.0 = value.to_string();
+        value
+    }
+
+    #[cfg_attr(not(any(feature = "oauth", feature = "webauthn")), allow(dead_code))]
+    fn delete(&self, key: &str) {
+        self.entries.lock().unwrap().remove(key);
+    }
+}
+
+/// State stored in Redis, shared between replicas
+pub struct RedisState {
+    client: redis::Client,
+    /// Connection opened on first use, and again after errors
+    connection: tokio::sync::Mutex<Option<redis::aio::MultiplexedConnection>>,
+}
+
+impl std::fmt::Debug for RedisState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RedisState")
+            .field("addr", &self.client.get_connection_info().addr)
+            .finish()
+    }
+}
+
+impl RedisState {
+    fn new(url: &str) -> Result<Self, StateError> {
+        Ok(Self {
+            client: redis::Client::open(url)?,
+            connection: Default::default(),
+        })
+    }
+
+    async fn connection(&self) -> Result<redis::aio::MultiplexedConnection, StateError> {
+        let mut connection = self.connection.lock().await;
+        if let Some(connection) = &*connection {
+            return Ok(connection.clone());
+        }
+
+        let opened = self.client.get_multiplexed_tokio_connection().await?;
+        *connection = Some(opened.clone());
+        Ok(opened)
+    }
+
+    /// Run a command, reconnecting on the next one if the connection failed
+    async fn run<T: FromRedisValue>(&self, command: Cmd) -> Result<T, StateError> {
+        let mut connection = self.connection().await?;
+        let result = command.query_async(&mut connection).await;
+        self.check(result).await
+    }
+
+    /// Run a pipeline of commands, reconnecting on the next one if the connection failed
+    async fn run_pipeline<T: FromRedisValue>(&self, pipeline: Pipeline) -> Result<T, StateError> {
+        let mut connection = self.connection().await?;
+        let result = pipeline.query_async(&mut connection).await;
+        self.check(result).await
+    }
+
+    async fn check<T>(&self, result: redis::RedisResult<T>) -> Result<T, StateError> {
+        if let Err(err) = &result {
+            if err.is_io_error() || err.is_connection_dropped() {
+                self.connection.lock().await.take();
+            }
+        }
+
+        Ok(result?)
+    }
+
+    async fn get(&self, key: &str) -> Result<Option<String>, StateError> {
+        self.run(Cmd::get(key)).await
+    }
+
+    async fn set(&self, key: &str, value: &str, ttl: Option<Duration>) -> Result<(), StateError> {
+        match ttl {
+            // Redis expiries are whole seconds
+            Some(ttl) => {
+                let seconds = ttl.as_secs().max(1) as usize;
+                self.run(Cmd::set_ex(key, value, seconds)).await
+            }
+            None => self.run(Cmd::set(key, value)).await,
+        }
+    }
+
+    async fn incr(&self, key: &str, ttl: Duration) -> Result<u64, StateError> {
+        // Create the counter with its expiry, then increment it, which keeps the expiry
+        let mut pipeline = redis::pipe();
+        pipeline
+            .atomic()
+            .cmd("SET")
+            .arg(key)
+            .arg(0)
+            .arg("EX")
+            .arg(ttl.as_secs().max(1))
+            .arg("NX")
+            .ignore()
+            .incr(key, 1);
+
+        let (value,): (u64,) = self.run_pipeline(pipeline).await?;
+        Ok(value)
+    }
+
+    #[cfg_attr(not(any(feature = "oauth", feature = "webauthn")), allow(dead_code))]
+    async fn delete(&self, key: &str) -> Result<(), StateError> {
+        self.run(Cmd::del(key)).await
+    }
+}
+
+/// Session store for login flows, so callbacks can reach any replica
+#[cfg(feature = "oauth")]
+#[derive(Debug, Clone)]
+pub struct SharedSessionStore {
+    state: Arc<SharedState>,
+    prefix: String,
+}
+
+#[cfg(feature = "oauth")]
+impl SharedSessionStore {
+    pub fn new(state: Arc<SharedState>, prefix: impl Into<String>) -> Self {
+        Self {
+            state,
+            prefix: prefix.into(),
+        }
+    }
+
+    fn key(&self, id: &str) -> String {
+        format!("{}:session:{}", self.prefix, id)
+    }
+}
+
+#[cfg(feature = "oauth")]
+#[async_trait]
+impl SessionStore for SharedSessionStore {
+    async fn load_session(&self, cookie_value: String) -> async_session::Result<Option<Session>> {
+        let id = Session::id_from_cookie_value(&cookie_value)?;
+        let Some(value) = self.state.get(&self.key(&id)).await? else {
+            return Ok(None);
+        };
+
+        let session: Session = serde_json::from_str(&value)?;
+        Ok(session.validate())
+    }
+
+    async fn store_session(&self, session: Session) -> async_session::Result<Option<String>> {
+        let ttl = session.expires_in().unwrap_or(SESSION_TTL);
+        self.state
+            .set(
+                &self.key(session.id()),
+                &serde_json::to_string(&session)?,
+                Some(ttl),
+            )
+            .await?;
+
+        session.reset_data_changed();
+        Ok(session.into_cookie_value())
+    }
+
+    async fn destroy_session(&self, session: Session) -> async_session::Result {
+        self.state.delete(&self.key(session.id())).await?;
+        Ok(())
+    }
+
+    async fn clear_store(&self) -> async_session::Result {
+        // Sessions expire on their own, and other replicas may still be using theirs
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn keys_are_namespaced() {
+        let state = SharedState::new(None, Some("replica-set")).unwrap();
+        state.set("maintenance", "true", None).await.unwrap();
+
+        let Backend::Memory(memory) = &state.backend else {
+            unreachable!();
+        };
+        assert_eq!(
+            memory.get("replica-set:maintenance").as_deref(),
+            Some("true")
+        );
+        assert_eq!(memory.get("maintenance"), None);
+    }
+
+    #[tokio::test]
+    async fn counters_expire() {
+        let state = SharedState::new(None, None).unwrap();
+        let ttl = Duration::from_millis(50);
+
+        assert_eq!(state.incr("calls", ttl).await.unwrap(), 1);
+        assert_eq!(state.incr("calls", ttl).await.unwrap(), 2);
+
+        tokio::time::sleep(ttl).await;
+        assert_eq!(state.incr("calls", ttl).await.unwrap(), 1);
+    }
+}
@@ -0,0 +1,208 @@
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use rusqlite::{params, OptionalExtension};
+use schemars::JsonSchema;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use thiserror::Error;
+
+/// Where data persisted by the proxy is kept
+#[derive(Debug, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct StorageConfig {
+    /// Path of an SQLite database to keep data in, in memory if unset
+    #[serde(default)]
+    pub sqlite: Option<PathBuf>,
+}
+
+impl StorageConfig {
+    pub fn open(&self) -> Result<Arc<dyn Storage>, StorageError> {
+        Ok(match &self.sqlite {
+            Some(path) => Arc::new(SqliteStorage::open(path)?),
+            None => Arc::new(MemoryStorage::default()),
+        })
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum StorageError {
+    #[error(transparent)]
+    Sqlite(#[from] rusqlite::Error),
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+}
+
+/// Key-value storage for data the proxy persists, grouped by namespace
+pub trait Storage: std::fmt::Debug + Send + Sync {
+    fn get(&self, namespace: &str, key: &str) -> Result<Option<Vec<u8>>, StorageError>;
+
+    fn put(&self, namespace: &str, key: &str, value: &[u8]) -> Result<(), StorageError>;
+
+    fn delete(&self, namespace: &str, key: &str) -> Result<(), StorageError>;
+
+    /// All the entries of a namespace, ordered by key
+    fn list(&self, namespace: &str) -> Result<Vec<(String, Vec<u8>)>, StorageError>;
+
+    /// Namespaces which have entries, in order
+    fn namespaces(&self) -> Result<Vec<String>, StorageError>;
+}
+
+impl<'a> dyn Storage + 'a {
+    pub fn get_json<T: DeserializeOwned>(
+        &self,
+        namespace: &str,
+        key: &str,
+    ) -> Result<Option<T>, StorageError> {
+        self.get(namespace, key)?
+            .map(|value| serde_json::from_slice(&value))
+            .transpose()
+            .map_err(Into::into)
+    }
+
+    pub fn put_json<T: Serialize>(
+        &self,
+        namespace: &str,
+        key: &str,
+        value: &T,
+    ) -> Result<(), StorageError> {
+        self.put(namespace, key, &serde_json::to_vec(value)?)
+    }
+}
+
+/// Storage local to this process
+#[derive(Debug, Default)]
+pub struct MemoryStorage {
+    entries: Mutex<BTreeMap<String, BTreeMap<String, Vec<u8>>>>,
+}
+
+impl Storage for MemoryStorage {
+    fn get(&self, namespace: &str, key: &str) -> Result<Option<Vec<u8>>, StorageError> {
+        Ok(self
+            .entries
+            .lock()
+            .unwrap()
+            .get(namespace)
+            .and_then(|entries| entries.get(key))
+            .cloned())
+    }
+
+    fn put(&self, namespace: &str, key: &str, value: &[u8]) -> Result<(), StorageError> {
+        self.entries
+            .lock()
+            .unwrap()
+            .entry(namespace.to_owned())
+            .or_default()
+            .insert(key.to_owned(), value.to_owned());
+        Ok(())
+    }
+
+    fn delete(&self, namespace: &str, key: &str) -> Result<(), StorageError> {
+        if let Some(entries) = self.entries.lock().unwrap().get_mut(namespace) {
+            entries.remove(key);
+        }
+        Ok(())
+    }
+
+    fn list(&self, namespace: &str) -> Result<Vec<(String, Vec<u8>)>, StorageError> {
+        Ok(self
+            .entries
+            .lock()
+            .unwrap()
+            .get(namespace)
+            .map(|entries| {
+                entries
+                    .iter()
+                    .map(|(key, value)| (key.clone(), value.clone()))
+                    .collect()
+            })
+            .unwrap_or_default())
+    }
+
+    fn namespaces(&self) -> Result<Vec<String>, StorageError> {
+        Ok(self
+            .entries
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(_, entries)| !entries.is_empty())
+            .map(|(namespace, _)| namespace.clone())
+            .collect())
+    }
+}
+
+/// Storage in an SQLite database
+#[derive(Debug)]
+pub struct SqliteStorage {
+    connection: Mutex<rusqlite::Connection>,
+}
+
+impl SqliteStorage {
+    pub fn open(path: &Path) -> Result<Self, StorageError> {
+        let connection = rusqlite::Connection::open(path)?;
+        connection.execute_batch(
+            "CREATE TABLE IF NOT EXISTS entries (
+                namespace TEXT NOT NULL,
+                key TEXT NOT NULL,
+                value BLOB NOT NULL,
+                PRIMARY KEY (namespace, key)
+            )",
+        )?;
+
+        Ok(Self {
+            connection: Mutex::new(connection),
+        })
+    }
+}
+
+impl Storage for SqliteStorage {
+    fn get(&self, namespace: &str, key: &str) -> Result<Option<Vec<u8>>, StorageError> {
+        Ok(self
+            .connection
+            .lock()
+            .unwrap()
+            .query_row(
+                "SELECT value FROM entries WHERE namespace = ?1 AND key = ?2",
+                params![namespace, key],
+                |row| row.get(0),
+            )
+            .optional()?)
+    }
+
+    fn put(&self, namespace: &str, key: &str, value: &[u8]) -> Result<(), StorageError> {
+        self.connection.lock().unwrap().execute(
+            "INSERT OR REPLACE INTO entries (namespace, key, value) VALUES (?1, ?2, ?3)",
+            params![namespace, key, value],
+        )?;
+        Ok(())
+    }
+
+    fn delete(&self, namespace: &str, key: &str) -> Result<(), StorageError> {
+        self.connection.lock().unwrap().execute(
+            "DELETE FROM entries WHERE namespace = ?1 AND key = ?2",
+            params![namespace, key],
+        )?;
+        Ok(())
+    }
+
+    fn list(&self, namespace: &str) -> Result<Vec<(String, Vec<u8>)>, StorageError> {
+        let connection = self.connection.lock().unwrap();
+        let mut statement = connection
+            .prepare("SELECT key, value FROM entries WHERE namespace = ?1 ORDER BY key")?;
+        let entries = statement
+            .query_map(params![namespace], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<Result<_, _>>()?;
+        Ok(entries)
+    }
+    fn namespaces(&self) -> Result<Vec<String>, StorageError> {
+        let connection = self.connection.lock().unwrap();
+        let mut statement =
+            connection.prepare("SELECT DISTINCT namespace FROM entries ORDER BY namespace")?;
+        let namespaces = statement
+            .query_map([], |row| row.get(0))?
+            .collect::<Result<_, _>>()?;
+        Ok(namespaces)
+    }
+}
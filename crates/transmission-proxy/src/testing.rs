@@ -0,0 +1,863 @@
+//! Test harness for running the proxy against a fake Transmission daemon
+//!
+//! [MockUpstream] implements the session id handshake and answers RPC calls with canned
+//! torrents or scripted responses, while recording every request it receives. [TestProxy] runs
+//! the proxy with a given configuration in front of it, so ACL configurations can be tested
+//! end-to-end.
+
+use std::{
+    net::{SocketAddr, TcpListener},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+use axum::{body::Bytes, http::HeaderMap, routing, Extension, Router, Server};
+use clap::Parser;
+use color_eyre::eyre;
+use hyper::{
+    header::{CONTENT_TYPE, HOST, LOCATION},
+    Body, Response, Uri,
+};
+use serde_json::{json, Value};
+use tokio::sync::{oneshot, Notify};
+
+use crate::{
+    config::Config,
+    rpc::{MethodCall, MethodName, Request, TorrentId, TorrentIdSet, TorrentIds},
+    server, Args,
+};
+
+pub use crate::rpc::proxy::SESSION_ID_HEADER;
+
+/// Secret key used for signing JWTs in tests
+const SECRET_KEY: &str = "transmission-proxy-test-util";
+
+struct MockState {
+    session_id: String,
+    torrents: Mutex<Vec<Value>>,
+    removed: Mutex<Vec<i32>>,
+    responses: Mutex<Vec<(MethodName, Value)>>,
+    requests: Mutex<Vec<Request>>,
+    /// Headers of the last RPC call
+    last_headers: Mutex<HeaderMap>,
+    /// Time taken to answer RPC calls
+    delay: Mutex<Duration>,
+    /// Lets the streamed web page finish
+    release: Notify,
+    /// Number of requests for the slow web page abandoned before it was sent
+    abandoned: AtomicUsize,
+    /// Chunks of the web interface page, replacing the default one
+    web_page: Mutex<Option<Vec<Bytes>>>,
+}
+
+/// A fake Transmission daemon
+pub struct MockUpstream {
+    addr: SocketAddr,
+    state: Arc<MockState>,
+    _shutdown: oneshot::Sender<()>,
+}
+
+impl MockUpstream {
+    /// Start a new mock daemon on a random local port
+    pub async fn start() -> eyre::Result<Self> {
+        let state = Arc::new(MockState {
+            session_id: "mock-session-id".to_owned(),
+            torrents: Default::default(),
+            removed: Default::default(),
+            responses: Default::default(),
+            requests: Default::default(),
+            last_headers: Default::default(),
+            delay: Default::default(),
+            release: Default::default(),
+            abandoned: Default::default(),
+            web_page: Default::default(),
+        });
+
+        let router = Router::new()
+            .route("/transmission/web/", routing::get(mock_web_page))
+            .route("/transmission/web", routing::get(mock_web_redirect))
+            .route("/transmission/web/stream", routing::get(mock_web_stream))
+            .route("/transmission/web/headers", routing::get(mock_web_headers))
+            .route("/transmission/web/slow", routing::get(mock_web_slow))
+            .fallback(handle_mock_request)
+            .layer(Extension(state.clone()));
+
+        let (addr, shutdown) = spawn(router)?;
+
+        Ok(Self {
+            addr,
+            state,
+            _shutdown: shutdown,
+        })
+    }
+
+    /// Base URI of the mock daemon, for use as the proxy upstream
+    pub fn uri(&self) -> Uri {
+        format!("http://{}", self.addr).parse().unwrap()
+    }
+
+    /// Session id expected by the mock daemon
+    pub fn session_id(&self) -> &str {
+        &self.state.session_id
+    }
+
+    /// Set the torrents returned by `torrent-get`. Each torrent is a JSON object which should at
+    /// least contain the `id` and `downloadDir` fields.
+    pub fn set_torrents(&self, torrents: Vec<Value>) {
+        *self.state.torrents.lock().unwrap() = torrents;
+    }
+
+    /// Set the ids reported as removed by `torrent-get` calls for the `recently-active` set
+    pub fn set_removed(&self, ids: Vec<i32>) {
+        *self.state.removed.lock().unwrap() = ids;
+    }
+
+    /// Set the arguments returned for calls to the given method
+    pub fn respond(&self, method: MethodName, arguments: Value) {
+        let mut responses = self.state.responses.lock().unwrap();
+        responses.retain(|(name, _)| *name != method);
+        responses.push((method, arguments));
+    }
+
+    /// Take some time to answer RPC calls, e.g. for them to overlap
+    pub fn set_delay(&self, delay: Duration) {
+        *self.state.delay.lock().unwrap() = delay;
+    }
+
+    /// Requests received by the mock daemon so far
+    pub fn requests(&self) -> Vec<Request> {
+        self.state.requests.lock().unwrap().clone()
+    }
+
+    /// Send the rest of the page streamed at `/transmission/web/stream`, or the page at
+    /// `/transmission/web/slow`
+    pub fn release_stream(&self) {
+        self.state.release.notify_one();
+    }
+
+    /// Serve the web interface page in the given chunks
+    pub fn set_web_page(&self, chunks: Vec<Bytes>) {
+        *self.state.web_page.lock().unwrap() = Some(chunks);
+    }
+
+    /// Number of requests for `/transmission/web/slow` dropped before the page was released
+    pub fn abandoned(&self) -> usize {
+        self.state.abandoned.load(Ordering::SeqCst)
+    }
+
+    /// Headers of the last RPC call received by the mock daemon
+    pub fn last_headers(&self) -> HeaderMap {
+        self.state.last_headers.lock().unwrap().clone()
+    }
+
+    /// Forget about the requests received so far
+    pub fn clear_requests(&self) {
+        self.state.requests.lock().unwrap().clear();
+    }
+}
+
+fn torrent_matches(torrent: &Value, ids: &Option<TorrentIds>) -> bool {
+    let id = torrent.get("id").and_then(Value::as_i64);
+    let hash = torrent.get("hashString").and_then(Value::as_str);
+
+    let matches = |torrent_id: &TorrentId| match torrent_id {
+        TorrentId::Id(needle) => id == Some(*needle as i64),
+        TorrentId::Sha1(needle) => hash == Some(needle.as_str()),
+    };
+
+    match ids {
+        None | Some(TorrentIds::Set(_)) => true,
+        Some(TorrentIds::Id(needle)) => matches(&TorrentId::Id(*needle)),
+        Some(TorrentIds::Ids(list)) => list.iter().any(matches),
+    }
+}
+
+/// Page served in place of the Transmission web interface
+async fn mock_web_page(Extension(state): Extension<Arc<MockState>>) -> Response<Body> {
+    let body = match state.web_page.lock().unwrap().clone() {
+        Some(chunks) => {
+            let (mut sender, body) = Body::channel();
+            tokio::spawn(async move {
+                for chunk in chunks {
+                    if sender.send_data(chunk).await.is_err() {
+                        return;
+                    }
+                }
+            });
+            body
+        }
+        None => Body::from(
+            "<html><head><title>Transmission</title></head><body class=\"mock\"></body></html>",
+        ),
+    };
+
+    Response::builder()
+        .header(CONTENT_TYPE, "text/html; charset=utf-8")
+        .body(body)
+        .unwrap()
+}
+
+/// Page sent in two chunks, the second one once the test releases it
+async fn mock_web_stream(Extension(state): Extension<Arc<MockState>>) -> Response<Body> {
+    let (mut sender, body) = Body::channel();
+    tokio::spawn(async move {
+        sender.send_data("first chunk".into()).await.ok();
+        state.release.notified().await;
+        sender.send_data("last chunk".into()).await.ok();
+    });
+
+    Response::new(body)
+}
+
+/// Page only sent once the test releases it
+async fn mock_web_slow(Extension(state): Extension<Arc<MockState>>) -> Response<Body> {
+    /// Counts the request as abandoned if dropped before the page is released
+    struct Pending(Option<Arc<MockState>>);
+
+    impl Drop for Pending {
+        fn drop(&mut self) {
+            if let Some(state) = self.0.take() {
+                state.abandoned.fetch_add(1, Ordering::SeqCst);
+            }
+        }
+    }
+
+    let mut pending = Pending(Some(state.clone()));
+    state.release.notified().await;
+    pending.0 = None;
+
+    mock_web_page(Extension(state)).await
+}
+
+/// Echo the request headers as a JSON object
+async fn mock_web_headers(headers: HeaderMap) -> Response<Body> {
+    let headers: serde_json::Map<String, Value> = headers
+        .iter()
+        .map(|(name, value)| {
+            (
+                name.to_string(),
+                String::from_utf8_lossy(value.as_bytes()).into(),
+            )
+        })
+        .collect();
+
+    Response::builder()
+        .header(CONTENT_TYPE, "application/json")
+        .header("X-Mock", "true")
+        .body(Body::from(Value::from(headers).to_string()))
+        .unwrap()
+}
+
+/// Redirect to the web interface with an absolute URL, as some daemons do
+async fn mock_web_redirect(headers: HeaderMap) -> Response<Body> {
+    let host = headers
+        .get(HOST)
+        .and_then(|host| host.to_str().ok())
+        .unwrap_or_default();
+
+    Response::builder()
+        .status(301)
+        .header(LOCATION, format!("http://{host}/transmission/web/"))
+        .body(Body::empty())
+        .unwrap()
+}
+
+async fn handle_mock_request(
+    Extension(state): Extension<Arc<MockState>>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response<Body> {
+    // Session id handshake
+    if headers.get(SESSION_ID_HEADER).map(|value| value.as_bytes())
+        != Some(state.session_id.as_bytes())
+    {
+        return Response::builder()
+            .status(409)
+            .header(SESSION_ID_HEADER, state.session_id.as_str())
+            .body(Body::empty())
+            .unwrap();
+    }
+
+    let request: Request = match serde_json::from_slice(&body) {
+        Ok(request) => request,
+        Err(err) => {
+            return Response::builder()
+                .status(400)
+                .body(Body::from(err.to_string()))
+                .unwrap();
+        }
+    };
+
+    state.requests.lock().unwrap().push(request.clone());
+    *state.last_headers.lock().unwrap() = headers;
+
+    let delay = *state.delay.lock().unwrap();
+    if !delay.is_zero() {
+        tokio::time::sleep(delay).await;
+    }
+
+    let method = MethodName::from(&request.call);
+    let scripted = state
+        .responses
+        .lock()
+        .unwrap()
+        .iter()
+        .find(|(name, _)| *name == method)
+        .map(|(_, arguments)| arguments.clone());
+
+    let arguments = match (scripted, &request.call) {
+        (Some(arguments), _) => arguments,
+        (None, MethodCall::TorrentGet { arguments }) => {
+            let torrents = state.torrents.lock().unwrap();
+            let mut result = json!({
+                "torrents": torrents
+                    .iter()
+                    .filter(|torrent| torrent_matches(torrent, &arguments.ids))
+                    .collect::<Vec<_>>(),
+            });
+
+            if let Some(TorrentIds::Set(TorrentIdSet::RecentlyActive)) = arguments.ids {
+                result["removed"] = json!(*state.removed.lock().unwrap());
+            }
+
+            result
+        }
+        (None, _) => json!({}),
+    };
+
+    Response::builder()
+        .status(200)
+        .header(CONTENT_TYPE, "application/json")
+        .body(Body::from(
+            json!({
+                "arguments": arguments,
+                "result": "success",
+                "tag": request.tag,
+            })
+            .to_string(),
+        ))
+        .unwrap()
+}
+
+/// Values recorded by a mock server, which tests can wait for
+struct Recorder<T> {
+    items: Mutex<Vec<T>>,
+    notify: Notify,
+}
+
+impl<T: Clone> Recorder<T> {
+    fn new() -> Arc<Self> {
+        Arc::new(Self {
+            items: Default::default(),
+            notify: Notify::new(),
+        })
+    }
+
+    fn push(&self, item: T) {
+        self.items.lock().unwrap().push(item);
+        self.notify.notify_waiters();
+    }
+
+    fn items(&self) -> Vec<T> {
+        self.items.lock().unwrap().clone()
+    }
+
+    /// Wait until at least `count` values were recorded, or a few seconds passed
+    async fn wait_for(&self, count: usize) -> Vec<T> {
+        let deadline = tokio::time::Instant::now() + Duration::from_secs(5);
+
+        loop {
+            let notified = self.notify.notified();
+            let items = self.items();
+            if items.len() >= count || tokio::time::Instant::now() >= deadline {
+                return items;
+            }
+
+            tokio::time::timeout_at(deadline, notified).await.ok();
+        }
+    }
+}
+
+/// Request received by a [MockWebhook]
+#[derive(Debug, Clone)]
+pub struct WebhookRequest {
+    pub path: String,
+    pub headers: HeaderMap,
+    pub body: Value,
+}
+
+/// A webhook receiver, recording the JSON bodies posted to it on any path
+pub struct MockWebhook {
+    addr: SocketAddr,
+    received: Arc<Recorder<WebhookRequest>>,
+    _shutdown: oneshot::Sender<()>,
+}
+
+impl MockWebhook {
+    pub async fn start() -> eyre::Result<Self> {
+        let received = Recorder::new();
+
+        let router = Router::new()
+            .fallback(receive_webhook)
+            .layer(Extension(received.clone()));
+
+        let (addr, shutdown) = spawn(router)?;
+
+        Ok(Self {
+            addr,
+            received,
+            _shutdown: shutdown,
+        })
+    }
+
+    /// URL to post notifications to
+    pub fn url(&self) -> String {
+        format!("http://{}/", self.addr)
+    }
+
+    /// Requests received so far
+    pub fn received(&self) -> Vec<WebhookRequest> {
+        self.received.items()
+    }
+
+    /// Wait until at least `count` requests were received, or a few seconds passed
+    pub async fn wait_for(&self, count: usize) -> Vec<WebhookRequest> {
+        self.received.wait_for(count).await
+    }
+}
+
+async fn receive_webhook(
+    Extension(received): Extension<Arc<Recorder<WebhookRequest>>>,
+    uri: Uri,
+    headers: HeaderMap,
+    body: Bytes,
+) {
+    received.push(WebhookRequest {
+        path: uri.path().to_owned(),
+        headers,
+        body: serde_json::from_slice(&body).unwrap_or(Value::Null),
+    });
+}
+
+/// An OAuth2 provider, issuing a token for any code on `/token` and answering `/userinfo` with
+/// the configured body
+pub struct MockOAuthProvider {
+    addr: SocketAddr,
+    userinfo: Arc<Mutex<Value>>,
+    _shutdown: oneshot::Sender<()>,
+}
+
+impl MockOAuthProvider {
+    pub async fn start() -> eyre::Result<Self> {
+        let userinfo = Arc::new(Mutex::new(Value::Null));
+
+        let router = Router::new()
+            .route(
+                "/token",
+                routing::post(|| async {
+                    axum::Json(json!({
+                        "access_token": "mock-access-token",
+                        "token_type": "bearer",
+                        "expires_in": 3600,
+                    }))
+                }),
+            )
+            .route(
+                "/userinfo",
+                routing::get(
+                    |Extension(userinfo): Extension<Arc<Mutex<Value>>>| async move {
+                        axum::Json(userinfo.lock().unwrap().clone())
+                    },
+                ),
+            )
+            .layer(Extension(userinfo.clone()));
+
+        let (addr, shutdown) = spawn(router)?;
+
+        Ok(Self {
+            addr,
+            userinfo,
+            _shutdown: shutdown,
+        })
+    }
+
+    /// URL of an endpoint of the provider, e.g. `/token`
+    pub fn url(&self, path: &str) -> String {
+        format!("http://{}{path}", self.addr)
+    }
+
+    /// Body of the userinfo responses
+    pub fn set_userinfo(&self, userinfo: Value) {
+        *self.userinfo.lock().unwrap() = userinfo;
+    }
+}
+
+/// A file server, serving the same file on `/file` and redirecting to it from `/redirect`
+pub struct MockFileServer {
+    addr: SocketAddr,
+    _shutdown: oneshot::Sender<()>,
+}
+
+impl MockFileServer {
+    pub async fn start(file: Vec<u8>) -> eyre::Result<Self> {
+        let router = Router::new()
+            .route("/file", routing::get(move || async move { file }))
+            .route(
+                "/redirect",
+                routing::get(|| async {
+                    Response::builder()
+                        .status(302)
+                        .header(LOCATION, "/file")
+                        .body(Body::empty())
+                        .unwrap()
+                }),
+            );
+
+        let (addr, shutdown) = spawn(router)?;
+
+        Ok(Self {
+            addr,
+            _shutdown: shutdown,
+        })
+    }
+
+    /// URL of a path on the server
+    pub fn url(&self, path: &str) -> String {
+        format!("http://{}{path}", self.addr)
+    }
+}
+
+/// Email received by a [MockSmtp]
+#[derive(Debug, Clone)]
+pub struct MockEmail {
+    /// Envelope recipients
+    pub to: Vec<String>,
+    /// Headers and body of the message
+    pub data: String,
+}
+
+/// A mail server accepting all emails without encryption or authentication
+pub struct MockSmtp {
+    port: u16,
+    received: Arc<Recorder<MockEmail>>,
+}
+
+impl MockSmtp {
+    pub async fn start() -> eyre::Result<Self> {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+        let port = listener.local_addr()?.port();
+        let received = Recorder::new();
+
+        let recorder = received.clone();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                tokio::spawn(smtp_session(stream, recorder.clone()));
+            }
+        });
+
+        Ok(Self { port, received })
+    }
+
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    /// Wait until at least `count` emails were received, or a few seconds passed
+    pub async fn wait_for(&self, count: usize) -> Vec<MockEmail> {
+        self.received.wait_for(count).await
+    }
+}
+
+async fn smtp_session(
+    stream: tokio::net::TcpStream,
+    received: Arc<Recorder<MockEmail>>,
+) -> std::io::Result<()> {
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+
+    let (reader, mut writer) = stream.into_split();
+    let mut lines = BufReader::new(reader).lines();
+    let mut to = Vec::new();
+
+    writer.write_all(b"220 mock ESMTP\r\n").await?;
+    while let Some(line) = lines.next_line().await? {
+        let command = line.to_ascii_uppercase();
+
+        let reply: &[u8] = if command.starts_with("RCPT TO:") {
+            to.push(
+                line[8..]
+                    .trim_matches(|c| c == '<' || c == '>' || c == ' ')
+                    .to_owned(),
+            );
+            b"250 OK\r\n"
+        } else if command == "DATA" {
+            writer
+                .write_all(b"354 End data with <CR><LF>.<CR><LF>\r\n")
+                .await?;
+
+            let mut data = String::new();
+            while let Some(line) = lines.next_line().await? {
+                if line == "." {
+                    break;
+                }
+                data.push_str(&line);
+                data.push('\n');
+            }
+
+            received.push(MockEmail {
+                to: std::mem::take(&mut to),
+                data,
+            });
+            b"250 OK\r\n"
+        } else if command == "QUIT" {
+            writer.write_all(b"221 Bye\r\n").await?;
+            return Ok(());
+        } else {
+            b"250 OK\r\n"
+        };
+
+        writer.write_all(reply).await?;
+    }
+
+    Ok(())
+}
+
+/// An instance of the proxy running on a random local port
+pub struct TestProxy {
+    addr: SocketAddr,
+    _shutdown: oneshot::Sender<()>,
+}
+
+impl TestProxy {
+    /// Start the proxy with the given YAML configuration in front of `upstream`
+    pub async fn start(config: &str, upstream: Uri) -> eyre::Result<Self> {
+        Self::start_with_args(config, upstream, &[]).await
+    }
+
+    /// Start the proxy with additional command-line arguments
+    pub async fn start_with_args(
+        config: &str,
+        upstream: Uri,
+        extra: &[&str],
+    ) -> eyre::Result<Self> {
+        let listener = bind()?;
+        let addr = listener.local_addr()?;
+
+        let bind = format!("http://{addr}/transmission");
+        let upstream = upstream.to_string();
+        // Tests may use a secret key file instead
+        let secret_key = if extra.iter().any(|arg| arg.starts_with("--secret-key")) {
+            &[][..]
+        } else {
+            &["--secret-key", SECRET_KEY][..]
+        };
+        let args = Args::try_parse_from(
+            [
+                "transmission-proxy",
+                "--bind",
+                &bind,
+                "--upstream",
+                &upstream,
+            ]
+            .into_iter()
+            .chain(secret_key.iter().copied())
+            .chain(extra.iter().copied()),
+        )?;
+
+        let config = Config::load(config, args.lenient_config)?;
+        let listener_config = config.listener.clone();
+        let router = server::app(args, config)?;
+
+        let (shutdown, rx) = oneshot::channel::<()>();
+        tokio::spawn(async move {
+            if let Err(err) = listener_config
+                .serve(listener, router, async {
+                    rx.await.ok();
+                })
+                .await
+            {
+                tracing::error!(%err, "test proxy failed");
+            }
+        });
+
+        Ok(Self {
+            addr,
+            _shutdown: shutdown,
+        })
+    }
+
+    /// Base URL of the proxy
+    pub fn url(&self) -> String {
+        self.origin() + "/transmission"
+    }
+
+    /// URL of the proxy root, outside of the bind path
+    pub fn origin(&self) -> String {
+        format!("http://{}", self.addr)
+    }
+
+    /// URL of the RPC endpoint
+    pub fn rpc_url(&self) -> String {
+        self.url() + "/rpc"
+    }
+}
+
+/// Session cookie signed with the secret key of test proxies, for claims they would not issue
+pub fn session_cookie(claim: &Value) -> String {
+    use hmac::Mac;
+    use jwt::SignWithKey;
+
+    let key = hmac::Hmac::<sha2::Sha256>::new_from_slice(SECRET_KEY.as_bytes()).unwrap();
+    claim.sign_with_key(&key).unwrap()
+}
+
+/// Current TOTP code for a base32 secret
+pub fn totp_code(secret: &str) -> String {
+    let secret = crate::totp::decode_secret(secret).expect("invalid totp secret");
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    crate::totp::code(&secret, now)
+}
+
+/// Software passkey, answering the challenges of the webauthn provider
+#[cfg(feature = "webauthn")]
+pub struct Authenticator {
+    key: p256::ecdsa::SigningKey,
+    credential_id: Vec<u8>,
+    rp_id: String,
+    origin: String,
+    sign_count: u32,
+}
+
+#[cfg(feature = "webauthn")]
+impl Authenticator {
+    /// New passkey for the given relying party and page origin
+    pub fn new(rp_id: &str, origin: &str) -> Self {
+        use rand::RngCore;
+
+        let mut credential_id = vec![0u8; 16];
+        rand::thread_rng().fill_bytes(&mut credential_id);
+
+        Self {
+            key: p256::ecdsa::SigningKey::random(&mut rand::rngs::OsRng),
+            credential_id,
+            rp_id: rp_id.to_owned(),
+            origin: origin.to_owned(),
+            sign_count: 0,
+        }
+    }
+
+    /// Answer the options of `register/start`, as `register/finish` expects
+    pub fn register(&mut self, options: &Value) -> Value {
+        use ciborium::value::Value as Cbor;
+
+        let client_data = self.client_data("webauthn.create", options);
+
+        let point = self.key.verifying_key().to_encoded_point(false);
+        let public_key = Cbor::Map(vec![
+            (1.into(), 2.into()),
+            (3.into(), crate::webauthn::ES256.into()),
+            ((-1).into(), 1.into()),
+            ((-2).into(), Cbor::Bytes(point.x().unwrap().to_vec())),
+            ((-3).into(), Cbor::Bytes(point.y().unwrap().to_vec())),
+        ]);
+
+        let mut auth_data = self.authenticator_data(0x41);
+        auth_data.extend_from_slice(&[0; 16]);
+        auth_data.extend_from_slice(&(self.credential_id.len() as u16).to_be_bytes());
+        auth_data.extend_from_slice(&self.credential_id);
+        ciborium::ser::into_writer(&public_key, &mut auth_data).unwrap();
+
+        let mut attestation_object = Vec::new();
+        ciborium::ser::into_writer(
+            &Cbor::Map(vec![
+                ("fmt".into(), "none".into()),
+                ("attStmt".into(), Cbor::Map(vec![])),
+                ("authData".into(), Cbor::Bytes(auth_data)),
+            ]),
+            &mut attestation_object,
+        )
+        .unwrap();
+
+        json!({
+            "clientDataJSON": encode(&client_data),
+            "attestationObject": encode(&attestation_object),
+        })
+    }
+
+    /// Answer the options of `login/start`, as `login/finish` expects
+    pub fn login(&mut self, options: &Value) -> Value {
+        use p256::ecdsa::{signature::Signer, Signature};
+        use sha2::{Digest, Sha256};
+
+        let client_data = self.client_data("webauthn.get", options);
+
+        self.sign_count += 1;
+        let auth_data = self.authenticator_data(0x01);
+
+        let mut message = auth_data.clone();
+        message.extend_from_slice(&Sha256::digest(&client_data));
+        let signature: Signature = self.key.sign(&message);
+
+        json!({
+            "id": encode(&self.credential_id),
+            "clientDataJSON": encode(&client_data),
+            "authenticatorData": encode(&auth_data),
+            "signature": encode(signature.to_der().as_bytes()),
+        })
+    }
+
+    fn client_data(&self, kind: &str, options: &Value) -> Vec<u8> {
+        serde_json::to_vec(&json!({
+            "type": kind,
+            "challenge": options["publicKey"]["challenge"],
+            "origin": self.origin,
+        }))
+        .unwrap()
+    }
+
+    fn authenticator_data(&self, flags: u8) -> Vec<u8> {
+        use sha2::{Digest, Sha256};
+
+        let mut data = Sha256::digest(self.rp_id.as_bytes()).to_vec();
+        data.push(flags);
+        data.extend_from_slice(&self.sign_count.to_be_bytes());
+        data
+    }
+}
+
+#[cfg(feature = "webauthn")]
+fn encode(bytes: &[u8]) -> String {
+    use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+
+    URL_SAFE_NO_PAD.encode(bytes)
+}
+
+fn bind() -> eyre::Result<TcpListener> {
+    let listener = TcpListener::bind("127.0.0.1:0")?;
+    listener.set_nonblocking(true)?;
+    Ok(listener)
+}
+
+fn spawn(router: Router) -> eyre::Result<(SocketAddr, oneshot::Sender<()>)> {
+    serve(bind()?, router)
+}
+
+fn serve(listener: TcpListener, router: Router) -> eyre::Result<(SocketAddr, oneshot::Sender<()>)> {
+    let addr = listener.local_addr()?;
+    let (tx, rx) = oneshot::channel::<()>();
+
+    let server = Server::from_tcp(listener)?
+        .serve(router.into_make_service())
+        .with_graceful_shutdown(async {
+            rx.await.ok();
+        });
+
+    tokio::spawn(server);
+
+    Ok((addr, tx))
+}
@@ -1,5 +1,9 @@
+use std::{collections::HashSet, fmt::Write};
+
 use serde::{Deserialize, Serialize};
+use serde_bencode::value::Value;
 use serde_bytes::ByteBuf;
+use sha1::{Digest, Sha1};
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Node(String, i64);
@@ -45,6 +49,9 @@ pub struct Torrent {
     #[serde(default)]
     pub httpseeds: Option<Vec<String>>,
     #[serde(default)]
+    #[serde(rename = "url-list")]
+    pub url_list: Option<UrlList>,
+    #[serde(default)]
     #[serde(rename = "announce-list")]
     pub announce_list: Option<Vec<Vec<String>>>,
     #[serde(default)]
@@ -56,3 +63,281 @@ pub struct Torrent {
     #[serde(rename = "created by")]
     pub created_by: Option<String>,
 }
+
+/// Web seeds of a torrent, as a single URL or a list of URLs
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum UrlList {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl Info {
+    /// Returns the first file path which could be written outside of the download dir, or is
+    /// hidden if `deny_hidden` is set
+    pub fn unsafe_path(&self, deny_hidden: bool) -> Option<String> {
+        let is_unsafe = |segment: &str| {
+            segment.is_empty()
+                || segment == "."
+                || segment == ".."
+                || segment.contains(['/', '\\'])
+                // Drive letters on Windows daemons
+                || matches!(segment.as_bytes(), [letter, b':', ..] if letter.is_ascii_alphabetic())
+                || (deny_hidden && segment.starts_with('.'))
+        };
+
+        if is_unsafe(&self.name) {
+            return Some(self.name.clone());
+        }
+
+        self.files
+            .iter()
+            .flatten()
+            .find(|file| file.path.is_empty() || file.path.iter().any(|s| is_unsafe(s)))
+            .map(|file| file.path.join("/"))
+    }
+}
+
+impl Torrent {
+    /// Announce URLs of all the trackers of this torrent
+    pub fn trackers(&self) -> impl Iterator<Item = &String> {
+        self.announce
+            .iter()
+            .chain(self.announce_list.iter().flatten().flatten())
+    }
+
+    /// Apply a filter to the web seed lists of this torrent (`url-list` and `httpseeds`),
+    /// removing the lists left empty
+    pub fn filter_webseeds(&mut self, mut filter: impl FnMut(&mut Vec<String>)) {
+        if let Some(url_list) = self.url_list.take() {
+            let mut urls = match url_list {
+                UrlList::One(url) => vec![url],
+                UrlList::Many(urls) => urls,
+            };
+
+            filter(&mut urls);
+            self.url_list = (!urls.is_empty()).then_some(UrlList::Many(urls));
+        }
+
+        if let Some(mut httpseeds) = self.httpseeds.take() {
+            filter(&mut httpseeds);
+            self.httpseeds = (!httpseeds.is_empty()).then_some(httpseeds);
+        }
+    }
+
+    /// Remove duplicate announce URLs and empty tiers from the announce list, keeping the first
+    /// occurrence of each URL, and merge all the tiers into one if `flatten` is set
+    pub fn normalize_trackers(&mut self, flatten: bool) {
+        let Some(announce_list) = self.announce_list.as_mut() else {
+            return;
+        };
+
+        let mut seen = HashSet::new();
+        for tier in announce_list.iter_mut() {
+            tier.retain(|url| seen.insert(url.clone()));
+        }
+        announce_list.retain(|tier| !tier.is_empty());
+
+        if flatten && announce_list.len() > 1 {
+            *announce_list = vec![announce_list.concat()];
+        }
+    }
+
+    /// Keep at most `max_tiers` tiers and `max_trackers` trackers in the announce list
+    pub fn limit_trackers(&mut self, max_trackers: Option<usize>, max_tiers: Option<usize>) {
+        let Some(announce_list) = self.announce_list.as_mut() else {
+            return;
+        };
+
+        announce_list.retain(|tier| !tier.is_empty());
+        announce_list.truncate(max_tiers.unwrap_or(usize::MAX));
+
+        let mut remaining = max_trackers.unwrap_or(usize::MAX);
+        for tier in announce_list.iter_mut() {
+            tier.truncate(remaining);
+            remaining -= tier.len();
+        }
+
+        announce_list.retain(|tier| !tier.is_empty());
+
+        // The main announce URL is only used by clients without announce list support
+        if let Some(first) = announce_list.first().and_then(|tier| tier.first()) {
+            if !announce_list
+                .iter()
+                .flatten()
+                .any(|url| Some(url) == self.announce.as_ref())
+            {
+                self.announce = Some(first.clone());
+            }
+        }
+    }
+}
+
+/// Announce URLs of the trackers in a magnet link
+pub fn magnet_trackers(magnet: &str) -> Vec<String> {
+    let query = magnet.split_once('?').map_or("", |(_, query)| query);
+
+    url::form_urlencoded::parse(query.as_bytes())
+        .filter(|(key, _)| key == "tr" || key.starts_with("tr."))
+        .map(|(_, value)| value.into_owned())
+        .collect()
+}
+
+/// Keep at most `max_trackers` trackers in a magnet link
+pub fn limit_magnet_trackers(magnet: &str, max_trackers: usize) -> String {
+    let Some((base, query)) = magnet.split_once('?') else {
+        return magnet.to_owned();
+    };
+
+    let mut trackers = 0;
+    let params: Vec<_> = query
+        .split('&')
+        .filter(|param| {
+            if param.starts_with("tr=") || param.starts_with("tr.") {
+                trackers += 1;
+                trackers <= max_trackers
+            } else {
+                true
+            }
+        })
+        .collect();
+
+    base.to_owned() + "?" + &params.join("&")
+}
+
+/// Remove the duplicate trackers of a magnet link
+pub fn dedup_magnet_trackers(magnet: &str) -> String {
+    let Some((base, query)) = magnet.split_once('?') else {
+        return magnet.to_owned();
+    };
+
+    let mut seen = HashSet::new();
+    let params: Vec<_> = query
+        .split('&')
+        .filter(|param| {
+            if param.starts_with("tr=") || param.starts_with("tr.") {
+                let tracker = url::form_urlencoded::parse(param.as_bytes())
+                    .next()
+                    .map(|(_, value)| value.into_owned());
+                seen.insert(tracker)
+            } else {
+                true
+            }
+        })
+        .collect();
+
+    base.to_owned() + "?" + &params.join("&")
+}
+
+/// Remove the web seeds of a magnet link
+pub fn strip_magnet_webseeds(magnet: &str) -> String {
+    let Some((base, query)) = magnet.split_once('?') else {
+        return magnet.to_owned();
+    };
+
+    let params: Vec<_> = query
+        .split('&')
+        .filter(|param| !param.starts_with("ws=") && !param.starts_with("ws."))
+        .collect();
+
+    base.to_owned() + "?" + &params.join("&")
+}
+
+/// File of a torrent, as reported by [Metadata]
+#[derive(Debug, Serialize)]
+pub struct MetadataFile {
+    pub path: String,
+    pub length: i64,
+}
+
+/// Summary of a torrent file or magnet link
+#[derive(Debug, Serialize)]
+pub struct Metadata {
+    pub name: Option<String>,
+    /// Hex encoded v1 info hash
+    pub info_hash: String,
+    /// Total size in bytes, unknown for magnet links
+    pub size: Option<i64>,
+    pub files: Vec<MetadataFile>,
+    pub private: bool,
+    pub trackers: Vec<String>,
+}
+
+impl Metadata {
+    /// Parse the metadata of a bencoded torrent file
+    pub fn from_metainfo(bytes: &[u8]) -> Result<Self, serde_bencode::Error> {
+        let torrent: Torrent = serde_bencode::from_bytes(bytes)?;
+
+        let files: Vec<MetadataFile> = match &torrent.info.files {
+            Some(files) => files
+                .iter()
+                .map(|file| MetadataFile {
+                    path: std::iter::once(torrent.info.name.as_str())
+                        .chain(file.path.iter().map(String::as_str))
+                        .collect::<Vec<_>>()
+                        .join("/"),
+                    length: file.length,
+                })
+                .collect(),
+            None => vec![MetadataFile {
+                path: torrent.info.name.clone(),
+                length: torrent.info.length.unwrap_or_default(),
+            }],
+        };
+
+        Ok(Self {
+            name: Some(torrent.info.name.clone()),
+            info_hash: info_hash(bytes)?,
+            size: Some(files.iter().map(|file| file.length).sum()),
+            files,
+            private: torrent.info.private == Some(1),
+            trackers: torrent.trackers().cloned().collect(),
+        })
+    }
+
+    /// Parse the metadata of a magnet link, returning None if it has no BitTorrent info hash
+    pub fn from_magnet(magnet: &str) -> Option<Self> {
+        let query = magnet.strip_prefix("magnet:?")?;
+        let params: Vec<_> = url::form_urlencoded::parse(query.as_bytes()).collect();
+
+        Some(Self {
+            info_hash: magnet_info_hash(magnet)?,
+            name: params
+                .iter()
+                .find(|(key, _)| key == "dn")
+                .map(|(_, value)| value.clone().into_owned()),
+            size: None,
+            files: Vec::new(),
+            private: false,
+            trackers: magnet_trackers(magnet),
+        })
+    }
+}
+
+/// Hex encoded v1 info hash of a bencoded torrent file
+pub fn info_hash(bytes: &[u8]) -> Result<String, serde_bencode::Error> {
+    // The hash is computed on the original info dictionary, including unknown keys
+    let info = match serde_bencode::from_bytes(bytes)? {
+        Value::Dict(mut dict) => dict.remove(b"info".as_slice()),
+        _ => None,
+    }
+    .ok_or_else(|| serde_bencode::Error::MissingField("info".to_owned()))?;
+
+    Ok(hex(&Sha1::digest(serde_bencode::to_bytes(&info)?)))
+}
+
+/// Hex encoded info hash of a magnet link, if it has a BitTorrent one
+pub fn magnet_info_hash(magnet: &str) -> Option<String> {
+    let query = magnet.strip_prefix("magnet:?")?;
+
+    url::form_urlencoded::parse(query.as_bytes()).find_map(|(key, value)| {
+        (key == "xt").then(|| value.strip_prefix("urn:btih:").map(str::to_ascii_lowercase))?
+    })
+}
+
+pub(crate) fn hex(bytes: &[u8]) -> String {
+    bytes.iter().fold(String::new(), |mut hex, byte| {
+        let _ = write!(hex, "{byte:02x}");
+        hex
+    })
+}
@@ -0,0 +1,250 @@
+use std::{
+    borrow::Cow,
+    collections::HashMap,
+    sync::RwLock,
+    time::{Duration, Instant},
+};
+
+use base64::Engine;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tracing::{error, info};
+
+use crate::{
+    ownership::OwnerLabels,
+    rpc::{
+        proxy::{FilterErrorKind, RpcProxyClient},
+        Torrent, TorrentAdd, TorrentGet, TorrentId,
+    },
+};
+
+#[derive(Debug, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct TorrentIndexConfig {
+    /// Load the torrents of the upstream into the index at startup
+    #[serde(default)]
+    pub seed: bool,
+}
+
+/// What is known of a torrent of the upstream
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IndexEntry {
+    pub id: i32,
+    /// Info hash, as a lowercase hex string
+    pub info_hash: Option<String>,
+    pub download_dir: Option<String>,
+    /// Name of the user who added the torrent
+    pub owner: Option<String>,
+}
+
+/// How long the download dir of a removed torrent is kept. Transmission reports the torrents
+/// removed in the last minute to `recently-active` calls, and their removal is only reported to
+/// users of the download dir.
+const REMOVED_RETENTION: Duration = Duration::from_secs(120);
+
+#[derive(Debug, Default)]
+struct Entries {
+    by_id: HashMap<i32, IndexEntry>,
+    by_hash: HashMap<String, i32>,
+    /// Download dirs of recently removed torrents, with the time they were removed
+    removed: HashMap<i32, (Instant, Option<String>)>,
+}
+
+/// In-memory index of the torrents of the upstream, maintained from proxied traffic
+#[derive(Debug)]
+pub struct TorrentIndex {
+    owner_labels: OwnerLabels,
+    entries: RwLock<Entries>,
+}
+
+impl TorrentIndex {
+    pub fn new(owner_labels: &OwnerLabels) -> Self {
+        Self {
+            owner_labels: owner_labels.clone(),
+            entries: Default::default(),
+        }
+    }
+
+    /// Record the fields of torrents returned by the upstream
+    pub fn update(&self, torrents: &[Torrent]) {
+        let mut entries = self.entries.write().unwrap();
+
+        for torrent in torrents {
+            let Some(TorrentId::Id(id)) = torrent.id else {
+                continue;
+            };
+
+            let owner = torrent
+                .labels
+                .as_deref()
+                .and_then(|labels| self.owner_labels.owner(labels))
+                .map(str::to_owned);
+
+            entries.insert(IndexEntry {
+                id,
+                info_hash: torrent.hash_string.clone(),
+                download_dir: torrent.download_dir.clone(),
+                owner,
+            });
+        }
+    }
+
+    /// Record a torrent added through the proxy
+    pub fn insert(&self, entry: IndexEntry) {
+        self.entries.write().unwrap().insert(entry);
+    }
+
+    /// Forget removed torrents, only keeping their download dir for a while
+    pub fn remove(&self, ids: impl IntoIterator<Item = i32>) {
+        let mut entries = self.entries.write().unwrap();
+
+        let now = Instant::now();
+        entries
+            .removed
+            .retain(|_, (removed_at, _)| now.duration_since(*removed_at) < REMOVED_RETENTION);
+
+        for id in ids {
+            let Some(entry) = entries.by_id.remove(&id) else {
+                continue;
+            };
+
+            if let Some(hash) = entry.info_hash {
+                if entries.by_hash.get(&hash) == Some(&id) {
+                    entries.by_hash.remove(&hash);
+                }
+            }
+            entries.removed.insert(id, (now, entry.download_dir));
+        }
+    }
+
+    /// Look up a torrent by info hash
+    pub fn get(&self, info_hash: &str) -> Option<IndexEntry> {
+        let entries = self.entries.read().unwrap();
+        entries
+            .by_hash
+            .get(&info_hash.to_ascii_lowercase())
+            .and_then(|id| entries.by_id.get(id))
+            .cloned()
+    }
+
+    /// Download dir of a torrent, including the torrents removed recently
+    pub fn download_dir(&self, id: i32) -> Option<String> {
+        let entries = self.entries.read().unwrap();
+        match entries.by_id.get(&id) {
+            Some(entry) => entry.download_dir.clone(),
+            None => entries
+                .removed
+                .get(&id)
+                .and_then(|(_, download_dir)| download_dir.clone()),
+        }
+    }
+
+    /// All the known torrents, by id
+    pub fn entries(&self) -> Vec<IndexEntry> {
+        let mut entries: Vec<_> = self
+            .entries
+            .read()
+            .unwrap()
+            .by_id
+            .values()
+            .cloned()
+            .collect();
+        entries.sort_by_key(|entry| entry.id);
+        entries
+    }
+
+    /// Load all the torrents of the upstream
+    pub async fn seed(&self, client: &RpcProxyClient) {
+        let torrents = client
+            .torrent_get(TorrentGet {
+                fields: vec![
+                    Cow::Borrowed("id"),
+                    Cow::Borrowed("hashString"),
+                    Cow::Borrowed("downloadDir"),
+                    Cow::Borrowed("labels"),
+                ],
+                ..Default::default()
+            })
+            .await;
+
+        match torrents {
+            Ok(torrents) => {
+                self.update(&torrents.torrents);
+                info!(torrents = torrents.torrents.len(), "seeded torrent index");
+            }
+            Err(err) => error!(%err, "could not seed torrent index"),
+        }
+    }
+}
+
+impl Entries {
+    /// Merge an entry with what is already known of the torrent
+    fn insert(&mut self, entry: IndexEntry) {
+        self.removed.remove(&entry.id);
+
+        let known = self.by_id.entry(entry.id).or_insert_with(|| IndexEntry {
+            id: entry.id,
+            ..Default::default()
+        });
+
+        if let Some(hash) = entry.info_hash {
+            let hash = hash.to_ascii_lowercase();
+            self.by_hash.insert(hash.clone(), entry.id);
+            known.info_hash = Some(hash);
+        }
+
+        if entry.download_dir.is_some() {
+            known.download_dir = entry.download_dir;
+        }
+
+        if entry.owner.is_some() {
+            known.owner = entry.owner;
+        }
+    }
+}
+
+/// Info hash of the torrent added by a torrent-add call, from its metainfo or magnet link
+pub fn added_info_hash(arguments: &TorrentAdd) -> Result<Option<String>, FilterErrorKind> {
+    if !arguments.metainfo.is_empty() {
+        let b64 = &base64::engine::general_purpose::STANDARD;
+        Ok(Some(crate::torrent::info_hash(
+            &b64.decode(&arguments.metainfo)?,
+        )?))
+    } else {
+        Ok(arguments
+            .filename
+            .as_deref()
+            .and_then(crate::torrent::magnet_info_hash))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn removed_torrents_are_no_longer_indexed() {
+        let index = TorrentIndex::new(&OwnerLabels::default());
+        index.insert(IndexEntry {
+            id: 1,
+            info_hash: Some("AA".to_owned()),
+            download_dir: Some("/data/alice".to_owned()),
+            owner: Some("alice".to_owned()),
+        });
+        index.insert(IndexEntry {
+            id: 2,
+            info_hash: Some("bb".to_owned()),
+            ..Default::default()
+        });
+        assert_eq!(index.get("aa").map(|entry| entry.id), Some(1));
+
+        index.remove([1]);
+        assert_eq!(index.get("aa"), None);
+        let ids: Vec<_> = index.entries().iter().map(|entry| entry.id).collect();
+        assert_eq!(ids, vec![2]);
+
+        // The removal is still reported to users of the download dir
+        assert_eq!(index.download_dir(1).as_deref(), Some("/data/alice"));
+        assert_eq!(index.download_dir(3), None);
+    }
+}
@@ -0,0 +1,329 @@
+//! Reports of errored and stalled torrents, sent to their owners through the notifier
+
+use std::{
+    borrow::Cow,
+    collections::{BTreeMap, HashMap, HashSet},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tracing::{debug, error};
+
+use crate::{
+    config::Config,
+    events::{Event, EventBus},
+    ownership::Attribution,
+    provisioning::ProvisionedAcls,
+    rpc::{proxy::RpcProxyClient, Torrent, TorrentGet, TorrentStatus},
+};
+
+fn default_interval() -> u64 {
+    600
+}
+
+fn default_min_interval() -> u64 {
+    3600
+}
+
+const FIELDS: &[&str] = &[
+    "id",
+    "name",
+    "hashString",
+    "downloadDir",
+    "labels",
+    "status",
+    "error",
+    "errorString",
+    "isStalled",
+];
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct TorrentReportsConfig {
+    /// Periodically tell owners about their torrents with tracker or local errors
+    #[serde(default)]
+    pub enabled: bool,
+
+    /// Also report downloading torrents which are stalled
+    #[serde(default)]
+    pub stalled: bool,
+
+    /// Time between checks of the torrents, in seconds
+    #[serde(default = "default_interval")]
+    pub interval: u64,
+
+    /// Minimum time between two reports to the same user, in seconds. Problems found meanwhile
+    /// are grouped into the next report.
+    #[serde(default = "default_min_interval")]
+    pub min_interval: u64,
+
+    /// List all the torrents which still have a problem in each report, instead of only the new
+    /// ones
+    #[serde(default)]
+    pub digest: bool,
+}
+
+impl Default for TorrentReportsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            stalled: false,
+            interval: default_interval(),
+            min_interval: default_min_interval(),
+            digest: false,
+        }
+    }
+}
+
+/// Torrent which needs the attention of its owner
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct TorrentProblem {
+    pub hash: String,
+    pub name: String,
+    /// Error reported by Transmission, or `stalled`
+    pub error: String,
+}
+
+impl TorrentProblem {
+    fn of(torrent: &Torrent, stalled: bool) -> Option<Self> {
+        let hash = torrent.hash_string.clone()?;
+
+        // Tracker warnings are usually transient
+        let error = match torrent.error {
+            Some(2 | 3) => torrent
+                .error_string
+                .clone()
+                .filter(|error| !error.is_empty())
+                .unwrap_or_else(|| "unknown error".to_owned()),
+            _ if stalled
+                && torrent.is_stalled == Some(true)
+                && torrent.status == Some(TorrentStatus::Downloading) =>
+            {
+                "stalled".to_owned()
+            }
+            _ => return None,
+        };
+
+        Some(Self {
+            hash,
+            name: torrent.name.clone(),
+            error,
+        })
+    }
+}
+
+/// Problems already reported, and the ones waiting for the next report of each user
+#[derive(Debug, Default)]
+struct Reports {
+    /// Hashes of the torrents with a known problem
+    known: HashSet<String>,
+    /// New problems not reported yet, by user
+    pending: BTreeMap<String, BTreeMap<String, TorrentProblem>>,
+    /// Unix timestamp of the last report to each user
+    last_sent: HashMap<String, u64>,
+}
+
+impl Reports {
+    /// Record the current problems by owner, returning the reports which are due
+    fn check(
+        &mut self,
+        config: &TorrentReportsConfig,
+        problems: BTreeMap<String, Vec<TorrentProblem>>,
+        now: u64,
+    ) -> Vec<(String, Vec<TorrentProblem>)> {
+        let current: HashSet<&str> = problems
+            .values()
+            .flatten()
+            .map(|problem| problem.hash.as_str())
+            .collect();
+
+        // Fixed torrents are reported again if they break again
+        self.known.retain(|hash| current.contains(hash.as_str()));
+        for pending in self.pending.values_mut() {
+            pending.retain(|hash, _| current.contains(hash.as_str()));
+        }
+
+        for (owner, owner_problems) in &problems {
+            for problem in owner_problems {
+                if self.known.insert(problem.hash.clone()) {
+                    self.pending
+                        .entry(owner.clone())
+                        .or_default()
+                        .insert(problem.hash.clone(), problem.clone());
+                }
+            }
+        }
+
+        let mut due = Vec::new();
+        for (owner, owner_problems) in problems {
+            // Digests remind users of their problems until they are fixed
+            let reportable = if config.digest {
+                !owner_problems.is_empty()
+            } else {
+                self.pending
+                    .get(&owner)
+                    .map_or(false, |pending| !pending.is_empty())
+            };
+            let allowed = self
+                .last_sent
+                .get(&owner)
+                .map_or(true, |last| now >= last + config.min_interval);
+            if !reportable || !allowed {
+                continue;
+            }
+
+            let pending = self.pending.remove(&owner).unwrap_or_default();
+            self.last_sent.insert(owner.clone(), now);
+            due.push(if config.digest {
+                (owner, owner_problems)
+            } else {
+                (owner, pending.into_values().collect())
+            });
+        }
+
+        due
+    }
+}
+
+/// Address of a user for notifications: the configured one for basic auth users, and the
+/// username of OAuth2 users, which is usually their email
+fn email_of(config: &Config, user: &str) -> Option<String> {
+    if user.contains('@') {
+        Some(user.to_owned())
+    } else {
+        config.providers.basic.email_of(user)
+    }
+}
+
+fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+/// Check the torrents periodically forever, emitting the reports of their owners
+pub async fn run(
+    client: &RpcProxyClient,
+    config: &Config,
+    provisioned: &ProvisionedAcls,
+    events: &EventBus,
+) {
+    let reports_config = &config.notifications.torrent_reports;
+    let mut interval = tokio::time::interval(Duration::from_secs(reports_config.interval.max(1)));
+    let mut reports = Reports::default();
+
+    loop {
+        interval.tick().await;
+
+        let torrents = match client
+            .torrent_get(TorrentGet {
+                fields: FIELDS.iter().map(|field| Cow::Borrowed(*field)).collect(),
+                ..Default::default()
+            })
+            .await
+        {
+            Ok(torrents) => torrents.torrents,
+            Err(err) => {
+                error!(%err, "could not check torrents for problems");
+                continue;
+            }
+        };
+
+        // Unattributed torrents have nobody to report to
+        let attribution = Attribution::new(&config.acl, &config.owner_labels, provisioned.list());
+        let mut problems: BTreeMap<String, Vec<TorrentProblem>> = BTreeMap::new();
+        for torrent in &torrents {
+            if let Some((owner, problem)) = attribution
+                .owner(torrent)
+                .zip(TorrentProblem::of(torrent, reports_config.stalled))
+            {
+                problems.entry(owner).or_default().push(problem);
+            }
+        }
+
+        for (user, torrents) in reports.check(reports_config, problems, unix_time()) {
+            debug!(user, count = torrents.len(), "reporting torrent problems");
+            events.emit(Event::TorrentProblems {
+                email: email_of(config, &user),
+                user,
+                torrents,
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn problem(hash: &str) -> TorrentProblem {
+        TorrentProblem {
+            hash: hash.to_owned(),
+            name: hash.to_uppercase(),
+            error: "No data found!".to_owned(),
+        }
+    }
+
+    fn problems(hashes: &[&str]) -> BTreeMap<String, Vec<TorrentProblem>> {
+        BTreeMap::from([(
+            "alice".to_owned(),
+            hashes.iter().map(|hash| problem(hash)).collect(),
+        )])
+    }
+
+    fn hashes(due: &[(String, Vec<TorrentProblem>)]) -> Vec<Vec<&str>> {
+        due.iter()
+            .map(|(_, problems)| problems.iter().map(|p| p.hash.as_str()).collect())
+            .collect()
+    }
+
+    #[test]
+    fn new_problems_are_reported_once_per_interval() {
+        let config = TorrentReportsConfig::default();
+        let mut reports = Reports::default();
+
+        assert_eq!(
+            hashes(&reports.check(&config, problems(&["a"]), 0)),
+            vec![vec!["a"]]
+        );
+        // Known problems aren't reported again, new ones wait for the next report
+        assert!(reports.check(&config, problems(&["a", "b"]), 60).is_empty());
+        assert_eq!(
+            hashes(&reports.check(&config, problems(&["a", "b", "c"]), 3600)),
+            vec![vec!["b", "c"]]
+        );
+
+        // Fixed torrents are reported again if they break again
+        assert!(reports.check(&config, problems(&[]), 7200).is_empty());
+        assert_eq!(
+            hashes(&reports.check(&config, problems(&["a"]), 7300)),
+            vec![vec!["a"]]
+        );
+    }
+
+    #[test]
+    fn digests_list_all_problems_until_fixed() {
+        let config = TorrentReportsConfig {
+            digest: true,
+            ..Default::default()
+        };
+        let mut reports = Reports::default();
+
+        assert_eq!(
+            hashes(&reports.check(&config, problems(&["a"]), 0)),
+            vec![vec!["a"]]
+        );
+        assert!(reports.check(&config, problems(&["a", "b"]), 60).is_empty());
+        assert_eq!(
+            hashes(&reports.check(&config, problems(&["a", "b"]), 3600)),
+            vec![vec!["a", "b"]]
+        );
+        assert_eq!(
+            hashes(&reports.check(&config, problems(&["b"]), 7200)),
+            vec![vec!["b"]]
+        );
+        assert!(reports.check(&config, problems(&[]), 10800).is_empty());
+    }
+}
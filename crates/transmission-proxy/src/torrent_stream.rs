@@ -0,0 +1,151 @@
+//! Torrent lists pushed to users as server-sent events
+//!
+//! The list is polled on behalf of the user, through the filters of their ACL. Clients get a
+//! full snapshot first, then deltas holding the torrents which were added or removed, and only
+//! the fields which changed for the others. Snapshots are sent again periodically, so clients
+//! can recover from missed events.
+
+use std::collections::BTreeMap;
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+fn default_interval() -> u64 {
+    2
+}
+
+fn default_snapshot_every() -> u32 {
+    30
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct TorrentStreamConfig {
+    /// Seconds between two polls of the torrent list
+    #[serde(default = "default_interval")]
+    pub interval: u64,
+
+    /// Number of polls between two full snapshots
+    #[serde(default = "default_snapshot_every")]
+    pub snapshot_every: u32,
+}
+
+impl Default for TorrentStreamConfig {
+    fn default() -> Self {
+        Self {
+            interval: default_interval(),
+            snapshot_every: default_snapshot_every(),
+        }
+    }
+}
+
+/// Changes between two polls of the torrent list
+#[derive(Debug, Default, PartialEq, Serialize)]
+pub struct Delta {
+    /// New torrents, with all their fields
+    pub added: Vec<Value>,
+    /// Ids of the torrents which are gone
+    pub removed: Vec<i64>,
+    /// Torrents which changed, with their id and the fields which changed
+    pub changed: Vec<Value>,
+}
+
+impl Delta {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+/// Torrents of the last poll, by id. Torrents without an id are ignored.
+#[derive(Debug, Default)]
+pub struct Snapshot {
+    torrents: BTreeMap<i64, Map<String, Value>>,
+}
+
+impl Snapshot {
+    pub fn new(torrents: Vec<Value>) -> Self {
+        Self {
+            torrents: by_id(torrents),
+        }
+    }
+
+    pub fn torrents(&self) -> Vec<Value> {
+        self.torrents.values().cloned().map(Value::Object).collect()
+    }
+
+    /// Replace the torrents of the snapshot, returning what changed
+    pub fn update(&mut self, torrents: Vec<Value>) -> Delta {
+        let torrents = by_id(torrents);
+        let mut delta = Delta::default();
+
+        for (id, torrent) in &torrents {
+            let Some(previous) = self.torrents.get(id) else {
+                delta.added.push(Value::Object(torrent.clone()));
+                continue;
+            };
+
+            let mut changed: Map<String, Value> = torrent
+                .iter()
+                .filter(|(field, value)| previous.get(*field) != Some(value))
+                .map(|(field, value)| (field.clone(), value.clone()))
+                .collect();
+            if !changed.is_empty() {
+                changed.insert("id".to_owned(), (*id).into());
+                delta.changed.push(Value::Object(changed));
+            }
+        }
+
+        delta.removed = self
+            .torrents
+            .keys()
+            .filter(|id| !torrents.contains_key(id))
+            .copied()
+            .collect();
+
+        self.torrents = torrents;
+        delta
+    }
+}
+
+fn by_id(torrents: Vec<Value>) -> BTreeMap<i64, Map<String, Value>> {
+    torrents
+        .into_iter()
+        .filter_map(|torrent| match torrent {
+            Value::Object(torrent) => Some((torrent.get("id")?.as_i64()?, torrent)),
+            _ => None,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn deltas_only_hold_changes() {
+        let mut snapshot = Snapshot::new(vec![
+            json!({ "id": 1, "name": "Kept", "percentDone": 0.5 }),
+            json!({ "id": 2, "name": "Removed", "percentDone": 1.0 }),
+            json!({ "id": 3, "name": "Unchanged", "percentDone": 1.0 }),
+        ]);
+
+        let delta = snapshot.update(vec![
+            json!({ "id": 1, "name": "Kept", "percentDone": 0.75 }),
+            json!({ "id": 3, "name": "Unchanged", "percentDone": 1.0 }),
+            json!({ "id": 4, "name": "Added", "percentDone": 0.0 }),
+        ]);
+        assert_eq!(
+            delta,
+            Delta {
+                added: vec![json!({ "id": 4, "name": "Added", "percentDone": 0.0 })],
+                removed: vec![2],
+                changed: vec![json!({ "id": 1, "percentDone": 0.75 })],
+            }
+        );
+
+        assert!(snapshot.update(snapshot.torrents()).is_empty());
+    }
+}
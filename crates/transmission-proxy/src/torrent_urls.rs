@@ -0,0 +1,310 @@
+//! Torrent files added by URL, fetched by the proxy so their metainfo goes through the filters
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "client")]
+use {
+    crate::{
+        http_client::{host_matches, HttpClientConfig},
+        rpc::proxy::{Denial, FilterErrorKind},
+    },
+    std::{
+        net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+        time::Duration,
+    },
+    tracing::debug,
+    url::{Host, Url},
+};
+
+fn default_max_size() -> usize {
+    10 * 1024 * 1024
+}
+
+fn default_timeout() -> u64 {
+    30
+}
+
+fn default_max_redirects() -> usize {
+    5
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct TorrentUrlsConfig {
+    /// Fetch the torrent files added by URL in the proxy, instead of letting the daemon fetch them
+    #[serde(default)]
+    pub fetch: bool,
+
+    /// Hosts torrent files may be fetched from, any host if empty. A leading dot matches all
+    /// subdomains.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub allowed_hosts: Vec<String>,
+
+    /// Hosts torrent files are never fetched from
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub denied_hosts: Vec<String>,
+
+    /// DANGEROUS: fetch torrent files from loopback, private and other non-public addresses,
+    /// which lets users reach the services of the proxy network
+    #[serde(default)]
+    pub allow_private_addresses: bool,
+
+    /// Largest torrent file, in bytes
+    #[serde(default = "default_max_size")]
+    pub max_size: usize,
+
+    /// Time allowed to fetch a torrent file, in seconds
+    #[serde(default = "default_timeout")]
+    pub timeout: u64,
+
+    /// Redirects followed when fetching a torrent file
+    #[serde(default = "default_max_redirects")]
+    pub max_redirects: usize,
+}
+
+impl Default for TorrentUrlsConfig {
+    fn default() -> Self {
+        Self {
+            fetch: false,
+            allowed_hosts: Vec::new(),
+            denied_hosts: Vec::new(),
+            allow_private_addresses: false,
+            max_size: default_max_size(),
+            timeout: default_timeout(),
+            max_redirects: default_max_redirects(),
+        }
+    }
+}
+
+/// Returns true if the address is reachable from the internet
+#[cfg(feature = "client")]
+fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, c, _] = ip.octets();
+
+            !(ip.is_private()
+                || ip.is_loopback()
+                || ip.is_link_local()
+                || ip.is_broadcast()
+                || ip.is_documentation()
+                || ip.is_unspecified()
+                || ip.is_multicast()
+                // This network, shared address space, IETF protocol assignments, benchmarking
+                // and reserved ranges
+                || a == 0
+                || (a == 100 && (64..128).contains(&b))
+                || (a == 192 && b == 0 && c == 0)
+                || (a == 198 && (18..20).contains(&b))
+                || a >= 240)
+        }
+        IpAddr::V6(ip) => {
+            if let Some(ip) = embedded_ipv4(ip) {
+                return is_public(IpAddr::V4(ip));
+            }
+
+            let [first, second, third, ..] = ip.segments();
+            !(ip.is_loopback()
+                || ip.is_unspecified()
+                || ip.is_multicast()
+                // Unique local, link local, documentation and local-use NAT64 ranges
+                || (first & 0xfe00) == 0xfc00
+                || (first & 0xffc0) == 0xfe80
+                || (first == 0x2001 && second == 0x0db8)
+                || (first == 0x64 && second == 0xff9b && third == 1))
+        }
+    }
+}
+
+/// IPv4 address an IPv6 address leads to: IPv4-mapped and IPv4-compatible addresses, NAT64
+/// addresses in 64:ff9b::/96 and 6to4 addresses in 2002::/16
+#[cfg(feature = "client")]
+fn embedded_ipv4(ip: Ipv6Addr) -> Option<Ipv4Addr> {
+    let segments = ip.segments();
+    let ipv4 = |high: u16, low: u16| Ipv4Addr::from((u32::from(high) << 16) | u32::from(low));
+
+    match segments {
+        // The loopback and unspecified addresses are not IPv4-compatible
+        _ if ip.is_loopback() || ip.is_unspecified() => None,
+        [0, 0, 0, 0, 0, 0xffff, high, low] | [0, 0, 0, 0, 0, 0, high, low] => Some(ipv4(high, low)),
+        [0x64, 0xff9b, 0, 0, 0, 0, high, low] => Some(ipv4(high, low)),
+        [0x2002, high, low, ..] => Some(ipv4(high, low)),
+        _ => None,
+    }
+}
+
+/// Fetches torrent files for torrent-add calls, refusing URLs which could reach internal services
+#[cfg(feature = "client")]
+pub struct TorrentUrlFetcher {
+    config: TorrentUrlsConfig,
+    http_client: HttpClientConfig,
+}
+
+#[cfg(feature = "client")]
+impl TorrentUrlFetcher {
+    pub fn new(config: &TorrentUrlsConfig, http_client: &HttpClientConfig) -> Option<Self> {
+        config.fetch.then(|| Self {
+            config: config.clone(),
+            http_client: http_client.clone(),
+        })
+    }
+
+    /// Returns true if the torrent-add filename is a URL the proxy fetches
+    pub fn handles(filename: &str) -> bool {
+        let filename = filename.trim_start().to_ascii_lowercase();
+        filename.starts_with("http://") || filename.starts_with("https://")
+    }
+
+    fn denied(url: &Url) -> FilterErrorKind {
+        FilterErrorKind::Forbidden(Some(Denial::TorrentUrl(url.to_string())))
+    }
+
+    /// Address to connect to for a URL, checking its host against the allowed and denied hosts
+    /// and addresses. It is pinned for the request, so DNS can't be changed to point elsewhere
+    /// in between.
+    async fn resolve(&self, url: &Url) -> Result<Option<SocketAddr>, FilterErrorKind> {
+        let allowed_ip = |ip| self.config.allow_private_addresses || is_public(ip);
+
+        let host = url.host_str().unwrap_or_default();
+        let host_ok = !self
+            .config
+            .denied_hosts
+            .iter()
+            .any(|entry| host_matches(entry, host))
+            && (self.config.allowed_hosts.is_empty()
+                || self
+                    .config
+                    .allowed_hosts
+                    .iter()
+                    .any(|entry| host_matches(entry, host)));
+
+        if !matches!(url.scheme(), "http" | "https") || !host_ok {
+            return Err(Self::denied(url));
+        }
+
+        match url.host() {
+            Some(Host::Ipv4(ip)) if allowed_ip(ip.into()) => Ok(None),
+            Some(Host::Ipv6(ip)) if allowed_ip(ip.into()) => Ok(None),
+            Some(Host::Domain(domain)) => {
+                let port = url.port_or_known_default().unwrap_or(80);
+                tokio::net::lookup_host((domain, port))
+                    .await
+                    .map_err(|err| FilterErrorKind::Fetch(err.to_string()))?
+                    .find(|addr| allowed_ip(addr.ip()))
+                    .map(Some)
+                    .ok_or_else(|| Self::denied(url))
+            }
+            _ => Err(Self::denied(url)),
+        }
+    }
+
+    /// Fetch the torrent file at a URL, following redirects to allowed URLs
+    pub async fn fetch(&self, url: &str) -> Result<Vec<u8>, FilterErrorKind> {
+        let fetch_error = |err: reqwest::Error| FilterErrorKind::Fetch(err.to_string());
+
+        let mut url =
+            Url::parse(url.trim()).map_err(|err| FilterErrorKind::Fetch(err.to_string()))?;
+
+        for _ in 0..=self.config.max_redirects {
+            let addr = self.resolve(&url).await?;
+
+            // Connections go straight to the checked address, as a proxy would resolve the host
+            // again and could reach the addresses refused above
+            let mut builder = self
+                .http_client
+                .builder()
+                .map_err(|err| FilterErrorKind::Fetch(err.to_string()))?
+                .no_proxy()
+                .timeout(Duration::from_secs(self.config.timeout));
+            if let (Some(addr), Some(host)) = (addr, url.host_str()) {
+                builder = builder.resolve(host, addr);
+            }
+
+            let mut response = builder
+                .build()
+                .map_err(fetch_error)?
+                .get(url.clone())
+                .send()
+                .await
+                .map_err(fetch_error)?;
+
+            if response.status().is_redirection() {
+                let location = response
+                    .headers()
+                    .get(reqwest::header::LOCATION)
+                    .and_then(|location| location.to_str().ok())
+                    .and_then(|location| url.join(location).ok())
+                    .ok_or_else(|| FilterErrorKind::Fetch("invalid redirect".to_owned()))?;
+
+                debug!(from = %url, to = %location, "following torrent url redirect");
+                url = location;
+                continue;
+            }
+
+            response = response.error_for_status().map_err(fetch_error)?;
+
+            let too_large = || FilterErrorKind::Fetch("torrent file is too large".to_owned());
+            if response
+                .content_length()
+                .map_or(false, |length| length > self.config.max_size as u64)
+            {
+                return Err(too_large());
+            }
+
+            let mut body = Vec::new();
+            while let Some(chunk) = response.chunk().await.map_err(fetch_error)? {
+                if body.len() + chunk.len() > self.config.max_size {
+                    return Err(too_large());
+                }
+
+                body.extend_from_slice(&chunk);
+            }
+
+            return Ok(body);
+        }
+
+        Err(FilterErrorKind::Fetch("too many redirects".to_owned()))
+    }
+}
+
+#[cfg(all(test, feature = "client"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn internal_addresses_are_not_public() {
+        for ip in [
+            "127.0.0.1",
+            "10.1.2.3",
+            "172.16.0.1",
+            "192.168.1.1",
+            "169.254.169.254",
+            "100.64.0.1",
+            "0.0.0.0",
+            "::1",
+            "fd00::1",
+            "fe80::1",
+            "::ffff:127.0.0.1",
+            "::127.0.0.1",
+            "::10.0.0.1",
+            "64:ff9b::a9fe:a9fe",
+            "64:ff9b::127.0.0.1",
+            "64:ff9b:1::1.1.1.1",
+            "2002:7f00:1::",
+            "2002:c0a8:101::1",
+        ] {
+            assert!(!is_public(ip.parse().unwrap()), "{ip}");
+        }
+
+        for ip in [
+            "1.1.1.1",
+            "2606:4700:4700::1111",
+            "::ffff:1.1.1.1",
+            "64:ff9b::1.1.1.1",
+            "2002:101:101::1",
+        ] {
+            assert!(is_public(ip.parse().unwrap()), "{ip}");
+        }
+    }
+}
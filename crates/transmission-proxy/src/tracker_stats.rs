@@ -0,0 +1,195 @@
+use std::{
+    borrow::Cow,
+    collections::BTreeMap,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tokio::sync::RwLock;
+use tracing::{debug, error};
+
+use crate::{
+    rpc::{
+        proxy::{FilterErrorKind, RpcProxyClient},
+        Torrent, TorrentGet,
+    },
+    storage::Storage,
+};
+
+/// Namespace and key of the latest report in storage
+const STORAGE_KEY: (&str, &str) = ("tracker-stats", "report");
+
+fn default_interval() -> u64 {
+    300
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct TrackerStatsConfig {
+    /// Periodically collect tracker statistics
+    #[serde(default)]
+    pub enabled: bool,
+
+    /// Collection interval, in seconds
+    #[serde(default = "default_interval")]
+    pub interval: u64,
+}
+
+impl Default for TrackerStatsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            interval: default_interval(),
+        }
+    }
+}
+
+/// Statistics for a single tracker host
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct TrackerHostStats {
+    /// Number of torrents using this tracker
+    pub torrents: usize,
+    /// Number of torrents whose last announce failed
+    pub announce_failures: usize,
+    /// Number of torrents whose last scrape failed
+    pub scrape_failures: usize,
+    /// Number of torrents for each last scrape result
+    pub last_scrape_results: BTreeMap<String, usize>,
+}
+
+/// Aggregated tracker health across all torrents
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct TrackerReport {
+    /// Unix timestamp of the collection
+    pub updated_at: u64,
+    /// Total number of torrents
+    pub torrents: usize,
+    /// Number of torrents without any working tracker
+    pub torrents_with_errors: usize,
+    /// Statistics by tracker host
+    pub trackers: BTreeMap<String, TrackerHostStats>,
+}
+
+impl TrackerReport {
+    pub fn from_torrents(torrents: &[Torrent]) -> Self {
+        let mut report = Self {
+            updated_at: unix_time(),
+            torrents: torrents.len(),
+            ..Default::default()
+        };
+
+        for torrent in torrents {
+            let tracker_stats = torrent.tracker_stats.as_deref().unwrap_or_default();
+
+            if !tracker_stats
+                .iter()
+                .any(|stat| stat.last_scrape_succeeded || stat.last_announce_succeeded)
+            {
+                report.torrents_with_errors += 1;
+            }
+
+            for stat in tracker_stats {
+                let host = report.trackers.entry(stat.host.clone()).or_default();
+
+                host.torrents += 1;
+
+                if stat.has_announced && !stat.last_announce_succeeded {
+                    host.announce_failures += 1;
+                }
+
+                if stat.has_scraped {
+                    if !stat.last_scrape_succeeded {
+                        host.scrape_failures += 1;
+                    }
+
+                    *host
+                        .last_scrape_results
+                        .entry(stat.last_scrape_result.clone())
+                        .or_default() += 1;
+                }
+            }
+        }
+
+        report
+    }
+}
+
+/// Holds the latest tracker report, which is persisted across restarts
+#[derive(Default)]
+pub struct TrackerStatsCollector {
+    report: RwLock<Option<TrackerReport>>,
+}
+
+impl TrackerStatsCollector {
+    pub async fn report(&self) -> Option<TrackerReport> {
+        self.report.read().await.clone()
+    }
+
+    async fn collect(
+        &self,
+        client: &RpcProxyClient,
+        storage: &dyn Storage,
+    ) -> Result<(), FilterErrorKind> {
+        let torrents = client
+            .torrent_get(TorrentGet {
+                fields: vec![Cow::Borrowed("id"), Cow::Borrowed("trackerStats")],
+                ..Default::default()
+            })
+            .await?;
+
+        let report = TrackerReport::from_torrents(&torrents.torrents);
+
+        debug!(
+            torrents = report.torrents,
+            trackers = report.trackers.len(),
+            "collected tracker stats"
+        );
+        if let Err(err) = storage.put_json(STORAGE_KEY.0, STORAGE_KEY.1, &report) {
+            error!(%err, "could not store tracker stats");
+        }
+        *self.report.write().await = Some(report);
+
+        Ok(())
+    }
+
+    /// Collect tracker statistics forever
+    pub async fn run(
+        &self,
+        client: &RpcProxyClient,
+        config: &TrackerStatsConfig,
+        storage: &dyn Storage,
+    ) {
+        let period = Duration::from_secs(config.interval.max(1));
+
+        // A report stored before a restart is kept until it is due
+        let stored: Option<TrackerReport> = storage
+            .get_json(STORAGE_KEY.0, STORAGE_KEY.1)
+            .unwrap_or_else(|err| {
+                error!(%err, "could not load tracker stats");
+                None
+            });
+        let age = stored.as_ref().map_or(period, |report| {
+            Duration::from_secs(unix_time().saturating_sub(report.updated_at))
+        });
+        *self.report.write().await = stored;
+
+        let start = tokio::time::Instant::now() + period.saturating_sub(age);
+        let mut interval = tokio::time::interval_at(start, period);
+
+        loop {
+            interval.tick().await;
+
+            if let Err(err) = self.collect(client, storage).await {
+                error!(%err, "could not collect tracker stats");
+            }
+        }
+    }
+}
+
+fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
@@ -0,0 +1,432 @@
+use std::{
+    collections::HashMap,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use base64::Engine;
+use hyper::{body::HttpBody, Body, StatusCode};
+use rand::{distributions::Alphanumeric, Rng};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tracing::{debug, warn};
+
+fn default_max_size() -> u64 {
+    256 * 1024 * 1024
+}
+
+fn default_max_per_user() -> usize {
+    4
+}
+
+fn default_ttl() -> u64 {
+    3600
+}
+
+/// Size of the reads when encoding an upload, a multiple of 3 so the base64 chunks concatenate
+const ENCODE_CHUNK: usize = 3 * 64 * 1024;
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct UploadsConfig {
+    /// Enable the resumable upload endpoints
+    #[serde(default)]
+    pub enabled: bool,
+
+    /// Directory where uploads are stored until they are added, the temporary directory if unset
+    #[serde(default)]
+    pub dir: Option<PathBuf>,
+
+    /// Maximum size of an uploaded torrent file, in bytes
+    #[serde(default = "default_max_size")]
+    pub max_size: u64,
+
+    /// Maximum number of unfinished uploads of a user
+    #[serde(default = "default_max_per_user")]
+    pub max_per_user: usize,
+
+    /// Maximum total size of the unfinished uploads of a user, in bytes
+    #[serde(default = "default_max_size")]
+    pub max_user_size: u64,
+
+    /// Seconds after which an upload without activity is removed, never if 0
+    #[serde(default = "default_ttl")]
+    pub ttl: u64,
+}
+
+impl Default for UploadsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            dir: None,
+            max_size: default_max_size(),
+            max_per_user: default_max_per_user(),
+            max_user_size: default_max_size(),
+            ttl: default_ttl(),
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum UploadError {
+    #[error("upload not found")]
+    NotFound,
+    #[error("upload is being written to")]
+    Busy,
+    #[error("upload is at offset {0}")]
+    OffsetMismatch(u64),
+    #[error("upload is larger than {0} bytes")]
+    TooLarge(u64),
+    #[error("too many unfinished uploads, at most {0} are allowed")]
+    TooMany(usize),
+    #[error("upload is incomplete")]
+    Incomplete,
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error(transparent)]
+    Body(#[from] hyper::Error),
+}
+
+impl UploadError {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            Self::NotFound => StatusCode::NOT_FOUND,
+            Self::Busy | Self::OffsetMismatch(_) | Self::Incomplete => StatusCode::CONFLICT,
+            Self::TooLarge(_) => StatusCode::PAYLOAD_TOO_LARGE,
+            Self::TooMany(_) => StatusCode::TOO_MANY_REQUESTS,
+            Self::Io(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            Self::Body(_) => StatusCode::BAD_REQUEST,
+        }
+    }
+}
+
+/// Progress of an upload
+#[derive(Debug, Clone, Serialize)]
+pub struct UploadStatus {
+    pub id: String,
+    /// Number of bytes received so far, where the next chunk starts
+    pub offset: u64,
+    /// Expected size of the upload, if known
+    pub length: Option<u64>,
+    /// Fraction of the upload received so far, if its size is known
+    pub progress: Option<f64>,
+    /// Seconds after which the upload is removed without further activity, if it expires
+    pub expires_in: Option<u64>,
+}
+
+#[derive(Debug)]
+struct Upload {
+    path: PathBuf,
+    status: UploadStatus,
+}
+
+#[derive(Debug)]
+struct Entry {
+    owner: String,
+    /// Bytes counted against the quota of the owner: the expected size, or else what was received
+    size: u64,
+    last_used: Instant,
+    upload: Arc<tokio::sync::Mutex<Upload>>,
+}
+
+/// Torrent file of a finished upload, removed when dropped
+#[derive(Debug)]
+pub struct UploadedFile {
+    path: PathBuf,
+}
+
+impl UploadedFile {
+    /// Contents of the file as base64, read in chunks so the raw file is never held in memory
+    pub async fn to_base64(&self) -> Result<String, UploadError> {
+        let b64 = &base64::engine::general_purpose::STANDARD;
+        let mut file = tokio::fs::File::open(&self.path).await?;
+        let len = file.metadata().await?.len() as usize;
+
+        let mut encoded = String::with_capacity((len + 2) / 3 * 4);
+        let mut buf = vec![0; ENCODE_CHUNK];
+        loop {
+            // Fill the buffer, so only the last chunk may need padding
+            let mut filled = 0;
+            while filled < buf.len() {
+                match file.read(&mut buf[filled..]).await? {
+                    0 => break,
+                    read => filled += read,
+                }
+            }
+
+            if filled == 0 {
+                break;
+            }
+
+            b64.encode_string(&buf[..filled], &mut encoded);
+            if filled < buf.len() {
+                break;
+            }
+        }
+
+        Ok(encoded)
+    }
+}
+
+impl Drop for UploadedFile {
+    fn drop(&mut self) {
+        if let Err(err) = std::fs::remove_file(&self.path) {
+            warn!(%err, path = %self.path.display(), "could not remove upload");
+        }
+    }
+}
+
+/// Torrent files uploaded in chunks, stored on disk until they are added
+#[derive(Debug)]
+pub struct Uploads {
+    dir: PathBuf,
+    max_size: u64,
+    max_per_user: usize,
+    max_user_size: u64,
+    ttl: Duration,
+    uploads: Mutex<HashMap<String, Entry>>,
+}
+
+impl Uploads {
+    pub fn new(config: &UploadsConfig) -> Self {
+        Self {
+            dir: config.dir.clone().unwrap_or_else(std::env::temp_dir),
+            max_size: config.max_size,
+            max_per_user: config.max_per_user,
+            max_user_size: config.max_user_size,
+            ttl: Duration::from_secs(config.ttl),
+            uploads: Default::default(),
+        }
+    }
+
+    fn get(&self, id: &str, owner: &str) -> Result<Arc<tokio::sync::Mutex<Upload>>, UploadError> {
+        // Other users' uploads are reported as missing
+        self.uploads
+            .lock()
+            .unwrap()
+            .get_mut(id)
+            .filter(|entry| entry.owner == owner)
+            .map(|entry| {
+                entry.last_used = Instant::now();
+                entry.upload.clone()
+            })
+            .ok_or(UploadError::NotFound)
+    }
+
+    /// Bytes counted against the quota of a user, besides the given upload
+    fn used_by(&self, owner: &str, except: Option<&str>) -> (usize, u64) {
+        self.uploads
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(id, entry)| entry.owner == owner && Some(id.as_str()) != except)
+            .fold((0, 0), |(count, size), (_, entry)| {
+                (count + 1, size + entry.size)
+            })
+    }
+
+    /// Status of an upload, with its progress
+    fn status_of(&self, upload: &Upload) -> UploadStatus {
+        UploadStatus {
+            progress: upload.status.length.map(|length| match length {
+                0 => 1.,
+                length => upload.status.offset as f64 / length as f64,
+            }),
+            expires_in: Some(self.ttl.as_secs()).filter(|ttl| *ttl > 0),
+            ..upload.status.clone()
+        }
+    }
+
+    /// Start a new upload of the given length, if known
+    pub async fn create(
+        &self,
+        owner: &str,
+        length: Option<u64>,
+    ) -> Result<UploadStatus, UploadError> {
+        if let Some(length) = length.filter(|length| *length > self.max_size) {
+            debug!(length, "upload is too large");
+            return Err(UploadError::TooLarge(self.max_size));
+        }
+
+        let (count, size) = self.used_by(owner, None);
+        if count >= self.max_per_user {
+            debug!(owner, count, "user has too many uploads");
+            return Err(UploadError::TooMany(self.max_per_user));
+        }
+        if size + length.unwrap_or_default() > self.max_user_size {
+            debug!(owner, size, "user uploads are too large");
+            return Err(UploadError::TooLarge(self.max_user_size));
+        }
+
+        let id: String = rand::thread_rng()
+            .sample_iter(&Alphanumeric)
+            .take(24)
+            .map(char::from)
+            .collect();
+        let path = self
+            .dir
+            .join(format!("transmission-proxy-upload-{id}.torrent"));
+        tokio::fs::File::create(&path).await?;
+
+        let upload = Upload {
+            path,
+            status: UploadStatus {
+                id: id.clone(),
+                offset: 0,
+                length,
+                progress: None,
+                expires_in: None,
+            },
+        };
+        let status = self.status_of(&upload);
+
+        self.uploads.lock().unwrap().insert(
+            id,
+            Entry {
+                owner: owner.to_owned(),
+                size: length.unwrap_or_default(),
+                last_used: Instant::now(),
+                upload: Arc::new(tokio::sync::Mutex::new(upload)),
+            },
+        );
+
+        Ok(status)
+    }
+
+    pub async fn status(&self, id: &str, owner: &str) -> Result<UploadStatus, UploadError> {
+        let upload = self.get(id, owner)?;
+        let upload = upload.lock().await;
+        Ok(self.status_of(&upload))
+    }
+
+    /// Append a chunk starting at the given offset, streaming it to disk
+    pub async fn append(
+        &self,
+        id: &str,
+        owner: &str,
+        offset: u64,
+        mut body: Body,
+    ) -> Result<UploadStatus, UploadError> {
+        let upload = self.get(id, owner)?;
+        let mut upload = upload.try_lock().map_err(|_| UploadError::Busy)?;
+
+        if offset != upload.status.offset {
+            return Err(UploadError::OffsetMismatch(upload.status.offset));
+        }
+
+        // Uploads of unknown size count against the quota as they are received
+        let (_, others) = self.used_by(owner, Some(id));
+        let limit = upload
+            .status
+            .length
+            .unwrap_or(self.max_size)
+            .min(self.max_user_size.saturating_sub(others));
+        let mut file = tokio::fs::OpenOptions::new()
+            .append(true)
+            .open(&upload.path)
+            .await?;
+
+        let result = async {
+            while let Some(chunk) = body.data().await {
+                let chunk = chunk?;
+                if upload.status.offset + chunk.len() as u64 > limit {
+                    return Err(UploadError::TooLarge(limit));
+                }
+
+                file.write_all(&chunk).await?;
+                upload.status.offset += chunk.len() as u64;
+            }
+
+            Ok(())
+        }
+        .await;
+
+        // Keep what was received so far, so the client can resume
+        file.flush().await?;
+        if upload.status.length.is_none() {
+            if let Some(entry) = self.uploads.lock().unwrap().get_mut(id) {
+                entry.size = upload.status.offset;
+            }
+        }
+
+        result.map(|()| self.status_of(&upload))
+    }
+
+    /// Finish an upload, returning its file
+    pub async fn take(&self, id: &str, owner: &str) -> Result<UploadedFile, UploadError> {
+        let upload = self.get(id, owner)?;
+        let upload = upload.try_lock().map_err(|_| UploadError::Busy)?;
+
+        if upload
+            .status
+            .length
+            .map_or(false, |length| length != upload.status.offset)
+        {
+            return Err(UploadError::Incomplete);
+        }
+
+        self.uploads.lock().unwrap().remove(id);
+        Ok(UploadedFile {
+            path: upload.path.clone(),
+        })
+    }
+
+    /// Cancel an upload
+    pub async fn remove(&self, id: &str, owner: &str) -> Result<(), UploadError> {
+        let upload = self.get(id, owner)?;
+        let upload = upload.try_lock().map_err(|_| UploadError::Busy)?;
+
+        self.uploads.lock().unwrap().remove(id);
+        remove_file(&upload).await;
+
+        Ok(())
+    }
+
+    /// Remove the uploads without activity for longer than the configured time
+    pub async fn sweep(&self) {
+        let expired: Vec<_> = {
+            let mut uploads = self.uploads.lock().unwrap();
+            let expired: Vec<_> = uploads
+                .iter()
+                // Uploads being written to are still in use
+                .filter(|(_, entry)| {
+                    entry.last_used.elapsed() >= self.ttl && entry.upload.try_lock().is_ok()
+                })
+                .map(|(id, _)| id.clone())
+                .collect();
+
+            expired
+                .into_iter()
+                .filter_map(|id| uploads.remove(&id).map(|entry| (id, entry)))
+                .collect()
+        };
+
+        for (id, entry) in expired {
+            debug!(%id, owner = %entry.owner, "removing abandoned upload");
+            let upload = entry.upload.lock().await;
+            remove_file(&upload).await;
+        }
+    }
+
+    /// Periodically remove abandoned uploads
+    pub async fn run(&self) {
+        let period = self
+            .ttl
+            .clamp(Duration::from_secs(1), Duration::from_secs(60));
+        let mut interval = tokio::time::interval(period);
+
+        loop {
+            interval.tick().await;
+            self.sweep().await;
+        }
+    }
+}
+
+async fn remove_file(upload: &Upload) {
+    if let Err(err) = tokio::fs::remove_file(&upload.path).await {
+        warn!(%err, path = %upload.path.display(), "could not remove upload");
+    }
+}
@@ -0,0 +1,155 @@
+//! Credentials sent to upstream daemons which keep their own RPC authentication enabled
+
+use base64::Engine;
+use color_eyre::eyre;
+use hyper::{header::AUTHORIZATION, http::HeaderValue, Body, Request};
+use schemars::JsonSchema;
+use secrecy::ExposeSecret;
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use crate::{acl::AclIdentity, auth::AuthUser, secret_file::SecretFile};
+
+fn basic(username: &str, password: &str) -> Option<HeaderValue> {
+    let credentials =
+        base64::engine::general_purpose::STANDARD.encode(format!("{username}:{password}"));
+    let mut value = HeaderValue::try_from(format!("Basic {credentials}")).ok()?;
+    value.set_sensitive(true);
+    Some(value)
+}
+
+/// The rpc-username and rpc-password of the upstream daemon
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct UpstreamCredentials {
+    pub username: String,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub password: Option<String>,
+
+    /// File holding the password, read again when it changes
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub password_file: Option<SecretFile>,
+}
+
+impl UpstreamCredentials {
+    /// Check that exactly one of the password and its file is set
+    fn validate(&self) -> eyre::Result<()> {
+        match (&self.password, &self.password_file) {
+            (Some(_), Some(_)) => eyre::bail!(
+                "password and password_file are both set for upstream user {}",
+                self.username
+            ),
+            (None, None) => eyre::bail!(
+                "password or password_file is required for upstream user {}",
+                self.username
+            ),
+            (None, Some(file)) => file.get().map(|_| ()),
+            (Some(_), None) => Ok(()),
+        }
+    }
+
+    fn header(&self) -> Option<HeaderValue> {
+        match (&self.password, &self.password_file) {
+            (Some(password), _) => basic(&self.username, password),
+            (None, Some(file)) => match file.get() {
+                Ok(password) => basic(&self.username, &password),
+                Err(err) => {
+                    warn!(%err, username = %self.username, "could not read upstream password");
+                    None
+                }
+            },
+            (None, None) => None,
+        }
+    }
+}
+
+/// Upstream credentials of a proxy user
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct MappedCredentials {
+    pub identity: AclIdentity,
+    pub credentials: UpstreamCredentials,
+}
+
+/// Authentication of the proxy to the upstream daemon
+#[derive(Debug, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct UpstreamAuth {
+    /// Forward the basic auth credentials of clients as-is, when their identity isn't mapped
+    #[serde(default)]
+    pub passthrough: bool,
+
+    /// Credentials of the calls made by the proxy itself, and of the users without credentials
+    /// of their own
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub credentials: Option<UpstreamCredentials>,
+
+    /// Credentials of specific users, the first matching identity applying
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub users: Vec<MappedCredentials>,
+}
+
+impl UpstreamAuth {
+    pub fn validate(&self) -> eyre::Result<()> {
+        self.credentials
+            .iter()
+            .chain(self.users.iter().map(|user| &user.credentials))
+            .try_for_each(UpstreamCredentials::validate)
+    }
+
+    /// Returns true if the proxy authenticates to the upstream, instead of forwarding the
+    /// Authorization header of requests untouched
+    pub fn enabled(&self) -> bool {
+        self.passthrough || self.credentials.is_some() || !self.users.is_empty()
+    }
+
+    /// Authorization header for the requests of the given user, or of the proxy itself
+    fn header(&self, user: Option<&AuthUser>) -> Option<HeaderValue> {
+        if let Some(user) = user {
+            if let Some(mapped) = self
+                .users
+                .iter()
+                .find(|mapped| mapped.identity.matches(user))
+            {
+                return mapped.credentials.header();
+            }
+
+            if self.passthrough {
+                let basic_user = match user {
+                    AuthUser::Linked { identity, .. } => identity,
+                    user => user,
+                };
+
+                if let AuthUser::Basic {
+                    username,
+                    password: Some(password),
+                } = basic_user
+                {
+                    return basic(username, password.expose_secret());
+                }
+            }
+        }
+
+        self.credentials
+            .as_ref()
+            .and_then(UpstreamCredentials::header)
+    }
+
+    /// Replace the Authorization header of a request to be forwarded upstream
+    pub fn apply(&self, req: &mut Request<Body>, user: Option<&AuthUser>) {
+        if !self.enabled() {
+            return;
+        }
+
+        match self.header(user) {
+            Some(value) => {
+                req.headers_mut().insert(AUTHORIZATION, value);
+            }
+            None => {
+                // The credentials of the proxy are not meant for the upstream
+                req.headers_mut().remove(AUTHORIZATION);
+            }
+        }
+    }
+}
@@ -0,0 +1,121 @@
+use std::{borrow::Cow, ops::RangeInclusive, time::Duration};
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use crate::{
+    error::Error,
+    rpc::{proxy::RpcProxyClient, SessionGet},
+};
+
+/// RPC versions whose messages are understood by the proxy
+pub const SUPPORTED_RPC_VERSIONS: RangeInclusive<i32> = 15..=17;
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_interval() -> u64 {
+    3600
+}
+
+/// Checks of the upstream daemon RPC version
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct VersionCheck {
+    /// Check the upstream RPC version on startup and periodically
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+
+    /// Refuse to start if the upstream RPC version is not supported
+    #[serde(default)]
+    pub strict: bool,
+
+    /// Interval between checks after startup, in seconds. 0 disables periodic checks.
+    #[serde(default = "default_interval")]
+    pub interval: u64,
+}
+
+impl Default for VersionCheck {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            strict: false,
+            interval: default_interval(),
+        }
+    }
+}
+
+/// Version fields of the session-get response
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct UpstreamVersion {
+    version: Option<String>,
+    rpc_version: i32,
+    rpc_version_minimum: i32,
+}
+
+impl VersionCheck {
+    /// Check the upstream RPC version. Returns an error only for unsupported versions in strict
+    /// mode, as the upstream may not be reachable yet.
+    pub async fn check(&self, client: &RpcProxyClient, strict: bool) -> Result<(), Error> {
+        let upstream: UpstreamVersion = match client
+            .session_get(SessionGet {
+                fields: vec![
+                    Cow::Borrowed("version"),
+                    Cow::Borrowed("rpc-version"),
+                    Cow::Borrowed("rpc-version-minimum"),
+                ],
+            })
+            .await
+        {
+            Ok(upstream) => upstream,
+            Err(err) => {
+                warn!(%err, "could not check upstream rpc version");
+                return Ok(());
+            }
+        };
+
+        client.set_upstream_rpc_version(upstream.rpc_version);
+
+        let version = upstream.version.as_deref().unwrap_or("unknown");
+        if SUPPORTED_RPC_VERSIONS.contains(&upstream.rpc_version) {
+            info!(
+                %version,
+                rpc_version = upstream.rpc_version,
+                rpc_version_minimum = upstream.rpc_version_minimum,
+                "upstream rpc version"
+            );
+            return Ok(());
+        }
+
+        warn!(
+            %version,
+            rpc_version = upstream.rpc_version,
+            supported = ?SUPPORTED_RPC_VERSIONS,
+            "unsupported upstream rpc version, some fields may be ignored or misinterpreted"
+        );
+
+        if strict {
+            return Err(Error::UnsupportedRpcVersion(upstream.rpc_version));
+        }
+
+        Ok(())
+    }
+
+    /// Check the upstream RPC version periodically, after the startup check
+    pub async fn run(&self, client: &RpcProxyClient) {
+        if self.interval == 0 {
+            return;
+        }
+
+        let mut interval = tokio::time::interval(Duration::from_secs(self.interval));
+        interval.tick().await;
+
+        loop {
+            interval.tick().await;
+            self.check(client, false).await.ok();
+        }
+    }
+}
@@ -0,0 +1,11 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+/// Changes made to the proxied Transmission web interface
+#[derive(Debug, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct WebUi {
+    /// Inject a toolbar with the current user name and a logout link into the web interface
+    #[serde(default)]
+    pub toolbar: bool,
+}
@@ -0,0 +1,262 @@
+//! Verification of WebAuthn registrations and assertions, for passkey logins
+//!
+//! Only ES256 credentials and the `none` attestation are supported: passkeys are trusted on first
+//! use by users who are already logged in.
+
+use std::io::Cursor;
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use ciborium::value::Value;
+use p256::ecdsa::{signature::Verifier, Signature, VerifyingKey};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+
+/// COSE identifier of ECDSA with SHA-256
+pub const ES256: i64 = -7;
+
+/// User present flag of the authenticator data
+const FLAG_USER_PRESENT: u8 = 0x01;
+
+/// Attested credential data flag of the authenticator data
+const FLAG_ATTESTED: u8 = 0x40;
+
+#[derive(Debug, Error)]
+pub enum WebauthnError {
+    #[error("invalid base64: {0}")]
+    Base64(#[from] base64::DecodeError),
+    #[error("invalid client data: {0}")]
+    ClientData(#[from] serde_json::Error),
+    #[error("unexpected ceremony type {0}")]
+    Type(String),
+    #[error("unexpected origin {0}")]
+    Origin(String),
+    #[error("invalid attestation object")]
+    Attestation,
+    #[error("invalid authenticator data")]
+    AuthenticatorData,
+    #[error("authenticator data is for another relying party")]
+    RelyingParty,
+    #[error("the user was not present")]
+    UserNotPresent,
+    #[error("unsupported credential public key, only ES256 is supported")]
+    UnsupportedKey,
+    #[error("invalid signature")]
+    Signature,
+    #[error("signature counter went back, the credential may have been cloned")]
+    Counter,
+}
+
+/// Data collected by the browser and signed by the authenticator
+#[derive(Debug, Deserialize)]
+pub struct ClientData {
+    #[serde(rename = "type")]
+    pub kind: String,
+    /// Base64url challenge sent by the proxy
+    pub challenge: String,
+    pub origin: String,
+}
+
+impl ClientData {
+    pub fn parse(client_data_json: &[u8]) -> Result<Self, WebauthnError> {
+        Ok(serde_json::from_slice(client_data_json)?)
+    }
+}
+
+/// Credential created by a registration
+#[derive(Debug)]
+pub struct NewCredential {
+    /// Base64url credential id
+    pub id: String,
+    /// SEC1 encoded public key
+    pub public_key: Vec<u8>,
+    pub sign_count: u32,
+}
+
+/// Parsed authenticator data
+struct AuthenticatorData<'a> {
+    rp_id_hash: &'a [u8],
+    flags: u8,
+    sign_count: u32,
+    /// Attested credential data and extensions, if any
+    rest: &'a [u8],
+}
+
+impl<'a> AuthenticatorData<'a> {
+    fn parse(data: &'a [u8]) -> Result<Self, WebauthnError> {
+        if data.len() < 37 {
+            return Err(WebauthnError::AuthenticatorData);
+        }
+
+        Ok(Self {
+            rp_id_hash: &data[..32],
+            flags: data[32],
+            sign_count: u32::from_be_bytes(data[33..37].try_into().unwrap()),
+            rest: &data[37..],
+        })
+    }
+
+    /// Credential id and public key of a registration
+    fn attested_credential(&self) -> Result<(&'a [u8], Vec<u8>), WebauthnError> {
+        if self.flags & FLAG_ATTESTED == 0 || self.rest.len() < 18 {
+            return Err(WebauthnError::AuthenticatorData);
+        }
+
+        // Skip the AAGUID
+        let len = u16::from_be_bytes([self.rest[16], self.rest[17]]) as usize;
+        let id = self
+            .rest
+            .get(18..18 + len)
+            .ok_or(WebauthnError::AuthenticatorData)?;
+
+        // The key may be followed by extensions
+        let key: Value = ciborium::de::from_reader(Cursor::new(&self.rest[18 + len..]))
+            .map_err(|_| WebauthnError::AuthenticatorData)?;
+
+        Ok((id, cose_to_sec1(&key)?))
+    }
+}
+
+/// Value of an integer key of a COSE map
+fn cose_field(key: &Value, label: i64) -> Option<&Value> {
+    key.as_map()?.iter().find_map(|(k, v)| {
+        k.as_integer()
+            .filter(|k| i128::from(*k) == i128::from(label))
+            .map(|_| v)
+    })
+}
+
+/// Convert an ES256 COSE key to a SEC1 encoded point
+fn cose_to_sec1(key: &Value) -> Result<Vec<u8>, WebauthnError> {
+    let int = |label| {
+        cose_field(key, label)
+            .and_then(Value::as_integer)
+            .map(i128::from)
+    };
+    let bytes = |label| cose_field(key, label).and_then(Value::as_bytes);
+
+    // EC2 key type, on the P-256 curve
+    if int(1) != Some(2) || int(3) != Some(ES256.into()) || int(-1) != Some(1) {
+        return Err(WebauthnError::UnsupportedKey);
+    }
+
+    let (Some(x), Some(y)) = (bytes(-2), bytes(-3)) else {
+        return Err(WebauthnError::UnsupportedKey);
+    };
+
+    let mut point = Vec::with_capacity(65);
+    point.push(0x04);
+    point.extend_from_slice(x);
+    point.extend_from_slice(y);
+
+    VerifyingKey::from_sec1_bytes(&point).map_err(|_| WebauthnError::UnsupportedKey)?;
+    Ok(point)
+}
+
+/// Identity and origin of the proxy, as seen by authenticators
+#[derive(Debug, Clone)]
+pub struct RelyingParty {
+    pub id: String,
+    pub origin: String,
+}
+
+impl RelyingParty {
+    /// Check the client data of a ceremony, returning its challenge
+    fn check_client_data(
+        &self,
+        client_data_json: &[u8],
+        kind: &str,
+    ) -> Result<ClientData, WebauthnError> {
+        let client_data = ClientData::parse(client_data_json)?;
+
+        if client_data.kind != kind {
+            return Err(WebauthnError::Type(client_data.kind));
+        }
+
+        if client_data.origin != self.origin {
+            return Err(WebauthnError::Origin(client_data.origin));
+        }
+
+        Ok(client_data)
+    }
+
+    fn check_authenticator_data(&self, data: &AuthenticatorData) -> Result<(), WebauthnError> {
+        if data.rp_id_hash != &Sha256::digest(self.id.as_bytes())[..] {
+            return Err(WebauthnError::RelyingParty);
+        }
+
+        if data.flags & FLAG_USER_PRESENT == 0 {
+            return Err(WebauthnError::UserNotPresent);
+        }
+
+        Ok(())
+    }
+
+    /// Verify the response to a `navigator.credentials.create()` call. The caller checks that the
+    /// challenge of the client data is one it issued.
+    pub fn register(
+        &self,
+        client_data_json: &[u8],
+        attestation_object: &[u8],
+    ) -> Result<NewCredential, WebauthnError> {
+        self.check_client_data(client_data_json, "webauthn.create")?;
+
+        let attestation: Value = ciborium::de::from_reader(attestation_object)
+            .map_err(|_| WebauthnError::Attestation)?;
+        let auth_data = attestation
+            .as_map()
+            .and_then(|map| {
+                map.iter()
+                    .find(|(k, _)| k.as_text() == Some("authData"))
+                    .and_then(|(_, v)| v.as_bytes())
+            })
+            .ok_or(WebauthnError::Attestation)?;
+
+        let data = AuthenticatorData::parse(auth_data)?;
+        self.check_authenticator_data(&data)?;
+        let (id, public_key) = data.attested_credential()?;
+
+        Ok(NewCredential {
+            id: URL_SAFE_NO_PAD.encode(id),
+            public_key,
+            sign_count: data.sign_count,
+        })
+    }
+
+    /// Verify the response to a `navigator.credentials.get()` call with a registered credential,
+    /// returning the new signature counter. The caller checks the challenge.
+    pub fn authenticate(
+        &self,
+        client_data_json: &[u8],
+        authenticator_data: &[u8],
+        signature: &[u8],
+        public_key: &[u8],
+        sign_count: u32,
+    ) -> Result<u32, WebauthnError> {
+        self.check_client_data(client_data_json, "webauthn.get")?;
+
+        let data = AuthenticatorData::parse(authenticator_data)?;
+        self.check_authenticator_data(&data)?;
+
+        let key =
+            VerifyingKey::from_sec1_bytes(public_key).map_err(|_| WebauthnError::UnsupportedKey)?;
+        let signature = Signature::from_der(signature).map_err(|_| WebauthnError::Signature)?;
+
+        let mut message = authenticator_data.to_vec();
+        message.extend_from_slice(&Sha256::digest(client_data_json));
+        key.verify(&message, &signature)
+            .map_err(|_| WebauthnError::Signature)?;
+
+        // Authenticators which don't count always send 0
+        if (data.sign_count != 0 || sign_count != 0) && data.sign_count <= sign_count {
+            return Err(WebauthnError::Counter);
+        }
+
+        Ok(data.sign_count)
+    }
+}
+
+/// Decode a base64url value sent by the browser
+pub fn decode(value: &str) -> Result<Vec<u8>, WebauthnError> {
+    Ok(URL_SAFE_NO_PAD.decode(value.trim_end_matches('='))?)
+}
@@ -0,0 +1,102 @@
+use reqwest::StatusCode;
+use serde_json::json;
+
+use transmission_proxy::testing::MockUpstream;
+use transmission_rpc_client::types::MethodCall;
+
+mod common;
+use common::{rpc, Harness};
+
+fn harness(users: &str) -> Harness {
+    Harness::new(&format!(
+        r#"
+users:
+  {users}
+acl:
+  rules:
+    - identities:
+        - provider: user
+          name: alice
+      download_dir: /data/alice
+owner_labels:
+  enabled: true
+  hide_from_others: true
+"#
+    ))
+    .users(&["alice", "alice-laptop", "bob"])
+}
+
+const ALICE: &str = r#"- name: alice
+    identities:
+      - provider: basic
+        name: alice
+      - provider: basic
+        name: alice-laptop"#;
+
+#[tokio::test]
+async fn linked_identities_share_ownership() {
+    let (upstream, proxy) = harness(ALICE)
+        .torrents(vec![
+            json!({ "id": 1, "downloadDir": "/data/alice", "labels": ["owner:alice"] }),
+        ])
+        .start()
+        .await;
+
+    for user in ["alice", "alice-laptop"] {
+        let (status, response) = rpc(
+            &proxy,
+            Some(user),
+            json!({ "method": "torrent-get", "arguments": { "fields": ["id", "downloadDir", "labels"] } }),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(
+            response["arguments"]["torrents"],
+            json!([{ "id": 1, "downloadDir": "/data/alice", "labels": ["owner:alice"] }]),
+            "{user}"
+        );
+    }
+
+    let (status, _) = rpc(
+        &proxy,
+        Some("alice-laptop"),
+        json!({
+            "method": "torrent-add",
+            "arguments": { "download-dir": "/data/alice", "metainfo": "", "paused": false },
+        }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+
+    let add = upstream
+        .requests()
+        .into_iter()
+        .find_map(|request| match request.call {
+            MethodCall::TorrentAdd { arguments } => Some(arguments),
+            _ => None,
+        })
+        .expect("torrent-add was not forwarded");
+    assert_eq!(add.labels, vec!["owner:alice"]);
+
+    // Identities outside of the account don't match the ACL
+    let (status, _) = rpc(&proxy, Some("bob"), json!({ "method": "session-stats" })).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn invalid_accounts_are_rejected() {
+    let upstream = MockUpstream::start().await.unwrap();
+
+    // ACLs name unknown users
+    assert!(harness("[]").start_proxy(upstream.uri()).await.is_err());
+
+    // Identities belong to a single user
+    let users = format!(
+        r#"{ALICE}
+  - name: laptop
+    identities:
+      - provider: basic
+        name: alice-laptop"#
+    );
+    assert!(harness(&users).start_proxy(upstream.uri()).await.is_err());
+}
@@ -0,0 +1,278 @@
+use reqwest::StatusCode;
+use serde_json::json;
+
+use transmission_proxy::testing::{MockUpstream, TestProxy};
+use transmission_rpc_client::types::{MethodCall, TorrentId, TorrentIds};
+
+mod common;
+use common::{rpc, torrent_ids, Harness};
+
+async fn setup() -> (MockUpstream, TestProxy) {
+    Harness::new(
+        r#"
+acl:
+  rules:
+    - identities:
+        - provider: basic
+          name: admin
+    - identities:
+        - provider: basic
+          name: operator
+      allow_dangerous_methods: true
+    - identities:
+        - provider: basic
+          name: alice
+      download_dir: /data/alice
+    - identities:
+        - provider: basic
+          name: readonly
+      allowed_methods:
+        - torrent-get
+    - deny: true
+"#,
+    )
+    .users(&["admin", "operator", "alice", "readonly"])
+    .torrents(vec![
+        json!({ "id": 1, "name": "alice's torrent", "downloadDir": "/data/alice" }),
+        json!({ "id": 2, "name": "bob's torrent", "downloadDir": "/data/bob" }),
+        json!({ "id": 3, "name": "alice's other torrent", "downloadDir": "/data/alice/sub" }),
+    ])
+    .start()
+    .await
+}
+
+#[tokio::test]
+async fn admin_sees_all_torrents() {
+    let (_upstream, proxy) = setup().await;
+
+    let (status, response) = rpc(
+        &proxy,
+        Some("admin"),
+        json!({ "method": "torrent-get", "arguments": { "fields": ["id", "downloadDir"] } }),
+    )
+    .await;
+
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(torrent_ids(&response), vec![1, 2, 3]);
+}
+
+#[tokio::test]
+async fn download_dir_filters_torrent_get() {
+    let (_upstream, proxy) = setup().await;
+
+    let (status, response) = rpc(
+        &proxy,
+        Some("alice"),
+        json!({ "method": "torrent-get", "arguments": { "fields": ["id", "downloadDir"] } }),
+    )
+    .await;
+
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(torrent_ids(&response), vec![1, 3]);
+}
+
+#[tokio::test]
+async fn download_dir_filters_mutating_ids() {
+    let (upstream, proxy) = setup().await;
+
+    let (status, _) = rpc(
+        &proxy,
+        Some("alice"),
+        json!({ "method": "torrent-stop", "arguments": { "ids": [1, 2] } }),
+    )
+    .await;
+
+    assert_eq!(status, StatusCode::OK);
+
+    let stop = upstream
+        .requests()
+        .into_iter()
+        .find_map(|request| match request.call {
+            MethodCall::TorrentStop { arguments } => Some(arguments),
+            _ => None,
+        })
+        .expect("torrent-stop was not forwarded");
+
+    assert_eq!(stop.ids, Some(TorrentIds::Ids(vec![TorrentId::Id(1)])));
+}
+
+#[tokio::test]
+async fn recently_active_set_is_filtered() {
+    let (upstream, proxy) = setup().await;
+
+    let (status, response) = rpc(
+        &proxy,
+        Some("alice"),
+        json!({ "method": "torrent-stop", "arguments": { "ids": "recently-active" } }),
+    )
+    .await;
+
+    assert_eq!(status, StatusCode::OK, "{response}");
+
+    let stop = upstream
+        .requests()
+        .into_iter()
+        .find_map(|request| match request.call {
+            MethodCall::TorrentStop { arguments } => Some(arguments),
+            _ => None,
+        })
+        .expect("torrent-stop was not forwarded");
+
+    assert_eq!(
+        stop.ids,
+        Some(TorrentIds::Ids(vec![TorrentId::Id(1), TorrentId::Id(3)]))
+    );
+
+    // Torrents 1 and 2 are removed, alice only saw the first one
+    upstream.set_torrents(vec![json!({ "id": 3, "downloadDir": "/data/alice/sub" })]);
+    upstream.set_removed(vec![1, 2, 4]);
+
+    let (status, response) = rpc(
+        &proxy,
+        Some("alice"),
+        json!({
+            "method": "torrent-get",
+            "arguments": { "ids": "recently-active", "fields": ["id", "downloadDir"] },
+        }),
+    )
+    .await;
+
+    assert_eq!(status, StatusCode::OK, "{response}");
+    assert_eq!(torrent_ids(&response), vec![3]);
+    assert_eq!(response["arguments"]["removed"], json!([1]));
+}
+
+#[tokio::test]
+async fn download_dir_rejects_foreign_add() {
+    let (upstream, proxy) = setup().await;
+
+    let (status, response) = rpc(
+        &proxy,
+        Some("alice"),
+        json!({
+            "method": "torrent-add",
+            "arguments": { "download-dir": "/data/bob", "metainfo": "", "paused": false },
+        }),
+    )
+    .await;
+
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    assert_eq!(
+        response["result"],
+        "access denied: /data/bob is outside of the allowed download dir"
+    );
+    assert_eq!(response["arguments"]["denial"], "location-not-allowed");
+    assert!(upstream.requests().is_empty());
+}
+
+#[tokio::test]
+async fn allowed_methods_are_enforced() {
+    let (upstream, proxy) = setup().await;
+
+    let (status, response) = rpc(
+        &proxy,
+        Some("readonly"),
+        json!({ "method": "torrent-start", "arguments": { "ids": [1] } }),
+    )
+    .await;
+
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    assert_eq!(
+        response["result"],
+        "access denied: method torrent-start is not allowed"
+    );
+    assert_eq!(response["arguments"]["denial"], "method-not-allowed");
+    assert!(upstream.requests().is_empty());
+
+    let (status, response) = rpc(
+        &proxy,
+        Some("readonly"),
+        json!({ "method": "torrent-get", "arguments": { "fields": ["id", "downloadDir"] } }),
+    )
+    .await;
+
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(torrent_ids(&response), vec![1, 2, 3]);
+}
+
+#[tokio::test]
+async fn dangerous_methods_must_be_allowed() {
+    let (upstream, proxy) = setup().await;
+    let close = json!({ "method": "session-close" });
+
+    for user in ["admin", "alice"] {
+        let (status, response) = rpc(&proxy, Some(user), close.clone()).await;
+
+        assert_eq!(status, StatusCode::FORBIDDEN, "{user}");
+        assert_eq!(response["arguments"]["denial"], "method-not-allowed");
+    }
+    assert!(upstream.requests().is_empty());
+
+    let (status, _) = rpc(&proxy, Some("operator"), close).await;
+
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(upstream.requests().len(), 1);
+}
+
+#[tokio::test]
+async fn anonymous_users_are_redirected() {
+    let (upstream, proxy) = setup().await;
+
+    let (status, _) = rpc(&proxy, None, json!({ "method": "session-stats" })).await;
+
+    assert!(status.is_redirection());
+    assert!(upstream.requests().is_empty());
+}
+
+#[tokio::test]
+async fn default_policy_applies_to_unmatched_users() {
+    for (policy, forwarded) in [("deny", false), ("allow", true)] {
+        let (upstream, proxy) = Harness::new(&format!(
+            r#"
+acl:
+  default_policy: {policy}
+  rules:
+    - identities:
+        - provider: basic
+          name: admin
+"#
+        ))
+        .start()
+        .await;
+
+        let (status, _) = rpc(&proxy, None, json!({ "method": "session-stats" })).await;
+
+        assert_eq!(status.is_success(), forwarded, "{policy}");
+        assert_eq!(!upstream.requests().is_empty(), forwarded, "{policy}");
+    }
+}
+
+#[tokio::test]
+async fn terse_denials_hide_the_reason() {
+    let (_upstream, proxy) = Harness::new(
+        r#"
+acl:
+  terse_denials: true
+  rules:
+    - identities:
+        - provider: basic
+          name: readonly
+      allowed_methods:
+        - torrent-get
+"#,
+    )
+    .users(&["readonly"])
+    .start()
+    .await;
+
+    let (status, response) = rpc(
+        &proxy,
+        Some("readonly"),
+        json!({ "method": "torrent-start", "arguments": { "ids": [1] } }),
+    )
+    .await;
+
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    assert_eq!(response["result"], "access denied");
+    assert!(response.get("arguments").is_none());
+}
@@ -0,0 +1,210 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use reqwest::{
+    header::{COOKIE, LOCATION, ORIGIN, SET_COOKIE},
+    StatusCode,
+};
+use serde_json::json;
+
+use transmission_proxy::testing::{session_cookie, MockUpstream, TestProxy};
+
+mod common;
+use common::Harness;
+
+async fn setup() -> (MockUpstream, TestProxy) {
+    Harness::new(
+        r#"
+acl:
+  rules: []
+  default_policy: allow
+"#,
+    )
+    .users(&["alice"])
+    .start()
+    .await
+}
+
+fn client() -> reqwest::Client {
+    reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .build()
+        .unwrap()
+}
+
+/// Log in with the basic auth provider, returning the session cookie
+async fn login(proxy: &TestProxy, password: &str, user_agent: &str) -> Option<String> {
+    let response = client()
+        .get(proxy.url() + "/auth/basic")
+        .basic_auth("alice", Some(password))
+        .header("User-Agent", user_agent)
+        .send()
+        .await
+        .unwrap();
+
+    response.headers().get(SET_COOKIE).map(|cookie| {
+        cookie
+            .to_str()
+            .unwrap()
+            .split(';')
+            .next()
+            .unwrap()
+            .to_owned()
+    })
+}
+
+async fn activity(proxy: &TestProxy, cookie: &str) -> reqwest::Response {
+    client()
+        .get(proxy.url() + "/account/activity")
+        .header(COOKIE, cookie)
+        .send()
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn logins_and_sessions_are_listed() {
+    let (_upstream, proxy) = setup().await;
+
+    assert!(login(&proxy, "wrong", "attacker-agent").await.is_none());
+    let cookie = login(&proxy, "password", "test-agent").await.unwrap();
+
+    let response = activity(&proxy, &cookie).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let page = response.text().await.unwrap();
+    assert!(page.contains("Activity of alice"));
+    assert!(page.contains("attacker-agent"));
+    assert!(page.contains("Failed"));
+    assert!(page.contains("test-agent"));
+    assert!(page.contains("127.0.0.1"));
+    assert!(page.contains("This session"));
+}
+
+#[tokio::test]
+async fn anonymous_users_are_sent_to_the_login_page() {
+    let (_upstream, proxy) = setup().await;
+
+    let response = client()
+        .get(proxy.url() + "/account/activity")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::SEE_OTHER);
+    assert!(response.headers()[LOCATION]
+        .to_str()
+        .unwrap()
+        .starts_with("/transmission/login"));
+}
+
+#[tokio::test]
+async fn signing_out_everywhere_revokes_all_sessions() {
+    let (_upstream, proxy) = setup().await;
+
+    let laptop = login(&proxy, "password", "laptop").await.unwrap();
+    let phone = login(&proxy, "password", "phone").await.unwrap();
+    assert_eq!(activity(&proxy, &phone).await.status(), StatusCode::OK);
+
+    let response = client()
+        .post(proxy.url() + "/account/sign-out-everywhere")
+        .header(COOKIE, &laptop)
+        .header(ORIGIN, proxy.origin())
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::SEE_OTHER);
+
+    for cookie in [laptop, phone] {
+        assert_eq!(
+            activity(&proxy, &cookie).await.status(),
+            StatusCode::SEE_OTHER
+        );
+    }
+
+    // Logging in again works
+    let cookie = login(&proxy, "password", "laptop").await.unwrap();
+    assert_eq!(activity(&proxy, &cookie).await.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn cross_site_sign_outs_are_rejected() {
+    let (_upstream, proxy) = setup().await;
+    let cookie = login(&proxy, "password", "laptop").await.unwrap();
+
+    let response = client()
+        .post(proxy.url() + "/account/sign-out-everywhere")
+        .header(COOKIE, &cookie)
+        .header(ORIGIN, "https://evil.example")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+    assert_eq!(activity(&proxy, &cookie).await.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn logging_out_ends_the_session() {
+    let (_upstream, proxy) = setup().await;
+
+    let cookie = login(&proxy, "password", "laptop").await.unwrap();
+    client()
+        .get(proxy.url() + "/logout")
+        .header(COOKIE, &cookie)
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(
+        activity(&proxy, &cookie).await.status(),
+        StatusCode::SEE_OTHER
+    );
+}
+
+#[tokio::test]
+async fn cookies_without_a_current_session_are_refused() {
+    let (_upstream, proxy) = setup().await;
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+
+    // Cookies set by older versions, without a token kind or a session id, could never be
+    // revoked. They are ignored so the user can log in again.
+    let legacy = session_cookie(&json!({ "Basic": { "username": "alice" } }));
+    let unrevokable =
+        session_cookie(&json!({ "typ": "session", "Basic": { "username": "alice" } }));
+    // Sessions expire after the cookie lifetime, 30 days by default
+    let expired = session_cookie(&json!({
+        "typ": "session",
+        "Basic": { "username": "alice" },
+        "sid": "expired",
+        "iat": now - 31 * 24 * 3600,
+    }));
+    // Other tokens signed by the proxy are not session cookies
+    let share = session_cookie(&json!({
+        "typ": "share",
+        "Basic": { "username": "alice" },
+        "sid": "share",
+        "iat": now,
+    }));
+    for cookie in [legacy, unrevokable, expired, share] {
+        assert_eq!(
+            activity(&proxy, &format!("_transmission_proxy={cookie}"))
+                .await
+                .status(),
+            StatusCode::SEE_OTHER
+        );
+    }
+
+    let current = session_cookie(&json!({
+        "typ": "session",
+        "Basic": { "username": "alice" },
+        "sid": "current",
+        "iat": now,
+    }));
+    assert_eq!(
+        activity(&proxy, &format!("_transmission_proxy={current}"))
+            .await
+            .status(),
+        StatusCode::OK
+    );
+}
@@ -0,0 +1,197 @@
+use reqwest::StatusCode;
+use serde_json::{json, Value};
+
+use transmission_proxy::testing::{TestProxy, SESSION_ID_HEADER};
+
+mod common;
+use common::{rpc, temp_path, torrent_ids, write_users_file, Harness};
+
+fn harness() -> Harness {
+    Harness::new(
+        r#"
+acl:
+  rules:
+    - identities:
+        - provider: basic
+          name: alice
+      download_dir: /data/alice
+"#,
+    )
+}
+
+/// RPC call authenticated by an API token
+async fn rpc_with_token(proxy: &TestProxy, token: &str, body: Value) -> (StatusCode, Value) {
+    let client = reqwest::Client::new();
+
+    let mut session_id = None;
+    loop {
+        let mut req = client.post(proxy.rpc_url()).bearer_auth(token).json(&body);
+        if let Some(session_id) = &session_id {
+            req = req.header(SESSION_ID_HEADER, session_id);
+        }
+
+        let res = req.send().await.unwrap();
+        if res.status() == StatusCode::CONFLICT && session_id.is_none() {
+            session_id = Some(res.headers()[SESSION_ID_HEADER].clone());
+            continue;
+        }
+
+        let status = res.status();
+        return (status, res.json().await.unwrap_or(Value::Null));
+    }
+}
+
+async fn create_token(proxy: &TestProxy, body: Value) -> (StatusCode, Value) {
+    let response = reqwest::Client::new()
+        .post(proxy.url() + "/account/tokens")
+        .basic_auth("alice", Some("password"))
+        .json(&body)
+        .send()
+        .await
+        .unwrap();
+    let status = response.status();
+    (status, response.json().await.unwrap_or(Value::Null))
+}
+
+#[tokio::test]
+async fn tokens_are_limited_to_their_scope() {
+    let (_upstream, proxy) = harness()
+        .users(&["alice"])
+        .torrents(vec![
+            json!({ "id": 1, "downloadDir": "/data/alice/tv" }),
+            json!({ "id": 2, "downloadDir": "/data/alice/movies" }),
+        ])
+        .start()
+        .await;
+
+    let (status, created) = create_token(
+        &proxy,
+        json!({
+            "name": "sonarr",
+            "methods": ["torrent-get", "torrent-add"],
+            "download_dir": "/data/alice/tv",
+        }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let token = created["token"].as_str().unwrap();
+
+    let get = json!({ "method": "torrent-get", "arguments": { "fields": ["id", "downloadDir"] } });
+    let (status, response) = rpc_with_token(&proxy, token, get.clone()).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(torrent_ids(&response), vec![1]);
+
+    // The owner keeps the permissions of their ACL
+    let (_, response) = rpc(&proxy, Some("alice"), get).await;
+    assert_eq!(torrent_ids(&response), vec![1, 2]);
+
+    let (_, response) = rpc_with_token(
+        &proxy,
+        token,
+        json!({ "method": "torrent-remove", "arguments": { "ids": [1] } }),
+    )
+    .await;
+    assert_eq!(
+        response["result"],
+        "access denied: method torrent-remove is not allowed"
+    );
+
+    // Clients which only support basic auth send the token as the password
+    let response = reqwest::Client::new()
+        .post(proxy.rpc_url())
+        .basic_auth("sonarr", Some(token))
+        .json(&json!({ "method": "torrent-get", "arguments": { "fields": ["id"] } }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::CONFLICT);
+
+    // Tokens can't issue other tokens
+    let response = reqwest::Client::new()
+        .post(proxy.url() + "/account/tokens")
+        .bearer_auth(token)
+        .json(&json!({ "name": "other" }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+}
+
+#[tokio::test]
+async fn tokens_can_be_revoked() {
+    let (_upstream, proxy) = harness().users(&["alice"]).start().await;
+
+    // Scopes can't be wider than the ACL
+    let (status, _) = create_token(
+        &proxy,
+        json!({ "name": "wide", "download_dir": "/data/bob" }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    let (_, created) = create_token(&proxy, json!({ "name": "script" })).await;
+    let token = created["token"].as_str().unwrap();
+    let stats = json!({ "method": "session-stats" });
+    assert_eq!(
+        rpc_with_token(&proxy, token, stats.clone()).await.0,
+        StatusCode::OK
+    );
+
+    let client = reqwest::Client::new();
+    let tokens: Value = client
+        .get(proxy.url() + "/account/tokens")
+        .basic_auth("alice", Some("password"))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(tokens.as_array().unwrap().len(), 1);
+    assert_eq!(tokens[0]["name"], "script");
+    assert!(tokens[0].get("token").is_none());
+
+    let response = client
+        .delete(format!(
+            "{}/account/tokens/{}",
+            proxy.url(),
+            tokens[0]["id"].as_str().unwrap()
+        ))
+        .basic_auth("alice", Some("password"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+
+    assert_eq!(
+        rpc_with_token(&proxy, token, stats).await.0,
+        StatusCode::UNAUTHORIZED
+    );
+}
+
+#[tokio::test]
+async fn tokens_of_removed_users_are_refused() {
+    let path = temp_path("api-tokens-users");
+    write_users_file(&path, &["alice"], 0);
+
+    let (_upstream, proxy) = harness()
+        .basic(&format!("users_file: {}", path.display()))
+        .start()
+        .await;
+
+    let (_, created) = create_token(&proxy, json!({ "name": "script" })).await;
+    let token = created["token"].as_str().unwrap();
+    let stats = json!({ "method": "session-stats" });
+    assert_eq!(
+        rpc_with_token(&proxy, token, stats.clone()).await.0,
+        StatusCode::OK
+    );
+
+    write_users_file(&path, &["bob"], 1);
+    assert_eq!(
+        rpc_with_token(&proxy, token, stats).await.0,
+        StatusCode::UNAUTHORIZED
+    );
+
+    std::fs::remove_file(&path).unwrap();
+}
@@ -0,0 +1,75 @@
+use reqwest::{header::WWW_AUTHENTICATE, StatusCode};
+
+use transmission_proxy::testing::{MockUpstream, TestProxy};
+
+mod common;
+use common::Harness;
+
+async fn setup(basic: &str) -> (MockUpstream, TestProxy) {
+    Harness::new(
+        r#"
+acl:
+  rules:
+    - deny: true
+"#,
+    )
+    .basic(basic)
+    .start()
+    .await
+}
+
+async fn anonymous_request(url: String, user_agent: &str) -> reqwest::Response {
+    reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .user_agent(user_agent)
+        .build()
+        .unwrap()
+        .post(url)
+        .send()
+        .await
+        .unwrap()
+}
+
+fn is_challenge(response: &reqwest::Response) -> bool {
+    response.status() == StatusCode::UNAUTHORIZED
+        && response.headers().contains_key(WWW_AUTHENTICATE)
+}
+
+#[tokio::test]
+async fn client_apps_get_a_challenge() {
+    let (_upstream, proxy) = setup("users: []").await;
+
+    for user_agent in [
+        "transmission-remote-gtk",
+        "transmission-remote/4.0.5 (a6fe2a64aa)",
+    ] {
+        let response = anonymous_request(proxy.rpc_url(), user_agent).await;
+        assert!(is_challenge(&response), "{user_agent}");
+    }
+
+    let response = anonymous_request(proxy.rpc_url(), "Mozilla/5.0").await;
+    assert!(response.status().is_redirection());
+}
+
+#[tokio::test]
+async fn configured_user_agents_get_a_challenge() {
+    let (_upstream, proxy) = setup("client_user_agents: [\"Transmissionic\"]").await;
+
+    let response = anonymous_request(proxy.rpc_url(), "Transmissionic/1.8.0").await;
+    assert!(is_challenge(&response));
+
+    let response = anonymous_request(proxy.rpc_url(), "transmission-remote-gtk").await;
+    assert!(response.status().is_redirection());
+}
+
+#[tokio::test]
+async fn rpc_always_gets_a_challenge() {
+    let (_upstream, proxy) = setup("rpc_basic_auth: always").await;
+
+    let response = anonymous_request(proxy.rpc_url(), "Mozilla/5.0").await;
+    assert!(is_challenge(&response));
+
+    // The web interface still redirects to the login page
+    let response = anonymous_request(proxy.url() + "/web/", "Mozilla/5.0").await;
+    assert!(response.status().is_redirection());
+}
@@ -0,0 +1,122 @@
+use std::time::{Duration, Instant};
+
+use reqwest::StatusCode;
+use serde_json::{json, Value};
+
+use transmission_proxy::testing::{MockUpstream, TestProxy, SESSION_ID_HEADER};
+
+mod common;
+use common::Harness;
+
+async fn setup() -> (MockUpstream, TestProxy) {
+    Harness::new(
+        r#"
+acl:
+  rules:
+    - identities:
+        - provider: basic
+          name: admin
+      admin: true
+    - identities:
+        - provider: basic
+          name: alice
+      download_dir: /data/alice
+"#,
+    )
+    .users(&["admin", "alice"])
+    .start()
+    .await
+}
+
+async fn set_faults(proxy: &TestProxy, faults: Value) -> reqwest::Response {
+    reqwest::Client::new()
+        .put(proxy.url() + "/admin/faults")
+        .basic_auth("admin", Some("password"))
+        .json(&faults)
+        .send()
+        .await
+        .unwrap()
+}
+
+async fn torrent_get(
+    proxy: &TestProxy,
+    upstream: &MockUpstream,
+) -> reqwest::Result<reqwest::Response> {
+    reqwest::Client::new()
+        .post(proxy.rpc_url())
+        .basic_auth("alice", Some("password"))
+        .header(SESSION_ID_HEADER, upstream.session_id())
+        .json(&json!({ "method": "torrent-get", "arguments": { "fields": ["id"] } }))
+        .send()
+        .await
+}
+
+#[tokio::test]
+async fn faults_are_injected_into_rpc_calls() {
+    let (upstream, proxy) = setup().await;
+
+    // Delays
+    let response = set_faults(&proxy, json!({ "delay": 200 })).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let start = Instant::now();
+    let response = torrent_get(&proxy, &upstream).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(start.elapsed() >= Duration::from_millis(200));
+
+    // Errors
+    set_faults(&proxy, json!({ "error_percent": 100 })).await;
+    let calls = upstream.requests().len();
+    let response = torrent_get(&proxy, &upstream).await.unwrap();
+    assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    assert_eq!(upstream.requests().len(), calls);
+
+    // Dropped connections
+    set_faults(&proxy, json!({ "drop_percent": 100 })).await;
+    let result = match torrent_get(&proxy, &upstream).await {
+        Ok(response) => response.bytes().await.map(|_| ()),
+        Err(err) => Err(err),
+    };
+    assert!(result.is_err());
+
+    // Clearing the faults
+    let response = reqwest::Client::new()
+        .delete(proxy.url() + "/admin/faults")
+        .basic_auth("admin", Some("password"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+    let response = torrent_get(&proxy, &upstream).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn faults_are_set_by_admins() {
+    let (_upstream, proxy) = setup().await;
+
+    let response = set_faults(&proxy, json!({ "error_percent": 60, "drop_percent": 60 })).await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    let response = reqwest::Client::new()
+        .put(proxy.url() + "/admin/faults")
+        .basic_auth("alice", Some("password"))
+        .json(&json!({ "error_percent": 100 }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+    let faults: Value = reqwest::Client::new()
+        .get(proxy.url() + "/admin/faults")
+        .basic_auth("admin", Some("password"))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(
+        faults,
+        json!({ "delay": 0, "error_percent": 0.0, "drop_percent": 0.0 })
+    );
+}
@@ -0,0 +1,114 @@
+use reqwest::{header::USER_AGENT, StatusCode};
+use serde_json::{json, Value};
+
+use transmission_proxy::testing::{MockUpstream, TestProxy, SESSION_ID_HEADER};
+use transmission_rpc_client::types::MethodCall;
+
+mod common;
+use common::Harness;
+
+fn harness() -> Harness {
+    Harness::new(
+        r#"
+client_profiles:
+  - name: Test client
+    user_agents: [TestClient]
+    basic_auth: true
+    torrent_fields: [labels]
+    disable_etag: true
+    rate_limit: 3
+acl:
+  rules:
+    - identities:
+        - provider: basic
+          name: alice
+      download_dir: /data/alice
+"#,
+    )
+    .basic("client_user_agents: []")
+    .users(&["alice"])
+}
+
+async fn call(
+    proxy: &TestProxy,
+    upstream: &MockUpstream,
+    user_agent: &str,
+    body: Value,
+) -> reqwest::Response {
+    reqwest::Client::new()
+        .post(proxy.rpc_url())
+        .basic_auth("alice", Some("password"))
+        .header(USER_AGENT, user_agent)
+        .header(SESSION_ID_HEADER, upstream.session_id())
+        .json(&body)
+        .send()
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn unauthenticated_clients_get_a_challenge() {
+    let (_upstream, proxy) = harness().start().await;
+
+    let client = reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .build()
+        .unwrap();
+    let get = |user_agent: &'static str| {
+        client
+            .get(proxy.rpc_url())
+            .header(USER_AGENT, user_agent)
+            .send()
+    };
+
+    let response = get("TestClient/1.0").await.unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    assert!(response.headers().contains_key("www-authenticate"));
+
+    // Browsers are sent to the login page
+    let response = get("Mozilla/5.0").await.unwrap();
+    assert!(response.status().is_redirection());
+}
+
+#[tokio::test]
+async fn profiles_adjust_rpc_calls() {
+    let (upstream, proxy) = harness()
+        .torrents(vec![
+            json!({ "id": 1, "name": "Alice", "downloadDir": "/data/alice", "labels": [] }),
+        ])
+        .start()
+        .await;
+
+    let torrent_get = json!({ "method": "torrent-get", "arguments": { "fields": ["id"] } });
+
+    // Fields are added and polls are never answered with 304
+    let response = call(&proxy, &upstream, "TestClient/1.0", torrent_get.clone()).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(!response.headers().contains_key("etag"));
+
+    let requests = upstream.requests();
+    let MethodCall::TorrentGet { arguments } = &requests.last().unwrap().call else {
+        panic!("expected a torrent-get call");
+    };
+    assert!(arguments.fields.iter().any(|field| field == "labels"));
+
+    // Other clients are left alone
+    let response = call(&proxy, &upstream, "Mozilla/5.0", torrent_get.clone()).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(response.headers().contains_key("etag"));
+
+    // The rate limit only counts the calls of the client app
+    for _ in 0..2 {
+        let response = call(&proxy, &upstream, "TestClient/1.0", torrent_get.clone()).await;
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+    let response = call(
+        &proxy,
+        &upstream,
+        "TestClient/1.0",
+        json!({ "method": "torrent-get", "arguments": { "fields": ["id"] }, "tag": 7 }),
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(response.json::<Value>().await.unwrap()["tag"], json!(7));
+}
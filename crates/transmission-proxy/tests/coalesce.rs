@@ -0,0 +1,118 @@
+use std::time::Duration;
+
+use reqwest::StatusCode;
+use serde_json::{json, Value};
+
+use transmission_proxy::testing::{MockUpstream, TestProxy};
+use transmission_rpc_client::types::MethodCall;
+
+mod common;
+use common::{rpc, torrent_ids, Harness};
+
+async fn setup(extra: &str) -> (MockUpstream, TestProxy) {
+    let (upstream, proxy) = Harness::new(&format!(
+        r#"
+coalesce:
+  enabled: true
+acl:
+  rules:
+    - identities:
+        - provider: basic
+          name: alice
+      download_dir: /data/alice
+    - identities:
+        - provider: basic
+          name: bob
+      download_dir: /data/bob
+{extra}
+"#
+    ))
+    .users(&["alice", "bob"])
+    .torrents(vec![
+        json!({ "id": 1, "name": "Alice", "downloadDir": "/data/alice" }),
+        json!({ "id": 2, "name": "Bob", "downloadDir": "/data/bob" }),
+    ])
+    .start()
+    .await;
+
+    // Make sure the proxy knows the session id, so each call is a single upstream request
+    rpc(&proxy, Some("alice"), json!({ "method": "session-get" })).await;
+    upstream.clear_requests();
+    upstream.set_delay(Duration::from_millis(300));
+
+    (upstream, proxy)
+}
+
+fn torrent_gets(upstream: &MockUpstream) -> usize {
+    upstream
+        .requests()
+        .iter()
+        .filter(|request| matches!(request.call, MethodCall::TorrentGet { .. }))
+        .count()
+}
+
+async fn poll(proxy: &TestProxy, user: &str, tag: i32) -> (StatusCode, Value) {
+    rpc(
+        proxy,
+        Some(user),
+        json!({
+            "method": "torrent-get",
+            "arguments": { "fields": ["id", "name", "downloadDir"] },
+            "tag": tag,
+        }),
+    )
+    .await
+}
+
+/// Poll as alice, then as bob while the first call is in flight
+async fn overlapping_polls(proxy: &TestProxy) -> ((StatusCode, Value), (StatusCode, Value)) {
+    tokio::join!(poll(proxy, "alice", 1), async {
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        poll(proxy, "bob", 2).await
+    })
+}
+
+#[tokio::test]
+async fn identical_polls_share_an_upstream_call() {
+    // Both users reach the upstream with the credentials of the proxy
+    let (upstream, proxy) = setup(
+        r#"
+upstream_auth:
+  credentials:
+    username: proxy
+    password: secret"#,
+    )
+    .await;
+    let ((alice_status, alice), (bob_status, bob)) = overlapping_polls(&proxy).await;
+
+    // Each user only sees their own torrents, with the tag of their call
+    assert_eq!(alice_status, StatusCode::OK, "{alice}");
+    assert_eq!(torrent_ids(&alice), vec![1]);
+    assert_eq!(alice["tag"], json!(1));
+    assert_eq!(bob_status, StatusCode::OK, "{bob}");
+    assert_eq!(torrent_ids(&bob), vec![2]);
+    assert_eq!(bob["tag"], json!(2));
+
+    assert_eq!(torrent_gets(&upstream), 1);
+
+    // Later polls are not served the same response
+    upstream.set_delay(Duration::ZERO);
+    poll(&proxy, "alice", 3).await;
+    assert_eq!(torrent_gets(&upstream), 2);
+}
+
+#[tokio::test]
+async fn polls_with_other_upstream_credentials_are_not_shared() {
+    // Each user reaches the upstream with their own credentials
+    let (upstream, proxy) = setup(
+        r#"
+upstream_auth:
+  passthrough: true"#,
+    )
+    .await;
+    let ((alice_status, alice), (bob_status, bob)) = overlapping_polls(&proxy).await;
+
+    assert_eq!(alice_status, StatusCode::OK, "{alice}");
+    assert_eq!(bob_status, StatusCode::OK, "{bob}");
+    assert_eq!(torrent_gets(&upstream), 2);
+}
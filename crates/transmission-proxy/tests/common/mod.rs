@@ -0,0 +1,203 @@
+#![allow(dead_code)]
+
+use std::{
+    path::{Path, PathBuf},
+    sync::OnceLock,
+    time::{Duration, SystemTime},
+};
+
+use hyper::Uri;
+use reqwest::StatusCode;
+use serde_json::Value;
+
+use transmission_proxy::testing::{MockUpstream, TestProxy, SESSION_ID_HEADER};
+
+/// Password of the users of the test configurations
+pub const PASSWORD: &str = "password";
+
+/// Hash of [`PASSWORD`], computed once as bcrypt is slow on purpose
+pub fn password_hash() -> &'static str {
+    static HASH: OnceLock<String> = OnceLock::new();
+    HASH.get_or_init(|| bcrypt::hash(PASSWORD, 4).unwrap())
+}
+
+/// Indent each line of a YAML fragment
+fn indent(yaml: &str, spaces: usize) -> String {
+    let mut indented = String::new();
+    for line in yaml.trim_matches('\n').lines() {
+        indented += &format!("{:spaces$}{line}\n", "");
+    }
+    indented
+}
+
+/// Path of a temporary file, unique to the test process
+pub fn temp_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("transmission-proxy-{name}-{}", std::process::id()))
+}
+
+/// Replace a file read by the proxy, making sure its modification time changes
+pub fn rotate(path: &Path, contents: &str, generation: u64) {
+    std::fs::write(path, contents).unwrap();
+    std::fs::File::options()
+        .write(true)
+        .open(path)
+        .unwrap()
+        .set_modified(SystemTime::now() + Duration::from_secs(generation))
+        .unwrap();
+}
+
+/// Replace a `users_file` of the basic auth provider with users who all have [`PASSWORD`]
+pub fn write_users_file(path: &Path, usernames: &[&str], generation: u64) {
+    let hash = password_hash();
+    let mut users = String::new();
+    for username in usernames {
+        users += &format!("- username: {username}\n  password: \"{hash}\"\n");
+    }
+
+    rotate(path, &users, generation);
+}
+
+/// Configuration of a test proxy, in front of a mock upstream
+#[derive(Debug, Default)]
+pub struct Harness {
+    config: String,
+    /// Users of the basic auth provider, with their additional fields
+    users: Vec<(String, String)>,
+    basic: String,
+    providers: String,
+    torrents: Vec<Value>,
+    args: Vec<String>,
+}
+
+impl Harness {
+    /// Start from a YAML configuration, which doesn't have a `providers` section
+    pub fn new(config: &str) -> Self {
+        Self {
+            config: config.to_owned(),
+            ..Default::default()
+        }
+    }
+
+    /// Add users to the basic auth provider, who all have [`PASSWORD`]
+    pub fn users(mut self, usernames: &[&str]) -> Self {
+        for username in usernames {
+            self = self.user_with(username, "");
+        }
+        self
+    }
+
+    /// Add a user to the basic auth provider, with additional fields, e.g. `email: ...`
+    pub fn user_with(mut self, username: &str, fields: &str) -> Self {
+        self.users.push((username.to_owned(), fields.to_owned()));
+        self
+    }
+
+    /// Add settings to the basic auth provider, e.g. `rpc_basic_auth: always`
+    pub fn basic(mut self, settings: &str) -> Self {
+        self.basic += settings;
+        self.basic.push('\n');
+        self
+    }
+
+    /// Add other providers, e.g. `webauthn: { enabled: true }`
+    pub fn providers(mut self, providers: &str) -> Self {
+        self.providers += providers;
+        self.providers.push('\n');
+        self
+    }
+
+    /// Torrents of the mock upstream
+    pub fn torrents(mut self, torrents: Vec<Value>) -> Self {
+        self.torrents = torrents;
+        self
+    }
+
+    /// Additional command-line arguments of the proxy
+    pub fn args(mut self, args: &[&str]) -> Self {
+        self.args = args.iter().map(|&arg| arg.to_owned()).collect();
+        self
+    }
+
+    /// Complete YAML configuration of the proxy
+    pub fn config(&self) -> String {
+        let mut config = self.config.clone();
+        if self.users.is_empty() && self.basic.is_empty() && self.providers.is_empty() {
+            return config;
+        }
+
+        config += "\nproviders:\n";
+        if !self.users.is_empty() || !self.basic.is_empty() {
+            config += "  basic:\n    enabled: true\n";
+            config += &indent(&self.basic, 4);
+        }
+        if !self.users.is_empty() {
+            config += "    users:\n";
+            for (username, fields) in &self.users {
+                config += &format!(
+                    "      - username: {username}\n        password: \"{}\"\n",
+                    password_hash()
+                );
+                config += &indent(fields, 8);
+            }
+        }
+        config += &indent(&self.providers, 2);
+        config
+    }
+
+    /// Start the mock upstream and the proxy in front of it
+    pub async fn start(&self) -> (MockUpstream, TestProxy) {
+        let upstream = MockUpstream::start().await.unwrap();
+        upstream.set_torrents(self.torrents.clone());
+
+        let proxy = self.start_proxy(upstream.uri()).await.unwrap();
+        (upstream, proxy)
+    }
+
+    /// Start the proxy in front of the given upstream
+    pub async fn start_proxy(&self, upstream: Uri) -> color_eyre::Result<TestProxy> {
+        let args: Vec<_> = self.args.iter().map(String::as_str).collect();
+        TestProxy::start_with_args(&self.config(), upstream, &args).await
+    }
+}
+
+pub async fn rpc(proxy: &TestProxy, user: Option<&str>, body: Value) -> (StatusCode, Value) {
+    let client = reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .build()
+        .unwrap();
+
+    let mut session_id = None;
+    loop {
+        let mut req = client.post(proxy.rpc_url()).json(&body);
+        if let Some(user) = user {
+            req = req.basic_auth(user, Some("password"));
+        }
+        if let Some(session_id) = &session_id {
+            req = req.header(SESSION_ID_HEADER, session_id);
+        }
+
+        let res = req.send().await.unwrap();
+        if res.status() == StatusCode::CONFLICT && session_id.is_none() {
+            session_id = Some(
+                res.headers()[SESSION_ID_HEADER]
+                    .to_str()
+                    .unwrap()
+                    .to_owned(),
+            );
+            continue;
+        }
+
+        let status = res.status();
+        let body = res.json().await.unwrap_or(Value::Null);
+        return (status, body);
+    }
+}
+
+pub fn torrent_ids(response: &Value) -> Vec<i64> {
+    response["arguments"]["torrents"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|torrent| torrent["id"].as_i64().unwrap())
+        .collect()
+}
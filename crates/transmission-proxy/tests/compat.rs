@@ -0,0 +1,94 @@
+use reqwest::StatusCode;
+use serde_json::json;
+
+use transmission_proxy::testing::{MockUpstream, TestProxy};
+use transmission_rpc_client::types::{MethodCall, MethodName};
+
+mod common;
+use common::{rpc, Harness};
+
+async fn setup() -> (MockUpstream, TestProxy) {
+    let (upstream, proxy) = Harness::new(
+        r#"
+acl:
+  rules: []
+  default_policy: allow
+compat:
+  enabled: true
+"#,
+    )
+    .torrents(vec![json!({
+        "id": 1,
+        "downloadDir": "/data",
+        "files": [{ "bytesCompleted": 0, "length": 1, "name": "a" }],
+        "trackers": [
+            { "id": 0, "tier": 0, "announce": "http://a/announce", "scrape": "http://a/scrape" },
+            { "id": 1, "tier": 0, "announce": "http://b/announce", "scrape": "http://b/scrape" },
+            { "id": 2, "tier": 1, "announce": "http://c/announce", "scrape": "http://c/scrape" },
+        ],
+    })])
+    .start()
+    .await;
+    upstream.respond(MethodName::SessionGet, json!({ "rpc-version": 16 }));
+
+    (upstream, proxy)
+}
+
+#[tokio::test]
+async fn newer_torrent_fields_are_synthesized() {
+    let (_upstream, proxy) = setup().await;
+
+    let (status, response) = rpc(
+        &proxy,
+        None,
+        json!({
+            "method": "torrent-get",
+            "arguments": { "fields": ["id", "downloadDir", "trackerList", "file-count"] },
+        }),
+    )
+    .await;
+
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(
+        response["arguments"]["torrents"],
+        json!([{
+            "id": 1,
+            "downloadDir": "/data",
+            "trackerList": "http://a/announce\nhttp://b/announce\n\nhttp://c/announce",
+            "file-count": 1,
+        }])
+    );
+}
+
+#[tokio::test]
+async fn tracker_list_is_translated() {
+    let (upstream, proxy) = setup().await;
+
+    let (status, _) = rpc(
+        &proxy,
+        None,
+        json!({
+            "method": "torrent-set",
+            "arguments": {
+                "ids": [1],
+                "trackerList": "http://a/announce\n\nhttp://d/announce",
+            },
+        }),
+    )
+    .await;
+
+    assert_eq!(status, StatusCode::OK);
+
+    let set = upstream
+        .requests()
+        .into_iter()
+        .find_map(|request| match request.call {
+            MethodCall::TorrentSet { arguments } => Some(arguments),
+            _ => None,
+        })
+        .expect("torrent-set was not forwarded");
+
+    assert_eq!(set.tracker_list, None);
+    assert_eq!(set.tracker_add, vec!["http://d/announce"]);
+    assert_eq!(set.tracker_remove, vec![1, 2]);
+}
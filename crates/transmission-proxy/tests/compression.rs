@@ -0,0 +1,128 @@
+use std::io::{Read, Write};
+
+use reqwest::header::{ACCEPT_ENCODING, CONTENT_ENCODING};
+use serde_json::{json, Value};
+
+use transmission_proxy::testing::{MockUpstream, TestProxy, SESSION_ID_HEADER};
+
+mod common;
+use common::Harness;
+
+async fn torrent_get(
+    proxy: &TestProxy,
+    upstream: &MockUpstream,
+    accept: &str,
+) -> reqwest::Response {
+    reqwest::Client::builder()
+        .no_gzip()
+        .build()
+        .unwrap()
+        .post(proxy.rpc_url())
+        .header(SESSION_ID_HEADER, upstream.session_id())
+        .header(ACCEPT_ENCODING, accept)
+        .json(&json!({ "method": "torrent-get", "arguments": { "fields": ["id", "name"] } }))
+        .send()
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn rpc_responses_are_compressed() {
+    let (upstream, proxy) = Harness::new(
+        r#"
+compression:
+  enabled: true
+  min_size: 256
+acl:
+  default_policy: allow
+  rules: []
+"#,
+    )
+    .start()
+    .await;
+
+    upstream.set_torrents(
+        (1..100)
+            .map(|id| json!({ "id": id, "name": format!("torrent {id}"), "downloadDir": "/data" }))
+            .collect(),
+    );
+
+    // zstd is preferred, unless the client refuses it
+    let response = torrent_get(&proxy, &upstream, "gzip, br, zstd").await;
+    assert_eq!(response.headers()[CONTENT_ENCODING], "zstd");
+    let body = zstd::decode_all(&response.bytes().await.unwrap()[..]).unwrap();
+    let body: Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(body["arguments"]["torrents"].as_array().unwrap().len(), 99);
+
+    let response = torrent_get(&proxy, &upstream, "gzip;q=0.5, zstd;q=0").await;
+    assert_eq!(response.headers()[CONTENT_ENCODING], "gzip");
+    let mut body = String::new();
+    flate2::read::GzDecoder::new(&response.bytes().await.unwrap()[..])
+        .read_to_string(&mut body)
+        .unwrap();
+    assert!(body.contains("torrent 99"));
+
+    // Small responses are sent as is
+    upstream.set_torrents(vec![
+        json!({ "id": 1, "name": "a", "downloadDir": "/data" }),
+    ]);
+    let response = torrent_get(&proxy, &upstream, "gzip").await;
+    assert!(response.headers().get(CONTENT_ENCODING).is_none());
+}
+
+async fn post_encoded(
+    proxy: &TestProxy,
+    upstream: &MockUpstream,
+    encoding: &str,
+    body: Vec<u8>,
+) -> reqwest::StatusCode {
+    reqwest::Client::new()
+        .post(proxy.rpc_url())
+        .header(SESSION_ID_HEADER, upstream.session_id())
+        .header(CONTENT_ENCODING, encoding)
+        .body(body)
+        .send()
+        .await
+        .unwrap()
+        .status()
+}
+
+#[tokio::test]
+async fn compressed_requests_are_decoded() {
+    let (upstream, proxy) = Harness::new(
+        r#"
+compression:
+  max_decoded_size: 1000
+acl:
+  default_policy: allow
+  rules: []
+"#,
+    )
+    .start()
+    .await;
+
+    let request = json!({ "method": "torrent-get", "arguments": { "fields": ["id"] } }).to_string();
+
+    let mut gzip = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+    gzip.write_all(request.as_bytes()).unwrap();
+    let status = post_encoded(&proxy, &upstream, "gzip", gzip.finish().unwrap()).await;
+    assert_eq!(status, 200);
+
+    let mut deflate =
+        flate2::write::DeflateEncoder::new(Vec::new(), flate2::Compression::default());
+    deflate.write_all(request.as_bytes()).unwrap();
+    let status = post_encoded(&proxy, &upstream, "deflate", deflate.finish().unwrap()).await;
+    assert_eq!(status, 200);
+
+    assert_eq!(upstream.requests().len(), 2);
+
+    // Corrupt and oversized bodies are rejected
+    let status = post_encoded(&proxy, &upstream, "gzip", b"not gzip".to_vec()).await;
+    assert_eq!(status, 400);
+
+    let mut gzip = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+    gzip.write_all(&[b' '; 2000]).unwrap();
+    let status = post_encoded(&proxy, &upstream, "gzip", gzip.finish().unwrap()).await;
+    assert_eq!(status, 400);
+    assert_eq!(upstream.requests().len(), 2);
+}
@@ -0,0 +1,92 @@
+use reqwest::{
+    header::{
+        ACCESS_CONTROL_ALLOW_CREDENTIALS, ACCESS_CONTROL_ALLOW_HEADERS,
+        ACCESS_CONTROL_ALLOW_ORIGIN, ACCESS_CONTROL_EXPOSE_HEADERS, ACCESS_CONTROL_REQUEST_METHOD,
+        ORIGIN,
+    },
+    Method, StatusCode,
+};
+use serde_json::json;
+
+use transmission_proxy::testing::{MockUpstream, TestProxy, SESSION_ID_HEADER};
+
+mod common;
+use common::Harness;
+
+const DASHBOARD: &str = "https://dashboard.example";
+
+async fn setup() -> (MockUpstream, TestProxy) {
+    Harness::new(&format!(
+        r#"
+acl:
+  rules: []
+  default_policy: allow
+cors:
+  allowed_origins:
+    - {DASHBOARD}
+  allow_credentials: true
+"#
+    ))
+    .start()
+    .await
+}
+
+async fn preflight(proxy: &TestProxy, origin: &str) -> reqwest::Response {
+    reqwest::Client::new()
+        .request(Method::OPTIONS, proxy.rpc_url())
+        .header(ORIGIN, origin)
+        .header(ACCESS_CONTROL_REQUEST_METHOD, "POST")
+        .send()
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn preflight_requests_are_answered() {
+    let (upstream, proxy) = setup().await;
+
+    let response = preflight(&proxy, DASHBOARD).await;
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+    assert_eq!(response.headers()[ACCESS_CONTROL_ALLOW_ORIGIN], DASHBOARD);
+    assert_eq!(response.headers()[ACCESS_CONTROL_ALLOW_CREDENTIALS], "true");
+    assert!(response.headers()[ACCESS_CONTROL_ALLOW_HEADERS]
+        .to_str()
+        .unwrap()
+        .contains("X-Transmission-Session-Id"));
+
+    let response = preflight(&proxy, "https://evil.example").await;
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    assert!(!response.headers().contains_key(ACCESS_CONTROL_ALLOW_ORIGIN));
+
+    assert!(upstream.requests().is_empty());
+}
+
+#[tokio::test]
+async fn rpc_responses_have_cors_headers() {
+    let (_upstream, proxy) = setup().await;
+
+    let response = reqwest::Client::new()
+        .post(proxy.rpc_url())
+        .header(ORIGIN, DASHBOARD)
+        .json(&json!({ "method": "session-stats" }))
+        .send()
+        .await
+        .unwrap();
+
+    // The session id must be readable for the handshake
+    assert_eq!(response.status(), StatusCode::CONFLICT);
+    assert_eq!(response.headers()[ACCESS_CONTROL_ALLOW_ORIGIN], DASHBOARD);
+    assert_eq!(
+        response.headers()[ACCESS_CONTROL_EXPOSE_HEADERS],
+        SESSION_ID_HEADER
+    );
+
+    // The web interface is not shared
+    let response = reqwest::Client::new()
+        .get(proxy.url() + "/web/")
+        .header(ORIGIN, DASHBOARD)
+        .send()
+        .await
+        .unwrap();
+    assert!(!response.headers().contains_key(ACCESS_CONTROL_ALLOW_ORIGIN));
+}
@@ -0,0 +1,160 @@
+use reqwest::{
+    header::{COOKIE, ORIGIN, SET_COOKIE},
+    redirect::Policy,
+    StatusCode,
+};
+use serde_json::json;
+
+use transmission_proxy::testing::{MockUpstream, TestProxy, SESSION_ID_HEADER};
+
+mod common;
+use common::Harness;
+
+async fn setup() -> (MockUpstream, TestProxy) {
+    Harness::new(
+        r#"
+acl:
+  rules:
+    - identities:
+        - provider: basic
+          name: alice
+      admin: true
+    - deny: true
+"#,
+    )
+    .users(&["alice"])
+    .start()
+    .await
+}
+
+fn client() -> reqwest::Client {
+    reqwest::Client::builder()
+        .redirect(Policy::none())
+        .build()
+        .unwrap()
+}
+
+/// Log in and return the session cookie
+async fn login(proxy: &TestProxy) -> String {
+    let response = client()
+        .get(proxy.url() + "/auth/basic")
+        .basic_auth("alice", Some("password"))
+        .send()
+        .await
+        .unwrap();
+
+    let cookie = response.headers()[SET_COOKIE].to_str().unwrap();
+    cookie.split(';').next().unwrap().to_owned()
+}
+
+fn session_stats(proxy: &TestProxy) -> reqwest::RequestBuilder {
+    client()
+        .post(proxy.rpc_url())
+        .header(SESSION_ID_HEADER, "mock-session-id")
+        .json(&json!({ "method": "session-stats" }))
+}
+
+#[tokio::test]
+async fn cross_site_cookie_requests_are_rejected() {
+    let (upstream, proxy) = setup().await;
+    let cookie = login(&proxy).await;
+
+    let response = session_stats(&proxy)
+        .header(COOKIE, &cookie)
+        .header(ORIGIN, "https://evil.example")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+    // Without an origin, the custom header is required
+    let response = client()
+        .post(proxy.rpc_url())
+        .header(COOKIE, &cookie)
+        .json(&json!({ "method": "session-stats" }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+    assert!(upstream.requests().is_empty());
+}
+
+#[tokio::test]
+async fn same_site_cookie_requests_are_allowed() {
+    let (upstream, proxy) = setup().await;
+    let cookie = login(&proxy).await;
+
+    let response = session_stats(&proxy)
+        .header(COOKIE, &cookie)
+        .header(ORIGIN, proxy.origin())
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let response = session_stats(&proxy)
+        .header(COOKIE, &cookie)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    assert_eq!(upstream.requests().len(), 2);
+}
+
+#[tokio::test]
+async fn basic_auth_clients_are_not_checked() {
+    let (_upstream, proxy) = setup().await;
+
+    let response = session_stats(&proxy)
+        .basic_auth("alice", Some("password"))
+        .header(ORIGIN, "https://evil.example")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn cross_site_cookie_requests_to_other_routes_are_rejected() {
+    let (_upstream, proxy) = setup().await;
+    let cookie = login(&proxy).await;
+
+    let inspect = |origin: String| {
+        client()
+            .post(proxy.url() + "/inspect")
+            .header(COOKIE, &cookie)
+            .header(ORIGIN, origin)
+            .body("magnet:?xt=urn:btih:0123456789abcdef0123456789abcdef01234567")
+            .send()
+    };
+
+    let response = inspect("https://evil.example".to_owned()).await.unwrap();
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+    let response = inspect(proxy.origin()).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn cross_site_owner_actions_are_rejected() {
+    let (upstream, proxy) = setup().await;
+    let cookie = login(&proxy).await;
+
+    for action in ["stop", "remove"] {
+        let response = client()
+            .post(format!(
+                "{}/admin/owners/{action}?owner=bob&delete_local_data=true",
+                proxy.url()
+            ))
+            .header(COOKIE, &cookie)
+            .header(ORIGIN, "https://evil.example")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    }
+
+    assert!(upstream.requests().is_empty());
+}
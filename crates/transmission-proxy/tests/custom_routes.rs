@@ -0,0 +1,122 @@
+use reqwest::{header::LOCATION, StatusCode};
+
+use transmission_proxy::testing::{MockUpstream, TestProxy};
+
+mod common;
+use common::Harness;
+
+async fn setup() -> (MockUpstream, MockUpstream, TestProxy) {
+    setup_with("").await
+}
+
+async fn setup_with(route: &str) -> (MockUpstream, MockUpstream, TestProxy) {
+    let upstream = MockUpstream::start().await.unwrap();
+    let sidecar = MockUpstream::start().await.unwrap();
+
+    let harness = Harness::new(&format!(
+        r#"
+acl:
+  rules:
+    - name: admins
+      identities:
+        - provider: basic
+          name: admin
+    - name: users
+      identities:
+        - provider: basic
+          name: user
+    - deny: true
+routes:
+  - prefix: /sidecar/
+    upstream: "{}transmission/"
+    acls: [admins]
+{route}
+"#,
+        sidecar.uri()
+    ))
+    .users(&["admin", "user"]);
+
+    let proxy = harness.start_proxy(upstream.uri()).await.unwrap();
+    (upstream, sidecar, proxy)
+}
+
+async fn get(proxy: &TestProxy, user: Option<&str>) -> reqwest::Response {
+    let client = reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .build()
+        .unwrap();
+
+    let mut req = client.get(proxy.origin() + "/sidecar/web/?page=1");
+    if let Some(user) = user {
+        req = req.basic_auth(user, Some("password"));
+    }
+
+    req.send().await.unwrap()
+}
+
+#[tokio::test]
+async fn allowed_users_reach_the_route_upstream() {
+    let (_upstream, _sidecar, proxy) = setup().await;
+
+    let response = get(&proxy, Some("admin")).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(response
+        .text()
+        .await
+        .unwrap()
+        .contains("<title>Transmission</title>"));
+}
+
+#[tokio::test]
+async fn other_users_are_rejected() {
+    let (_upstream, _sidecar, proxy) = setup().await;
+
+    let response = get(&proxy, Some("user")).await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn anonymous_users_are_sent_to_login() {
+    let (_upstream, _sidecar, proxy) = setup().await;
+
+    let response = get(&proxy, None).await;
+    assert_eq!(response.status(), StatusCode::SEE_OTHER);
+    assert_eq!(
+        response.headers()[LOCATION],
+        "/transmission/login?redirect_to=%2Fsidecar%2Fweb%2F%3Fpage%3D1"
+    );
+}
+
+async fn sidecar_headers(proxy: &TestProxy) -> serde_json::Value {
+    reqwest::Client::new()
+        .get(proxy.origin() + "/sidecar/web/headers")
+        .basic_auth("admin", Some("password"))
+        .header("Cookie", "other=value")
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn credentials_are_not_forwarded() {
+    let (_upstream, _sidecar, proxy) = setup().await;
+
+    let headers = sidecar_headers(&proxy).await;
+    assert!(headers.get("authorization").is_none());
+    assert!(headers.get("cookie").is_none());
+}
+
+#[tokio::test]
+async fn credentials_are_forwarded_on_request() {
+    let (_upstream, _sidecar, proxy) = setup_with("    forward_credentials: true").await;
+
+    let headers = sidecar_headers(&proxy).await;
+    assert!(headers["authorization"]
+        .as_str()
+        .unwrap()
+        .starts_with("Basic "));
+    assert_eq!(headers["cookie"], "other=value");
+}
@@ -0,0 +1,73 @@
+use std::time::Duration;
+
+use reqwest::StatusCode;
+
+use transmission_proxy::testing::{MockUpstream, TestProxy};
+
+mod common;
+use common::Harness;
+
+async fn setup(deadline: Option<u64>) -> (MockUpstream, TestProxy) {
+    let listener = deadline.map_or(String::new(), |deadline| {
+        format!("listener:\n  request_deadline: {deadline}\n")
+    });
+
+    Harness::new(&format!(
+        r#"
+{listener}
+acl:
+  default_policy: allow
+  rules: []
+"#
+    ))
+    .start()
+    .await
+}
+
+async fn wait_abandoned(upstream: &MockUpstream) -> usize {
+    for _ in 0..100 {
+        if upstream.abandoned() > 0 {
+            break;
+        }
+
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+
+    upstream.abandoned()
+}
+
+#[tokio::test]
+async fn deadline_aborts_upstream_request() {
+    let (upstream, proxy) = setup(Some(1)).await;
+
+    let response = reqwest::get(proxy.url() + "/web/slow").await.unwrap();
+    assert_eq!(response.status(), StatusCode::GATEWAY_TIMEOUT);
+    assert_eq!(wait_abandoned(&upstream).await, 1);
+}
+
+#[tokio::test]
+async fn requests_within_deadline_go_through() {
+    let (upstream, proxy) = setup(Some(5)).await;
+
+    let request = tokio::spawn(reqwest::get(proxy.url() + "/web/slow"));
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    upstream.release_stream();
+
+    let response = request.await.unwrap().unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(upstream.abandoned(), 0);
+}
+
+#[tokio::test]
+async fn client_disconnect_aborts_upstream_request() {
+    let (upstream, proxy) = setup(None).await;
+
+    let result = reqwest::Client::new()
+        .get(proxy.url() + "/web/slow")
+        .timeout(Duration::from_millis(200))
+        .send()
+        .await;
+    assert!(result.is_err());
+
+    assert_eq!(wait_abandoned(&upstream).await, 1);
+}
@@ -0,0 +1,114 @@
+use base64::Engine;
+use reqwest::StatusCode;
+use serde_json::json;
+
+use transmission_proxy::{testing::MockUpstream, torrent};
+use transmission_rpc_client::types::MethodCall;
+
+mod common;
+use common::{rpc, Harness};
+
+const METAINFO: &str = "d4:infod6:lengthi1e4:name1:a12:piece lengthi16384e6:pieces0:ee";
+
+#[tokio::test]
+async fn duplicates_of_other_users_torrents_are_denied() {
+    let (upstream, proxy) = Harness::new(
+        r#"
+acl:
+  rules:
+    - identities:
+        - provider: basic
+          name: alice
+      download_dir: /data/alice
+"#,
+    )
+    .users(&["alice"])
+    .start()
+    .await;
+
+    let info_hash = torrent::info_hash(METAINFO.as_bytes()).unwrap();
+    let add = json!({
+        "method": "torrent-add",
+        "arguments": {
+            "download-dir": "/data/alice",
+            "metainfo": base64::engine::general_purpose::STANDARD.encode(METAINFO),
+            "paused": false,
+        },
+    });
+    let forwarded = |upstream: &MockUpstream| {
+        upstream
+            .requests()
+            .iter()
+            .any(|request| matches!(request.call, MethodCall::TorrentAdd { .. }))
+    };
+
+    upstream.set_torrents(vec![
+        json!({ "id": 1, "hashString": info_hash, "name": "a", "downloadDir": "/data/bob" }),
+    ]);
+
+    let (status, response) = rpc(&proxy, Some("alice"), add.clone()).await;
+
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    assert_eq!(
+        response["arguments"],
+        json!({ "denial": "duplicate-torrent" })
+    );
+    assert!(!forwarded(&upstream));
+
+    // The upstream reports duplicates of the user's own torrents
+    upstream.set_torrents(vec![
+        json!({ "id": 1, "hashString": info_hash, "name": "a", "downloadDir": "/data/alice" }),
+    ]);
+
+    let (status, _) = rpc(&proxy, Some("alice"), add).await;
+
+    assert_eq!(status, StatusCode::OK);
+    assert!(forwarded(&upstream));
+}
+
+#[tokio::test]
+async fn duplicates_of_torrents_owned_by_others_are_denied() {
+    let (upstream, proxy) = Harness::new(
+        r#"
+acl:
+  rules:
+    - identities:
+        - provider: basic
+          name: alice
+owner_labels:
+  enabled: true
+"#,
+    )
+    .users(&["alice"])
+    .start()
+    .await;
+
+    let info_hash = torrent::info_hash(METAINFO.as_bytes()).unwrap();
+    let add = json!({
+        "method": "torrent-add",
+        "arguments": {
+            "download-dir": "/data",
+            "metainfo": base64::engine::general_purpose::STANDARD.encode(METAINFO),
+        },
+    });
+
+    upstream.set_torrents(vec![
+        json!({ "id": 1, "hashString": info_hash, "name": "a", "labels": ["owner:bob"] }),
+    ]);
+
+    let (status, response) = rpc(&proxy, Some("alice"), add.clone()).await;
+
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    assert_eq!(
+        response["arguments"],
+        json!({ "denial": "duplicate-torrent" })
+    );
+
+    upstream.set_torrents(vec![
+        json!({ "id": 1, "hashString": info_hash, "name": "a", "labels": ["owner:alice"] }),
+    ]);
+
+    let (status, _) = rpc(&proxy, Some("alice"), add).await;
+
+    assert_eq!(status, StatusCode::OK);
+}
@@ -0,0 +1,70 @@
+use reqwest::{
+    header::{ETAG, IF_NONE_MATCH},
+    StatusCode,
+};
+use serde_json::json;
+
+use transmission_proxy::testing::{MockUpstream, TestProxy, SESSION_ID_HEADER};
+
+mod common;
+use common::Harness;
+
+async fn poll(proxy: &TestProxy, upstream: &MockUpstream, etag: Option<&str>) -> reqwest::Response {
+    let mut req = reqwest::Client::new()
+        .post(proxy.rpc_url())
+        .basic_auth("alice", Some("password"))
+        .header(SESSION_ID_HEADER, upstream.session_id())
+        .json(&json!({
+            "method": "torrent-get",
+            "arguments": { "fields": ["id", "name", "downloadDir"] },
+        }));
+    if let Some(etag) = etag {
+        req = req.header(IF_NONE_MATCH, etag);
+    }
+
+    req.send().await.unwrap()
+}
+
+#[tokio::test]
+async fn unchanged_torrent_lists_are_not_sent_again() {
+    let (upstream, proxy) = Harness::new(
+        r#"
+acl:
+  rules:
+    - identities:
+        - provider: basic
+          name: alice
+      download_dir: /data/alice
+"#,
+    )
+    .users(&["alice"])
+    .start()
+    .await;
+
+    upstream.set_torrents(vec![
+        json!({ "id": 1, "name": "a", "downloadDir": "/data/alice" }),
+        json!({ "id": 2, "name": "b", "downloadDir": "/data/bob" }),
+    ]);
+
+    let response = poll(&proxy, &upstream, None).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let etag = response.headers()[ETAG].to_str().unwrap().to_owned();
+
+    let response = poll(&proxy, &upstream, Some(&etag)).await;
+    assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+
+    // Changes to torrents the user can't see don't matter
+    upstream.set_torrents(vec![
+        json!({ "id": 1, "name": "a", "downloadDir": "/data/alice" }),
+        json!({ "id": 2, "name": "c", "downloadDir": "/data/bob" }),
+    ]);
+    let response = poll(&proxy, &upstream, Some(&etag)).await;
+    assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+
+    upstream.set_torrents(vec![
+        json!({ "id": 1, "name": "d", "downloadDir": "/data/alice" }),
+    ]);
+    let response = poll(&proxy, &upstream, Some(&etag)).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_ne!(response.headers()[ETAG], etag.as_str());
+}
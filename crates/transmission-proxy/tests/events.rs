@@ -0,0 +1,90 @@
+use std::time::Duration;
+
+use reqwest::StatusCode;
+use serde_json::json;
+
+use transmission_proxy::testing::{MockUpstream, TestProxy};
+use transmission_rpc_client::types::MethodName;
+
+mod common;
+use common::{rpc, Harness};
+
+async fn setup() -> (MockUpstream, TestProxy) {
+    Harness::new(
+        r#"
+acl:
+  rules:
+    - identities:
+        - provider: basic
+          name: admin
+      admin: true
+    - identities:
+        - provider: basic
+          name: alice
+      download_dir: /data/alice
+"#,
+    )
+    .users(&["admin", "alice"])
+    .start()
+    .await
+}
+
+fn events(proxy: &TestProxy, user: &str) -> reqwest::RequestBuilder {
+    reqwest::Client::new()
+        .get(proxy.url() + "/admin/events")
+        .basic_auth(user, Some("password"))
+}
+
+/// Read the event stream until it contains all the given strings, or a few seconds passed
+async fn read_until(response: &mut reqwest::Response, needles: &[&str]) -> String {
+    let mut text = String::new();
+
+    let _ = tokio::time::timeout(Duration::from_secs(5), async {
+        while let Ok(Some(chunk)) = response.chunk().await {
+            text.push_str(&String::from_utf8_lossy(&chunk));
+            if needles.iter().all(|needle| text.contains(needle)) {
+                break;
+            }
+        }
+    })
+    .await;
+
+    text
+}
+
+#[tokio::test]
+async fn denials_and_added_torrents_are_streamed_to_admins() {
+    let (upstream, proxy) = setup().await;
+    upstream.respond(
+        MethodName::TorrentAdd,
+        json!({ "torrent-added": { "id": 7, "name": "ubuntu.iso", "hashString": "abcd" } }),
+    );
+
+    let mut stream = events(&proxy, "admin").send().await.unwrap();
+    assert_eq!(stream.status(), StatusCode::OK);
+    assert_eq!(stream.headers()["content-type"], "text/event-stream");
+
+    let add = |dir: &str| {
+        json!({
+            "method": "torrent-add",
+            "arguments": { "download-dir": dir, "metainfo": "", "paused": false },
+        })
+    };
+
+    let (status, _) = rpc(&proxy, Some("alice"), add("/data/bob")).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let (status, _) = rpc(&proxy, Some("alice"), add("/data/alice")).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let text = read_until(&mut stream, &["event:rpc_denied", "event:torrent_added"]).await;
+    assert!(text.contains(r#""user":"alice","code":"location-not-allowed""#));
+    assert!(text.contains(r#""user":"alice","id":7,"hash":"abcd","name":"ubuntu.iso""#));
+}
+
+#[tokio::test]
+async fn only_admins_get_the_event_stream() {
+    let (_upstream, proxy) = setup().await;
+
+    let response = events(&proxy, "alice").send().await.unwrap();
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+}
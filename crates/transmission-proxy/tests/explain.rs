@@ -0,0 +1,142 @@
+use reqwest::StatusCode;
+use serde_json::{json, Value};
+
+use transmission_proxy::testing::{MockUpstream, TestProxy, SESSION_ID_HEADER};
+use transmission_rpc_client::types::MethodCall;
+
+mod common;
+use common::Harness;
+
+async fn setup() -> (MockUpstream, TestProxy) {
+    Harness::new(
+        r#"
+acl:
+  rules:
+    - name: admins
+      identities:
+        - provider: basic
+          name: admin
+      admin: true
+    - name: alice
+      identities:
+        - provider: basic
+          name: alice
+      download_dir: /data/alice
+      allowed_methods: [torrent-get, torrent-start]
+"#,
+    )
+    .users(&["admin", "alice"])
+    .torrents(vec![
+        json!({ "id": 1, "downloadDir": "/data/alice" }),
+        json!({ "id": 2, "downloadDir": "/data/bob" }),
+    ])
+    .start()
+    .await
+}
+
+async fn explain(proxy: &TestProxy, request: Value) -> (StatusCode, Value) {
+    let response = reqwest::Client::new()
+        .post(proxy.url() + "/admin/explain")
+        .basic_auth("admin", Some("password"))
+        .json(&json!({
+            "identity": { "provider": "basic", "name": "alice" },
+            "request": request,
+        }))
+        .send()
+        .await
+        .unwrap();
+
+    (
+        response.status(),
+        response.json().await.unwrap_or(Value::Null),
+    )
+}
+
+fn checks(explanation: &Value) -> Vec<(String, bool)> {
+    explanation["decisions"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|decision| {
+            (
+                decision["check"].as_str().unwrap().to_owned(),
+                decision["allowed"].as_bool().unwrap(),
+            )
+        })
+        .collect()
+}
+
+#[tokio::test]
+async fn explains_rewritten_requests_without_forwarding() {
+    let (upstream, proxy) = setup().await;
+
+    let (status, explanation) = explain(
+        &proxy,
+        json!({ "method": "torrent-start", "arguments": { "ids": [1, 2] } }),
+    )
+    .await;
+
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(explanation["acl"], "alice");
+    assert_eq!(
+        checks(&explanation),
+        vec![
+            ("acl".to_owned(), true),
+            ("method".to_owned(), true),
+            ("torrent ids".to_owned(), true),
+        ]
+    );
+    assert_eq!(explanation["body"]["arguments"]["ids"], json!([1]));
+
+    assert!(!upstream
+        .requests()
+        .iter()
+        .any(|request| matches!(request.call, MethodCall::TorrentStart { .. })));
+}
+
+#[tokio::test]
+async fn explains_rejections() {
+    let (_upstream, proxy) = setup().await;
+
+    let (status, explanation) = explain(
+        &proxy,
+        json!({ "method": "torrent-remove", "arguments": { "ids": [1] } }),
+    )
+    .await;
+
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(explanation["status"], 403);
+    assert_eq!(
+        checks(&explanation),
+        vec![("acl".to_owned(), true), ("method".to_owned(), false)]
+    );
+}
+
+#[tokio::test]
+async fn explain_is_for_admins() {
+    let (_upstream, proxy) = setup().await;
+    let client = reqwest::Client::new();
+
+    let status = client
+        .post(proxy.url() + "/admin/explain")
+        .basic_auth("alice", Some("password"))
+        .json(&json!({ "request": { "method": "session-stats" } }))
+        .send()
+        .await
+        .unwrap()
+        .status();
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    // The explain header is ignored for other users
+    let response = client
+        .post(proxy.rpc_url())
+        .basic_auth("alice", Some("password"))
+        .header("X-Proxy-Explain", "1")
+        .header(SESSION_ID_HEADER, "mock-session-id")
+        .json(&json!({ "method": "torrent-get", "arguments": { "fields": ["id"] } }))
+        .send()
+        .await
+        .unwrap();
+    let body: Value = response.json().await.unwrap();
+    assert!(body.get("decisions").is_none());
+}
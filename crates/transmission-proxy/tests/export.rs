@@ -0,0 +1,107 @@
+use reqwest::StatusCode;
+use serde_json::{json, Value};
+
+use transmission_proxy::testing::{MockUpstream, TestProxy};
+
+mod common;
+use common::Harness;
+
+async fn setup() -> (MockUpstream, TestProxy) {
+    Harness::new(
+        r#"
+acl:
+  rules:
+    - identities:
+        - provider: basic
+          name: alice
+      download_dir: /data/alice
+    - identities:
+        - provider: basic
+          name: bob
+      deny: true
+"#,
+    )
+    .users(&["alice", "bob"])
+    .torrents(vec![
+        json!({ "id": 1, "name": "Plain", "downloadDir": "/data/alice", "totalSize": 1000 }),
+        json!({ "id": 2, "name": "Hidden", "downloadDir": "/data/carol", "totalSize": 2000 }),
+        json!({ "id": 3, "name": "Comma, \"quoted\"", "downloadDir": "/data/alice/tv", "totalSize": 3000 }),
+    ])
+    .start()
+    .await
+}
+
+async fn export(proxy: &TestProxy, user: &str, query: &str) -> reqwest::Response {
+    reqwest::Client::new()
+        .get(proxy.url() + "/export" + query)
+        .basic_auth(user, Some("password"))
+        .send()
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn torrents_are_exported_as_csv() {
+    let (_upstream, proxy) = setup().await;
+
+    let response = export(&proxy, "alice", "?format=csv&fields=id,name,totalSize").await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        response.headers()["content-type"],
+        "text/csv; charset=utf-8"
+    );
+    assert_eq!(
+        response.headers()["content-disposition"],
+        "attachment; filename=\"torrents.csv\""
+    );
+
+    // Only the torrents of the user are exported
+    assert_eq!(
+        response.text().await.unwrap(),
+        "id,name,totalSize\r\n1,Plain,1000\r\n3,\"Comma, \"\"quoted\"\"\",3000\r\n"
+    );
+}
+
+#[tokio::test]
+async fn torrents_are_exported_as_ndjson() {
+    let (_upstream, proxy) = setup().await;
+
+    let response = export(&proxy, "alice", "?fields=name,downloadDir").await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()["content-type"], "application/x-ndjson");
+
+    let lines: Vec<Value> = response
+        .text()
+        .await
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(
+        lines,
+        vec![
+            json!({ "name": "Plain", "downloadDir": "/data/alice" }),
+            json!({ "name": "Comma, \"quoted\"", "downloadDir": "/data/alice/tv" }),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn exports_need_access() {
+    let (_upstream, proxy) = setup().await;
+
+    assert_eq!(
+        export(&proxy, "bob", "").await.status(),
+        StatusCode::UNAUTHORIZED
+    );
+    assert_eq!(
+        export(&proxy, "alice", "?fields=name,../etc")
+            .await
+            .status(),
+        StatusCode::BAD_REQUEST
+    );
+    assert_eq!(
+        export(&proxy, "alice", "?format=xml").await.status(),
+        StatusCode::BAD_REQUEST
+    );
+}
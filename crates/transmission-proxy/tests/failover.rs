@@ -0,0 +1,61 @@
+use std::time::Duration;
+
+use reqwest::StatusCode;
+use serde_json::json;
+
+use transmission_proxy::testing::{MockUpstream, MockWebhook};
+
+mod common;
+use common::{rpc, torrent_ids, Harness};
+
+#[tokio::test]
+async fn fails_over_to_the_standby() {
+    let primary = MockUpstream::start().await.unwrap();
+    primary.set_torrents(vec![json!({ "id": 1, "downloadDir": "/data" })]);
+
+    let standby = MockUpstream::start().await.unwrap();
+    standby.set_torrents(vec![json!({ "id": 2, "downloadDir": "/data" })]);
+
+    let webhook = MockWebhook::start().await.unwrap();
+
+    let proxy = Harness::new(&format!(
+        r#"
+acl:
+  rules: []
+  default_policy: allow
+failover:
+  upstream: "{}"
+  after: 0
+  probe_interval: 1
+notifications:
+  webhooks:
+    - url: {}
+"#,
+        standby.uri(),
+        webhook.url()
+    ))
+    .start_proxy(primary.uri())
+    .await
+    .unwrap();
+    let torrent_get = json!({ "method": "torrent-get", "arguments": { "fields": ["id"] } });
+
+    let (status, response) = rpc(&proxy, None, torrent_get.clone()).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(torrent_ids(&response), vec![1]);
+
+    drop(primary);
+
+    for _ in 0..50 {
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        let (status, response) = rpc(&proxy, None, torrent_get.clone()).await;
+        if status == StatusCode::OK && torrent_ids(&response) == vec![2] {
+            // Administrators are told about it
+            let received = webhook.wait_for(1).await;
+            assert_eq!(received[0].body["event"], "failed_over");
+            return;
+        }
+    }
+
+    panic!("the proxy did not fail over to the standby");
+}
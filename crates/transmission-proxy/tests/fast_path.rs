@@ -0,0 +1,105 @@
+use reqwest::{header::ACCEPT_ENCODING, StatusCode};
+use serde_json::{json, Value};
+
+use transmission_proxy::testing::{MockUpstream, TestProxy, SESSION_ID_HEADER};
+
+mod common;
+use common::{torrent_ids, Harness};
+
+async fn setup(fast_path: bool) -> (MockUpstream, TestProxy) {
+    Harness::new(&format!(
+        r#"
+acl:
+  fast_path: {fast_path}
+  rules:
+    - identities:
+        - provider: basic
+          name: admin
+      admin: true
+      allow_dangerous_methods: true
+    - identities:
+        - provider: basic
+          name: alice
+      download_dir: /data/alice
+"#
+    ))
+    .users(&["admin", "alice"])
+    .torrents(vec![
+        json!({ "id": 1, "downloadDir": "/data/alice" }),
+        json!({ "id": 2, "downloadDir": "/data/bob" }),
+    ])
+    .start()
+    .await
+}
+
+async fn torrent_get(proxy: &TestProxy, upstream: &MockUpstream, user: &str) -> Vec<i64> {
+    let response = reqwest::Client::new()
+        .post(proxy.rpc_url())
+        .basic_auth(user, Some("password"))
+        .header(SESSION_ID_HEADER, upstream.session_id())
+        .header(ACCEPT_ENCODING, "gzip")
+        .json(&json!({ "method": "torrent-get", "arguments": { "fields": ["id"] } }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    torrent_ids(&response.json().await.unwrap())
+}
+
+#[tokio::test]
+async fn unrestricted_calls_are_forwarded_untouched() {
+    let (upstream, proxy) = setup(true).await;
+
+    assert_eq!(torrent_get(&proxy, &upstream, "admin").await, vec![1, 2]);
+    assert_eq!(upstream.last_headers()[ACCEPT_ENCODING], "gzip");
+
+    // Restricted users still go through the filters
+    assert_eq!(torrent_get(&proxy, &upstream, "alice").await, vec![1]);
+    assert!(!upstream.last_headers().contains_key(ACCEPT_ENCODING));
+}
+
+#[tokio::test]
+async fn fast_path_is_opt_in() {
+    let (upstream, proxy) = setup(false).await;
+
+    assert_eq!(torrent_get(&proxy, &upstream, "admin").await, vec![1, 2]);
+    assert!(!upstream.last_headers().contains_key(ACCEPT_ENCODING));
+}
+
+#[tokio::test]
+async fn fast_path_calls_are_measured_and_faulted() {
+    let (upstream, proxy) = setup(true).await;
+    let client = reqwest::Client::new();
+
+    torrent_get(&proxy, &upstream, "admin").await;
+    let latency: Value = client
+        .get(proxy.url() + "/admin/latency")
+        .basic_auth("admin", Some("password"))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(latency["fast-path"]["count"], json!(1), "{latency}");
+
+    client
+        .put(proxy.url() + "/admin/faults")
+        .basic_auth("admin", Some("password"))
+        .json(&json!({ "error_percent": 100 }))
+        .send()
+        .await
+        .unwrap();
+    let calls = upstream.requests().len();
+    let response = client
+        .post(proxy.rpc_url())
+        .basic_auth("admin", Some("password"))
+        .header(SESSION_ID_HEADER, upstream.session_id())
+        .json(&json!({ "method": "torrent-get", "arguments": { "fields": ["id"] } }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    assert_eq!(upstream.requests().len(), calls);
+}
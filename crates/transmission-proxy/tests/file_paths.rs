@@ -0,0 +1,78 @@
+use base64::Engine;
+use reqwest::StatusCode;
+use serde_json::json;
+
+use transmission_proxy::testing::{MockUpstream, TestProxy};
+use transmission_rpc_client::types::MethodCall;
+
+mod common;
+use common::{rpc, Harness};
+
+async fn setup() -> (MockUpstream, TestProxy) {
+    Harness::new(
+        r#"
+acl:
+  rules:
+    - identities:
+        - provider: basic
+          name: alice
+      download_dir: /data/alice
+      deny_hidden_files: true
+"#,
+    )
+    .users(&["alice"])
+    .start()
+    .await
+}
+
+/// Add a multi-file torrent containing a single file with the given bencoded path
+async fn add_torrent(proxy: &TestProxy, path: &str) -> StatusCode {
+    let metainfo = format!(
+        "d4:infod5:filesld6:lengthi1e4:pathl{path}eee4:name1:a12:piece lengthi16384e6:pieces0:ee"
+    );
+
+    let (status, _) = rpc(
+        proxy,
+        Some("alice"),
+        json!({
+            "method": "torrent-add",
+            "arguments": {
+                "download-dir": "/data/alice",
+                "metainfo": base64::engine::general_purpose::STANDARD.encode(metainfo),
+                "paused": false,
+            },
+        }),
+    )
+    .await;
+
+    status
+}
+
+#[tokio::test]
+async fn safe_paths_are_forwarded() {
+    let (upstream, proxy) = setup().await;
+
+    assert_eq!(add_torrent(&proxy, "3:sub4:file").await, StatusCode::OK);
+    assert!(upstream
+        .requests()
+        .iter()
+        .any(|request| matches!(request.call, MethodCall::TorrentAdd { .. })));
+}
+
+#[tokio::test]
+async fn unsafe_paths_are_rejected() {
+    let (upstream, proxy) = setup().await;
+
+    for path in ["2:..4:file", "5:/file", "0:4:file", "7:.hidden"] {
+        assert_eq!(
+            add_torrent(&proxy, path).await,
+            StatusCode::BAD_REQUEST,
+            "{path}"
+        );
+    }
+
+    assert!(!upstream
+        .requests()
+        .iter()
+        .any(|request| matches!(request.call, MethodCall::TorrentAdd { .. })));
+}
@@ -0,0 +1,132 @@
+use reqwest::StatusCode;
+use serde_json::{json, Value};
+
+use transmission_proxy::testing::{MockUpstream, TestProxy};
+use transmission_rpc_client::types::MethodCall;
+
+mod common;
+use common::{rpc, Harness};
+
+async fn setup() -> (MockUpstream, TestProxy) {
+    Harness::new(
+        r#"
+acl:
+  rules:
+    - identities:
+        - provider: basic
+          name: alice
+      download_dir: /data/alice
+"#,
+    )
+    .users(&["alice"])
+    .torrents(vec![
+        json!({
+            "id": 1,
+            "name": "movie",
+            "downloadDir": "/data/alice",
+            "files": [
+                { "bytesCompleted": 1, "length": 1, "name": "movie/movie.mkv" },
+                { "bytesCompleted": 2, "length": 2, "name": "movie/../../bob/secret.txt" },
+            ],
+            "fileStats": [
+                { "bytesCompleted": 1, "wanted": true, "priority": 0 },
+                { "bytesCompleted": 2, "wanted": true, "priority": 1 },
+            ],
+            "priorities": [0, 1],
+        }),
+        json!({ "id": 2, "name": "series", "downloadDir": "/data/alice/" }),
+    ])
+    .start()
+    .await
+}
+
+async fn rename(proxy: &TestProxy, path: &str, name: &str) -> StatusCode {
+    rpc(
+        proxy,
+        Some("alice"),
+        json!({
+            "method": "torrent-rename-path",
+            "arguments": { "ids": [1], "path": path, "name": name },
+        }),
+    )
+    .await
+    .0
+}
+
+fn forwarded_renames(upstream: &MockUpstream) -> Vec<Value> {
+    upstream
+        .requests()
+        .into_iter()
+        .filter_map(|request| match request.call {
+            MethodCall::TorrentRenamePath { arguments } => {
+                Some(json!({ "path": arguments.path, "name": arguments.name }))
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+#[tokio::test]
+async fn files_outside_download_dir_are_hidden() {
+    let (_upstream, proxy) = setup().await;
+
+    let (status, response) = rpc(
+        &proxy,
+        Some("alice"),
+        json!({ "method": "torrent-get", "arguments": { "ids": [1], "fields": ["id", "downloadDir", "fileStats", "priorities"] } }),
+    )
+    .await;
+
+    assert_eq!(status, StatusCode::OK);
+
+    // Hidden files keep their index, which torrent-set calls refer to
+    let torrent = &response["arguments"]["torrents"][0];
+    assert_eq!(
+        torrent["files"],
+        json!([
+            { "bytesCompleted": 1, "length": 1, "name": "movie/movie.mkv" },
+            { "bytesCompleted": 0, "length": 0, "name": "[hidden]" },
+        ])
+    );
+    assert_eq!(
+        torrent["fileStats"],
+        json!([
+            { "bytesCompleted": 1, "wanted": true, "priority": 0 },
+            { "bytesCompleted": 0, "wanted": true, "priority": 1 },
+        ])
+    );
+    assert_eq!(torrent["priorities"], json!([0, 1]));
+}
+
+#[tokio::test]
+async fn renames_are_sandboxed() {
+    let (upstream, proxy) = setup().await;
+
+    assert_eq!(
+        rename(&proxy, "movie", "../bob").await,
+        StatusCode::FORBIDDEN
+    );
+    assert_eq!(rename(&proxy, "movie", "a/b").await, StatusCode::FORBIDDEN);
+    assert_eq!(
+        rename(&proxy, "../bob/movie", "film").await,
+        StatusCode::FORBIDDEN
+    );
+    assert_eq!(
+        rename(&proxy, "movie", "series").await,
+        StatusCode::FORBIDDEN
+    );
+    assert!(forwarded_renames(&upstream).is_empty());
+
+    assert_eq!(rename(&proxy, "movie", "film").await, StatusCode::OK);
+    assert_eq!(
+        rename(&proxy, "movie/movie.mkv", "series").await,
+        StatusCode::OK
+    );
+    assert_eq!(
+        forwarded_renames(&upstream),
+        vec![
+            json!({ "path": "movie", "name": "film" }),
+            json!({ "path": "movie/movie.mkv", "name": "series" }),
+        ]
+    );
+}
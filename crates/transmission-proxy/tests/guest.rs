@@ -0,0 +1,96 @@
+use reqwest::{header::WWW_AUTHENTICATE, StatusCode};
+use serde_json::json;
+
+use transmission_proxy::testing::{MockUpstream, TestProxy};
+
+mod common;
+use common::{rpc, torrent_ids, Harness};
+
+async fn setup() -> (MockUpstream, TestProxy) {
+    Harness::new(
+        r#"
+acl:
+  rules:
+    - identities:
+        - provider: basic
+          name: admin
+    - read_only: true
+"#,
+    )
+    .users(&["admin"])
+    .torrents(vec![json!({ "id": 1, "downloadDir": "/data" })])
+    .start()
+    .await
+}
+
+#[tokio::test]
+async fn guests_can_read() {
+    let (_upstream, proxy) = setup().await;
+
+    let (status, response) = rpc(
+        &proxy,
+        None,
+        json!({ "method": "torrent-get", "arguments": { "fields": ["id"] } }),
+    )
+    .await;
+
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(torrent_ids(&response), vec![1]);
+}
+
+#[tokio::test]
+async fn guests_must_login_to_modify() {
+    let (upstream, proxy) = setup().await;
+    let start = json!({ "method": "torrent-start", "arguments": { "ids": [1] } });
+
+    let (status, _) = rpc(&proxy, None, start.clone()).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    assert!(upstream.requests().is_empty());
+
+    let (status, _) = rpc(&proxy, Some("admin"), start).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(upstream.requests().len(), 1);
+}
+
+#[tokio::test]
+async fn login_challenge_is_sent_to_guests() {
+    let (_upstream, proxy) = setup().await;
+
+    let response = reqwest::Client::new()
+        .post(proxy.rpc_url())
+        .header("X-Transmission-Session-Id", "mock-session-id")
+        .json(&json!({ "method": "blocklist-update" }))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    assert!(response.headers().contains_key(WWW_AUTHENTICATE));
+}
+
+#[tokio::test]
+async fn guests_see_a_banner() {
+    let (_upstream, proxy) = setup().await;
+
+    let page = reqwest::get(proxy.url() + "/web/")
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert!(page.starts_with(
+        "<html><head><title>Transmission</title></head><body class=\"mock\"><div id=\"transmission-proxy-guest-banner\""
+    ));
+    assert!(page.contains("/transmission/login?redirect_to=%2Ftransmission%2Fweb%2F"));
+
+    let page = reqwest::Client::new()
+        .get(proxy.url() + "/web/")
+        .basic_auth("admin", Some("password"))
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert!(!page.contains("guest-banner"));
+}
@@ -0,0 +1,66 @@
+use serde_json::Value;
+
+use transmission_proxy::testing::MockUpstream;
+
+mod common;
+use common::Harness;
+
+#[tokio::test]
+async fn headers_follow_the_policy() {
+    let (_upstream, proxy) = Harness::new(
+        r#"
+headers:
+  strip_forwarded: true
+  via: 1.1 transmission-proxy
+  request:
+    drop: [X-Debug]
+    set:
+      X-Custom: custom
+  response:
+    drop: [X-Mock]
+    set:
+      X-Served-By: transmission-proxy
+acl:
+  default_policy: allow
+  rules: []
+"#,
+    )
+    .start()
+    .await;
+
+    let response = reqwest::Client::new()
+        .get(proxy.url() + "/web/headers")
+        .header("X-Forwarded-For", "203.0.113.1")
+        .header("Forwarded", "for=203.0.113.1")
+        .header("X-Debug", "1")
+        .send()
+        .await
+        .unwrap();
+
+    assert!(response.headers().get("x-mock").is_none());
+    assert_eq!(response.headers()["x-served-by"], "transmission-proxy");
+
+    let headers: Value = response.json().await.unwrap();
+    assert!(headers.get("x-forwarded-for").is_none());
+    assert!(headers.get("forwarded").is_none());
+    assert!(headers.get("x-debug").is_none());
+    assert_eq!(headers["x-custom"], "custom");
+    assert_eq!(headers["via"], "1.1 transmission-proxy");
+}
+
+#[tokio::test]
+async fn session_id_header_is_preserved() {
+    let upstream = MockUpstream::start().await.unwrap();
+    assert!(Harness::new(
+        r#"
+headers:
+  request:
+    drop: [X-Transmission-Session-Id]
+acl:
+  rules: []
+"#
+    )
+    .start_proxy(upstream.uri())
+    .await
+    .is_err());
+}
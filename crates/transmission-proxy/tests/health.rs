@@ -0,0 +1,55 @@
+use reqwest::StatusCode;
+use serde_json::{json, Value};
+
+use transmission_rpc_client::types::MethodName;
+
+mod common;
+use common::Harness;
+
+const CONFIG: &str = r#"
+acl:
+  rules: []
+"#;
+
+fn session_stats(torrent_count: i32) -> Value {
+    let stats = json!({
+        "uploadedBytes": 0,
+        "downloadedBytes": 0,
+        "filesAdded": 0,
+        "sessionCount": 1,
+        "secondsActive": 0,
+    });
+
+    json!({
+        "activeTorrentCount": 0,
+        "downloadSpeed": 0,
+        "pausedTorrentCount": 0,
+        "torrentCount": torrent_count,
+        "uploadSpeed": 0,
+        "cumulative-stats": stats,
+        "current-stats": stats,
+    })
+}
+
+#[tokio::test]
+async fn readyz_reports_upstream_stats() {
+    let (upstream, proxy) = Harness::new(CONFIG).start().await;
+    upstream.respond(MethodName::SessionStats, session_stats(3));
+
+    let response = reqwest::get(proxy.origin() + "/readyz").await.unwrap();
+
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        response.json::<Value>().await.unwrap(),
+        json!({ "upstream": "ok", "torrents": 3 })
+    );
+}
+
+#[tokio::test]
+async fn readyz_fails_on_invalid_upstream_response() {
+    let (_upstream, proxy) = Harness::new(CONFIG).start().await;
+
+    let response = reqwest::get(proxy.origin() + "/readyz").await.unwrap();
+
+    assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+}
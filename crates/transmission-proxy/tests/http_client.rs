@@ -0,0 +1,49 @@
+use transmission_proxy::testing::{MockUpstream, TestProxy};
+
+mod common;
+use common::Harness;
+
+async fn start(http_client: &str) -> color_eyre::eyre::Result<TestProxy> {
+    let upstream = MockUpstream::start().await.unwrap();
+    Harness::new(&format!("acl:\n  rules: []\nhttp_client:\n{http_client}"))
+        .start_proxy(upstream.uri())
+        .await
+}
+
+#[tokio::test]
+async fn invalid_ca_bundles_are_rejected() {
+    let err = start("  ca_bundle: /nonexistent/ca.pem\n")
+        .await
+        .err()
+        .expect("proxy started");
+    assert!(err.to_string().contains("/nonexistent/ca.pem"));
+
+    let empty = std::env::temp_dir().join("transmission-proxy-empty-ca.pem");
+    std::fs::write(&empty, "").unwrap();
+
+    let err = start(&format!("  ca_bundle: {}\n", empty.display()))
+        .await
+        .err()
+        .expect("proxy started");
+    assert!(err.to_string().starts_with("no certificate in"));
+}
+
+#[tokio::test]
+async fn certificate_verification_can_be_disabled() {
+    start("  danger_accept_invalid_certs: true\n")
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn outbound_proxies_are_validated() {
+    start("  proxy: socks5h://proxy.internal:1080\n  no_proxy: [.internal]\n")
+        .await
+        .unwrap();
+
+    let err = start("  proxy: ftp://proxy.internal\n")
+        .await
+        .err()
+        .expect("proxy started");
+    assert!(err.to_string().starts_with("unsupported proxy scheme"));
+}
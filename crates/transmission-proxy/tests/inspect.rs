@@ -0,0 +1,100 @@
+use reqwest::StatusCode;
+use serde_json::{json, Value};
+
+use transmission_proxy::testing::{MockUpstream, TestProxy};
+
+mod common;
+use common::Harness;
+
+async fn setup() -> (MockUpstream, TestProxy) {
+    Harness::new(
+        r#"
+acl:
+  rules:
+    - identities:
+        - provider: basic
+          name: alice
+      tracker_rules:
+        - from: "^http://"
+          to: "https://"
+    - deny: true
+"#,
+    )
+    .users(&["alice"])
+    .start()
+    .await
+}
+
+async fn inspect(
+    proxy: &TestProxy,
+    user: Option<&str>,
+    body: &'static [u8],
+) -> (StatusCode, Value) {
+    let mut req = reqwest::Client::new()
+        .post(proxy.url() + "/inspect")
+        .body(body);
+    if let Some(user) = user {
+        req = req.basic_auth(user, Some("password"));
+    }
+
+    let res = req.send().await.unwrap();
+    (res.status(), res.json().await.unwrap_or(Value::Null))
+}
+
+#[tokio::test]
+async fn torrent_files_are_inspected() {
+    let (upstream, proxy) = setup().await;
+
+    let (status, metadata) = inspect(
+        &proxy,
+        Some("alice"),
+        concat!(
+            "d8:announce14:http://a/annou4:infod6:lengthi5e4:name5:a.txt12:piece lengthi16384e",
+            "6:pieces0:7:privatei1e6:source4:testee"
+        )
+        .as_bytes(),
+    )
+    .await;
+
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(
+        metadata,
+        json!({
+            "name": "a.txt",
+            "info_hash": "42fffd36319b18cd37224a8b5f0dc899d6a51c07",
+            "size": 5,
+            "files": [{ "path": "a.txt", "length": 5 }],
+            "private": true,
+            "trackers": ["https://a/annou"],
+        })
+    );
+
+    // Nothing is added
+    assert!(upstream.requests().is_empty());
+}
+
+#[tokio::test]
+async fn magnet_links_are_inspected() {
+    let (_upstream, proxy) = setup().await;
+
+    let (status, metadata) = inspect(
+        &proxy,
+        Some("alice"),
+        b"magnet:?xt=urn:btih:C7ECD0FEC7BD18D3D924B5C111A22D6B9423FB49&dn=a.txt&tr=http%3A%2F%2Fa%2Fannounce",
+    )
+    .await;
+
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(
+        metadata["info_hash"],
+        "c7ecd0fec7bd18d3d924b5c111a22d6b9423fb49"
+    );
+    assert_eq!(metadata["name"], "a.txt");
+    assert_eq!(metadata["trackers"], json!(["https://a/announce"]));
+
+    let (status, _) = inspect(&proxy, Some("alice"), b"not a torrent").await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    let (status, _) = inspect(&proxy, None, b"magnet:?xt=urn:btih:00").await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+}
@@ -0,0 +1,99 @@
+use reqwest::StatusCode;
+use serde_json::{json, Value};
+
+use transmission_proxy::testing::{MockUpstream, TestProxy, SESSION_ID_HEADER};
+
+mod common;
+use common::Harness;
+
+async fn setup(latency: &str) -> (MockUpstream, TestProxy) {
+    Harness::new(&format!(
+        r#"
+latency:
+{latency}
+acl:
+  rules:
+    - identities:
+        - provider: basic
+          name: admin
+      admin: true
+    - identities:
+        - provider: basic
+          name: alice
+      download_dir: /data/alice
+"#
+    ))
+    .users(&["admin", "alice"])
+    .start()
+    .await
+}
+
+#[tokio::test]
+async fn latency_is_tracked_per_method() {
+    let (upstream, proxy) = setup("  samples: 10\n  slow_threshold: 0").await;
+    let client = reqwest::Client::new();
+
+    for _ in 0..3 {
+        let response = client
+            .post(proxy.rpc_url())
+            .basic_auth("alice", Some("password"))
+            .header(SESSION_ID_HEADER, upstream.session_id())
+            .json(&json!({ "method": "torrent-get", "arguments": { "fields": ["id"] } }))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    let latency: Value = client
+        .get(proxy.url() + "/admin/latency")
+        .basic_auth("admin", Some("password"))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let torrent_get = &latency["torrent-get"];
+    assert!(torrent_get["count"].as_u64().unwrap() >= 3, "{latency}");
+    for field in ["p50", "p95", "p99", "max"] {
+        assert!(torrent_get[field].as_f64().unwrap() >= 0., "{latency}");
+    }
+
+    let response = client
+        .get(proxy.url() + "/admin/metrics")
+        .basic_auth("admin", Some("password"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let metrics = response.text().await.unwrap();
+    assert!(metrics.contains(
+        "transmission_proxy_upstream_latency_seconds{method=\"torrent-get\",quantile=\"0.99\"}"
+    ));
+    assert!(metrics.contains("# TYPE transmission_proxy_upstream_latency_seconds summary"));
+
+    // Only admins see the latency of the upstream
+    for path in ["/admin/latency", "/admin/metrics"] {
+        let response = client
+            .get(proxy.url() + path)
+            .basic_auth("alice", Some("password"))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN, "{path}");
+    }
+}
+
+#[tokio::test]
+async fn latency_tracking_can_be_disabled() {
+    let (_upstream, proxy) = setup("  enabled: false").await;
+
+    let response = reqwest::Client::new()
+        .get(proxy.url() + "/admin/latency")
+        .basic_auth("admin", Some("password"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
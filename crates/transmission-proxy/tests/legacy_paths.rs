@@ -0,0 +1,124 @@
+use base64::Engine;
+use reqwest::{
+    header::{CONTENT_TYPE, LOCATION},
+    redirect::Policy,
+    StatusCode,
+};
+
+use serde_json::json;
+use transmission_proxy::testing::{MockUpstream, TestProxy};
+use transmission_rpc_client::types::{MethodCall, MethodName};
+
+mod common;
+use common::Harness;
+
+const METAINFO: &str = "d4:infod6:lengthi1e4:name1:a12:piece lengthi16384e6:pieces0:ee";
+
+async fn setup() -> (MockUpstream, TestProxy) {
+    Harness::new(
+        r#"
+legacy_paths:
+  prefix: /legacy
+acl:
+  rules:
+    - identities:
+        - provider: basic
+          name: alice
+"#,
+    )
+    .users(&["alice"])
+    .start()
+    .await
+}
+
+fn client() -> reqwest::Client {
+    reqwest::Client::builder()
+        .redirect(Policy::none())
+        .build()
+        .unwrap()
+}
+
+#[tokio::test]
+async fn legacy_paths_are_redirected_to_the_web_interface() {
+    let (_upstream, proxy) = setup().await;
+
+    for (path, location) in [
+        ("/legacy/web", "/transmission/web/"),
+        ("/legacy/web/", "/transmission/web/"),
+        (
+            "/legacy/web/index.html?lang=fr",
+            "/transmission/web/index.html?lang=fr",
+        ),
+        ("/favicon.ico", "/transmission/web/images/favicon.ico"),
+    ] {
+        let response = client().get(proxy.origin() + path).send().await.unwrap();
+        assert_eq!(response.status(), StatusCode::PERMANENT_REDIRECT, "{path}");
+        assert_eq!(response.headers()[LOCATION], location, "{path}");
+    }
+}
+
+#[tokio::test]
+async fn uploads_are_added_through_the_acl() {
+    let (upstream, proxy) = setup().await;
+    upstream.respond(
+        MethodName::SessionGet,
+        json!({ "download-dir": "/downloads" }),
+    );
+
+    let body = format!(
+        "--boundary\r\n\
+         Content-Disposition: form-data; name=\"torrent_files[]\"; filename=\"a.torrent\"\r\n\
+         Content-Type: application/x-bittorrent\r\n\
+         \r\n\
+         {METAINFO}\r\n\
+         --boundary--\r\n"
+    );
+
+    for url in [
+        proxy.origin() + "/legacy/upload?paused=true",
+        proxy.url() + "/upload?paused=true",
+    ] {
+        let response = client()
+            .post(&url)
+            .basic_auth("alice", Some("password"))
+            .header(CONTENT_TYPE, "multipart/form-data; boundary=boundary")
+            .body(body.clone())
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK, "{url}");
+        assert_eq!(
+            response.headers()[CONTENT_TYPE],
+            "text/html; charset=UTF-8",
+            "{url}"
+        );
+    }
+
+    let adds: Vec<_> = upstream
+        .requests()
+        .into_iter()
+        .filter_map(|request| match request.call {
+            MethodCall::TorrentAdd { arguments } => Some(arguments),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(adds.len(), 2);
+    for add in adds {
+        assert_eq!(
+            add.metainfo,
+            base64::engine::general_purpose::STANDARD.encode(METAINFO)
+        );
+        assert!(add.paused.as_bool());
+        assert_eq!(add.download_dir, "/downloads");
+    }
+
+    // Anonymous users can't add torrents
+    let response = client()
+        .post(proxy.url() + "/upload")
+        .header(CONTENT_TYPE, "multipart/form-data; boundary=boundary")
+        .body(body)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+}
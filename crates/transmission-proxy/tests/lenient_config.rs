@@ -0,0 +1,97 @@
+use reqwest::StatusCode;
+use serde_json::{json, Value};
+
+use transmission_proxy::testing::{MockUpstream, TestProxy};
+
+mod common;
+use common::{rpc, Harness};
+
+fn harness() -> Harness {
+    Harness::new(
+        r#"
+tracker_rule_sets:
+  broken:
+    - from: "("
+      to: ""
+acl:
+  rules:
+    - name: admins
+      identities:
+        - provider: basic
+          name: admin
+      admin: true
+    - name: alice
+      identities:
+        - provider: basic
+          name: alice
+      tracker_rules:
+        - from: "[unclosed"
+          to: ""
+    - name: bob
+      identities:
+        - provider: basic
+          name: bob
+      tracker_rules: [broken]
+    - name: carol
+      identities:
+        - provider: basic
+          name: carol
+      download_dir: /data
+"#,
+    )
+    .users(&["admin", "alice", "bob", "carol"])
+}
+
+async fn session_stats(proxy: &TestProxy, user: &str) -> StatusCode {
+    rpc(proxy, Some(user), json!({ "method": "session-stats" }))
+        .await
+        .0
+}
+
+#[tokio::test]
+async fn invalid_configs_are_fatal_by_default() {
+    let upstream = MockUpstream::start().await.unwrap();
+    assert!(harness().start_proxy(upstream.uri()).await.is_err());
+}
+
+#[tokio::test]
+async fn invalid_acls_deny_access_in_lenient_mode() {
+    let (_upstream, proxy) = harness().args(&["--lenient-config"]).start().await;
+
+    // The users of invalid ACLs are denied instead of falling through to other rules
+    assert_eq!(
+        session_stats(&proxy, "alice").await,
+        StatusCode::UNAUTHORIZED
+    );
+    assert_eq!(session_stats(&proxy, "bob").await, StatusCode::UNAUTHORIZED);
+    assert_eq!(session_stats(&proxy, "carol").await, StatusCode::OK);
+
+    let response = reqwest::Client::new()
+        .get(proxy.url() + "/admin/config")
+        .basic_auth("admin", Some("password"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let state: Value = response.json().await.unwrap();
+    assert_eq!(state["lenient"], true);
+    let skipped: Vec<_> = state["skipped"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|problem| problem.as_str().unwrap().to_owned())
+        .collect();
+    assert_eq!(skipped.len(), 3, "{skipped:?}");
+    assert!(skipped[0].starts_with("ACL 1:"));
+    assert!(skipped[1].starts_with("tracker rule set broken:"));
+    assert!(skipped[2].contains("bob"));
+
+    let response = reqwest::Client::new()
+        .get(proxy.url() + "/admin/config")
+        .basic_auth("carol", Some("password"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+}
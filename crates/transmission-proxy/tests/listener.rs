@@ -0,0 +1,116 @@
+use reqwest::{StatusCode, Version};
+
+use transmission_proxy::testing::TestProxy;
+
+mod common;
+use common::Harness;
+
+async fn login_page(proxy: &TestProxy) -> reqwest::Result<reqwest::Response> {
+    reqwest::Client::builder()
+        .http2_prior_knowledge()
+        .build()
+        .unwrap()
+        .get(proxy.url() + "/login")
+        .send()
+        .await
+}
+
+#[tokio::test]
+async fn http2_prior_knowledge_is_supported() {
+    let (_upstream, proxy) = Harness::new(
+        r#"
+acl:
+  rules: []
+listener:
+  http2_max_concurrent_streams: 16
+  http2_keep_alive_interval: 30
+  tcp_keep_alive: 60
+"#,
+    )
+    .start()
+    .await;
+
+    let response = login_page(&proxy).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.version(), Version::HTTP_2);
+}
+
+#[tokio::test]
+async fn http2_can_be_disabled() {
+    let (_upstream, proxy) = Harness::new(
+        r#"
+acl:
+  rules: []
+listener:
+  http2: false
+"#,
+    )
+    .start()
+    .await;
+
+    assert!(login_page(&proxy).await.is_err());
+
+    let response = reqwest::get(proxy.url() + "/login").await.unwrap();
+    assert_eq!(response.version(), Version::HTTP_11);
+}
+
+#[tokio::test]
+async fn cached_acme_certificate_is_served() {
+    let cache_dir =
+        std::env::temp_dir().join(format!("transmission-proxy-acme-{}", std::process::id()));
+    std::fs::create_dir_all(&cache_dir).unwrap();
+
+    let cert = rcgen::generate_simple_self_signed(vec!["localhost".to_owned()]).unwrap();
+    let cert_pem = cert.serialize_pem().unwrap();
+    std::fs::write(cache_dir.join("cert.pem"), &cert_pem).unwrap();
+    std::fs::write(cache_dir.join("key.pem"), cert.serialize_private_key_pem()).unwrap();
+
+    let (_upstream, proxy) = Harness::new(&format!(
+        r#"
+acl:
+  rules: []
+listener:
+  acme:
+    domains:
+      - localhost
+    directory: https://127.0.0.1:1/directory
+    cache_dir: {}
+"#,
+        cache_dir.display()
+    ))
+    .start()
+    .await;
+
+    let addr = proxy
+        .origin()
+        .trim_start_matches("http://")
+        .parse()
+        .unwrap();
+    let client = reqwest::Client::builder()
+        .add_root_certificate(reqwest::Certificate::from_pem(cert_pem.as_bytes()).unwrap())
+        .resolve("localhost", addr)
+        .build()
+        .unwrap();
+
+    // The certificate is loaded in the background
+    let mut response = None;
+    for _ in 0..50 {
+        match client
+            .get(format!(
+                "https://localhost:{}/transmission/login",
+                addr.port()
+            ))
+            .send()
+            .await
+        {
+            Ok(ok) => {
+                response = Some(ok);
+                break;
+            }
+            Err(_) => tokio::time::sleep(std::time::Duration::from_millis(20)).await,
+        }
+    }
+
+    assert_eq!(response.unwrap().status(), StatusCode::OK);
+    std::fs::remove_dir_all(cache_dir).ok();
+}
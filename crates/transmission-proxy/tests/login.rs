@@ -0,0 +1,106 @@
+use reqwest::{header::LOCATION, redirect::Policy, StatusCode};
+
+use transmission_proxy::testing::{MockUpstream, TestProxy};
+
+mod common;
+use common::Harness;
+
+async fn setup() -> (MockUpstream, TestProxy) {
+    Harness::new(
+        r#"
+acl:
+  rules:
+    - identities:
+        - provider: basic
+          name: alice
+    - deny: true
+"#,
+    )
+    .users(&["alice"])
+    .start()
+    .await
+}
+
+fn client() -> reqwest::Client {
+    reqwest::Client::builder()
+        .redirect(Policy::none())
+        .build()
+        .unwrap()
+}
+
+async fn login_redirect(proxy: &TestProxy, redirect_to: &str) -> String {
+    let response = client()
+        .get(proxy.url() + "/auth/basic")
+        .query(&[("redirect_to", redirect_to)])
+        .basic_auth("alice", Some("password"))
+        .send()
+        .await
+        .unwrap();
+
+    assert!(response.status().is_redirection());
+    response.headers()[LOCATION].to_str().unwrap().to_owned()
+}
+
+#[tokio::test]
+async fn redirects_stay_on_the_proxy() {
+    let (_upstream, proxy) = setup().await;
+
+    for target in [
+        "https://evil.example/transmission/web/",
+        "//evil.example/transmission/web/",
+        "/\\evil.example/transmission/web/",
+        "/elsewhere",
+        "web/",
+    ] {
+        assert_eq!(
+            login_redirect(&proxy, target).await,
+            "/transmission/web/",
+            "{target}"
+        );
+    }
+
+    assert_eq!(
+        login_redirect(&proxy, "/transmission/web/?page=1#torrents").await,
+        "/transmission/web/?page=1#torrents"
+    );
+    assert_eq!(
+        login_redirect(&proxy, &(proxy.url() + "/web/index.html")).await,
+        "/transmission/web/index.html"
+    );
+}
+
+#[tokio::test]
+async fn deep_links_are_preserved() {
+    let (_upstream, proxy) = setup().await;
+
+    let response = client()
+        .get(proxy.url() + "/web/?page=1")
+        .send()
+        .await
+        .unwrap();
+
+    assert!(response.status().is_redirection());
+    assert_eq!(
+        response.headers()[LOCATION],
+        "/transmission/login?redirect_to=%2Ftransmission%2Fweb%2F%3Fpage%3D1"
+    );
+}
+
+#[tokio::test]
+async fn login_page_accepts_posted_targets() {
+    let (_upstream, proxy) = setup().await;
+
+    let response = client()
+        .post(proxy.url() + "/login")
+        .form(&[("redirect_to", "/transmission/web/#torrents")])
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(response
+        .text()
+        .await
+        .unwrap()
+        .contains("auth/basic?redirect_to=%2Ftransmission%2Fweb%2F%23torrents"));
+}
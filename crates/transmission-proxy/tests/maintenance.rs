@@ -0,0 +1,95 @@
+use reqwest::StatusCode;
+use serde_json::json;
+
+use transmission_proxy::testing::{MockUpstream, TestProxy};
+
+mod common;
+use common::{rpc, Harness};
+
+async fn setup(enabled: bool) -> (MockUpstream, TestProxy) {
+    Harness::new(&format!(
+        r#"
+acl:
+  rules:
+    - identities:
+        - provider: basic
+          name: admin
+      admin: true
+    - identities:
+        - provider: basic
+          name: user
+maintenance:
+  enabled: {enabled}
+  message: Moving the downloads
+"#
+    ))
+    .users(&["admin", "user"])
+    .start()
+    .await
+}
+
+fn session_get() -> serde_json::Value {
+    json!({ "method": "session-get", "arguments": {} })
+}
+
+#[tokio::test]
+async fn only_admins_go_through() {
+    let (_upstream, proxy) = setup(true).await;
+
+    let (status, response) = rpc(&proxy, Some("user"), session_get()).await;
+    assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(response["result"], "Moving the downloads");
+
+    let (status, _) = rpc(&proxy, Some("admin"), session_get()).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let page = reqwest::Client::new()
+        .get(proxy.url() + "/web/")
+        .basic_auth("user", Some("password"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(page.status(), StatusCode::SERVICE_UNAVAILABLE);
+    assert!(page.text().await.unwrap().contains("Moving the downloads"));
+}
+
+#[tokio::test]
+async fn admins_toggle_maintenance() {
+    let (_upstream, proxy) = setup(false).await;
+    let client = reqwest::Client::new();
+    let url = proxy.url() + "/admin/maintenance";
+
+    let status = client
+        .put(&url)
+        .basic_auth("user", Some("password"))
+        .json(&json!({ "enabled": true }))
+        .send()
+        .await
+        .unwrap()
+        .status();
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    let status = client
+        .put(&url)
+        .basic_auth("admin", Some("password"))
+        .json(&json!({ "enabled": true }))
+        .send()
+        .await
+        .unwrap()
+        .status();
+    assert_eq!(status, StatusCode::OK);
+
+    let (status, _) = rpc(&proxy, Some("user"), session_get()).await;
+    assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+
+    let state: serde_json::Value = client
+        .get(&url)
+        .basic_auth("admin", Some("password"))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(state, json!({ "enabled": true }));
+}
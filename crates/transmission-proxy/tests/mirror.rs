@@ -0,0 +1,65 @@
+use std::time::Duration;
+
+use reqwest::StatusCode;
+use serde_json::json;
+
+use transmission_proxy::testing::MockUpstream;
+use transmission_rpc_client::types::MethodCall;
+
+mod common;
+use common::{rpc, Harness};
+
+#[tokio::test]
+async fn read_only_calls_are_mirrored() {
+    let upstream = MockUpstream::start().await.unwrap();
+    let mirror = MockUpstream::start().await.unwrap();
+    upstream.set_torrents(vec![json!({ "id": 1, "downloadDir": "/data" })]);
+    mirror.set_torrents(vec![json!({ "id": 1, "downloadDir": "/other" })]);
+
+    let proxy = Harness::new(&format!(
+        r#"
+acl:
+  rules: []
+  default_policy: allow
+mirror:
+  upstream: {}
+  percent: 100
+"#,
+        mirror.uri()
+    ))
+    .start_proxy(upstream.uri())
+    .await
+    .unwrap();
+
+    let (status, response) = rpc(
+        &proxy,
+        None,
+        json!({ "method": "torrent-get", "arguments": { "fields": ["id", "downloadDir"] } }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(response["arguments"]["torrents"][0]["downloadDir"], "/data");
+
+    let (status, _) = rpc(
+        &proxy,
+        None,
+        json!({ "method": "torrent-start", "arguments": { "ids": [1] } }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+
+    // Mirrored calls are sent in the background
+    for _ in 0..50 {
+        if !mirror.requests().is_empty() {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+
+    let calls: Vec<_> = mirror
+        .requests()
+        .into_iter()
+        .map(|request| request.call)
+        .collect();
+    assert!(matches!(calls[..], [MethodCall::TorrentGet { .. }]));
+}
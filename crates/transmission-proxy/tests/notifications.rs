@@ -0,0 +1,237 @@
+use reqwest::header::SET_COOKIE;
+use serde_json::json;
+
+use transmission_proxy::testing::{MockSmtp, MockUpstream, MockWebhook, TestProxy, WebhookRequest};
+
+mod common;
+use common::Harness;
+
+async fn setup(notifications: &str) -> (MockUpstream, TestProxy) {
+    Harness::new(&format!(
+        r#"
+acl:
+  rules: []
+  default_policy: allow
+notifications:
+  new_logins: true
+{notifications}
+"#
+    ))
+    .user_with("alice", "email: alice@example.com")
+    .start()
+    .await
+}
+
+async fn login(proxy: &TestProxy, user_agent: &str) {
+    let response = reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .build()
+        .unwrap()
+        .get(proxy.url() + "/auth/basic")
+        .basic_auth("alice", Some("password"))
+        .header("User-Agent", user_agent)
+        .send()
+        .await
+        .unwrap();
+    assert!(response.headers().contains_key(SET_COOKIE));
+}
+
+#[tokio::test]
+async fn logins_from_new_devices_are_notified() {
+    let webhook = MockWebhook::start().await.unwrap();
+    let (_upstream, proxy) = setup(&format!(
+        "  webhooks:\n    - url: {}\n      headers:\n        Authorization: Bearer webhook-token",
+        webhook.url()
+    ))
+    .await;
+
+    // The first login and known devices are not notified
+    login(&proxy, "laptop").await;
+    login(&proxy, "laptop").await;
+
+    login(&proxy, "phone").await;
+    let received = webhook.wait_for(1).await;
+    assert_eq!(received.len(), 1);
+
+    let WebhookRequest { headers, body, .. } = &received[0];
+    assert_eq!(headers["authorization"], "Bearer webhook-token");
+    assert_eq!(body["event"], "new_login");
+    assert_eq!(body["user"], "alice");
+    assert_eq!(body["email"], "alice@example.com");
+    assert_eq!(body["provider"], "basic");
+    assert_eq!(body["client_ip"], "127.0.0.1");
+    assert_eq!(body["user_agent"], "phone");
+
+    login(&proxy, "phone").await;
+    login(&proxy, "laptop").await;
+    assert_eq!(webhook.wait_for(2).await.len(), 1);
+}
+
+#[tokio::test]
+async fn failed_logins_do_not_hide_previous_logins() {
+    let webhook = MockWebhook::start().await.unwrap();
+    let (_upstream, proxy) = setup(&format!("  webhooks:\n    - url: {}", webhook.url())).await;
+
+    login(&proxy, "laptop").await;
+
+    // Push the successful login out of the login history
+    for _ in 0..25 {
+        reqwest::Client::new()
+            .get(proxy.url() + "/auth/basic")
+            .basic_auth("alice", Some("wrong"))
+            .header("User-Agent", "attacker")
+            .send()
+            .await
+            .unwrap();
+    }
+
+    login(&proxy, "phone").await;
+    let received = webhook.wait_for(1).await;
+    assert_eq!(received.len(), 1);
+    assert_eq!(received[0].body["user_agent"], "phone");
+}
+
+#[tokio::test]
+async fn new_logins_are_emailed_to_admins_and_users() {
+    let smtp = MockSmtp::start().await.unwrap();
+    let (_upstream, proxy) = setup(&format!(
+        r#"  smtp:
+    host: 127.0.0.1
+    port: {}
+    tls: none
+    from: Transmission Proxy <proxy@example.com>
+    to: [admin@example.com]
+    notify_users: true"#,
+        smtp.port()
+    ))
+    .await;
+
+    login(&proxy, "laptop").await;
+    login(&proxy, "phone").await;
+
+    let mut emails = smtp.wait_for(2).await;
+    emails.sort_by(|a, b| a.to.cmp(&b.to));
+    assert_eq!(emails.len(), 2);
+    assert_eq!(emails[0].to, vec!["admin@example.com"]);
+    assert_eq!(emails[1].to, vec!["alice@example.com"]);
+
+    for email in emails {
+        assert!(email.data.contains("Subject: New login to Transmission"));
+        assert!(email
+            .data
+            .contains("From: \"Transmission Proxy\" <proxy@example.com>"));
+        assert!(email.data.contains("phone"));
+    }
+}
+
+#[tokio::test]
+async fn new_logins_are_sent_to_the_apprise_key_of_their_event() {
+    let apprise = MockWebhook::start().await.unwrap();
+    let (_upstream, proxy) = setup(&format!(
+        "  apprise:\n    url: {}apprise\n    key: transmission\n    keys:\n      new_login: security",
+        apprise.url()
+    ))
+    .await;
+
+    login(&proxy, "laptop").await;
+    login(&proxy, "phone").await;
+
+    let received = apprise.wait_for(1).await;
+    assert_eq!(received.len(), 1);
+    assert_eq!(received[0].path, "/apprise/notify/security");
+    assert_eq!(received[0].body["title"], "New login to Transmission");
+    assert_eq!(received[0].body["type"], "warning");
+    assert!(received[0].body["body"]
+        .as_str()
+        .unwrap()
+        .contains("New login for alice"));
+}
+
+#[tokio::test]
+async fn unknown_apprise_events_are_rejected() {
+    let upstream = MockUpstream::start().await.unwrap();
+
+    let err = Harness::new(
+        r#"
+acl:
+  rules: []
+notifications:
+  apprise:
+    url: http://localhost:8000
+    keys:
+      new_logins: security
+"#,
+    )
+    .start_proxy(upstream.uri())
+    .await
+    .err()
+    .expect("proxy started");
+    assert!(err
+        .to_string()
+        .contains("unknown apprise notification event"));
+}
+
+#[tokio::test]
+async fn invalid_webhook_headers_are_rejected() {
+    let upstream = MockUpstream::start().await.unwrap();
+
+    let err = Harness::new(
+        r#"
+acl:
+  rules: []
+notifications:
+  webhooks:
+    - url: http://localhost/
+      headers:
+        "Bad Header": value
+"#,
+    )
+    .start_proxy(upstream.uri())
+    .await
+    .err()
+    .expect("proxy started");
+    assert!(err.to_string().contains("invalid HTTP header name"));
+}
+
+#[tokio::test]
+async fn torrent_problems_are_reported_to_owners() {
+    let webhook = MockWebhook::start().await.unwrap();
+    let (_upstream, _proxy) = Harness::new(&format!(
+        r#"
+acl:
+  rules: []
+  default_policy: allow
+owner_labels:
+  enabled: true
+notifications:
+  webhooks:
+    - url: {}
+  torrent_reports:
+    enabled: true
+    interval: 1
+"#,
+        webhook.url()
+    ))
+    .user_with("alice", "email: alice@example.com")
+    .torrents(vec![
+        json!({ "id": 1, "hashString": "aaaa", "name": "Broken", "labels": ["owner:alice"], "error": 3, "errorString": "No data found!" }),
+        json!({ "id": 2, "hashString": "bbbb", "name": "Fine", "labels": ["owner:alice"], "error": 0 }),
+        json!({ "id": 3, "hashString": "cccc", "name": "Warning", "labels": ["owner:alice"], "error": 1, "errorString": "Timed out" }),
+        json!({ "id": 4, "hashString": "dddd", "name": "Orphan", "error": 2, "errorString": "Not registered" }),
+    ])
+    .start()
+    .await;
+
+    // Only errors of attributed torrents are reported, once
+    let received = webhook.wait_for(2).await;
+    assert_eq!(received.len(), 1);
+    assert_eq!(
+        received[0].body,
+        json!({
+            "event": "torrent_problems",
+            "user": "alice",
+            "email": "alice@example.com",
+            "torrents": [{ "hash": "aaaa", "name": "Broken", "error": "No data found!" }],
+        })
+    );
+}
@@ -0,0 +1,159 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use reqwest::{
+    header::{COOKIE, LOCATION, SET_COOKIE},
+    StatusCode,
+};
+use serde_json::json;
+
+use transmission_proxy::testing::{session_cookie, MockOAuthProvider, MockUpstream, TestProxy};
+
+mod common;
+use common::Harness;
+
+async fn setup() -> (MockUpstream, MockOAuthProvider, TestProxy) {
+    let provider = MockOAuthProvider::start().await.unwrap();
+
+    let (upstream, proxy) = Harness::new(
+        r#"
+acl:
+  rules: []
+  default_policy: allow
+"#,
+    )
+    .providers(&format!(
+        r#"
+oauth2:
+  - name: mock
+    client_id: id
+    client_secret: secret
+    auth_url: {}
+    token_url: {}
+    userinfo_url: {}
+    email_path: $.email
+    email_verified_path: $.email_verified
+    allowed_domains: [example.org]
+"#,
+        provider.url("/authorize"),
+        provider.url("/token"),
+        provider.url("/userinfo"),
+    ))
+    .start()
+    .await;
+    (upstream, provider, proxy)
+}
+
+fn client() -> reqwest::Client {
+    reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .build()
+        .unwrap()
+}
+
+fn cookie(response: &reqwest::Response) -> Option<String> {
+    response.headers().get(SET_COOKIE).map(|cookie| {
+        cookie
+            .to_str()
+            .unwrap()
+            .split(';')
+            .next()
+            .unwrap()
+            .to_owned()
+    })
+}
+
+/// Go through the login flow of the provider, returning the callback response
+async fn login(proxy: &TestProxy) -> reqwest::Response {
+    let response = client()
+        .get(proxy.url() + "/auth/mock/login")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::SEE_OTHER);
+
+    let oauth_cookie = cookie(&response).unwrap();
+    let location = url::Url::parse(response.headers()[LOCATION].to_str().unwrap()).unwrap();
+    let state = location
+        .query_pairs()
+        .find(|(name, _)| name == "state")
+        .unwrap()
+        .1
+        .into_owned();
+
+    client()
+        .get(proxy.url() + "/auth/mock/callback")
+        .query(&[("state", state.as_str()), ("code", "code")])
+        .header(COOKIE, oauth_cookie)
+        .send()
+        .await
+        .unwrap()
+}
+
+async fn activity_status(proxy: &TestProxy, cookie: &str) -> StatusCode {
+    client()
+        .get(proxy.url() + "/account/activity")
+        .header(COOKIE, cookie)
+        .send()
+        .await
+        .unwrap()
+        .status()
+}
+
+#[tokio::test]
+async fn verified_emails_log_in() {
+    let (_upstream, provider, proxy) = setup().await;
+    provider.set_userinfo(json!({ "email": "alice@example.org", "email_verified": true }));
+
+    let response = login(&proxy).await;
+    assert_eq!(response.status(), StatusCode::SEE_OTHER);
+    let cookie = cookie(&response).unwrap();
+    assert!(cookie.starts_with("_transmission_proxy="));
+    assert_eq!(activity_status(&proxy, &cookie).await, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn unverified_emails_are_refused() {
+    let (_upstream, provider, proxy) = setup().await;
+
+    for userinfo in [
+        json!({ "email": "alice@example.org", "email_verified": false }),
+        json!({ "email": "alice@example.org" }),
+    ] {
+        provider.set_userinfo(userinfo);
+        let response = login(&proxy).await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        assert!(cookie(&response).is_none());
+    }
+
+    // Verified emails outside of the allowlists are refused too
+    provider.set_userinfo(json!({ "email": "mallory@example.com", "email_verified": true }));
+    assert_eq!(login(&proxy).await.status(), StatusCode::FORBIDDEN);
+}
+
+#[tokio::test]
+async fn sessions_of_removed_providers_are_refused() {
+    let (_upstream, _provider, proxy) = setup().await;
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+
+    let session = |provider: &str| {
+        let claim = json!({
+            "typ": "session",
+            "OAuth2": { "username": "alice@example.org", "provider": provider },
+            "sid": "session",
+            "iat": now,
+        });
+        format!("_transmission_proxy={}", session_cookie(&claim))
+    };
+
+    assert_eq!(
+        activity_status(&proxy, &session("mock")).await,
+        StatusCode::OK
+    );
+    assert_eq!(
+        activity_status(&proxy, &session("removed")).await,
+        StatusCode::SEE_OTHER
+    );
+}
@@ -0,0 +1,177 @@
+use reqwest::StatusCode;
+use serde_json::{json, Value};
+
+use transmission_proxy::testing::{MockUpstream, TestProxy};
+use transmission_rpc_client::types::{MethodCall, TorrentId, TorrentIds};
+
+mod common;
+use common::Harness;
+
+async fn setup() -> (MockUpstream, TestProxy) {
+    Harness::new(
+        r#"
+acl:
+  rules:
+    - identities:
+        - provider: basic
+          name: admin
+      admin: true
+    - identities:
+        - provider: basic
+          name: alice
+      download_dir: /data/alice
+    - identities:
+        - provider: basic
+          name: bob
+      download_dir: /data/bob
+owner_labels:
+  enabled: true
+"#,
+    )
+    .users(&["admin", "alice", "bob"])
+    .torrents(vec![
+        json!({ "id": 1, "name": "Labeled", "downloadDir": "/data/bob", "labels": ["owner:carol"], "status": 4, "sizeWhenDone": 1000 }),
+        json!({ "id": 2, "name": "Show", "downloadDir": "/data/alice/tv", "status": 0, "sizeWhenDone": 2000 }),
+        json!({ "id": 3, "name": "Movie", "downloadDir": "/data/alice", "status": 6, "sizeWhenDone": 3000 }),
+        json!({ "id": 4, "name": "Other", "downloadDir": "/data/bob-other", "status": 4, "sizeWhenDone": 4000 }),
+    ])
+    .start()
+    .await
+}
+
+#[tokio::test]
+async fn admins_see_torrents_by_owner() {
+    let (_upstream, proxy) = setup().await;
+    let client = reqwest::Client::new();
+
+    let response = client
+        .get(proxy.url() + "/admin/owners")
+        .basic_auth("alice", Some("password"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+    let owners: Value = client
+        .get(proxy.url() + "/admin/owners")
+        .basic_auth("admin", Some("password"))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+
+    // Owner labels take precedence over download dirs
+    let summary: Vec<_> = owners
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|group| {
+            (
+                group["owner"].clone(),
+                group["count"].clone(),
+                group["size_when_done"].clone(),
+                group["active"].clone(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        summary,
+        vec![
+            (Value::Null, json!(1), json!(4000), json!(1)),
+            (json!("alice"), json!(2), json!(5000), json!(1)),
+            (json!("carol"), json!(1), json!(1000), json!(1)),
+        ]
+    );
+
+    let page = client
+        .get(proxy.url() + "/admin/owners")
+        .basic_auth("admin", Some("password"))
+        .header("Accept", "text/html")
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert!(page.contains("Unattributed"));
+    assert!(page.contains("owners/stop?owner=alice"));
+    assert!(page.contains("5.0 kB"));
+}
+
+#[tokio::test]
+async fn admins_act_on_the_torrents_of_an_owner() {
+    let (upstream, proxy) = setup().await;
+    let client = reqwest::Client::new();
+
+    let response = client
+        .post(proxy.url() + "/admin/owners/stop?owner=alice")
+        .basic_auth("bob", Some("password"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+    let stopped: Value = client
+        .post(proxy.url() + "/admin/owners/stop?owner=alice")
+        .basic_auth("admin", Some("password"))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(stopped, json!({ "ids": [2, 3] }));
+
+    // Unattributed torrents are selected without an owner
+    let removed: Value = client
+        .post(proxy.url() + "/admin/owners/remove")
+        .basic_auth("admin", Some("password"))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(removed, json!({ "ids": [4] }));
+
+    // Owners without torrents don't select all torrents
+    let none: Value = client
+        .post(proxy.url() + "/admin/owners/remove?owner=dave")
+        .basic_auth("admin", Some("password"))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(none, json!({ "ids": [] }));
+
+    let requests = upstream.requests();
+    let stop = requests
+        .iter()
+        .find_map(|request| match &request.call {
+            MethodCall::TorrentStop { arguments } => Some(arguments),
+            _ => None,
+        })
+        .expect("torrent-stop was not sent");
+    assert_eq!(
+        stop.ids,
+        Some(TorrentIds::Ids(vec![TorrentId::Id(2), TorrentId::Id(3)]))
+    );
+
+    let removes: Vec<_> = requests
+        .iter()
+        .filter_map(|request| match &request.call {
+            MethodCall::TorrentRemove { arguments } => Some(arguments),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(removes.len(), 1);
+    assert_eq!(
+        removes[0].ids,
+        Some(TorrentIds::Ids(vec![TorrentId::Id(4)]))
+    );
+    assert_eq!(removes[0].delete_local_data, Some(false));
+}
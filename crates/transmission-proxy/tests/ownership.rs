@@ -0,0 +1,116 @@
+use reqwest::StatusCode;
+use serde_json::json;
+
+use transmission_proxy::testing::{MockUpstream, TestProxy};
+use transmission_rpc_client::types::MethodCall;
+
+mod common;
+use common::{rpc, Harness};
+
+async fn setup() -> (MockUpstream, TestProxy) {
+    Harness::new(
+        r#"
+acl:
+  rules:
+    - identities:
+        - provider: basic
+          name: admin
+      admin: true
+    - identities:
+        - provider: basic
+          name: alice
+      download_dir: /data
+owner_labels:
+  enabled: true
+  hide_from_others: true
+  added_by: true
+"#,
+    )
+    .users(&["admin", "alice"])
+    .torrents(vec![
+        json!({ "id": 1, "downloadDir": "/data", "labels": ["owner:alice", "movies"] }),
+        json!({ "id": 2, "downloadDir": "/data", "labels": ["owner:bob"] }),
+    ])
+    .start()
+    .await
+}
+
+#[tokio::test]
+async fn added_torrents_are_labeled_with_owner() {
+    let (upstream, proxy) = setup().await;
+
+    let (status, _) = rpc(
+        &proxy,
+        Some("alice"),
+        json!({
+            "method": "torrent-add",
+            "arguments": {
+                "download-dir": "/data",
+                "metainfo": "",
+                "paused": false,
+                "labels": ["owner:bob", "movies"],
+            },
+        }),
+    )
+    .await;
+
+    assert_eq!(status, StatusCode::OK);
+
+    let add = upstream
+        .requests()
+        .into_iter()
+        .find_map(|request| match request.call {
+            MethodCall::TorrentAdd { arguments } => Some(arguments),
+            _ => None,
+        })
+        .expect("torrent-add was not forwarded");
+
+    assert_eq!(add.labels, vec!["movies", "owner:alice"]);
+}
+
+#[tokio::test]
+async fn foreign_owner_labels_are_hidden() {
+    let (_upstream, proxy) = setup().await;
+
+    let (status, response) = rpc(
+        &proxy,
+        Some("alice"),
+        json!({ "method": "torrent-get", "arguments": { "fields": ["id", "downloadDir", "labels"] } }),
+    )
+    .await;
+
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(
+        response["arguments"]["torrents"],
+        json!([
+            { "id": 1, "downloadDir": "/data", "labels": ["owner:alice", "movies"] },
+            { "id": 2, "downloadDir": "/data", "labels": [] },
+        ])
+    );
+}
+
+#[tokio::test]
+async fn owners_are_added_to_torrents() {
+    let (_upstream, proxy) = setup().await;
+    let get = json!({ "method": "torrent-get", "arguments": { "fields": ["id", "addedBy"] } });
+
+    let (status, response) = rpc(&proxy, Some("admin"), get.clone()).await;
+    assert_eq!(status, StatusCode::OK);
+    let added_by: Vec<_> = response["arguments"]["torrents"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|torrent| torrent["addedBy"].clone())
+        .collect();
+    assert_eq!(added_by, vec![json!("alice"), json!("bob")]);
+
+    let (status, response) = rpc(&proxy, Some("alice"), get).await;
+    assert_eq!(status, StatusCode::OK);
+    let added_by: Vec<_> = response["arguments"]["torrents"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|torrent| torrent.get("addedBy").cloned())
+        .collect();
+    assert_eq!(added_by, vec![Some(json!("alice")), None]);
+}
@@ -0,0 +1,61 @@
+use argon2::{
+    password_hash::{rand_core::OsRng, PasswordHasher, SaltString},
+    Argon2,
+};
+use reqwest::StatusCode;
+use scrypt::Scrypt;
+use serde_json::json;
+
+mod common;
+use common::{password_hash, rpc, Harness};
+
+#[tokio::test]
+async fn argon2_and_scrypt_hashes_are_accepted() {
+    let salt = SaltString::generate(&mut OsRng);
+    let argon2 = Argon2::default()
+        .hash_password(b"password", &salt)
+        .unwrap()
+        .to_string();
+    let scrypt = Scrypt
+        .hash_password(b"password", &salt)
+        .unwrap()
+        .to_string();
+    let bcrypt = password_hash();
+
+    let (_upstream, proxy) = Harness::new(
+        r#"
+acl:
+  rules: []
+  default_policy: allow
+"#,
+    )
+    .basic(&format!(
+        r#"
+rpc_basic_auth: always
+users:
+  - username: alice
+    password: "{argon2}"
+  - username: bob
+    password: "{scrypt}"
+  - username: carol
+    password: "{bcrypt}"
+"#
+    ))
+    .start()
+    .await;
+
+    for user in ["alice", "bob", "carol"] {
+        let (status, _) = rpc(&proxy, Some(user), json!({ "method": "session-stats" })).await;
+        assert_eq!(status, StatusCode::OK, "{user}");
+    }
+
+    // A wrong password gets a new challenge
+    let response = reqwest::Client::new()
+        .post(proxy.rpc_url())
+        .basic_auth("alice", Some("wrong"))
+        .json(&json!({ "method": "session-stats" }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+}
@@ -0,0 +1,101 @@
+use reqwest::StatusCode;
+use serde_json::json;
+
+use transmission_proxy::testing::MockUpstream;
+use transmission_rpc_client::types::{MethodCall, MethodName};
+
+mod common;
+use common::{rpc, Harness};
+
+#[tokio::test]
+async fn port_tests_are_cached_for_other_users() {
+    let (upstream, proxy) = Harness::new(
+        r#"
+port_test:
+  cache: 300
+  admin_only: true
+acl:
+  rules:
+    - identities:
+        - provider: basic
+          name: admin
+      admin: true
+      allow_dangerous_methods: true
+    - identities:
+        - provider: basic
+          name: alice
+      download_dir: /data/alice
+    - identities:
+        - provider: basic
+          name: carol
+      allowed_methods: [torrent-get]
+"#,
+    )
+    .users(&["admin", "alice", "carol"])
+    .start()
+    .await;
+    upstream.respond(MethodName::PortTest, json!({ "port-is-open": true }));
+
+    let port_test = json!({ "method": "port-test" });
+    let forwarded = |upstream: &MockUpstream| {
+        upstream
+            .requests()
+            .iter()
+            .filter(|request| matches!(request.call, MethodCall::PortTest))
+            .count()
+    };
+
+    // Nothing to serve yet
+    let (status, _) = rpc(&proxy, Some("alice"), port_test.clone()).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    let (status, response) = rpc(&proxy, Some("admin"), port_test.clone()).await;
+    assert_eq!(status, StatusCode::OK, "{response}");
+    assert_eq!(response["arguments"], json!({ "port-is-open": true }));
+    assert_eq!(forwarded(&upstream), 1);
+
+    for user in ["admin", "alice"] {
+        let (status, response) = rpc(&proxy, Some(user), port_test.clone()).await;
+        assert_eq!(status, StatusCode::OK, "{response}");
+        assert_eq!(response["arguments"]["port-is-open"], json!(true));
+        assert_eq!(response["arguments"]["cached"], json!(true));
+        assert_eq!(response["arguments"]["cache-age"], json!(0));
+    }
+
+    // ACLs whose methods exclude port tests don't get cached results either
+    let (status, _) = rpc(&proxy, Some("carol"), port_test).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    assert_eq!(forwarded(&upstream), 1);
+}
+
+#[tokio::test]
+async fn session_stats_are_cached() {
+    let (upstream, proxy) = Harness::new(
+        r#"
+session_stats:
+  cache: 300
+acl:
+  rules: []
+  default_policy: allow
+"#,
+    )
+    .start()
+    .await;
+    upstream.respond(MethodName::SessionStats, json!({ "torrentCount": 3 }));
+
+    let session_stats = json!({ "method": "session-stats", "tag": 7 });
+    for _ in 0..2 {
+        let (status, response) = rpc(&proxy, None, session_stats.clone()).await;
+        assert_eq!(status, StatusCode::OK, "{response}");
+        assert_eq!(response["arguments"]["torrentCount"], json!(3));
+        assert_eq!(response["tag"], json!(7));
+    }
+
+    let forwarded = upstream
+        .requests()
+        .iter()
+        .filter(|request| matches!(request.call, MethodCall::SessionStats))
+        .count();
+    assert_eq!(forwarded, 1);
+}
@@ -0,0 +1,80 @@
+use reqwest::StatusCode;
+use serde_json::{json, Value};
+
+use transmission_proxy::testing::MockUpstream;
+
+mod common;
+use common::Harness;
+
+fn harness(provisioning: &str) -> Harness {
+    Harness::new(&format!(
+        r#"
+acl:
+  rules:
+    - identities:
+        - provider: basic
+          name: admin
+      admin: true
+    - identities:
+        - provider: basic
+          name: alice
+  provisioning:
+{provisioning}
+"#
+    ))
+    .users(&["admin", "alice"])
+}
+
+#[tokio::test]
+async fn invalid_templates_are_rejected() {
+    let upstream = MockUpstream::start().await.unwrap();
+
+    for provisioning in [
+        "    enabled: true\n    template:\n      identities:\n        - provider: basic\n          name: alice",
+        "    enabled: true\n    providers: [github]",
+        "    enabled: true\n    template:\n      web_ui_headers:\n        \"bad header\": value",
+    ] {
+        assert!(
+            harness(provisioning)
+                .start_proxy(upstream.uri())
+                .await
+                .is_err(),
+            "{provisioning}"
+        );
+    }
+}
+
+#[tokio::test]
+async fn admins_manage_provisioned_acls() {
+    let (_upstream, proxy) =
+        harness("    enabled: true\n    template:\n      download_dir: /data/{user}")
+            .start()
+            .await;
+    let client = reqwest::Client::new();
+
+    let response = client
+        .get(proxy.url() + "/admin/provisioned")
+        .basic_auth("admin", Some("password"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.json::<Value>().await.unwrap(), json!([]));
+
+    let response = client
+        .delete(proxy.url() + "/admin/provisioned")
+        .basic_auth("admin", Some("password"))
+        .json(&json!({ "provider": "oauth2", "oauth2": "google", "name": "bob@example.org" }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+    let response = client
+        .get(proxy.url() + "/admin/provisioned")
+        .basic_auth("alice", Some("password"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+}
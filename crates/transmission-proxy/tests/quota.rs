@@ -0,0 +1,47 @@
+use reqwest::StatusCode;
+use serde_json::json;
+
+use transmission_rpc_client::types::MethodName;
+
+mod common;
+use common::{rpc, Harness};
+
+#[tokio::test]
+async fn free_space_reports_the_remaining_quota() {
+    let (upstream, proxy) = Harness::new(
+        r#"
+acl:
+  rules:
+    - identities:
+        - provider: basic
+          name: alice
+      download_dir: /data/alice
+      quota: 1000
+"#,
+    )
+    .users(&["alice"])
+    .torrents(vec![
+        json!({ "id": 1, "downloadDir": "/data/alice", "sizeWhenDone": 300 }),
+        json!({ "id": 2, "downloadDir": "/data/alice/sub/", "sizeWhenDone": 200 }),
+        json!({ "id": 3, "downloadDir": "/data/bob", "sizeWhenDone": 5000 }),
+    ])
+    .start()
+    .await;
+    upstream.respond(
+        MethodName::FreeSpace,
+        json!({ "path": "/data/alice", "size-bytes": 100000, "total-size": 200000 }),
+    );
+
+    let (status, response) = rpc(
+        &proxy,
+        Some("alice"),
+        json!({ "method": "free-space", "arguments": { "path": "/data/alice" } }),
+    )
+    .await;
+
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(
+        response["arguments"],
+        json!({ "path": "/data/alice", "size-bytes": 500, "total_size": 1000 })
+    );
+}
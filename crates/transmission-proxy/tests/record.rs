@@ -0,0 +1,87 @@
+use clap::Parser;
+use reqwest::StatusCode;
+use serde_json::{json, Value};
+
+use transmission_proxy::{testing::MockUpstream, Args};
+use transmission_rpc_client::types::MethodName;
+
+mod common;
+use common::{rpc, temp_path, Harness};
+
+const CONFIG: &str = r#"
+acl:
+  rules: []
+  default_policy: allow
+"#;
+
+fn torrents() -> Vec<Value> {
+    vec![json!({ "id": 1, "name": "a", "downloadDir": "/data" })]
+}
+
+fn methods(upstream: &MockUpstream) -> Vec<MethodName> {
+    upstream
+        .requests()
+        .iter()
+        .map(|request| MethodName::from(&request.call))
+        .collect()
+}
+
+#[tokio::test]
+async fn recorded_calls_are_replayed_against_the_mock_daemon() {
+    let path = temp_path("record.jsonl");
+    let _ = std::fs::remove_file(&path);
+    let record_arg = path.display().to_string();
+
+    let (upstream, proxy) = Harness::new(CONFIG)
+        .args(&["--record", &record_arg])
+        .torrents(torrents())
+        .start()
+        .await;
+
+    for call in [
+        json!({ "method": "torrent-get", "arguments": { "fields": ["id", "name"] } }),
+        json!({
+            "method": "torrent-add",
+            "arguments": {
+                "download-dir": "/data",
+                "filename": "https://example.com/a.torrent",
+                "metainfo": "",
+                "paused": false,
+                "cookies": "uid=secret",
+            },
+        }),
+    ] {
+        let (status, response) = rpc(&proxy, None, call).await;
+        assert_eq!(status, StatusCode::OK, "{response}");
+    }
+
+    let recorded = methods(&upstream);
+    drop(proxy);
+    drop(upstream);
+
+    // Credentials are redacted from the recording
+    let recording = std::fs::read_to_string(&path).unwrap();
+    assert!(!recording.contains("uid=secret"));
+    assert!(recording.contains("<redacted>"));
+
+    // The recording is enough to send the same calls again, without the original upstream
+    let target = MockUpstream::start().await.unwrap();
+    target.set_torrents(torrents());
+    let args = Args::try_parse_from([
+        "transmission-proxy",
+        "replay",
+        &record_arg,
+        "--target",
+        &format!("{}transmission/rpc", target.uri()),
+    ])
+    .unwrap();
+    transmission_proxy::run(args).await.unwrap();
+
+    assert_eq!(methods(&target), recorded);
+    assert_eq!(
+        recorded,
+        vec![MethodName::TorrentGet, MethodName::TorrentAdd]
+    );
+
+    std::fs::remove_file(&path).unwrap();
+}
@@ -0,0 +1,46 @@
+use reqwest::{header::LOCATION, redirect::Policy, StatusCode};
+
+use transmission_proxy::testing::TestProxy;
+
+mod common;
+use common::Harness;
+
+const CONFIG: &str = r#"
+acl:
+  default_policy: allow
+  rules: []
+"#;
+
+async fn web_redirect(proxy: &TestProxy) -> String {
+    let response = reqwest::Client::builder()
+        .redirect(Policy::none())
+        .build()
+        .unwrap()
+        .get(proxy.url() + "/web")
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::MOVED_PERMANENTLY);
+    response.headers()[LOCATION].to_str().unwrap().to_owned()
+}
+
+#[tokio::test]
+async fn upstream_redirects_point_to_the_proxy() {
+    let (_upstream, proxy) = Harness::new(CONFIG).start().await;
+
+    assert_eq!(web_redirect(&proxy).await, proxy.url() + "/web/");
+}
+
+#[tokio::test]
+async fn upstream_redirects_honor_the_public_url() {
+    let (_upstream, proxy) = Harness::new(CONFIG)
+        .args(&["--public-url", "https://example.com/torrents"])
+        .start()
+        .await;
+
+    assert_eq!(
+        web_redirect(&proxy).await,
+        "https://example.com/torrents/web/"
+    );
+}
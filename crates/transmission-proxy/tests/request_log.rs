@@ -0,0 +1,161 @@
+use std::time::Duration;
+
+use reqwest::{header::USER_AGENT, StatusCode};
+use serde_json::{json, Value};
+
+use transmission_proxy::testing::{MockUpstream, TestProxy, SESSION_ID_HEADER};
+
+mod common;
+use common::Harness;
+
+async fn setup(verbose: bool) -> (MockUpstream, TestProxy) {
+    Harness::new(&format!(
+        r#"
+request_log:
+  routes:
+    - prefix: /transmission/rpc
+      headers: true
+    - prefix: /transmission/export
+      query: true
+  redaction:
+    verbose: {verbose}
+    headers: [x-api-key]
+    query: [fields]
+    event_fields: [user]
+acl:
+  rules:
+    - identities:
+        - provider: basic
+          name: admin
+      admin: true
+    - identities:
+        - provider: basic
+          name: alice
+      download_dir: /data/alice
+"#
+    ))
+    .users(&["admin", "alice"])
+    .start()
+    .await
+}
+
+async fn make_requests(proxy: &TestProxy, upstream: &MockUpstream) {
+    let client = reqwest::Client::new();
+
+    let response = client
+        .post(proxy.rpc_url())
+        .basic_auth("alice", Some("password"))
+        .header(SESSION_ID_HEADER, upstream.session_id())
+        .header(USER_AGENT, "TestClient/1.0")
+        .header("x-api-key", "secret")
+        .json(&json!({ "method": "torrent-get", "arguments": { "fields": ["id"] } }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let response = client
+        .get(proxy.url() + "/export?format=csv&fields=id,name")
+        .basic_auth("alice", Some("password"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    response.bytes().await.unwrap();
+}
+
+async fn recent(proxy: &TestProxy, user: &str) -> reqwest::Response {
+    reqwest::Client::new()
+        .get(proxy.url() + "/admin/requests")
+        .basic_auth(user, Some("password"))
+        .send()
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn logged_requests_are_redacted() {
+    let (upstream, proxy) = setup(false).await;
+    make_requests(&proxy, &upstream).await;
+
+    let response = recent(&proxy, "admin").await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let entries: Vec<Value> = response.json().await.unwrap();
+    assert_eq!(entries.len(), 2);
+
+    let rpc = &entries[0];
+    assert_eq!(rpc["user"], "alice");
+    assert_eq!(rpc["method"], "POST");
+    assert_eq!(rpc["path"], "/transmission/rpc");
+    assert_eq!(rpc["status"], 200);
+    assert_eq!(rpc["headers"]["authorization"], "[redacted]");
+    assert_eq!(rpc["headers"]["x-transmission-session-id"], "[redacted]");
+    assert_eq!(rpc["headers"]["x-api-key"], "[redacted]");
+    assert_eq!(rpc["headers"]["user-agent"], "TestClient/1.0");
+    assert!(rpc.get("query").is_none());
+
+    let export = &entries[1];
+    assert_eq!(export["query"], "format=csv&fields=[redacted]");
+    assert!(export.get("headers").is_none());
+}
+
+#[tokio::test]
+async fn verbose_logs_everything() {
+    let (upstream, proxy) = setup(true).await;
+    make_requests(&proxy, &upstream).await;
+
+    let entries: Vec<Value> = recent(&proxy, "admin").await.json().await.unwrap();
+    assert!(entries[0]["headers"]["authorization"]
+        .as_str()
+        .unwrap()
+        .starts_with("Basic "));
+    assert_eq!(entries[1]["query"], "format=csv&fields=id,name");
+}
+
+#[tokio::test]
+async fn only_admins_get_the_request_log() {
+    let (_upstream, proxy) = setup(false).await;
+
+    let response = recent(&proxy, "alice").await;
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+}
+
+#[tokio::test]
+async fn streamed_events_are_redacted() {
+    let (upstream, proxy) = setup(false).await;
+
+    let mut stream = reqwest::Client::new()
+        .get(proxy.url() + "/admin/events")
+        .basic_auth("admin", Some("password"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(stream.status(), StatusCode::OK);
+
+    let response = reqwest::Client::new()
+        .post(proxy.rpc_url())
+        .basic_auth("alice", Some("password"))
+        .header(SESSION_ID_HEADER, upstream.session_id())
+        .json(&json!({
+            "method": "torrent-add",
+            "arguments": { "download-dir": "/data/bob", "metainfo": "", "paused": false },
+        }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+    let mut text = String::new();
+    let _ = tokio::time::timeout(Duration::from_secs(5), async {
+        while let Ok(Some(chunk)) = stream.chunk().await {
+            text.push_str(&String::from_utf8_lossy(&chunk));
+            if text.contains("event:rpc_denied") {
+                break;
+            }
+        }
+    })
+    .await;
+
+    assert!(text.contains(r#""user":"[redacted]""#), "{text}");
+    assert!(!text.contains("alice"), "{text}");
+}
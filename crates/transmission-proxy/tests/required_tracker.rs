@@ -0,0 +1,87 @@
+use base64::Engine;
+use reqwest::StatusCode;
+use serde_json::{json, Value};
+
+use transmission_proxy::testing::{MockUpstream, TestProxy};
+
+mod common;
+use common::{rpc, Harness};
+
+async fn setup() -> (MockUpstream, TestProxy) {
+    Harness::new(
+        r#"
+acl:
+  rules:
+    - identities:
+        - provider: basic
+          name: alice
+      require_tracker: ^https://private\.example/
+"#,
+    )
+    .users(&["alice"])
+    .start()
+    .await
+}
+
+async fn add(proxy: &TestProxy, arguments: Value) -> (StatusCode, Value) {
+    let mut arguments = arguments;
+    arguments["download-dir"] = json!("/data");
+    arguments["paused"] = json!(false);
+    if arguments.get("metainfo").is_none() {
+        arguments["metainfo"] = json!("");
+    }
+
+    rpc(
+        proxy,
+        Some("alice"),
+        json!({ "method": "torrent-add", "arguments": arguments }),
+    )
+    .await
+}
+
+fn metainfo(announce: &str) -> String {
+    base64::engine::general_purpose::STANDARD.encode(format!(
+        "d8:announce{}:{announce}4:infod6:lengthi1e4:name1:a12:piece lengthi16384e6:pieces0:ee",
+        announce.len()
+    ))
+}
+
+#[tokio::test]
+async fn torrents_need_a_required_tracker() {
+    let (upstream, proxy) = setup().await;
+
+    let (status, response) = add(
+        &proxy,
+        json!({ "metainfo": metainfo("http://public.example/announce") }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    assert_eq!(response["arguments"]["denial"], "tracker-required");
+    assert!(upstream.requests().is_empty());
+
+    let (status, _) = add(
+        &proxy,
+        json!({ "metainfo": metainfo("https://private.example/announce") }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn magnet_links_need_a_required_tracker() {
+    let (_upstream, proxy) = setup().await;
+
+    let (status, _) = add(
+        &proxy,
+        json!({ "filename": "magnet:?xt=urn:btih:abc&tr=http%3A%2F%2Fpublic.example%2Fannounce" }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    let (status, _) = add(
+        &proxy,
+        json!({ "filename": "magnet:?xt=urn:btih:abc&tr=https%3A%2F%2Fprivate.example%2Fannounce" }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+}
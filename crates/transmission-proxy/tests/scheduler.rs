@@ -0,0 +1,108 @@
+use std::time::Duration;
+
+use reqwest::StatusCode;
+use serde_json::json;
+
+use transmission_proxy::testing::{MockUpstream, TestProxy, SESSION_ID_HEADER};
+use transmission_rpc_client::types::MethodName;
+
+mod common;
+use common::{rpc, Harness};
+
+async fn setup() -> (MockUpstream, TestProxy) {
+    let (upstream, proxy) = Harness::new(
+        r#"
+scheduler:
+  enabled: true
+  concurrency: 1
+  background_queue: 1
+acl:
+  default_policy: allow
+  rules: []
+"#,
+    )
+    .start()
+    .await;
+
+    // Make sure the proxy knows the session id, so each call is a single upstream request
+    rpc(
+        &proxy,
+        None,
+        json!({ "method": "session-get", "arguments": {} }),
+    )
+    .await;
+    upstream.clear_requests();
+
+    (upstream, proxy)
+}
+
+fn torrent_set() -> serde_json::Value {
+    json!({ "method": "torrent-set", "arguments": { "ids": [1], "labels": ["bulk"] } })
+}
+
+/// Occupy the upstream until it is released
+async fn block_upstream(proxy: &TestProxy) -> tokio::task::JoinHandle<StatusCode> {
+    let url = proxy.url() + "/web/slow";
+    let blocker = tokio::spawn(async move { reqwest::get(url).await.unwrap().status() });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    blocker
+}
+
+/// Send an RPC call which waits for the upstream
+async fn queue_call(
+    proxy: &TestProxy,
+    upstream: &MockUpstream,
+    body: serde_json::Value,
+) -> tokio::task::JoinHandle<StatusCode> {
+    let request = reqwest::Client::new()
+        .post(proxy.rpc_url())
+        .header(SESSION_ID_HEADER, upstream.session_id())
+        .json(&body);
+    let call = tokio::spawn(async move { request.send().await.unwrap().status() });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    call
+}
+
+#[tokio::test]
+async fn interactive_calls_go_first() {
+    let (upstream, proxy) = setup().await;
+    let blocker = block_upstream(&proxy).await;
+
+    let background = queue_call(&proxy, &upstream, torrent_set()).await;
+
+    let interactive = queue_call(
+        &proxy,
+        &upstream,
+        json!({ "method": "torrent-get", "arguments": { "fields": ["id"] } }),
+    )
+    .await;
+
+    upstream.release_stream();
+    assert_eq!(blocker.await.unwrap(), StatusCode::OK);
+    assert_eq!(interactive.await.unwrap(), StatusCode::OK);
+    assert_eq!(background.await.unwrap(), StatusCode::OK);
+
+    let methods: Vec<_> = upstream
+        .requests()
+        .iter()
+        .map(|request| MethodName::from(&request.call))
+        .collect();
+    assert_eq!(methods, [MethodName::TorrentGet, MethodName::TorrentSet]);
+}
+
+#[tokio::test]
+async fn full_queues_are_refused() {
+    let (upstream, proxy) = setup().await;
+    let blocker = block_upstream(&proxy).await;
+
+    let queued = queue_call(&proxy, &upstream, torrent_set()).await;
+
+    // The background queue only holds one call
+    let (status, response) = rpc(&proxy, None, torrent_set()).await;
+    assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE, "{response}");
+    assert_eq!(response["result"], "upstream is busy, try again later");
+
+    upstream.release_stream();
+    assert_eq!(blocker.await.unwrap(), StatusCode::OK);
+    assert_eq!(queued.await.unwrap(), StatusCode::OK);
+}
@@ -0,0 +1,23 @@
+use std::process::Command;
+
+use serde_json::Value;
+
+#[test]
+fn schema_describes_the_config() {
+    let output = Command::new(env!("CARGO_BIN_EXE_transmission-proxy"))
+        .arg("schema")
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+
+    let schema: Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(schema["required"], serde_json::json!(["acl"]));
+    assert_eq!(schema["additionalProperties"], false);
+
+    let acl = &schema["definitions"]["Acl"]["properties"];
+    assert!(acl["download_dir"]["description"].is_string());
+    assert!(schema["definitions"]["MethodName"]["enum"]
+        .as_array()
+        .unwrap()
+        .contains(&"torrent-add".into()));
+}
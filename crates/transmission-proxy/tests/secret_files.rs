@@ -0,0 +1,116 @@
+use std::path::Path;
+
+use reqwest::{
+    header::{COOKIE, SET_COOKIE},
+    StatusCode,
+};
+use serde_json::json;
+
+use transmission_proxy::testing::{MockUpstream, TestProxy};
+
+mod common;
+use common::{rotate, rpc, temp_path, write_users_file, Harness};
+
+async fn setup(extra: &[&str], users_file: &Path) -> (MockUpstream, TestProxy) {
+    Harness::new(
+        r#"
+acl:
+  rules:
+    - identities:
+        - provider: basic
+          name: alice
+    - deny: true
+"#,
+    )
+    .basic(&format!("users_file: {}", users_file.display()))
+    .args(extra)
+    .start()
+    .await
+}
+
+#[tokio::test]
+async fn users_are_read_again_when_the_file_changes() {
+    let path = temp_path("users");
+    write_users_file(&path, &["alice"], 0);
+    let (_upstream, proxy) = setup(&[], &path).await;
+
+    let request = json!({ "method": "session-stats" });
+    assert_eq!(
+        rpc(&proxy, Some("alice"), request.clone()).await.0,
+        StatusCode::OK
+    );
+
+    write_users_file(&path, &["bob"], 1);
+    assert_ne!(rpc(&proxy, Some("alice"), request).await.0, StatusCode::OK);
+
+    std::fs::remove_file(&path).unwrap();
+}
+
+/// Log in with the basic auth provider, returning the session cookie
+async fn login(proxy: &TestProxy) -> String {
+    let response = reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .build()
+        .unwrap()
+        .get(proxy.url() + "/auth/basic")
+        .basic_auth("alice", Some("password"))
+        .send()
+        .await
+        .unwrap();
+
+    let cookie = response.headers()[SET_COOKIE].to_str().unwrap();
+    cookie.split(';').next().unwrap().to_owned()
+}
+
+async fn session_status(proxy: &TestProxy, cookie: &str) -> StatusCode {
+    let response = reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .build()
+        .unwrap()
+        .get(proxy.url() + "/account/activity")
+        .header(COOKIE, cookie)
+        .send()
+        .await
+        .unwrap();
+
+    response.status()
+}
+
+#[tokio::test]
+async fn rotated_secret_keys_invalidate_sessions() {
+    let users_path = temp_path("key-users");
+    write_users_file(&users_path, &["alice"], 0);
+    let key_path = temp_path("secret-key");
+    rotate(&key_path, "first key\n", 0);
+
+    let key_arg = key_path.display().to_string();
+    let (_upstream, proxy) = setup(&["--secret-key-file", &key_arg], &users_path).await;
+
+    let cookie = login(&proxy).await;
+    assert_eq!(session_status(&proxy, &cookie).await, StatusCode::OK);
+
+    // Sessions signed with the previous key are ignored, so users are sent to the login page
+    rotate(&key_path, "second key\n", 1);
+    assert_eq!(session_status(&proxy, &cookie).await, StatusCode::SEE_OTHER);
+
+    let cookie = login(&proxy).await;
+    assert_eq!(session_status(&proxy, &cookie).await, StatusCode::OK);
+
+    std::fs::remove_file(&users_path).unwrap();
+    std::fs::remove_file(&key_path).unwrap();
+}
+
+#[tokio::test]
+async fn missing_secret_files_are_fatal() {
+    let upstream = MockUpstream::start().await.unwrap();
+    let harness = Harness::new(
+        r#"
+acl:
+  default_policy: allow
+  rules: []
+"#,
+    )
+    .basic(&format!("users_file: {}", temp_path("missing").display()));
+
+    assert!(harness.start_proxy(upstream.uri()).await.is_err());
+}
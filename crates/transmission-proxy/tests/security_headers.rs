@@ -0,0 +1,78 @@
+use reqwest::header::{CONTENT_SECURITY_POLICY, STRICT_TRANSPORT_SECURITY, X_FRAME_OPTIONS};
+
+use transmission_proxy::testing::MockUpstream;
+
+mod common;
+use common::Harness;
+
+#[tokio::test]
+async fn security_headers_are_added() {
+    let (_upstream, proxy) = Harness::new(
+        r#"
+acl:
+  rules: []
+  default_policy: allow
+security_headers:
+  paths:
+    - prefix: /transmission/web/
+      headers:
+        X-Frame-Options: ""
+        Content-Security-Policy: "frame-ancestors 'self'"
+"#,
+    )
+    .start()
+    .await;
+
+    let response = reqwest::get(proxy.url() + "/login").await.unwrap();
+    assert_eq!(response.headers()[X_FRAME_OPTIONS], "DENY");
+    assert!(response.headers()[CONTENT_SECURITY_POLICY]
+        .to_str()
+        .unwrap()
+        .contains("frame-ancestors 'none'"));
+    assert!(!response.headers().contains_key(STRICT_TRANSPORT_SECURITY));
+
+    let response = reqwest::get(proxy.url() + "/web/").await.unwrap();
+    assert!(!response.headers().contains_key(X_FRAME_OPTIONS));
+    assert_eq!(
+        response.headers()[CONTENT_SECURITY_POLICY],
+        "frame-ancestors 'self'"
+    );
+}
+
+#[tokio::test]
+async fn invalid_headers_are_rejected() {
+    let upstream = MockUpstream::start().await.unwrap();
+
+    assert!(Harness::new(
+        r#"
+acl:
+  rules: []
+security_headers:
+  headers:
+    "Invalid Header": value
+"#,
+    )
+    .start_proxy(upstream.uri())
+    .await
+    .is_err());
+}
+
+#[tokio::test]
+async fn default_csp_skips_web_ui() {
+    let (_upstream, proxy) = Harness::new(
+        r#"
+acl:
+  rules: []
+  default_policy: allow
+"#,
+    )
+    .start()
+    .await;
+
+    let response = reqwest::get(proxy.url() + "/web/").await.unwrap();
+    assert_eq!(response.headers()[X_FRAME_OPTIONS], "DENY");
+    assert!(!response.headers().contains_key(CONTENT_SECURITY_POLICY));
+
+    let response = reqwest::get(proxy.url() + "/login").await.unwrap();
+    assert!(response.headers().contains_key(CONTENT_SECURITY_POLICY));
+}
@@ -0,0 +1,101 @@
+use reqwest::{
+    header::{COOKIE, SET_COOKIE},
+    redirect::Policy,
+    StatusCode,
+};
+
+use transmission_proxy::testing::{MockUpstream, TestProxy};
+
+mod common;
+use common::Harness;
+
+async fn setup(cookies: &str) -> (MockUpstream, TestProxy) {
+    Harness::new(&format!(
+        r#"
+{cookies}
+acl:
+  rules:
+    - identities:
+        - provider: basic
+          name: alice
+    - deny: true
+"#
+    ))
+    .users(&["alice"])
+    .start()
+    .await
+}
+
+fn client() -> reqwest::Client {
+    reqwest::Client::builder()
+        .redirect(Policy::none())
+        .build()
+        .unwrap()
+}
+
+/// Log in with basic auth, returning the session cookie
+async fn login(proxy: &TestProxy) -> String {
+    let response = client()
+        .get(proxy.url() + "/auth/basic")
+        .basic_auth("alice", Some("password"))
+        .send()
+        .await
+        .unwrap();
+    assert!(response.status().is_redirection());
+
+    response.headers()[SET_COOKIE].to_str().unwrap().to_owned()
+}
+
+#[tokio::test]
+async fn login_cookies_are_strict_by_default() {
+    let (_upstream, proxy) = setup("").await;
+
+    let cookie = login(&proxy).await;
+    assert!(cookie.starts_with("_transmission_proxy="), "{cookie}");
+    assert!(cookie.contains("SameSite=Strict"), "{cookie}");
+    assert!(cookie.contains("HttpOnly"), "{cookie}");
+    assert!(!cookie.contains("Secure"), "{cookie}");
+}
+
+#[tokio::test]
+async fn login_cookie_attributes_are_configurable() {
+    let (_upstream, proxy) = setup(
+        r#"
+session_cookies:
+  login:
+    name: dashboard_session
+    same_site: none
+    domain: example.com
+    max_age: 3600
+"#,
+    )
+    .await;
+
+    let cookie = login(&proxy).await;
+    assert!(cookie.starts_with("dashboard_session="), "{cookie}");
+    assert!(cookie.contains("SameSite=None"), "{cookie}");
+    assert!(cookie.contains("Secure"), "{cookie}");
+    assert!(cookie.contains("Domain=example.com"), "{cookie}");
+    assert!(cookie.contains("Max-Age=3600"), "{cookie}");
+
+    // The cookie is read back under its name
+    let session = cookie.split(';').next().unwrap().to_owned();
+    let response = client()
+        .get(proxy.url() + "/web/")
+        .header(COOKIE, &session)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    // And cleared on logout
+    let response = client()
+        .get(proxy.url() + "/logout")
+        .header(COOKIE, &session)
+        .send()
+        .await
+        .unwrap();
+    let cleared = response.headers()[SET_COOKIE].to_str().unwrap();
+    assert!(cleared.starts_with("dashboard_session=;"), "{cleared}");
+    assert!(cleared.contains("Domain=example.com"), "{cleared}");
+}
@@ -0,0 +1,59 @@
+use reqwest::StatusCode;
+use serde_json::json;
+
+use transmission_proxy::testing::MockUpstream;
+
+mod common;
+use common::{rpc, Harness};
+
+fn harness(state: &str) -> Harness {
+    Harness::new(&format!(
+        r#"
+state: "{state}"
+acl:
+  rules:
+    - identities:
+        - provider: basic
+          name: admin
+      admin: true
+"#
+    ))
+    .users(&["admin"])
+}
+
+#[tokio::test]
+async fn unsupported_state_is_rejected() {
+    let upstream = MockUpstream::start().await.unwrap();
+
+    let err = harness("memcached://localhost")
+        .start_proxy(upstream.uri())
+        .await
+        .err()
+        .expect("proxy should not start");
+    assert!(err.to_string().contains("unsupported state url"), "{err}");
+}
+
+#[tokio::test]
+async fn unreachable_redis_is_not_fatal() {
+    let (_upstream, proxy) = harness("redis://127.0.0.1:1").start().await;
+
+    // Requests go through as if maintenance mode was off
+    let (status, response) = rpc(
+        &proxy,
+        Some("admin"),
+        json!({ "method": "session-get", "arguments": {} }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK, "{response}");
+
+    // Toggling maintenance mode needs the shared state
+    let status = reqwest::Client::new()
+        .put(proxy.url() + "/admin/maintenance")
+        .basic_auth("admin", Some("password"))
+        .json(&json!({ "enabled": true }))
+        .send()
+        .await
+        .unwrap()
+        .status();
+    assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+}
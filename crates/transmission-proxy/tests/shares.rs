@@ -0,0 +1,128 @@
+use reqwest::StatusCode;
+use serde_json::{json, Value};
+
+use transmission_proxy::testing::TestProxy;
+
+mod common;
+use common::Harness;
+
+fn harness() -> Harness {
+    Harness::new(
+        r#"
+acl:
+  rules:
+    - identities:
+        - provider: basic
+          name: alice
+      download_dir: /data/alice
+    - identities:
+        - provider: basic
+          name: bob
+      deny: true
+shares:
+  enabled: true
+  max_lifetime: 3600
+"#,
+    )
+    .users(&["alice", "bob"])
+}
+
+async fn share(proxy: &TestProxy, user: Option<&str>, body: Value) -> (StatusCode, Value) {
+    let mut req = reqwest::Client::new()
+        .post(proxy.url() + "/shares")
+        .json(&body);
+    if let Some(user) = user {
+        req = req.basic_auth(user, Some("password"));
+    }
+
+    let response = req.send().await.unwrap();
+    let status = response.status();
+    (status, response.json().await.unwrap_or(Value::Null))
+}
+
+#[tokio::test]
+async fn guests_see_shared_torrents() {
+    let (_upstream, proxy) = harness()
+        .torrents(vec![
+            json!({
+                "id": 1,
+                "hashString": "aaaa",
+                "name": "Shared",
+                "downloadDir": "/data/alice",
+                "percentDone": 0.5,
+                "status": 4,
+            }),
+            json!({ "id": 2, "hashString": "bbbb", "name": "Other", "downloadDir": "/data/carol" }),
+        ])
+        .start()
+        .await;
+
+    // Only the torrents of the user are shared, for at most the maximum lifetime
+    let (status, created) = share(
+        &proxy,
+        Some("alice"),
+        json!({ "ids": [1, 2], "lifetime": 86400 }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(created["hashes"], json!(["aaaa"]));
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    assert!(created["expires_at"].as_u64().unwrap() <= now + 3600);
+
+    // Guests only get the shared fields, without paths
+    let url = created["url"].as_str().unwrap();
+    let response = reqwest::get(url).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let shared: Value = response.json().await.unwrap();
+    assert_eq!(
+        shared["torrents"],
+        json!([{ "hashString": "aaaa", "name": "Shared", "percentDone": 0.5, "status": 4 }])
+    );
+
+    let page = reqwest::Client::new()
+        .get(url)
+        .header("Accept", "text/html")
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert!(page.contains("Shared"));
+    assert!(page.contains("Downloading"));
+
+    // Tampered links are rejected
+    let response = reqwest::get(url.to_owned() + "x").await.unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn shares_need_access() {
+    let (_upstream, proxy) = harness()
+        .torrents(vec![
+            json!({ "id": 1, "hashString": "aaaa", "downloadDir": "/data/alice" }),
+        ])
+        .start()
+        .await;
+
+    let body = json!({ "ids": [1] });
+    assert_eq!(
+        share(&proxy, None, body.clone()).await.0,
+        StatusCode::UNAUTHORIZED
+    );
+    assert_eq!(
+        share(&proxy, Some("bob"), body).await.0,
+        StatusCode::UNAUTHORIZED
+    );
+    assert_eq!(
+        share(&proxy, Some("alice"), json!({ "ids": [] })).await.0,
+        StatusCode::BAD_REQUEST
+    );
+    assert_eq!(
+        share(&proxy, Some("alice"), json!({ "ids": [2] })).await.0,
+        StatusCode::NOT_FOUND
+    );
+}
@@ -0,0 +1,184 @@
+use reqwest::StatusCode;
+use serde_json::{json, Value};
+
+use transmission_proxy::testing::{MockUpstream, TestProxy};
+
+mod common;
+use common::{rpc, Harness};
+
+async fn setup() -> (MockUpstream, TestProxy) {
+    Harness::new(
+        r#"
+acl:
+  rules:
+    - name: admins
+      identities:
+        - provider: basic
+          name: admin
+      admin: true
+    - name: users
+      identities:
+        - provider: basic
+          name: alice
+      download_dir: /data
+      quota: 1000
+"#,
+    )
+    .users(&["admin", "alice", "bob"])
+    .torrents(vec![
+        json!({ "id": 1, "hashString": "aa", "downloadDir": "/data" }),
+    ])
+    .start()
+    .await
+}
+
+async fn export(proxy: &TestProxy, user: &str) -> (StatusCode, Value) {
+    let response = reqwest::Client::new()
+        .get(proxy.url() + "/admin/snapshot")
+        .basic_auth(user, Some("password"))
+        .send()
+        .await
+        .unwrap();
+
+    let status = response.status();
+    (status, response.json().await.unwrap_or_default())
+}
+
+#[tokio::test]
+async fn export_is_sanitized() {
+    let (_upstream, proxy) = setup().await;
+
+    let (status, _) = rpc(
+        &proxy,
+        Some("alice"),
+        json!({ "method": "torrent-get", "arguments": { "fields": ["id", "hashString"] } }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (status, _) = export(&proxy, "alice").await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    let (status, snapshot) = export(&proxy, "admin").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(snapshot["version"], 1);
+    assert_eq!(
+        snapshot["config"]["providers"]["basic"]["users"][0]["password"],
+        "<redacted>"
+    );
+    assert_eq!(
+        snapshot["users"],
+        json!([
+            { "identity": { "provider": "basic", "name": "admin" }, "acl": "admins" },
+            { "identity": { "provider": "basic", "name": "alice" }, "acl": "users" },
+            { "identity": { "provider": "basic", "name": "bob" }, "acl": null },
+        ])
+    );
+    assert_eq!(snapshot["torrent_index"][0]["id"], 1);
+    assert_eq!(snapshot["torrent_index"][0]["info_hash"], "aa");
+    assert_eq!(snapshot["quotas"], json!({ "users": 1000 }));
+}
+
+#[tokio::test]
+async fn snapshots_round_trip() {
+    let (_upstream, proxy) = setup().await;
+
+    let (status, _) = rpc(
+        &proxy,
+        Some("alice"),
+        json!({ "method": "torrent-get", "arguments": { "fields": ["id", "hashString"] } }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (status, _) = rpc(
+        &proxy,
+        Some("alice"),
+        json!({
+            "method": "torrent-add",
+            "arguments": { "download-dir": "/elsewhere", "metainfo": "" },
+        }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    // The audit log is written in the background
+    let mut snapshot = Value::Null;
+    for _ in 0..50 {
+        snapshot = export(&proxy, "admin").await.1;
+        if !snapshot["audit"].as_array().unwrap().is_empty() {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+    }
+    assert_eq!(snapshot["audit"][0]["event"], "rpc_denied");
+    assert_eq!(snapshot["audit"][0]["user"], "alice");
+
+    // Restore the export on another instance
+    let (_upstream, other) = setup().await;
+    let response = reqwest::Client::new()
+        .put(other.url() + "/admin/snapshot")
+        .basic_auth("admin", Some("password"))
+        .json(&snapshot)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let (_, restored) = export(&other, "admin").await;
+    for field in [
+        "config",
+        "users",
+        "torrent_index",
+        "quotas",
+        "audit",
+        "storage",
+    ] {
+        assert_eq!(restored[field], snapshot[field], "{field} differs");
+    }
+}
+
+#[tokio::test]
+async fn import_restores_state() {
+    let (_upstream, proxy) = setup().await;
+
+    let snapshot = json!({
+        "version": 1,
+        "created_at": 0,
+        "config": {},
+        "users": [],
+        "torrent_index": [
+            { "id": 7, "info_hash": "bb", "download_dir": "/data/alice", "owner": "alice" },
+        ],
+        "storage": { "notes": { "greeting": "aGVsbG8=" } },
+    });
+
+    let response = reqwest::Client::new()
+        .put(proxy.url() + "/admin/snapshot")
+        .basic_auth("admin", Some("password"))
+        .json(&snapshot)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        response.json::<Value>().await.unwrap(),
+        json!({ "torrents": 1, "entries": 1 })
+    );
+
+    let (_, exported) = export(&proxy, "admin").await;
+    assert_eq!(exported["torrent_index"], snapshot["torrent_index"]);
+    assert_eq!(exported["storage"], snapshot["storage"]);
+
+    // Snapshots from other versions are refused
+    let mut newer = snapshot.clone();
+    newer["version"] = 2.into();
+    let response = reqwest::Client::new()
+        .put(proxy.url() + "/admin/snapshot")
+        .basic_auth("admin", Some("password"))
+        .json(&newer)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
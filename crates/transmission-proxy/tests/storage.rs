@@ -0,0 +1,78 @@
+use std::{path::Path, time::Duration};
+
+use reqwest::StatusCode;
+use serde_json::{json, Value};
+
+use transmission_proxy::testing::{MockUpstream, TestProxy};
+
+mod common;
+use common::Harness;
+
+async fn start(upstream: &MockUpstream, db: &Path) -> TestProxy {
+    Harness::new(&format!(
+        r#"
+storage:
+  sqlite: "{db}"
+tracker_stats:
+  enabled: true
+  interval: 300
+acl:
+  rules:
+    - identities:
+        - provider: basic
+          name: admin
+      admin: true
+"#,
+        db = db.display()
+    ))
+    .users(&["admin"])
+    .start_proxy(upstream.uri())
+    .await
+    .unwrap()
+}
+
+async fn tracker_report(proxy: &TestProxy) -> Value {
+    for _ in 0..50 {
+        let response = reqwest::Client::new()
+            .get(proxy.url() + "/stats/trackers")
+            .basic_auth("admin", Some("password"))
+            .send()
+            .await
+            .unwrap();
+
+        if response.status() == StatusCode::OK {
+            return response.json().await.unwrap();
+        }
+
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+
+    panic!("no tracker report");
+}
+
+#[tokio::test]
+async fn data_survives_restarts() {
+    let db = std::env::temp_dir().join(format!(
+        "transmission-proxy-storage-{}.db",
+        std::process::id()
+    ));
+    let _ = std::fs::remove_file(&db);
+
+    let upstream = MockUpstream::start().await.unwrap();
+    upstream.set_torrents(vec![json!({ "id": 1, "trackerStats": [] })]);
+
+    let proxy = start(&upstream, &db).await;
+    assert_eq!(tracker_report(&proxy).await["torrents"], 1);
+    drop(proxy);
+
+    // The stored report is still fresh, so it is served without asking the upstream
+    upstream.set_torrents(vec![
+        json!({ "id": 1, "trackerStats": [] }),
+        json!({ "id": 2, "trackerStats": [] }),
+    ]);
+
+    let proxy = start(&upstream, &db).await;
+    assert_eq!(tracker_report(&proxy).await["torrents"], 1);
+
+    let _ = std::fs::remove_file(&db);
+}
@@ -0,0 +1,72 @@
+use std::time::Duration;
+
+use axum::body::Bytes;
+
+mod common;
+use common::Harness;
+
+const CONFIG: &str = r#"
+acl:
+  default_policy: allow
+  rules: []
+"#;
+
+#[tokio::test]
+async fn web_responses_are_streamed() {
+    let (upstream, proxy) = Harness::new(CONFIG).start().await;
+
+    let mut response = reqwest::get(proxy.url() + "/web/stream").await.unwrap();
+
+    // The first chunk goes through before the upstream is done
+    let first = tokio::time::timeout(Duration::from_secs(5), response.chunk())
+        .await
+        .expect("response was buffered")
+        .unwrap();
+    assert_eq!(first.as_deref(), Some(&b"first chunk"[..]));
+
+    upstream.release_stream();
+    assert_eq!(response.text().await.unwrap(), "last chunk");
+}
+
+#[tokio::test]
+async fn fragments_are_inserted_across_chunks() {
+    let (upstream, proxy) = Harness::new(
+        r#"
+acl:
+  rules:
+    - identities:
+        - provider: basic
+          name: alice
+web_ui:
+  toolbar: true
+"#,
+    )
+    .users(&["alice"])
+    .start()
+    .await;
+
+    // The body tag is split between chunks, and followed by a large chunked body
+    let filler = "x".repeat(16 * 1024);
+    let mut chunks = vec![
+        Bytes::from("<html><head><title>Transmission</title></head><bo"),
+        Bytes::from("dy class=\"mock\">"),
+    ];
+    chunks.extend((0..256).map(|_| Bytes::from(filler.clone())));
+    chunks.push(Bytes::from("</body></html>"));
+    upstream.set_web_page(chunks);
+
+    let page = reqwest::Client::new()
+        .get(proxy.url() + "/web/")
+        .basic_auth("alice", Some("password"))
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+
+    let head = "<html><head><title>Transmission</title></head><body class=\"mock\">";
+    assert!(page.starts_with(head));
+    assert!(page[head.len()..].starts_with("<div id=\"transmission-proxy-toolbar\""));
+    assert!(page.ends_with(&(filler.repeat(256) + "</body></html>")));
+}
@@ -0,0 +1,57 @@
+use transmission_proxy::testing::{MockUpstream, TestProxy};
+
+mod common;
+use common::Harness;
+
+async fn setup() -> (MockUpstream, TestProxy) {
+    Harness::new(
+        r#"
+acl:
+  rules:
+    - identities:
+        - provider: basic
+          name: admin
+      admin: true
+    - identities:
+        - provider: basic
+          name: alice
+web_ui:
+  toolbar: true
+"#,
+    )
+    .users(&["admin", "alice"])
+    .start()
+    .await
+}
+
+async fn web_page(proxy: &TestProxy, user: &str) -> String {
+    reqwest::Client::new()
+        .get(proxy.url() + "/web/")
+        .basic_auth(user, Some("password"))
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn users_see_a_toolbar() {
+    let (_upstream, proxy) = setup().await;
+
+    let page = web_page(&proxy, "alice").await;
+    assert!(page.contains("<div id=\"transmission-proxy-toolbar\""));
+    assert!(page.contains("<strong>alice</strong>"));
+    assert!(page.contains("href=\"/transmission/logout\""));
+    assert!(!page.contains("/transmission/stats/trackers"));
+}
+
+#[tokio::test]
+async fn admins_see_a_stats_link() {
+    let (_upstream, proxy) = setup().await;
+
+    let page = web_page(&proxy, "admin").await;
+    assert!(page.contains("<strong>admin</strong>"));
+    assert!(page.contains("href=\"/transmission/stats/trackers\""));
+}
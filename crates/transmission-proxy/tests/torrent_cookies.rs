@@ -0,0 +1,84 @@
+use reqwest::StatusCode;
+use serde_json::json;
+
+use transmission_proxy::testing::{MockUpstream, TestProxy};
+use transmission_rpc_client::types::MethodCall;
+
+mod common;
+use common::{rpc, Harness};
+
+async fn setup() -> (MockUpstream, TestProxy) {
+    Harness::new(
+        r#"
+acl:
+  rules:
+    - identities:
+        - provider: basic
+          name: admin
+    - identities:
+        - provider: basic
+          name: alice
+      download_dir: /data/alice
+    - identities:
+        - provider: basic
+          name: bob
+      download_dir: /data/bob
+      torrent_cookies: [tracker.example.org]
+"#,
+    )
+    .users(&["admin", "alice", "bob"])
+    .start()
+    .await
+}
+
+/// Add a torrent by URL with cookies, returning the cookies the upstream received
+async fn forwarded_cookies(
+    upstream: &MockUpstream,
+    proxy: &TestProxy,
+    user: &str,
+    dir: &str,
+) -> Option<String> {
+    upstream.clear_requests();
+
+    let (status, _) = rpc(
+        proxy,
+        Some(user),
+        json!({
+            "method": "torrent-add",
+            "arguments": {
+                "download-dir": dir,
+                "metainfo": "",
+                "paused": false,
+                "filename": "https://tracker.example.org/download/1.torrent",
+                "cookies": "uid=1; pass=secret",
+            },
+        }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+
+    upstream
+        .requests()
+        .into_iter()
+        .find_map(|request| match request.call {
+            MethodCall::TorrentAdd { arguments } => Some(arguments.cookies),
+            _ => None,
+        })
+        .expect("torrent-add was not forwarded")
+}
+
+#[tokio::test]
+async fn cookies_follow_the_acl_policy() {
+    let (upstream, proxy) = setup().await;
+
+    // Unrestricted users pass cookies, restricted users don't unless the domain is allowed
+    assert!(forwarded_cookies(&upstream, &proxy, "admin", "/data")
+        .await
+        .is_some());
+    assert!(forwarded_cookies(&upstream, &proxy, "alice", "/data/alice")
+        .await
+        .is_none());
+    assert!(forwarded_cookies(&upstream, &proxy, "bob", "/data/bob")
+        .await
+        .is_some());
+}
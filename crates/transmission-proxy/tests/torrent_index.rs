@@ -0,0 +1,99 @@
+use std::time::Duration;
+
+use reqwest::StatusCode;
+use serde_json::{json, Value};
+
+use transmission_proxy::testing::{MockUpstream, TestProxy};
+use transmission_rpc_client::types::MethodName;
+
+mod common;
+use common::{rpc, Harness};
+
+async fn setup(seed: bool) -> (MockUpstream, TestProxy) {
+    Harness::new(&format!(
+        r#"
+torrent_index:
+  seed: {seed}
+acl:
+  rules:
+    - identities:
+        - provider: basic
+          name: alice
+      download_dir: /data/alice
+"#
+    ))
+    .users(&["alice"])
+    .torrents(vec![
+        json!({ "id": 1, "hashString": "aa", "downloadDir": "/data/alice" }),
+        json!({ "id": 2, "hashString": "bb", "downloadDir": "/data/bob" }),
+    ])
+    .start()
+    .await
+}
+
+async fn recently_removed(proxy: &TestProxy) -> Value {
+    let (status, response) = rpc(
+        proxy,
+        Some("alice"),
+        json!({
+            "method": "torrent-get",
+            "arguments": { "ids": "recently-active", "fields": ["id", "downloadDir"] },
+        }),
+    )
+    .await;
+
+    assert_eq!(status, StatusCode::OK, "{response}");
+    response["arguments"]["removed"].clone()
+}
+
+#[tokio::test]
+async fn index_is_seeded_at_startup() {
+    let (upstream, proxy) = setup(true).await;
+
+    // Alice never listed her torrents, but the index knows where they were
+    upstream.set_removed(vec![1, 2]);
+
+    let mut removed = Value::Null;
+    for _ in 0..50 {
+        if !upstream.requests().is_empty() {
+            upstream.set_torrents(vec![]);
+            removed = recently_removed(&proxy).await;
+            if removed == json!([1]) {
+                break;
+            }
+        }
+
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+
+    assert_eq!(removed, json!([1]));
+}
+
+#[tokio::test]
+async fn added_torrents_are_indexed() {
+    let (upstream, proxy) = setup(false).await;
+    upstream.set_torrents(vec![]);
+    upstream.respond(
+        MethodName::TorrentAdd,
+        json!({ "torrent-added": { "id": 3, "hashString": "cc", "name": "c" } }),
+    );
+
+    let (status, response) = rpc(
+        &proxy,
+        Some("alice"),
+        json!({
+            "method": "torrent-add",
+            "arguments": {
+                "download-dir": "/data/alice",
+                "filename": "https://example.com/c.torrent",
+                "metainfo": "",
+                "paused": false,
+            },
+        }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK, "{response}");
+
+    upstream.set_removed(vec![1, 3]);
+    assert_eq!(recently_removed(&proxy).await, json!([3]));
+}
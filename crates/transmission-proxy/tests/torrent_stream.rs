@@ -0,0 +1,145 @@
+use std::time::Duration;
+
+use reqwest::StatusCode;
+use serde_json::{json, Value};
+
+use transmission_proxy::testing::TestProxy;
+
+mod common;
+use common::Harness;
+
+fn harness() -> Harness {
+    Harness::new(
+        r#"
+torrent_stream:
+  interval: 1
+  snapshot_every: 3
+acl:
+  rules:
+    - identities:
+        - provider: basic
+          name: alice
+      download_dir: /data/alice
+    - identities:
+        - provider: basic
+          name: bob
+      deny: true
+"#,
+    )
+    .users(&["alice", "bob"])
+}
+
+/// Server-sent events read from a response
+struct Events {
+    response: reqwest::Response,
+    buffer: String,
+}
+
+impl Events {
+    /// Name and data of the next event
+    async fn next(&mut self) -> (String, Value) {
+        loop {
+            if let Some(end) = self.buffer.find("\n\n") {
+                let event: String = self.buffer.drain(..end + 2).collect();
+                let field = |name: &str| {
+                    event
+                        .lines()
+                        .find_map(|line| line.strip_prefix(name))
+                        .map(|value| value.trim().to_owned())
+                };
+
+                // Skip keep-alive comments
+                if let (Some(name), Some(data)) = (field("event:"), field("data:")) {
+                    return (name, serde_json::from_str(&data).unwrap());
+                }
+                continue;
+            }
+
+            let chunk = tokio::time::timeout(Duration::from_secs(5), self.response.chunk())
+                .await
+                .expect("no event received")
+                .unwrap()
+                .expect("stream ended");
+            self.buffer += std::str::from_utf8(&chunk).unwrap();
+        }
+    }
+}
+
+async fn stream(proxy: &TestProxy, user: &str, query: &str) -> reqwest::Response {
+    reqwest::Client::new()
+        .get(proxy.url() + "/torrents/stream" + query)
+        .basic_auth(user, Some("password"))
+        .send()
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn torrents_are_streamed_as_deltas() {
+    let (upstream, proxy) = harness()
+        .torrents(vec![
+            json!({ "id": 1, "name": "Kept", "percentDone": 0.5, "downloadDir": "/data/alice" }),
+            json!({ "id": 2, "name": "Removed", "percentDone": 1.0, "downloadDir": "/data/alice" }),
+            json!({ "id": 3, "name": "Hidden", "percentDone": 1.0, "downloadDir": "/data/carol" }),
+        ])
+        .start()
+        .await;
+
+    let response = stream(&proxy, "alice", "?fields=name,percentDone").await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()["content-type"], "text/event-stream");
+    let mut events = Events {
+        response,
+        buffer: String::new(),
+    };
+
+    // Only the torrents of the user are streamed, keyed by id
+    assert_eq!(
+        events.next().await,
+        (
+            "snapshot".to_owned(),
+            json!({ "torrents": [
+                { "id": 1, "name": "Kept", "percentDone": 0.5 },
+                { "id": 2, "name": "Removed", "percentDone": 1.0 },
+            ] })
+        )
+    );
+
+    upstream.set_torrents(vec![
+        json!({ "id": 1, "name": "Kept", "percentDone": 0.75, "downloadDir": "/data/alice" }),
+        json!({ "id": 3, "name": "Hidden", "percentDone": 1.0, "downloadDir": "/data/carol" }),
+        json!({ "id": 4, "name": "Added", "percentDone": 0.0, "downloadDir": "/data/alice" }),
+    ]);
+    assert_eq!(
+        events.next().await,
+        (
+            "delta".to_owned(),
+            json!({
+                "added": [{ "id": 4, "name": "Added", "percentDone": 0.0 }],
+                "removed": [2],
+                "changed": [{ "id": 1, "percentDone": 0.75 }],
+            })
+        )
+    );
+
+    // Unchanged polls are skipped until the next full snapshot
+    let (name, data) = events.next().await;
+    assert_eq!(name, "snapshot");
+    assert_eq!(data["torrents"].as_array().unwrap().len(), 2);
+}
+
+#[tokio::test]
+async fn streams_need_access() {
+    let (_upstream, proxy) = harness().start().await;
+
+    assert_eq!(
+        stream(&proxy, "bob", "").await.status(),
+        StatusCode::UNAUTHORIZED
+    );
+    assert_eq!(
+        stream(&proxy, "alice", "?fields=name,../etc")
+            .await
+            .status(),
+        StatusCode::BAD_REQUEST
+    );
+}
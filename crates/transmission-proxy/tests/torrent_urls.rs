@@ -0,0 +1,126 @@
+use base64::Engine;
+use reqwest::StatusCode;
+use serde_json::json;
+
+use transmission_proxy::{
+    testing::{MockFileServer, MockUpstream, TestProxy},
+    torrent::Torrent,
+};
+use transmission_rpc_client::types::MethodCall;
+
+mod common;
+use common::{rpc, Harness};
+
+const METAINFO: &str = concat!(
+    "d8:announce14:http://a/annou",
+    "4:infod6:lengthi1e4:name1:a12:piece lengthi16384e6:pieces0:ee"
+);
+
+async fn setup(torrent_urls: &str) -> (MockUpstream, TestProxy, MockFileServer) {
+    let files = MockFileServer::start(METAINFO.as_bytes().to_vec())
+        .await
+        .unwrap();
+
+    let (upstream, proxy) = Harness::new(&format!(
+        r#"
+torrent_urls:
+  fetch: true
+  {torrent_urls}
+acl:
+  rules:
+    - identities:
+        - provider: basic
+          name: alice
+      tracker_rules:
+        - from: "^http://"
+          to: "https://"
+"#
+    ))
+    .users(&["alice"])
+    .start()
+    .await;
+    (upstream, proxy, files)
+}
+
+async fn add(proxy: &TestProxy, url: &str) -> (StatusCode, serde_json::Value) {
+    rpc(
+        proxy,
+        Some("alice"),
+        json!({
+            "method": "torrent-add",
+            "arguments": {
+                "download-dir": "/data",
+                "metainfo": "",
+                "paused": false,
+                "filename": url,
+            },
+        }),
+    )
+    .await
+}
+
+fn forwarded_add(upstream: &MockUpstream) -> (Option<String>, String) {
+    upstream
+        .requests()
+        .into_iter()
+        .find_map(|request| match request.call {
+            MethodCall::TorrentAdd { arguments } => Some((arguments.filename, arguments.metainfo)),
+            _ => None,
+        })
+        .expect("torrent-add was not forwarded")
+}
+
+#[tokio::test]
+async fn private_addresses_are_not_fetched() {
+    let (upstream, proxy, files) = setup("").await;
+
+    let (status, body) = add(&proxy, &files.url("/file")).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    assert!(body.to_string().contains("torrent-url-not-allowed"));
+    assert!(upstream.requests().is_empty());
+}
+
+#[tokio::test]
+async fn fetched_torrents_are_filtered() {
+    let (upstream, proxy, files) = setup("allow_private_addresses: true").await;
+
+    let (status, _) = add(&proxy, &files.url("/redirect")).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (filename, metainfo) = forwarded_add(&upstream);
+    assert!(filename.is_none());
+
+    let metainfo = base64::engine::general_purpose::STANDARD
+        .decode(metainfo)
+        .unwrap();
+    let torrent: Torrent = serde_bencode::from_bytes(&metainfo).unwrap();
+    assert_eq!(torrent.announce.as_deref(), Some("https://a/annou"));
+}
+
+#[tokio::test]
+async fn denied_hosts_are_not_fetched() {
+    let (upstream, proxy, files) = setup(
+        r#"allow_private_addresses: true
+  denied_hosts: ["127.0.0.1"]"#,
+    )
+    .await;
+
+    let (status, _) = add(&proxy, &files.url("/file")).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    assert!(upstream.requests().is_empty());
+}
+
+#[tokio::test]
+async fn torrent_urls_are_not_fetched_through_proxies() {
+    // Going through the proxy would bypass the checks of the addresses it connects to
+    let (upstream, proxy, files) = setup(
+        r#"allow_private_addresses: true
+http_client:
+  proxy: http://127.0.0.1:1"#,
+    )
+    .await;
+
+    let (status, response) = add(&proxy, &files.url("/file")).await;
+    assert_eq!(status, StatusCode::OK, "{response}");
+    assert!(forwarded_add(&upstream).0.is_none());
+}
@@ -0,0 +1,117 @@
+use reqwest::{
+    header::{LOCATION, SET_COOKIE},
+    StatusCode,
+};
+use serde_json::json;
+
+use transmission_proxy::testing::{totp_code, MockUpstream, TestProxy, SESSION_ID_HEADER};
+
+mod common;
+use common::Harness;
+
+const SECRET: &str = "JBSWY3DPEHPK3PXP";
+
+const TOTP_HEADER: &str = "X-Transmission-Proxy-Totp";
+
+async fn setup() -> (MockUpstream, TestProxy) {
+    Harness::new(
+        r#"
+acl:
+  rules: []
+  default_policy: allow
+"#,
+    )
+    .basic("rpc_basic_auth: always")
+    .user_with("alice", &format!("totp_secret: {SECRET}"))
+    .start()
+    .await
+}
+
+fn client() -> reqwest::Client {
+    reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .build()
+        .unwrap()
+}
+
+async fn session_stats(proxy: &TestProxy, code: Option<&str>) -> StatusCode {
+    let mut session_id = String::new();
+    loop {
+        let mut req = client()
+            .post(proxy.rpc_url())
+            .basic_auth("alice", Some("password"))
+            .header(SESSION_ID_HEADER, &session_id)
+            .json(&json!({ "method": "session-stats" }));
+        if let Some(code) = code {
+            req = req.header(TOTP_HEADER, code);
+        }
+
+        let res = req.send().await.unwrap();
+        if res.status() == StatusCode::CONFLICT && session_id.is_empty() {
+            session_id = res.headers()[SESSION_ID_HEADER]
+                .to_str()
+                .unwrap()
+                .to_owned();
+            continue;
+        }
+
+        return res.status();
+    }
+}
+
+#[tokio::test]
+async fn rpc_calls_need_the_code_header() {
+    let (_upstream, proxy) = setup().await;
+
+    assert_eq!(session_stats(&proxy, None).await, StatusCode::UNAUTHORIZED);
+    assert_eq!(
+        session_stats(&proxy, Some("000000")).await,
+        StatusCode::UNAUTHORIZED
+    );
+    assert_eq!(
+        session_stats(&proxy, Some(&totp_code(SECRET))).await,
+        StatusCode::OK
+    );
+}
+
+#[tokio::test]
+async fn login_asks_for_the_code() {
+    let (_upstream, proxy) = setup().await;
+    let url = proxy.url() + "/auth/basic";
+
+    let response = client()
+        .get(&url)
+        .query(&[("redirect_to", "/transmission/web/")])
+        .basic_auth("alice", Some("password"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    assert!(response.headers().get(SET_COOKIE).is_none());
+    assert!(response.text().await.unwrap().contains(r#"name="code""#));
+
+    // A wrong code shows the form again
+    let response = client()
+        .post(&url)
+        .basic_auth("alice", Some("password"))
+        .form(&[("code", "000000"), ("redirect_to", "/transmission/web/")])
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    assert!(response.text().await.unwrap().contains("Invalid code"));
+
+    let response = client()
+        .post(&url)
+        .basic_auth("alice", Some("password"))
+        .form(&[
+            ("code", totp_code(SECRET).as_str()),
+            ("redirect_to", "/transmission/web/"),
+        ])
+        .send()
+        .await
+        .unwrap();
+    assert!(response.status().is_redirection());
+    assert_eq!(response.headers()[LOCATION], "/transmission/web/");
+    assert!(response.headers().contains_key(SET_COOKIE));
+}
@@ -0,0 +1,107 @@
+use base64::Engine;
+use reqwest::StatusCode;
+use serde_json::json;
+
+use transmission_proxy::{
+    testing::{MockUpstream, TestProxy},
+    torrent::Torrent,
+};
+use transmission_rpc_client::types::MethodCall;
+
+mod common;
+use common::{rpc, Harness};
+
+async fn setup() -> (MockUpstream, TestProxy) {
+    Harness::new(
+        r#"
+acl:
+  rules:
+    - identities:
+        - provider: basic
+          name: alice
+      max_trackers: 3
+      max_tracker_tiers: 2
+"#,
+    )
+    .users(&["alice"])
+    .start()
+    .await
+}
+
+fn forwarded_add(upstream: &MockUpstream) -> (Option<String>, String) {
+    upstream
+        .requests()
+        .into_iter()
+        .find_map(|request| match request.call {
+            MethodCall::TorrentAdd { arguments } => Some((arguments.filename, arguments.metainfo)),
+            _ => None,
+        })
+        .expect("torrent-add was not forwarded")
+}
+
+#[tokio::test]
+async fn torrent_trackers_are_limited() {
+    let (upstream, proxy) = setup().await;
+
+    let metainfo = concat!(
+        "d8:announce14:http://a/annou",
+        "13:announce-listll14:http://a/annou14:http://b/annouel14:http://c/annou",
+        "14:http://d/annouel14:http://e/annouee",
+        "4:infod6:lengthi1e4:name1:a12:piece lengthi16384e6:pieces0:ee"
+    );
+    let b64 = &base64::engine::general_purpose::STANDARD;
+
+    let (status, _) = rpc(
+        &proxy,
+        Some("alice"),
+        json!({
+            "method": "torrent-add",
+            "arguments": {
+                "download-dir": "/data",
+                "metainfo": b64.encode(metainfo),
+                "paused": false,
+            },
+        }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (_, metainfo) = forwarded_add(&upstream);
+    let torrent: Torrent = serde_bencode::from_bytes(&b64.decode(metainfo).unwrap()).unwrap();
+
+    assert_eq!(torrent.announce.as_deref(), Some("http://a/annou"));
+    assert_eq!(
+        torrent.announce_list,
+        Some(vec![
+            vec!["http://a/annou".to_owned(), "http://b/annou".to_owned()],
+            vec!["http://c/annou".to_owned()],
+        ])
+    );
+}
+
+#[tokio::test]
+async fn magnet_trackers_are_limited() {
+    let (upstream, proxy) = setup().await;
+
+    let (status, _) = rpc(
+        &proxy,
+        Some("alice"),
+        json!({
+            "method": "torrent-add",
+            "arguments": {
+                "download-dir": "/data",
+                "metainfo": "",
+                "paused": false,
+                "filename": "magnet:?xt=urn:btih:abc&tr=http://a&tr=http://b&tr=http://c&dn=a",
+            },
+        }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (filename, _) = forwarded_add(&upstream);
+    assert_eq!(
+        filename.as_deref(),
+        Some("magnet:?xt=urn:btih:abc&tr=http://a&tr=http://b&dn=a")
+    );
+}
@@ -0,0 +1,118 @@
+use base64::Engine;
+use reqwest::StatusCode;
+use serde_json::{json, Value};
+
+use transmission_proxy::{
+    testing::{MockUpstream, TestProxy},
+    torrent::Torrent,
+};
+use transmission_rpc_client::types::MethodCall;
+
+mod common;
+use common::{rpc, Harness};
+
+async fn setup() -> (MockUpstream, TestProxy) {
+    Harness::new(
+        r#"
+acl:
+  rules:
+    - identities:
+        - provider: basic
+          name: alice
+      tracker_rules:
+        - from: "//a/"
+          to: "//b/"
+    - identities:
+        - provider: basic
+          name: bob
+      flatten_tracker_tiers: true
+      tracker_rules:
+        - from: "//a/"
+          to: "//b/"
+    - identities:
+        - provider: basic
+          name: carol
+      flatten_tracker_tiers: true
+"#,
+    )
+    .users(&["alice", "bob", "carol"])
+    .start()
+    .await
+}
+
+/// Add a torrent, returning the filename and metainfo the upstream received
+async fn add(
+    upstream: &MockUpstream,
+    proxy: &TestProxy,
+    user: &str,
+    arguments: Value,
+) -> (Option<String>, String) {
+    upstream.clear_requests();
+
+    let mut arguments = arguments;
+    arguments["download-dir"] = json!("/data");
+    arguments["paused"] = json!(false);
+
+    let (status, _) = rpc(
+        proxy,
+        Some(user),
+        json!({ "method": "torrent-add", "arguments": arguments }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+
+    upstream
+        .requests()
+        .into_iter()
+        .find_map(|request| match request.call {
+            MethodCall::TorrentAdd { arguments } => Some((arguments.filename, arguments.metainfo)),
+            _ => None,
+        })
+        .expect("torrent-add was not forwarded")
+}
+
+fn announce_list(metainfo: &str) -> Option<Vec<Vec<String>>> {
+    let b64 = &base64::engine::general_purpose::STANDARD;
+    let torrent: Torrent = serde_bencode::from_bytes(&b64.decode(metainfo).unwrap()).unwrap();
+    torrent.announce_list
+}
+
+#[tokio::test]
+async fn announce_lists_are_normalized() {
+    let (upstream, proxy) = setup().await;
+
+    let metainfo = base64::engine::general_purpose::STANDARD.encode(concat!(
+        "d8:announce14:http://a/annou",
+        "13:announce-listll14:http://a/annou14:http://b/annouel14:http://b/annouel14:http://c/annouee",
+        "4:infod6:lengthi1e4:name1:a12:piece lengthi16384e6:pieces0:ee"
+    ));
+    let b = "http://b/annou".to_owned();
+    let c = "http://c/annou".to_owned();
+
+    let (_, forwarded) = add(&upstream, &proxy, "alice", json!({ "metainfo": metainfo })).await;
+    assert_eq!(
+        announce_list(&forwarded),
+        Some(vec![vec![b.clone()], vec![c.clone()]])
+    );
+
+    let (_, forwarded) = add(&upstream, &proxy, "bob", json!({ "metainfo": metainfo })).await;
+    assert_eq!(announce_list(&forwarded), Some(vec![vec![b, c]]));
+}
+
+#[tokio::test]
+async fn magnet_trackers_are_deduplicated() {
+    let (upstream, proxy) = setup().await;
+
+    let magnet = "magnet:?xt=urn:btih:abc&tr=http://a&tr=http%3A%2F%2Fa&tr=http://b";
+    let (filename, _) = add(
+        &upstream,
+        &proxy,
+        "carol",
+        json!({ "metainfo": "", "filename": magnet }),
+    )
+    .await;
+    assert_eq!(
+        filename.as_deref(),
+        Some("magnet:?xt=urn:btih:abc&tr=http://a&tr=http://b")
+    );
+}
@@ -0,0 +1,181 @@
+use base64::Engine;
+use reqwest::StatusCode;
+use serde_json::json;
+
+use transmission_proxy::{testing::MockUpstream, torrent::Torrent};
+use transmission_rpc_client::types::MethodCall;
+
+mod common;
+use common::{rpc, Harness};
+
+fn harness(tracker_rules: &str) -> Harness {
+    Harness::new(&format!(
+        r#"
+tracker_rule_sets:
+  https:
+    - from: "^http://"
+      to: "https://"
+  mirror:
+    - from: "//a/"
+      to: "//b/"
+acl:
+  rules:
+    - identities:
+        - provider: basic
+          name: alice
+      tracker_rules: {tracker_rules}
+"#
+    ))
+    .users(&["alice"])
+}
+
+fn forwarded_tracker_add(upstream: &MockUpstream) -> Vec<String> {
+    upstream
+        .requests()
+        .into_iter()
+        .find_map(|request| match request.call {
+            MethodCall::TorrentSet { arguments } => Some(arguments.tracker_add),
+            _ => None,
+        })
+        .expect("torrent-set was not forwarded")
+}
+
+#[tokio::test]
+async fn rule_sets_are_expanded() {
+    let (upstream, proxy) = harness(r#"[https, { from: "/announce$", to: "/scrape" }, mirror]"#)
+        .start()
+        .await;
+
+    let (status, _) = rpc(
+        &proxy,
+        Some("alice"),
+        json!({
+            "method": "torrent-set",
+            "arguments": { "ids": [1], "trackerAdd": ["http://a/announce"] },
+        }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+
+    assert_eq!(
+        forwarded_tracker_add(&upstream),
+        vec!["https://b/scrape".to_owned()]
+    );
+}
+
+#[tokio::test]
+async fn rules_rewrite_url_components() {
+    let (upstream, proxy) = harness(
+        r#"
+        - from: "^http://(?P<host>[^/]+)/(?P<key>[0-9a-f]+)/announce$"
+          to: "http://${host}/announce?passkey=${key}"
+        - match_host: "^tracker\\.example\\.com$"
+          scheme: https
+          host: tracker.example.org
+          query:
+            passkey: "1234"
+            uid: null"#,
+    )
+    .start()
+    .await;
+
+    let (status, _) = rpc(
+        &proxy,
+        Some("alice"),
+        json!({
+            "method": "torrent-set",
+            "arguments": {
+                "ids": [1],
+                "trackerAdd": [
+                    "http://tracker.example.com/abcd/announce",
+                    "http://other.example.com/announce?uid=1",
+                ],
+            },
+        }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+
+    assert_eq!(
+        forwarded_tracker_add(&upstream),
+        vec![
+            "https://tracker.example.org/announce?passkey=1234".to_owned(),
+            "http://other.example.com/announce?uid=1".to_owned(),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn unknown_rule_sets_are_rejected() {
+    let upstream = MockUpstream::start().await.unwrap();
+
+    assert!(harness("[missing]")
+        .start_proxy(upstream.uri())
+        .await
+        .is_err());
+}
+
+#[tokio::test]
+async fn passkeys_are_set_per_user() {
+    let (upstream, proxy) = Harness::new(
+        r#"
+tracker_rule_sets:
+  passkeys:
+    - match_host: "^tracker\\.example\\.com$"
+      set_passkey:
+        segment: 0
+        passkeys:
+          - identity:
+              provider: basic
+              name: alice
+            passkey: alicekey
+acl:
+  rules:
+    - identities:
+        - provider: basic
+          name: alice
+        - provider: basic
+          name: bob
+      tracker_rules: [passkeys]
+"#,
+    )
+    .users(&["alice", "bob"])
+    .start()
+    .await;
+
+    let metainfo = concat!(
+        "d8:announce39:http://tracker.example.com/ownerkey/ann",
+        "4:infod6:lengthi1e4:name1:a12:piece lengthi16384e6:pieces0:ee"
+    );
+    let b64 = &base64::engine::general_purpose::STANDARD;
+    let add = json!({
+        "method": "torrent-add",
+        "arguments": {
+            "download-dir": "/data",
+            "metainfo": b64.encode(metainfo),
+            "paused": false,
+        },
+    });
+
+    for (user, announce) in [
+        ("alice", Some("http://tracker.example.com/alicekey/ann")),
+        ("bob", None),
+    ] {
+        upstream.clear_requests();
+
+        let (status, _) = rpc(&proxy, Some(user), add.clone()).await;
+        assert_eq!(status, StatusCode::OK);
+
+        let metainfo = upstream
+            .requests()
+            .into_iter()
+            .find_map(|request| match request.call {
+                MethodCall::TorrentAdd { arguments } => Some(arguments.metainfo),
+                _ => None,
+            })
+            .expect("torrent-add was not forwarded");
+        let torrent: Torrent = serde_bencode::from_bytes(&b64.decode(metainfo).unwrap()).unwrap();
+
+        assert_eq!(torrent.announce.as_deref(), announce, "{user}");
+    }
+}
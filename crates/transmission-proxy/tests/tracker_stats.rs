@@ -0,0 +1,114 @@
+use std::time::Duration;
+
+use reqwest::StatusCode;
+use serde_json::{json, Value};
+
+use transmission_proxy::testing::TestProxy;
+
+mod common;
+use common::Harness;
+
+/// Tracker stats of a torrent, as returned by the daemon. Scrapes are `(succeeded, result)`.
+fn tracker_stat(host: &str, announce_succeeded: bool, scrape: Option<(bool, &str)>) -> Value {
+    json!({
+        "id": 0,
+        "tier": 0,
+        "announce": format!("https://{host}/announce"),
+        "scrape": format!("https://{host}/scrape"),
+        "host": host,
+        "isBackup": false,
+        "hasAnnounced": true,
+        "lastAnnounceSucceeded": announce_succeeded,
+        "lastAnnounceResult": if announce_succeeded { "Success" } else { "Connection failed" },
+        "lastAnnouncePeerCount": 0,
+        "lastAnnounceTimedOut": false,
+        "hasScraped": scrape.is_some(),
+        "lastScrapeSucceeded": scrape.map_or(false, |(succeeded, _)| succeeded),
+        "lastScrapeResult": scrape.map_or("", |(_, result)| result),
+    })
+}
+
+async fn tracker_report(proxy: &TestProxy, user: &str) -> (StatusCode, Value) {
+    for _ in 0..50 {
+        let response = reqwest::Client::new()
+            .get(proxy.url() + "/stats/trackers")
+            .basic_auth(user, Some("password"))
+            .send()
+            .await
+            .unwrap();
+
+        // The first collection runs in the background after startup
+        if response.status() != StatusCode::SERVICE_UNAVAILABLE {
+            let status = response.status();
+            return (status, response.json().await.unwrap_or_default());
+        }
+
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+
+    panic!("no tracker report");
+}
+
+#[tokio::test]
+async fn tracker_stats_are_aggregated_by_host() {
+    let (_upstream, proxy) = Harness::new(
+        r#"
+tracker_stats:
+  enabled: true
+acl:
+  rules:
+    - identities:
+        - provider: basic
+          name: admin
+      admin: true
+    - identities:
+        - provider: basic
+          name: alice
+"#,
+    )
+    .users(&["admin", "alice"])
+    .torrents(vec![
+        json!({ "id": 1, "trackerStats": [tracker_stat("a.org", true, Some((true, "")))] }),
+        json!({ "id": 2, "trackerStats": [
+            tracker_stat("a.org", false, Some((false, "Connection failed"))),
+            tracker_stat("b.org", true, None),
+        ] }),
+        json!({ "id": 3, "trackerStats": [
+            tracker_stat("b.org", false, Some((false, "Tracker gave HTTP response code 404"))),
+        ] }),
+        json!({ "id": 4, "trackerStats": [] }),
+    ])
+    .start()
+    .await;
+
+    let (status, _) = tracker_report(&proxy, "alice").await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    let (status, mut report) = tracker_report(&proxy, "admin").await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(report["updated_at"].as_u64().unwrap() > 0);
+    report.as_object_mut().unwrap().remove("updated_at");
+
+    // Torrents without any working tracker, including those without trackers, have errors
+    assert_eq!(
+        report,
+        json!({
+            "torrents": 4,
+            "torrents_with_errors": 2,
+            "trackers": {
+                "a.org": {
+                    "torrents": 2,
+                    "announce_failures": 1,
+                    "scrape_failures": 1,
+                    "last_scrape_results": { "": 1, "Connection failed": 1 },
+                },
+                "b.org": {
+                    "torrents": 2,
+                    "announce_failures": 1,
+                    "scrape_failures": 1,
+                    "last_scrape_results": { "Tracker gave HTTP response code 404": 1 },
+                },
+            },
+        })
+    );
+}
@@ -0,0 +1,178 @@
+use base64::Engine;
+use reqwest::{Method, StatusCode};
+use serde_json::{json, Value};
+
+use transmission_proxy::testing::{MockUpstream, TestProxy};
+use transmission_rpc_client::types::MethodCall;
+
+mod common;
+use common::Harness;
+
+const METAINFO: &str = "d4:infod6:lengthi1e4:name1:a12:piece lengthi16384e6:pieces0:ee";
+
+async fn setup() -> (MockUpstream, TestProxy) {
+    setup_with("").await
+}
+
+async fn setup_with(uploads: &str) -> (MockUpstream, TestProxy) {
+    Harness::new(&format!(
+        r#"
+uploads:
+  enabled: true
+{uploads}
+acl:
+  rules:
+    - identities:
+        - provider: basic
+          name: alice
+      download_dir: /data/alice
+    - identities:
+        - provider: basic
+          name: bob
+      download_dir: /data/bob
+"#
+    ))
+    .users(&["alice", "bob"])
+    .start()
+    .await
+}
+
+async fn call(
+    proxy: &TestProxy,
+    method: Method,
+    path: &str,
+    user: &str,
+    build: impl FnOnce(reqwest::RequestBuilder) -> reqwest::RequestBuilder,
+) -> (StatusCode, Value) {
+    let req = reqwest::Client::new()
+        .request(method, proxy.url() + "/uploads" + path)
+        .basic_auth(user, Some("password"));
+
+    let res = build(req).send().await.unwrap();
+    (res.status(), res.json().await.unwrap_or(Value::Null))
+}
+
+#[tokio::test]
+async fn torrents_are_uploaded_in_chunks() {
+    let (upstream, proxy) = setup().await;
+
+    let (status, upload) = call(&proxy, Method::POST, "", "alice", |req| {
+        req.header("Upload-Length", METAINFO.len())
+    })
+    .await;
+    assert_eq!(status, StatusCode::CREATED);
+    assert_eq!(upload["offset"], json!(0));
+
+    let path = format!("/{}", upload["id"].as_str().unwrap());
+    let (head, tail) = METAINFO.split_at(20);
+
+    let (status, upload) = call(&proxy, Method::PATCH, &path, "alice", |req| {
+        req.header("Upload-Offset", 0).body(head)
+    })
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(upload["offset"], json!(20));
+    assert_eq!(
+        upload["progress"],
+        json!(20. / METAINFO.len() as f64),
+        "{upload}"
+    );
+    assert_eq!(upload["expires_in"], json!(3600));
+
+    // Chunks must resume where the upload stopped
+    let (status, _) = call(&proxy, Method::PATCH, &path, "alice", |req| {
+        req.header("Upload-Offset", 0).body(tail)
+    })
+    .await;
+    assert_eq!(status, StatusCode::CONFLICT);
+
+    // Other users don't see the upload
+    let (status, _) = call(&proxy, Method::GET, &path, "bob", |req| req).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    let (status, _) = call(
+        &proxy,
+        Method::POST,
+        &(path.clone() + "/add"),
+        "alice",
+        |req| req.json(&json!({ "download-dir": "/data/alice", "paused": false })),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CONFLICT);
+
+    let (status, upload) = call(&proxy, Method::PATCH, &path, "alice", |req| {
+        req.header("Upload-Offset", 20).body(tail)
+    })
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(upload["offset"], json!(METAINFO.len()));
+
+    let (status, response) = call(
+        &proxy,
+        Method::POST,
+        &(path.clone() + "/add"),
+        "alice",
+        |req| req.json(&json!({ "download-dir": "/data/alice", "paused": false })),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK, "{response}");
+
+    let add = upstream
+        .requests()
+        .into_iter()
+        .find_map(|request| match request.call {
+            MethodCall::TorrentAdd { arguments } => Some(arguments),
+            _ => None,
+        })
+        .expect("torrent-add was not forwarded");
+    assert_eq!(
+        add.metainfo,
+        base64::engine::general_purpose::STANDARD.encode(METAINFO)
+    );
+
+    // The upload is gone once added
+    let (status, _) = call(&proxy, Method::GET, &path, "alice", |req| req).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn uploads_are_limited_per_user() {
+    let (_upstream, proxy) = setup_with("  max_per_user: 1\n  max_user_size: 100").await;
+
+    let create = |user: &'static str, length: usize| {
+        let proxy = &proxy;
+        async move {
+            call(proxy, Method::POST, "", user, |req| {
+                req.header("Upload-Length", length)
+            })
+            .await
+        }
+    };
+
+    let (status, _) = create("alice", 101).await;
+    assert_eq!(status, StatusCode::PAYLOAD_TOO_LARGE);
+
+    let (status, _) = create("alice", 10).await;
+    assert_eq!(status, StatusCode::CREATED);
+
+    let (status, _) = create("alice", 10).await;
+    assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+
+    // The limits are per user
+    let (status, _) = create("bob", 10).await;
+    assert_eq!(status, StatusCode::CREATED);
+}
+
+#[tokio::test]
+async fn abandoned_uploads_are_removed() {
+    let (_upstream, proxy) = setup_with("  ttl: 1").await;
+
+    let (status, upload) = call(&proxy, Method::POST, "", "alice", |req| req).await;
+    assert_eq!(status, StatusCode::CREATED);
+    let path = format!("/{}", upload["id"].as_str().unwrap());
+
+    tokio::time::sleep(std::time::Duration::from_millis(2500)).await;
+
+    let (status, _) = call(&proxy, Method::GET, &path, "alice", |req| req).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
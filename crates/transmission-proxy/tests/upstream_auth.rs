@@ -0,0 +1,96 @@
+use base64::Engine;
+use reqwest::{header::AUTHORIZATION, StatusCode};
+use serde_json::json;
+
+use transmission_proxy::testing::{MockUpstream, TestProxy, SESSION_ID_HEADER};
+
+mod common;
+use common::Harness;
+
+async fn setup(passthrough: bool) -> (MockUpstream, TestProxy) {
+    Harness::new(&format!(
+        r#"
+upstream_auth:
+  passthrough: {passthrough}
+  credentials:
+    username: proxy
+    password: proxy-secret
+  users:
+    - identity:
+        provider: basic
+        name: alice
+      credentials:
+        username: alice-upstream
+        password: alice-secret
+acl:
+  rules:
+    - identities:
+        - provider: basic
+          name: alice
+        - provider: basic
+          name: bob
+"#
+    ))
+    .users(&["alice", "bob"])
+    .start()
+    .await
+}
+
+fn basic(username: &str, password: &str) -> String {
+    format!(
+        "Basic {}",
+        base64::engine::general_purpose::STANDARD.encode(format!("{username}:{password}"))
+    )
+}
+
+/// Authorization header received by the upstream for a call of the given user
+async fn upstream_authorization(
+    proxy: &TestProxy,
+    upstream: &MockUpstream,
+    user: &str,
+) -> Option<String> {
+    let response = reqwest::Client::new()
+        .post(proxy.rpc_url())
+        .basic_auth(user, Some("password"))
+        .header(SESSION_ID_HEADER, upstream.session_id())
+        .json(&json!({ "method": "torrent-get", "arguments": { "fields": ["id"] } }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    upstream
+        .last_headers()
+        .get(AUTHORIZATION)
+        .map(|value| value.to_str().unwrap().to_owned())
+}
+
+#[tokio::test]
+async fn identities_are_mapped_to_upstream_credentials() {
+    let (upstream, proxy) = setup(false).await;
+
+    assert_eq!(
+        upstream_authorization(&proxy, &upstream, "alice").await,
+        Some(basic("alice-upstream", "alice-secret"))
+    );
+
+    // Unmapped users get the credentials of the proxy
+    assert_eq!(
+        upstream_authorization(&proxy, &upstream, "bob").await,
+        Some(basic("proxy", "proxy-secret"))
+    );
+}
+
+#[tokio::test]
+async fn client_credentials_are_passed_through() {
+    let (upstream, proxy) = setup(true).await;
+
+    assert_eq!(
+        upstream_authorization(&proxy, &upstream, "alice").await,
+        Some(basic("alice-upstream", "alice-secret"))
+    );
+    assert_eq!(
+        upstream_authorization(&proxy, &upstream, "bob").await,
+        Some(basic("bob", "password"))
+    );
+}
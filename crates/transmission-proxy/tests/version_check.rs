@@ -0,0 +1,79 @@
+use std::time::Duration;
+
+use clap::Parser;
+use serde_json::json;
+
+use transmission_proxy::{testing::MockUpstream, Args};
+use transmission_rpc_client::types::{MethodCall, MethodName, Request};
+
+/// Run the proxy with the given configuration until it exits or a second elapses
+async fn run(config: &str, upstream: &MockUpstream) -> Option<String> {
+    let path = std::env::temp_dir().join(format!(
+        "transmission-proxy-version-check-{}.yaml",
+        upstream.uri().port_u16().unwrap()
+    ));
+    std::fs::write(&path, config).unwrap();
+
+    let args = Args::try_parse_from([
+        "transmission-proxy",
+        "--bind",
+        "http://127.0.0.1:0/transmission",
+        "--upstream",
+        &upstream.uri().to_string(),
+        "--config",
+        path.to_str().unwrap(),
+        "--secret-key",
+        "version-check",
+    ])
+    .unwrap();
+
+    let result = tokio::time::timeout(Duration::from_secs(1), transmission_proxy::run(args)).await;
+    std::fs::remove_file(&path).ok();
+
+    result.ok().map(|result| result.unwrap_err().to_string())
+}
+
+async fn upstream(rpc_version: i32) -> MockUpstream {
+    let upstream = MockUpstream::start().await.unwrap();
+    upstream.respond(
+        MethodName::SessionGet,
+        json!({ "version": "9.0.0", "rpc-version": rpc_version, "rpc-version-minimum": 14 }),
+    );
+    upstream
+}
+
+const STRICT: &str = r#"
+acl:
+  rules: []
+version_check:
+  strict: true
+"#;
+
+#[tokio::test]
+async fn strict_check_refuses_unsupported_versions() {
+    let upstream = upstream(99).await;
+
+    let err = run(STRICT, &upstream).await.expect("proxy started");
+    assert_eq!(err, "unsupported upstream rpc version 99");
+}
+
+#[tokio::test]
+async fn supported_versions_start() {
+    let upstream = upstream(17).await;
+
+    assert_eq!(run(STRICT, &upstream).await, None);
+    assert!(matches!(
+        upstream.requests()[..],
+        [Request {
+            call: MethodCall::SessionGet { .. },
+            ..
+        }]
+    ));
+}
+
+#[tokio::test]
+async fn unsupported_versions_only_warn_by_default() {
+    let upstream = upstream(99).await;
+
+    assert_eq!(run("acl:\n  rules: []\n", &upstream).await, None);
+}
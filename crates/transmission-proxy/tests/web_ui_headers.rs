@@ -0,0 +1,69 @@
+use transmission_proxy::testing::{MockUpstream, TestProxy};
+
+mod common;
+use common::Harness;
+
+async fn setup() -> (MockUpstream, TestProxy) {
+    Harness::new(
+        r#"
+acl:
+  rules:
+    - identities:
+        - provider: basic
+          name: alice
+      web_ui_headers:
+        X-Robots-Tag: noindex
+        X-Banner: staging
+    - identities:
+        - provider: basic
+          name: bob
+"#,
+    )
+    .users(&["alice", "bob"])
+    .start()
+    .await
+}
+
+async fn get(proxy: &TestProxy, user: &str, path: &str) -> reqwest::Response {
+    reqwest::Client::new()
+        .get(proxy.url() + path)
+        .basic_auth(user, Some("password"))
+        .send()
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn html_pages_get_the_headers_of_the_acl() {
+    let (_upstream, proxy) = setup().await;
+
+    let response = get(&proxy, "alice", "/web/").await;
+    assert_eq!(response.headers()["x-robots-tag"], "noindex");
+    assert_eq!(response.headers()["x-banner"], "staging");
+
+    // Other users and content types are left alone
+    let response = get(&proxy, "bob", "/web/").await;
+    assert!(response.headers().get("x-robots-tag").is_none());
+
+    let response = get(&proxy, "alice", "/web/headers").await;
+    assert!(response.headers().get("x-robots-tag").is_none());
+}
+
+#[tokio::test]
+async fn invalid_headers_are_rejected() {
+    let upstream = MockUpstream::start().await.unwrap();
+    let config = r#"
+acl:
+  rules:
+    - name: guests
+      web_ui_headers:
+        "Bad Header": value
+"#;
+
+    let err = Harness::new(config)
+        .start_proxy(upstream.uri())
+        .await
+        .err()
+        .expect("proxy started");
+    assert!(err.to_string().contains("ACL guests"));
+}
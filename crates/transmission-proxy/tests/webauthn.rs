@@ -0,0 +1,193 @@
+use reqwest::{
+    header::{COOKIE, SET_COOKIE},
+    StatusCode,
+};
+use serde_json::{json, Value};
+
+use transmission_proxy::testing::{Authenticator, MockUpstream, TestProxy};
+
+mod common;
+use common::{temp_path, write_users_file, Harness};
+
+fn harness() -> Harness {
+    Harness::new(
+        r#"
+acl:
+  rules: []
+  default_policy: allow
+"#,
+    )
+    .basic("rpc_basic_auth: always")
+    .providers("webauthn: { enabled: true }")
+}
+
+async fn setup() -> (MockUpstream, TestProxy) {
+    harness().users(&["alice"]).start().await
+}
+
+fn authenticator(proxy: &TestProxy) -> Authenticator {
+    Authenticator::new("127.0.0.1", &proxy.origin())
+}
+
+async fn post(
+    proxy: &TestProxy,
+    path: &str,
+    user: Option<&str>,
+    body: &Value,
+) -> reqwest::Response {
+    let mut req = reqwest::Client::new()
+        .post(proxy.url() + "/auth/webauthn/" + path)
+        .json(body);
+    if let Some(user) = user {
+        req = req.basic_auth(user, Some("password"));
+    }
+    req.send().await.unwrap()
+}
+
+/// Register a passkey for alice
+async fn register(proxy: &TestProxy, authenticator: &mut Authenticator) {
+    let response = post(proxy, "register/start", Some("alice"), &json!({})).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let options: Value = response.json().await.unwrap();
+    assert_eq!(options["publicKey"]["rp"]["id"], "127.0.0.1");
+
+    let response = post(
+        proxy,
+        "register/finish",
+        Some("alice"),
+        &authenticator.register(&options),
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::CREATED);
+}
+
+async fn login_options(proxy: &TestProxy) -> Value {
+    let response = post(proxy, "login/start", None, &json!({})).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    response.json().await.unwrap()
+}
+
+#[tokio::test]
+async fn registered_passkeys_log_in() {
+    let (_upstream, proxy) = setup().await;
+    let mut authenticator = authenticator(&proxy);
+    register(&proxy, &mut authenticator).await;
+
+    let options = login_options(&proxy).await;
+    let mut body = authenticator.login(&options);
+    body["redirect_to"] = json!("/transmission/web/");
+
+    let response = post(&proxy, "login/finish", None, &body).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let cookie = response.headers()[SET_COOKIE]
+        .to_str()
+        .unwrap()
+        .split(';')
+        .next()
+        .unwrap()
+        .to_owned();
+    let result: Value = response.json().await.unwrap();
+    assert_eq!(result["redirect_to"], "/transmission/web/");
+
+    // The session cookie is the same as other providers'
+    let response = reqwest::Client::new()
+        .get(proxy.url() + "/login")
+        .header(COOKIE, cookie)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.url().path(), "/transmission/web/");
+}
+
+#[tokio::test]
+async fn anonymous_users_cannot_register() {
+    let (_upstream, proxy) = setup().await;
+
+    let response = post(&proxy, "register/start", None, &json!({})).await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+    let response = reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .build()
+        .unwrap()
+        .get(proxy.url() + "/auth/webauthn/register")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::SEE_OTHER);
+}
+
+#[tokio::test]
+async fn challenges_cannot_be_replayed() {
+    let (_upstream, proxy) = setup().await;
+    let mut authenticator = authenticator(&proxy);
+    register(&proxy, &mut authenticator).await;
+
+    let options = login_options(&proxy).await;
+    let response = post(&proxy, "login/finish", None, &authenticator.login(&options)).await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let response = post(&proxy, "login/finish", None, &authenticator.login(&options)).await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn other_origins_are_rejected() {
+    let (_upstream, proxy) = setup().await;
+    let mut authenticator = authenticator(&proxy);
+    register(&proxy, &mut authenticator).await;
+
+    let mut phishing = Authenticator::new("127.0.0.1", "https://example.com");
+    let options = login_options(&proxy).await;
+    let response = post(&proxy, "login/finish", None, &phishing.login(&options)).await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+    // Unknown passkeys are rejected too
+    let options = login_options(&proxy).await;
+    let mut stranger = Authenticator::new("127.0.0.1", &proxy.origin());
+    let response = post(&proxy, "login/finish", None, &stranger.login(&options)).await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn registered_credential_ids_cannot_be_replaced() {
+    let (_upstream, proxy) = setup().await;
+    let mut authenticator = authenticator(&proxy);
+    register(&proxy, &mut authenticator).await;
+
+    // Registering the same credential id again would take over the passkey
+    let response = post(&proxy, "register/start", Some("alice"), &json!({})).await;
+    let options: Value = response.json().await.unwrap();
+    let response = post(
+        &proxy,
+        "register/finish",
+        Some("alice"),
+        &authenticator.register(&options),
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::CONFLICT);
+
+    let options = login_options(&proxy).await;
+    let response = post(&proxy, "login/finish", None, &authenticator.login(&options)).await;
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn passkeys_of_removed_users_are_rejected() {
+    let path = temp_path("webauthn-users");
+    write_users_file(&path, &["alice"], 0);
+    let (_upstream, proxy) = harness()
+        .basic(&format!("users_file: {}", path.display()))
+        .start()
+        .await;
+
+    let mut authenticator = authenticator(&proxy);
+    register(&proxy, &mut authenticator).await;
+
+    write_users_file(&path, &["bob"], 1);
+    let options = login_options(&proxy).await;
+    let response = post(&proxy, "login/finish", None, &authenticator.login(&options)).await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+    std::fs::remove_file(&path).unwrap();
+}
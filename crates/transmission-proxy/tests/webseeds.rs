@@ -0,0 +1,93 @@
+use base64::Engine;
+use reqwest::StatusCode;
+use serde_json::json;
+
+use transmission_proxy::{
+    testing::{MockUpstream, TestProxy},
+    torrent::{Torrent, UrlList},
+};
+use transmission_rpc_client::types::MethodCall;
+
+mod common;
+use common::{rpc, Harness};
+
+async fn setup() -> (MockUpstream, TestProxy) {
+    Harness::new(
+        r#"
+acl:
+  webseeds: rewrite
+  rules:
+    - identities:
+        - provider: basic
+          name: alice
+      webseeds: strip
+    - identities:
+        - provider: basic
+          name: bob
+      tracker_rules:
+        - from: "^http://"
+          to: "https://"
+        - match_host: "^internal$"
+          set_passkey:
+            passkeys: []
+"#,
+    )
+    .users(&["alice", "bob"])
+    .start()
+    .await
+}
+
+/// Add a torrent, returning the torrent the upstream received
+async fn add(upstream: &MockUpstream, proxy: &TestProxy, user: &str, metainfo: &str) -> Torrent {
+    upstream.clear_requests();
+    let b64 = &base64::engine::general_purpose::STANDARD;
+
+    let (status, _) = rpc(
+        proxy,
+        Some(user),
+        json!({
+            "method": "torrent-add",
+            "arguments": {
+                "download-dir": "/data",
+                "metainfo": b64.encode(metainfo),
+                "paused": false,
+            },
+        }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+
+    let metainfo = upstream
+        .requests()
+        .into_iter()
+        .find_map(|request| match request.call {
+            MethodCall::TorrentAdd { arguments } => Some(arguments.metainfo),
+            _ => None,
+        })
+        .expect("torrent-add was not forwarded");
+    serde_bencode::from_bytes(&b64.decode(metainfo).unwrap()).unwrap()
+}
+
+const INFO: &str = "4:infod6:lengthi1e4:name1:a12:piece lengthi16384e6:pieces0:ee";
+
+#[tokio::test]
+async fn webseeds_are_stripped() {
+    let (upstream, proxy) = setup().await;
+
+    let metainfo = format!("d9:httpseedsl14:http://a/seed1e8:url-list14:http://b/seed2{INFO}");
+    let torrent = add(&upstream, &proxy, "alice", &metainfo).await;
+    assert!(torrent.url_list.is_none());
+    assert!(torrent.httpseeds.is_none());
+}
+
+#[tokio::test]
+async fn webseeds_are_rewritten() {
+    let (upstream, proxy) = setup().await;
+
+    let metainfo = format!("d8:url-listl14:http://b/seed221:http://internal/seed3e{INFO}");
+    let torrent = add(&upstream, &proxy, "bob", &metainfo).await;
+    assert!(matches!(
+        torrent.url_list,
+        Some(UrlList::Many(urls)) if urls == ["https://b/seed2"]
+    ));
+}
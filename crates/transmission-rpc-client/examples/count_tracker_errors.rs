@@ -1,5 +1,6 @@
 use std::{borrow::Cow, process::exit};
-use transmission_rpc_client::types::TorrentGet;
+
+use futures_util::{pin_mut, TryStreamExt};
 
 struct Stats {
     /// Number of torrents with errors
@@ -15,18 +16,14 @@ async fn get_torrent_stats() -> anyhow::Result<Stats> {
     let mut tracker_errors = 0;
     let mut total = 0;
 
-    for torrent in &client
-        .torrent_get(TorrentGet {
-            fields: vec![
-                Cow::Borrowed("id"),
-                Cow::Borrowed("name"),
-                Cow::Borrowed("trackerStats"),
-            ],
-            ..Default::default()
-        })
-        .await?
-        .torrents
-    {
+    let torrents = client.torrents_iter(vec![
+        Cow::Borrowed("id"),
+        Cow::Borrowed("name"),
+        Cow::Borrowed("trackerStats"),
+    ]);
+    pin_mut!(torrents);
+
+    while let Some(torrent) = torrents.try_next().await? {
         if !torrent
             .tracker_stats
             .as_ref()
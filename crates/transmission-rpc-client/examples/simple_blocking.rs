@@ -0,0 +1,17 @@
+use std::borrow::Cow;
+use transmission_rpc_client::types::TorrentGet;
+
+fn main() -> anyhow::Result<()> {
+    let mut client =
+        transmission_rpc_client::blocking::Client::new("http://localhost:9091/transmission/rpc")?;
+
+    println!(
+        "{:#?}",
+        client.torrent_get(TorrentGet {
+            fields: vec![Cow::Borrowed("id"), Cow::Borrowed("trackerStats")],
+            ..Default::default()
+        })
+    );
+
+    Ok(())
+}
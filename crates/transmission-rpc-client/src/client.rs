@@ -1,3 +1,6 @@
+use std::{borrow::Cow, collections::VecDeque, time::Duration};
+
+use futures_util::{stream, Stream, TryStreamExt};
 use thiserror::Error;
 
 use crate::types::*;
@@ -5,6 +8,7 @@ use crate::types::*;
 pub struct Client {
     rpc_url: url::Url,
     client: reqwest::Client,
+    options: ClientOptions,
     state: ClientState,
     tag: i32,
 }
@@ -25,16 +29,56 @@ pub enum Error {
     HttpError(#[from] reqwest::Error),
 }
 
-const SESSION_ID_HEADER: &str = "X-Transmission-Session-Id";
+impl Error {
+    /// Returns true if the request may succeed if retried
+    pub fn is_transient(&self) -> bool {
+        match self {
+            Error::HttpError(err) => {
+                err.is_timeout()
+                    || err.is_connect()
+                    || err
+                        .status()
+                        .map_or(false, |status| status.is_server_error())
+            }
+            _ => false,
+        }
+    }
+
+    /// Returns true if the request never reached the daemon
+    fn is_connect(&self) -> bool {
+        matches!(self, Error::HttpError(err) if err.is_connect())
+    }
+}
+
+/// Returns true if sending the call twice has the same effect as sending it once
+fn is_idempotent(call: &MethodCall) -> bool {
+    matches!(
+        call,
+        MethodCall::TorrentGet { .. }
+            | MethodCall::TorrentStart { .. }
+            | MethodCall::TorrentStartNow { .. }
+            | MethodCall::TorrentStop { .. }
+            | MethodCall::SessionGet { .. }
+            | MethodCall::SessionSet { .. }
+            | MethodCall::SessionStats
+            | MethodCall::FreeSpace { .. }
+            | MethodCall::PortTest
+    )
+}
+
+pub(crate) const SESSION_ID_HEADER: &str = "X-Transmission-Session-Id";
+
+/// Initial tag for requests
+pub(crate) const INITIAL_TAG: i32 = 57680;
 
 #[derive(Debug, Clone, PartialEq, Eq)]
-enum ClientState {
+pub(crate) enum ClientState {
     NoSession,
     HasSession(String),
 }
 
 impl ClientState {
-    fn get_session_id(&self) -> Result<&str> {
+    pub(crate) fn get_session_id(&self) -> Result<&str> {
         match self {
             Self::HasSession(id) => Ok(id.as_str()),
             _ => Err(Error::NoSessionId),
@@ -48,6 +92,132 @@ impl Default for ClientState {
     }
 }
 
+/// Number of torrents fetched per request by [Client::torrents_iter]
+pub const TORRENTS_CHUNK_SIZE: usize = 256;
+
+/// Options shared by the async and blocking clients
+#[derive(Debug, Clone)]
+pub(crate) struct ClientOptions {
+    /// Number of retries on transient errors
+    pub retries: u32,
+    /// Delay before the first retry, doubled on each attempt
+    pub initial_backoff: Duration,
+    /// Maximum delay between two attempts
+    pub max_backoff: Duration,
+    /// Basic authentication credentials
+    pub credentials: Option<(String, Option<String>)>,
+}
+
+impl Default for ClientOptions {
+    fn default() -> Self {
+        Self {
+            retries: 0,
+            initial_backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_secs(30),
+            credentials: None,
+        }
+    }
+}
+
+impl ClientOptions {
+    /// Delay before the given retry attempt (starting at 0)
+    pub(crate) fn backoff(&self, attempt: u32) -> Duration {
+        self.initial_backoff
+            .saturating_mul(2u32.saturating_pow(attempt))
+            .min(self.max_backoff)
+    }
+
+    /// Returns true if a call which failed with the given error may be sent again. Calls which
+    /// aren't idempotent are only retried when they didn't reach the daemon.
+    pub(crate) fn may_retry(&self, call: &MethodCall, err: &Error, attempt: u32) -> bool {
+        attempt < self.retries && err.is_transient() && (is_idempotent(call) || err.is_connect())
+    }
+}
+
+/// Builder for configuring RPC clients
+#[derive(Debug, Clone)]
+pub struct ClientBuilder {
+    rpc_url: String,
+    connect_timeout: Option<Duration>,
+    timeout: Option<Duration>,
+    options: ClientOptions,
+}
+
+impl ClientBuilder {
+    pub fn new(rpc_url: impl Into<String>) -> Self {
+        Self {
+            rpc_url: rpc_url.into(),
+            connect_timeout: None,
+            timeout: None,
+            options: Default::default(),
+        }
+    }
+
+    /// Timeout for connecting to the daemon
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = Some(timeout);
+        self
+    }
+
+    /// Timeout for a complete request, from connecting to reading the response body
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Number of times a request is retried on transient errors (timeouts, connection errors
+    /// and server errors). Calls which aren't idempotent, such as torrent-add, are only retried
+    /// on connection errors.
+    pub fn retries(mut self, retries: u32) -> Self {
+        self.options.retries = retries;
+        self
+    }
+
+    /// Delay before the first retry, doubled after each failed attempt up to `max`
+    pub fn backoff(mut self, initial: Duration, max: Duration) -> Self {
+        self.options.initial_backoff = initial;
+        self.options.max_backoff = max;
+        self
+    }
+
+    /// Credentials for the daemon RPC authentication
+    pub fn basic_auth(mut self, username: impl Into<String>, password: Option<String>) -> Self {
+        self.options.credentials = Some((username.into(), password));
+        self
+    }
+
+    pub fn build(self) -> Result<Client> {
+        let mut builder = reqwest::Client::builder();
+
+        if let Some(timeout) = self.connect_timeout {
+            builder = builder.connect_timeout(timeout);
+        }
+
+        if let Some(timeout) = self.timeout {
+            builder = builder.timeout(timeout);
+        }
+
+        let mut client = Client::with_client(self.rpc_url.as_str(), builder.build()?)?;
+        client.options = self.options;
+        Ok(client)
+    }
+
+    #[cfg(feature = "blocking")]
+    pub fn build_blocking(self) -> Result<crate::blocking::Client> {
+        let mut builder = reqwest::blocking::Client::builder();
+
+        if let Some(timeout) = self.connect_timeout {
+            builder = builder.connect_timeout(timeout);
+        }
+
+        if let Some(timeout) = self.timeout {
+            builder = builder.timeout(timeout);
+        }
+
+        crate::blocking::Client::with_options(self.rpc_url.as_str(), builder.build()?, self.options)
+    }
+}
+
 macro_rules! rpc_call {
     ($self:ident, $call:expr, $resp:path) => {
         Ok(match $self.rpc_call($call).await?.arguments {
@@ -64,30 +234,76 @@ impl Client {
         Self::with_client(rpc_url, reqwest::Client::new())
     }
 
+    pub fn builder(rpc_url: impl Into<String>) -> ClientBuilder {
+        ClientBuilder::new(rpc_url)
+    }
+
     pub fn with_client(rpc_url: impl reqwest::IntoUrl, client: reqwest::Client) -> Result<Self> {
         Ok(Self {
             rpc_url: rpc_url.into_url()?,
             client,
+            options: Default::default(),
             state: Default::default(),
-            tag: 57680,
+            tag: INITIAL_TAG,
         })
     }
 
-    async fn rpc_call(&mut self, call: MethodCall) -> Result<Response> {
+    fn post(&self) -> reqwest::RequestBuilder {
+        let request = self.client.post(self.rpc_url.clone());
+
+        match &self.options.credentials {
+            Some((username, password)) => request.basic_auth(username, password.as_ref()),
+            None => request,
+        }
+    }
+
+    fn update_session(&mut self, response: &reqwest::Response) -> Result<()> {
+        if let Some(session_id_value) = response.headers().get(SESSION_ID_HEADER) {
+            self.state = ClientState::HasSession(session_id_value.to_str()?.to_owned());
+        }
+
+        Ok(())
+    }
+
+    async fn try_rpc_call(&mut self, request: &Request) -> Result<Response> {
         // Check that we have a session id
         match self.state {
             ClientState::NoSession => {
-                let response = self.client.post(self.rpc_url.clone()).send().await?;
-                if let Some(session_id_value) = response.headers().get(SESSION_ID_HEADER) {
-                    self.state = ClientState::HasSession(session_id_value.to_str()?.to_owned());
-                }
+                let response = self.post().send().await?;
+                self.update_session(&response)?;
             }
             ClientState::HasSession(_) => {}
         }
 
-        // Get session id
-        let session_id = self.state.get_session_id()?;
+        let mut response = self
+            .post()
+            .header(SESSION_ID_HEADER, self.state.get_session_id()?)
+            .json(request)
+            .send()
+            .await?;
+
+        // The session id expired, try again with the new one
+        if response.status() == reqwest::StatusCode::CONFLICT {
+            self.update_session(&response)?;
+
+            response = self
+                .post()
+                .header(SESSION_ID_HEADER, self.state.get_session_id()?)
+                .json(request)
+                .send()
+                .await?;
+        }
+
+        let response: Response = response.error_for_status()?.json().await?;
+
+        if response.tag != request.tag {
+            return Err(Error::TagMismatch);
+        }
+
+        Ok(response)
+    }
 
+    async fn rpc_call(&mut self, call: MethodCall) -> Result<Response> {
         // Build request
         let request = Request {
             call,
@@ -97,21 +313,16 @@ impl Client {
         // Increment tag for next requests
         self.tag += 1;
 
-        let response: Response = self
-            .client
-            .post(self.rpc_url.clone())
-            .header(SESSION_ID_HEADER, session_id)
-            .json(&request)
-            .send()
-            .await?
-            .json()
-            .await?;
-
-        if response.tag != request.tag {
-            return Err(Error::TagMismatch);
+        let mut attempt = 0;
+        loop {
+            match self.try_rpc_call(&request).await {
+                Err(err) if self.options.may_retry(&request.call, &err, attempt) => {
+                    tokio::time::sleep(self.options.backoff(attempt)).await;
+                    attempt += 1;
+                }
+                result => return result,
+            }
         }
-
-        Ok(response)
     }
 
     pub async fn session_get(&mut self, arguments: SessionGet) -> Result<SessionArguments> {
@@ -122,6 +333,20 @@ impl Client {
         )
     }
 
+    pub async fn session_stats(&mut self) -> Result<SessionStats> {
+        rpc_call!(self, MethodCall::SessionStats, ResponseKind::SessionStats)
+    }
+
+    pub async fn free_space(&mut self, path: impl Into<String>) -> Result<FreeSpaceResult> {
+        rpc_call!(
+            self,
+            MethodCall::FreeSpace {
+                arguments: FreeSpace { path: path.into() },
+            },
+            ResponseKind::FreeSpace
+        )
+    }
+
     pub async fn torrent_get(&mut self, arguments: TorrentGet) -> Result<Torrents> {
         rpc_call!(
             self,
@@ -129,4 +354,275 @@ impl Client {
             ResponseKind::Torrents
         )
     }
+
+    /// Stream all torrents with the given fields
+    ///
+    /// The list of torrent ids is fetched first, then the torrents are requested in chunks of
+    /// [TORRENTS_CHUNK_SIZE] ids, so the whole torrent list is never held in memory at once.
+    pub fn torrents_iter(
+        &mut self,
+        fields: Vec<Cow<'static, str>>,
+    ) -> impl Stream<Item = Result<Torrent>> + '_ {
+        stream::try_unfold((self, None::<VecDeque<TorrentId>>), move |(client, ids)| {
+            let fields = fields.clone();
+
+            async move {
+                let mut ids = match ids {
+                    Some(ids) => ids,
+                    None => client
+                        .torrent_get(TorrentGet {
+                            fields: vec![Cow::Borrowed("id")],
+                            ..Default::default()
+                        })
+                        .await?
+                        .torrents
+                        .into_iter()
+                        .filter_map(|torrent| torrent.id)
+                        .collect(),
+                };
+
+                if ids.is_empty() {
+                    return Ok::<_, Error>(None);
+                }
+
+                let chunk: Vec<_> = ids.drain(..ids.len().min(TORRENTS_CHUNK_SIZE)).collect();
+                let torrents = client
+                    .torrent_get(TorrentGet {
+                        ids: Some(TorrentIds::Ids(chunk)),
+                        fields,
+                        ..Default::default()
+                    })
+                    .await?
+                    .torrents;
+
+                Ok(Some((
+                    stream::iter(torrents.into_iter().map(Ok)),
+                    (client, Some(ids)),
+                )))
+            }
+        })
+        .try_flatten()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    };
+
+    use tokio::{
+        io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader},
+        net::{TcpListener, TcpStream},
+    };
+
+    use super::*;
+
+    /// Start a daemon failing every call with a 500 error, and count the requests it receives
+    async fn failing_daemon() -> (String, Arc<AtomicUsize>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/transmission/rpc", listener.local_addr().unwrap());
+        let requests = Arc::new(AtomicUsize::new(0));
+
+        let count = requests.clone();
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                count.fetch_add(1, Ordering::SeqCst);
+
+                // Read the request up to its body, which isn't needed
+                let mut buf = vec![0; 8192];
+                let _ = stream.read(&mut buf).await;
+
+                let _ = stream
+                    .write_all(
+                        b"HTTP/1.1 500 Internal Server Error\r\n\
+                          X-Transmission-Session-Id: session\r\n\
+                          Content-Length: 0\r\n\
+                          Connection: close\r\n\r\n",
+                    )
+                    .await;
+            }
+        });
+
+        (url, requests)
+    }
+
+    fn client(url: &str) -> Client {
+        Client::builder(url)
+            .retries(2)
+            .backoff(Duration::from_millis(1), Duration::from_millis(1))
+            .build()
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn idempotent_calls_are_retried() {
+        let (url, requests) = failing_daemon().await;
+
+        let err = client(&url).session_stats().await.unwrap_err();
+        assert!(err.is_transient());
+
+        // Session handshake, then the first attempt and two retries
+        assert_eq!(requests.load(Ordering::SeqCst), 4);
+    }
+
+    #[tokio::test]
+    async fn other_calls_are_sent_once() {
+        let (url, requests) = failing_daemon().await;
+
+        let arguments: TorrentAdd = serde_json::from_value(serde_json::json!({
+            "download-dir": "/downloads",
+            "filename": "magnet:?xt=urn:btih:0000000000000000000000000000000000000000",
+        }))
+        .unwrap();
+
+        let err = client(&url)
+            .rpc_call(MethodCall::TorrentAdd { arguments })
+            .await
+            .unwrap_err();
+        assert!(err.is_transient());
+
+        // Session handshake, then a single attempt
+        assert_eq!(requests.load(Ordering::SeqCst), 2);
+    }
+
+    /// Answer one torrent-get call like a daemon holding torrents with the given ids
+    async fn serve_torrents(
+        mut stream: TcpStream,
+        ids: &[i32],
+        calls: &Mutex<Vec<TorrentGet>>,
+    ) -> std::io::Result<()> {
+        let mut reader = BufReader::new(&mut stream);
+
+        let mut has_session = false;
+        let mut content_length = 0;
+        loop {
+            let mut line = String::new();
+            reader.read_line(&mut line).await?;
+            if line == "\r\n" || line.is_empty() {
+                break;
+            }
+
+            let line = line.to_ascii_lowercase();
+            if let Some(length) = line.strip_prefix("content-length:") {
+                content_length = length.trim().parse().unwrap();
+            }
+            has_session |= line.starts_with("x-transmission-session-id:");
+        }
+
+        let mut body = vec![0; content_length];
+        reader.read_exact(&mut body).await?;
+
+        let response = if has_session {
+            let request: Request = serde_json::from_slice(&body).unwrap();
+            let MethodCall::TorrentGet { arguments } = request.call else {
+                panic!("unexpected call {:?}", request.call);
+            };
+
+            let torrents: Vec<_> = match &arguments.ids {
+                None => ids
+                    .iter()
+                    .map(|id| serde_json::json!({ "id": id }))
+                    .collect(),
+                Some(TorrentIds::Ids(requested)) => requested
+                    .iter()
+                    .map(|id| {
+                        let TorrentId::Id(id) = id else {
+                            panic!("unexpected id {id:?}");
+                        };
+                        serde_json::json!({ "id": id, "name": format!("torrent {id}") })
+                    })
+                    .collect(),
+                Some(ids) => panic!("unexpected ids {ids:?}"),
+            };
+            calls.lock().unwrap().push(arguments);
+
+            let body = serde_json::json!({
+                "result": "success",
+                "tag": request.tag,
+                "arguments": { "torrents": torrents },
+            })
+            .to_string();
+            format!(
+                "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                body.len()
+            )
+        } else {
+            "HTTP/1.1 409 Conflict\r\nX-Transmission-Session-Id: session\r\n\
+             Content-Length: 0\r\nConnection: close\r\n\r\n"
+                .to_owned()
+        };
+
+        stream.write_all(response.as_bytes()).await
+    }
+
+    /// Start a daemon holding `count` torrents, and record the torrent-get calls it receives
+    async fn torrent_daemon(count: i32) -> (String, Arc<Mutex<Vec<TorrentGet>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/transmission/rpc", listener.local_addr().unwrap());
+        let calls = Arc::new(Mutex::new(Vec::new()));
+
+        let received = calls.clone();
+        tokio::spawn(async move {
+            let ids: Vec<_> = (1..=count).collect();
+            while let Ok((stream, _)) = listener.accept().await {
+                let _ = serve_torrents(stream, &ids, &received).await;
+            }
+        });
+
+        (url, calls)
+    }
+
+    fn fields() -> Vec<Cow<'static, str>> {
+        vec![Cow::Borrowed("id"), Cow::Borrowed("name")]
+    }
+
+    #[tokio::test]
+    async fn torrents_iter_ends_without_torrents() {
+        let (url, calls) = torrent_daemon(0).await;
+
+        let mut client = Client::new(url.as_str()).unwrap();
+        let torrents: Vec<_> = client.torrents_iter(fields()).try_collect().await.unwrap();
+        assert!(torrents.is_empty());
+
+        // Only the ids were requested
+        let calls = calls.lock().unwrap();
+        assert_eq!(calls.len(), 1);
+        assert_eq!(calls[0].ids, None);
+        assert_eq!(calls[0].fields, vec![Cow::Borrowed("id")]);
+    }
+
+    #[tokio::test]
+    async fn torrents_iter_fetches_torrents_in_chunks() {
+        let count = TORRENTS_CHUNK_SIZE as i32 * 2 + 1;
+        let (url, calls) = torrent_daemon(count).await;
+
+        let mut client = Client::new(url.as_str()).unwrap();
+        let torrents: Vec<_> = client.torrents_iter(fields()).try_collect().await.unwrap();
+
+        let ids: Vec<_> = torrents
+            .iter()
+            .filter_map(|torrent| torrent.id.clone())
+            .collect();
+        assert_eq!(ids, (1..=count).map(TorrentId::Id).collect::<Vec<_>>());
+        assert_eq!(
+            torrents[count as usize - 1].name,
+            format!("torrent {count}")
+        );
+
+        // The ids, then one call per chunk with the requested fields
+        let calls = calls.lock().unwrap();
+        let chunks: Vec<_> = calls[1..]
+            .iter()
+            .map(|call| {
+                assert_eq!(call.fields, fields());
+                match &call.ids {
+                    Some(TorrentIds::Ids(ids)) => ids.len(),
+                    ids => panic!("unexpected ids {ids:?}"),
+                }
+            })
+            .collect();
+        assert_eq!(chunks, vec![TORRENTS_CHUNK_SIZE, TORRENTS_CHUNK_SIZE, 1]);
+    }
 }
@@ -84,6 +84,111 @@ pub enum TorrentIds {
     Set(TorrentIdSet),
 }
 
+/// Conversions of an enum from and to its integer values, values added by newer daemons being
+/// kept in a catch-all variant
+macro_rules! int_enum {
+    ($t:ident { $($variant:ident = $value:expr),* $(,)? } else $unknown:ident) => {
+        impl From<i32> for $t {
+            fn from(value: i32) -> Self {
+                match value {
+                    $($value => Self::$variant,)*
+                    other => Self::$unknown(other),
+                }
+            }
+        }
+
+        impl From<$t> for i32 {
+            fn from(value: $t) -> Self {
+                match value {
+                    $($t::$variant => $value,)*
+                    $t::$unknown(value) => value,
+                }
+            }
+        }
+    };
+}
+
+/// Status of a torrent
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    Hash,
+    Serialize,
+    Deserialize,
+    strum::Display,
+    strum::EnumString,
+)]
+#[serde(from = "i32", into = "i32")]
+#[strum(serialize_all = "kebab-case")]
+pub enum TorrentStatus {
+    /// Torrent is stopped
+    Stopped,
+    /// Torrent is queued to verify local data
+    CheckWait,
+    /// Torrent is verifying local data
+    Checking,
+    /// Torrent is queued to download
+    DownloadWait,
+    /// Torrent is downloading
+    Downloading,
+    /// Torrent is queued to seed
+    SeedWait,
+    /// Torrent is seeding
+    Seeding,
+    /// Status unknown to this version of the client
+    #[strum(to_string = "unknown")]
+    Unknown(i32),
+}
+
+int_enum!(TorrentStatus {
+    Stopped = 0,
+    CheckWait = 1,
+    Checking = 2,
+    DownloadWait = 3,
+    Downloading = 4,
+    SeedWait = 5,
+    Seeding = 6,
+} else Unknown);
+
+/// State of a tracker announce or scrape
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    Hash,
+    Serialize,
+    Deserialize,
+    strum::Display,
+    strum::EnumString,
+)]
+#[serde(from = "i32", into = "i32")]
+#[strum(serialize_all = "kebab-case")]
+pub enum TrackerState {
+    /// Won't announce or scrape this torrent to this tracker
+    Inactive,
+    /// Will announce or scrape this torrent to this tracker
+    Waiting,
+    /// Ready to announce or scrape, waiting for a slot
+    Queued,
+    /// Announce or scrape in progress
+    Active,
+    /// State unknown to this version of the client
+    #[strum(to_string = "unknown")]
+    Unknown(i32),
+}
+
+int_enum!(TrackerState {
+    Inactive = 0,
+    Waiting = 1,
+    Queued = 2,
+    Active = 3,
+} else Unknown);
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Torrent {
@@ -94,22 +199,77 @@ pub struct Torrent {
     pub name: String,
     #[serde(default)]
     pub download_dir: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hash_string: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub labels: Option<Vec<String>>,
+    /// Name of the user who added the torrent, synthesized by transmission-proxy
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub added_by: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub status: Option<TorrentStatus>,
+    /// Bytes of the wanted files
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub size_when_done: Option<i64>,
+    /// 0 if the torrent is fine, 1 for a tracker warning, 2 for a tracker error and 3 for a
+    /// local error, e.g. missing files
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<i32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error_string: Option<String>,
+    /// The torrent has not transferred anything for a while
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub is_stalled: Option<bool>,
 
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub trackers: Option<Vec<Tracker>>,
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub tracker_stats: Option<Vec<TrackerStats>>,
 
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub files: Option<Vec<TorrentFile>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub file_stats: Option<Vec<FileStats>>,
+    /// Priority of each file, in the same order as `files`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub priorities: Option<Vec<i32>>,
+    /// Wanted flag of each file, in the same order as `files`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub wanted: Option<Vec<IntBool>>,
+
+    #[serde(flatten)]
+    extra: HashMap<String, serde_json::Value>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TorrentFile {
+    pub bytes_completed: i64,
+    pub length: i64,
+    /// Path of the file, relative to the torrent download dir
+    pub name: String,
+
     #[serde(flatten)]
     extra: HashMap<String, serde_json::Value>,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FileStats {
+    pub bytes_completed: i64,
+    pub wanted: bool,
+    pub priority: i32,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Tracker {
     pub id: i32,
     pub tier: i32,
     pub announce: String,
     pub scrape: String,
+    /// Name of the tracker site (Transmission 4.0+)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sitename: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -120,16 +280,43 @@ pub struct TrackerStats {
     pub announce: String,
     pub scrape: String,
 
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub announce_state: Option<TrackerState>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub download_count: Option<i32>,
     pub has_announced: bool,
     pub has_scraped: bool,
     pub host: String,
     pub is_backup: bool,
     pub last_announce_peer_count: i32,
     pub last_announce_result: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_announce_start_time: Option<i64>,
     pub last_announce_succeeded: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_announce_time: Option<i64>,
     pub last_announce_timed_out: bool,
     pub last_scrape_result: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_scrape_start_time: Option<i64>,
     pub last_scrape_succeeded: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_scrape_time: Option<i64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_scrape_timed_out: Option<IntBool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub leecher_count: Option<i32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub next_announce_time: Option<i64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub next_scrape_time: Option<i64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scrape_state: Option<TrackerState>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seeder_count: Option<i32>,
+    /// Name of the tracker site (Transmission 4.0+)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sitename: Option<String>,
 
     #[serde(flatten)]
     extra: HashMap<String, serde_json::Value>,
@@ -142,6 +329,7 @@ pub enum ResponseKind {
     Torrents(Torrents),
     Session(SessionArguments),
     SessionStats(SessionStats),
+    FreeSpace(FreeSpaceResult),
     Other {
         #[serde(flatten)]
         extra: serde_json::Value,
@@ -155,6 +343,16 @@ pub enum ResponseStatus {
     Failure(String),
 }
 
+impl ResponseStatus {
+    /// Returns true if the call succeeded, which the daemon reports as the "success" string
+    pub fn is_success(&self) -> bool {
+        match self {
+            Self::Success => true,
+            Self::Failure(result) => result == "success",
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct RawResponse {
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -249,7 +447,7 @@ pub struct SessionArguments {
     pub rpc_version_minimum: i32,
     /// the current RPC API version in a semver-compatible string
     #[serde(default, skip_serializing_if = "Option::is_none")]
-    pub rpc_version_semver: Option<i32>,
+    pub rpc_version_semver: Option<String>,
     /// the current RPC API version
     pub rpc_version: i32,
     /// whether or not to call the added script
@@ -317,11 +515,11 @@ pub struct SessionStats {
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Stats {
-    pub uploaded_bytes: i32,
-    pub downloaded_bytes: i32,
-    pub files_added: i32,
-    pub session_count: i32,
-    pub seconds_active: i32,
+    pub uploaded_bytes: i64,
+    pub downloaded_bytes: i64,
+    pub files_added: i64,
+    pub session_count: i64,
+    pub seconds_active: i64,
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -459,7 +657,10 @@ pub struct TorrentAdd {
     pub filename: Option<String>,
     #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub labels: Vec<String>,
+    /// Base64-encoded torrent file, used instead of `filename` if not empty
+    #[serde(default, skip_serializing_if = "String::is_empty")]
     pub metainfo: String,
+    #[serde(default)]
     pub paused: IntBool,
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub peer_limit: Option<i32>,
@@ -564,18 +765,26 @@ pub struct TorrentSet {
     pub seed_ratio_mode: Option<i32>,
     #[serde(default, skip_serializing_if = "Vec::is_empty", rename = "trackerAdd")]
     pub tracker_add: Vec<String>,
+    /// Ids of the trackers to remove
     #[serde(
         default,
         skip_serializing_if = "Vec::is_empty",
         rename = "trackerRemove"
     )]
-    pub tracker_remove: Vec<String>,
+    pub tracker_remove: Vec<i32>,
     #[serde(
         default,
         skip_serializing_if = "Vec::is_empty",
         rename = "trackerReplace"
     )]
     pub tracker_replace: Vec<String>,
+    /// Announce URLs, one per line with a blank line between tiers (RPC 17+)
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        rename = "trackerList"
+    )]
+    pub tracker_list: Option<String>,
     #[serde(
         default,
         skip_serializing_if = "Option::is_none",
@@ -699,6 +908,22 @@ pub struct FreeSpace {
     pub path: String,
 }
 
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct FreeSpaceResult {
+    pub path: String,
+    /// size, in bytes, of the free space in that directory
+    pub size_bytes: i64,
+    /// total capacity, in bytes, of that directory
+    #[serde(
+        rename = "total_size",
+        alias = "total-size",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub total_size: Option<i64>,
+}
+
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize, strum::EnumDiscriminants)]
 #[serde(rename_all = "kebab-case", tag = "method")]
 #[strum_discriminants(
@@ -706,6 +931,10 @@ pub struct FreeSpace {
     name(MethodName),
     serde(rename_all = "kebab-case")
 )]
+#[cfg_attr(
+    feature = "schemars",
+    strum_discriminants(derive(schemars::JsonSchema))
+)]
 pub enum MethodCall {
     TorrentStart {
         arguments: TorrentAction,
@@ -772,5 +1001,6 @@ pub enum MethodCall {
 pub struct Request {
     #[serde(flatten)]
     pub call: MethodCall,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub tag: Option<i32>,
 }
@@ -0,0 +1,572 @@
+//! Serde round-trips of the RPC types, so changes to the types don't silently alter the wire
+//! format
+
+use std::path::PathBuf;
+
+use proptest::{prelude::*, strategy::Union};
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::{json, Map, Value};
+
+use transmission_rpc_client::types::{
+    FreeSpaceResult, RawResponse, Request, Response, ResponseKind, SessionArguments, SessionStats,
+    TorrentStatus, Torrents, TrackerState,
+};
+
+/// Daemon versions the fixtures were written for
+const VERSIONS: &[&str] = &["transmission-3.00", "transmission-4.0"];
+
+fn fixture(version: &str, name: &str) -> Value {
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures")
+        .join(version)
+        .join(format!("{name}.json"));
+
+    serde_json::from_str(&std::fs::read_to_string(&path).unwrap())
+        .unwrap_or_else(|err| panic!("{}: {err}", path.display()))
+}
+
+/// Decode a value and encode it back
+fn round_trip<T: Serialize + DeserializeOwned>(value: &Value) -> Result<Value, serde_json::Error> {
+    serde_json::to_value(serde_json::from_value::<T>(value.clone())?)
+}
+
+/// Arguments of a fixture response, decoded as T
+fn arguments<T: Serialize + DeserializeOwned>(response: &Value) -> (Value, T) {
+    let raw: RawResponse = serde_json::from_value(response.clone()).unwrap();
+    assert!(raw.result.is_success());
+
+    let arguments = raw.arguments.unwrap();
+    let typed = serde_json::from_value(arguments.clone()).unwrap();
+    (arguments, typed)
+}
+
+#[test]
+fn fixture_requests_round_trip() {
+    for version in VERSIONS {
+        let Value::Array(requests) = fixture(version, "requests") else {
+            panic!("{version}: requests should be a list");
+        };
+
+        for request in requests {
+            assert_eq!(
+                round_trip::<Request>(&request).unwrap(),
+                request,
+                "{version}"
+            );
+        }
+    }
+}
+
+#[test]
+fn fixture_torrents_round_trip() {
+    for version in VERSIONS {
+        let (arguments, torrents) = arguments::<Torrents>(&fixture(version, "torrent-get"));
+        assert_eq!(serde_json::to_value(&torrents).unwrap(), arguments);
+        insta::with_settings!({ sort_maps => true }, {
+            insta::assert_json_snapshot!(format!("{version}-torrent-get"), torrents);
+        });
+    }
+}
+
+#[test]
+fn fixture_session_stats_round_trip() {
+    for version in VERSIONS {
+        let (arguments, stats) = arguments::<SessionStats>(&fixture(version, "session-stats"));
+        assert_eq!(serde_json::to_value(&stats).unwrap(), arguments);
+    }
+}
+
+#[test]
+fn fixture_free_space_round_trip() {
+    for version in VERSIONS {
+        let (arguments, free_space) = arguments::<FreeSpaceResult>(&fixture(version, "free-space"));
+        assert_eq!(serde_json::to_value(&free_space).unwrap(), arguments);
+        insta::with_settings!({ sort_maps => true }, {
+            insta::assert_json_snapshot!(format!("{version}-free-space"), free_space);
+        });
+    }
+}
+
+#[test]
+fn fixture_session_get_decodes() {
+    for version in VERSIONS {
+        // Only some session fields are modeled, the others are dropped
+        let (_, session) = arguments::<SessionArguments>(&fixture(version, "session-get"));
+        insta::with_settings!({ sort_maps => true }, {
+            insta::assert_json_snapshot!(format!("{version}-session-get"), session);
+        });
+    }
+}
+
+#[test]
+fn older_tracker_stats_decode() {
+    // Tracker stats of daemons which predate the start times and the peer counts
+    let torrents = json!({
+        "torrents": [{
+            "id": 1,
+            "name": "a",
+            "downloadDir": "/downloads",
+            "status": 9,
+            "trackerStats": [{
+                "id": 0,
+                "tier": 0,
+                "announce": "http://tracker.example.com/announce",
+                "scrape": "http://tracker.example.com/scrape",
+                "hasAnnounced": true,
+                "hasScraped": false,
+                "host": "http://tracker.example.com:80",
+                "isBackup": false,
+                "lastAnnouncePeerCount": 3,
+                "lastAnnounceResult": "Success",
+                "lastAnnounceSucceeded": true,
+                "lastAnnounceTimedOut": false,
+                "lastScrapeResult": "",
+                "lastScrapeSucceeded": false,
+                "announceState": 7,
+            }],
+        }],
+    });
+
+    let decoded: Torrents = serde_json::from_value(torrents.clone()).unwrap();
+    let torrent = &decoded.torrents[0];
+    assert_eq!(torrent.status, Some(TorrentStatus::Unknown(9)));
+
+    let stats = &torrent.tracker_stats.as_ref().unwrap()[0];
+    assert_eq!(stats.announce_state, Some(TrackerState::Unknown(7)));
+    assert_eq!(stats.seeder_count, None);
+
+    assert_eq!(serde_json::to_value(&decoded).unwrap(), torrents);
+}
+
+#[test]
+fn fixture_responses_pick_their_kind() {
+    for version in VERSIONS {
+        let kind = |name| {
+            serde_json::from_value::<Response>(fixture(version, name))
+                .unwrap()
+                .arguments
+                .unwrap()
+        };
+
+        assert!(matches!(kind("torrent-get"), ResponseKind::Torrents(_)));
+        assert!(matches!(kind("session-get"), ResponseKind::Session(_)));
+        assert!(matches!(
+            kind("session-stats"),
+            ResponseKind::SessionStats(_)
+        ));
+        assert!(matches!(kind("free-space"), ResponseKind::FreeSpace(_)));
+    }
+}
+
+/// JSON type of a field
+#[derive(Debug, Clone, Copy)]
+enum Kind {
+    Int,
+    Bool,
+    /// Boolean sent as either a JSON boolean or a number
+    IntBool,
+    Ratio,
+    Text,
+    Ids,
+    Ints,
+    Texts,
+    Format,
+    Units,
+}
+
+fn value(kind: Kind) -> BoxedStrategy<Value> {
+    let text = || "[a-z0-9/._ -]{1,16}";
+
+    match kind {
+        Kind::Int => any::<i32>().prop_map(Value::from).boxed(),
+        Kind::Bool => any::<bool>().prop_map(Value::from).boxed(),
+        Kind::IntBool => prop_oneof![
+            any::<bool>().prop_map(Value::from),
+            (0..2).prop_map(Value::from),
+        ]
+        .boxed(),
+        Kind::Ratio => (-1.0e6f32..1.0e6f32).prop_map(Value::from).boxed(),
+        Kind::Text => text().prop_map(Value::from).boxed(),
+        Kind::Ids => {
+            let id = prop_oneof![
+                any::<i32>().prop_map(Value::from),
+                "[0-9a-f]{40}".prop_map(Value::from),
+            ];
+
+            prop_oneof![
+                any::<i32>().prop_map(Value::from),
+                prop::collection::vec(id, 0..4).prop_map(Value::from),
+                Just(json!("recently-active")),
+            ]
+            .boxed()
+        }
+        Kind::Ints => prop::collection::vec(any::<i32>(), 1..4)
+            .prop_map(Value::from)
+            .boxed(),
+        Kind::Texts => prop::collection::vec(text(), 1..4)
+            .prop_map(Value::from)
+            .boxed(),
+        Kind::Format => Just(json!("table")).boxed(),
+        Kind::Units => object(UNITS),
+    }
+}
+
+/// Fields of a JSON object, and whether they are required
+type Fields = &'static [(&'static str, Kind, bool)];
+
+/// Objects with the required fields and a random subset of the optional ones
+fn object(fields: Fields) -> BoxedStrategy<Value> {
+    let entries: Vec<_> = fields
+        .iter()
+        .map(|&(name, kind, required)| {
+            let entry = value(kind).prop_map(move |value| (name.to_owned(), value));
+            if required {
+                entry.prop_map(Some).boxed()
+            } else {
+                proptest::option::of(entry).boxed()
+            }
+        })
+        .collect();
+
+    entries
+        .prop_map(|entries| Value::Object(entries.into_iter().flatten().collect::<Map<_, _>>()))
+        .boxed()
+}
+
+const UNITS: Fields = &[
+    ("speed-units", Kind::Texts, true),
+    ("speed-bytes", Kind::Int, true),
+    ("size-units", Kind::Texts, true),
+    ("size-bytes", Kind::Int, true),
+    ("memory-units", Kind::Texts, true),
+    ("memory-bytes", Kind::Int, true),
+];
+
+const IDS: Fields = &[("ids", Kind::Ids, false)];
+
+const TORRENT_SET: Fields = &[
+    ("bandwidthPriority", Kind::Int, false),
+    ("downloadLimit", Kind::Int, false),
+    ("downloadLimited", Kind::Bool, false),
+    ("files-wanted", Kind::Ints, false),
+    ("files-unwanted", Kind::Ints, false),
+    ("honorsSessionLimits", Kind::Bool, false),
+    ("ids", Kind::Ids, false),
+    ("labels", Kind::Texts, false),
+    ("location", Kind::Text, false),
+    ("peer-limit", Kind::Int, false),
+    ("priority-high", Kind::Ints, false),
+    ("priority-low", Kind::Ints, false),
+    ("priority-normal", Kind::Ints, false),
+    ("queuePosition", Kind::Int, false),
+    ("seedIdleLimit", Kind::Int, false),
+    ("seedIdleMode", Kind::Int, false),
+    ("seedRatioLimit", Kind::Ratio, false),
+    ("seedRatioMode", Kind::Int, false),
+    ("trackerAdd", Kind::Texts, false),
+    ("trackerRemove", Kind::Ints, false),
+    ("trackerReplace", Kind::Texts, false),
+    ("trackerList", Kind::Text, false),
+    ("uploadLimit", Kind::Int, false),
+    ("uploadLimited", Kind::Bool, false),
+];
+
+const TORRENT_GET: Fields = &[
+    ("ids", Kind::Ids, false),
+    ("fields", Kind::Texts, false),
+    ("format", Kind::Format, false),
+];
+
+const TORRENT_ADD: Fields = &[
+    ("cookies", Kind::Text, false),
+    ("download-dir", Kind::Text, true),
+    ("filename", Kind::Text, false),
+    ("labels", Kind::Texts, false),
+    ("metainfo", Kind::Text, false),
+    ("paused", Kind::IntBool, true),
+    ("peer-limit", Kind::Int, false),
+    ("bandwidthPriority", Kind::Int, false),
+    ("files-wanted", Kind::Ints, false),
+    ("files-unwanted", Kind::Ints, false),
+    ("priority-high", Kind::Ints, false),
+    ("priority-low", Kind::Ints, false),
+    ("priority-normal", Kind::Ints, false),
+];
+
+const TORRENT_REMOVE: Fields = &[
+    ("ids", Kind::Ids, false),
+    ("delete-local-data", Kind::Bool, false),
+];
+
+const TORRENT_SET_LOCATION: Fields = &[
+    ("ids", Kind::Ids, false),
+    ("location", Kind::Text, true),
+    ("move", Kind::IntBool, true),
+];
+
+const TORRENT_RENAME_PATH: Fields = &[
+    ("ids", Kind::Ids, false),
+    ("path", Kind::Text, true),
+    ("name", Kind::Text, true),
+];
+
+const SESSION_SET: Fields = &[
+    ("alt-speed-down", Kind::Int, true),
+    ("alt-speed-enabled", Kind::IntBool, true),
+    ("alt-speed-time-begin", Kind::Int, true),
+    ("alt-speed-time-day", Kind::Int, true),
+    ("alt-speed-time-enabled", Kind::IntBool, true),
+    ("alt-speed-time-end", Kind::Int, true),
+    ("alt-speed-up", Kind::Int, true),
+    ("blocklist-enabled", Kind::IntBool, true),
+    ("blocklist-url", Kind::Text, true),
+    ("cache-size-mb", Kind::Int, true),
+    ("dht-enabled", Kind::IntBool, true),
+    ("download-dir", Kind::Text, true),
+    ("download-queue-enabled", Kind::IntBool, true),
+    ("download-queue-size", Kind::Int, true),
+    ("encryption", Kind::Text, true),
+    ("idle-seeding-limit-enabled", Kind::IntBool, true),
+    ("idle-seeding-limit", Kind::Int, true),
+    ("incomplete-dir-enabled", Kind::IntBool, true),
+    ("incomplete-dir", Kind::Text, true),
+    ("lpd-enabled", Kind::IntBool, true),
+    ("peer-limit-global", Kind::Int, true),
+    ("peer-limit-per-torrent", Kind::Int, true),
+    ("peer-port-random-on-start", Kind::IntBool, true),
+    ("peer-port", Kind::Int, true),
+    ("pex-enabled", Kind::IntBool, true),
+    ("port-forwarding-enabled", Kind::IntBool, true),
+    ("queue-stalled-enabled", Kind::IntBool, true),
+    ("queue-stalled-minutes", Kind::Int, true),
+    ("rename-partial-files", Kind::IntBool, true),
+    ("script-torrent-added-enabled", Kind::Bool, false),
+    ("script-torrent-added-filename", Kind::Text, false),
+    ("script-torrent-done-enabled", Kind::Bool, false),
+    ("script-torrent-done-filename", Kind::Text, false),
+    ("script-torrent-done-seeding-enabled", Kind::Bool, false),
+    ("script-torrent-done-seeding-filename", Kind::Text, false),
+    ("seed-queue-enabled", Kind::IntBool, true),
+    ("seed-queue-size", Kind::Int, true),
+    ("seedRatioLimit", Kind::Ratio, true),
+    ("seedRatioLimited", Kind::IntBool, true),
+    ("speed-limit-down-enabled", Kind::IntBool, true),
+    ("speed-limit-down", Kind::Int, true),
+    ("speed-limit-up-enabled", Kind::IntBool, true),
+    ("speed-limit-up", Kind::Int, true),
+    ("start-added-torrents", Kind::IntBool, true),
+    ("trash-original-torrent-files", Kind::IntBool, true),
+    ("units", Kind::Units, true),
+    ("utp-enabled", Kind::IntBool, true),
+];
+
+const SESSION_GET: Fields = &[("fields", Kind::Texts, false)];
+
+const FREE_SPACE: Fields = &[("path", Kind::Text, true)];
+
+/// Arguments of each RPC method, None for methods without arguments
+const METHODS: &[(&str, Option<Fields>)] = &[
+    ("torrent-start", Some(IDS)),
+    ("torrent-start-now", Some(IDS)),
+    ("torrent-stop", Some(IDS)),
+    ("torrent-verify", Some(IDS)),
+    ("torrent-reannounce", Some(IDS)),
+    ("torrent-set", Some(TORRENT_SET)),
+    ("torrent-get", Some(TORRENT_GET)),
+    ("torrent-add", Some(TORRENT_ADD)),
+    ("torrent-remove", Some(TORRENT_REMOVE)),
+    ("torrent-set-location", Some(TORRENT_SET_LOCATION)),
+    ("torrent-rename-path", Some(TORRENT_RENAME_PATH)),
+    ("session-set", Some(SESSION_SET)),
+    ("session-get", Some(SESSION_GET)),
+    ("session-stats", None),
+    ("blocklist-update", None),
+    ("port-test", None),
+    ("session-close", None),
+    ("queue-move-top", Some(IDS)),
+    ("queue-move-up", Some(IDS)),
+    ("queue-move-down", Some(IDS)),
+    ("queue-move-bottom", Some(IDS)),
+    ("free-space", Some(FREE_SPACE)),
+];
+
+fn requests() -> impl Strategy<Value = Value> {
+    let calls = METHODS.iter().map(|&(method, fields)| {
+        let arguments = match fields {
+            Some(fields) => object(fields).prop_map(Some).boxed(),
+            None => Just(None).boxed(),
+        };
+
+        (arguments, proptest::option::of(any::<i32>()))
+            .prop_map(move |(arguments, tag)| {
+                let mut request = json!({ "method": method });
+                if let Some(arguments) = arguments {
+                    request["arguments"] = arguments;
+                }
+                if let Some(tag) = tag {
+                    request["tag"] = tag.into();
+                }
+                request
+            })
+            .boxed()
+    });
+
+    Union::new(calls)
+}
+
+const TORRENT_FILE: Fields = &[
+    ("bytesCompleted", Kind::Int, true),
+    ("length", Kind::Int, true),
+    ("name", Kind::Text, true),
+];
+
+const FILE_STATS: Fields = &[
+    ("bytesCompleted", Kind::Int, true),
+    ("wanted", Kind::Bool, true),
+    ("priority", Kind::Int, true),
+];
+
+const TRACKER: Fields = &[
+    ("id", Kind::Int, true),
+    ("tier", Kind::Int, true),
+    ("announce", Kind::Text, true),
+    ("scrape", Kind::Text, true),
+    ("sitename", Kind::Text, false),
+];
+
+fn torrents() -> impl Strategy<Value = Value> {
+    let optional = |strategy: BoxedStrategy<Value>| proptest::option::of(strategy);
+    let list = |fields| prop::collection::vec(object(fields), 0..3).prop_map(Value::from);
+
+    let torrent = (
+        (
+            value(Kind::Int),
+            value(Kind::Text),
+            proptest::option::of(value(Kind::Text)),
+            optional(value(Kind::Text)),
+            optional(value(Kind::Texts)),
+            optional((0..7).prop_map(Value::from).boxed()),
+            optional(value(Kind::Int)),
+        ),
+        (
+            optional(list(TRACKER).boxed()),
+            optional(list(TORRENT_FILE).boxed()),
+            optional(list(FILE_STATS).boxed()),
+            optional(value(Kind::Ints)),
+            optional(
+                prop::collection::vec(value(Kind::IntBool), 0..3)
+                    .prop_map(Value::from)
+                    .boxed(),
+            ),
+            // Fields which aren't modeled are kept as is
+            optional(value(Kind::Ratio)),
+        ),
+    )
+        .prop_map(
+            |(
+                (id, name, download_dir, hash_string, labels, status, size_when_done),
+                (trackers, files, file_stats, priorities, wanted, upload_ratio),
+            )| {
+                let mut torrent = json!({
+                    "id": id,
+                    "name": name,
+                    "downloadDir": download_dir,
+                });
+
+                for (key, value) in [
+                    ("hashString", hash_string),
+                    ("labels", labels),
+                    ("status", status),
+                    ("sizeWhenDone", size_when_done),
+                    ("trackers", trackers),
+                    ("files", files),
+                    ("fileStats", file_stats),
+                    ("priorities", priorities),
+                    ("wanted", wanted),
+                    ("uploadRatio", upload_ratio),
+                ] {
+                    if let Some(value) = value {
+                        torrent[key] = value;
+                    }
+                }
+
+                torrent
+            },
+        );
+
+    (
+        prop::collection::vec(torrent, 0..4),
+        proptest::option::of(value(Kind::Ints)),
+    )
+        .prop_map(|(torrents, removed)| {
+            let mut arguments = json!({ "torrents": torrents });
+            if let Some(removed) = removed {
+                arguments["removed"] = removed;
+            }
+            arguments
+        })
+}
+
+proptest! {
+    #[test]
+    fn requests_round_trip(request in requests()) {
+        prop_assert_eq!(round_trip::<Request>(&request)?, request);
+    }
+
+    #[test]
+    fn torrents_round_trip(torrents in torrents()) {
+        prop_assert_eq!(round_trip::<Torrents>(&torrents)?, torrents);
+    }
+
+    #[test]
+    fn free_space_round_trip(
+        free_space in object(&[
+            ("path", Kind::Text, true),
+            ("size-bytes", Kind::Int, true),
+            ("total_size", Kind::Int, false),
+        ])
+    ) {
+        prop_assert_eq!(round_trip::<FreeSpaceResult>(&free_space)?, free_space);
+    }
+
+    #[test]
+    fn failures_round_trip(result in "[a-z ]{1,32}", tag in proptest::option::of(any::<i32>())) {
+        let mut response = json!({ "result": result });
+        if let Some(tag) = tag {
+            response["tag"] = tag.into();
+        }
+
+        prop_assert_eq!(round_trip::<Response>(&response)?, response);
+    }
+}
+
+#[test]
+fn requests_are_snapshotted() {
+    let sample = |method, arguments: Value| {
+        serde_json::from_value::<Request>(json!({ "method": method, "arguments": arguments }))
+            .unwrap()
+    };
+
+    insta::assert_json_snapshot!(
+        "requests",
+        [
+            sample(
+                "torrent-get",
+                json!({ "ids": [1, "ab"], "fields": ["id", "name"] })
+            ),
+            sample(
+                "torrent-set",
+                json!({ "ids": 1, "trackerList": "a\n\nb", "uploadLimited": true }),
+            ),
+            sample(
+                "torrent-add",
+                json!({ "download-dir": "/data", "filename": "magnet:?", "paused": 1 }),
+            ),
+            sample(
+                "torrent-set-location",
+                json!({ "ids": "recently-active", "location": "/data" }),
+            ),
+            sample("session-get", json!({})),
+        ]
+    );
+}